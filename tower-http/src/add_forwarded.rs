@@ -0,0 +1,466 @@
+//! Middleware that adds `Forwarded` and `X-Forwarded-*` headers to requests.
+//!
+//! Proxies forwarding requests to a backend should tell it about the original request, which is
+//! otherwise lost. [`AddForwarded`] appends an element describing the incoming request to the
+//! [RFC 7239] `Forwarded` header, and optionally sets the legacy `X-Forwarded-For`,
+//! `X-Forwarded-Proto`, and `X-Forwarded-Host` headers, so proxies don't have to implement the
+//! quoting and chaining rules themselves. Apply it before the request is rewritten for the
+//! backend, since the `Host` header and URI are read from the request.
+//!
+//! The element contains:
+//!
+//! - `for`: the IP address of the peer, read from a [`SocketAddr`] request extension, which has
+//!   to be added by the server, for example with [`AddExtension`]. Without the extension it is
+//!   `unknown`.
+//! - `by`: the identifier of the proxy, if configured with [`AddForwardedLayer::by`].
+//! - `proto`: the scheme of the request URI, or the one configured with
+//!   [`AddForwardedLayer::proto`].
+//! - `host`: the `Host` header, or the authority of the request URI.
+//!
+//! Headers added by previous proxies are kept: the element is appended to an existing `Forwarded`
+//! header, the peer is appended to an existing `X-Forwarded-For` header, and existing
+//! `X-Forwarded-Proto` and `X-Forwarded-Host` headers are left unchanged. Proxies at the edge,
+//! which receive requests directly from clients, should use
+//! [`AddForwardedLayer::clear_incoming`] instead, since clients can send these headers too.
+//!
+//! [RFC 7239]: https://www.rfc-editor.org/rfc/rfc7239
+//! [`AddExtension`]: crate::add_extension::AddExtension
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::{convert::Infallible, net::SocketAddr};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::add_forwarded::AddForwardedLayer;
+//!
+//! async fn forward(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     assert_eq!(
+//!         req.headers()[header::FORWARDED],
+//!         r#"for=192.0.2.43, for="[2001:db8::1]";proto=https;host=example.com"#,
+//!     );
+//!     assert_eq!(req.headers()["x-forwarded-for"], "192.0.2.43, 2001:db8::1");
+//!     // send the request to the backend...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(AddForwardedLayer::new().proto("https").x_forwarded(true))
+//!     .service_fn(forward);
+//!
+//! let mut request = Request::get("/")
+//!     .header(header::HOST, "example.com")
+//!     .header(header::FORWARDED, "for=192.0.2.43")
+//!     .header("x-forwarded-for", "192.0.2.43")
+//!     .body(Body::empty())?;
+//! // added by the server
+//! request
+//!     .extensions_mut()
+//!     .insert("[2001:db8::1]:50000".parse::<SocketAddr>()?);
+//! service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Request,
+};
+use std::{
+    fmt::Write as _,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+#[derive(Debug, Clone)]
+struct Config {
+    by: Option<String>,
+    proto: Option<String>,
+    forwarded: bool,
+    x_forwarded: bool,
+    clear_incoming: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            by: None,
+            proto: None,
+            forwarded: true,
+            x_forwarded: false,
+            clear_incoming: false,
+        }
+    }
+}
+
+impl Config {
+    fn apply<B>(&self, req: &mut Request<B>) {
+        let peer = req
+            .extensions()
+            .get::<SocketAddr>()
+            .map(|addr| canonical(addr.ip()));
+        let proto = self
+            .proto
+            .as_deref()
+            .or_else(|| req.uri().scheme_str())
+            .map(str::to_owned);
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+            .map(str::to_owned);
+
+        let headers = req.headers_mut();
+        if self.clear_incoming {
+            headers.remove(header::FORWARDED);
+            headers.remove(X_FORWARDED_FOR);
+            headers.remove(X_FORWARDED_PROTO);
+            headers.remove(X_FORWARDED_HOST);
+        }
+
+        if self.forwarded {
+            let mut element = String::from("for=");
+            match peer {
+                Some(peer) => push_node(&mut element, peer),
+                None => element.push_str("unknown"),
+            }
+            if let Some(by) = &self.by {
+                element.push_str(";by=");
+                push_value(&mut element, by);
+            }
+            if let Some(proto) = &proto {
+                element.push_str(";proto=");
+                push_value(&mut element, proto);
+            }
+            if let Some(host) = &host {
+                element.push_str(";host=");
+                push_value(&mut element, host);
+            }
+            append_to_list(headers, header::FORWARDED, &element);
+        }
+
+        if self.x_forwarded {
+            if let Some(peer) = peer {
+                append_to_list(headers, X_FORWARDED_FOR, &peer.to_string());
+            }
+            if let Some(proto) = proto {
+                set_if_missing(headers, X_FORWARDED_PROTO, &proto);
+            }
+            if let Some(host) = host {
+                set_if_missing(headers, X_FORWARDED_HOST, &host);
+            }
+        }
+    }
+}
+
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+fn push_node(element: &mut String, addr: IpAddr) {
+    match addr {
+        IpAddr::V4(addr) => write!(element, "{}", addr).unwrap(),
+        // IPv6 addresses are enclosed in brackets, which are not allowed in tokens
+        IpAddr::V6(addr) => write!(element, "\"[{}]\"", addr).unwrap(),
+    }
+}
+
+/// Append `value` as a token, or as a quoted string if it contains other characters.
+fn push_value(element: &mut String, value: &str) {
+    let is_token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if is_token {
+        element.push_str(value);
+        return;
+    }
+
+    element.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            element.push('\\');
+        }
+        element.push(c);
+    }
+    element.push('"');
+}
+
+/// Append `item` to the comma separated list in the header called `name`, combining multiple
+/// header fields into one.
+fn append_to_list(headers: &mut HeaderMap, name: HeaderName, item: &str) {
+    let mut list = String::new();
+    for value in headers.get_all(&name) {
+        if let Ok(value) = value.to_str() {
+            let value = value.trim();
+            if !value.is_empty() {
+                list.push_str(value);
+                list.push_str(", ");
+            }
+        }
+    }
+    list.push_str(item);
+
+    if let Ok(value) = HeaderValue::from_str(&list) {
+        headers.insert(name, value);
+    }
+}
+
+fn set_if_missing(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if headers.contains_key(&name) {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Layer that applies [`AddForwarded`] which adds `Forwarded` and `X-Forwarded-*` headers to
+/// requests.
+///
+/// See the [module docs](crate::add_forwarded) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct AddForwardedLayer {
+    config: Arc<Config>,
+}
+
+impl AddForwardedLayer {
+    /// Create a new `AddForwardedLayer` that only adds the `Forwarded` header.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the identifier of this proxy, sent in the `by` parameter.
+    ///
+    /// This can be an IP address, or an obfuscated identifier such as `_proxy1`.
+    pub fn by(mut self, by: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).by = Some(by.into());
+        self
+    }
+
+    /// Set the protocol the requests were received with, such as `https` for a proxy terminating
+    /// TLS.
+    ///
+    /// Defaults to the scheme of the request URI, which is usually missing in servers, in which
+    /// case no protocol is sent.
+    pub fn proto(mut self, proto: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).proto = Some(proto.into());
+        self
+    }
+
+    /// Whether to add the `Forwarded` header. Defaults to `true`.
+    pub fn forwarded(mut self, forwarded: bool) -> Self {
+        Arc::make_mut(&mut self.config).forwarded = forwarded;
+        self
+    }
+
+    /// Whether to add the `X-Forwarded-For`, `X-Forwarded-Proto`, and `X-Forwarded-Host` headers.
+    /// Defaults to `false`.
+    pub fn x_forwarded(mut self, x_forwarded: bool) -> Self {
+        Arc::make_mut(&mut self.config).x_forwarded = x_forwarded;
+        self
+    }
+
+    /// Whether to remove the forwarding headers of incoming requests rather than appending to
+    /// them. Defaults to `false`.
+    ///
+    /// This should be enabled for proxies that receive requests directly from clients.
+    pub fn clear_incoming(mut self, clear_incoming: bool) -> Self {
+        Arc::make_mut(&mut self.config).clear_incoming = clear_incoming;
+        self
+    }
+}
+
+impl<S> Layer<S> for AddForwardedLayer {
+    type Service = AddForwarded<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AddForwarded {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that adds `Forwarded` and `X-Forwarded-*` headers to requests.
+///
+/// See the [module docs](crate::add_forwarded) for an example.
+#[derive(Debug, Clone)]
+pub struct AddForwarded<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> AddForwarded<S> {
+    /// Create a new `AddForwarded` that only adds the `Forwarded` header.
+    pub fn new(inner: S) -> Self {
+        AddForwardedLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `AddForwarded` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> AddForwardedLayer {
+        AddForwardedLayer::new()
+    }
+
+    /// Set the identifier of this proxy, sent in the `by` parameter.
+    ///
+    /// See [`AddForwardedLayer::by`] for more details.
+    pub fn by(mut self, by: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).by = Some(by.into());
+        self
+    }
+
+    /// Set the protocol the requests were received with.
+    ///
+    /// See [`AddForwardedLayer::proto`] for more details.
+    pub fn proto(mut self, proto: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).proto = Some(proto.into());
+        self
+    }
+
+    /// Whether to add the `Forwarded` header.
+    ///
+    /// See [`AddForwardedLayer::forwarded`] for more details.
+    pub fn forwarded(mut self, forwarded: bool) -> Self {
+        Arc::make_mut(&mut self.config).forwarded = forwarded;
+        self
+    }
+
+    /// Whether to add the `X-Forwarded-*` headers.
+    ///
+    /// See [`AddForwardedLayer::x_forwarded`] for more details.
+    pub fn x_forwarded(mut self, x_forwarded: bool) -> Self {
+        Arc::make_mut(&mut self.config).x_forwarded = x_forwarded;
+        self
+    }
+
+    /// Whether to remove the forwarding headers of incoming requests.
+    ///
+    /// See [`AddForwardedLayer::clear_incoming`] for more details.
+    pub fn clear_incoming(mut self, clear_incoming: bool) -> Self {
+        Arc::make_mut(&mut self.config).clear_incoming = clear_incoming;
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for AddForwarded<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        self.config.apply(&mut req);
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Response;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn forward(layer: AddForwardedLayer, req: Request<Body>) -> HeaderMap {
+        let svc = layer.layer(service_fn(|req: Request<Body>| async move {
+            Ok::<_, BoxError>(Response::new(req.headers().clone()))
+        }));
+        svc.oneshot(req).await.unwrap().into_body()
+    }
+
+    #[tokio::test]
+    async fn adds_forwarded_element() {
+        let mut req = Request::get("https://example.com:8443/")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(SocketAddr::from(([192, 0, 2, 1], 1234)));
+        let headers = forward(AddForwardedLayer::new().by("_proxy"), req).await;
+        assert_eq!(
+            headers[header::FORWARDED],
+            r#"for=192.0.2.1;by=_proxy;proto=https;host="example.com:8443""#
+        );
+        assert!(!headers.contains_key(X_FORWARDED_FOR));
+
+        let req = Request::get("/")
+            .header(header::FORWARDED, "for=192.0.2.1")
+            .header(header::FORWARDED, "for=192.0.2.2")
+            .header(header::HOST, "a\"b")
+            .body(Body::empty())
+            .unwrap();
+        let headers = forward(AddForwardedLayer::new(), req).await;
+        assert_eq!(
+            headers
+                .get_all(header::FORWARDED)
+                .iter()
+                .collect::<Vec<_>>(),
+            [r#"for=192.0.2.1, for=192.0.2.2, for=unknown;host="a\"b""#]
+        );
+    }
+
+    #[tokio::test]
+    async fn adds_x_forwarded_headers() {
+        let mut req = Request::get("/")
+            .header(header::HOST, "example.com")
+            .header(X_FORWARDED_FOR, "192.0.2.1")
+            .header(X_FORWARDED_PROTO, "https")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert("[::ffff:192.0.2.2]:1234".parse::<SocketAddr>().unwrap());
+        let layer = AddForwardedLayer::new()
+            .forwarded(false)
+            .x_forwarded(true)
+            .proto("http");
+        let headers = forward(layer, req).await;
+        assert!(!headers.contains_key(header::FORWARDED));
+        assert_eq!(headers[X_FORWARDED_FOR], "192.0.2.1, 192.0.2.2");
+        assert_eq!(headers[X_FORWARDED_PROTO], "https");
+        assert_eq!(headers[X_FORWARDED_HOST], "example.com");
+    }
+
+    #[tokio::test]
+    async fn clear_incoming() {
+        let mut req = Request::get("/")
+            .header(header::FORWARDED, "for=192.0.2.1")
+            .header(X_FORWARDED_FOR, "192.0.2.1")
+            .header(X_FORWARDED_HOST, "evil.example")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert("[2001:db8::1]:1234".parse::<SocketAddr>().unwrap());
+        let layer = AddForwardedLayer::new()
+            .x_forwarded(true)
+            .clear_incoming(true);
+        let headers = forward(layer, req).await;
+        assert_eq!(headers[header::FORWARDED], r#"for="[2001:db8::1]""#);
+        assert_eq!(headers[X_FORWARDED_FOR], "2001:db8::1");
+        assert!(!headers.contains_key(X_FORWARDED_HOST));
+    }
+}
@@ -0,0 +1,466 @@
+//! Middleware that answers `OPTIONS` requests and rejects unsupported methods with
+//! `405 Method Not Allowed`.
+//!
+//! [`AllowedMethods`] is configured with the methods supported by the paths of the inner service.
+//! For a path with a configured set of methods, it
+//!
+//! - answers `OPTIONS` requests with `204 No Content` and an `Allow` header listing the methods,
+//! - responds to requests with other methods with `405 Method Not Allowed` and the same `Allow`
+//!   header, rather than whatever the inner service would respond with, often `404 Not Found`,
+//! - passes all other requests to the inner service.
+//!
+//! `HEAD` is allowed wherever `GET` is, and `OPTIONS` everywhere. If `OPTIONS` is configured
+//! explicitly, `OPTIONS` requests are passed to the inner service. Requests to paths without a
+//! configured set of methods are always passed to the inner service.
+//!
+//! CORS preflight requests are `OPTIONS` requests too, so a [`CorsLayer`] should be applied
+//! outside of this middleware.
+//!
+//! [`CorsLayer`]: crate::cors::CorsLayer
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Method, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::allowed_methods::AllowedMethodsLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         AllowedMethodsLayer::new()
+//!             .path("/users", [Method::GET, Method::POST])
+//!             .route(
+//!                 |path| path.starts_with("/users/"),
+//!                 [Method::GET, Method::PUT, Method::DELETE],
+//!             ),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::delete("/users").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+//! assert_eq!(response.headers()[header::ALLOW], "GET, POST, HEAD, OPTIONS");
+//!
+//! let request = Request::options("/users/1").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::NO_CONTENT);
+//! assert_eq!(response.headers()[header::ALLOW], "GET, PUT, DELETE, HEAD, OPTIONS");
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The methods allowed on the paths matching a predicate.
+#[derive(Clone)]
+struct Route {
+    matches: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+    methods: Vec<Method>,
+    // whether `OPTIONS` requests are handled by the inner service
+    native_options: bool,
+    allow: HeaderValue,
+}
+
+impl Route {
+    fn new<F, I>(matches: F, methods: I) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+        I: IntoIterator<Item = Method>,
+    {
+        let mut allowed = Vec::new();
+        for method in methods {
+            if !allowed.contains(&method) {
+                allowed.push(method);
+            }
+        }
+        let native_options = allowed.contains(&Method::OPTIONS);
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+        if !native_options {
+            allowed.push(Method::OPTIONS);
+        }
+
+        let allow = allowed
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            matches: Arc::new(matches),
+            methods: allowed,
+            native_options,
+            allow: HeaderValue::from_str(&allow).expect("methods are valid header values"),
+        }
+    }
+}
+
+/// Layer that applies [`AllowedMethods`] which answers `OPTIONS` requests and rejects
+/// unsupported methods with `405 Method Not Allowed`.
+///
+/// See the [module docs](crate::allowed_methods) for an example.
+#[derive(Clone, Default)]
+pub struct AllowedMethodsLayer {
+    routes: Arc<Vec<Route>>,
+}
+
+impl AllowedMethodsLayer {
+    /// Create a new `AllowedMethodsLayer` without any configured paths.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow `methods` on paths matching `predicate`.
+    ///
+    /// Paths are matched against the routes in the order they were added, and the first match
+    /// wins.
+    pub fn route<F, I>(mut self, predicate: F, methods: I) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+        I: IntoIterator<Item = Method>,
+    {
+        Arc::make_mut(&mut self.routes).push(Route::new(predicate, methods));
+        self
+    }
+
+    /// Allow `methods` on `path`.
+    ///
+    /// See [`AllowedMethodsLayer::route`] for more details.
+    pub fn path<I>(self, path: &str, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        let path = path.to_owned();
+        self.route(move |candidate| candidate == path, methods)
+    }
+}
+
+impl fmt::Debug for AllowedMethodsLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllowedMethodsLayer")
+            .field("routes", &self.routes.len())
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for AllowedMethodsLayer {
+    type Service = AllowedMethods<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AllowedMethods {
+            inner,
+            routes: self.routes.clone(),
+        }
+    }
+}
+
+/// Middleware that answers `OPTIONS` requests and rejects unsupported methods with
+/// `405 Method Not Allowed`.
+///
+/// See the [module docs](crate::allowed_methods) for an example.
+#[derive(Clone)]
+pub struct AllowedMethods<S> {
+    inner: S,
+    routes: Arc<Vec<Route>>,
+}
+
+impl<S> AllowedMethods<S> {
+    /// Create a new `AllowedMethods` without any configured paths.
+    pub fn new(inner: S) -> Self {
+        AllowedMethodsLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `AllowedMethods` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> AllowedMethodsLayer {
+        AllowedMethodsLayer::new()
+    }
+
+    /// Allow `methods` on paths matching `predicate`.
+    ///
+    /// See [`AllowedMethodsLayer::route`] for more details.
+    pub fn route<F, I>(mut self, predicate: F, methods: I) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+        I: IntoIterator<Item = Method>,
+    {
+        Arc::make_mut(&mut self.routes).push(Route::new(predicate, methods));
+        self
+    }
+
+    /// Allow `methods` on `path`.
+    ///
+    /// See [`AllowedMethodsLayer::route`] for more details.
+    pub fn path<I>(self, path: &str, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        let path = path.to_owned();
+        self.route(move |candidate| candidate == path, methods)
+    }
+}
+
+impl<S> fmt::Debug for AllowedMethods<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllowedMethods")
+            .field("inner", &self.inner)
+            .field("routes", &self.routes.len())
+            .finish()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AllowedMethods<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let path = req.uri().path();
+        let route = self.routes.iter().find(|route| (route.matches)(path));
+        let kind = match route {
+            Some(route) if *req.method() == Method::OPTIONS && !route.native_options => {
+                Kind::Respond {
+                    status: StatusCode::NO_CONTENT,
+                    allow: Some(route.allow.clone()),
+                }
+            }
+            Some(route) if !route.methods.contains(req.method()) => Kind::Respond {
+                status: StatusCode::METHOD_NOT_ALLOWED,
+                allow: Some(route.allow.clone()),
+            },
+            _ => Kind::Future {
+                future: self.inner.call(req),
+            },
+        };
+        ResponseFuture { kind }
+    }
+}
+
+pin_project! {
+    /// Response future for [`AllowedMethods`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        Respond {
+            status: StatusCode,
+            allow: Option<HeaderValue>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future } => {
+                let res = ready!(future.poll(cx))?;
+                Poll::Ready(Ok(res.map(|body| ResponseBody {
+                    kind: BodyKind::Body { body },
+                })))
+            }
+            KindProj::Respond { status, allow } => {
+                let mut res = Response::new(ResponseBody {
+                    kind: BodyKind::Empty,
+                });
+                *res.status_mut() = *status;
+                if let Some(allow) = allow.take() {
+                    res.headers_mut().insert(header::ALLOW, allow);
+                }
+                Poll::Ready(Ok(res))
+            }
+        }
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+pin_project! {
+    /// Response body for [`AllowedMethods`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B> {
+        Body {
+            #[pin]
+            body: B,
+        },
+        Empty,
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_data(cx),
+            BodyKindProj::Empty => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+            BodyKindProj::Empty => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Body { body } => body.is_end_stream(),
+            BodyKind::Empty => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Body { body } => body.size_hint(),
+            BodyKind::Empty => SizeHint::with_exact(0),
+        }
+    }
+}
+
+impl<B> fmt::Debug for ResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let res = Response::builder()
+            .header("x-method", req.method().as_str())
+            .body(Body::empty())
+            .unwrap();
+        Ok(res)
+    }
+
+    async fn send<S, B>(svc: S, method: Method, uri: &str) -> Response<B>
+    where
+        S: Service<Request<Body>, Response = Response<B>>,
+        S::Error: fmt::Debug,
+    {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        svc.oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn allowed_methods() {
+        let svc = AllowedMethodsLayer::new()
+            .path("/a", [Method::GET, Method::POST])
+            .route(|path| path.starts_with("/a"), [Method::DELETE])
+            .layer(service_fn(handle));
+
+        for method in [Method::GET, Method::HEAD, Method::POST] {
+            let res = send(svc.clone(), method.clone(), "/a").await;
+            assert_eq!(res.headers()["x-method"], method.as_str());
+        }
+
+        let res = send(svc.clone(), Method::PUT, "/a").await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, POST, HEAD, OPTIONS");
+
+        let res = send(svc.clone(), Method::OPTIONS, "/a").await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[header::ALLOW], "GET, POST, HEAD, OPTIONS");
+        assert!(!res.headers().contains_key("x-method"));
+
+        // the first matching route wins
+        let res = send(svc.clone(), Method::GET, "/a/b").await;
+        assert_eq!(res.headers()[header::ALLOW], "DELETE, OPTIONS");
+
+        // other paths are passed through
+        let res = send(svc, Method::PUT, "/b").await;
+        assert_eq!(res.headers()["x-method"], "PUT");
+    }
+
+    #[tokio::test]
+    async fn native_options() {
+        let svc = AllowedMethodsLayer::new()
+            .path("/", [Method::GET, Method::HEAD, Method::OPTIONS])
+            .layer(service_fn(handle));
+
+        let res = send(svc.clone(), Method::OPTIONS, "/").await;
+        assert_eq!(res.headers()["x-method"], "OPTIONS");
+
+        let res = send(svc, Method::POST, "/").await;
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+    }
+}
@@ -0,0 +1,342 @@
+//! Middleware that answers `HEAD` requests with the inner service's `GET` handlers.
+//!
+//! A response to a `HEAD` request must have the same headers as the response to the
+//! corresponding `GET` request, but no body. [`AutoHead`] sends `HEAD` requests to the inner
+//! service as `GET` requests and removes the body from the response, so services only need to
+//! implement `GET`.
+//!
+//! The `Content-Length` and other headers of the `GET` response are kept. If the response has no
+//! `Content-Length` but the exact size of its body is known, it is added. Services that implement
+//! `HEAD` themselves, for example to avoid producing expensive bodies, can be excluded with
+//! [`AutoHeadLayer::native_head`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Method, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::auto_head::AutoHeadLayer;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // Only `GET` is implemented.
+//!     assert_eq!(req.method(), Method::GET);
+//!     Ok(Response::new(Body::from("hello")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(AutoHeadLayer::new())
+//!     .service_fn(handle);
+//!
+//! let request = Request::head("/").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.headers()[header::CONTENT_LENGTH], "5");
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert!(body.is_empty());
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, Uri};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+type NativeHead = Arc<dyn Fn(&Uri) -> bool + Send + Sync>;
+
+/// Layer that applies [`AutoHead`] which answers `HEAD` requests with the inner service's `GET`
+/// handlers.
+///
+/// See the [module docs](crate::auto_head) for an example.
+#[derive(Clone, Default)]
+pub struct AutoHeadLayer {
+    native_head: Option<NativeHead>,
+}
+
+impl AutoHeadLayer {
+    /// Create a new `AutoHeadLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass `HEAD` requests to URIs matching `predicate` to the inner service unchanged, for
+    /// services that implement `HEAD` themselves.
+    pub fn native_head<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Uri) -> bool + Send + Sync + 'static,
+    {
+        self.native_head = Some(Arc::new(predicate));
+        self
+    }
+}
+
+impl fmt::Debug for AutoHeadLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoHeadLayer")
+            .field("native_head", &self.native_head.is_some())
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for AutoHeadLayer {
+    type Service = AutoHead<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AutoHead {
+            inner,
+            native_head: self.native_head.clone(),
+        }
+    }
+}
+
+/// Middleware that answers `HEAD` requests with the inner service's `GET` handlers.
+///
+/// See the [module docs](crate::auto_head) for an example.
+#[derive(Clone)]
+pub struct AutoHead<S> {
+    inner: S,
+    native_head: Option<NativeHead>,
+}
+
+impl<S> AutoHead<S> {
+    /// Create a new `AutoHead`.
+    pub fn new(inner: S) -> Self {
+        AutoHeadLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `AutoHead` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> AutoHeadLayer {
+        AutoHeadLayer::new()
+    }
+
+    /// Pass `HEAD` requests to URIs matching `predicate` to the inner service unchanged.
+    ///
+    /// See [`AutoHeadLayer::native_head`] for more details.
+    pub fn native_head<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Uri) -> bool + Send + Sync + 'static,
+    {
+        self.native_head = Some(Arc::new(predicate));
+        self
+    }
+}
+
+impl<S> fmt::Debug for AutoHead<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoHead")
+            .field("inner", &self.inner)
+            .field("native_head", &self.native_head.is_some())
+            .finish()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AutoHead<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let head = req.method() == Method::HEAD
+            && !self
+                .native_head
+                .as_ref()
+                .map_or(false, |native_head| native_head(req.uri()));
+        if head {
+            *req.method_mut() = Method::GET;
+        }
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            head,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`AutoHead`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        head: bool,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+        if !*this.head {
+            return Poll::Ready(Ok(res.map(|body| ResponseBody {
+                kind: BodyKind::Body { body },
+            })));
+        }
+
+        let (mut parts, body) = res.into_parts();
+        if !parts.headers.contains_key(header::CONTENT_LENGTH) {
+            if let Some(len) = body.size_hint().exact() {
+                parts
+                    .headers
+                    .insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+            }
+        }
+        // the body is dropped without being read
+        Poll::Ready(Ok(Response::from_parts(
+            parts,
+            ResponseBody {
+                kind: BodyKind::Empty,
+            },
+        )))
+    }
+}
+
+pin_project! {
+    /// Response body for [`AutoHead`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B> {
+        Body {
+            #[pin]
+            body: B,
+        },
+        Empty,
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_data(cx),
+            BodyKindProj::Empty => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+            BodyKindProj::Empty => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Body { body } => body.is_end_stream(),
+            BodyKind::Empty => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Body { body } => body.size_hint(),
+            BodyKind::Empty => SizeHint::with_exact(0),
+        }
+    }
+}
+
+impl<B> fmt::Debug for ResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let res = Response::builder()
+            .header("x-method", req.method().as_str())
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from("hello"))
+            .unwrap();
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn converts_head_to_get() {
+        let svc = AutoHeadLayer::new().layer(service_fn(handle));
+
+        let req = Request::head("/").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "GET");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+        assert!(res.body().is_end_stream());
+
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn native_head() {
+        let svc = AutoHeadLayer::new()
+            .native_head(|uri| uri.path().starts_with("/native"))
+            .layer(service_fn(handle));
+
+        let req = Request::head("/native/a").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "HEAD");
+
+        let req = Request::head("/other").body(Body::empty()).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "GET");
+    }
+}
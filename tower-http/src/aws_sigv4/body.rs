@@ -0,0 +1,103 @@
+use crate::BoxError;
+use bytes::{Buf, Bytes};
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Request body for [`AwsSigV4`].
+///
+/// The body, or the part of it that was read before it turned out to be too large to be
+/// buffered, has been buffered to hash it.
+///
+/// [`AwsSigV4`]: super::AwsSigV4
+pub struct AwsSigV4Body<B> {
+    buffered: Option<Bytes>,
+    rest: Pin<Box<B>>,
+    // whether the data of `rest` has been read completely
+    rest_done: bool,
+}
+
+impl<B> AwsSigV4Body<B> {
+    pub(super) fn new(buffered: Bytes, rest: Pin<Box<B>>, rest_done: bool) -> Self {
+        Self {
+            buffered: Some(buffered).filter(|data| !data.is_empty()),
+            rest,
+            rest_done,
+        }
+    }
+}
+
+impl<B> Body for AwsSigV4Body<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        if let Some(data) = this.buffered.take() {
+            return Poll::Ready(Some(Ok(data)));
+        }
+        if this.rest_done {
+            return Poll::Ready(None);
+        }
+        match this.rest.as_mut().poll_data(cx) {
+            Poll::Ready(Some(Ok(mut data))) => {
+                Poll::Ready(Some(Ok(data.copy_to_bytes(data.remaining()))))
+            }
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err.into()))),
+            Poll::Ready(None) => {
+                this.rest_done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.get_mut()
+            .rest
+            .as_mut()
+            .poll_trailers(cx)
+            .map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buffered.is_none() && (self.rest_done || self.rest.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self.buffered.as_ref().map_or(0, |data| data.len() as u64);
+        if self.rest_done {
+            return SizeHint::with_exact(buffered);
+        }
+        let rest = self.rest.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(buffered + rest.lower());
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(buffered + upper);
+        }
+        hint
+    }
+}
+
+impl<B> fmt::Debug for AwsSigV4Body<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsSigV4Body")
+            .field("buffered", &self.buffered.as_ref().map(Bytes::len))
+            .field("rest_done", &self.rest_done)
+            .finish()
+    }
+}
@@ -0,0 +1,124 @@
+use super::{
+    service::Config,
+    signing::{self, Scope, UNSIGNED_PAYLOAD},
+    AwsSigV4Body,
+};
+use crate::{
+    buffer::{Buffer, Buffered},
+    BoxError,
+};
+use futures_util::ready;
+use http::{request::Parts, Request};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future for [`AwsSigV4`].
+    ///
+    /// [`AwsSigV4`]: super::AwsSigV4
+    pub struct ResponseFuture<S, B>
+    where
+        S: Service<Request<AwsSigV4Body<B>>>,
+    {
+        #[pin]
+        state: State<S, B, S::Future>,
+        config: Arc<Config>,
+    }
+}
+
+impl<S, B> ResponseFuture<S, B>
+where
+    S: Service<Request<AwsSigV4Body<B>>>,
+{
+    pub(super) fn new(state: State<S, B, S::Future>, config: Arc<Config>) -> Self {
+        Self { state, config }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    pub(super) enum State<S, B, F> {
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, B> Future for ResponseFuture<S, B>
+where
+    S: Service<Request<AwsSigV4Body<B>>>,
+    S::Error: Into<BoxError>,
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<S::Response, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    service,
+                } => {
+                    let (Buffered { data, complete, .. }, rest) = match ready!(buffer.poll(cx)) {
+                        Ok(buffered) => buffered,
+                        Err(err) => return Poll::Ready(Err(err.into())),
+                    };
+                    // what has been read of bodies that are too large is sent followed by the
+                    // rest, without signing the payload
+                    let payload_hash = if complete {
+                        signing::sha256_hex(&data)
+                    } else {
+                        UNSIGNED_PAYLOAD.to_owned()
+                    };
+
+                    let credentials = this.config.credentials.provide_credentials()?;
+                    let scope = Scope {
+                        service: &this.config.service,
+                        region: &this.config.region,
+                        credentials: &credentials,
+                    };
+                    let mut parts = parts.take().expect("future polled after completion");
+                    signing::sign(&mut parts, &payload_hash, &scope, SystemTime::now())?;
+
+                    let body = AwsSigV4Body::new(data, rest, complete);
+                    let mut service = service.take().expect("future polled after completion");
+                    State::Called {
+                        future: service.call(Request::from_parts(parts, body)),
+                    }
+                }
+                StateProj::Called { future } => return future.poll(cx).map_err(Into::into),
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<AwsSigV4Body<B>>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("config", &self.config)
+            .finish()
+    }
+}
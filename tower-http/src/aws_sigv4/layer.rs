@@ -0,0 +1,45 @@
+use super::{service::Config, AwsSigV4, ProvideCredentials};
+use std::sync::Arc;
+use tower_layer::Layer;
+
+/// Layer that applies the [`AwsSigV4`] middleware, which signs requests with AWS Signature
+/// Version 4.
+///
+/// See the [module docs](crate::aws_sigv4) for an example.
+#[derive(Clone, Debug)]
+pub struct AwsSigV4Layer {
+    config: Arc<Config>,
+}
+
+impl AwsSigV4Layer {
+    /// Create a new [`AwsSigV4Layer`] signing requests to `service` in `region`, such as `s3`
+    /// and `us-east-1`, with the credentials from `credentials`.
+    pub fn new<P>(service: &str, region: &str, credentials: P) -> Self
+    where
+        P: ProvideCredentials + Send + Sync + 'static,
+    {
+        Self {
+            config: Arc::new(Config::new(service, region, Arc::new(credentials))),
+        }
+    }
+
+    /// Set the size of the largest body that is buffered to be signed. Larger bodies are signed
+    /// with `UNSIGNED-PAYLOAD`.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_body_size = limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for AwsSigV4Layer {
+    type Service = AwsSigV4<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AwsSigV4 {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
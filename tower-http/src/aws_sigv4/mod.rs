@@ -0,0 +1,247 @@
+//! Middleware for HTTP clients that signs requests with [AWS Signature Version 4][sigv4].
+//!
+//! [`AwsSigV4`] signs requests to AWS and AWS-compatible APIs, such as S3 or OpenSearch, by
+//! setting their `Authorization`, `X-Amz-Date` and, with temporary credentials,
+//! `X-Amz-Security-Token` headers. The `Host`, `Content-Type`, `Content-MD5` and `X-Amz-*`
+//! headers are signed.
+//!
+//! The signature covers a SHA-256 digest of the body, so bodies are hashed and buffered before
+//! the request is sent. Bodies larger than [`AwsSigV4Layer::max_body_size`] are sent as they are
+//! read instead, and signed with `UNSIGNED-PAYLOAD`. S3 accepts such requests, but most other
+//! services don't. Requests to S3 also get the `X-Amz-Content-SHA256` header it requires.
+//!
+//! Credentials are fetched for every request from a [`ProvideCredentials`], which is
+//! implemented for [`Credentials`] and for closures returning them.
+//!
+//! [sigv4]: https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_aws-signing.html
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::aws_sigv4::{AwsSigV4Body, AwsSigV4Layer, Credentials};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! # let http_client = tower::service_fn(|req: Request<AwsSigV4Body<Body>>| async move {
+//! #     assert!(req.headers()[header::AUTHORIZATION]
+//! #         .to_str()?
+//! #         .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+//! #     Ok::<_, tower::BoxError>(Response::new(Body::empty()))
+//! # });
+//! let credentials = Credentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+//!
+//! let mut client = ServiceBuilder::new()
+//!     .layer(AwsSigV4Layer::new("s3", "eu-west-1", credentials))
+//!     .service(http_client);
+//!
+//! let request = Request::put("https://my-bucket.s3.eu-west-1.amazonaws.com/hello.txt")
+//!     .header(header::CONTENT_TYPE, "text/plain")
+//!     .body(Body::from("hello"))?;
+//! let response = client.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod future;
+mod layer;
+mod service;
+mod signing;
+
+pub use self::{
+    body::AwsSigV4Body, future::ResponseFuture, layer::AwsSigV4Layer, service::AwsSigV4,
+};
+
+use crate::BoxError;
+use std::fmt;
+
+/// AWS credentials.
+#[derive(Clone)]
+pub struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    /// Create new long-term `Credentials`.
+    pub fn new(access_key_id: impl Into<String>, secret_access_key: impl Into<String>) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// Set the session token of temporary credentials.
+    pub fn session_token(mut self, session_token: impl Into<String>) -> Self {
+        self.session_token = Some(session_token.into());
+        self
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"** redacted **")
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| "** redacted **"),
+            )
+            .finish()
+    }
+}
+
+/// Trait for providing the [`Credentials`] requests are signed with.
+///
+/// This is implemented for [`Credentials`], which are used as they are, and for closures
+/// returning `Result<Credentials, BoxError>`, which can return cached temporary credentials.
+/// Refreshing them has to happen elsewhere, since this is called while signing.
+pub trait ProvideCredentials {
+    /// Provide the credentials for signing a request.
+    ///
+    /// If this fails the request isn't sent and the error is returned.
+    fn provide_credentials(&self) -> Result<Credentials, BoxError>;
+}
+
+impl ProvideCredentials for Credentials {
+    fn provide_credentials(&self) -> Result<Credentials, BoxError> {
+        Ok(self.clone())
+    }
+}
+
+impl<F> ProvideCredentials for F
+where
+    F: Fn() -> Result<Credentials, BoxError>,
+{
+    fn provide_credentials(&self) -> Result<Credentials, BoxError> {
+        self()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, HeaderMap, Request, Response};
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{service_fn, Layer, ServiceExt};
+
+    /// A client that returns the headers and body of the request it was sent.
+    async fn send(
+        layer: AwsSigV4Layer,
+        req: Request<Body>,
+    ) -> Result<(HeaderMap, Option<u64>, Bytes), BoxError> {
+        let svc = layer.layer(service_fn(|req: Request<AwsSigV4Body<Body>>| async move {
+            let (parts, body) = req.into_parts();
+            let size = body.size_hint().exact();
+            let body = hyper::body::to_bytes(body).await?;
+            Ok::<_, BoxError>(Response::new((parts.headers, size, body)))
+        }));
+        Ok(svc.oneshot(req).await?.into_body())
+    }
+
+    fn credentials() -> Credentials {
+        Credentials::new("AKID", "secret")
+    }
+
+    #[tokio::test]
+    async fn signs_requests() {
+        let layer = AwsSigV4Layer::new("es", "eu-west-1", credentials());
+        let req = Request::post("https://search.example.com/index/_doc")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::USER_AGENT, "test")
+            .body(Body::from("{}"))
+            .unwrap();
+        let (headers, size, body) = send(layer, req).await.unwrap();
+
+        let authorization = headers[header::AUTHORIZATION].to_str().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKID/"));
+        assert!(authorization.contains("/eu-west-1/es/aws4_request"));
+        assert!(authorization.contains("SignedHeaders=content-type;host;x-amz-date,"));
+        assert!(headers.contains_key("x-amz-date"));
+        assert!(!headers.contains_key("x-amz-content-sha256"));
+        assert_eq!(size, Some(2));
+        assert_eq!(body, "{}");
+    }
+
+    #[tokio::test]
+    async fn s3_and_session_tokens() {
+        let credentials = credentials().session_token("token");
+        let layer = AwsSigV4Layer::new("s3", "us-east-1", credentials);
+        let req = Request::get("https://bucket.s3.amazonaws.com/key")
+            .body(Body::empty())
+            .unwrap();
+        let (headers, _, _) = send(layer, req).await.unwrap();
+
+        assert_eq!(
+            headers["x-amz-content-sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(headers["x-amz-security-token"], "token");
+        let authorization = headers[header::AUTHORIZATION].to_str().unwrap();
+        assert!(authorization
+            .contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token,"));
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_unsigned() {
+        let layer = AwsSigV4Layer::new("s3", "us-east-1", credentials()).max_body_size(4);
+
+        // the size isn't known up front
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data("hel".into()).await.unwrap();
+            tx.send_data("lo ".into()).await.unwrap();
+            tx.send_data("world".into()).await.unwrap();
+        });
+        let req = Request::put("https://bucket.s3.amazonaws.com/key")
+            .body(body)
+            .unwrap();
+        let (headers, _, body) = send(layer.clone(), req).await.unwrap();
+        assert_eq!(headers["x-amz-content-sha256"], "UNSIGNED-PAYLOAD");
+        assert_eq!(body, "hello world");
+
+        // the size is known up front
+        let req = Request::put("https://bucket.s3.amazonaws.com/key")
+            .body(Body::from("hello world"))
+            .unwrap();
+        let (headers, size, body) = send(layer, req).await.unwrap();
+        assert_eq!(headers["x-amz-content-sha256"], "UNSIGNED-PAYLOAD");
+        assert_eq!(size, Some(11));
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn credential_errors() {
+        let layer = AwsSigV4Layer::new("s3", "us-east-1", || {
+            Err::<Credentials, BoxError>("expired".into())
+        });
+        let req = Request::get("https://bucket.s3.amazonaws.com/key")
+            .body(Body::empty())
+            .unwrap();
+        let err = send(layer, req).await.unwrap_err();
+        assert_eq!(err.to_string(), "expired");
+
+        // credentials that can't be sent in headers
+        let layer = AwsSigV4Layer::new("s3", "us-east-1", || {
+            Ok::<_, BoxError>(credentials().session_token("token\n"))
+        });
+        let req = Request::get("https://bucket.s3.amazonaws.com/key")
+            .body(Body::empty())
+            .unwrap();
+        assert!(send(layer, req).await.is_err());
+    }
+
+    #[test]
+    fn credentials_debug_is_redacted() {
+        let debug = format!("{:?}", credentials().session_token("token"));
+        assert!(!debug.contains("\"secret\""));
+        assert!(!debug.contains("\"token\""));
+    }
+}
@@ -0,0 +1,115 @@
+use super::{future::State, AwsSigV4Body, AwsSigV4Layer, ProvideCredentials, ResponseFuture};
+use crate::{buffer::Buffer, ready::take_ready, BoxError};
+use http::Request;
+use http_body::Body;
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Clone)]
+pub(super) struct Config {
+    pub(super) service: String,
+    pub(super) region: String,
+    pub(super) credentials: Arc<dyn ProvideCredentials + Send + Sync>,
+    pub(super) max_body_size: usize,
+}
+
+impl Config {
+    pub(super) fn new(
+        service: &str,
+        region: &str,
+        credentials: Arc<dyn ProvideCredentials + Send + Sync>,
+    ) -> Self {
+        Self {
+            service: service.to_owned(),
+            region: region.to_owned(),
+            credentials,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("service", &self.service)
+            .field("region", &self.region)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+/// Middleware that signs requests with AWS Signature Version 4.
+///
+/// See the [module docs](crate::aws_sigv4) for more details.
+#[derive(Clone, Debug)]
+pub struct AwsSigV4<S> {
+    pub(super) inner: S,
+    pub(super) config: Arc<Config>,
+}
+
+impl<S> AwsSigV4<S> {
+    /// Create a new [`AwsSigV4`] signing requests to `service` in `region` with the credentials
+    /// from `credentials`.
+    pub fn new<P>(inner: S, service: &str, region: &str, credentials: P) -> Self
+    where
+        P: ProvideCredentials + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            config: Arc::new(Config::new(service, region, Arc::new(credentials))),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with an `AwsSigV4` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<P>(service: &str, region: &str, credentials: P) -> AwsSigV4Layer
+    where
+        P: ProvideCredentials + Send + Sync + 'static,
+    {
+        AwsSigV4Layer::new(service, region, credentials)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Set the size of the largest body that is buffered to be signed.
+    ///
+    /// See [`AwsSigV4Layer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_body_size = limit;
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for AwsSigV4<S>
+where
+    S: Service<Request<AwsSigV4Body<ReqBody>>> + Clone,
+    S::Error: Into<BoxError>,
+    ReqBody: Body,
+    ReqBody::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (parts, body) = req.into_parts();
+        let state = State::Buffering {
+            buffer: Buffer::new(body, self.config.max_body_size),
+            parts: Some(parts),
+            service: Some(take_ready(&mut self.inner)),
+        };
+        ResponseFuture::new(state, self.config.clone())
+    }
+}
@@ -0,0 +1,279 @@
+//! The Signature Version 4 algorithm.
+
+use super::Credentials;
+use crate::date::civil_from_days;
+use hmac::{Hmac, Mac};
+use http::{
+    header::{self, InvalidHeaderValue},
+    request::Parts,
+    HeaderName, HeaderValue,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, convert::TryFrom, fmt::Write, time::SystemTime};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// The payload hash of bodies that are too large to be buffered.
+pub(super) const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+const X_AMZ_CONTENT_SHA256: HeaderName = HeaderName::from_static("x-amz-content-sha256");
+const X_AMZ_DATE: HeaderName = HeaderName::from_static("x-amz-date");
+const X_AMZ_SECURITY_TOKEN: HeaderName = HeaderName::from_static("x-amz-security-token");
+
+/// Everything except the unreserved characters of RFC 3986.
+const URI_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// The service, region and credentials a request is signed with.
+pub(super) struct Scope<'a> {
+    pub(super) service: &'a str,
+    pub(super) region: &'a str,
+    pub(super) credentials: &'a Credentials,
+}
+
+/// The lowercase hex encoded SHA-256 digest of `data`.
+pub(super) fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Sign a request by setting its `Authorization` and `X-Amz-*` headers.
+///
+/// `payload_hash` is the hex encoded SHA-256 digest of the body or [`UNSIGNED_PAYLOAD`].
+///
+/// Fails if the credentials can't be sent in headers.
+pub(super) fn sign(
+    parts: &mut Parts,
+    payload_hash: &str,
+    scope: &Scope<'_>,
+    time: SystemTime,
+) -> Result<(), InvalidHeaderValue> {
+    let (date, date_time) = format_time(time);
+
+    // the host header is signed, so set it in case the client would only add it later
+    if !parts.headers.contains_key(header::HOST) {
+        if let Some(authority) = parts.uri.authority() {
+            if let Ok(host) = HeaderValue::try_from(authority.as_str()) {
+                parts.headers.insert(header::HOST, host);
+            }
+        }
+    }
+    parts
+        .headers
+        .insert(X_AMZ_DATE, header_value(date_time.clone())?);
+    // S3 requires the payload hash, other services don't expect it
+    if scope.service == "s3" {
+        parts
+            .headers
+            .insert(X_AMZ_CONTENT_SHA256, header_value(payload_hash.to_owned())?);
+    }
+    if let Some(token) = &scope.credentials.session_token {
+        parts
+            .headers
+            .insert(X_AMZ_SECURITY_TOKEN, header_value(token.clone())?);
+    }
+
+    let (canonical_headers, signed_headers) = canonical_headers(parts);
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        parts.method,
+        canonical_path(parts.uri.path(), scope.service),
+        canonical_query(parts.uri.query().unwrap_or("")),
+        canonical_headers,
+        signed_headers,
+        payload_hash,
+    );
+
+    let credential_scope = format!("{}/{}/{}/aws4_request", date, scope.region, scope.service);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        date_time,
+        credential_scope,
+        sha256_hex(canonical_request.as_bytes()),
+    );
+
+    let secret = format!("AWS4{}", scope.credentials.secret_access_key);
+    let key = hmac(secret.as_bytes(), &date);
+    let key = hmac(&key, scope.region);
+    let key = hmac(&key, scope.service);
+    let key = hmac(&key, "aws4_request");
+    let signature = hex(&hmac(&key, &string_to_sign));
+
+    let authorization = format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM, scope.credentials.access_key_id, credential_scope, signed_headers, signature,
+    );
+    let mut authorization = header_value(authorization)?;
+    authorization.set_sensitive(true);
+    parts.headers.insert(header::AUTHORIZATION, authorization);
+    Ok(())
+}
+
+fn header_value(value: String) -> Result<HeaderValue, InvalidHeaderValue> {
+    HeaderValue::try_from(value)
+}
+
+/// Each path segment is URI encoded twice, except for S3 where they are encoded once.
+fn canonical_path(path: &str, service: &str) -> String {
+    if path.is_empty() {
+        return "/".to_owned();
+    }
+    path.split('/')
+        .map(|segment| {
+            let segment = percent_decode_str(segment).decode_utf8_lossy();
+            let encoded = utf8_percent_encode(&segment, URI_ENCODE).to_string();
+            if service == "s3" {
+                encoded
+            } else {
+                utf8_percent_encode(&encoded, URI_ENCODE).to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The URI encoded query parameters, sorted by name and value.
+fn canonical_query(query: &str) -> String {
+    let encode = |s: &str| {
+        let decoded = percent_decode_str(s).decode_utf8_lossy();
+        utf8_percent_encode(&decoded, URI_ENCODE).to_string()
+    };
+    let mut params = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            (encode(name), encode(value))
+        })
+        .collect::<Vec<_>>();
+    params.sort();
+    params
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The canonical headers and the list of signed headers.
+///
+/// Only `Host`, `Content-Type`, `Content-MD5` and `X-Amz-*` headers are signed, since proxies
+/// may change others.
+fn canonical_headers(parts: &Parts) -> (String, String) {
+    let mut headers = BTreeMap::<&str, Vec<String>>::new();
+    for (name, value) in &parts.headers {
+        let name = name.as_str();
+        let signed = name == "host"
+            || name == "content-type"
+            || name == "content-md5"
+            || name.starts_with("x-amz-");
+        if signed {
+            // trim the value and collapse sequential spaces
+            let value = String::from_utf8_lossy(value.as_bytes())
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            headers.entry(name).or_default().push(value);
+        }
+    }
+
+    let canonical = headers
+        .iter()
+        .map(|(name, values)| format!("{}:{}\n", name, values.join(",")))
+        .collect();
+    let signed = headers.keys().copied().collect::<Vec<_>>().join(";");
+    (canonical, signed)
+}
+
+/// Format `time` as a `YYYYMMDD` date and a `YYYYMMDD'T'HHMMSS'Z'` date and time.
+fn format_time(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    let (year, month, day) = civil_from_days(days as i64);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let date_time = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    (date, date_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Request;
+    use std::time::Duration;
+
+    fn time(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn formats_time() {
+        assert_eq!(format_time(time(0)).1, "19700101T000000Z");
+        assert_eq!(format_time(time(1_440_938_160)).1, "20150830T123600Z");
+        assert_eq!(format_time(time(951_782_400)).1, "20000229T000000Z");
+    }
+
+    #[test]
+    fn canonical_uri() {
+        assert_eq!(canonical_path("", "iam"), "/");
+        assert_eq!(canonical_path("/a b/c%20d", "s3"), "/a%20b/c%20d");
+        assert_eq!(canonical_path("/a%20b", "es"), "/a%2520b");
+        assert_eq!(canonical_query("b=2&a=%7e&a=1&c"), "a=1&a=~&b=2&c=");
+    }
+
+    // the example from the AWS documentation
+    #[test]
+    fn signs_example_request() {
+        let req = Request::get("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")
+            .header(
+                header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )
+            .body(())
+            .unwrap();
+        let (mut parts, _) = req.into_parts();
+        let credentials =
+            Credentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+        let scope = Scope {
+            service: "iam",
+            region: "us-east-1",
+            credentials: &credentials,
+        };
+        sign(&mut parts, &sha256_hex(b""), &scope, time(1_440_938_160)).unwrap();
+
+        assert_eq!(parts.headers["host"], "iam.amazonaws.com");
+        assert_eq!(parts.headers["x-amz-date"], "20150830T123600Z");
+        assert_eq!(
+            parts.headers[header::AUTHORIZATION],
+            "AWS4-HMAC-SHA256 \
+             Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+}
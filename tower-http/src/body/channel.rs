@@ -0,0 +1,287 @@
+use crate::BoxError;
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+
+/// Create a new channel backed body.
+///
+/// Returns a [`Sender`] used to produce data and trailers and a [`ChannelBody`] that yields them.
+/// `buffer` is the number of frames that can be queued before [`Sender::send_data`] waits for the
+/// body to be polled.
+///
+/// The body ends when the [`Sender`] is dropped or [`Sender::send_trailers`] is called.
+///
+/// # Panics
+///
+/// Panics if `buffer` is zero.
+///
+/// # Example
+///
+/// ```
+/// use bytes::Bytes;
+/// use http::{HeaderMap, HeaderValue, Response};
+/// use tower_http::body::channel;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), tower_http::BoxError> {
+/// let (mut tx, body) = channel(16);
+///
+/// tokio::spawn(async move {
+///     tx.send_data(Bytes::from("hello ")).await?;
+///     tx.send_data(Bytes::from("world")).await?;
+///
+///     let mut trailers = HeaderMap::new();
+///     trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+///     tx.send_trailers(trailers).await
+/// });
+///
+/// let response = Response::new(body);
+/// # let bytes = hyper::body::to_bytes(response.into_body()).await?;
+/// # assert_eq!(bytes, "hello world");
+/// # Ok(())
+/// # }
+/// ```
+pub fn channel(buffer: usize) -> (Sender, ChannelBody) {
+    let (tx, rx) = mpsc::channel(buffer);
+    let sender = Sender { tx };
+    let body = ChannelBody {
+        rx,
+        trailers: None,
+        done: false,
+    };
+    (sender, body)
+}
+
+enum Frame {
+    Data(Bytes),
+    Trailers(HeaderMap),
+    Error(BoxError),
+}
+
+/// The sending half of a [`channel`].
+pub struct Sender {
+    tx: mpsc::Sender<Frame>,
+}
+
+impl Sender {
+    /// Send a chunk of data, waiting for capacity if the buffer is full.
+    ///
+    /// Returns an error if the [`ChannelBody`] has been dropped.
+    pub async fn send_data(&mut self, data: Bytes) -> Result<(), SendError> {
+        self.tx
+            .send(Frame::Data(data))
+            .await
+            .map_err(|_| SendError(()))
+    }
+
+    /// Try to send a chunk of data without waiting.
+    ///
+    /// Returns the data back if the buffer is full or the [`ChannelBody`] has been dropped.
+    pub fn try_send_data(&mut self, data: Bytes) -> Result<(), Bytes> {
+        self.tx.try_send(Frame::Data(data)).map_err(|err| {
+            let frame = match err {
+                mpsc::error::TrySendError::Full(frame) => frame,
+                mpsc::error::TrySendError::Closed(frame) => frame,
+            };
+            match frame {
+                Frame::Data(data) => data,
+                Frame::Trailers(_) | Frame::Error(_) => unreachable!(),
+            }
+        })
+    }
+
+    /// Send trailers, ending the body.
+    ///
+    /// Returns an error if the [`ChannelBody`] has been dropped.
+    pub async fn send_trailers(self, trailers: HeaderMap) -> Result<(), SendError> {
+        self.tx
+            .send(Frame::Trailers(trailers))
+            .await
+            .map_err(|_| SendError(()))
+    }
+
+    /// Abort the body with an error.
+    ///
+    /// The next call to [`Body::poll_data`] on the [`ChannelBody`] will return the error, after
+    /// all previously sent data has been yielded.
+    pub async fn abort<E>(self, err: E) -> Result<(), SendError>
+    where
+        E: Into<BoxError>,
+    {
+        self.tx
+            .send(Frame::Error(err.into()))
+            .await
+            .map_err(|_| SendError(()))
+    }
+
+    /// Returns `true` if the [`ChannelBody`] has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl fmt::Debug for Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish()
+    }
+}
+
+/// A body that receives its data and trailers from a [`Sender`].
+///
+/// Created with [`channel`].
+pub struct ChannelBody {
+    rx: mpsc::Receiver<Frame>,
+    trailers: Option<HeaderMap>,
+    done: bool,
+}
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        match futures_core::ready!(self.rx.poll_recv(cx)) {
+            Some(Frame::Data(data)) => Poll::Ready(Some(Ok(data))),
+            Some(Frame::Trailers(trailers)) => {
+                self.trailers = Some(trailers);
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Some(Frame::Error(err)) => {
+                self.done = true;
+                Poll::Ready(Some(Err(err)))
+            }
+            None => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        // drain any remaining data frames so we see the trailers
+        while !self.done {
+            if let Some(Err(err)) = futures_core::ready!(self.as_mut().poll_data(cx)) {
+                return Poll::Ready(Err(err));
+            }
+        }
+
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.is_end_stream() {
+            SizeHint::with_exact(0)
+        } else {
+            SizeHint::default()
+        }
+    }
+}
+
+impl fmt::Debug for ChannelBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelBody")
+            .field("trailers", &self.trailers)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+/// Error returned by [`Sender`] if the [`ChannelBody`] has been dropped.
+#[derive(Debug)]
+pub struct SendError(());
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("channel body has been dropped")
+    }
+}
+
+impl std::error::Error for SendError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[tokio::test]
+    async fn data_and_trailers() {
+        let (mut tx, mut body) = channel(4);
+
+        tx.send_data(Bytes::from("one")).await.unwrap();
+        tx.try_send_data(Bytes::from("two")).unwrap();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-foo", HeaderValue::from_static("bar"));
+        tx.send_trailers(trailers).await.unwrap();
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "one");
+        assert_eq!(body.data().await.unwrap().unwrap(), "two");
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["x-foo"], "bar");
+        assert!(body.is_end_stream());
+    }
+
+    #[tokio::test]
+    async fn trailers_without_reading_data() {
+        let (mut tx, mut body) = channel(4);
+
+        tx.send_data(Bytes::from("one")).await.unwrap();
+        tx.send_trailers(HeaderMap::new()).await.unwrap();
+
+        assert!(body.trailers().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn ends_when_sender_is_dropped() {
+        let (mut tx, mut body) = channel(4);
+
+        tx.send_data(Bytes::from("one")).await.unwrap();
+        drop(tx);
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "one");
+        assert!(body.data().await.is_none());
+        assert!(body.trailers().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn abort() {
+        let (tx, mut body) = channel(4);
+
+        tx.abort("oh no").await.unwrap();
+
+        let err = body.data().await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "oh no");
+        assert!(body.data().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn send_errors_when_body_is_dropped() {
+        let (mut tx, body) = channel(4);
+        drop(body);
+
+        assert!(tx.is_closed());
+        assert!(tx.send_data(Bytes::from("one")).await.is_err());
+        assert_eq!(tx.try_send_data(Bytes::from("two")).unwrap_err(), "two");
+    }
+}
@@ -0,0 +1,18 @@
+//! Utilities for constructing response bodies.
+//!
+//! - [`channel`]: Create a body that is fed data and trailers through a [`Sender`].
+//! - [`StreamBody`]: Adapt a [`Stream`] of chunks into a body.
+//!
+//! These don't depend on any particular server implementation so services wrapped in middleware
+//! from this crate can produce streaming responses without pulling in types from [hyper].
+//!
+//! [`Stream`]: futures_core::Stream
+//! [hyper]: https://crates.io/crates/hyper
+
+mod channel;
+mod stream;
+
+pub use self::{
+    channel::{channel, ChannelBody, SendError, Sender},
+    stream::StreamBody,
+};
@@ -0,0 +1,122 @@
+use bytes::Buf;
+use futures_core::{ready, Stream};
+use http::HeaderMap;
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// A body created from a [`Stream`] of data chunks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use http::Response;
+    /// use std::convert::Infallible;
+    /// use tower_http::body::StreamBody;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let chunks: Vec<Result<_, Infallible>> = vec![
+    ///     Ok(Bytes::from("hello ")),
+    ///     Ok(Bytes::from("world")),
+    /// ];
+    /// let stream = futures::stream::iter(chunks);
+    ///
+    /// let response = Response::new(StreamBody::new(stream));
+    /// # let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    /// # assert_eq!(bytes, "hello world");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub struct StreamBody<S> {
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S> StreamBody<S> {
+    /// Create a new [`StreamBody`].
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// Consumes `self`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, D, E> Body for StreamBody<S>
+where
+    S: Stream<Item = Result<D, E>>,
+    D: Buf,
+{
+    type Data = D;
+    type Error = E;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut stream = self.project().stream;
+        loop {
+            match ready!(stream.as_mut().poll_next(cx)) {
+                // skip empty chunks so they aren't mistaken for the end of the body
+                Some(Ok(data)) if !data.has_remaining() => {}
+                other => return Poll::Ready(other),
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+impl<S> fmt::Debug for StreamBody<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamBody")
+            .field("stream", &std::any::type_name::<S>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn yields_chunks() {
+        let chunks: Vec<Result<_, &str>> = vec![
+            Ok(Bytes::from("one")),
+            Ok(Bytes::new()),
+            Ok(Bytes::from("two")),
+        ];
+        let mut body = StreamBody::new(futures::stream::iter(chunks));
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "one");
+        assert_eq!(body.data().await.unwrap().unwrap(), "two");
+        assert!(body.data().await.is_none());
+        assert!(body.trailers().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn yields_errors() {
+        let chunks: Vec<Result<Bytes, &str>> = vec![Err("oh no")];
+        let mut body = StreamBody::new(futures::stream::iter(chunks));
+
+        assert_eq!(body.data().await.unwrap().unwrap_err(), "oh no");
+    }
+}
@@ -0,0 +1,302 @@
+//! Middleware that erases the type of response bodies.
+//!
+//! Stacking middleware such as compression, decompression and body limits produces deeply nested
+//! response body types. That makes it hard to name the type of the final service, for example when
+//! it needs to be stored in a struct or returned from a function in another crate.
+//!
+//! [`BoxBodyLayer`] boxes the response body into a [`BoxBody`] with [`Bytes`] as the data type
+//! and [`BoxError`] as the error type. If the response body isn't `Sync` use
+//! [`UnsyncBoxBodyLayer`] which produces an [`UnsyncBoxBody`] instead.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body::combinators::BoxBody;
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, Service, ServiceExt};
+//! use tower_http::{box_body::BoxBodyLayer, BoxError};
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::from("foo")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut svc = ServiceBuilder::new()
+//!     .layer(BoxBodyLayer::new())
+//!     .service_fn(handle);
+//!
+//! // The response body type no longer depends on the inner service.
+//! let response: Response<BoxBody<Bytes, BoxError>> = svc
+//!     .ready()
+//!     .await?
+//!     .call(Request::new(Body::empty()))
+//!     .await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`BoxBody`]: http_body::combinators::BoxBody
+//! [`UnsyncBoxBody`]: http_body::combinators::UnsyncBoxBody
+
+use crate::BoxError;
+use bytes::Bytes;
+use futures_core::ready;
+use http::{Request, Response};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`BoxBody`] middleware which boxes response bodies.
+///
+/// See the [module docs](crate::box_body) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoxBodyLayer {
+    _priv: (),
+}
+
+impl BoxBodyLayer {
+    /// Create a new [`BoxBodyLayer`].
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for BoxBodyLayer {
+    type Service = BoxBody<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BoxBody::new(inner)
+    }
+}
+
+/// Middleware that boxes response bodies into an [`http_body::combinators::BoxBody`].
+///
+/// See the [module docs](crate::box_body) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct BoxBody<S> {
+    inner: S,
+}
+
+impl<S> BoxBody<S> {
+    /// Create a new [`BoxBody`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `BoxBody` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> BoxBodyLayer {
+        BoxBodyLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for BoxBody<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body<Data = Bytes> + Send + Sync + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<http_body::combinators::BoxBody<Bytes, BoxError>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`BoxBody`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<Response<http_body::combinators::BoxBody<Bytes, BoxError>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(self.project().inner.poll(cx)?);
+        Poll::Ready(Ok(res.map(|body| body.map_err(Into::into).boxed())))
+    }
+}
+
+/// Layer that applies the [`UnsyncBoxBody`] middleware which boxes response bodies.
+///
+/// See the [module docs](crate::box_body) for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnsyncBoxBodyLayer {
+    _priv: (),
+}
+
+impl UnsyncBoxBodyLayer {
+    /// Create a new [`UnsyncBoxBodyLayer`].
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for UnsyncBoxBodyLayer {
+    type Service = UnsyncBoxBody<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UnsyncBoxBody::new(inner)
+    }
+}
+
+/// Middleware that boxes response bodies into an [`http_body::combinators::UnsyncBoxBody`].
+///
+/// Unlike [`BoxBody`] this doesn't require the response body to be `Sync`.
+///
+/// See the [module docs](crate::box_body) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct UnsyncBoxBody<S> {
+    inner: S,
+}
+
+impl<S> UnsyncBoxBody<S> {
+    /// Create a new [`UnsyncBoxBody`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `UnsyncBoxBody` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> UnsyncBoxBodyLayer {
+        UnsyncBoxBodyLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for UnsyncBoxBody<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<http_body::combinators::UnsyncBoxBody<Bytes, BoxError>>;
+    type Error = S::Error;
+    type Future = UnsyncResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        UnsyncResponseFuture {
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`UnsyncBoxBody`].
+    pub struct UnsyncResponseFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, B, E> Future for UnsyncResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<Response<http_body::combinators::UnsyncBoxBody<Bytes, BoxError>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(self.project().inner.poll(cx)?);
+        Poll::Ready(Ok(res.map(|body| body.map_err(Into::into).boxed_unsync())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn boxes_body() {
+        let svc = ServiceBuilder::new()
+            .layer(BoxBodyLayer::new())
+            .service_fn(echo);
+
+        let res = svc
+            .oneshot(Request::new(Body::from("foobar")))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "foobar");
+    }
+
+    #[tokio::test]
+    async fn boxes_body_unsync() {
+        let svc = ServiceBuilder::new()
+            .layer(UnsyncBoxBodyLayer::new())
+            .service_fn(echo);
+
+        let res = svc
+            .oneshot(Request::new(Body::from("foobar")))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "foobar");
+    }
+
+    #[tokio::test]
+    async fn unifies_body_types() {
+        let a = ServiceBuilder::new()
+            .layer(BoxBodyLayer::new())
+            .service_fn(echo);
+        let b =
+            ServiceBuilder::new()
+                .layer(BoxBodyLayer::new())
+                .service_fn(|_: Request<Body>| async {
+                    Ok::<_, Infallible>(Response::new(http_body::Full::from("full")))
+                });
+
+        let svcs = vec![a.boxed_clone(), b.boxed_clone()];
+        for svc in svcs {
+            let res = svc.oneshot(Request::new(Body::from("echo"))).await.unwrap();
+            hyper::body::to_bytes(res.into_body()).await.unwrap();
+        }
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -0,0 +1,125 @@
+//! Helpers for middleware that needs the whole request body before calling its inner service.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::ready;
+use http_body::Body;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Future that buffers a request body of up to a limit, shared by the middleware that needs the
+/// whole body before calling its inner service.
+pub(crate) struct Buffer<B> {
+    body: Option<Pin<Box<B>>>,
+    data: BytesMut,
+    limit: usize,
+    #[cfg(feature = "buffer-request-body")]
+    trailers: bool,
+    // whether the data of the body has been read completely
+    data_done: bool,
+}
+
+/// The output of [`Buffer`], along with the rest of the body, which is only left with data or
+/// trailers to send if it's incomplete.
+pub(crate) struct Buffered {
+    /// The buffered data.
+    pub(crate) data: Bytes,
+    /// The trailers, if they were requested with [`Buffer::with_trailers`].
+    #[cfg(feature = "buffer-request-body")]
+    pub(crate) trailers: Option<http::HeaderMap>,
+    /// Whether all of the body's data has been buffered, or it turned out to be larger than the
+    /// limit.
+    pub(crate) complete: bool,
+}
+
+impl<B> Buffer<B> {
+    pub(crate) fn new(body: B, limit: usize) -> Self {
+        Self {
+            body: Some(Box::pin(body)),
+            data: BytesMut::new(),
+            limit,
+            #[cfg(feature = "buffer-request-body")]
+            trailers: false,
+            data_done: false,
+        }
+    }
+
+    /// Also read the trailers of bodies that have been buffered completely.
+    #[cfg(feature = "buffer-request-body")]
+    pub(crate) fn with_trailers(mut self) -> Self {
+        self.trailers = true;
+        self
+    }
+}
+
+impl<B> Future for Buffer<B>
+where
+    B: Body,
+{
+    type Output = Result<(Buffered, Pin<Box<B>>), B::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let body = this.body.as_mut().expect("future polled after completion");
+
+        // don't bother buffering bodies that are known to be too large
+        let mut complete = this.data.len() as u64 + body.size_hint().lower() <= this.limit as u64;
+        while complete && !this.data_done {
+            match ready!(body.as_mut().poll_data(cx)) {
+                Some(Ok(chunk)) => {
+                    // what has been read so far is sent followed by the rest of bodies that are
+                    // too large
+                    complete = this.data.len() + chunk.remaining() <= this.limit;
+                    this.data.put(chunk);
+                }
+                Some(Err(err)) => return Poll::Ready(Err(err)),
+                None => this.data_done = true,
+            }
+        }
+
+        #[cfg(feature = "buffer-request-body")]
+        let trailers = if complete && this.trailers {
+            ready!(body.as_mut().poll_trailers(cx))?
+        } else {
+            None
+        };
+        let buffered = Buffered {
+            data: this.data.split().freeze(),
+            #[cfg(feature = "buffer-request-body")]
+            trailers,
+            complete,
+        };
+        let rest = this.body.take().expect("future polled after completion");
+        Poll::Ready(Ok((buffered, rest)))
+    }
+}
+
+/// Returns `true` if the `Content-Length` of a request is larger than `limit`, so it can be
+/// rejected before reading its body.
+#[cfg(any(
+    feature = "buffer-request-body",
+    feature = "content-digest",
+    feature = "hmac-signature",
+))]
+pub(crate) fn content_length_exceeds(headers: &http::HeaderMap, limit: usize) -> bool {
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+        .map_or(false, |length| length > limit as u64)
+}
+
+#[cfg(any(
+    feature = "buffer-request-body",
+    feature = "content-digest",
+    feature = "hmac-signature",
+))]
+pub(crate) fn rejection<B>(status: http::StatusCode) -> http::Response<B>
+where
+    B: Default,
+{
+    let mut res = http::Response::new(B::default());
+    *res.status_mut() = status;
+    res
+}
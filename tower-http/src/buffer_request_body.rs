@@ -0,0 +1,409 @@
+//! Middleware that buffers request bodies.
+//!
+//! Some middleware needs the whole request body before handling a request, for example to
+//! verify a signature over it, to retry the request with the same body or to inspect its
+//! content. [`BufferRequestBody`] collects request bodies up to a limit before calling the inner
+//! service, which gets the request with a [`BufferedBody`] that can be cloned cheaply. The
+//! buffered bytes are also added to the request extensions as [`BufferedBytes`], so they stay
+//! available to inner middleware after the body has been consumed.
+//!
+//! Requests whose body is larger than the limit are rejected with `413 Payload Too Large`,
+//! before reading the body if their `Content-Length` header is too large already. Requests
+//! whose body fails are rejected with `400 Bad Request`.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use tower::{BoxError, ServiceBuilder, ServiceExt};
+//! use tower_http::buffer_request_body::{BufferRequestBodyLayer, BufferedBody, BufferedBytes};
+//!
+//! async fn handle(req: Request<BufferedBody>) -> Result<Response<Body>, BoxError> {
+//!     let bytes = req.extensions().get::<BufferedBytes>().unwrap();
+//!     Ok(Response::new(Body::from(format!("got {} bytes", bytes.as_bytes().len()))))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = ServiceBuilder::new()
+//!     // buffer request bodies of up to 4 KiB
+//!     .layer(BufferRequestBodyLayer::new(4096))
+//!     .service_fn(handle);
+//!
+//! let request = Request::post("/").body(Body::from("Hello, World!"))?;
+//! let response = service.clone().oneshot(request).await?;
+//! assert_eq!(
+//!     hyper::body::to_bytes(response.into_body()).await?,
+//!     Bytes::from("got 13 bytes"),
+//! );
+//!
+//! let request = Request::post("/").body(Body::from(vec![0u8; 5000]))?;
+//! let response = service.oneshot(request).await?;
+//! assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    buffer::{content_length_exceeds, rejection, Buffer},
+    ready::take_ready,
+};
+use bytes::Bytes;
+use futures_util::ready;
+use http::{request::Parts, HeaderMap, Request, Response, StatusCode};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    convert::Infallible,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Request body for [`BufferRequestBody`], which has been buffered in memory.
+///
+/// Clones share the buffered data.
+#[derive(Debug, Clone, Default)]
+pub struct BufferedBody {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl BufferedBody {
+    fn new(data: Bytes, trailers: Option<HeaderMap>) -> Self {
+        Self {
+            data: Some(data).filter(|data| !data.is_empty()),
+            trailers,
+        }
+    }
+}
+
+impl From<Bytes> for BufferedBody {
+    fn from(data: Bytes) -> Self {
+        Self::new(data, None)
+    }
+}
+
+impl Body for BufferedBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.data.as_ref().map_or(0, |data| data.len() as u64))
+    }
+}
+
+/// Request extension with the bytes buffered by [`BufferRequestBody`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferedBytes(Bytes);
+
+impl BufferedBytes {
+    /// Returns the buffered bytes.
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    /// Consume `self`, returning the buffered bytes.
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+/// Layer that applies [`BufferRequestBody`] which buffers request bodies.
+///
+/// See the [module docs](crate::buffer_request_body) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct BufferRequestBodyLayer {
+    limit: usize,
+}
+
+impl BufferRequestBodyLayer {
+    /// Create a new `BufferRequestBodyLayer` buffering request bodies of up to `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for BufferRequestBodyLayer {
+    type Service = BufferRequestBody<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BufferRequestBody {
+            inner,
+            limit: self.limit,
+        }
+    }
+}
+
+/// Middleware that buffers request bodies.
+///
+/// See the [module docs](crate::buffer_request_body) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct BufferRequestBody<S> {
+    inner: S,
+    limit: usize,
+}
+
+impl<S> BufferRequestBody<S> {
+    /// Create a new `BufferRequestBody` buffering request bodies of up to `limit` bytes.
+    pub fn new(inner: S, limit: usize) -> Self {
+        BufferRequestBodyLayer::new(limit).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `BufferRequestBody` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(limit: usize) -> BufferRequestBodyLayer {
+        BufferRequestBodyLayer::new(limit)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for BufferRequestBody<S>
+where
+    S: Service<Request<BufferedBody>, Response = Response<ResBody>> + Clone,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if content_length_exceeds(req.headers(), self.limit) {
+            return ResponseFuture::rejected(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let (parts, body) = req.into_parts();
+        ResponseFuture {
+            state: State::Buffering {
+                buffer: Buffer::new(body, self.limit).with_trailers(),
+                parts: Some(parts),
+                service: Some(take_ready(&mut self.inner)),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`BufferRequestBody`].
+    pub struct ResponseFuture<S, B>
+    where
+        S: Service<Request<BufferedBody>>,
+    {
+        #[pin]
+        state: State<S, B, S::Future>,
+    }
+}
+
+impl<S, B> ResponseFuture<S, B>
+where
+    S: Service<Request<BufferedBody>>,
+{
+    fn rejected(status: StatusCode) -> Self {
+        Self {
+            state: State::Rejected { status },
+        }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, B, F> {
+        Rejected {
+            status: StatusCode,
+        },
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, B, ResBody> Future for ResponseFuture<S, B>
+where
+    S: Service<Request<BufferedBody>, Response = Response<ResBody>>,
+    B: Body,
+    ResBody: Default,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Rejected { status } => return Poll::Ready(Ok(rejection(*status))),
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    service,
+                } => match ready!(buffer.poll(cx)) {
+                    Ok((buffered, _)) if buffered.complete => {
+                        let mut parts = parts.take().expect("future polled after completion");
+                        parts
+                            .extensions
+                            .insert(BufferedBytes(buffered.data.clone()));
+                        let body = BufferedBody::new(buffered.data, buffered.trailers);
+
+                        let mut service = service.take().expect("future polled after completion");
+                        State::Called {
+                            future: service.call(Request::from_parts(parts, body)),
+                        }
+                    }
+                    Ok(_) => State::Rejected {
+                        status: StatusCode::PAYLOAD_TOO_LARGE,
+                    },
+                    Err(_) => State::Rejected {
+                        status: StatusCode::BAD_REQUEST,
+                    },
+                },
+                StateProj::Called { future } => return future.poll(cx),
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<BufferedBody>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header;
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{BoxError, ServiceBuilder, ServiceExt};
+
+    async fn echo(req: Request<BufferedBody>) -> Result<Response<Body>, BoxError> {
+        let bytes = req.extensions().get::<BufferedBytes>().unwrap().clone();
+        let body = req.into_body();
+        assert_eq!(
+            body.size_hint().exact(),
+            Some(bytes.as_bytes().len() as u64)
+        );
+
+        // clones replay the same data
+        let first = hyper::body::to_bytes(body.clone()).await.unwrap();
+        let second = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first, bytes.into_bytes());
+        Ok(Response::new(Body::from(first)))
+    }
+
+    #[tokio::test]
+    async fn buffers_body() {
+        let svc = ServiceBuilder::new()
+            .layer(BufferRequestBodyLayer::new(16))
+            .service_fn(echo);
+
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data("hello ".into()).await.unwrap();
+            tx.send_data("world".into()).await.unwrap();
+        });
+        let res = svc.oneshot(Request::new(body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn keeps_trailers() {
+        let svc = ServiceBuilder::new()
+            .layer(BufferRequestBodyLayer::new(16))
+            .service_fn(|req: Request<BufferedBody>| async move {
+                let mut body = req.into_body();
+                assert_eq!(body.data().await.unwrap().unwrap(), "data");
+                let trailers = body.trailers().await.unwrap().unwrap();
+                assert_eq!(trailers["grpc-status"], "0");
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            });
+
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data("data".into()).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", "0".parse().unwrap());
+            tx.send_trailers(trailers).await.unwrap();
+        });
+        let res = svc.oneshot(Request::new(body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_large_bodies() {
+        let svc = ServiceBuilder::new()
+            .layer(BufferRequestBodyLayer::new(4))
+            .service_fn(echo);
+
+        // rejected without reading the body
+        let req = Request::post("/")
+            .header(header::CONTENT_LENGTH, "5")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data("abc".into()).await.unwrap();
+            tx.send_data("de".into()).await.unwrap();
+        });
+        let res = svc.oneshot(Request::new(body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn rejects_failing_bodies() {
+        let svc = ServiceBuilder::new()
+            .layer(BufferRequestBodyLayer::new(16))
+            .service_fn(echo);
+
+        let (tx, body) = Body::channel();
+        tx.abort();
+        let res = svc.oneshot(Request::new(body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
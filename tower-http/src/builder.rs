@@ -6,6 +6,8 @@ use crate::classify::{GrpcErrorsAsFailures, ServerErrorsAsFailures, SharedClassi
 #[allow(unused_imports)]
 use http::header::HeaderName;
 #[allow(unused_imports)]
+use std::time::Duration;
+#[allow(unused_imports)]
 use tower_layer::Stack;
 
 /// Extension trait that adds methods to [`tower::ServiceBuilder`] for adding middleware from
@@ -40,6 +42,27 @@ use tower_layer::Stack;
 /// # service.ready().await.unwrap().call(Request::new(Body::empty())).await.unwrap();
 /// # }
 /// ```
+///
+/// # Layers without methods
+///
+/// Some layers don't do anything useful until they're configured with their own builder methods,
+/// so they don't have a method here and are added with [`ServiceBuilder::layer`] instead:
+///
+/// - [`CorsLayer`], whose default rejects every cross-origin request.
+/// - [`ThrottleBodyLayer`], [`DeprecationLayer`], [`PreloadLinksLayer`], [`AllowedMethodsLayer`],
+///   [`ModifyQueryLayer`] and [`RewriteUriLayer`], which are built from rules and do nothing
+///   without them.
+/// - [`SteerByHostLayer`], which is built from the services for each host.
+///
+/// [`ServiceBuilder::layer`]: tower::ServiceBuilder::layer
+/// [`CorsLayer`]: crate::cors::CorsLayer
+/// [`ThrottleBodyLayer`]: crate::throttle::ThrottleBodyLayer
+/// [`DeprecationLayer`]: crate::deprecation::DeprecationLayer
+/// [`PreloadLinksLayer`]: crate::preload_links::PreloadLinksLayer
+/// [`AllowedMethodsLayer`]: crate::allowed_methods::AllowedMethodsLayer
+/// [`ModifyQueryLayer`]: crate::modify_query::ModifyQueryLayer
+/// [`RewriteUriLayer`]: crate::rewrite_uri::RewriteUriLayer
+/// [`SteerByHostLayer`]: crate::steer_by_host::SteerByHostLayer
 #[cfg(feature = "util")]
 // ^ work around rustdoc not inferring doc(cfg)s for cfg's from surrounding scopes
 pub trait ServiceBuilderExt<L>: crate::sealed::Sealed<L> + Sized {
@@ -367,6 +390,588 @@ pub trait ServiceBuilderExt<L>: crate::sealed::Sealed<L> + Sized {
     fn trim_trailing_slash(
         self,
     ) -> ServiceBuilder<Stack<crate::normalize_path::NormalizePathLayer, L>>;
+
+    /// Decompress request bodies.
+    ///
+    /// See [`tower_http::decompression`] for more details.
+    ///
+    /// [`tower_http::decompression`]: crate::decompression
+    #[cfg(any(
+        feature = "decompression-br",
+        feature = "decompression-deflate",
+        feature = "decompression-gzip",
+        feature = "decompression-zstd",
+    ))]
+    fn request_decompression(
+        self,
+    ) -> ServiceBuilder<Stack<crate::decompression::RequestDecompressionLayer, L>>;
+
+    /// Fail response bodies that are larger than `limit` bytes.
+    ///
+    /// See [`tower_http::limit`] for more details.
+    ///
+    /// [`tower_http::limit`]: crate::limit
+    #[cfg(feature = "limit")]
+    fn response_body_limit(
+        self,
+        limit: usize,
+    ) -> ServiceBuilder<Stack<crate::limit::ResponseBodyLimitLayer, L>>;
+
+    /// Set the `Content-Length` of requests whose body size is known.
+    ///
+    /// See [`tower_http::content_length`] for more details.
+    ///
+    /// [`tower_http::content_length`]: crate::content_length
+    #[cfg(feature = "content-length")]
+    fn request_content_length(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_length::RequestContentLengthLayer, L>>;
+
+    /// Set the `Content-Length` of responses whose body size is known.
+    ///
+    /// See [`tower_http::content_length`] for more details.
+    ///
+    /// [`tower_http::content_length`]: crate::content_length
+    #[cfg(feature = "content-length")]
+    fn response_content_length(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_length::ResponseContentLengthLayer, L>>;
+
+    /// Fail request bodies that take longer than `timeout` between frames.
+    ///
+    /// See [`tower_http::timeout`] for more details.
+    ///
+    /// [`tower_http::timeout`]: crate::timeout
+    #[cfg(feature = "timeout")]
+    fn request_body_timeout(
+        self,
+        timeout: Duration,
+    ) -> ServiceBuilder<Stack<crate::timeout::RequestBodyTimeoutLayer, L>>;
+
+    /// Fail response bodies that take longer than `timeout` between frames.
+    ///
+    /// See [`tower_http::timeout`] for more details.
+    ///
+    /// [`tower_http::timeout`]: crate::timeout
+    #[cfg(feature = "timeout")]
+    fn response_body_timeout(
+        self,
+        timeout: Duration,
+    ) -> ServiceBuilder<Stack<crate::timeout::ResponseBodyTimeoutLayer, L>>;
+
+    /// Fail requests that exceed the deadline set by the client.
+    ///
+    /// See [`tower_http::timeout`] for more details.
+    ///
+    /// [`tower_http::timeout`]: crate::timeout
+    #[cfg(feature = "timeout")]
+    fn enforce_deadline(self) -> ServiceBuilder<Stack<crate::timeout::EnforceDeadlineLayer, L>>;
+
+    /// Forward the remaining time of the deadline to upstream services.
+    ///
+    /// See [`tower_http::timeout`] for more details.
+    ///
+    /// [`tower_http::timeout`]: crate::timeout
+    #[cfg(feature = "timeout")]
+    fn propagate_deadline(self)
+        -> ServiceBuilder<Stack<crate::timeout::PropagateDeadlineLayer, L>>;
+
+    /// Add `ETag` headers to responses and answer conditional requests.
+    ///
+    /// See [`tower_http::etag`] for more details.
+    ///
+    /// [`tower_http::etag`]: crate::etag
+    #[cfg(feature = "etag")]
+    fn etag(self) -> ServiceBuilder<Stack<crate::etag::EtagLayer, L>>;
+
+    /// Answer `HEAD` requests with the headers of the `GET` response.
+    ///
+    /// See [`tower_http::auto_head`] for more details.
+    ///
+    /// [`tower_http::auto_head`]: crate::auto_head
+    #[cfg(feature = "auto-head")]
+    fn auto_head(self) -> ServiceBuilder<Stack<crate::auto_head::AutoHeadLayer, L>>;
+
+    /// Override the method of `POST` requests from a header or form field.
+    ///
+    /// See [`tower_http::method_override`] for more details.
+    ///
+    /// [`tower_http::method_override`]: crate::method_override
+    #[cfg(feature = "method-override")]
+    fn method_override(
+        self,
+    ) -> ServiceBuilder<Stack<crate::method_override::MethodOverrideLayer, L>>;
+
+    /// Let handlers send `103 Early Hints` responses.
+    ///
+    /// See [`tower_http::early_hints`] for more details.
+    ///
+    /// [`tower_http::early_hints`]: crate::early_hints
+    #[cfg(feature = "early-hints")]
+    fn early_hints(self) -> ServiceBuilder<Stack<crate::early_hints::SendEarlyHintsLayer, L>>;
+
+    /// Send keep-alive comments in idle server-sent event streams.
+    ///
+    /// See [`tower_http::sse_keep_alive`] for more details.
+    ///
+    /// [`tower_http::sse_keep_alive`]: crate::sse_keep_alive
+    #[cfg(feature = "sse-keep-alive")]
+    fn sse_keep_alive(
+        self,
+        interval: Duration,
+    ) -> ServiceBuilder<Stack<crate::sse_keep_alive::SseKeepAliveLayer, L>>;
+
+    /// Translate gRPC-Web requests to gRPC and back.
+    ///
+    /// See [`tower_http::grpc_web`] for more details.
+    ///
+    /// [`tower_http::grpc_web`]: crate::grpc_web
+    #[cfg(feature = "grpc-web")]
+    fn grpc_web(self) -> ServiceBuilder<Stack<crate::grpc_web::GrpcWebLayer, L>>;
+
+    /// Map the status of gRPC responses to HTTP status codes.
+    ///
+    /// See [`tower_http::map_grpc_status`] for more details.
+    ///
+    /// [`tower_http::map_grpc_status`]: crate::map_grpc_status
+    #[cfg(feature = "map-grpc-status")]
+    fn map_grpc_status(
+        self,
+    ) -> ServiceBuilder<Stack<crate::map_grpc_status::MapGrpcStatusLayer, L>>;
+
+    /// Resolve the IP address of the client into the request extensions.
+    ///
+    /// See [`tower_http::client_ip`] for more details.
+    ///
+    /// [`tower_http::client_ip`]: crate::client_ip
+    #[cfg(feature = "client-ip")]
+    fn client_ip(self) -> ServiceBuilder<Stack<crate::client_ip::ClientIpLayer, L>>;
+
+    /// Add `Forwarded` headers to proxied requests.
+    ///
+    /// See [`tower_http::add_forwarded`] for more details.
+    ///
+    /// [`tower_http::add_forwarded`]: crate::add_forwarded
+    #[cfg(feature = "add-forwarded")]
+    fn add_forwarded(self) -> ServiceBuilder<Stack<crate::add_forwarded::AddForwardedLayer, L>>;
+
+    /// Remove hop-by-hop headers from proxied requests and responses.
+    ///
+    /// See [`tower_http::remove_hop_by_hop`] for more details.
+    ///
+    /// [`tower_http::remove_hop_by_hop`]: crate::remove_hop_by_hop
+    #[cfg(feature = "remove-hop-by-hop")]
+    fn remove_hop_by_hop_headers(
+        self,
+    ) -> ServiceBuilder<Stack<crate::remove_hop_by_hop::RemoveHopByHopHeadersLayer, L>>;
+
+    /// Redirect plain HTTP requests to HTTPS.
+    ///
+    /// See [`tower_http::https_redirect`] for more details.
+    ///
+    /// [`tower_http::https_redirect`]: crate::https_redirect
+    #[cfg(feature = "https-redirect")]
+    fn https_redirect(self) -> ServiceBuilder<Stack<crate::https_redirect::HttpsRedirectLayer, L>>;
+
+    /// Normalize the percent-encoding of request URIs.
+    ///
+    /// See [`tower_http::normalize_percent_encoding`] for more details.
+    ///
+    /// [`tower_http::normalize_percent_encoding`]: crate::normalize_percent_encoding
+    #[cfg(feature = "normalize-percent-encoding")]
+    fn normalize_percent_encoding(
+        self,
+    ) -> ServiceBuilder<Stack<crate::normalize_percent_encoding::NormalizePercentEncodingLayer, L>>;
+
+    /// Remove a prefix from request paths.
+    ///
+    /// See [`tower_http::path_prefix`] for more details.
+    ///
+    /// [`tower_http::path_prefix`]: crate::path_prefix
+    #[cfg(feature = "path-prefix")]
+    fn strip_prefix(
+        self,
+        prefix: &str,
+    ) -> ServiceBuilder<Stack<crate::path_prefix::StripPrefixLayer, L>>;
+
+    /// Turn empty error responses into `application/problem+json` responses.
+    ///
+    /// See [`tower_http::problem_details`] for more details.
+    ///
+    /// [`tower_http::problem_details`]: crate::problem_details
+    #[cfg(feature = "problem-details")]
+    fn problem_details(
+        self,
+    ) -> ServiceBuilder<Stack<crate::problem_details::ProblemDetailsLayer, L>>;
+
+    /// Share the response of identical concurrent requests.
+    ///
+    /// See [`tower_http::coalesce`] for more details.
+    ///
+    /// [`tower_http::coalesce`]: crate::coalesce
+    #[cfg(feature = "coalesce")]
+    fn coalesce(self) -> ServiceBuilder<Stack<crate::coalesce::CoalesceLayer, L>>;
+
+    /// Reject requests until the service is ready.
+    ///
+    /// See [`tower_http::readiness`] for more details.
+    ///
+    /// [`tower_http::readiness`]: crate::readiness
+    #[cfg(feature = "readiness")]
+    fn readiness_gate(
+        self,
+        readiness: crate::readiness::Readiness,
+    ) -> ServiceBuilder<Stack<crate::readiness::ReadinessGateLayer, L>>;
+
+    /// Set the `Content-Digest` of request bodies.
+    ///
+    /// See [`tower_http::content_digest`] for more details.
+    ///
+    /// [`tower_http::content_digest`]: crate::content_digest
+    #[cfg(feature = "content-digest")]
+    fn set_content_digest(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_digest::SetContentDigestLayer, L>>;
+
+    /// Reject requests whose body doesn't match their `Content-Digest`.
+    ///
+    /// See [`tower_http::content_digest`] for more details.
+    ///
+    /// [`tower_http::content_digest`]: crate::content_digest
+    #[cfg(feature = "content-digest")]
+    fn validate_content_digest(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_digest::ValidateContentDigestLayer, L>>;
+
+    /// Parse request cookies and set the cookies queued by handlers.
+    ///
+    /// See [`tower_http::cookies`] for more details.
+    ///
+    /// [`tower_http::cookies`]: crate::cookies
+    #[cfg(feature = "cookies")]
+    fn cookies(self) -> ServiceBuilder<Stack<crate::cookies::CookiesLayer, L>>;
+
+    /// Keep cookies between requests made by clients.
+    ///
+    /// See [`tower_http::cookie_jar`] for more details.
+    ///
+    /// [`tower_http::cookie_jar`]: crate::cookie_jar
+    #[cfg(feature = "cookie-jar")]
+    fn manage_cookies(
+        self,
+        jar: crate::cookie_jar::CookieJar,
+    ) -> ServiceBuilder<Stack<crate::cookie_jar::ManageCookiesLayer, L>>;
+
+    /// Respond with a fixed status code, overriding the status of the inner service's responses.
+    ///
+    /// See [`tower_http::set_status`] for more details.
+    ///
+    /// [`tower_http::set_status`]: crate::set_status
+    #[cfg(feature = "set-status")]
+    fn set_status(
+        self,
+        status: http::StatusCode,
+    ) -> ServiceBuilder<Stack<crate::set_status::SetStatusLayer, L>>;
+
+    /// Respond with `503 Service Unavailable` when the inner service isn't ready.
+    ///
+    /// Named so it doesn't clash with [`ServiceBuilder::load_shed`], which fails with an error
+    /// instead.
+    ///
+    /// See [`tower_http::load_shed`] for more details.
+    ///
+    /// [`tower_http::load_shed`]: crate::load_shed
+    /// [`ServiceBuilder::load_shed`]: tower::ServiceBuilder
+    #[cfg(feature = "load-shed")]
+    fn load_shed_for_http(self) -> ServiceBuilder<Stack<crate::load_shed::LoadShedLayer, L>>;
+
+    /// Limit the rate of requests, responding with `429 Too Many Requests` once `quota` is
+    /// used up.
+    ///
+    /// Named so it doesn't clash with [`ServiceBuilder::rate_limit`].
+    ///
+    /// See [`tower_http::rate_limit`] for more details.
+    ///
+    /// [`tower_http::rate_limit`]: crate::rate_limit
+    /// [`ServiceBuilder::rate_limit`]: tower::ServiceBuilder
+    #[cfg(feature = "rate-limit")]
+    fn rate_limit_for_http(
+        self,
+        quota: crate::rate_limit::Quota,
+    ) -> ServiceBuilder<Stack<crate::rate_limit::RateLimitLayer, L>>;
+
+    /// Retry requests that failed with an error.
+    ///
+    /// Named so it doesn't clash with [`ServiceBuilder::retry`].
+    ///
+    /// See [`tower_http::retry`] for more details.
+    ///
+    /// [`tower_http::retry`]: crate::retry
+    /// [`ServiceBuilder::retry`]: tower::ServiceBuilder
+    #[cfg(feature = "retry")]
+    fn retry_for_http(self) -> ServiceBuilder<Stack<crate::retry::RetryLayer, L>>;
+
+    /// Stop calling the inner service while it keeps failing, counting errors and `5xx`
+    /// responses as failures.
+    ///
+    /// See [`tower_http::circuit_breaker`] for more details.
+    ///
+    /// [`tower_http::circuit_breaker`]: crate::circuit_breaker
+    #[cfg(feature = "circuit-breaker")]
+    fn circuit_breaker_for_http(
+        self,
+    ) -> ServiceBuilder<
+        Stack<
+            crate::circuit_breaker::CircuitBreakerLayer<
+                crate::classify::SharedClassifier<crate::classify::ServerErrorsAsFailures>,
+            >,
+            L,
+        >,
+    >;
+
+    /// Cache responses in `store`.
+    ///
+    /// See [`tower_http::cache`] for more details.
+    ///
+    /// [`tower_http::cache`]: crate::cache
+    #[cfg(feature = "cache")]
+    fn cache<T>(self, store: T) -> ServiceBuilder<Stack<crate::cache::CacheLayer, L>>
+    where
+        T: crate::cache::CacheStore;
+
+    /// Limit the number of concurrent requests, admitting requests by priority.
+    ///
+    /// See [`tower_http::prioritize`] for more details.
+    ///
+    /// [`tower_http::prioritize`]: crate::prioritize
+    #[cfg(feature = "prioritize")]
+    fn prioritize<C>(
+        self,
+        classifier: C,
+        max: usize,
+    ) -> ServiceBuilder<Stack<crate::prioritize::PrioritizeLayer<C>, L>>;
+
+    /// Send a copy of each request to a shadow service.
+    ///
+    /// See [`tower_http::mirror`] for more details.
+    ///
+    /// [`tower_http::mirror`]: crate::mirror
+    #[cfg(feature = "mirror")]
+    fn mirror<M>(self, shadow: M) -> ServiceBuilder<Stack<crate::mirror::MirrorLayer<M>, L>>;
+
+    /// Replay the stored response for requests with a repeated `Idempotency-Key`.
+    ///
+    /// See [`tower_http::idempotency`] for more details.
+    ///
+    /// [`tower_http::idempotency`]: crate::idempotency
+    #[cfg(feature = "idempotency")]
+    fn idempotency(self) -> ServiceBuilder<Stack<crate::idempotency::IdempotencyLayer, L>>;
+
+    /// Answer `Expect: 100-continue`, rejecting requests with a `Content-Length` larger than
+    /// `limit`.
+    ///
+    /// See [`tower_http::expect_continue`] for more details.
+    ///
+    /// [`tower_http::expect_continue`]: crate::expect_continue
+    #[cfg(feature = "expect-continue")]
+    fn expect_continue(
+        self,
+        limit: u64,
+    ) -> ServiceBuilder<
+        Stack<
+            crate::expect_continue::ExpectContinueLayer<crate::expect_continue::MaxContentLength>,
+            L,
+        >,
+    >;
+
+    /// Validate WebSocket upgrade requests.
+    ///
+    /// See [`tower_http::websocket_upgrade`] for more details.
+    ///
+    /// [`tower_http::websocket_upgrade`]: crate::websocket_upgrade
+    #[cfg(feature = "websocket-upgrade")]
+    fn websocket_upgrade(
+        self,
+    ) -> ServiceBuilder<Stack<crate::websocket_upgrade::WebSocketUpgradeLayer, L>>;
+
+    /// Negotiate the response media type from the `Accept` header.
+    ///
+    /// See [`tower_http::negotiate`] for more details.
+    ///
+    /// [`tower_http::negotiate`]: crate::negotiate
+    #[cfg(feature = "negotiate")]
+    fn negotiate<I>(
+        self,
+        media_types: I,
+    ) -> ServiceBuilder<Stack<crate::negotiate::NegotiateLayer, L>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>;
+
+    /// Sign requests with an HMAC of `key`.
+    ///
+    /// See [`tower_http::hmac_signature`] for more details.
+    ///
+    /// [`tower_http::hmac_signature`]: crate::hmac_signature
+    #[cfg(feature = "hmac-signature")]
+    fn sign_requests<K>(
+        self,
+        key: K,
+    ) -> ServiceBuilder<Stack<crate::hmac_signature::SignRequestLayer, L>>
+    where
+        K: AsRef<[u8]>;
+
+    /// Reject requests without a valid HMAC signature made with `key`.
+    ///
+    /// See [`tower_http::hmac_signature`] for more details.
+    ///
+    /// [`tower_http::hmac_signature`]: crate::hmac_signature
+    #[cfg(feature = "hmac-signature")]
+    fn verify_signatures<K>(
+        self,
+        key: K,
+    ) -> ServiceBuilder<Stack<crate::hmac_signature::VerifySignatureLayer, L>>
+    where
+        K: AsRef<[u8]>;
+
+    /// Box response bodies into [`BoxBody`](crate::box_body::BoxBody) so services with different
+    /// body types can be used interchangeably.
+    ///
+    /// See [`tower_http::box_body`] for more details.
+    ///
+    /// [`tower_http::box_body`]: crate::box_body
+    #[cfg(feature = "box-body")]
+    fn box_body(self) -> ServiceBuilder<Stack<crate::box_body::BoxBodyLayer, L>>;
+
+    /// Box response bodies into [`UnsyncBoxBody`](crate::box_body::UnsyncBoxBody), for bodies
+    /// that aren't `Sync`.
+    ///
+    /// See [`tower_http::box_body`] for more details.
+    ///
+    /// [`tower_http::box_body`]: crate::box_body
+    #[cfg(feature = "box-body")]
+    fn unsync_box_body(self) -> ServiceBuilder<Stack<crate::box_body::UnsyncBoxBodyLayer, L>>;
+
+    /// Report the lifecycle of requests to `recorder`.
+    ///
+    /// See [`tower_http::metrics::recorder`] for more details.
+    ///
+    /// [`tower_http::metrics::recorder`]: crate::metrics::recorder
+    #[cfg(feature = "metrics")]
+    fn metrics<R>(self, recorder: R) -> ServiceBuilder<Stack<crate::metrics::MetricsLayer<R>, L>>
+    where
+        R: crate::metrics::HttpMetricsRecorder;
+
+    /// Report the size of request bodies to `recorder`.
+    ///
+    /// See [`tower_http::metrics::recorder`] for more details.
+    ///
+    /// [`tower_http::metrics::recorder`]: crate::metrics::recorder
+    #[cfg(feature = "metrics")]
+    fn request_body_metrics<R>(
+        self,
+        recorder: R,
+    ) -> ServiceBuilder<Stack<crate::metrics::RequestBodyMetricsLayer<R>, L>>
+    where
+        R: crate::metrics::HttpMetricsRecorder;
+
+    /// Record request counts, durations and response sizes into `registry`.
+    ///
+    /// See [`tower_http::metrics::prometheus`] for more details.
+    ///
+    /// [`tower_http::metrics::prometheus`]: crate::metrics::prometheus
+    #[cfg(feature = "metrics")]
+    fn prometheus(
+        self,
+        registry: crate::metrics::prometheus::Registry,
+    ) -> ServiceBuilder<Stack<crate::metrics::prometheus::PrometheusLayer, L>>;
+
+    /// Count the requests in flight with `counter`.
+    ///
+    /// See [`tower_http::metrics::in_flight_requests`] for more details.
+    ///
+    /// [`tower_http::metrics::in_flight_requests`]: crate::metrics::in_flight_requests
+    #[cfg(feature = "metrics")]
+    fn in_flight_requests(
+        self,
+        counter: crate::metrics::in_flight_requests::InFlightRequestsCounter,
+    ) -> ServiceBuilder<Stack<crate::metrics::InFlightRequestsLayer, L>>;
+
+    /// Limit the number of concurrent requests, responding with `503 Service Unavailable` to
+    /// excess requests.
+    ///
+    /// Named so it doesn't clash with [`ServiceBuilder::concurrency_limit`], which waits for
+    /// capacity instead.
+    ///
+    /// See [`tower_http::concurrency_limit`] for more details.
+    ///
+    /// [`tower_http::concurrency_limit`]: crate::concurrency_limit
+    /// [`ServiceBuilder::concurrency_limit`]: tower::ServiceBuilder
+    #[cfg(feature = "concurrency-limit")]
+    fn concurrency_limit_for_http(
+        self,
+        max: usize,
+    ) -> ServiceBuilder<Stack<crate::concurrency_limit::ConcurrencyLimitLayer, L>>;
+
+    /// Convert errors from the inner service into responses with `handler`.
+    ///
+    /// See [`tower_http::handle_error`] for more details.
+    ///
+    /// [`tower_http::handle_error`]: crate::handle_error
+    #[cfg(feature = "handle-error")]
+    fn handle_error<H>(
+        self,
+        handler: H,
+    ) -> ServiceBuilder<Stack<crate::handle_error::HandleErrorLayer<H>, L>>;
+
+    /// Insert the affinity token from the cookie called `name` as a request extension.
+    ///
+    /// See [`tower_http::sticky_session`] for more details.
+    ///
+    /// [`tower_http::sticky_session`]: crate::sticky_session
+    #[cfg(feature = "sticky-session")]
+    fn extract_affinity_cookie(
+        self,
+        name: &str,
+    ) -> ServiceBuilder<Stack<crate::sticky_session::ExtractAffinityLayer, L>>;
+
+    /// Set the affinity cookie called `name` on responses that were steered to a backend.
+    ///
+    /// See [`tower_http::sticky_session`] for more details.
+    ///
+    /// [`tower_http::sticky_session`]: crate::sticky_session
+    #[cfg(feature = "sticky-session")]
+    fn set_affinity_cookie(
+        self,
+        name: &str,
+    ) -> ServiceBuilder<Stack<crate::sticky_session::SetAffinityCookieLayer, L>>;
+
+    /// Sign requests with HTTP Message Signatures made with `key`, identified by `key_id`.
+    ///
+    /// See [`tower_http::message_signature`] for more details.
+    ///
+    /// [`tower_http::message_signature`]: crate::message_signature
+    #[cfg(feature = "message-signature")]
+    fn sign_messages<K>(
+        self,
+        key_id: &str,
+        key: K,
+    ) -> ServiceBuilder<Stack<crate::message_signature::SignMessageLayer, L>>
+    where
+        K: crate::message_signature::SigningKey + Send + Sync + 'static;
+
+    /// Reject requests without a valid HTTP Message Signature, looking up keys with `resolver`.
+    ///
+    /// See [`tower_http::message_signature`] for more details.
+    ///
+    /// [`tower_http::message_signature`]: crate::message_signature
+    #[cfg(feature = "message-signature")]
+    fn verify_messages<R>(
+        self,
+        resolver: R,
+    ) -> ServiceBuilder<Stack<crate::message_signature::VerifyMessageLayer<R>, L>>
+    where
+        R: crate::message_signature::ResolveKey;
 }
 
 impl<L> crate::sealed::Sealed<L> for ServiceBuilder<L> {}
@@ -599,4 +1204,414 @@ impl<L> ServiceBuilderExt<L> for ServiceBuilder<L> {
     ) -> ServiceBuilder<Stack<crate::normalize_path::NormalizePathLayer, L>> {
         self.layer(crate::normalize_path::NormalizePathLayer::trim_trailing_slash())
     }
+
+    #[cfg(any(
+        feature = "decompression-br",
+        feature = "decompression-deflate",
+        feature = "decompression-gzip",
+        feature = "decompression-zstd",
+    ))]
+    fn request_decompression(
+        self,
+    ) -> ServiceBuilder<Stack<crate::decompression::RequestDecompressionLayer, L>> {
+        self.layer(crate::decompression::RequestDecompressionLayer::new())
+    }
+
+    #[cfg(feature = "limit")]
+    fn response_body_limit(
+        self,
+        limit: usize,
+    ) -> ServiceBuilder<Stack<crate::limit::ResponseBodyLimitLayer, L>> {
+        self.layer(crate::limit::ResponseBodyLimitLayer::new(limit))
+    }
+
+    #[cfg(feature = "content-length")]
+    fn request_content_length(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_length::RequestContentLengthLayer, L>> {
+        self.layer(crate::content_length::RequestContentLengthLayer::new())
+    }
+
+    #[cfg(feature = "content-length")]
+    fn response_content_length(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_length::ResponseContentLengthLayer, L>> {
+        self.layer(crate::content_length::ResponseContentLengthLayer::new())
+    }
+
+    #[cfg(feature = "timeout")]
+    fn request_body_timeout(
+        self,
+        timeout: Duration,
+    ) -> ServiceBuilder<Stack<crate::timeout::RequestBodyTimeoutLayer, L>> {
+        self.layer(crate::timeout::RequestBodyTimeoutLayer::new(timeout))
+    }
+
+    #[cfg(feature = "timeout")]
+    fn response_body_timeout(
+        self,
+        timeout: Duration,
+    ) -> ServiceBuilder<Stack<crate::timeout::ResponseBodyTimeoutLayer, L>> {
+        self.layer(crate::timeout::ResponseBodyTimeoutLayer::new(timeout))
+    }
+
+    #[cfg(feature = "timeout")]
+    fn enforce_deadline(self) -> ServiceBuilder<Stack<crate::timeout::EnforceDeadlineLayer, L>> {
+        self.layer(crate::timeout::EnforceDeadlineLayer::new())
+    }
+
+    #[cfg(feature = "timeout")]
+    fn propagate_deadline(
+        self,
+    ) -> ServiceBuilder<Stack<crate::timeout::PropagateDeadlineLayer, L>> {
+        self.layer(crate::timeout::PropagateDeadlineLayer::new())
+    }
+
+    #[cfg(feature = "etag")]
+    fn etag(self) -> ServiceBuilder<Stack<crate::etag::EtagLayer, L>> {
+        self.layer(crate::etag::EtagLayer::new())
+    }
+
+    #[cfg(feature = "auto-head")]
+    fn auto_head(self) -> ServiceBuilder<Stack<crate::auto_head::AutoHeadLayer, L>> {
+        self.layer(crate::auto_head::AutoHeadLayer::new())
+    }
+
+    #[cfg(feature = "method-override")]
+    fn method_override(
+        self,
+    ) -> ServiceBuilder<Stack<crate::method_override::MethodOverrideLayer, L>> {
+        self.layer(crate::method_override::MethodOverrideLayer::new())
+    }
+
+    #[cfg(feature = "early-hints")]
+    fn early_hints(self) -> ServiceBuilder<Stack<crate::early_hints::SendEarlyHintsLayer, L>> {
+        self.layer(crate::early_hints::SendEarlyHintsLayer::new())
+    }
+
+    #[cfg(feature = "sse-keep-alive")]
+    fn sse_keep_alive(
+        self,
+        interval: Duration,
+    ) -> ServiceBuilder<Stack<crate::sse_keep_alive::SseKeepAliveLayer, L>> {
+        self.layer(crate::sse_keep_alive::SseKeepAliveLayer::new(interval))
+    }
+
+    #[cfg(feature = "grpc-web")]
+    fn grpc_web(self) -> ServiceBuilder<Stack<crate::grpc_web::GrpcWebLayer, L>> {
+        self.layer(crate::grpc_web::GrpcWebLayer::new())
+    }
+
+    #[cfg(feature = "map-grpc-status")]
+    fn map_grpc_status(
+        self,
+    ) -> ServiceBuilder<Stack<crate::map_grpc_status::MapGrpcStatusLayer, L>> {
+        self.layer(crate::map_grpc_status::MapGrpcStatusLayer::new())
+    }
+
+    #[cfg(feature = "client-ip")]
+    fn client_ip(self) -> ServiceBuilder<Stack<crate::client_ip::ClientIpLayer, L>> {
+        self.layer(crate::client_ip::ClientIpLayer::new())
+    }
+
+    #[cfg(feature = "add-forwarded")]
+    fn add_forwarded(self) -> ServiceBuilder<Stack<crate::add_forwarded::AddForwardedLayer, L>> {
+        self.layer(crate::add_forwarded::AddForwardedLayer::new())
+    }
+
+    #[cfg(feature = "remove-hop-by-hop")]
+    fn remove_hop_by_hop_headers(
+        self,
+    ) -> ServiceBuilder<Stack<crate::remove_hop_by_hop::RemoveHopByHopHeadersLayer, L>> {
+        self.layer(crate::remove_hop_by_hop::RemoveHopByHopHeadersLayer::new())
+    }
+
+    #[cfg(feature = "https-redirect")]
+    fn https_redirect(self) -> ServiceBuilder<Stack<crate::https_redirect::HttpsRedirectLayer, L>> {
+        self.layer(crate::https_redirect::HttpsRedirectLayer::new())
+    }
+
+    #[cfg(feature = "normalize-percent-encoding")]
+    fn normalize_percent_encoding(
+        self,
+    ) -> ServiceBuilder<Stack<crate::normalize_percent_encoding::NormalizePercentEncodingLayer, L>>
+    {
+        self.layer(crate::normalize_percent_encoding::NormalizePercentEncodingLayer::new())
+    }
+
+    #[cfg(feature = "path-prefix")]
+    fn strip_prefix(
+        self,
+        prefix: &str,
+    ) -> ServiceBuilder<Stack<crate::path_prefix::StripPrefixLayer, L>> {
+        self.layer(crate::path_prefix::StripPrefixLayer::new(prefix))
+    }
+
+    #[cfg(feature = "problem-details")]
+    fn problem_details(
+        self,
+    ) -> ServiceBuilder<Stack<crate::problem_details::ProblemDetailsLayer, L>> {
+        self.layer(crate::problem_details::ProblemDetailsLayer::new())
+    }
+
+    #[cfg(feature = "coalesce")]
+    fn coalesce(self) -> ServiceBuilder<Stack<crate::coalesce::CoalesceLayer, L>> {
+        self.layer(crate::coalesce::CoalesceLayer::new())
+    }
+
+    #[cfg(feature = "readiness")]
+    fn readiness_gate(
+        self,
+        readiness: crate::readiness::Readiness,
+    ) -> ServiceBuilder<Stack<crate::readiness::ReadinessGateLayer, L>> {
+        self.layer(crate::readiness::ReadinessGateLayer::new(readiness))
+    }
+
+    #[cfg(feature = "content-digest")]
+    fn set_content_digest(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_digest::SetContentDigestLayer, L>> {
+        self.layer(crate::content_digest::SetContentDigestLayer::new())
+    }
+
+    #[cfg(feature = "content-digest")]
+    fn validate_content_digest(
+        self,
+    ) -> ServiceBuilder<Stack<crate::content_digest::ValidateContentDigestLayer, L>> {
+        self.layer(crate::content_digest::ValidateContentDigestLayer::new())
+    }
+
+    #[cfg(feature = "cookies")]
+    fn cookies(self) -> ServiceBuilder<Stack<crate::cookies::CookiesLayer, L>> {
+        self.layer(crate::cookies::CookiesLayer::new())
+    }
+
+    #[cfg(feature = "cookie-jar")]
+    fn manage_cookies(
+        self,
+        jar: crate::cookie_jar::CookieJar,
+    ) -> ServiceBuilder<Stack<crate::cookie_jar::ManageCookiesLayer, L>> {
+        self.layer(crate::cookie_jar::ManageCookiesLayer::new(jar))
+    }
+
+    #[cfg(feature = "set-status")]
+    fn set_status(
+        self,
+        status: http::StatusCode,
+    ) -> ServiceBuilder<Stack<crate::set_status::SetStatusLayer, L>> {
+        self.layer(crate::set_status::SetStatusLayer::new(status))
+    }
+
+    #[cfg(feature = "load-shed")]
+    fn load_shed_for_http(self) -> ServiceBuilder<Stack<crate::load_shed::LoadShedLayer, L>> {
+        self.layer(crate::load_shed::LoadShedLayer::new())
+    }
+
+    #[cfg(feature = "rate-limit")]
+    fn rate_limit_for_http(
+        self,
+        quota: crate::rate_limit::Quota,
+    ) -> ServiceBuilder<Stack<crate::rate_limit::RateLimitLayer, L>> {
+        self.layer(crate::rate_limit::RateLimitLayer::new(quota))
+    }
+
+    #[cfg(feature = "retry")]
+    fn retry_for_http(self) -> ServiceBuilder<Stack<crate::retry::RetryLayer, L>> {
+        self.layer(crate::retry::RetryLayer::new())
+    }
+
+    #[cfg(feature = "circuit-breaker")]
+    fn circuit_breaker_for_http(
+        self,
+    ) -> ServiceBuilder<
+        Stack<
+            crate::circuit_breaker::CircuitBreakerLayer<
+                crate::classify::SharedClassifier<crate::classify::ServerErrorsAsFailures>,
+            >,
+            L,
+        >,
+    > {
+        self.layer(crate::circuit_breaker::CircuitBreakerLayer::new_for_http())
+    }
+
+    #[cfg(feature = "cache")]
+    fn cache<T>(self, store: T) -> ServiceBuilder<Stack<crate::cache::CacheLayer, L>>
+    where
+        T: crate::cache::CacheStore,
+    {
+        self.layer(crate::cache::CacheLayer::new(store))
+    }
+
+    #[cfg(feature = "prioritize")]
+    fn prioritize<C>(
+        self,
+        classifier: C,
+        max: usize,
+    ) -> ServiceBuilder<Stack<crate::prioritize::PrioritizeLayer<C>, L>> {
+        self.layer(crate::prioritize::PrioritizeLayer::new(classifier, max))
+    }
+
+    #[cfg(feature = "mirror")]
+    fn mirror<M>(self, shadow: M) -> ServiceBuilder<Stack<crate::mirror::MirrorLayer<M>, L>> {
+        self.layer(crate::mirror::MirrorLayer::new(shadow))
+    }
+
+    #[cfg(feature = "idempotency")]
+    fn idempotency(self) -> ServiceBuilder<Stack<crate::idempotency::IdempotencyLayer, L>> {
+        self.layer(crate::idempotency::IdempotencyLayer::new())
+    }
+
+    #[cfg(feature = "expect-continue")]
+    fn expect_continue(
+        self,
+        limit: u64,
+    ) -> ServiceBuilder<
+        Stack<
+            crate::expect_continue::ExpectContinueLayer<crate::expect_continue::MaxContentLength>,
+            L,
+        >,
+    > {
+        self.layer(crate::expect_continue::ExpectContinueLayer::max_content_length(limit))
+    }
+
+    #[cfg(feature = "websocket-upgrade")]
+    fn websocket_upgrade(
+        self,
+    ) -> ServiceBuilder<Stack<crate::websocket_upgrade::WebSocketUpgradeLayer, L>> {
+        self.layer(crate::websocket_upgrade::WebSocketUpgradeLayer::new())
+    }
+
+    #[cfg(feature = "negotiate")]
+    fn negotiate<I>(
+        self,
+        media_types: I,
+    ) -> ServiceBuilder<Stack<crate::negotiate::NegotiateLayer, L>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.layer(crate::negotiate::NegotiateLayer::new(media_types))
+    }
+
+    #[cfg(feature = "hmac-signature")]
+    fn sign_requests<K>(
+        self,
+        key: K,
+    ) -> ServiceBuilder<Stack<crate::hmac_signature::SignRequestLayer, L>>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.layer(crate::hmac_signature::SignRequestLayer::new(key))
+    }
+
+    #[cfg(feature = "hmac-signature")]
+    fn verify_signatures<K>(
+        self,
+        key: K,
+    ) -> ServiceBuilder<Stack<crate::hmac_signature::VerifySignatureLayer, L>>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.layer(crate::hmac_signature::VerifySignatureLayer::new(key))
+    }
+
+    #[cfg(feature = "box-body")]
+    fn box_body(self) -> ServiceBuilder<Stack<crate::box_body::BoxBodyLayer, L>> {
+        self.layer(crate::box_body::BoxBodyLayer::new())
+    }
+
+    #[cfg(feature = "box-body")]
+    fn unsync_box_body(self) -> ServiceBuilder<Stack<crate::box_body::UnsyncBoxBodyLayer, L>> {
+        self.layer(crate::box_body::UnsyncBoxBodyLayer::new())
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics<R>(self, recorder: R) -> ServiceBuilder<Stack<crate::metrics::MetricsLayer<R>, L>>
+    where
+        R: crate::metrics::HttpMetricsRecorder,
+    {
+        self.layer(crate::metrics::MetricsLayer::new(recorder))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn request_body_metrics<R>(
+        self,
+        recorder: R,
+    ) -> ServiceBuilder<Stack<crate::metrics::RequestBodyMetricsLayer<R>, L>>
+    where
+        R: crate::metrics::HttpMetricsRecorder,
+    {
+        self.layer(crate::metrics::RequestBodyMetricsLayer::new(recorder))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn prometheus(
+        self,
+        registry: crate::metrics::prometheus::Registry,
+    ) -> ServiceBuilder<Stack<crate::metrics::prometheus::PrometheusLayer, L>> {
+        self.layer(crate::metrics::prometheus::PrometheusLayer::new(registry))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn in_flight_requests(
+        self,
+        counter: crate::metrics::in_flight_requests::InFlightRequestsCounter,
+    ) -> ServiceBuilder<Stack<crate::metrics::InFlightRequestsLayer, L>> {
+        self.layer(crate::metrics::InFlightRequestsLayer::new(counter))
+    }
+
+    #[cfg(feature = "concurrency-limit")]
+    fn concurrency_limit_for_http(
+        self,
+        max: usize,
+    ) -> ServiceBuilder<Stack<crate::concurrency_limit::ConcurrencyLimitLayer, L>> {
+        self.layer(crate::concurrency_limit::ConcurrencyLimitLayer::new(max))
+    }
+
+    #[cfg(feature = "handle-error")]
+    fn handle_error<H>(
+        self,
+        handler: H,
+    ) -> ServiceBuilder<Stack<crate::handle_error::HandleErrorLayer<H>, L>> {
+        self.layer(crate::handle_error::HandleErrorLayer::new(handler))
+    }
+
+    #[cfg(feature = "sticky-session")]
+    fn extract_affinity_cookie(
+        self,
+        name: &str,
+    ) -> ServiceBuilder<Stack<crate::sticky_session::ExtractAffinityLayer, L>> {
+        self.layer(crate::sticky_session::ExtractAffinityLayer::cookie(name))
+    }
+
+    #[cfg(feature = "sticky-session")]
+    fn set_affinity_cookie(
+        self,
+        name: &str,
+    ) -> ServiceBuilder<Stack<crate::sticky_session::SetAffinityCookieLayer, L>> {
+        self.layer(crate::sticky_session::SetAffinityCookieLayer::new(name))
+    }
+
+    #[cfg(feature = "message-signature")]
+    fn sign_messages<K>(
+        self,
+        key_id: &str,
+        key: K,
+    ) -> ServiceBuilder<Stack<crate::message_signature::SignMessageLayer, L>>
+    where
+        K: crate::message_signature::SigningKey + Send + Sync + 'static,
+    {
+        self.layer(crate::message_signature::SignMessageLayer::new(key_id, key))
+    }
+
+    #[cfg(feature = "message-signature")]
+    fn verify_messages<R>(
+        self,
+        resolver: R,
+    ) -> ServiceBuilder<Stack<crate::message_signature::VerifyMessageLayer<R>, L>>
+    where
+        R: crate::message_signature::ResolveKey,
+    {
+        self.layer(crate::message_signature::VerifyMessageLayer::new(resolver))
+    }
 }
@@ -0,0 +1,171 @@
+use super::store::{CachedResponse, SharedStore};
+use bytes::{Bytes, BytesMut};
+use futures_core::ready;
+use http::HeaderMap;
+use http_body::{Body, Full, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryInto,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response body for [`Cache`].
+    ///
+    /// [`Cache`]: super::Cache
+    pub struct ResponseBody<B> {
+        #[pin]
+        inner: ResponseBodyInner<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyProj]
+    enum ResponseBodyInner<B> {
+        Cached {
+            #[pin]
+            body: Full<Bytes>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            recorder: Option<Recorder>,
+        },
+    }
+}
+
+/// Collects the body of a response to store it once it is complete.
+pub(super) struct Recorder {
+    pub(super) store: SharedStore,
+    pub(super) key: String,
+    pub(super) response: CachedResponse,
+    pub(super) buf: BytesMut,
+    pub(super) max_size: usize,
+}
+
+impl Recorder {
+    fn finish(self) {
+        let mut response = self.response;
+        response.body = self.buf.freeze();
+        self.store.0.put(&self.key, response);
+    }
+}
+
+impl<B> ResponseBody<B> {
+    pub(super) fn cached(body: Bytes) -> Self {
+        Self {
+            inner: ResponseBodyInner::Cached {
+                body: Full::new(body),
+            },
+        }
+    }
+
+    pub(super) fn new(body: B) -> Self {
+        Self {
+            inner: ResponseBodyInner::Body {
+                body,
+                recorder: None,
+            },
+        }
+    }
+
+    pub(super) fn recorded(body: B, recorder: Recorder) -> Self
+    where
+        B: Body,
+    {
+        let too_large = body
+            .size_hint()
+            .lower()
+            .try_into()
+            .map_or(true, |lower: usize| lower > recorder.max_size);
+        if too_large {
+            return Self::new(body);
+        }
+
+        // empty bodies might never be polled
+        if body.is_end_stream() {
+            recorder.finish();
+            return Self::new(body);
+        }
+
+        Self {
+            inner: ResponseBodyInner::Body {
+                body,
+                recorder: Some(recorder),
+            },
+        }
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let (mut body, recorder) = match self.project().inner.project() {
+            BodyProj::Cached { body } => {
+                return body.poll_data(cx).map_err(|err| match err {});
+            }
+            BodyProj::Body { body, recorder } => (body, recorder),
+        };
+
+        let result = ready!(body.as_mut().poll_data(cx));
+        match &result {
+            Some(Ok(data)) => {
+                if let Some(rec) = recorder {
+                    if rec.buf.len() + data.len() > rec.max_size {
+                        *recorder = None;
+                    } else {
+                        rec.buf.extend_from_slice(data);
+                    }
+                }
+                // hyper stops polling once the body says it has ended, so it
+                // might never see the final `None`
+                if body.is_end_stream() {
+                    if let Some(recorder) = recorder.take() {
+                        recorder.finish();
+                    }
+                }
+            }
+            // bodies that failed might be incomplete
+            Some(Err(_)) => *recorder = None,
+            None => {
+                if let Some(recorder) = recorder.take() {
+                    recorder.finish();
+                }
+            }
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().inner.project() {
+            BodyProj::Cached { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyProj::Body { body, .. } => body.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            ResponseBodyInner::Cached { body } => body.is_end_stream(),
+            ResponseBodyInner::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            ResponseBodyInner::Cached { body } => body.size_hint(),
+            ResponseBodyInner::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use super::{
+    body::Recorder,
+    policy,
+    store::{CachedResponse, SharedStore},
+    ResponseBody,
+};
+use bytes::{Bytes, BytesMut};
+use futures_core::ready;
+use http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+pin_project! {
+    /// Response future for [`Cache`].
+    ///
+    /// [`Cache`]: super::Cache
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: ResponseFutureInner<F>,
+    }
+}
+
+pin_project! {
+    #[project = ResFutProj]
+    enum ResponseFutureInner<F> {
+        Cached {
+            response: Option<Response<Bytes>>,
+        },
+        Future {
+            #[pin]
+            future: F,
+            action: Option<Action>,
+        },
+    }
+}
+
+/// What to do with the response of the inner service.
+pub(super) enum Action {
+    None,
+    Store(Pending),
+    /// Serve the stale response of the [`Pending`] if the inner service responds with
+    /// `304 Not Modified`.
+    Revalidate(Pending),
+    /// Remove the cached response if the request was successful.
+    Invalidate(SharedStore, String),
+}
+
+/// A request whose response might be stored.
+pub(super) struct Pending {
+    pub(super) store: SharedStore,
+    pub(super) key: String,
+    pub(super) request_headers: HeaderMap,
+    pub(super) max_object_size: usize,
+    pub(super) head: bool,
+    pub(super) shared: bool,
+    /// The stored response that is stale, served if the inner service fails and
+    /// `stale-if-error` allows it.
+    pub(super) stale: Option<CachedResponse>,
+    /// The `stale-if-error` directive of the request.
+    pub(super) stale_if_error: Option<Duration>,
+}
+
+impl Pending {
+    /// The stale response to serve instead of an error or a `5xx` response, if any.
+    fn stale_if_error(&self, now: SystemTime) -> Option<Response<Bytes>> {
+        let stale = self.stale.as_ref()?;
+        let window = self
+            .stale_if_error
+            .or_else(|| policy::CacheControl::from_headers(stale.headers()).stale_if_error);
+        if stale.can_serve_stale(window, now, self.shared) {
+            Some(cached_response(stale, now, self.head, false))
+        } else {
+            None
+        }
+    }
+
+    fn response<B>(self, res: Response<B>) -> Response<ResponseBody<B>>
+    where
+        B: Body,
+    {
+        let now = SystemTime::now();
+        if self.head
+            || !policy::is_storable(
+                &self.request_headers,
+                res.status(),
+                res.headers(),
+                now,
+                self.shared,
+            )
+        {
+            return res.map(ResponseBody::new);
+        }
+
+        let recorder = Recorder {
+            response: CachedResponse::new(
+                res.status(),
+                res.headers().clone(),
+                Bytes::new(),
+                &self.request_headers,
+                now,
+            ),
+            store: self.store,
+            key: self.key,
+            buf: BytesMut::new(),
+            max_size: self.max_object_size,
+        };
+        res.map(|body| ResponseBody::recorded(body, recorder))
+    }
+}
+
+/// Build a response from the cache.
+pub(super) fn cached_response(
+    cached: &CachedResponse,
+    now: SystemTime,
+    head: bool,
+    not_modified: bool,
+) -> Response<Bytes> {
+    let mut res = Response::new(cached.body.clone());
+    *res.status_mut() = cached.status;
+    *res.headers_mut() = cached.headers.clone();
+    res.headers_mut().insert(
+        header::AGE,
+        HeaderValue::from(cached.current_age(now).as_secs()),
+    );
+
+    if not_modified {
+        *res.status_mut() = StatusCode::NOT_MODIFIED;
+        res.headers_mut().remove(header::CONTENT_LENGTH);
+        *res.body_mut() = Bytes::new();
+    } else if head {
+        *res.body_mut() = Bytes::new();
+    }
+    res
+}
+
+impl<F> ResponseFuture<F> {
+    pub(super) fn cached(response: Response<Bytes>) -> Self {
+        Self {
+            inner: ResponseFutureInner::Cached {
+                response: Some(response),
+            },
+        }
+    }
+
+    pub(super) fn new(future: F, action: Action) -> Self {
+        Self {
+            inner: ResponseFutureInner::Future {
+                future,
+                action: Some(action),
+            },
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (future, action) = match self.project().inner.project() {
+            ResFutProj::Cached { response } => {
+                let response = response.take().expect("future polled after completion");
+                return Poll::Ready(Ok(response.map(ResponseBody::cached)));
+            }
+            ResFutProj::Future { future, action } => (future, action),
+        };
+
+        let result = ready!(future.poll(cx));
+        let action = action.take().expect("future polled after completion");
+        if let Action::Store(pending) | Action::Revalidate(pending) = &action {
+            let failed = result
+                .as_ref()
+                .map_or(true, |res| res.status().is_server_error());
+            if failed {
+                if let Some(stale) = pending.stale_if_error(SystemTime::now()) {
+                    return Poll::Ready(Ok(stale.map(ResponseBody::cached)));
+                }
+            }
+        }
+
+        let res = result?;
+        let res = match action {
+            Action::None => res.map(ResponseBody::new),
+            Action::Store(pending) => pending.response(res),
+            Action::Revalidate(mut pending) if res.status() == StatusCode::NOT_MODIFIED => {
+                let mut cached = pending
+                    .stale
+                    .take()
+                    .expect("revalidated requests have a stale response");
+                let now = SystemTime::now();
+                cached.refresh(res.headers(), now);
+                if policy::is_storable(
+                    &pending.request_headers,
+                    cached.status,
+                    &cached.headers,
+                    now,
+                    pending.shared,
+                ) {
+                    pending.store.0.put(&pending.key, cached.clone());
+                } else {
+                    pending.store.0.remove(&pending.key);
+                }
+                cached_response(&cached, now, pending.head, false).map(ResponseBody::cached)
+            }
+            Action::Revalidate(pending) => pending.response(res),
+            Action::Invalidate(store, key) => {
+                if res.status().is_success() || res.status().is_redirection() {
+                    store.0.remove(&key);
+                }
+                res.map(ResponseBody::new)
+            }
+        };
+        Poll::Ready(Ok(res))
+    }
+}
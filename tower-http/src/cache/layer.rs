@@ -0,0 +1,65 @@
+use super::{store::SharedStore, Cache, CacheStore, DEFAULT_MAX_OBJECT_SIZE};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tower_layer::Layer;
+
+/// Layer that applies the [`Cache`] middleware, which caches responses.
+///
+/// See the [module docs](crate::cache) for more details.
+#[derive(Clone, Debug)]
+pub struct CacheLayer {
+    store: SharedStore,
+    max_object_size: usize,
+    shared: bool,
+    revalidating: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CacheLayer {
+    /// Create a new [`CacheLayer`] storing responses in `store`.
+    pub fn new<T>(store: T) -> Self
+    where
+        T: CacheStore,
+    {
+        Self {
+            store: SharedStore(Arc::new(store)),
+            max_object_size: DEFAULT_MAX_OBJECT_SIZE,
+            shared: true,
+            revalidating: Default::default(),
+        }
+    }
+
+    /// Set the size of the largest response body that is stored.
+    ///
+    /// Larger responses are passed through without being stored. Defaults to 1 MiB.
+    pub fn max_object_size(mut self, max_object_size: usize) -> Self {
+        self.max_object_size = max_object_size;
+        self
+    }
+
+    /// Set whether the cache is shared between users.
+    ///
+    /// Caches in servers are shared, while caches in HTTP clients are usually private to a
+    /// single user. Private caches also store responses marked `private`, responses setting
+    /// cookies and responses to requests with an `Authorization` header, and ignore the
+    /// `s-maxage` directive. Defaults to `true`.
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+}
+
+impl<S> Layer<S> for CacheLayer {
+    type Service = Cache<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Cache {
+            inner,
+            store: self.store.clone(),
+            max_object_size: self.max_object_size,
+            shared: self.shared,
+            revalidating: self.revalidating.clone(),
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Middleware that caches responses, acting as a shared cache as described in [RFC 9111].
+//!
+//! [`Cache`] stores responses to `GET` requests in a [`CacheStore`], such as the in-memory
+//! [`MemoryStore`], and answers later requests for the same scheme, host, path and query from
+//! the store while the response is fresh. In particular:
+//!
+//! - Freshness is determined by the `s-maxage` and `max-age` directives of the `Cache-Control`
+//!   header, or the `Expires` header. Responses with a `Last-Modified` header but without explicit
+//!   freshness are fresh for a tenth of their age, up to a day.
+//! - Responses marked `no-store` or `private`, responses setting cookies, partial responses and
+//!   responses to requests with an `Authorization` header, unless marked `public`, aren't stored.
+//! - Stale responses with an `ETag` or `Last-Modified` header are revalidated with a conditional
+//!   request, and served from the store again if the inner service responds with
+//!   `304 Not Modified`.
+//! - Responses are only served to requests that have the same values of the headers named by the
+//!   `Vary` header as the request they were stored for. Only the most recent response for each
+//!   resource is kept.
+//! - The `no-cache`, `no-store`, `max-age`, `min-fresh`, `max-stale` and `only-if-cached`
+//!   directives of requests are respected.
+//! - The `stale-while-revalidate` and `stale-if-error` directives from [RFC 5861] are supported.
+//!   Within their windows, stale responses are served while they're revalidated in the
+//!   background, or instead of errors and `5xx` responses of the inner service. Background
+//!   revalidations run in a task spawned with [`tokio::spawn`], so they require a Tokio
+//!   runtime.
+//! - Successful `POST`, `PUT`, `DELETE` and other unsafe requests remove the stored response for
+//!   their path.
+//!
+//! Bodies larger than [`CacheLayer::max_object_size`] aren't stored, and bodies are only stored
+//! once they have been sent completely. Trailers aren't stored.
+//!
+//! The middleware can also be used in HTTP clients, by making it a private cache with
+//! [`CacheLayer::shared`]. Stale responses are then revalidated with the server and a
+//! `304 Not Modified` response is turned into the full stored response, so callers always see
+//! complete responses.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::{
+//!     convert::Infallible,
+//!     sync::atomic::{AtomicUsize, Ordering},
+//! };
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::cache::{CacheLayer, MemoryStore};
+//!
+//! static CALLS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     CALLS.fetch_add(1, Ordering::SeqCst);
+//!     let res = Response::builder()
+//!         .header(header::CACHE_CONTROL, "max-age=60")
+//!         .body(Body::from("expensive"))
+//!         .unwrap();
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     // Keep up to 64 MiB of responses in memory
+//!     .layer(CacheLayer::new(MemoryStore::new(64 * 1024 * 1024)))
+//!     .service_fn(handle);
+//!
+//! for _ in 0..2 {
+//!     let request = Request::get("/report").body(Body::empty())?;
+//!     let response = service.ready().await?.call(request).await?;
+//!     let body = hyper::body::to_bytes(response.into_body()).await?;
+//!     assert_eq!(body, "expensive");
+//! }
+//!
+//! assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Caching the responses of a client:
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::cache::{CacheLayer, MemoryStore};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let http_client = tower::service_fn(|_: Request<Body>| async {
+//! #     Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+//! # });
+//! let mut client = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(MemoryStore::new(16 * 1024 * 1024)).shared(false))
+//!     .service(http_client);
+//!
+//! let request = Request::get("https://example.com/feed").body(Body::empty())?;
+//! let response = client.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [RFC 9111]: https://www.rfc-editor.org/rfc/rfc9111
+//! [RFC 5861]: https://www.rfc-editor.org/rfc/rfc5861
+
+mod body;
+mod future;
+mod layer;
+mod policy;
+mod service;
+mod store;
+
+pub use self::{
+    body::ResponseBody,
+    future::ResponseFuture,
+    layer::CacheLayer,
+    service::Cache,
+    store::{CacheStore, CachedResponse, MemoryStore},
+};
+
+const DEFAULT_MAX_OBJECT_SIZE: usize = 1024 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+    use http_body::Body as _;
+    use hyper::Body;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tower::{service_fn, BoxError, ServiceBuilder, ServiceExt};
+
+    type Handler = Arc<dyn Fn(&Request<Body>) -> Response<Body> + Send + Sync>;
+
+    struct Origin {
+        calls: Arc<AtomicUsize>,
+        svc: tower::util::BoxCloneService<Request<Body>, Response<ResponseBody<Body>>, Infallible>,
+    }
+
+    fn origin(handler: Handler) -> Origin {
+        origin_with(CacheLayer::new(MemoryStore::new(1024)), handler)
+    }
+
+    fn origin_with(layer: CacheLayer, handler: Handler) -> Origin {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let svc = ServiceBuilder::new()
+            .layer(layer.max_object_size(16))
+            .service(service_fn(move |req: Request<Body>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let res = handler(&req);
+                async move { Ok::<_, Infallible>(res) }
+            }));
+        Origin {
+            calls,
+            svc: tower::util::BoxCloneService::new(svc),
+        }
+    }
+
+    impl Origin {
+        async fn request(&self, req: Request<Body>) -> (Response<()>, Bytes) {
+            let res = self.svc.clone().oneshot(req).await.unwrap();
+            let (parts, body) = res.into_parts();
+            let body = hyper::body::to_bytes(body)
+                .await
+                .map_err(BoxError::from)
+                .unwrap();
+            (Response::from_parts(parts, ()), body)
+        }
+
+        async fn get(&self, uri: &str) -> (Response<()>, Bytes) {
+            self.request(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    fn response(cache_control: &'static str, body: &'static str) -> Response<Body> {
+        Response::builder()
+            .header(header::CACHE_CONTROL, cache_control)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_responses() {
+        let origin = origin(Arc::new(|req| match req.uri().path() {
+            "/fresh" => response("max-age=60", "fresh"),
+            "/no-store" => response("no-store, max-age=60", "no-store"),
+            "/large" => response("max-age=60", "larger than sixteen bytes"),
+            _ => response("max-age=0", "stale"),
+        }));
+
+        for path in ["/fresh", "/no-store", "/large", "/stale"] {
+            origin.get(path).await;
+            let (res, body) = origin.get(path).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(!body.is_empty());
+        }
+        // only the second request for `/fresh` was served from the cache
+        assert_eq!(origin.calls(), 7);
+
+        let (res, body) = origin.get("/fresh").await;
+        assert_eq!(body, "fresh");
+        assert_eq!(res.headers()[header::AGE], "0");
+
+        let req = Request::head("/fresh").body(Body::empty()).unwrap();
+        let (_, body) = origin.request(req).await;
+        assert!(body.is_empty());
+        assert_eq!(origin.calls(), 7);
+
+        // different hosts are different resources
+        let req = Request::get("/fresh")
+            .header(header::HOST, "other.example.com")
+            .body(Body::empty())
+            .unwrap();
+        origin.request(req).await;
+        assert_eq!(origin.calls(), 8);
+    }
+
+    #[tokio::test]
+    async fn request_directives() {
+        let origin = origin(Arc::new(|_| response("max-age=60", "fresh")));
+        origin.get("/").await;
+
+        for cache_control in ["no-cache", "max-age=0", "min-fresh=120"] {
+            let req = Request::get("/")
+                .header(header::CACHE_CONTROL, cache_control)
+                .body(Body::empty())
+                .unwrap();
+            origin.request(req).await;
+        }
+        assert_eq!(origin.calls(), 4);
+
+        let req = Request::get("/missing")
+            .header(header::CACHE_CONTROL, "only-if-cached")
+            .body(Body::empty())
+            .unwrap();
+        let (res, _) = origin.request(req).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(origin.calls(), 4);
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_responses() {
+        let origin = origin(Arc::new(|req| {
+            if req.headers().get(header::IF_NONE_MATCH) == Some(&HeaderValue::from_static("\"v1\""))
+            {
+                return Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header(header::ETAG, "\"v1\"")
+                    .header(header::CACHE_CONTROL, "no-cache")
+                    .header("x-revalidated", "true")
+                    .body(Body::empty())
+                    .unwrap();
+            }
+            Response::builder()
+                .header(header::ETAG, "\"v1\"")
+                .header(header::CACHE_CONTROL, "no-cache")
+                .body(Body::from("body"))
+                .unwrap()
+        }));
+
+        let (res, body) = origin.get("/").await;
+        assert_eq!(body, "body");
+        assert!(res.headers().get("x-revalidated").is_none());
+
+        let (res, body) = origin.get("/").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body, "body");
+        assert_eq!(res.headers()["x-revalidated"], "true");
+        assert_eq!(origin.calls(), 2);
+
+        // conditional requests from clients are passed through
+        let req = Request::get("/")
+            .header(header::IF_NONE_MATCH, "\"v1\"")
+            .body(Body::empty())
+            .unwrap();
+        let (res, _) = origin.request(req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn not_modified_from_cache() {
+        let origin = origin(Arc::new(|_| {
+            Response::builder()
+                .header(header::ETAG, "W/\"v1\"")
+                .header(header::CACHE_CONTROL, "max-age=60")
+                .body(Body::from("body"))
+                .unwrap()
+        }));
+        origin.get("/").await;
+
+        let req = Request::get("/")
+            .header(header::IF_NONE_MATCH, "\"v0\", \"v1\"")
+            .body(Body::empty())
+            .unwrap();
+        let (res, body) = origin.request(req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+        assert_eq!(origin.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn vary() {
+        let origin = origin(Arc::new(|req| {
+            let language = req
+                .headers()
+                .get(header::ACCEPT_LANGUAGE)
+                .map_or("none", |value| value.to_str().unwrap())
+                .to_owned();
+            Response::builder()
+                .header(header::CACHE_CONTROL, "max-age=60")
+                .header(header::VARY, "accept-language")
+                .body(Body::from(language))
+                .unwrap()
+        }));
+
+        let get = |language: &'static str| {
+            Request::get("/")
+                .header(header::ACCEPT_LANGUAGE, language)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(origin.request(get("en")).await.1, "en");
+        assert_eq!(origin.request(get("en")).await.1, "en");
+        assert_eq!(origin.calls(), 1);
+        assert_eq!(origin.request(get("de")).await.1, "de");
+        assert_eq!(origin.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn unsafe_requests_invalidate() {
+        let origin = origin(Arc::new(|_| response("max-age=60", "fresh")));
+        origin.get("/").await;
+        origin.get("/").await;
+        assert_eq!(origin.calls(), 1);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .body(Body::empty())
+            .unwrap();
+        origin.request(req).await;
+        origin.get("/").await;
+        assert_eq!(origin.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn stale_while_revalidate() {
+        let version = Arc::new(AtomicUsize::new(0));
+        let origin = origin(Arc::new(move |_| {
+            let version = version.fetch_add(1, Ordering::SeqCst) + 1;
+            Response::builder()
+                .header(
+                    header::CACHE_CONTROL,
+                    "max-age=0, stale-while-revalidate=60",
+                )
+                .body(Body::from(format!("v{}", version)))
+                .unwrap()
+        }));
+
+        assert_eq!(origin.get("/").await.1, "v1");
+        assert_eq!(origin.get("/").await.1, "v1");
+        for _ in 0..100 {
+            if origin.calls() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(origin.calls(), 2);
+        // wait for the task to store the response
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(origin.get("/").await.1, "v2");
+
+        let req = Request::get("/")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(origin.request(req).await.1, "v4");
+    }
+
+    #[tokio::test]
+    async fn stale_if_error() {
+        let calls = AtomicUsize::new(0);
+        let origin = origin(Arc::new(move |req| {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                let cache_control = if req.uri().path() == "/fallback" {
+                    "max-age=0, stale-if-error=60"
+                } else {
+                    "max-age=0"
+                };
+                return response(cache_control, "ok");
+            }
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::empty())
+                .unwrap()
+        }));
+
+        origin.get("/fallback").await;
+        let (res, body) = origin.get("/fallback").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body, "ok");
+
+        // the request can limit the staleness too
+        let req = Request::get("/fallback")
+            .header(header::CACHE_CONTROL, "stale-if-error=0")
+            .body(Body::empty())
+            .unwrap();
+        let (res, _) = origin.request(req).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn private_cache() {
+        let client = origin_with(
+            CacheLayer::new(MemoryStore::new(1024)).shared(false),
+            Arc::new(|req| {
+                let mut res = response("private, max-age=60, s-maxage=0", "private");
+                if req.uri().scheme_str() == Some("http") {
+                    *res.body_mut() = Body::from("insecure");
+                }
+                res
+            }),
+        );
+
+        let get = |uri: &'static str| {
+            Request::get(uri)
+                .header(header::AUTHORIZATION, "Bearer token")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(
+            client.request(get("https://example.com/")).await.1,
+            "private"
+        );
+        assert_eq!(
+            client.request(get("https://example.com/")).await.1,
+            "private"
+        );
+        assert_eq!(client.calls(), 1);
+        assert_eq!(
+            client.request(get("http://example.com/")).await.1,
+            "insecure"
+        );
+        assert_eq!(client.calls(), 2);
+
+        // shared caches don't store private responses
+        let origin = origin(Arc::new(|_| response("private, max-age=60", "private")));
+        origin.get("/").await;
+        origin.get("/").await;
+        assert_eq!(origin.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn stores_bodies_that_are_not_polled_to_the_end() {
+        let origin = origin(Arc::new(|_| response("max-age=60", "fresh")));
+
+        // hyper stops polling a body once `is_end_stream` returns true
+        let res = origin
+            .svc
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap());
+        let mut body = res.await.unwrap().into_body();
+        while !body.is_end_stream() {
+            body.data().await.unwrap().unwrap();
+        }
+        drop(body);
+
+        let (_, body) = origin.get("/").await;
+        assert_eq!(body, "fresh");
+        assert_eq!(origin.calls(), 1);
+    }
+}
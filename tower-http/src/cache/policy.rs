@@ -0,0 +1,412 @@
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, StatusCode,
+};
+use std::time::{Duration, SystemTime};
+
+/// Upper bound of heuristic freshness lifetimes, for responses without explicit freshness.
+const MAX_HEURISTIC_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The `Cache-Control` directives used by [`Cache`](super::Cache).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct CacheControl {
+    pub(super) no_store: bool,
+    pub(super) no_cache: bool,
+    pub(super) private: bool,
+    pub(super) public: bool,
+    pub(super) must_revalidate: bool,
+    pub(super) only_if_cached: bool,
+    pub(super) max_age: Option<Duration>,
+    pub(super) s_maxage: Option<Duration>,
+    pub(super) min_fresh: Option<Duration>,
+    // `Duration::MAX` if `max-stale` doesn't have a value, which allows any staleness
+    pub(super) max_stale: Option<Duration>,
+    // the extensions from RFC 5861
+    pub(super) stale_while_revalidate: Option<Duration>,
+    pub(super) stale_if_error: Option<Duration>,
+}
+
+impl CacheControl {
+    pub(super) fn from_headers(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                // directives we can't read could be `no-store`
+                Err(_) => {
+                    directives.no_store = true;
+                    continue;
+                }
+            };
+
+            for directive in value.split(',') {
+                let (name, argument) = match directive.split_once('=') {
+                    Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                    None => (directive, None),
+                };
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "no-store" => directives.no_store = true,
+                    // `no-cache` and `private` with field names are treated as if they applied
+                    // to the whole response
+                    "no-cache" => directives.no_cache = true,
+                    "private" => directives.private = true,
+                    "public" => directives.public = true,
+                    "must-revalidate" | "proxy-revalidate" => directives.must_revalidate = true,
+                    "only-if-cached" => directives.only_if_cached = true,
+                    "max-age" => directives.max_age = Some(seconds(argument)),
+                    "s-maxage" => directives.s_maxage = Some(seconds(argument)),
+                    "min-fresh" => directives.min_fresh = Some(seconds(argument)),
+                    "max-stale" => {
+                        directives.max_stale =
+                            Some(argument.map_or(Duration::MAX, |_| seconds(argument)))
+                    }
+                    "stale-while-revalidate" => {
+                        directives.stale_while_revalidate = Some(seconds(argument))
+                    }
+                    "stale-if-error" => directives.stale_if_error = Some(seconds(argument)),
+                    _ => {}
+                }
+            }
+        }
+
+        // `Pragma: no-cache` is only used by clients that don't send `Cache-Control`
+        if !headers.contains_key(header::CACHE_CONTROL)
+            && headers
+                .get_all(header::PRAGMA)
+                .iter()
+                .any(|value| value.as_bytes().eq_ignore_ascii_case(b"no-cache"))
+        {
+            directives.no_cache = true;
+        }
+
+        directives
+    }
+}
+
+/// Parse delta-seconds, invalid values are treated as zero so responses are considered stale.
+fn seconds(argument: Option<&str>) -> Duration {
+    let seconds = argument
+        .and_then(|argument| argument.parse::<u64>().ok())
+        .unwrap_or(0);
+    Duration::from_secs(seconds)
+}
+
+/// Statuses that can be cached without explicit freshness information.
+pub(super) fn is_heuristically_cacheable(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// Whether the response to a `GET` request can be stored, by a shared cache if `shared` is true
+/// or a private cache otherwise.
+pub(super) fn is_storable(
+    request_headers: &HeaderMap,
+    status: StatusCode,
+    headers: &HeaderMap,
+    now: SystemTime,
+    shared: bool,
+) -> bool {
+    // partial content and `304 Not Modified` aren't complete responses
+    if status == StatusCode::PARTIAL_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || status.is_informational()
+    {
+        return false;
+    }
+
+    let directives = CacheControl::from_headers(headers);
+    if directives.no_store || (shared && directives.private) {
+        return false;
+    }
+
+    // responses setting cookies are most likely specific to a user
+    if shared && headers.contains_key(header::SET_COOKIE) {
+        return false;
+    }
+
+    if vary_names(headers).any(|name| name.is_none()) {
+        return false;
+    }
+
+    if shared
+        && request_headers.contains_key(header::AUTHORIZATION)
+        && !directives.public
+        && !directives.must_revalidate
+        && directives.s_maxage.is_none()
+    {
+        return false;
+    }
+
+    let explicit = directives.max_age.is_some()
+        || (shared && directives.s_maxage.is_some())
+        || headers.contains_key(header::EXPIRES)
+        || directives.public;
+    if !explicit && !is_heuristically_cacheable(status) {
+        return false;
+    }
+
+    // responses that are stale right away are only useful if they can be revalidated or served
+    // while stale
+    freshness_lifetime(status, headers, now, shared) > Duration::ZERO
+        || has_validators(headers)
+        || directives.stale_while_revalidate.is_some()
+        || directives.stale_if_error.is_some()
+}
+
+/// How long a response is fresh after it was generated by the origin.
+///
+/// `s-maxage` only applies to shared caches.
+pub(super) fn freshness_lifetime(
+    status: StatusCode,
+    headers: &HeaderMap,
+    now: SystemTime,
+    shared: bool,
+) -> Duration {
+    let directives = CacheControl::from_headers(headers);
+    if let (Some(s_maxage), true) = (directives.s_maxage, shared) {
+        return s_maxage;
+    }
+    if let Some(max_age) = directives.max_age {
+        return max_age;
+    }
+
+    let response_date = date(headers, header::DATE).unwrap_or(now);
+    if let Some(expires) = headers.get(header::EXPIRES) {
+        // invalid dates, such as `0`, mean the response has already expired
+        return parse_date(expires)
+            .and_then(|expires| expires.duration_since(response_date).ok())
+            .unwrap_or_default();
+    }
+
+    match date(headers, header::LAST_MODIFIED) {
+        Some(last_modified) if is_heuristically_cacheable(status) => response_date
+            .duration_since(last_modified)
+            .map(|age| (age / 10).min(MAX_HEURISTIC_LIFETIME))
+            .unwrap_or_default(),
+        _ => Duration::ZERO,
+    }
+}
+
+/// The value of the `Age` header, zero if it's missing or invalid.
+pub(super) fn age(headers: &HeaderMap) -> Duration {
+    seconds(
+        headers
+            .get(header::AGE)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+pub(super) fn has_validators(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED)
+}
+
+/// The header names of the `Vary` header, `None` for `*`.
+pub(super) fn vary_names(headers: &HeaderMap) -> impl Iterator<Item = Option<HeaderName>> + '_ {
+    headers
+        .get_all(header::VARY)
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or("*").split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            if name == "*" {
+                Some(None)
+            } else {
+                // names that aren't valid can't be sent by clients so are ignored
+                HeaderName::from_bytes(name.as_bytes()).ok().map(Some)
+            }
+        })
+}
+
+fn date(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    headers.get(name).and_then(parse_date)
+}
+
+fn parse_date(value: &HeaderValue) -> Option<SystemTime> {
+    httpdate::parse_http_date(value.to_str().ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn directives() {
+        let directives = CacheControl::from_headers(&headers(&[
+            (
+                header::CACHE_CONTROL,
+                "Public, max-age=\"60\", no-cache=\"set-cookie\"",
+            ),
+            (header::CACHE_CONTROL, "s-maxage=bogus, max-stale"),
+            (
+                header::CACHE_CONTROL,
+                "stale-while-revalidate=30, stale-if-error=600",
+            ),
+        ]));
+        assert_eq!(
+            directives,
+            CacheControl {
+                public: true,
+                no_cache: true,
+                max_age: Some(Duration::from_secs(60)),
+                s_maxage: Some(Duration::ZERO),
+                max_stale: Some(Duration::MAX),
+                stale_while_revalidate: Some(Duration::from_secs(30)),
+                stale_if_error: Some(Duration::from_secs(600)),
+                ..Default::default()
+            }
+        );
+
+        let directives = CacheControl::from_headers(&headers(&[(header::PRAGMA, "no-cache")]));
+        assert!(directives.no_cache);
+    }
+
+    #[test]
+    fn lifetimes() {
+        let now = SystemTime::now();
+        let lifetime = |pairs: &[(HeaderName, &'static str)]| {
+            freshness_lifetime(StatusCode::OK, &headers(pairs), now, true)
+        };
+
+        assert_eq!(
+            lifetime(&[(header::CACHE_CONTROL, "max-age=60, s-maxage=120")]),
+            Duration::from_secs(120)
+        );
+        assert_eq!(
+            freshness_lifetime(
+                StatusCode::OK,
+                &headers(&[(header::CACHE_CONTROL, "max-age=60, s-maxage=120")]),
+                now,
+                false,
+            ),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            lifetime(&[
+                (header::DATE, "Wed, 21 Oct 2015 07:28:00 GMT"),
+                (header::EXPIRES, "Wed, 21 Oct 2015 08:28:00 GMT"),
+            ]),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(lifetime(&[(header::EXPIRES, "0")]), Duration::ZERO);
+        assert_eq!(
+            lifetime(&[
+                (header::DATE, "Wed, 21 Oct 2015 07:28:00 GMT"),
+                (header::LAST_MODIFIED, "Wed, 21 Oct 2015 06:28:00 GMT"),
+            ]),
+            Duration::from_secs(360)
+        );
+        assert_eq!(lifetime(&[]), Duration::ZERO);
+    }
+
+    #[test]
+    fn storable() {
+        let now = SystemTime::now();
+        let storable = |request: &[(HeaderName, &'static str)],
+                        status: u16,
+                        response: &[(HeaderName, &'static str)]| {
+            is_storable(
+                &headers(request),
+                StatusCode::from_u16(status).unwrap(),
+                &headers(response),
+                now,
+                true,
+            )
+        };
+
+        assert!(storable(&[], 200, &[(header::CACHE_CONTROL, "max-age=60")]));
+        assert!(storable(&[], 200, &[(header::ETAG, "\"a\"")]));
+        assert!(storable(
+            &[],
+            200,
+            &[(header::CACHE_CONTROL, "max-age=0, stale-if-error=60")]
+        ));
+        assert!(storable(&[], 404, &[(header::CACHE_CONTROL, "max-age=60")]));
+        assert!(storable(&[], 302, &[(header::CACHE_CONTROL, "max-age=60")]));
+
+        assert!(!storable(&[], 200, &[]));
+        assert!(!storable(&[], 302, &[(header::ETAG, "\"a\"")]));
+        assert!(!storable(
+            &[],
+            206,
+            &[(header::CACHE_CONTROL, "max-age=60")]
+        ));
+        for cache_control in ["no-store, max-age=60", "private, max-age=60"] {
+            assert!(!storable(
+                &[],
+                200,
+                &[(header::CACHE_CONTROL, cache_control)]
+            ));
+        }
+        assert!(!storable(
+            &[],
+            200,
+            &[(header::CACHE_CONTROL, "max-age=60"), (header::VARY, "*")]
+        ));
+        assert!(!storable(
+            &[],
+            200,
+            &[
+                (header::CACHE_CONTROL, "max-age=60"),
+                (header::SET_COOKIE, "a=b")
+            ]
+        ));
+
+        let authorized = [(header::AUTHORIZATION, "Bearer token")];
+        assert!(!storable(
+            &authorized,
+            200,
+            &[(header::CACHE_CONTROL, "max-age=60")]
+        ));
+        assert!(storable(
+            &authorized,
+            200,
+            &[(header::CACHE_CONTROL, "public, max-age=60")]
+        ));
+    }
+
+    #[test]
+    fn storable_by_private_caches() {
+        let now = SystemTime::now();
+        let storable = |request: &[(HeaderName, &'static str)],
+                        response: &[(HeaderName, &'static str)]| {
+            is_storable(
+                &headers(request),
+                StatusCode::OK,
+                &headers(response),
+                now,
+                false,
+            )
+        };
+
+        assert!(storable(
+            &[],
+            &[(header::CACHE_CONTROL, "private, max-age=60")]
+        ));
+        assert!(storable(
+            &[(header::AUTHORIZATION, "Bearer token")],
+            &[(header::CACHE_CONTROL, "max-age=60")]
+        ));
+        assert!(storable(
+            &[],
+            &[
+                (header::CACHE_CONTROL, "max-age=60"),
+                (header::SET_COOKIE, "a=b")
+            ]
+        ));
+        assert!(!storable(
+            &[],
+            &[(header::CACHE_CONTROL, "no-store, max-age=60")]
+        ));
+        assert!(!storable(&[], &[(header::CACHE_CONTROL, "s-maxage=60")]));
+    }
+}
@@ -0,0 +1,274 @@
+use super::{
+    future::{cached_response, Action, Pending},
+    policy::{self, CacheControl},
+    store::{CachedResponse, SharedStore},
+    CacheLayer, CacheStore, ResponseBody, ResponseFuture, DEFAULT_MAX_OBJECT_SIZE,
+};
+use crate::{entity_tag, ready::take_ready, sync::lock_ignore_poison};
+use bytes::Bytes;
+use http::{header, HeaderMap, Method, Request, Response, StatusCode};
+use http_body::Body;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tower_service::Service;
+
+/// Middleware that caches responses.
+///
+/// See the [module docs](crate::cache) for more details.
+#[derive(Clone, Debug)]
+pub struct Cache<S> {
+    pub(crate) inner: S,
+    pub(super) store: SharedStore,
+    pub(super) max_object_size: usize,
+    pub(super) shared: bool,
+    // the keys of the responses being revalidated in the background
+    pub(super) revalidating: Arc<Mutex<HashSet<String>>>,
+}
+
+impl<S> Cache<S> {
+    /// Create a new [`Cache`] storing responses in `store`.
+    pub fn new<T>(inner: S, store: T) -> Self
+    where
+        T: CacheStore,
+    {
+        Self {
+            inner,
+            store: SharedStore(Arc::new(store)),
+            max_object_size: DEFAULT_MAX_OBJECT_SIZE,
+            shared: true,
+            revalidating: Default::default(),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `Cache` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<T>(store: T) -> CacheLayer
+    where
+        T: CacheStore,
+    {
+        CacheLayer::new(store)
+    }
+
+    /// Set the size of the largest response body that is stored.
+    ///
+    /// See [`CacheLayer::max_object_size`] for more details.
+    pub fn max_object_size(mut self, max_object_size: usize) -> Self {
+        self.max_object_size = max_object_size;
+        self
+    }
+
+    /// Set whether the cache is shared between users.
+    ///
+    /// See [`CacheLayer::shared`] for more details.
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Cache<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let key = cache_key(&req);
+        let method = req.method().clone();
+        if method != Method::GET && method != Method::HEAD {
+            let action = if method.is_safe() {
+                Action::None
+            } else {
+                Action::Invalidate(self.store.clone(), key)
+            };
+            return ResponseFuture::new(self.inner.call(req), action);
+        }
+
+        let directives = CacheControl::from_headers(req.headers());
+        if directives.no_store || req.headers().contains_key(header::RANGE) {
+            return ResponseFuture::new(self.inner.call(req), Action::None);
+        }
+
+        let now = SystemTime::now();
+        let head = method == Method::HEAD;
+        let cached = self
+            .store
+            .0
+            .get(&key)
+            .filter(|cached| cached.matches_vary(req.headers()));
+
+        let mut stale_while_revalidate = None;
+        if let Some(cached) = &cached {
+            let not_modified = cached
+                .headers()
+                .get(header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map_or(false, |etag| {
+                    let if_none_match = req.headers().get_all(header::IF_NONE_MATCH);
+                    entity_tag::if_none_match(if_none_match, Some(etag))
+                });
+            if is_fresh(cached, &directives, now, self.shared) {
+                return ResponseFuture::cached(cached_response(cached, now, head, not_modified));
+            }
+
+            let window = CacheControl::from_headers(cached.headers()).stale_while_revalidate;
+            if !directives.no_cache && cached.can_serve_stale(window, now, self.shared) {
+                stale_while_revalidate = Some(cached_response(cached, now, head, not_modified));
+            }
+        }
+
+        if directives.only_if_cached {
+            let mut res = Response::new(Bytes::new());
+            *res.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+            return ResponseFuture::cached(res);
+        }
+
+        let pending = Pending {
+            store: self.store.clone(),
+            key,
+            request_headers: req.headers().clone(),
+            max_object_size: self.max_object_size,
+            head,
+            shared: self.shared,
+            stale: cached,
+            stale_if_error: directives.stale_if_error,
+        };
+
+        let has_conditionals = req.headers().contains_key(header::IF_NONE_MATCH)
+            || req.headers().contains_key(header::IF_MODIFIED_SINCE);
+        let action = match &pending.stale {
+            Some(stale) if !has_conditionals && policy::has_validators(stale.headers()) => {
+                add_validators(req.headers_mut(), stale);
+                Action::Revalidate(pending)
+            }
+            _ => Action::Store(pending),
+        };
+
+        if let Some(stale) = stale_while_revalidate {
+            self.revalidate_in_background(req, action);
+            return ResponseFuture::cached(stale);
+        }
+        ResponseFuture::new(self.inner.call(req), action)
+    }
+}
+
+impl<S> Cache<S> {
+    /// Send `req` to the inner service in a new task, unless the response for it is already
+    /// being revalidated.
+    fn revalidate_in_background<ReqBody, ResBody>(&mut self, req: Request<ReqBody>, action: Action)
+    where
+        S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+        ResBody: Body<Data = Bytes> + Send + 'static,
+    {
+        let key = match &action {
+            Action::Store(pending) | Action::Revalidate(pending) => pending.key.clone(),
+            _ => return,
+        };
+        if !lock_ignore_poison(&self.revalidating).insert(key.clone()) {
+            return;
+        }
+        let guard = RevalidationGuard {
+            revalidating: self.revalidating.clone(),
+            key,
+        };
+
+        // the inner service is ready so use it for the revalidation
+        let mut inner = take_ready(&mut self.inner);
+        let future = ResponseFuture::new(inner.call(req), action);
+
+        tokio::spawn(async move {
+            let _guard = guard;
+            let res = match future.await {
+                Ok(res) => res,
+                Err(_) => return,
+            };
+            // responses are stored once their body has been read completely
+            let mut body = Box::pin(res.into_body());
+            while let Some(Ok(_)) = body.data().await {}
+        });
+    }
+}
+
+/// Removes the key from the keys being revalidated once the revalidation is done.
+struct RevalidationGuard {
+    revalidating: Arc<Mutex<HashSet<String>>>,
+    key: String,
+}
+
+impl Drop for RevalidationGuard {
+    fn drop(&mut self) {
+        lock_ignore_poison(&self.revalidating).remove(&self.key);
+    }
+}
+
+/// The key responses are stored under, the scheme, host and the path and query of the request.
+fn cache_key<B>(req: &Request<B>) -> String {
+    let scheme = req.uri().scheme_str().unwrap_or_default();
+    let host = req
+        .uri()
+        .authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| req.headers().get(header::HOST)?.to_str().ok())
+        .unwrap_or_default();
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    format!(
+        "{}://{}{}",
+        scheme,
+        host.to_ascii_lowercase(),
+        path_and_query
+    )
+}
+
+fn is_fresh(
+    cached: &CachedResponse,
+    request: &CacheControl,
+    now: SystemTime,
+    shared: bool,
+) -> bool {
+    let response = CacheControl::from_headers(cached.headers());
+    if request.no_cache || response.no_cache {
+        return false;
+    }
+
+    let age = cached.current_age(now);
+    if request.max_age.map_or(false, |max_age| age > max_age) {
+        return false;
+    }
+
+    let lifetime = cached.freshness_lifetime(shared);
+    match lifetime.checked_sub(age) {
+        Some(remaining) if remaining > Duration::ZERO => request
+            .min_fresh
+            .map_or(true, |min_fresh| remaining >= min_fresh),
+        _ => cached.can_serve_stale(request.max_stale, now, shared),
+    }
+}
+
+fn add_validators(headers: &mut HeaderMap, cached: &CachedResponse) {
+    if let Some(etag) = cached.headers().get(header::ETAG) {
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+    }
+    if let Some(last_modified) = cached.headers().get(header::LAST_MODIFIED) {
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+    }
+}
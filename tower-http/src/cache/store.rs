@@ -0,0 +1,258 @@
+use super::policy;
+use crate::{lru::Lru, sync::lock_ignore_poison};
+use bytes::Bytes;
+use http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Storage for the responses cached by [`Cache`](super::Cache).
+///
+/// Keys identify the resource a response is for. Only the most recent response for each key is
+/// used, so stores don't need to handle multiple responses varying by request headers.
+///
+/// The methods are called while handling requests so must not block, stores that are slow to
+/// access should keep recently used responses in memory.
+pub trait CacheStore: Send + Sync + 'static {
+    /// Get the response stored for `key`.
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    /// Store `response` for `key`, replacing any response already stored for it.
+    ///
+    /// Stores are free to ignore responses, for example to limit their size.
+    fn put(&self, key: &str, response: CachedResponse);
+
+    /// Remove the response stored for `key`, if any.
+    fn remove(&self, key: &str);
+}
+
+impl<T> CacheStore for Arc<T>
+where
+    T: CacheStore + ?Sized,
+{
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        (**self).put(key, response)
+    }
+
+    fn remove(&self, key: &str) {
+        (**self).remove(key)
+    }
+}
+
+/// A response stored in a [`CacheStore`].
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    pub(super) status: StatusCode,
+    pub(super) headers: HeaderMap,
+    pub(super) body: Bytes,
+    // the request headers named by the `Vary` header of the response
+    pub(super) vary: Vec<(HeaderName, Vec<HeaderValue>)>,
+    pub(super) response_time: SystemTime,
+    // the `Age` of the response when it was received
+    pub(super) initial_age: Duration,
+}
+
+impl CachedResponse {
+    pub(super) fn new(
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+        request_headers: &HeaderMap,
+        now: SystemTime,
+    ) -> Self {
+        let vary = policy::vary_names(&headers)
+            .flatten()
+            .map(|name| {
+                let values = request_headers.get_all(&name).iter().cloned().collect();
+                (name, values)
+            })
+            .collect();
+
+        Self {
+            status,
+            initial_age: policy::age(&headers),
+            headers,
+            body,
+            vary,
+            response_time: now,
+        }
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The approximate number of bytes the response takes up.
+    pub fn size(&self) -> usize {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum::<usize>();
+        self.body.len() + headers
+    }
+
+    /// Whether the response was stored for a request with the same values of the headers named
+    /// by the `Vary` header.
+    pub(super) fn matches_vary(&self, request_headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, values)| request_headers.get_all(name).iter().eq(values.iter()))
+    }
+
+    /// How long ago the response was generated by the origin.
+    pub(super) fn current_age(&self, now: SystemTime) -> Duration {
+        let resident_time = now.duration_since(self.response_time).unwrap_or_default();
+        self.initial_age + resident_time
+    }
+
+    pub(super) fn freshness_lifetime(&self, shared: bool) -> Duration {
+        policy::freshness_lifetime(self.status, &self.headers, self.response_time, shared)
+    }
+
+    /// Whether the response may be served while it has been stale for at most `window`.
+    pub(super) fn can_serve_stale(
+        &self,
+        window: Option<Duration>,
+        now: SystemTime,
+        shared: bool,
+    ) -> bool {
+        let directives = policy::CacheControl::from_headers(&self.headers);
+        // `s-maxage` forbids serving stale responses like `proxy-revalidate`
+        if directives.must_revalidate || (shared && directives.s_maxage.is_some()) {
+            return false;
+        }
+        let staleness = self
+            .current_age(now)
+            .saturating_sub(self.freshness_lifetime(shared));
+        window.map_or(false, |window| staleness <= window)
+    }
+
+    /// Update the response with the headers of a `304 Not Modified` response validating it.
+    pub(super) fn refresh(&mut self, not_modified: &HeaderMap, now: SystemTime) {
+        for name in not_modified.keys() {
+            // these describe the body of the `304` rather than the stored one
+            if name == http::header::CONTENT_LENGTH || name == http::header::CONTENT_ENCODING {
+                continue;
+            }
+            self.headers.remove(name);
+            for value in not_modified.get_all(name) {
+                self.headers.append(name.clone(), value.clone());
+            }
+        }
+        self.initial_age = policy::age(not_modified);
+        self.response_time = now;
+    }
+}
+
+/// A [`CacheStore`] keeping responses in memory, evicting the least recently used ones when it
+/// is full.
+///
+/// Clones share the same responses.
+#[derive(Clone)]
+pub struct MemoryStore {
+    inner: Arc<Mutex<Lru<String, CachedResponse>>>,
+}
+
+impl MemoryStore {
+    /// Create a new [`MemoryStore`] holding up to `max_size` bytes of responses.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Lru::new(max_size))),
+        }
+    }
+
+    /// Remove all responses.
+    pub fn clear(&self) {
+        lock_ignore_poison(&self.inner).clear();
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        lock_ignore_poison(&self.inner).get(key).cloned()
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        let size = response.size();
+        lock_ignore_poison(&self.inner).insert(key.to_owned(), response, size);
+    }
+
+    fn remove(&self, key: &str) {
+        lock_ignore_poison(&self.inner).remove(key);
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = lock_ignore_poison(&self.inner);
+        f.debug_struct("MemoryStore")
+            .field("entries", &inner.len())
+            .field("size", &inner.size())
+            .field("max_size", &inner.max_size())
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct SharedStore(pub(super) Arc<dyn CacheStore>);
+
+impl fmt::Debug for SharedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedStore").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &'static str) -> CachedResponse {
+        CachedResponse::new(
+            StatusCode::OK,
+            HeaderMap::new(),
+            Bytes::from_static(body.as_bytes()),
+            &HeaderMap::new(),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let store = MemoryStore::new(10);
+        store.put("a", response("aaaa"));
+        store.put("b", response("bbbb"));
+        assert!(store.get("a").is_some());
+
+        store.put("c", response("cccc"));
+        assert!(store.get("a").is_some());
+        assert!(store.get("b").is_none());
+        assert!(store.get("c").is_some());
+
+        // too large to ever be stored
+        store.put("d", response("ddddddddddd"));
+        assert!(store.get("d").is_none());
+
+        store.remove("a");
+        assert!(store.get("a").is_none());
+        store.clear();
+        assert!(store.get("c").is_none());
+    }
+}
@@ -81,18 +81,81 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! Using a panic handler that also receives the request that caused the panic:
+//!
+//! ```rust
+//! use http::{Request, StatusCode, Response};
+//! use std::{any::Any, convert::Infallible};
+//! use tower::{Service, ServiceExt, ServiceBuilder};
+//! use tower_http::catch_panic::{CatchPanicLayer, PanicRequest};
+//! use hyper::Body;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     panic!("something went wrong...")
+//! }
+//!
+//! fn handle_panic(err: Box<dyn Any + Send + 'static>, req: &PanicRequest) -> Response<Body> {
+//!     let body = format!("{} {} panicked", req.method(), req.uri());
+//!
+//!     Response::builder()
+//!         .status(StatusCode::INTERNAL_SERVER_ERROR)
+//!         .body(Body::from(body))
+//!         .unwrap()
+//! }
+//!
+//! let mut svc = ServiceBuilder::new()
+//!     .layer(CatchPanicLayer::custom_with_request(handle_panic))
+//!     .service_fn(handle);
+//!
+//! let request = Request::post("/users").body(Body::empty())?;
+//! let response = svc.ready().await?.call(request).await?;
+//!
+//! let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+//! assert_eq!(body, "POST /users panicked");
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Reporting panics, for example to an error tracker, separately from creating the response:
+//!
+//! ```rust
+//! use http::{Request, Response};
+//! use std::convert::Infallible;
+//! use tower::ServiceBuilder;
+//! use tower_http::catch_panic::{CatchPanicLayer, PanicReport};
+//! use hyper::Body;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     panic!("something went wrong...")
+//! }
+//!
+//! let svc = ServiceBuilder::new()
+//!     .layer(CatchPanicLayer::new().report_panics_with(|report: &PanicReport<'_>| {
+//!         // The backtrace is only captured if enabled with `RUST_BACKTRACE` or
+//!         // `RUST_LIB_BACKTRACE`.
+//!         eprintln!("panic: {:?}\n{}", report.message(), report.backtrace());
+//!     }))
+//!     .service_fn(handle);
+//! ```
 
 use bytes::Bytes;
 use futures_core::ready;
 use futures_util::future::{CatchUnwind, FutureExt};
-use http::{HeaderValue, Request, Response, StatusCode};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri, Version};
 use http_body::{combinators::UnsyncBoxBody, Body, Full};
 use pin_project_lite::pin_project;
 use std::{
     any::Any,
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
     future::Future,
     panic::AssertUnwindSafe,
     pin::Pin,
+    sync::Once,
     task::{Context, Poll},
 };
 use tower_layer::Layer;
@@ -105,8 +168,9 @@ use crate::BoxError;
 ///
 /// See the [module docs](self) for an example.
 #[derive(Debug, Clone, Copy, Default)]
-pub struct CatchPanicLayer<T> {
+pub struct CatchPanicLayer<T, R = ()> {
     panic_handler: T,
+    reporter: R,
 }
 
 impl CatchPanicLayer<DefaultResponseForPanic> {
@@ -114,6 +178,7 @@ impl CatchPanicLayer<DefaultResponseForPanic> {
     pub fn new() -> Self {
         CatchPanicLayer {
             panic_handler: DefaultResponseForPanic,
+            reporter: (),
         }
     }
 }
@@ -124,20 +189,66 @@ impl<T> CatchPanicLayer<T> {
     where
         T: ResponseForPanic,
     {
-        Self { panic_handler }
+        Self {
+            panic_handler,
+            reporter: (),
+        }
+    }
+}
+
+impl<T, R> CatchPanicLayer<T, R> {
+    /// Report panics with `reporter` before creating the response.
+    ///
+    /// The reporter receives a [`PanicReport`] with a [`Backtrace`] captured where the panic
+    /// happened. See [`ReportPanic`] for more details.
+    ///
+    /// # Panic hook
+    ///
+    /// Backtraces are captured by a process-wide panic hook, which is installed with
+    /// [`std::panic::set_hook`] the first time a reporter is set. The hook that was installed
+    /// before is still called, so panics are printed as usual.
+    ///
+    /// A hook that the application installs afterwards replaces it, so it has to call the hook
+    /// returned by [`std::panic::take_hook`] to keep backtraces captured. Otherwise panics are
+    /// still reported, but [`PanicReport::backtrace`] is disabled.
+    pub fn report_panics_with<NewR>(self, reporter: NewR) -> CatchPanicLayer<T, NewR>
+    where
+        NewR: ReportPanic,
+    {
+        install_backtrace_hook();
+        CatchPanicLayer {
+            panic_handler: self.panic_handler,
+            reporter,
+        }
     }
 }
 
-impl<T, S> Layer<S> for CatchPanicLayer<T>
+impl<F> CatchPanicLayer<WithRequest<F>> {
+    /// Create a new `CatchPanicLayer` with a custom panic handler that also receives the
+    /// [`PanicRequest`] that caused the panic.
+    pub fn custom_with_request<B>(panic_handler: F) -> Self
+    where
+        F: FnMut(Box<dyn Any + Send + 'static>, &PanicRequest) -> Response<B> + Clone,
+    {
+        Self {
+            panic_handler: WithRequest(panic_handler),
+            reporter: (),
+        }
+    }
+}
+
+impl<T, R, S> Layer<S> for CatchPanicLayer<T, R>
 where
     T: Clone,
+    R: Clone,
 {
-    type Service = CatchPanic<S, T>;
+    type Service = CatchPanic<S, T, R>;
 
     fn layer(&self, inner: S) -> Self::Service {
         CatchPanic {
             inner,
             panic_handler: self.panic_handler.clone(),
+            reporter: self.reporter.clone(),
         }
     }
 }
@@ -146,9 +257,10 @@ where
 ///
 /// See the [module docs](self) for an example.
 #[derive(Debug, Clone, Copy)]
-pub struct CatchPanic<S, T> {
+pub struct CatchPanic<S, T, R = ()> {
     inner: S,
     panic_handler: T,
+    reporter: R,
 }
 
 impl<S> CatchPanic<S, DefaultResponseForPanic> {
@@ -157,13 +269,12 @@ impl<S> CatchPanic<S, DefaultResponseForPanic> {
         Self {
             inner,
             panic_handler: DefaultResponseForPanic,
+            reporter: (),
         }
     }
 }
 
 impl<S, T> CatchPanic<S, T> {
-    define_inner_service_accessors!();
-
     /// Create a new `CatchPanic` with a custom panic handler.
     pub fn custom(inner: S, panic_handler: T) -> Self
     where
@@ -172,11 +283,46 @@ impl<S, T> CatchPanic<S, T> {
         Self {
             inner,
             panic_handler,
+            reporter: (),
+        }
+    }
+}
+
+impl<S, T, R> CatchPanic<S, T, R> {
+    define_inner_service_accessors!();
+
+    /// Report panics with `reporter` before creating the response.
+    ///
+    /// See [`CatchPanicLayer::report_panics_with`] for more details.
+    pub fn report_panics_with<NewR>(self, reporter: NewR) -> CatchPanic<S, T, NewR>
+    where
+        NewR: ReportPanic,
+    {
+        install_backtrace_hook();
+        CatchPanic {
+            inner: self.inner,
+            panic_handler: self.panic_handler,
+            reporter,
+        }
+    }
+}
+
+impl<S, F> CatchPanic<S, WithRequest<F>> {
+    /// Create a new `CatchPanic` with a custom panic handler that also receives the
+    /// [`PanicRequest`] that caused the panic.
+    pub fn custom_with_request<B>(inner: S, panic_handler: F) -> Self
+    where
+        F: FnMut(Box<dyn Any + Send + 'static>, &PanicRequest) -> Response<B> + Clone,
+    {
+        Self {
+            inner,
+            panic_handler: WithRequest(panic_handler),
+            reporter: (),
         }
     }
 }
 
-impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for CatchPanic<S, T>
+impl<S, T, R, ReqBody, ResBody> Service<Request<ReqBody>> for CatchPanic<S, T, R>
 where
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
     ResBody: Body<Data = Bytes> + Send + 'static,
@@ -184,10 +330,11 @@ where
     T: ResponseForPanic + Clone,
     T::ResponseBody: Body<Data = Bytes> + Send + 'static,
     <T::ResponseBody as Body>::Error: Into<BoxError>,
+    R: ReportPanic,
 {
     type Response = Response<UnsyncBoxBody<Bytes, BoxError>>;
     type Error = S::Error;
-    type Future = ResponseFuture<S::Future, T>;
+    type Future = ResponseFuture<S::Future, T, R>;
 
     #[inline]
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -195,17 +342,34 @@ where
     }
 
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
-        match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(req))) {
+        let request = if self.panic_handler.captures_request() {
+            Some(PanicRequest::from_request(&req))
+        } else {
+            None
+        };
+
+        let capture_backtrace = self.reporter.captures_backtrace();
+        let inner = &mut self.inner;
+        let (result, backtrace) = capturing_backtrace(capture_backtrace, || {
+            std::panic::catch_unwind(AssertUnwindSafe(|| inner.call(req)))
+        });
+
+        match result {
             Ok(future) => ResponseFuture {
                 kind: Kind::Future {
                     future: AssertUnwindSafe(future).catch_unwind(),
                     panic_handler: Some(self.panic_handler.clone()),
+                    reporter: Some(self.reporter.clone()),
+                    request,
                 },
             },
             Err(panic_err) => ResponseFuture {
                 kind: Kind::Panicked {
                     panic_err: Some(panic_err),
                     panic_handler: Some(self.panic_handler.clone()),
+                    reporter: Some(self.reporter.clone()),
+                    request,
+                    backtrace,
                 },
             },
         }
@@ -214,28 +378,33 @@ where
 
 pin_project! {
     /// Response future for [`CatchPanic`].
-    pub struct ResponseFuture<F, T> {
+    pub struct ResponseFuture<F, T, R = ()> {
         #[pin]
-        kind: Kind<F, T>,
+        kind: Kind<F, T, R>,
     }
 }
 
 pin_project! {
     #[project = KindProj]
-    enum Kind<F, T> {
+    enum Kind<F, T, R> {
         Panicked {
             panic_err: Option<Box<dyn Any + Send + 'static>>,
             panic_handler: Option<T>,
+            reporter: Option<R>,
+            request: Option<PanicRequest>,
+            backtrace: Option<Backtrace>,
         },
         Future {
             #[pin]
             future: CatchUnwind<AssertUnwindSafe<F>>,
             panic_handler: Option<T>,
+            reporter: Option<R>,
+            request: Option<PanicRequest>,
         }
     }
 }
 
-impl<F, ResBody, E, T> Future for ResponseFuture<F, T>
+impl<F, ResBody, E, T, R> Future for ResponseFuture<F, T, R>
 where
     F: Future<Output = Result<Response<ResBody>, E>>,
     ResBody: Body<Data = Bytes> + Send + 'static,
@@ -243,6 +412,7 @@ where
     T: ResponseForPanic,
     T::ResponseBody: Body<Data = Bytes> + Send + 'static,
     <T::ResponseBody as Body>::Error: Into<BoxError>,
+    R: ReportPanic,
 {
     type Output = Result<Response<UnsyncBoxBody<Bytes, BoxError>>, E>;
 
@@ -251,44 +421,239 @@ where
             KindProj::Panicked {
                 panic_err,
                 panic_handler,
+                reporter,
+                request,
+                backtrace,
             } => {
                 let panic_handler = panic_handler
                     .take()
                     .expect("future polled after completion");
+                let reporter = reporter.take().expect("future polled after completion");
                 let panic_err = panic_err.take().expect("future polled after completion");
-                Poll::Ready(Ok(response_for_panic(panic_handler, panic_err)))
+                Poll::Ready(Ok(response_for_panic(
+                    panic_handler,
+                    reporter,
+                    panic_err,
+                    request.take(),
+                    backtrace.take(),
+                )))
             }
             KindProj::Future {
                 future,
                 panic_handler,
-            } => match ready!(future.poll(cx)) {
-                Ok(Ok(res)) => {
-                    Poll::Ready(Ok(res.map(|body| body.map_err(Into::into).boxed_unsync())))
+                reporter,
+                request,
+            } => {
+                let capture_backtrace = reporter
+                    .as_ref()
+                    .map_or(false, ReportPanic::captures_backtrace);
+                let (result, backtrace) =
+                    capturing_backtrace(capture_backtrace, || future.poll(cx));
+                match ready!(result) {
+                    Ok(Ok(res)) => {
+                        Poll::Ready(Ok(res.map(|body| body.map_err(Into::into).boxed_unsync())))
+                    }
+                    Ok(Err(svc_err)) => Poll::Ready(Err(svc_err)),
+                    Err(panic_err) => Poll::Ready(Ok(response_for_panic(
+                        panic_handler
+                            .take()
+                            .expect("future polled after completion"),
+                        reporter.take().expect("future polled after completion"),
+                        panic_err,
+                        request.take(),
+                        backtrace,
+                    ))),
                 }
-                Ok(Err(svc_err)) => Poll::Ready(Err(svc_err)),
-                Err(panic_err) => Poll::Ready(Ok(response_for_panic(
-                    panic_handler
-                        .take()
-                        .expect("future polled after completion"),
-                    panic_err,
-                ))),
-            },
+            }
         }
     }
 }
 
-fn response_for_panic<T>(
+fn response_for_panic<T, R>(
     mut panic_handler: T,
+    mut reporter: R,
     err: Box<dyn Any + Send + 'static>,
+    request: Option<PanicRequest>,
+    backtrace: Option<Backtrace>,
 ) -> Response<UnsyncBoxBody<Bytes, BoxError>>
 where
     T: ResponseForPanic,
     T::ResponseBody: Body<Data = Bytes> + Send + 'static,
     <T::ResponseBody as Body>::Error: Into<BoxError>,
+    R: ReportPanic,
+{
+    let backtrace = backtrace.unwrap_or_else(Backtrace::disabled);
+    reporter.report_panic(&PanicReport {
+        payload: &*err,
+        backtrace: &backtrace,
+        request: request.as_ref(),
+    });
+
+    let res = match request {
+        Some(request) => panic_handler.response_for_panic_with_request(err, &request),
+        None => panic_handler.response_for_panic(err),
+    };
+    res.map(|body| body.map_err(Into::into).boxed_unsync())
+}
+
+thread_local! {
+    static CAPTURE_BACKTRACE: Cell<bool> = const { Cell::new(false) };
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Install a panic hook that captures a backtrace for panics inside [`capturing_backtrace`].
+///
+/// The previous hook is still called, so panics are printed as usual.
+fn install_backtrace_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let prev = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if CAPTURE_BACKTRACE.with(Cell::get) {
+                let backtrace = Backtrace::capture();
+                BACKTRACE.with(|slot| *slot.borrow_mut() = Some(backtrace));
+            }
+            prev(info);
+        }));
+    });
+}
+
+/// Call `f`, returning the backtrace of the last panic inside it, if `capture` is `true`.
+///
+/// Backtraces left over from earlier panics are discarded first, so a panic resumed with
+/// [`std::panic::resume_unwind`], which doesn't call the panic hook, isn't reported with a stale
+/// backtrace.
+fn capturing_backtrace<F, O>(capture: bool, f: F) -> (O, Option<Backtrace>)
+where
+    F: FnOnce() -> O,
 {
-    panic_handler
-        .response_for_panic(err)
-        .map(|body| body.map_err(Into::into).boxed_unsync())
+    if !capture {
+        return (f(), None);
+    }
+    BACKTRACE.with(|slot| slot.borrow_mut().take());
+    let prev = CAPTURE_BACKTRACE.with(|c| c.replace(true));
+    let out = f();
+    CAPTURE_BACKTRACE.with(|c| c.set(prev));
+    (out, BACKTRACE.with(|slot| slot.borrow_mut().take()))
+}
+
+/// Information about a panic passed to [`ReportPanic`].
+#[derive(Debug)]
+pub struct PanicReport<'a> {
+    payload: &'a (dyn Any + Send + 'static),
+    backtrace: &'a Backtrace,
+    request: Option<&'a PanicRequest>,
+}
+
+impl<'a> PanicReport<'a> {
+    /// The panic payload.
+    pub fn payload(&self) -> &'a (dyn Any + Send + 'static) {
+        self.payload
+    }
+
+    /// The panic message, if the payload is a string.
+    pub fn message(&self) -> Option<&'a str> {
+        if let Some(s) = self.payload.downcast_ref::<String>() {
+            Some(s)
+        } else {
+            self.payload.downcast_ref::<&str>().copied()
+        }
+    }
+
+    /// The backtrace captured where the panic happened.
+    ///
+    /// Backtraces are captured with [`Backtrace::capture`] so are only available if enabled with
+    /// the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables. They are also
+    /// disabled if the panic hook has been replaced, see
+    /// [`CatchPanicLayer::report_panics_with`].
+    pub fn backtrace(&self) -> &'a Backtrace {
+        self.backtrace
+    }
+
+    /// The request that caused the panic, if the panic handler captures it.
+    ///
+    /// See [`ResponseForPanic::captures_request`].
+    pub fn request(&self) -> Option<&'a PanicRequest> {
+        self.request
+    }
+}
+
+/// Trait for reporting panics, separately from creating the response.
+///
+/// Backtraces are captured by a panic hook installed the first time a reporter is set, see
+/// [`CatchPanicLayer::report_panics_with`] for how it interacts with other panic hooks.
+pub trait ReportPanic: Clone {
+    /// Report the panic.
+    fn report_panic(&mut self, report: &PanicReport<'_>);
+
+    /// Whether backtraces should be captured.
+    ///
+    /// If `false` the reporter is still called but [`PanicReport::backtrace`] is disabled.
+    /// Defaults to `true`.
+    fn captures_backtrace(&self) -> bool {
+        true
+    }
+}
+
+impl ReportPanic for () {
+    fn report_panic(&mut self, _report: &PanicReport<'_>) {}
+
+    fn captures_backtrace(&self) -> bool {
+        false
+    }
+}
+
+impl<F> ReportPanic for F
+where
+    F: FnMut(&PanicReport<'_>) + Clone,
+{
+    fn report_panic(&mut self, report: &PanicReport<'_>) {
+        self(report)
+    }
+}
+
+/// The parts of the request that caused a panic.
+///
+/// Captured before calling the inner service, for panic handlers where
+/// [`ResponseForPanic::captures_request`] returns `true`.
+#[derive(Debug, Clone)]
+pub struct PanicRequest {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+}
+
+impl PanicRequest {
+    fn from_request<B>(req: &Request<B>) -> Self {
+        Self {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+        }
+    }
+
+    /// The request's method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The request's URI.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The request's HTTP version.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The request's headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
 }
 
 /// Trait for creating responses from panics.
@@ -301,6 +666,27 @@ pub trait ResponseForPanic: Clone {
         &mut self,
         err: Box<dyn Any + Send + 'static>,
     ) -> Response<Self::ResponseBody>;
+
+    /// Whether the request's method, URI and headers should be captured and passed to
+    /// [`response_for_panic_with_request`](ResponseForPanic::response_for_panic_with_request).
+    ///
+    /// Defaults to `false` so requests aren't cloned unless needed.
+    fn captures_request(&self) -> bool {
+        false
+    }
+
+    /// Create a response from the panic error and the request that caused it.
+    ///
+    /// Only called if [`captures_request`](ResponseForPanic::captures_request) returns `true`.
+    /// Defaults to calling [`response_for_panic`](ResponseForPanic::response_for_panic).
+    fn response_for_panic_with_request(
+        &mut self,
+        err: Box<dyn Any + Send + 'static>,
+        request: &PanicRequest,
+    ) -> Response<Self::ResponseBody> {
+        let _ = request;
+        self.response_for_panic(err)
+    }
 }
 
 impl<F, B> ResponseForPanic for F
@@ -317,6 +703,43 @@ where
     }
 }
 
+/// A `ResponseForPanic` that passes the [`PanicRequest`] to a closure.
+///
+/// Created with [`CatchPanicLayer::custom_with_request`] or [`CatchPanic::custom_with_request`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithRequest<F>(F);
+
+impl<F, B> ResponseForPanic for WithRequest<F>
+where
+    F: FnMut(Box<dyn Any + Send + 'static>, &PanicRequest) -> Response<B> + Clone,
+    B: Default,
+{
+    type ResponseBody = B;
+
+    /// Only called without the request if it wasn't captured, so respond with an empty
+    /// `500 Internal Server Error` response.
+    fn response_for_panic(
+        &mut self,
+        _err: Box<dyn Any + Send + 'static>,
+    ) -> Response<Self::ResponseBody> {
+        let mut res = Response::new(B::default());
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        res
+    }
+
+    fn captures_request(&self) -> bool {
+        true
+    }
+
+    fn response_for_panic_with_request(
+        &mut self,
+        err: Box<dyn Any + Send + 'static>,
+        request: &PanicRequest,
+    ) -> Response<Self::ResponseBody> {
+        (self.0)(err, request)
+    }
+}
+
 /// The default `ResponseForPanic` used by `CatchPanic`.
 ///
 /// It will log the panic message and return a `500 Internal Server` error response with an empty
@@ -360,7 +783,10 @@ mod tests {
 
     use super::*;
     use hyper::{Body, Response};
-    use std::convert::Infallible;
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
     use tower::{ServiceBuilder, ServiceExt};
 
     #[tokio::test]
@@ -398,4 +824,136 @@ mod tests {
         let body = hyper::body::to_bytes(res).await.unwrap();
         assert_eq!(&body[..], b"Service panicked");
     }
+
+    #[tokio::test]
+    async fn panic_handler_receives_request() {
+        let svc = ServiceBuilder::new()
+            .layer(CatchPanicLayer::custom_with_request(
+                |_err: Box<dyn Any + Send + 'static>, req: &PanicRequest| {
+                    let body = format!(
+                        "{} {} {:?}",
+                        req.method(),
+                        req.uri(),
+                        req.headers().get("x-id").unwrap()
+                    );
+                    Response::new(Body::from(body))
+                },
+            ))
+            .service_fn(|_: Request<Body>| async {
+                panic!("future panic");
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let req = Request::delete("/items/1")
+            .header("x-id", "abc")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = svc.oneshot(req).await.unwrap();
+
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        assert_eq!(&body[..], b"DELETE /items/1 \"abc\"");
+    }
+
+    #[test]
+    fn panic_handler_without_request() {
+        let mut handler = WithRequest(|_err: Box<dyn Any + Send + 'static>, _: &PanicRequest| {
+            Response::new(Body::from("handled"))
+        });
+        let res = handler.response_for_panic(Box::new("panic"));
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn panic_reporter() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let svc = ServiceBuilder::new()
+            .layer(CatchPanicLayer::new().report_panics_with({
+                let reports = reports.clone();
+                move |report: &PanicReport<'_>| {
+                    reports
+                        .lock()
+                        .unwrap()
+                        .push(report.message().unwrap().to_owned());
+                }
+            }))
+            .service_fn(|_: Request<Body>| async {
+                panic!("future panic");
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(*reports.lock().unwrap(), vec!["future panic".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn reports_without_backtrace() {
+        #[derive(Clone, Default)]
+        struct Reporter(Arc<Mutex<Vec<String>>>);
+
+        impl ReportPanic for Reporter {
+            fn report_panic(&mut self, report: &PanicReport<'_>) {
+                assert_eq!(
+                    report.backtrace().status(),
+                    std::backtrace::BacktraceStatus::Disabled
+                );
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(report.message().unwrap().to_owned());
+            }
+
+            fn captures_backtrace(&self) -> bool {
+                false
+            }
+        }
+
+        let reporter = Reporter::default();
+        let svc = ServiceBuilder::new()
+            .layer(CatchPanicLayer::new().report_panics_with(reporter.clone()))
+            .service_fn(|_: Request<Body>| {
+                panic!("service panic");
+                async { Ok::<_, Infallible>(Response::new(Body::empty())) }
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            *reporter.0.lock().unwrap(),
+            vec!["service panic".to_owned()]
+        );
+    }
+
+    #[test]
+    fn discards_stale_backtraces() {
+        BACKTRACE.with(|slot| *slot.borrow_mut() = Some(Backtrace::disabled()));
+
+        let ((), backtrace) = capturing_backtrace(true, || {});
+
+        assert!(backtrace.is_none());
+    }
+
+    #[test]
+    fn later_panic_hooks_have_to_call_the_previous_hook() {
+        install_backtrace_hook();
+
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let (result, backtrace) =
+            capturing_backtrace(true, || std::panic::catch_unwind(|| panic!("replaced")));
+        std::panic::set_hook(hook);
+        assert!(result.is_err());
+        assert!(backtrace.is_none());
+
+        let prev = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| prev(info)));
+        let (result, backtrace) =
+            capturing_backtrace(true, || std::panic::catch_unwind(|| panic!("chained")));
+        assert!(result.is_err());
+        assert!(backtrace.is_some());
+    }
 }
@@ -0,0 +1,123 @@
+use super::breaker::Ticket;
+use crate::classify::ClassifyEos;
+use futures_util::ready;
+use http::HeaderMap;
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response body for [`CircuitBreaker`].
+    ///
+    /// Responses that can only be classified at the end of the stream, such as gRPC responses
+    /// reporting their status in trailers, are counted once the trailers have been received.
+    ///
+    /// [`CircuitBreaker`]: super::CircuitBreaker
+    pub struct ResponseBody<B, C>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B, C>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B, C>
+    where
+        B: Body,
+    {
+        Open {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            classify_eos: Option<(C, Ticket)>,
+        },
+    }
+}
+
+impl<B, C> ResponseBody<B, C>
+where
+    B: Body,
+{
+    pub(super) fn empty() -> Self {
+        Self {
+            kind: BodyKind::Open { body: Empty::new() },
+        }
+    }
+
+    pub(super) fn new(body: B, classify_eos: Option<(C, Ticket)>) -> Self {
+        Self {
+            kind: BodyKind::Body { body, classify_eos },
+        }
+    }
+}
+
+impl<B, C> Body for ResponseBody<B, C>
+where
+    B: Body,
+    C: ClassifyEos,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Open { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body, classify_eos } => {
+                let data = ready!(body.poll_data(cx));
+                if let Some(Err(_)) = &data {
+                    if let Some((_, ticket)) = classify_eos.take() {
+                        ticket.failure();
+                    }
+                }
+                Poll::Ready(data)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Open { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body, classify_eos } => {
+                let trailers = ready!(body.poll_trailers(cx));
+                if let Some((classify_eos, ticket)) = classify_eos.take() {
+                    match &trailers {
+                        Ok(trailers) if classify_eos.classify_eos(trailers.as_ref()).is_ok() => {
+                            ticket.success()
+                        }
+                        _ => ticket.failure(),
+                    }
+                }
+                Poll::Ready(trailers)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Open { body } => body.is_end_stream(),
+            BodyKind::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Open { body } => body.size_hint(),
+            BodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
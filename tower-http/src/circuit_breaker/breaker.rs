@@ -0,0 +1,371 @@
+use crate::sync::lock_ignore_poison;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The number of buckets the failure window is divided into.
+const BUCKETS: u32 = 10;
+
+/// The state of a circuit breaker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are passed to the inner service and their outcomes are counted.
+    Closed,
+    /// Requests fail fast without being passed to the inner service.
+    Open,
+    /// A limited number of probe requests are passed to the inner service to find out if it has
+    /// recovered.
+    HalfOpen,
+}
+
+/// Error returned by [`CircuitBreaker`] in place of calling the inner service while the circuit
+/// is open, when configured with [`CircuitBreakerLayer::return_error`].
+///
+/// [`CircuitBreaker`]: super::CircuitBreaker
+/// [`CircuitBreakerLayer::return_error`]: super::CircuitBreakerLayer::return_error
+#[derive(Debug)]
+pub struct CircuitOpen {
+    retry_after: Option<Duration>,
+}
+
+impl CircuitOpen {
+    pub(super) fn new(retry_after: Option<Duration>) -> Self {
+        Self { retry_after }
+    }
+
+    /// Returns how long the circuit stays open before probe requests are let through.
+    ///
+    /// Returns `None` if the circuit is half-open and all probes are in flight.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("circuit breaker is open")
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+/// The settings of the circuit breaker.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Config {
+    pub(super) failure_rate: f64,
+    pub(super) min_requests: u32,
+    pub(super) window: Duration,
+    pub(super) open_duration: Duration,
+    pub(super) probes: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            failure_rate: 0.5,
+            min_requests: 20,
+            window: Duration::from_secs(10),
+            open_duration: Duration::from_secs(30),
+            probes: 3,
+        }
+    }
+}
+
+/// The state shared by all services produced by the same layer.
+#[derive(Clone)]
+pub(super) struct Breaker {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    circuit: Circuit,
+    // bumped on every transition so outcomes of requests admitted in an earlier state are ignored
+    generation: u64,
+}
+
+enum Circuit {
+    Closed(Window),
+    Open { until: Instant },
+    HalfOpen { in_flight: u32, successes: u32 },
+}
+
+pub(super) enum Admission {
+    Admitted(Ticket),
+    Rejected(Option<Duration>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Success,
+    Failure,
+    Cancelled,
+}
+
+impl Breaker {
+    pub(super) fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                circuit: Circuit::Closed(Window::new(Instant::now())),
+                generation: 0,
+            })),
+        }
+    }
+
+    pub(super) fn state(&self, now: Instant) -> CircuitState {
+        match lock_ignore_poison(&self.inner).circuit {
+            Circuit::Closed(_) => CircuitState::Closed,
+            Circuit::Open { until } if now < until => CircuitState::Open,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    pub(super) fn admit(&self, config: &Config, now: Instant) -> Admission {
+        let mut inner = lock_ignore_poison(&self.inner);
+        if let Circuit::Open { until } = inner.circuit {
+            if now < until {
+                return Admission::Rejected(Some(until - now));
+            }
+            inner.transition(Circuit::HalfOpen {
+                in_flight: 0,
+                successes: 0,
+            });
+        }
+
+        let probe = match &mut inner.circuit {
+            Circuit::Closed(_) => false,
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } => {
+                if *in_flight + *successes >= config.probes {
+                    return Admission::Rejected(None);
+                }
+                *in_flight += 1;
+                true
+            }
+            Circuit::Open { .. } => unreachable!("open circuits are half-opened above"),
+        };
+
+        Admission::Admitted(Ticket {
+            breaker: Some(self.clone()),
+            config: *config,
+            generation: inner.generation,
+            probe,
+        })
+    }
+
+    fn record(&self, ticket: &Ticket, outcome: Outcome, now: Instant) {
+        let config = &ticket.config;
+        let mut inner = lock_ignore_poison(&self.inner);
+        if inner.generation != ticket.generation {
+            return;
+        }
+
+        let open = Circuit::Open {
+            until: now + config.open_duration,
+        };
+        let next = match &mut inner.circuit {
+            Circuit::Closed(window) => {
+                if outcome == Outcome::Cancelled {
+                    return;
+                }
+                window.record(now, config.window, outcome == Outcome::Failure);
+                if window.should_open(config) {
+                    open
+                } else {
+                    return;
+                }
+            }
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if ticket.probe => {
+                *in_flight -= 1;
+                match outcome {
+                    Outcome::Success => {
+                        *successes += 1;
+                        if *successes < config.probes {
+                            return;
+                        }
+                        Circuit::Closed(Window::new(now))
+                    }
+                    Outcome::Failure => open,
+                    Outcome::Cancelled => return,
+                }
+            }
+            Circuit::HalfOpen { .. } | Circuit::Open { .. } => return,
+        };
+        inner.transition(next);
+    }
+}
+
+impl fmt::Debug for Breaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Breaker")
+            .field("state", &self.state(Instant::now()))
+            .finish()
+    }
+}
+
+impl Inner {
+    fn transition(&mut self, circuit: Circuit) {
+        self.circuit = circuit;
+        self.generation += 1;
+    }
+}
+
+/// A request admitted by the circuit breaker whose outcome hasn't been recorded yet.
+///
+/// Dropping the ticket without recording an outcome, for example because the response body was
+/// dropped before it was classified, frees up its probe slot without counting the request.
+pub(super) struct Ticket {
+    breaker: Option<Breaker>,
+    config: Config,
+    generation: u64,
+    probe: bool,
+}
+
+impl Ticket {
+    pub(super) fn success(self) {
+        self.finish(Outcome::Success);
+    }
+
+    pub(super) fn failure(self) {
+        self.finish(Outcome::Failure);
+    }
+
+    fn finish(mut self, outcome: Outcome) {
+        if let Some(breaker) = self.breaker.take() {
+            breaker.record(&self, outcome, Instant::now());
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.breaker.take() {
+            breaker.record(self, Outcome::Cancelled, Instant::now());
+        }
+    }
+}
+
+/// Request counts over a sliding window divided into buckets.
+struct Window {
+    buckets: [Counts; BUCKETS as usize],
+    current: usize,
+    started: Instant,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    total: u32,
+    failures: u32,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Self {
+            buckets: Default::default(),
+            current: 0,
+            started: now,
+        }
+    }
+
+    fn record(&mut self, now: Instant, window: Duration, failure: bool) {
+        let width = (window / BUCKETS).max(Duration::from_nanos(1));
+        let elapsed = now.saturating_duration_since(self.started);
+        let steps = elapsed.as_nanos() / width.as_nanos();
+        if steps >= u128::from(BUCKETS) {
+            *self = Self::new(now);
+        } else {
+            for _ in 0..steps {
+                self.current = (self.current + 1) % self.buckets.len();
+                self.buckets[self.current] = Counts::default();
+                self.started += width;
+            }
+        }
+
+        let bucket = &mut self.buckets[self.current];
+        bucket.total += 1;
+        bucket.failures += u32::from(failure);
+    }
+
+    fn should_open(&self, config: &Config) -> bool {
+        let (total, failures) = self
+            .buckets
+            .iter()
+            .fold((0, 0), |(total, failures), counts| {
+                (total + counts.total, failures + counts.failures)
+            });
+        total >= config.min_requests.max(1)
+            && f64::from(failures) >= config.failure_rate * f64::from(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            failure_rate: 0.5,
+            min_requests: 4,
+            window: Duration::from_secs(10),
+            open_duration: Duration::from_secs(5),
+            probes: 2,
+        }
+    }
+
+    fn admit(breaker: &Breaker, now: Instant) -> Ticket {
+        match breaker.admit(&config(), now) {
+            Admission::Admitted(ticket) => ticket,
+            Admission::Rejected(_) => panic!("request was rejected"),
+        }
+    }
+
+    #[test]
+    fn old_failures_leave_the_window() {
+        let start = Instant::now();
+        let mut window = Window::new(start);
+        for _ in 0..3 {
+            window.record(start, config().window, true);
+        }
+        assert!(!window.should_open(&config()));
+
+        // the failures have left the window by the time the fourth request completes
+        window.record(start + Duration::from_secs(11), config().window, true);
+        assert!(!window.should_open(&config()));
+    }
+
+    #[test]
+    fn half_open_circuit_admits_limited_probes() {
+        let breaker = Breaker::new();
+        let now = Instant::now();
+        for _ in 0..4 {
+            admit(&breaker, now).failure();
+        }
+        assert_eq!(breaker.state(now), CircuitState::Open);
+        assert!(matches!(
+            breaker.admit(&config(), now + Duration::from_secs(1)),
+            Admission::Rejected(Some(retry_after)) if retry_after >= Duration::from_secs(4)
+        ));
+
+        let later = now + Duration::from_secs(6);
+        assert_eq!(breaker.state(later), CircuitState::HalfOpen);
+        let first = admit(&breaker, later);
+        let second = admit(&breaker, later);
+        assert!(matches!(
+            breaker.admit(&config(), later),
+            Admission::Rejected(None)
+        ));
+
+        // a dropped probe makes room for another one
+        drop(second);
+        let third = admit(&breaker, later);
+        first.success();
+        third.success();
+        assert_eq!(breaker.state(later), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,105 @@
+use super::{breaker::Ticket, OnOpen, ResponseBody};
+use crate::classify::{ClassifiedResponse, ClassifyResponse};
+use futures_util::ready;
+use http::Response;
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+pin_project! {
+    /// Response future for [`CircuitBreaker`].
+    ///
+    /// [`CircuitBreaker`]: super::CircuitBreaker
+    pub struct ResponseFuture<F, C, R> {
+        #[pin]
+        kind: Kind<F, C>,
+        on_open: R,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, C> {
+        Called {
+            #[pin]
+            future: F,
+            classifier: Option<(C, Ticket)>,
+        },
+        Open {
+            retry_after: Option<Duration>,
+        },
+    }
+}
+
+impl<F, C, R> ResponseFuture<F, C, R> {
+    pub(super) fn called(future: F, classifier: C, ticket: Ticket, on_open: R) -> Self {
+        Self {
+            kind: Kind::Called {
+                future,
+                classifier: Some((classifier, ticket)),
+            },
+            on_open,
+        }
+    }
+
+    pub(super) fn open(retry_after: Option<Duration>, on_open: R) -> Self {
+        Self {
+            kind: Kind::Open { retry_after },
+            on_open,
+        }
+    }
+}
+
+impl<F, C, R, B, E> Future for ResponseFuture<F, C, R>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    C: ClassifyResponse,
+    R: OnOpen<E>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B, C::ClassifyEos>>, R::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.kind.project() {
+            KindProj::Open { retry_after } => Poll::Ready(this.on_open.reject(*retry_after)),
+            KindProj::Called { future, classifier } => {
+                let result = ready!(future.poll(cx));
+                let (classifier, ticket) =
+                    classifier.take().expect("future polled after completion");
+                let res = match result {
+                    Ok(res) => res,
+                    Err(err) => {
+                        ticket.failure();
+                        return Poll::Ready(Err(R::map_err(err)));
+                    }
+                };
+
+                let classify_eos = match classifier.classify_response(&res) {
+                    ClassifiedResponse::Ready(Ok(())) => {
+                        ticket.success();
+                        None
+                    }
+                    ClassifiedResponse::Ready(Err(_)) => {
+                        ticket.failure();
+                        None
+                    }
+                    ClassifiedResponse::RequiresEos(classify_eos) => Some((classify_eos, ticket)),
+                };
+                Poll::Ready(Ok(res.map(|body| ResponseBody::new(body, classify_eos))))
+            }
+        }
+    }
+}
+
+impl<F, C, R> fmt::Debug for ResponseFuture<F, C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
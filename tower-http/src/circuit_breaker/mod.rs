@@ -0,0 +1,283 @@
+//! Middleware that stops calling a failing service for a while.
+//!
+//! [`CircuitBreaker`] classifies responses with this crate's [`classify`] machinery and counts
+//! failures over a sliding window. Once enough requests have completed and the share of failures
+//! reaches the configured rate, the circuit opens and requests fail fast without calling the
+//! inner service. After a while the circuit becomes half-open and lets a limited number of probe
+//! requests through. The circuit closes again if all probes succeed and reopens if one fails.
+//!
+//! What counts as a failure depends on the classifier:
+//!
+//! - [`CircuitBreakerLayer::new_for_http`] counts `5xx` responses.
+//! - [`CircuitBreakerLayer::new_for_grpc`] counts gRPC responses with an error status, which may
+//!   only be known once the trailers of the response body have been received.
+//! - [`CircuitBreakerLayer::new`] accepts any [`MakeClassifier`].
+//!
+//! Errors of the inner service, such as those of [`tower::timeout`], are always failures.
+//!
+//! By default requests are failed with `503 Service Unavailable` responses, which is what servers
+//! want. Clients can use [`CircuitBreakerLayer::return_error`] to fail requests with a
+//! [`CircuitOpen`] error instead.
+//!
+//! [`classify`]: crate::classify
+//! [`MakeClassifier`]: crate::classify::MakeClassifier
+//! [`tower::timeout`]: https://docs.rs/tower/latest/tower/timeout/index.html
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::circuit_breaker::CircuitBreakerLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // a dependency of the service is down
+//!     let mut res = Response::new(Body::empty());
+//!     *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         // Open the circuit once half of at least 10 requests within 10 seconds have failed
+//!         // and try again after 30 seconds.
+//!         CircuitBreakerLayer::new_for_http()
+//!             .failure_rate(0.5)
+//!             .min_requests(10)
+//!             .window(Duration::from_secs(10))
+//!             .open_duration(Duration::from_secs(30)),
+//!     )
+//!     .service_fn(handle);
+//!
+//! for _ in 0..10 {
+//!     let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//!     assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+//! }
+//!
+//! // The circuit is open so `handle` isn't called anymore.
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+//! assert_eq!(response.headers()["retry-after"], "30");
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod breaker;
+mod future;
+mod on_open;
+mod service;
+
+pub use self::{
+    body::ResponseBody,
+    breaker::{CircuitOpen, CircuitState},
+    future::ResponseFuture,
+    on_open::{OnOpen, RespondUnavailable, ReturnError},
+    service::{CircuitBreaker, CircuitBreakerLayer},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classify::{
+        GrpcCode, GrpcErrorsAsFailures, ServerErrorsAsFailures, SharedClassifier,
+    };
+    use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+    use hyper::Body;
+    use std::{
+        sync::{
+            atomic::{AtomicU16, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tower::{service_fn, BoxError, Layer, Service, ServiceExt};
+
+    /// A service responding with the status in `status` that counts how often it was called.
+    fn service(
+        status: Arc<AtomicU16>,
+        calls: Arc<AtomicUsize>,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError> + Clone {
+        service_fn(move |_: Request<Body>| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let status = StatusCode::from_u16(status.load(Ordering::SeqCst)).unwrap();
+            async move {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = status;
+                Ok::<_, BoxError>(res)
+            }
+        })
+    }
+
+    fn layer() -> CircuitBreakerLayer<SharedClassifier<ServerErrorsAsFailures>> {
+        CircuitBreakerLayer::new_for_http()
+            .min_requests(4)
+            .failure_rate(0.5)
+            .open_duration(Duration::from_millis(50))
+            .probes(2)
+    }
+
+    async fn status<S, B>(svc: &mut S) -> StatusCode
+    where
+        S: Service<Request<Body>, Response = Response<B>>,
+        S::Error: std::fmt::Debug,
+    {
+        svc.ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn opens_after_failures_and_fails_fast() {
+        let code = Arc::new(AtomicU16::new(200));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut svc = layer().layer(service(code.clone(), calls.clone()));
+
+        // successes keep the failure rate below the threshold
+        for _ in 0..3 {
+            assert_eq!(status(&mut svc).await, StatusCode::OK);
+        }
+        code.store(500, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert_eq!(status(&mut svc).await, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert_eq!(svc.state(), CircuitState::Closed);
+        assert_eq!(status(&mut svc).await, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(svc.state(), CircuitState::Open);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn probes_close_or_reopen_the_circuit() {
+        let code = Arc::new(AtomicU16::new(500));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut svc = layer().layer(service(code.clone(), calls.clone()));
+
+        for _ in 0..4 {
+            status(&mut svc).await;
+        }
+        assert_eq!(svc.state(), CircuitState::Open);
+
+        // a failing probe opens the circuit again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(svc.state(), CircuitState::HalfOpen);
+        assert_eq!(status(&mut svc).await, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(svc.state(), CircuitState::Open);
+        assert_eq!(status(&mut svc).await, StatusCode::SERVICE_UNAVAILABLE);
+
+        // the circuit closes once all probes have succeeded
+        code.store(200, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(status(&mut svc).await, StatusCode::OK);
+        assert_eq!(svc.state(), CircuitState::HalfOpen);
+        assert_eq!(status(&mut svc).await, StatusCode::OK);
+        assert_eq!(svc.state(), CircuitState::Closed);
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+    }
+
+    #[tokio::test]
+    async fn errors_are_failures_and_clients_get_typed_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let svc = service_fn(move |_: Request<Body>| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            async { Err::<Response<Body>, _>(BoxError::from("connection refused")) }
+        });
+        let mut svc = layer().return_error().layer(svc);
+
+        for _ in 0..4 {
+            let err = svc.call(Request::new(Body::empty())).await.err().unwrap();
+            assert_eq!(err.to_string(), "connection refused");
+        }
+
+        let err = svc.call(Request::new(Body::empty())).await.err().unwrap();
+        let err = err.downcast::<CircuitOpen>().unwrap();
+        assert!(err.retry_after().unwrap() <= Duration::from_millis(50));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn classifies_grpc_trailers() {
+        let make_classifier =
+            SharedClassifier::new(GrpcErrorsAsFailures::new().with_success(GrpcCode::NotFound));
+        let code = Arc::new(AtomicU16::new(0));
+        let status = code.clone();
+        let svc = service_fn(move |_: Request<Body>| {
+            let status = status.load(Ordering::SeqCst);
+            async move {
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("grpc-status", HeaderValue::from(status));
+                    sender.send_trailers(trailers).await.unwrap();
+                });
+                Ok::<_, BoxError>(Response::new(body))
+            }
+        });
+        let mut svc = CircuitBreakerLayer::new(make_classifier)
+            .min_requests(2)
+            .failure_rate(0.6)
+            .layer(svc);
+
+        // `NotFound` isn't a failure
+        code.store(5, Ordering::SeqCst);
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        consume(res.into_body()).await;
+
+        // responses whose trailers are never received aren't counted
+        code.store(14, Ordering::SeqCst);
+        svc.call(Request::new(Body::empty())).await.unwrap();
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        consume(res.into_body()).await;
+        assert_eq!(svc.state(), CircuitState::Closed);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        consume(res.into_body()).await;
+        assert_eq!(svc.state(), CircuitState::Open);
+    }
+
+    async fn consume<B>(mut body: B)
+    where
+        B: http_body::Body + Unpin,
+        B::Error: std::fmt::Debug,
+    {
+        while body.data().await.is_some() {}
+        body.trailers().await.unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "probes must be greater than zero")]
+    fn zero_probes() {
+        CircuitBreakerLayer::new_for_http().probes(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "failure rate must be greater than 0.0 and at most 1.0")]
+    fn zero_failure_rate() {
+        CircuitBreakerLayer::new_for_http().failure_rate(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "failure rate must be greater than 0.0 and at most 1.0")]
+    fn failure_rate_above_one() {
+        CircuitBreakerLayer::new_for_http().failure_rate(1.5);
+    }
+}
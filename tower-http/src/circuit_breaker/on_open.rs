@@ -0,0 +1,97 @@
+use super::{CircuitOpen, ResponseBody};
+use crate::BoxError;
+use http::{header, HeaderValue, Response, StatusCode};
+use http_body::Body;
+use std::time::Duration;
+
+/// Trait for deciding how [`CircuitBreaker`] fails requests while the circuit is open.
+///
+/// This trait is sealed and implemented by [`RespondUnavailable`] and [`ReturnError`].
+///
+/// [`CircuitBreaker`]: super::CircuitBreaker
+pub trait OnOpen<E>: sealed::Sealed {
+    /// The error type of the middleware.
+    type Error;
+
+    #[doc(hidden)]
+    fn reject<B, C>(
+        &self,
+        retry_after: Option<Duration>,
+    ) -> Result<Response<ResponseBody<B, C>>, Self::Error>
+    where
+        B: Body;
+
+    #[doc(hidden)]
+    fn map_err(err: E) -> Self::Error;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Respond with `503 Service Unavailable` while the circuit is open.
+///
+/// While the circuit is fully open the response has a `Retry-After` header with the time left
+/// until probe requests are let through, rounded up to whole seconds.
+///
+/// This is the default and is meant for servers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RespondUnavailable {
+    _priv: (),
+}
+
+impl sealed::Sealed for RespondUnavailable {}
+
+impl<E> OnOpen<E> for RespondUnavailable {
+    type Error = E;
+
+    fn reject<B, C>(&self, retry_after: Option<Duration>) -> Result<Response<ResponseBody<B, C>>, E>
+    where
+        B: Body,
+    {
+        let mut res = Response::new(ResponseBody::empty());
+        *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        if let Some(retry_after) = retry_after {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        Ok(res)
+    }
+
+    fn map_err(err: E) -> E {
+        err
+    }
+}
+
+/// Fail with a [`CircuitOpen`] error while the circuit is open.
+///
+/// The errors of the middleware are boxed, so `CircuitOpen` has to be found by downcasting. This
+/// is meant for clients.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReturnError {
+    _priv: (),
+}
+
+impl sealed::Sealed for ReturnError {}
+
+impl<E> OnOpen<E> for ReturnError
+where
+    E: Into<BoxError>,
+{
+    type Error = BoxError;
+
+    fn reject<B, C>(
+        &self,
+        retry_after: Option<Duration>,
+    ) -> Result<Response<ResponseBody<B, C>>, BoxError>
+    where
+        B: Body,
+    {
+        Err(Box::new(CircuitOpen::new(retry_after)))
+    }
+
+    fn map_err(err: E) -> BoxError {
+        err.into()
+    }
+}
@@ -0,0 +1,301 @@
+use super::{
+    breaker::{Admission, Breaker, Config},
+    CircuitState, OnOpen, RespondUnavailable, ResponseBody, ResponseFuture, ReturnError,
+};
+use crate::classify::{
+    GrpcErrorsAsFailures, MakeClassifier, ServerErrorsAsFailures, SharedClassifier,
+};
+use http::{Request, Response};
+use http_body::Body;
+use std::{
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`CircuitBreaker`] middleware which stops calling a failing service.
+///
+/// The circuit is shared by all services produced by the same layer.
+///
+/// See the [module docs](crate::circuit_breaker) for an example.
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerLayer<M, R = RespondUnavailable> {
+    make_classifier: M,
+    breaker: Breaker,
+    config: Config,
+    on_open: R,
+}
+
+impl<M> CircuitBreakerLayer<M> {
+    /// Create a new `CircuitBreakerLayer` counting the failures classified by the given
+    /// [`MakeClassifier`].
+    pub fn new(make_classifier: M) -> Self
+    where
+        M: MakeClassifier,
+    {
+        Self {
+            make_classifier,
+            breaker: Breaker::new(),
+            config: Config::default(),
+            on_open: RespondUnavailable::default(),
+        }
+    }
+}
+
+impl CircuitBreakerLayer<SharedClassifier<ServerErrorsAsFailures>> {
+    /// Create a new `CircuitBreakerLayer` counting errors and `5xx` responses as failures.
+    pub fn new_for_http() -> Self {
+        Self::new(SharedClassifier::new(ServerErrorsAsFailures::default()))
+    }
+}
+
+impl CircuitBreakerLayer<SharedClassifier<GrpcErrorsAsFailures>> {
+    /// Create a new `CircuitBreakerLayer` counting errors and gRPC responses with an error status
+    /// as failures.
+    ///
+    /// Use [`GrpcErrorsAsFailures::with_success`] to select the gRPC codes that aren't counted.
+    pub fn new_for_grpc() -> Self {
+        Self::new(SharedClassifier::new(GrpcErrorsAsFailures::default()))
+    }
+}
+
+impl<M, R> CircuitBreakerLayer<M, R> {
+    /// Set the share of failed requests within the window that opens the circuit.
+    ///
+    /// Defaults to `0.5`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` isn't greater than `0.0` and at most `1.0`.
+    pub fn failure_rate(mut self, rate: f64) -> Self {
+        self.config.failure_rate = check_failure_rate(rate);
+        self
+    }
+
+    /// Set the number of requests that have to complete within the window before the failure
+    /// rate is considered.
+    ///
+    /// Defaults to `20`.
+    pub fn min_requests(mut self, min: u32) -> Self {
+        self.config.min_requests = min;
+        self
+    }
+
+    /// Set the duration over which failures are counted.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    /// Set how long the circuit stays open before probe requests are let through.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.config.open_duration = duration;
+        self
+    }
+
+    /// Set the number of probe requests that are let through while the circuit is half-open.
+    ///
+    /// The circuit closes once all probes have succeeded and opens again as soon as one fails.
+    /// Other requests fail fast until then. Defaults to `3`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probes` is zero.
+    pub fn probes(mut self, probes: u32) -> Self {
+        self.config.probes = check_probes(probes);
+        self
+    }
+
+    /// Fail with a [`CircuitOpen`] error instead of responding with `503 Service Unavailable`
+    /// while the circuit is open.
+    ///
+    /// This changes the error type of the middleware to [`BoxError`].
+    ///
+    /// [`CircuitOpen`]: super::CircuitOpen
+    /// [`BoxError`]: crate::BoxError
+    pub fn return_error(self) -> CircuitBreakerLayer<M, ReturnError> {
+        CircuitBreakerLayer {
+            make_classifier: self.make_classifier,
+            breaker: self.breaker,
+            config: self.config,
+            on_open: ReturnError::default(),
+        }
+    }
+}
+
+impl<S, M, R> Layer<S> for CircuitBreakerLayer<M, R>
+where
+    M: Clone,
+    R: Clone,
+{
+    type Service = CircuitBreaker<S, M, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            make_classifier: self.make_classifier.clone(),
+            breaker: self.breaker.clone(),
+            config: self.config,
+            on_open: self.on_open.clone(),
+        }
+    }
+}
+
+/// Middleware that stops calling the inner service while it is failing.
+///
+/// See the [module docs](crate::circuit_breaker) for an example.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker<S, M, R = RespondUnavailable> {
+    inner: S,
+    make_classifier: M,
+    breaker: Breaker,
+    config: Config,
+    on_open: R,
+}
+
+impl<S, M> CircuitBreaker<S, M> {
+    /// Create a new `CircuitBreaker` counting the failures classified by the given
+    /// [`MakeClassifier`].
+    pub fn new(inner: S, make_classifier: M) -> Self
+    where
+        M: MakeClassifier + Clone,
+    {
+        CircuitBreakerLayer::new(make_classifier).layer(inner)
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `CircuitBreaker` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(make_classifier: M) -> CircuitBreakerLayer<M>
+    where
+        M: MakeClassifier,
+    {
+        CircuitBreakerLayer::new(make_classifier)
+    }
+}
+
+impl<S, M, R> CircuitBreaker<S, M, R> {
+    define_inner_service_accessors!();
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.breaker.state(Instant::now())
+    }
+
+    /// Set the share of failed requests within the window that opens the circuit.
+    ///
+    /// See [`CircuitBreakerLayer::failure_rate`] for more details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` isn't greater than `0.0` and at most `1.0`.
+    pub fn failure_rate(mut self, rate: f64) -> Self {
+        self.config.failure_rate = check_failure_rate(rate);
+        self
+    }
+
+    /// Set the number of requests that have to complete within the window before the failure
+    /// rate is considered.
+    ///
+    /// See [`CircuitBreakerLayer::min_requests`] for more details.
+    pub fn min_requests(mut self, min: u32) -> Self {
+        self.config.min_requests = min;
+        self
+    }
+
+    /// Set the duration over which failures are counted.
+    ///
+    /// See [`CircuitBreakerLayer::window`] for more details.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    /// Set how long the circuit stays open before probe requests are let through.
+    ///
+    /// See [`CircuitBreakerLayer::open_duration`] for more details.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.config.open_duration = duration;
+        self
+    }
+
+    /// Set the number of probe requests that are let through while the circuit is half-open.
+    ///
+    /// See [`CircuitBreakerLayer::probes`] for more details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probes` is zero.
+    pub fn probes(mut self, probes: u32) -> Self {
+        self.config.probes = check_probes(probes);
+        self
+    }
+
+    /// Fail with a [`CircuitOpen`] error instead of responding with `503 Service Unavailable`
+    /// while the circuit is open.
+    ///
+    /// See [`CircuitBreakerLayer::return_error`] for more details.
+    ///
+    /// [`CircuitOpen`]: super::CircuitOpen
+    pub fn return_error(self) -> CircuitBreaker<S, M, ReturnError> {
+        CircuitBreaker {
+            inner: self.inner,
+            make_classifier: self.make_classifier,
+            breaker: self.breaker,
+            config: self.config,
+            on_open: ReturnError::default(),
+        }
+    }
+}
+
+impl<S, M, R, ReqBody, ResBody> Service<Request<ReqBody>> for CircuitBreaker<S, M, R>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    M: MakeClassifier,
+    R: OnOpen<S::Error> + Clone,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody, M::ClassifyEos>>;
+    type Error = R::Error;
+    type Future = ResponseFuture<S::Future, M::Classifier, R>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(R::map_err)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        match self.breaker.admit(&self.config, Instant::now()) {
+            Admission::Admitted(ticket) => {
+                let classifier = self.make_classifier.make_classifier(&req);
+                ResponseFuture::called(
+                    self.inner.call(req),
+                    classifier,
+                    ticket,
+                    self.on_open.clone(),
+                )
+            }
+            Admission::Rejected(retry_after) => {
+                ResponseFuture::open(retry_after, self.on_open.clone())
+            }
+        }
+    }
+}
+
+fn check_failure_rate(rate: f64) -> f64 {
+    assert!(
+        rate > 0.0 && rate <= 1.0,
+        "failure rate must be greater than 0.0 and at most 1.0"
+    );
+    rate
+}
+
+fn check_probes(probes: u32) -> u32 {
+    assert!(probes > 0, "probes must be greater than zero");
+    probes
+}
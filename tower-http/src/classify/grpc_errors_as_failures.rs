@@ -68,6 +68,54 @@ impl GrpcCode {
             Self::Unauthenticated => GrpcCodeBitmask::UNAUTHENTICATED,
         }
     }
+
+    #[allow(dead_code)]
+    pub(crate) fn from_i32(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Ok),
+            1 => Some(Self::Cancelled),
+            2 => Some(Self::Unknown),
+            3 => Some(Self::InvalidArgument),
+            4 => Some(Self::DeadlineExceeded),
+            5 => Some(Self::NotFound),
+            6 => Some(Self::AlreadyExists),
+            7 => Some(Self::PermissionDenied),
+            8 => Some(Self::ResourceExhausted),
+            9 => Some(Self::FailedPrecondition),
+            10 => Some(Self::Aborted),
+            11 => Some(Self::OutOfRange),
+            12 => Some(Self::Unimplemented),
+            13 => Some(Self::Internal),
+            14 => Some(Self::Unavailable),
+            15 => Some(Self::DataLoss),
+            16 => Some(Self::Unauthenticated),
+            _ => None,
+        }
+    }
+
+    /// The name of the code, such as `NOT_FOUND`.
+    #[allow(dead_code)]
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Cancelled => "CANCELLED",
+            Self::Unknown => "UNKNOWN",
+            Self::InvalidArgument => "INVALID_ARGUMENT",
+            Self::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Self::NotFound => "NOT_FOUND",
+            Self::AlreadyExists => "ALREADY_EXISTS",
+            Self::PermissionDenied => "PERMISSION_DENIED",
+            Self::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Self::FailedPrecondition => "FAILED_PRECONDITION",
+            Self::Aborted => "ABORTED",
+            Self::OutOfRange => "OUT_OF_RANGE",
+            Self::Unimplemented => "UNIMPLEMENTED",
+            Self::Internal => "INTERNAL",
+            Self::Unavailable => "UNAVAILABLE",
+            Self::DataLoss => "DATA_LOSS",
+            Self::Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
 }
 
 bitflags! {
@@ -5,6 +5,8 @@ use std::{convert::Infallible, fmt, marker::PhantomData};
 
 pub(crate) mod grpc_errors_as_failures;
 mod map_failure_class;
+mod or;
+mod retain_none;
 mod status_in_range_is_error;
 
 pub use self::{
@@ -12,6 +14,8 @@ pub use self::{
         GrpcCode, GrpcEosErrorsAsFailures, GrpcErrorsAsFailures, GrpcFailureClass,
     },
     map_failure_class::MapFailureClass,
+    or::{Or, OrClassifyEos},
+    retain_none::RetainNone,
     status_in_range_is_error::{StatusInRangeAsFailures, StatusInRangeFailureClass},
 };
 
@@ -235,6 +239,66 @@ pub trait ClassifyResponse {
     {
         MapFailureClass::new(self, f)
     }
+
+    /// Combine this classifier with another, considering responses to be failures if either
+    /// classifier does.
+    ///
+    /// `self` is asked first, so its failure class is used if both classify a response as a
+    /// failure. Errors are classified by `self`. Both classifiers must have the same failure
+    /// class, which [`map_failure_class`](ClassifyResponse::map_failure_class) can help with.
+    ///
+    /// # Example
+    ///
+    /// Treating server errors and `429 Too Many Requests` as failures, but not other client
+    /// errors:
+    ///
+    /// ```
+    /// use tower_http::classify::{
+    ///     ClassifiedResponse, ClassifyResponse, ServerErrorsAsFailures, ServerErrorsFailureClass,
+    ///     StatusInRangeAsFailures, StatusInRangeFailureClass,
+    /// };
+    /// use http::{Response, StatusCode};
+    ///
+    /// let too_many_requests = StatusInRangeAsFailures::new(429..=429).map_failure_class(
+    ///     |class| match class {
+    ///         StatusInRangeFailureClass::StatusCode(status) => {
+    ///             ServerErrorsFailureClass::StatusCode(status)
+    ///         }
+    ///         StatusInRangeFailureClass::Error(error) => ServerErrorsFailureClass::Error(error),
+    ///     },
+    /// );
+    /// let classifier = ServerErrorsAsFailures::new().or(too_many_requests);
+    ///
+    /// let response = Response::builder()
+    ///     .status(StatusCode::TOO_MANY_REQUESTS)
+    ///     .body(())
+    ///     .unwrap();
+    ///
+    /// assert!(matches!(
+    ///     classifier.classify_response(&response),
+    ///     ClassifiedResponse::Ready(Err(ServerErrorsFailureClass::StatusCode(_)))
+    /// ));
+    /// ```
+    fn or<C>(self, other: C) -> Or<Self, C>
+    where
+        Self: Sized,
+        C: ClassifyResponse<FailureClass = Self::FailureClass>,
+    {
+        Or::new(self, other)
+    }
+
+    /// Consider all responses to be successes, only classifying errors as failures.
+    ///
+    /// This is useful for middleware that should only react to errors such as failed
+    /// connections, whatever the responses are, or as a starting point for [`or`].
+    ///
+    /// [`or`]: ClassifyResponse::or
+    fn retain_none(self) -> RetainNone<Self>
+    where
+        Self: Sized,
+    {
+        RetainNone::new(self)
+    }
 }
 
 /// Trait for classifying end of streams (EOS) as either success or failure.
@@ -0,0 +1,178 @@
+use super::{ClassifiedResponse, ClassifyEos, ClassifyResponse};
+use http::{HeaderMap, Response};
+use std::fmt;
+
+/// Response classifier that considers responses to be failures if either of two classifiers
+/// does.
+///
+/// Created with [`ClassifyResponse::or`].
+#[derive(Clone, Copy, Debug)]
+pub struct Or<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Or<A, B> {
+    pub(super) fn new(a: A, b: B) -> Self {
+        Self { a, b }
+    }
+}
+
+impl<A, B> ClassifyResponse for Or<A, B>
+where
+    A: ClassifyResponse,
+    B: ClassifyResponse<FailureClass = A::FailureClass>,
+{
+    type FailureClass = A::FailureClass;
+    type ClassifyEos = OrClassifyEos<A::ClassifyEos, B::ClassifyEos>;
+
+    fn classify_response<Body>(
+        self,
+        res: &Response<Body>,
+    ) -> ClassifiedResponse<Self::FailureClass, Self::ClassifyEos> {
+        let a = match self.a.classify_response(res) {
+            ClassifiedResponse::Ready(Err(class)) => return ClassifiedResponse::Ready(Err(class)),
+            ClassifiedResponse::Ready(Ok(())) => None,
+            ClassifiedResponse::RequiresEos(eos) => Some(eos),
+        };
+        let kind = match (a, self.b.classify_response(res)) {
+            (_, ClassifiedResponse::Ready(Err(class))) => {
+                return ClassifiedResponse::Ready(Err(class))
+            }
+            (None, ClassifiedResponse::Ready(Ok(()))) => return ClassifiedResponse::Ready(Ok(())),
+            (Some(a), ClassifiedResponse::Ready(Ok(()))) => Kind::A(a),
+            (None, ClassifiedResponse::RequiresEos(b)) => Kind::B(b),
+            (Some(a), ClassifiedResponse::RequiresEos(b)) => Kind::Both(a, b),
+        };
+        ClassifiedResponse::RequiresEos(OrClassifyEos { kind })
+    }
+
+    fn classify_error<E>(self, error: &E) -> Self::FailureClass
+    where
+        E: fmt::Display + 'static,
+    {
+        self.a.classify_error(error)
+    }
+}
+
+/// The [`ClassifyEos`] of [`Or`].
+pub struct OrClassifyEos<A, B> {
+    kind: Kind<A, B>,
+}
+
+enum Kind<A, B> {
+    A(A),
+    B(B),
+    Both(A, B),
+}
+
+impl<A, B> ClassifyEos for OrClassifyEos<A, B>
+where
+    A: ClassifyEos,
+    B: ClassifyEos<FailureClass = A::FailureClass>,
+{
+    type FailureClass = A::FailureClass;
+
+    fn classify_eos(self, trailers: Option<&HeaderMap>) -> Result<(), Self::FailureClass> {
+        match self.kind {
+            Kind::A(a) => a.classify_eos(trailers),
+            Kind::B(b) => b.classify_eos(trailers),
+            Kind::Both(a, b) => {
+                a.classify_eos(trailers)?;
+                b.classify_eos(trailers)
+            }
+        }
+    }
+
+    fn classify_error<E>(self, error: &E) -> Self::FailureClass
+    where
+        E: fmt::Display + 'static,
+    {
+        match self.kind {
+            Kind::A(a) | Kind::Both(a, _) => a.classify_error(error),
+            Kind::B(b) => b.classify_error(error),
+        }
+    }
+}
+
+impl<A, B> fmt::Debug for OrClassifyEos<A, B>
+where
+    A: fmt::Debug,
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("OrClassifyEos");
+        match &self.kind {
+            Kind::A(a) => f.field("a", a),
+            Kind::B(b) => f.field("b", b),
+            Kind::Both(a, b) => f.field("a", a).field("b", b),
+        };
+        f.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classify::{
+        GrpcCode, GrpcErrorsAsFailures, GrpcFailureClass, ServerErrorsAsFailures,
+        ServerErrorsFailureClass,
+    };
+    use http::{HeaderValue, StatusCode};
+
+    fn classifier() -> impl ClassifyResponse<FailureClass = GrpcFailureClass> + Clone {
+        // treat gRPC errors and HTTP server errors as failures
+        let server_errors = ServerErrorsAsFailures::new().map_failure_class(|class| match class {
+            ServerErrorsFailureClass::StatusCode(status) => {
+                GrpcFailureClass::Error(status.to_string())
+            }
+            ServerErrorsFailureClass::Error(err) => GrpcFailureClass::Error(err),
+        });
+        GrpcErrorsAsFailures::new().or(server_errors)
+    }
+
+    fn response(status: StatusCode, grpc_status: Option<i32>) -> Response<()> {
+        let mut res = Response::builder().status(status).body(()).unwrap();
+        if let Some(code) = grpc_status {
+            res.headers_mut()
+                .insert("grpc-status", HeaderValue::from(code));
+        }
+        res
+    }
+
+    #[test]
+    fn fails_if_either_fails() {
+        let res = response(StatusCode::OK, Some(GrpcCode::Unknown as i32));
+        assert!(matches!(
+            classifier().classify_response(&res),
+            ClassifiedResponse::Ready(Err(GrpcFailureClass::Code(_)))
+        ));
+
+        let res = response(StatusCode::BAD_GATEWAY, None);
+        assert!(matches!(
+            classifier().classify_response(&res),
+            ClassifiedResponse::Ready(Err(GrpcFailureClass::Error(_)))
+        ));
+
+        let res = response(StatusCode::OK, Some(GrpcCode::Ok as i32));
+        assert!(matches!(
+            classifier().classify_response(&res),
+            ClassifiedResponse::Ready(Ok(()))
+        ));
+    }
+
+    #[test]
+    fn classifies_eos() {
+        let res = response(StatusCode::OK, None);
+        let eos = match classifier().classify_response(&res) {
+            ClassifiedResponse::RequiresEos(eos) => eos,
+            ClassifiedResponse::Ready(_) => panic!("expected eos classification"),
+        };
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from(GrpcCode::Internal as i32));
+        assert!(matches!(
+            eos.classify_eos(Some(&trailers)),
+            Err(GrpcFailureClass::Code(_))
+        ));
+    }
+}
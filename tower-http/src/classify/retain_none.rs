@@ -0,0 +1,40 @@
+use super::{ClassifiedResponse, ClassifyResponse, NeverClassifyEos};
+use http::Response;
+use std::fmt;
+
+/// Response classifier that considers all responses to be successes, retaining none of the
+/// response failures of some other classifier, and only classifies errors.
+///
+/// Created with [`ClassifyResponse::retain_none`].
+#[derive(Clone, Copy, Debug)]
+pub struct RetainNone<C> {
+    inner: C,
+}
+
+impl<C> RetainNone<C> {
+    pub(super) fn new(inner: C) -> Self {
+        Self { inner }
+    }
+}
+
+impl<C> ClassifyResponse for RetainNone<C>
+where
+    C: ClassifyResponse,
+{
+    type FailureClass = C::FailureClass;
+    type ClassifyEos = NeverClassifyEos<C::FailureClass>;
+
+    fn classify_response<B>(
+        self,
+        _res: &Response<B>,
+    ) -> ClassifiedResponse<Self::FailureClass, Self::ClassifyEos> {
+        ClassifiedResponse::Ready(Ok(()))
+    }
+
+    fn classify_error<E>(self, error: &E) -> Self::FailureClass
+    where
+        E: fmt::Display + 'static,
+    {
+        self.inner.classify_error(error)
+    }
+}
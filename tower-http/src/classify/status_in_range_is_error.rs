@@ -34,7 +34,7 @@ use std::{fmt, ops::RangeInclusive};
 /// ```
 #[derive(Debug, Clone)]
 pub struct StatusInRangeAsFailures {
-    range: RangeInclusive<u16>,
+    ranges: Vec<RangeInclusive<u16>>,
 }
 
 impl StatusInRangeAsFailures {
@@ -47,6 +47,31 @@ impl StatusInRangeAsFailures {
     ///
     /// [`StatusCode::from_u16`]: https://docs.rs/http/latest/http/status/struct.StatusCode.html#method.from_u16
     pub fn new(range: RangeInclusive<u16>) -> Self {
+        Self { ranges: Vec::new() }.range(range)
+    }
+
+    /// Also consider responses with a status code within `range` to be failures.
+    ///
+    /// # Example
+    ///
+    /// Considering server errors and `429 Too Many Requests` to be failures, but not other
+    /// client errors:
+    ///
+    /// ```
+    /// use tower_http::classify::StatusInRangeAsFailures;
+    /// use http::StatusCode;
+    ///
+    /// let classifier = StatusInRangeAsFailures::new(500..=599)
+    ///     .status(StatusCode::TOO_MANY_REQUESTS);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start or end of `range` aren't valid status codes as determined by
+    /// [`StatusCode::from_u16`].
+    ///
+    /// [`StatusCode::from_u16`]: https://docs.rs/http/latest/http/status/struct.StatusCode.html#method.from_u16
+    pub fn range(mut self, range: RangeInclusive<u16>) -> Self {
         assert!(
             StatusCode::from_u16(*range.start()).is_ok(),
             "range start isn't a valid status code"
@@ -56,7 +81,16 @@ impl StatusInRangeAsFailures {
             "range end isn't a valid status code"
         );
 
-        Self { range }
+        self.ranges.push(range);
+        self
+    }
+
+    /// Also consider responses with the status code `status` to be failures.
+    ///
+    /// See [`StatusInRangeAsFailures::range`] for an example.
+    pub fn status(self, status: StatusCode) -> Self {
+        let status = status.as_u16();
+        self.range(status..=status)
     }
 
     /// Creates a new `StatusInRangeAsFailures` that classifies client and server responses as
@@ -83,7 +117,8 @@ impl ClassifyResponse for StatusInRangeAsFailures {
         self,
         res: &http::Response<B>,
     ) -> ClassifiedResponse<Self::FailureClass, Self::ClassifyEos> {
-        if self.range.contains(&res.status().as_u16()) {
+        let status = res.status().as_u16();
+        if self.ranges.iter().any(|range| range.contains(&status)) {
             let class = StatusInRangeFailureClass::StatusCode(res.status());
             ClassifiedResponse::Ready(Err(class))
         } else {
@@ -151,6 +186,24 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn multiple_ranges() {
+        let classifier =
+            StatusInRangeAsFailures::new(500..=599).status(StatusCode::TOO_MANY_REQUESTS);
+
+        for (status, failure) in [(200, false), (404, false), (429, true), (503, true)] {
+            let classified = classifier
+                .clone()
+                .classify_response(&response_with_status(status));
+            assert_eq!(
+                matches!(classified, ClassifiedResponse::Ready(Err(_))),
+                failure,
+                "{}",
+                status
+            );
+        }
+    }
+
     fn response_with_status(status: u16) -> Response<()> {
         Response::builder().status(status).body(()).unwrap()
     }
@@ -0,0 +1,560 @@
+//! Middleware that determines the IP address of the client.
+//!
+//! Behind reverse proxies and load balancers the address of the peer a request is received from
+//! is the one of the last proxy, and the address of the client is only known from headers added
+//! by the proxies: `Forwarded`, `X-Forwarded-For`, or `X-Real-IP`. Since clients can send these
+//! headers too, they can only be trusted when the request was received from a known proxy.
+//!
+//! [`ClientIp`] reads the address of the peer from a [`SocketAddr`] request extension, which has
+//! to be added by the server, for example with [`AddExtension`]. If the peer is one of the
+//! [trusted proxies](ClientIpLayer::trusted_proxies), the first of the
+//! [configured headers](ClientIpLayer::sources) present in the request is used, which is only
+//! `X-Forwarded-For` by default. The addresses in the header are walked from the closest hop
+//! backwards, skipping trusted proxies, and the first untrusted address is the client's. The walk
+//! stops at a hop that isn't an IP address, such as an obfuscated `Forwarded` identifier, and the
+//! last trusted proxy before it is used instead. Otherwise the address of the peer is the
+//! client's.
+//!
+//! The result is added to the request extensions as a [`ClientAddr`], for logging, rate limiting,
+//! or authorization by later middleware and the inner service. Requests without a [`SocketAddr`]
+//! extension are passed on without a [`ClientAddr`].
+//!
+//! [`AddExtension`]: crate::add_extension::AddExtension
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::{convert::Infallible, net::SocketAddr};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::client_ip::{ClientAddr, ClientIpLayer};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let ClientAddr(ip) = req.extensions().get::<ClientAddr>().copied().unwrap();
+//!     assert_eq!(ip.to_string(), "203.0.113.7");
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(ClientIpLayer::new().trusted_proxies(["10.0.0.0/8".parse()?]))
+//!     .service_fn(handle);
+//!
+//! let mut request = Request::get("/")
+//!     .header("x-forwarded-for", "192.0.2.1, 203.0.113.7, 10.1.2.3")
+//!     .body(Body::empty())?;
+//! // added by the server
+//! request.extensions_mut().insert(SocketAddr::from(([10, 0, 0, 1], 50000)));
+//! service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderMap, Request};
+use std::{
+    error::Error,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The IP address of the client, added to the request extensions by [`ClientIp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientAddr(pub IpAddr);
+
+impl fmt::Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A header the address of the client is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClientIpSource {
+    /// The `for` parameters of the standard `Forwarded` header.
+    Forwarded,
+    /// The `X-Forwarded-For` header.
+    XForwardedFor,
+    /// The `X-Real-IP` header.
+    XRealIp,
+}
+
+impl ClientIpSource {
+    /// The hops from the header, from the client to the closest one, or `None` if the header is
+    /// missing. Hops that can't be parsed, such as obfuscated identifiers or `unknown`, are
+    /// `None`.
+    fn hops(self, headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+        let values = match self {
+            Self::Forwarded => headers.get_all(header::FORWARDED),
+            Self::XForwardedFor => headers.get_all("x-forwarded-for"),
+            Self::XRealIp => headers.get_all("x-real-ip"),
+        };
+        let mut hops = Vec::new();
+        for value in values {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => {
+                    hops.push(None);
+                    continue;
+                }
+            };
+            for element in value.split(',') {
+                let node = match self {
+                    Self::Forwarded => match forwarded_for(element) {
+                        Some(node) => node,
+                        // elements without `for` only have information about other parameters
+                        None => continue,
+                    },
+                    Self::XForwardedFor | Self::XRealIp => element,
+                };
+                hops.push(parse_node(node));
+            }
+        }
+        if hops.is_empty() {
+            None
+        } else {
+            Some(hops)
+        }
+    }
+}
+
+fn forwarded_for(element: &str) -> Option<&str> {
+    element.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("for") {
+            Some(value.trim().trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse an address with an optional port, where IPv6 addresses with a port are in brackets.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    let addr = node
+        .parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .unwrap_or(node)
+                .parse::<IpAddr>()
+        })
+        .ok()?;
+    Some(canonical(addr))
+}
+
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// A range of IP addresses in CIDR notation, such as `10.0.0.0/8` or `2001:db8::/32`.
+///
+/// A single address without a prefix length is parsed as a range containing only that address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Whether `addr` is in the range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, canonical(addr)) {
+            (IpAddr::V4(range), IpAddr::V4(addr)) => {
+                prefix_matches(&range.octets(), &addr.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                prefix_matches(&range.octets(), &addr.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(range: &[u8], addr: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = usize::from(prefix_len / 8);
+    if range[..full_bytes] != addr[..full_bytes] {
+        return false;
+    }
+    let rest = prefix_len % 8;
+    if rest == 0 {
+        return true;
+    }
+    let mask = !(0xff_u8 >> rest);
+    range[full_bytes] & mask == addr[full_bytes] & mask
+}
+
+impl From<IpAddr> for IpRange {
+    fn from(addr: IpAddr) -> Self {
+        let addr = canonical(addr);
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix_len }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = InvalidIpRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let range = Self::from(addr.parse::<IpAddr>().map_err(|_| InvalidIpRange(()))?);
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|_| InvalidIpRange(()))?,
+            None => return Ok(range),
+        };
+        if prefix_len > range.prefix_len {
+            return Err(InvalidIpRange(()));
+        }
+        Ok(Self {
+            prefix_len,
+            ..range
+        })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Error returned when parsing an [`IpRange`] fails.
+#[derive(Debug)]
+pub struct InvalidIpRange(());
+
+impl fmt::Display for InvalidIpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid IP address range")
+    }
+}
+
+impl Error for InvalidIpRange {}
+
+#[derive(Debug, Clone)]
+struct Config {
+    trusted_proxies: Vec<IpRange>,
+    sources: Vec<ClientIpSource>,
+}
+
+impl Config {
+    fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|range| range.contains(addr))
+    }
+
+    fn client_addr(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = canonical(peer);
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        // only the first header present is used, even if it has no usable address, since
+        // falling back to another header would let clients choose the one that is trusted
+        let hops = match self.sources.iter().find_map(|source| source.hops(headers)) {
+            Some(hops) => hops,
+            None => return peer,
+        };
+
+        // walk back from the closest hop, and stop at the first one that can't be parsed since
+        // the addresses before it can't be attributed to a trusted proxy
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(addr) => {
+                    client = addr;
+                    if !self.is_trusted(addr) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        client
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            trusted_proxies: Vec::new(),
+            sources: vec![ClientIpSource::XForwardedFor],
+        }
+    }
+}
+
+/// Layer that applies [`ClientIp`] which determines the IP address of the client.
+///
+/// See the [module docs](crate::client_ip) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct ClientIpLayer {
+    config: Arc<Config>,
+}
+
+impl ClientIpLayer {
+    /// Create a new `ClientIpLayer` that doesn't trust any proxies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the ranges of addresses of proxies whose headers are trusted.
+    ///
+    /// Defaults to no proxies, so the client is always the peer.
+    pub fn trusted_proxies<I>(mut self, ranges: I) -> Self
+    where
+        I: IntoIterator<Item = IpRange>,
+    {
+        Arc::make_mut(&mut self.config).trusted_proxies = ranges.into_iter().collect();
+        self
+    }
+
+    /// Set the headers to read the address of the client from, in order of precedence.
+    ///
+    /// Only the first of these headers present in a request is used, even if none of its
+    /// addresses can be parsed. Defaults to only `X-Forwarded-For`. Only include the headers set
+    /// by the proxies, since clients can send the others.
+    pub fn sources<I>(mut self, sources: I) -> Self
+    where
+        I: IntoIterator<Item = ClientIpSource>,
+    {
+        Arc::make_mut(&mut self.config).sources = sources.into_iter().collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for ClientIpLayer {
+    type Service = ClientIp<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientIp {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that determines the IP address of the client.
+///
+/// See the [module docs](crate::client_ip) for an example.
+#[derive(Debug, Clone)]
+pub struct ClientIp<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> ClientIp<S> {
+    /// Create a new `ClientIp` that doesn't trust any proxies.
+    pub fn new(inner: S) -> Self {
+        ClientIpLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ClientIp` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> ClientIpLayer {
+        ClientIpLayer::new()
+    }
+
+    /// Set the ranges of addresses of proxies whose headers are trusted.
+    ///
+    /// See [`ClientIpLayer::trusted_proxies`] for more details.
+    pub fn trusted_proxies<I>(mut self, ranges: I) -> Self
+    where
+        I: IntoIterator<Item = IpRange>,
+    {
+        Arc::make_mut(&mut self.config).trusted_proxies = ranges.into_iter().collect();
+        self
+    }
+
+    /// Set the headers to read the address of the client from, in order of precedence.
+    ///
+    /// See [`ClientIpLayer::sources`] for more details.
+    pub fn sources<I>(mut self, sources: I) -> Self
+    where
+        I: IntoIterator<Item = ClientIpSource>,
+    {
+        Arc::make_mut(&mut self.config).sources = sources.into_iter().collect();
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ClientIp<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if let Some(peer) = req.extensions().get::<SocketAddr>() {
+            let addr = self.config.client_addr(peer.ip(), req.headers());
+            req.extensions_mut().insert(ClientAddr(addr));
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Response;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn client_addr(
+        layer: &ClientIpLayer,
+        peer: Option<[u8; 4]>,
+        headers: &[(&'static str, &str)],
+    ) -> Option<String> {
+        let svc = layer.layer(service_fn(|req: Request<Body>| async move {
+            let addr = req.extensions().get::<ClientAddr>().copied();
+            Ok::<_, BoxError>(Response::new(addr))
+        }));
+
+        let mut req = Request::new(Body::empty());
+        for (name, value) in headers {
+            req.headers_mut().append(*name, value.parse().unwrap());
+        }
+        if let Some(peer) = peer {
+            req.extensions_mut().insert(SocketAddr::from((peer, 1234)));
+        }
+        let res = svc.oneshot(req).await.unwrap();
+        res.into_body().map(|addr| addr.to_string())
+    }
+
+    fn trusting_proxies() -> ClientIpLayer {
+        ClientIpLayer::new().trusted_proxies([
+            "10.0.0.0/8".parse().unwrap(),
+            "192.168.1.1".parse().unwrap(),
+        ])
+    }
+
+    #[tokio::test]
+    async fn untrusted_peer() {
+        let layer = trusting_proxies();
+        let headers = [("x-forwarded-for", "1.1.1.1")];
+
+        let addr = client_addr(&layer, Some([2, 2, 2, 2]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("2.2.2.2"));
+
+        let addr = client_addr(&ClientIpLayer::new(), Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("10.0.0.1"));
+
+        assert_eq!(client_addr(&layer, None, &headers).await, None);
+    }
+
+    #[tokio::test]
+    async fn x_forwarded_for() {
+        let layer = trusting_proxies();
+
+        let headers = [
+            ("x-forwarded-for", "3.3.3.3, 1.1.1.1"),
+            ("x-forwarded-for", "192.168.1.1,10.2.3.4"),
+        ];
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("1.1.1.1"));
+
+        // every hop is trusted
+        let headers = [("x-forwarded-for", "10.9.9.9, 192.168.1.1")];
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("10.9.9.9"));
+
+        // invalid headers are ignored
+        let headers = [("x-forwarded-for", "1.1.1.1, nope")];
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("10.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn stops_at_unparsable_hop() {
+        let layer = trusting_proxies();
+
+        let headers = [("x-forwarded-for", "1.1.1.1, unknown, 10.2.3.4")];
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("10.2.3.4"));
+
+        let layer = layer.sources([ClientIpSource::Forwarded, ClientIpSource::XRealIp]);
+        let headers = [
+            ("forwarded", "for=1.1.1.1, for=_hidden"),
+            ("x-real-ip", "2.2.2.2"),
+        ];
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("10.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn only_x_forwarded_for_by_default() {
+        let headers = [("forwarded", "for=1.1.1.1"), ("x-real-ip", "2.2.2.2")];
+        let addr = client_addr(&trusting_proxies(), Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("10.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn forwarded() {
+        let layer = trusting_proxies().sources([ClientIpSource::Forwarded]);
+
+        let headers = [(
+            "forwarded",
+            r#"for=1.1.1.1;proto=https, for="[2001:db8::1]:4711", proto=http, For=10.1.1.1"#,
+        )];
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("2001:db8::1"));
+    }
+
+    #[tokio::test]
+    async fn source_precedence() {
+        let headers = [("x-real-ip", "1.1.1.1"), ("x-forwarded-for", "2.2.2.2")];
+
+        let addr = client_addr(&trusting_proxies(), Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("2.2.2.2"));
+
+        let layer = trusting_proxies().sources([ClientIpSource::XRealIp]);
+        let addr = client_addr(&layer, Some([10, 0, 0, 1]), &headers).await;
+        assert_eq!(addr.as_deref(), Some("1.1.1.1"));
+    }
+
+    #[test]
+    fn ip_range() {
+        let range = "10.128.0.0/9".parse::<IpRange>().unwrap();
+        assert!(range.contains([10, 200, 1, 1].into()));
+        assert!(!range.contains([10, 1, 1, 1].into()));
+        assert!(range.contains("::ffff:10.128.0.1".parse().unwrap()));
+
+        let range = "2001:db8::/32".parse::<IpRange>().unwrap();
+        assert!(range.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!range.contains("2001:db9::1".parse().unwrap()));
+        assert!(!range.contains([10, 0, 0, 1].into()));
+
+        assert!("0.0.0.0/0"
+            .parse::<IpRange>()
+            .unwrap()
+            .contains([1, 2, 3, 4].into()));
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("10.0.0.0/".parse::<IpRange>().is_err());
+        assert!("nope".parse::<IpRange>().is_err());
+    }
+}
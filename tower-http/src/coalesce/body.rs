@@ -0,0 +1,229 @@
+use super::flight::{Buffered, Leader};
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::ready;
+use http::{HeaderMap, StatusCode, Version};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response body for [`Coalesce`].
+    ///
+    /// [`Coalesce`]: super::Coalesce
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        Shared {
+            data: Option<Bytes>,
+            trailers: Option<HeaderMap>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            recorder: Option<Recorder>,
+            // set once the data is done if the trailers were polled to share them
+            trailers: Option<Result<Option<HeaderMap>, B::Error>>,
+        },
+    }
+}
+
+/// Records the response of a leader so it can be shared with the followers.
+pub(super) struct Recorder {
+    leader: Leader,
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    chunks: Vec<Bytes>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Recorder {
+    pub(super) fn new<B>(leader: Leader, res: &http::Response<B>, max_size: usize) -> Self {
+        Self {
+            leader,
+            status: res.status(),
+            version: res.version(),
+            headers: res.headers().clone(),
+            chunks: Vec::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Share a response whose body is already known to be empty.
+    pub(super) fn finish_empty(self) {
+        self.finish(None);
+    }
+
+    /// Record a chunk, returning `false` once the body has become too large to be shared.
+    fn record(&mut self, chunk: &Bytes) -> bool {
+        self.size += chunk.len();
+        self.chunks.push(chunk.clone());
+        self.size <= self.max_size
+    }
+
+    fn finish(self, trailers: Option<HeaderMap>) {
+        let mut body = BytesMut::with_capacity(self.size);
+        for chunk in &self.chunks {
+            body.extend_from_slice(chunk);
+        }
+        let buffered = Buffered {
+            status: self.status,
+            version: self.version,
+            headers: self.headers,
+            body: body.freeze(),
+            trailers,
+        };
+        self.leader.finish(Some(Arc::new(buffered)));
+    }
+}
+
+impl<B> ResponseBody<B>
+where
+    B: Body,
+{
+    pub(super) fn shared(buffered: &Buffered) -> Self {
+        Self {
+            kind: BodyKind::Shared {
+                data: Some(buffered.body.clone()).filter(|data| !data.is_empty()),
+                trailers: buffered.trailers.clone(),
+            },
+        }
+    }
+
+    pub(super) fn new(body: B, recorder: Option<Recorder>) -> Self {
+        Self {
+            kind: BodyKind::Body {
+                body,
+                recorder,
+                trailers: None,
+            },
+        }
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Shared { data, .. } => Poll::Ready(data.take().map(Ok)),
+            BodyKindProj::Body {
+                mut body,
+                recorder,
+                trailers,
+            } => {
+                if trailers.is_some() {
+                    return Poll::Ready(None);
+                }
+                if recorder.is_none() {
+                    return match ready!(body.poll_data(cx)) {
+                        Some(Ok(mut data)) => {
+                            Poll::Ready(Some(Ok(data.copy_to_bytes(data.remaining()))))
+                        }
+                        Some(Err(err)) => Poll::Ready(Some(Err(err))),
+                        None => Poll::Ready(None),
+                    };
+                }
+
+                let done = match ready!(body.as_mut().poll_data(cx)) {
+                    Some(Ok(mut data)) => {
+                        let data = data.copy_to_bytes(data.remaining());
+                        let shareable = recorder.as_mut().map(|recorder| recorder.record(&data));
+                        if shareable == Some(false) {
+                            // too large to be shared, the followers call the service themselves
+                            recorder.take();
+                        }
+                        // hyper stops polling once the body says it has ended, so it might never
+                        // see the final `None`, and an ended body has no trailers either
+                        if body.is_end_stream() {
+                            if let Some(recorder) = recorder.take() {
+                                recorder.finish(None);
+                            }
+                        }
+                        return Poll::Ready(Some(Ok(data)));
+                    }
+                    Some(Err(err)) => {
+                        recorder.take();
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    None => ready!(body.poll_trailers(cx)),
+                };
+
+                // the trailers are polled right away since the followers need them, they are
+                // handed out when the trailers of this body are polled
+                if let (Some(recorder), Ok(shared)) = (recorder.take(), &done) {
+                    recorder.finish(shared.clone());
+                }
+                *trailers = Some(done);
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Shared { trailers, .. } => Poll::Ready(Ok(trailers.take())),
+            BodyKindProj::Body {
+                body,
+                recorder,
+                trailers,
+            } => {
+                if let Some(trailers) = trailers.take() {
+                    return Poll::Ready(trailers);
+                }
+                let result = ready!(body.poll_trailers(cx));
+                // the data was never polled to its end
+                recorder.take();
+                Poll::Ready(result)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Shared { data, trailers } => data.is_none() && trailers.is_none(),
+            BodyKind::Body { body, trailers, .. } => match trailers {
+                Some(Ok(trailers)) => trailers.is_none(),
+                Some(Err(_)) => false,
+                None => body.is_end_stream(),
+            },
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Shared { data, .. } => {
+                SizeHint::with_exact(data.as_ref().map_or(0, |data| data.len() as u64))
+            }
+            BodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::sync::lock_ignore_poison;
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, Uri, Version};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// What makes requests identical.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(super) struct Key {
+    pub(super) method: Method,
+    pub(super) uri: Uri,
+    pub(super) headers: Vec<Option<HeaderValue>>,
+}
+
+/// The in-flight requests of all services produced by the same layer.
+#[derive(Clone, Default)]
+pub(super) struct Flights {
+    map: Arc<Mutex<HashMap<Key, Arc<Flight>>>>,
+}
+
+pub(super) enum Role {
+    Leader(Leader),
+    Follower(Arc<Flight>),
+}
+
+impl Flights {
+    /// Join the flight for `key`, leading a new one if there is none.
+    pub(super) fn join(&self, key: Key) -> Role {
+        let mut map = lock_ignore_poison(&self.map);
+        if let Some(flight) = map.get(&key) {
+            return Role::Follower(flight.clone());
+        }
+        let flight = Arc::new(Flight::default());
+        map.insert(key.clone(), flight.clone());
+        Role::Leader(Leader {
+            flights: self.clone(),
+            key,
+            flight,
+            finished: false,
+        })
+    }
+
+    pub(super) fn len(&self) -> usize {
+        lock_ignore_poison(&self.map).len()
+    }
+}
+
+impl fmt::Debug for Flights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Flights")
+            .field("in_flight", &self.len())
+            .finish()
+    }
+}
+
+/// A request that other identical requests are waiting for.
+#[derive(Default)]
+pub(super) struct Flight {
+    state: Mutex<FlightState>,
+}
+
+#[derive(Default)]
+struct FlightState {
+    finished: bool,
+    // `None` if the response couldn't be shared
+    buffered: Option<Arc<Buffered>>,
+    wakers: Vec<Waker>,
+}
+
+impl Flight {
+    /// Wait for the response of the leader, or `None` if it couldn't be shared.
+    pub(super) fn poll_outcome(&self, cx: &mut Context<'_>) -> Poll<Option<Arc<Buffered>>> {
+        let mut state = lock_ignore_poison(&self.state);
+        if state.finished {
+            return Poll::Ready(state.buffered.clone());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// The request that actually calls the inner service.
+///
+/// Dropping the leader before it finished lets the followers call the inner service themselves.
+pub(super) struct Leader {
+    flights: Flights,
+    key: Key,
+    flight: Arc<Flight>,
+    finished: bool,
+}
+
+impl Leader {
+    pub(super) fn finish(mut self, outcome: Option<Arc<Buffered>>) {
+        self.complete(outcome);
+    }
+
+    fn complete(&mut self, outcome: Option<Arc<Buffered>>) {
+        self.finished = true;
+        {
+            // requests arriving from now on start a new flight
+            let mut map = lock_ignore_poison(&self.flights.map);
+            if matches!(map.get(&self.key), Some(flight) if Arc::ptr_eq(flight, &self.flight)) {
+                map.remove(&self.key);
+            }
+        }
+        let wakers = {
+            let mut state = lock_ignore_poison(&self.flight.state);
+            state.finished = true;
+            state.buffered = outcome;
+            std::mem::take(&mut state.wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.complete(None);
+        }
+    }
+}
+
+/// A response buffered to be shared with the followers.
+///
+/// Extensions can't be cloned so they aren't shared.
+pub(super) struct Buffered {
+    pub(super) status: StatusCode,
+    pub(super) version: Version,
+    pub(super) headers: HeaderMap,
+    pub(super) body: Bytes,
+    pub(super) trailers: Option<HeaderMap>,
+}
+
+impl Buffered {
+    pub(super) fn response<B>(&self, body: B) -> Response<B> {
+        let mut res = Response::new(body);
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers.clone();
+        res
+    }
+}
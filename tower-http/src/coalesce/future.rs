@@ -0,0 +1,119 @@
+use super::{
+    body::Recorder,
+    flight::{Flight, Leader},
+    ResponseBody,
+};
+use futures_util::ready;
+use http::Response;
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future for [`Coalesce`].
+    ///
+    /// [`Coalesce`]: super::Coalesce
+    pub struct ResponseFuture<S, R>
+    where
+        S: Service<R>,
+    {
+        #[pin]
+        state: State<S, R, S::Future>,
+        max_size: usize,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    pub(super) enum State<S, R, F> {
+        Leading {
+            #[pin]
+            future: F,
+            leader: Option<Leader>,
+        },
+        Following {
+            flight: Arc<Flight>,
+            service: S,
+            request: Option<R>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, R> ResponseFuture<S, R>
+where
+    S: Service<R>,
+{
+    pub(super) fn new(state: State<S, R, S::Future>, max_size: usize) -> Self {
+        Self { state, max_size }
+    }
+}
+
+impl<S, R, B> Future for ResponseFuture<S, R>
+where
+    S: Service<R, Response = Response<B>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Leading { future, leader } => {
+                    let res = ready!(future.poll(cx))?;
+                    let leader = leader.take().expect("future polled after completion");
+                    let mut recorder = Some(Recorder::new(leader, &res, *this.max_size));
+                    if res.body().is_end_stream() {
+                        // the body may never be polled
+                        if let Some(recorder) = recorder.take() {
+                            recorder.finish_empty();
+                        }
+                    }
+                    return Poll::Ready(Ok(res.map(|body| ResponseBody::new(body, recorder))));
+                }
+                StateProj::Following {
+                    flight,
+                    service,
+                    request,
+                } => match ready!(flight.poll_outcome(cx)) {
+                    Some(buffered) => {
+                        return Poll::Ready(Ok(buffered.response(ResponseBody::shared(&buffered))));
+                    }
+                    // the response couldn't be shared so send the request ourselves
+                    None => {
+                        ready!(service.poll_ready(cx))?;
+                        let request = request.take().expect("future polled after completion");
+                        State::Called {
+                            future: service.call(request),
+                        }
+                    }
+                },
+                StateProj::Called { future } => {
+                    let res = ready!(future.poll(cx))?;
+                    return Poll::Ready(Ok(res.map(|body| ResponseBody::new(body, None))));
+                }
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, R> fmt::Debug for ResponseFuture<S, R>
+where
+    S: Service<R>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
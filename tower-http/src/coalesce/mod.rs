@@ -0,0 +1,243 @@
+//! Middleware that deduplicates concurrent identical requests.
+//!
+//! When a request arrives while an identical request is already being processed, [`Coalesce`]
+//! doesn't call the inner service again but waits for the response of the first request and
+//! responds with a copy of it. This protects expensive handlers from stampedes, for example when
+//! a popular cache entry expires.
+//!
+//! Only `GET` and `HEAD` requests without a body are coalesced. Requests are identical if they
+//! have the same method, URI and key headers, see [`CoalesceLayer::key_header`].
+//!
+//! The response of the first request is streamed to its client as usual and recorded on the way,
+//! and the copies are sent once its body has been received completely. If the body is larger than
+//! [`CoalesceLayer::max_body_size`], fails, or isn't consumed, the waiting requests are sent to
+//! the inner service themselves. Response extensions aren't copied since they can't be cloned.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{
+//!     convert::Infallible,
+//!     sync::atomic::{AtomicUsize, Ordering},
+//!     time::Duration,
+//! };
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::coalesce::{CoalesceLayer, ResponseBody};
+//!
+//! static CALLS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! async fn expensive(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     CALLS.fetch_add(1, Ordering::SeqCst);
+//!     tokio::time::sleep(Duration::from_millis(10)).await;
+//!     Ok(Response::new(Body::from("report")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! let service = ServiceBuilder::new()
+//!     .layer(CoalesceLayer::new().max_body_size(64 * 1024))
+//!     .service_fn(expensive);
+//!
+//! // Both requests are sent at the same time.
+//! let (first, second) = tokio::join!(
+//!     fetch(service.clone(), "/report"),
+//!     fetch(service.clone(), "/report"),
+//! );
+//! assert_eq!(first?, "report");
+//! assert_eq!(second?, "report");
+//! assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+//! # Ok(())
+//! # }
+//!
+//! async fn fetch<S>(service: S, uri: &str) -> Result<hyper::body::Bytes, tower::BoxError>
+//! where
+//!     S: Service<Request<Body>, Response = Response<ResponseBody<Body>>, Error = Infallible>,
+//! {
+//!     let response = service.oneshot(Request::get(uri).body(Body::empty())?).await?;
+//!     Ok(hyper::body::to_bytes(response.into_body()).await?)
+//! }
+//! ```
+
+mod body;
+mod flight;
+mod future;
+mod service;
+
+pub use self::{
+    body::ResponseBody,
+    future::ResponseFuture,
+    service::{Coalesce, CoalesceLayer},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, HeaderMap, Request, Response};
+    use http_body::Body as _;
+    use hyper::Body;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::sync::oneshot;
+    use tower::{service_fn, BoxError, Layer, Service, ServiceExt};
+
+    /// A service that counts its calls and responds with `body` after `delay`.
+    fn service(
+        calls: Arc<AtomicUsize>,
+        body: &'static str,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError> + Clone {
+        service_fn(move |req: Request<Body>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let res = Response::builder()
+                    .header("x-call", call)
+                    .header("x-path", req.uri().path())
+                    .body(Body::from(body))
+                    .unwrap();
+                Ok(res)
+            }
+        })
+    }
+
+    async fn get<S, B>(svc: S, uri: &str) -> (HeaderMap, Bytes)
+    where
+        S: Service<Request<Body>, Response = Response<B>>,
+        S::Error: std::fmt::Debug,
+        B: http_body::Body,
+        B::Error: std::fmt::Debug,
+    {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let (parts, body) = svc.oneshot(req).await.unwrap().into_parts();
+        (parts.headers, hyper::body::to_bytes(body).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn coalesces_identical_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = CoalesceLayer::new().layer(service(calls.clone(), "hello"));
+
+        let ((first, first_body), (second, second_body), (other, _)) = tokio::join!(
+            get(svc.clone(), "/a"),
+            get(svc.clone(), "/a"),
+            get(svc.clone(), "/b"),
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(first["x-call"], second["x-call"]);
+        assert_eq!(first_body, "hello");
+        assert_eq!(second_body, "hello");
+        assert_eq!(other["x-path"], "/b");
+        assert_eq!(svc.in_flight(), 0);
+
+        // the flight is over so the next request calls the service again
+        let res = svc
+            .oneshot(Request::get("/a").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["x-call"], "2");
+    }
+
+    #[tokio::test]
+    async fn only_coalesces_safe_requests_with_the_same_key() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = CoalesceLayer::new().layer(service(calls.clone(), "hello"));
+
+        let post = Request::post("/").body(Body::empty()).unwrap();
+        let with_body = Request::get("/").body(Body::from("body")).unwrap();
+        let json = Request::get("/")
+            .header(header::ACCEPT, "application/json")
+            .body(Body::empty())
+            .unwrap();
+        let html = Request::get("/")
+            .header(header::ACCEPT, "text/html")
+            .body(Body::empty())
+            .unwrap();
+        tokio::try_join!(
+            svc.clone().oneshot(post),
+            svc.clone()
+                .oneshot(Request::post("/").body(Body::empty()).unwrap()),
+            svc.clone().oneshot(with_body),
+            svc.clone().oneshot(json),
+            svc.clone().oneshot(html),
+        )
+        .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_not_shared() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = CoalesceLayer::new()
+            .max_body_size(4)
+            .layer(service(calls.clone(), "too large"));
+
+        let ((_, first), (_, second)) = tokio::join!(get(svc.clone(), "/"), get(svc.clone(), "/"));
+        assert_eq!(first, "too large");
+        assert_eq!(second, "too large");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn shares_trailers() {
+        let (tx, rx) = oneshot::channel::<()>();
+        let rx = Arc::new(tokio::sync::Mutex::new(Some(rx)));
+        let svc = service_fn(move |_: Request<Body>| {
+            let rx = rx.clone();
+            async move {
+                let rx = rx.lock().await.take().unwrap();
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    rx.await.unwrap();
+                    sender.send_data(Bytes::from("data")).await.unwrap();
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("grpc-status", "0".parse().unwrap());
+                    sender.send_trailers(trailers).await.unwrap();
+                });
+                Ok::<_, BoxError>(Response::new(body))
+            }
+        });
+        let mut svc = CoalesceLayer::new().layer(svc);
+
+        let first = svc.call(Request::new(Body::empty())).await.unwrap();
+        let second = svc.call(Request::new(Body::empty()));
+        tx.send(()).unwrap();
+
+        let mut first = first.into_body();
+        assert_eq!(first.data().await.unwrap().unwrap(), "data");
+        assert!(first.data().await.is_none());
+        assert_eq!(first.trailers().await.unwrap().unwrap()["grpc-status"], "0");
+
+        let mut second = second.await.unwrap().into_body();
+        assert_eq!(second.data().await.unwrap().unwrap(), "data");
+        assert!(second.data().await.is_none());
+        assert_eq!(
+            second.trailers().await.unwrap().unwrap()["grpc-status"],
+            "0"
+        );
+    }
+
+    #[tokio::test]
+    async fn shares_bodies_that_are_not_polled_to_the_end() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = CoalesceLayer::new().layer(service(calls.clone(), "hello"));
+
+        // hyper stops polling a body once `is_end_stream` returns true
+        let leader = async {
+            let req = Request::get("/").body(Body::empty()).unwrap();
+            let mut body = svc.clone().oneshot(req).await.unwrap().into_body();
+            while !body.is_end_stream() {
+                body.data().await.unwrap().unwrap();
+            }
+        };
+        let ((), (_, body)) = tokio::join!(leader, get(svc.clone(), "/"));
+        assert_eq!(body, "hello");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
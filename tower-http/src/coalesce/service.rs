@@ -0,0 +1,183 @@
+use super::{
+    flight::{Flights, Key, Role},
+    future::State,
+    ResponseBody, ResponseFuture,
+};
+use crate::ready::take_ready;
+use http::{header, HeaderName, Method, Request, Response};
+use http_body::Body;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`Coalesce`] middleware which deduplicates concurrent identical
+/// requests.
+///
+/// Requests are only coalesced with requests to services produced by the same layer.
+///
+/// See the [module docs](crate::coalesce) for an example.
+#[derive(Clone, Debug)]
+pub struct CoalesceLayer {
+    flights: Flights,
+    headers: Vec<HeaderName>,
+    max_body_size: usize,
+}
+
+impl CoalesceLayer {
+    /// Create a new `CoalesceLayer`.
+    pub fn new() -> Self {
+        Self {
+            flights: Flights::default(),
+            headers: vec![
+                header::ACCEPT,
+                header::ACCEPT_ENCODING,
+                header::ACCEPT_LANGUAGE,
+                header::AUTHORIZATION,
+                header::COOKIE,
+            ],
+            max_body_size: 1024 * 1024,
+        }
+    }
+
+    /// Also require requests to have the same value of the given header to be coalesced.
+    ///
+    /// By default requests have to have the same `Accept`, `Accept-Encoding`, `Accept-Language`,
+    /// `Authorization` and `Cookie` headers, since responses usually depend on them.
+    pub fn key_header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    /// Set the maximum size of response bodies that are buffered to be shared.
+    ///
+    /// If the response is larger, the waiting requests are sent to the inner service themselves.
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+}
+
+impl Default for CoalesceLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for CoalesceLayer {
+    type Service = Coalesce<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Coalesce {
+            inner,
+            flights: self.flights.clone(),
+            headers: self.headers.clone(),
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+/// Middleware that deduplicates concurrent identical `GET` and `HEAD` requests.
+///
+/// See the [module docs](crate::coalesce) for an example.
+#[derive(Clone, Debug)]
+pub struct Coalesce<S> {
+    inner: S,
+    flights: Flights,
+    headers: Vec<HeaderName>,
+    max_body_size: usize,
+}
+
+impl<S> Coalesce<S> {
+    /// Create a new `Coalesce`.
+    pub fn new(inner: S) -> Self {
+        CoalesceLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Coalesce` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> CoalesceLayer {
+        CoalesceLayer::new()
+    }
+
+    /// Also require requests to have the same value of the given header to be coalesced.
+    ///
+    /// See [`CoalesceLayer::key_header`] for more details.
+    pub fn key_header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    /// Set the maximum size of response bodies that are buffered to be shared.
+    ///
+    /// See [`CoalesceLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+
+    /// Returns the number of requests other requests can currently be coalesced with.
+    pub fn in_flight(&self) -> usize {
+        self.flights.len()
+    }
+
+    fn key<B>(&self, req: &Request<B>) -> Option<Key>
+    where
+        B: Body,
+    {
+        let coalesce = matches!(*req.method(), Method::GET | Method::HEAD)
+            && req.body().size_hint().exact() == Some(0);
+        if !coalesce {
+            return None;
+        }
+        Some(Key {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers: self
+                .headers
+                .iter()
+                .map(|name| req.headers().get(name).cloned())
+                .collect(),
+        })
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Coalesce<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    ReqBody: Body,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, Request<ReqBody>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match self.key(&req).map(|key| self.flights.join(key)) {
+            Some(Role::Leader(leader)) => State::Leading {
+                future: self.inner.call(req),
+                leader: Some(leader),
+            },
+            Some(Role::Follower(flight)) => {
+                // the request may be sent later
+                State::Following {
+                    flight,
+                    service: take_ready(&mut self.inner),
+                    request: Some(req),
+                }
+            }
+            None => State::Called {
+                future: self.inner.call(req),
+            },
+        };
+        ResponseFuture::new(state, self.max_body_size)
+    }
+}
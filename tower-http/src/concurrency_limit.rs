@@ -0,0 +1,516 @@
+//! Middleware that limits the number of concurrent requests and sheds excess load.
+//!
+//! Unlike [`tower::limit::ConcurrencyLimit`], which applies backpressure through `poll_ready`,
+//! [`ConcurrencyLimit`] always accepts requests and responds with `503 Service Unavailable` when
+//! the limit is reached. Optionally a bounded number of requests can wait for capacity before
+//! excess requests are shed, and a `Retry-After` header can be added to the `503` responses.
+//!
+//! A request counts against the limit until its response body has been consumed or dropped.
+//!
+//! The limit is shared by all services produced by the same [`ConcurrencyLimitLayer`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::concurrency_limit::ConcurrencyLimitLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         // Process at most 100 requests at a time, let 50 more wait, and shed the rest.
+//!         ConcurrencyLimitLayer::new(100)
+//!             .queue(50)
+//!             .retry_after(Duration::from_secs(5)),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`tower::limit::ConcurrencyLimit`]: https://docs.rs/tower/latest/tower/limit/concurrency/struct.ConcurrencyLimit.html
+
+use crate::ready::take_ready;
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`ConcurrencyLimit`] middleware which limits the number of concurrent
+/// requests.
+///
+/// See the [module docs](crate::concurrency_limit) for an example.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimitLayer {
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    queue: usize,
+    retry_after: Option<HeaderValue>,
+}
+
+impl ConcurrencyLimitLayer {
+    /// Create a new `ConcurrencyLimitLayer` that allows at most `max` concurrent requests.
+    pub fn new(max: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            queue: 0,
+            retry_after: None,
+        }
+    }
+
+    /// Let up to `queue` requests wait for capacity before shedding requests.
+    ///
+    /// Defaults to `0`, meaning requests are shed as soon as the limit is reached.
+    pub fn queue(mut self, queue: usize) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// The duration is rounded down to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConcurrencyLimit {
+            inner,
+            semaphore: self.semaphore.clone(),
+            waiting: self.waiting.clone(),
+            queue: self.queue,
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+/// Middleware that limits the number of concurrent requests and responds with
+/// `503 Service Unavailable` once the limit is reached.
+///
+/// See the [module docs](crate::concurrency_limit) for an example.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    queue: usize,
+    retry_after: Option<HeaderValue>,
+}
+
+impl<S> ConcurrencyLimit<S> {
+    /// Create a new `ConcurrencyLimit` that allows at most `max` concurrent requests.
+    pub fn new(inner: S, max: usize) -> Self {
+        ConcurrencyLimitLayer::new(max).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ConcurrencyLimit` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(max: usize) -> ConcurrencyLimitLayer {
+        ConcurrencyLimitLayer::new(max)
+    }
+
+    /// Let up to `queue` requests wait for capacity before shedding requests.
+    ///
+    /// See [`ConcurrencyLimitLayer::queue`] for more details.
+    pub fn queue(mut self, queue: usize) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// See [`ConcurrencyLimitLayer::retry_after`] for more details.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    /// Returns the number of requests that can currently be processed without waiting.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ConcurrencyLimit<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, Request<ReqBody>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => State::Called {
+                future: self.inner.call(req),
+                permit: Some(permit),
+            },
+            Err(_) => match WaitingGuard::new(&self.waiting, self.queue) {
+                Some(guard) => {
+                    // the request is processed later
+                    let service = take_ready(&mut self.inner);
+                    State::Waiting {
+                        acquire: Box::pin(self.semaphore.clone().acquire_owned()),
+                        service,
+                        request: Some(req),
+                        guard: Some(guard),
+                        permit: None,
+                    }
+                }
+                None => State::Overloaded,
+            },
+        };
+
+        ResponseFuture {
+            state,
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+struct WaitingGuard {
+    waiting: Arc<AtomicUsize>,
+}
+
+impl WaitingGuard {
+    fn new(waiting: &Arc<AtomicUsize>, queue: usize) -> Option<Self> {
+        let mut current = waiting.load(Ordering::Acquire);
+        loop {
+            if current >= queue {
+                return None;
+            }
+            match waiting.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    return Some(Self {
+                        waiting: waiting.clone(),
+                    })
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        self.waiting.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+type AcquireFuture =
+    Pin<Box<dyn Future<Output = Result<OwnedSemaphorePermit, AcquireError>> + Send + 'static>>;
+
+pin_project! {
+    /// Response future for [`ConcurrencyLimit`].
+    pub struct ResponseFuture<S, R>
+    where
+        S: Service<R>,
+    {
+        #[pin]
+        state: State<S, R, S::Future>,
+        retry_after: Option<HeaderValue>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, R, F> {
+        Overloaded,
+        Waiting {
+            acquire: AcquireFuture,
+            service: S,
+            request: Option<R>,
+            guard: Option<WaitingGuard>,
+            permit: Option<OwnedSemaphorePermit>,
+        },
+        Called {
+            #[pin]
+            future: F,
+            permit: Option<OwnedSemaphorePermit>,
+        },
+    }
+}
+
+impl<S, R, B> Future for ResponseFuture<S, R>
+where
+    S: Service<R, Response = Response<B>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Overloaded => {
+                    return Poll::Ready(Ok(service_unavailable(this.retry_after.clone())));
+                }
+                StateProj::Waiting {
+                    acquire,
+                    service,
+                    request,
+                    guard,
+                    permit,
+                } => {
+                    if permit.is_none() {
+                        match ready!(acquire.as_mut().poll(cx)) {
+                            Ok(acquired) => {
+                                *permit = Some(acquired);
+                                guard.take();
+                            }
+                            // the semaphore is never closed
+                            Err(_) => unreachable!("semaphore closed"),
+                        }
+                    }
+                    ready!(service.poll_ready(cx))?;
+                    let request = request.take().expect("future polled after completion");
+                    State::Called {
+                        future: service.call(request),
+                        permit: permit.take(),
+                    }
+                }
+                StateProj::Called { future, permit } => {
+                    let res = ready!(future.poll(cx))?;
+                    let permit = permit.take();
+                    return Poll::Ready(Ok(res.map(|body| ResponseBody {
+                        kind: BodyKind::Body { body, permit },
+                    })));
+                }
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, R> fmt::Debug for ResponseFuture<S, R>
+where
+    S: Service<R>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+fn service_unavailable<B>(retry_after: Option<HeaderValue>) -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut res = Response::new(ResponseBody {
+        kind: BodyKind::Overloaded { body: Empty::new() },
+    });
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    if let Some(retry_after) = retry_after {
+        res.headers_mut().insert(header::RETRY_AFTER, retry_after);
+    }
+    res
+}
+
+pin_project! {
+    /// Response body for [`ConcurrencyLimit`].
+    ///
+    /// Holds on to the request's share of the limit until the body is consumed or dropped.
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        Overloaded {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            permit: Option<OwnedSemaphorePermit>,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Overloaded { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { mut body, permit } => {
+                let data = ready!(body.as_mut().poll_data(cx));
+                if data.is_none() && body.is_end_stream() {
+                    permit.take();
+                }
+                Poll::Ready(data)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Overloaded { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body, permit } => {
+                let trailers = ready!(body.poll_trailers(cx));
+                permit.take();
+                Poll::Ready(trailers)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Overloaded { body } => body.is_end_stream(),
+            BodyKind::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Overloaded { body } => body.size_hint(),
+            BodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn sheds_excess_requests() {
+        let mut svc = ServiceBuilder::new()
+            .layer(ConcurrencyLimitLayer::new(1).retry_after(Duration::from_secs(3)))
+            .service_fn(echo);
+
+        let first = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::from("foo")))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+
+        let second = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(second.headers()[header::RETRY_AFTER], "3");
+
+        // consuming the body of the first response frees up capacity
+        hyper::body::to_bytes(first.into_body()).await.unwrap();
+        let third = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn queued_requests_wait_for_capacity() {
+        let mut svc = ServiceBuilder::new()
+            .layer(ConcurrencyLimitLayer::new(1).queue(1))
+            .service_fn(echo);
+
+        let first = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+
+        let queued = svc.ready().await.unwrap().call(Request::new(Body::empty()));
+
+        // the queue is full
+        let shed = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(shed.headers().get(header::RETRY_AFTER).is_none());
+
+        drop(first);
+        let queued = queued.await.unwrap();
+        assert_eq!(queued.status(), StatusCode::OK);
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -0,0 +1,103 @@
+use crate::BoxError;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response body for [`Conditional`], which is the body of the service that handled the
+    /// request.
+    ///
+    /// [`Conditional`]: super::Conditional
+    #[derive(Debug)]
+    pub struct ConditionalBody<A, B> {
+        #[pin]
+        kind: Kind<A, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    #[derive(Debug)]
+    enum Kind<A, B> {
+        Matched {
+            #[pin]
+            body: A,
+        },
+        Skipped {
+            #[pin]
+            body: B,
+        },
+    }
+}
+
+impl<A, B> ConditionalBody<A, B> {
+    pub(super) fn matched(body: A) -> Self {
+        Self {
+            kind: Kind::Matched { body },
+        }
+    }
+
+    pub(super) fn skipped(body: B) -> Self {
+        Self {
+            kind: Kind::Skipped { body },
+        }
+    }
+
+    /// Returns `true` if the response was produced by the wrapped service.
+    pub fn is_matched(&self) -> bool {
+        matches!(self.kind, Kind::Matched { .. })
+    }
+}
+
+impl<A, B> Body for ConditionalBody<A, B>
+where
+    A: Body,
+    A::Error: Into<BoxError>,
+    B: Body<Data = A::Data>,
+    B::Error: Into<BoxError>,
+{
+    type Data = A::Data;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            KindProj::Matched { body } => body
+                .poll_data(cx)
+                .map(|data| data.map(|data| data.map_err(Into::into))),
+            KindProj::Skipped { body } => body
+                .poll_data(cx)
+                .map(|data| data.map(|data| data.map_err(Into::into))),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            KindProj::Matched { body } => body.poll_trailers(cx).map_err(Into::into),
+            KindProj::Skipped { body } => body.poll_trailers(cx).map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            Kind::Matched { body } => body.is_end_stream(),
+            Kind::Skipped { body } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            Kind::Matched { body } => body.size_hint(),
+            Kind::Skipped { body } => body.size_hint(),
+        }
+    }
+}
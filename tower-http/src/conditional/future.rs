@@ -0,0 +1,63 @@
+use super::ConditionalBody;
+use futures_util::ready;
+use http::Response;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response future for [`Conditional`].
+    ///
+    /// [`Conditional`]: super::Conditional
+    pub struct ResponseFuture<A, B> {
+        #[pin]
+        kind: Kind<A, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<A, B> {
+        Matched {
+            #[pin]
+            future: A,
+        },
+        Skipped {
+            #[pin]
+            future: B,
+        },
+    }
+}
+
+impl<A, B> ResponseFuture<A, B> {
+    pub(super) fn matched(future: A) -> Self {
+        Self {
+            kind: Kind::Matched { future },
+        }
+    }
+
+    pub(super) fn skipped(future: B) -> Self {
+        Self {
+            kind: Kind::Skipped { future },
+        }
+    }
+}
+
+impl<A, B, ResA, ResB, E> Future for ResponseFuture<A, B>
+where
+    A: Future<Output = Result<Response<ResA>, E>>,
+    B: Future<Output = Result<Response<ResB>, E>>,
+{
+    type Output = Result<Response<ConditionalBody<ResA, ResB>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = match self.project().kind.project() {
+            KindProj::Matched { future } => ready!(future.poll(cx))?.map(ConditionalBody::matched),
+            KindProj::Skipped { future } => ready!(future.poll(cx))?.map(ConditionalBody::skipped),
+        };
+        Poll::Ready(Ok(res))
+    }
+}
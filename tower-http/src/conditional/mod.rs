@@ -0,0 +1,217 @@
+//! Middleware that applies a layer only to some requests.
+//!
+//! [`Conditional`] sends requests matching a [`Predicate`] through a service wrapped in some
+//! layer, and all others straight to the inner service. This allows applying middleware to
+//! some routes without a router, such as compressing responses only under `/api` or requiring
+//! authorization only under `/admin`.
+//!
+//! Both services must be ready before a request is accepted, and the inner service is cloned
+//! to build the wrapped one. Since the wrapped layer may change the response body type, the
+//! responses have a [`ConditionalBody`] which is either body.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::{
+//!     conditional::{predicate::PathPrefix, ConditionalLayer},
+//!     set_header::SetResponseHeaderLayer,
+//! };
+//! use http::HeaderValue;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Infallible> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(ConditionalLayer::new(
+//!         PathPrefix::new("/api"),
+//!         SetResponseHeaderLayer::overriding(
+//!             header::CACHE_CONTROL,
+//!             HeaderValue::from_static("no-store"),
+//!         ),
+//!     ))
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/api/users").body(Body::empty()).unwrap();
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+//!
+//! let request = Request::get("/index.html").body(Body::empty()).unwrap();
+//! let response = service.ready().await?.call(request).await?;
+//! assert!(!response.headers().contains_key(header::CACHE_CONTROL));
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod predicate;
+
+mod body;
+mod future;
+
+pub use self::{body::ConditionalBody, future::ResponseFuture, predicate::Predicate};
+
+use http::{Request, Response};
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies [`Conditional`] which applies a layer only to requests matching a
+/// predicate.
+///
+/// See the [module docs](crate::conditional) for an example.
+#[derive(Debug, Clone)]
+pub struct ConditionalLayer<P, L> {
+    predicate: P,
+    layer: L,
+}
+
+impl<P, L> ConditionalLayer<P, L> {
+    /// Create a new `ConditionalLayer` applying `layer` to requests matching `predicate`.
+    pub fn new(predicate: P, layer: L) -> Self
+    where
+        P: Predicate,
+    {
+        Self { predicate, layer }
+    }
+}
+
+impl<S, P, L> Layer<S> for ConditionalLayer<P, L>
+where
+    S: Clone,
+    P: Clone,
+    L: Layer<S>,
+{
+    type Service = Conditional<S, P, L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Conditional {
+            wrapped: self.layer.layer(inner.clone()),
+            inner,
+            predicate: self.predicate.clone(),
+        }
+    }
+}
+
+/// Middleware that applies a layer only to requests matching a predicate.
+///
+/// See the [module docs](crate::conditional) for an example.
+#[derive(Debug, Clone)]
+pub struct Conditional<S, P, W> {
+    inner: S,
+    wrapped: W,
+    predicate: P,
+}
+
+impl<S, P, W> Conditional<S, P, W> {
+    /// Create a new `Conditional` sending requests matching `predicate` to `wrapped`, and all
+    /// others to `inner`.
+    pub fn new(inner: S, predicate: P, wrapped: W) -> Self
+    where
+        P: Predicate,
+    {
+        Self {
+            inner,
+            wrapped,
+            predicate,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Gets a reference to the service handling matching requests.
+    pub fn get_wrapped(&self) -> &W {
+        &self.wrapped
+    }
+
+    /// Gets a mutable reference to the service handling matching requests.
+    pub fn get_wrapped_mut(&mut self) -> &mut W {
+        &mut self.wrapped
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `Conditional` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<L>(predicate: P, layer: L) -> ConditionalLayer<P, L>
+    where
+        P: Predicate,
+    {
+        ConditionalLayer::new(predicate, layer)
+    }
+}
+
+impl<S, P, W, ReqBody, ResBody, WrappedResBody> Service<Request<ReqBody>> for Conditional<S, P, W>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    W: Service<Request<ReqBody>, Response = Response<WrappedResBody>, Error = S::Error>,
+    P: Predicate,
+{
+    type Response = Response<ConditionalBody<WrappedResBody, ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<W::Future, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // poll both so neither is left unready if the other returns `Pending`
+        let wrapped = self.wrapped.poll_ready(cx)?;
+        let inner = self.inner.poll_ready(cx)?;
+        if wrapped.is_ready() && inner.is_ready() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if self.predicate.matches(&req) {
+            ResponseFuture::matched(self.wrapped.call(req))
+        } else {
+            ResponseFuture::skipped(self.inner.call(req))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::predicate::{ForMethods, PathPrefix};
+    use super::*;
+    use crate::compression::CompressionLayer;
+    use http::{header, Method};
+    use hyper::{body::to_bytes, Body};
+    use tower::{service_fn, BoxError, ServiceBuilder, ServiceExt};
+
+    async fn handle(_req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(Body::from("a".repeat(64))))
+    }
+
+    #[tokio::test]
+    async fn applies_layer_to_matching_requests() {
+        let svc = ServiceBuilder::new()
+            .layer(ConditionalLayer::new(
+                PathPrefix::new("/api").and(ForMethods::new([Method::GET])),
+                CompressionLayer::new(),
+            ))
+            .service(service_fn(handle));
+
+        let req = Request::get("/api/items")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert!(res.body().is_matched());
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(to_bytes(res.into_body()).await.unwrap(), "a".repeat(64));
+
+        let req = Request::get("/static/app.js")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), "a".repeat(64));
+    }
+}
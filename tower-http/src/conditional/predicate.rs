@@ -0,0 +1,253 @@
+//! Predicates deciding which requests a conditional layer is applied to.
+//!
+//! Predicates are used with [`ConditionalLayer::new`].
+//!
+//! [`ConditionalLayer::new`]: super::ConditionalLayer::new
+
+use http::{header::HeaderName, HeaderMap, HeaderValue, Method, Request, Uri};
+use std::{convert::TryFrom, sync::Arc};
+
+/// Predicate used to determine if a request should be handled by the wrapped layer or not.
+pub trait Predicate: Clone {
+    /// Should this request be handled by the wrapped layer or not?
+    fn matches<B>(&self, request: &Request<B>) -> bool;
+
+    /// Combine two predicates into one.
+    ///
+    /// The resulting predicate matches if both inner predicates do.
+    fn and<Other>(self, other: Other) -> And<Self, Other>
+    where
+        Self: Sized,
+        Other: Predicate,
+    {
+        And {
+            lhs: self,
+            rhs: other,
+        }
+    }
+
+    /// Combine two predicates into one.
+    ///
+    /// The resulting predicate matches if either inner predicate does.
+    fn or<Other>(self, other: Other) -> Or<Self, Other>
+    where
+        Self: Sized,
+        Other: Predicate,
+    {
+        Or {
+            lhs: self,
+            rhs: other,
+        }
+    }
+
+    /// Invert the predicate.
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not { inner: self }
+    }
+}
+
+impl<F> Predicate for F
+where
+    F: Fn(&Method, &Uri, &HeaderMap) -> bool + Clone,
+{
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        self(request.method(), request.uri(), request.headers())
+    }
+}
+
+/// Two predicates combined into one that matches if both do.
+///
+/// Created with [`Predicate::and`]
+#[derive(Debug, Clone, Default, Copy)]
+pub struct And<Lhs, Rhs> {
+    lhs: Lhs,
+    rhs: Rhs,
+}
+
+impl<Lhs, Rhs> Predicate for And<Lhs, Rhs>
+where
+    Lhs: Predicate,
+    Rhs: Predicate,
+{
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        self.lhs.matches(request) && self.rhs.matches(request)
+    }
+}
+
+/// Two predicates combined into one that matches if either does.
+///
+/// Created with [`Predicate::or`]
+#[derive(Debug, Clone, Default, Copy)]
+pub struct Or<Lhs, Rhs> {
+    lhs: Lhs,
+    rhs: Rhs,
+}
+
+impl<Lhs, Rhs> Predicate for Or<Lhs, Rhs>
+where
+    Lhs: Predicate,
+    Rhs: Predicate,
+{
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        self.lhs.matches(request) || self.rhs.matches(request)
+    }
+}
+
+/// A predicate that matches if its inner predicate doesn't.
+///
+/// Created with [`Predicate::not`]
+#[derive(Debug, Clone, Default, Copy)]
+pub struct Not<P> {
+    inner: P,
+}
+
+impl<P> Predicate for Not<P>
+where
+    P: Predicate,
+{
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        !self.inner.matches(request)
+    }
+}
+
+/// Predicate that matches requests whose path starts with a prefix.
+///
+/// The prefix only matches whole segments, so `/api` matches `/api` and `/api/users` but not
+/// `/apiusers`.
+#[derive(Debug, Clone)]
+pub struct PathPrefix {
+    prefix: Arc<str>,
+}
+
+impl PathPrefix {
+    /// Create a new `PathPrefix`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` doesn't start with `/`.
+    pub fn new(prefix: &str) -> Self {
+        assert!(prefix.starts_with('/'), "prefix must start with `/`");
+        Self {
+            prefix: prefix.trim_end_matches('/').into(),
+        }
+    }
+}
+
+impl Predicate for PathPrefix {
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        match request.uri().path().strip_prefix(&*self.prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// Predicate that matches requests with one of a set of methods.
+#[derive(Debug, Clone)]
+pub struct ForMethods {
+    methods: Arc<[Method]>,
+}
+
+impl ForMethods {
+    /// Create a new `ForMethods` matching requests with any of `methods`.
+    pub fn new<I>(methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        Self {
+            methods: methods.into_iter().collect(),
+        }
+    }
+}
+
+impl Predicate for ForMethods {
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        self.methods.contains(request.method())
+    }
+}
+
+/// Predicate that matches requests with a header, optionally with a specific value.
+#[derive(Debug, Clone)]
+pub struct HasHeader {
+    name: HeaderName,
+    value: Option<HeaderValue>,
+}
+
+impl HasHeader {
+    /// Create a new `HasHeader` matching requests that have the header `name`.
+    pub fn new(name: HeaderName) -> Self {
+        Self { name, value: None }
+    }
+
+    /// Create a new `HasHeader` matching requests where one of the `name` headers is `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    pub fn with_value(name: HeaderName, value: &str) -> Self {
+        let value = HeaderValue::try_from(value).expect("invalid header value");
+        Self {
+            name,
+            value: Some(value),
+        }
+    }
+}
+
+impl Predicate for HasHeader {
+    fn matches<B>(&self, request: &Request<B>) -> bool {
+        let mut values = request.headers().get_all(&self.name).iter();
+        match &self.value {
+            Some(expected) => values.any(|value| value == expected),
+            None => values.next().is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: Method, uri: &str) -> Request<()> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-debug", "1")
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn path_prefix_matches_segments() {
+        let predicate = PathPrefix::new("/api/");
+        assert!(predicate.matches(&request(Method::GET, "/api")));
+        assert!(predicate.matches(&request(Method::GET, "/api/users?page=1")));
+        assert!(!predicate.matches(&request(Method::GET, "/apiusers")));
+        assert!(!predicate.matches(&request(Method::GET, "/")));
+
+        assert!(PathPrefix::new("/").matches(&request(Method::GET, "/anything")));
+    }
+
+    #[test]
+    fn combinators() {
+        let predicate = PathPrefix::new("/admin")
+            .and(ForMethods::new([Method::GET]).not())
+            .or(HasHeader::with_value(
+                HeaderName::from_static("x-debug"),
+                "2",
+            ));
+        assert!(predicate.matches(&request(Method::POST, "/admin")));
+        assert!(!predicate.matches(&request(Method::GET, "/admin")));
+        assert!(!predicate.matches(&request(Method::POST, "/")));
+
+        let closure = |method: &Method, _: &Uri, headers: &HeaderMap| {
+            method == Method::DELETE && headers.contains_key("x-debug")
+        };
+        assert!(closure.matches(&request(Method::DELETE, "/")));
+        assert!(
+            HasHeader::new(HeaderName::from_static("x-debug")).matches(&request(Method::GET, "/"))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use super::{content_digest, Hasher, CONTENT_DIGEST};
+use crate::BoxError;
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+use http::{HeaderMap, HeaderValue};
+use http_body::{Body, SizeHint};
+use std::{
+    convert::TryFrom,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Request body for [`SetContentDigest`] and [`ValidateContentDigest`].
+///
+/// The body, or the part of it that was read before it turned out to be too large to be
+/// buffered, has been buffered to compute its digest.
+///
+/// [`SetContentDigest`]: super::SetContentDigest
+/// [`ValidateContentDigest`]: super::ValidateContentDigest
+pub struct ContentDigestBody<B> {
+    buffered: Option<Bytes>,
+    rest: Pin<Box<B>>,
+    // whether the data of `rest` has been read completely
+    rest_done: bool,
+    // computes the digest sent in the trailers of streamed bodies
+    trailer: Option<Hasher>,
+}
+
+impl<B> ContentDigestBody<B> {
+    pub(super) fn new(buffered: Bytes, rest: Pin<Box<B>>, rest_done: bool) -> Self {
+        Self {
+            buffered: Some(buffered).filter(|data| !data.is_empty()),
+            rest,
+            rest_done,
+            trailer: None,
+        }
+    }
+
+    /// Send the digest of the whole body, computed by `hasher`, in the trailers.
+    ///
+    /// `hasher` must have been updated with the buffered data already.
+    pub(super) fn with_trailer(mut self, hasher: Hasher) -> Self {
+        self.trailer = Some(hasher);
+        self
+    }
+}
+
+impl<B> Body for ContentDigestBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        if let Some(data) = this.buffered.take() {
+            return Poll::Ready(Some(Ok(data)));
+        }
+        if this.rest_done {
+            return Poll::Ready(None);
+        }
+        match ready!(this.rest.as_mut().poll_data(cx)) {
+            Some(Ok(mut data)) => {
+                let data = data.copy_to_bytes(data.remaining());
+                if let Some(hasher) = &mut this.trailer {
+                    hasher.update(&data);
+                }
+                Poll::Ready(Some(Ok(data)))
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err.into()))),
+            None => {
+                this.rest_done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        let trailers = ready!(this.rest.as_mut().poll_trailers(cx)).map_err(Into::into)?;
+        let hasher = match this.trailer.take() {
+            Some(hasher) => hasher,
+            None => return Poll::Ready(Ok(trailers)),
+        };
+
+        let algorithm = hasher.algorithm();
+        let digest = content_digest(algorithm, &hasher.finalize());
+        let mut trailers = trailers.unwrap_or_default();
+        trailers.insert(CONTENT_DIGEST, HeaderValue::try_from(digest)?);
+        Poll::Ready(Ok(Some(trailers)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buffered.is_none()
+            && (self.rest_done || self.rest.is_end_stream())
+            && self.trailer.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self.buffered.as_ref().map_or(0, |data| data.len() as u64);
+        if self.rest_done {
+            return SizeHint::with_exact(buffered);
+        }
+        let rest = self.rest.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(buffered + rest.lower());
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(buffered + upper);
+        }
+        hint
+    }
+}
+
+impl<B> fmt::Debug for ContentDigestBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentDigestBody")
+            .field("buffered", &self.buffered.as_ref().map(Bytes::len))
+            .field("rest_done", &self.rest_done)
+            .field("trailer", &self.trailer.as_ref().map(Hasher::algorithm))
+            .finish()
+    }
+}
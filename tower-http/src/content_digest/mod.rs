@@ -0,0 +1,416 @@
+//! Middleware that sets and validates the digests of request bodies.
+//!
+//! [`SetContentDigest`] is used by clients. It computes the digest of request bodies and sends
+//! it in the `Content-Digest` header ([RFC 9530]), such as `sha-256=:...:`. Bodies larger than
+//! [`SetContentDigestLayer::max_body_size`] aren't buffered. They are streamed and their digest
+//! is sent in a `Content-Digest` trailer instead, which requires a protocol that supports
+//! trailers such as HTTP/2. Requests that already have a `Content-Digest` header are left
+//! alone.
+//!
+//! [`ValidateContentDigest`] is used by servers. It buffers the bodies of requests with a
+//! `Content-Digest` header, or the legacy `Digest` header ([RFC 3230]), and responds with
+//! `400 Bad Request` unless all digests with a supported algorithm match. Requests without
+//! digests are passed through, unless [`ValidateContentDigestLayer::require`] is set.
+//!
+//! The `sha-256` and `sha-512` algorithms are supported.
+//!
+//! [RFC 9530]: https://www.rfc-editor.org/rfc/rfc9530
+//! [RFC 3230]: https://www.rfc-editor.org/rfc/rfc3230
+//!
+//! # Signing bodies
+//!
+//! HTTP Message Signatures don't cover bodies, but they can cover the `Content-Digest` header.
+//! Clients set the digest before signing, and servers validate it before the signature is
+//! verified, or after since the order doesn't matter:
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, ServiceExt};
+//! use tower_http::{
+//!     content_digest::{ContentDigestBody, SetContentDigestLayer, ValidateContentDigestLayer},
+//!     message_signature::{HmacSha256Key, SignMessageLayer, VerifyMessageLayer},
+//! };
+//!
+//! async fn handle<B>(req: Request<B>) -> Result<Response<Body>, Infallible> {
+//!     // only requests with a valid signature and body digest get here
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! let components = ["@method", "@authority", "@path", "@query", "content-digest"];
+//!
+//! let server = ServiceBuilder::new()
+//!     .layer(ValidateContentDigestLayer::new().require(true))
+//!     .layer(
+//!         VerifyMessageLayer::new(|_: &str| Some(HmacSha256Key::new(b"secret")))
+//!             .required_components(components),
+//!     )
+//!     .service_fn(handle::<ContentDigestBody<ContentDigestBody<Body>>>);
+//!
+//! let client = ServiceBuilder::new()
+//!     .layer(SetContentDigestLayer::new())
+//!     .layer(
+//!         SignMessageLayer::new("key", HmacSha256Key::new(b"secret"))
+//!             .covered_components(components),
+//!     )
+//!     .service(server);
+//!
+//! let request = Request::post("https://example.com/payments").body(Body::from("{}"))?;
+//! let response = client.oneshot(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod set;
+mod validate;
+
+pub use self::{
+    body::ContentDigestBody,
+    set::{ResponseFuture as SetResponseFuture, SetContentDigest, SetContentDigestLayer},
+    validate::{
+        ResponseFuture as ValidateResponseFuture, ValidateContentDigest, ValidateContentDigestLayer,
+    },
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use http::{HeaderMap, HeaderName};
+use sha2::{Digest, Sha256, Sha512};
+
+const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+const DIGEST: HeaderName = HeaderName::from_static("digest");
+
+/// The hash algorithms digests can be computed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DigestAlgorithm {
+    /// SHA-256, `sha-256`.
+    Sha256,
+    /// SHA-512, `sha-512`.
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    /// The key of the algorithm in `Content-Digest` headers.
+    fn key(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha-256",
+            Self::Sha512 => "sha-512",
+        }
+    }
+
+    /// The algorithm called `name`, which is case insensitive as legacy `Digest` headers use
+    /// uppercase names.
+    fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("sha-256") {
+            Some(Self::Sha256)
+        } else if name.eq_ignore_ascii_case("sha-512") {
+            Some(Self::Sha512)
+        } else {
+            None
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+            Self::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// An incremental hasher for one of the [`DigestAlgorithm`]s.
+#[derive(Clone)]
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn algorithm(&self) -> DigestAlgorithm {
+        match self {
+            Self::Sha256(_) => DigestAlgorithm::Sha256,
+            Self::Sha512(_) => DigestAlgorithm::Sha512,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Sha512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// The value of a `Content-Digest` header, such as `sha-256=:...:`.
+fn content_digest(algorithm: DigestAlgorithm, digest: &[u8]) -> String {
+    format!("{}=:{}:", algorithm.key(), STANDARD.encode(digest))
+}
+
+/// The value of a legacy `Digest` header, such as `SHA-256=...`.
+fn legacy_digest(algorithm: DigestAlgorithm, digest: &[u8]) -> String {
+    format!(
+        "{}={}",
+        algorithm.key().to_ascii_uppercase(),
+        STANDARD.encode(digest)
+    )
+}
+
+/// The digests of the `Content-Digest` and `Digest` headers.
+///
+/// Returns `None` if there are no digests, and digests with an unsupported algorithm or an
+/// invalid encoding are skipped.
+fn parse_digests(headers: &HeaderMap) -> Option<Vec<(DigestAlgorithm, Vec<u8>)>> {
+    let mut found = false;
+    let mut digests = Vec::new();
+    let values = headers
+        .get_all(CONTENT_DIGEST)
+        .iter()
+        .map(|value| (value, true))
+        .chain(headers.get_all(DIGEST).iter().map(|value| (value, false)));
+    for (value, structured) in values {
+        found = true;
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        for member in value.split(',') {
+            let (name, digest) = match member.trim().split_once('=') {
+                Some(member) => member,
+                None => continue,
+            };
+            let digest = if structured {
+                // byte sequences are delimited by colons and may be followed by parameters
+                digest
+                    .split(';')
+                    .next()
+                    .and_then(|digest| digest.strip_prefix(':')?.strip_suffix(':'))
+            } else {
+                Some(digest)
+            };
+            let digest = digest.and_then(|digest| STANDARD.decode(digest).ok());
+            if let (Some(algorithm), Some(digest)) = (DigestAlgorithm::from_name(name), digest) {
+                digests.push((algorithm, digest));
+            }
+        }
+    }
+    if found {
+        Some(digests)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Request, Response, StatusCode};
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, Layer, ServiceExt};
+
+    async fn echo<B>(req: Request<B>) -> Result<Response<Body>, BoxError>
+    where
+        B: http_body::Body,
+        B::Error: Into<BoxError>,
+    {
+        let body = hyper::body::to_bytes(req.into_body())
+            .await
+            .map_err(Into::into)?;
+        Ok(Response::new(Body::from(body)))
+    }
+
+    async fn validate(layer: ValidateContentDigestLayer, req: Request<Body>) -> StatusCode {
+        let res = layer
+            .layer(service_fn(echo::<ContentDigestBody<Body>>))
+            .oneshot(req)
+            .await
+            .unwrap();
+        res.status()
+    }
+
+    fn request(digest: &str) -> Request<Body> {
+        Request::post("/")
+            .header("content-digest", digest)
+            .body(Body::from("hello"))
+            .unwrap()
+    }
+
+    const HELLO_SHA256: &str = "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:";
+
+    #[test]
+    fn parses_digests() {
+        let req = Request::post("/")
+            .header(
+                "content-digest",
+                "sha-256=:AAAA:, md5=:AAAA:, sha-512=:AAAA:;p=1",
+            )
+            .header("digest", "SHA-256=AAAA")
+            .body(())
+            .unwrap();
+        let digests = parse_digests(req.headers()).unwrap();
+        assert_eq!(
+            digests.iter().map(|(alg, _)| *alg).collect::<Vec<_>>(),
+            [
+                DigestAlgorithm::Sha256,
+                DigestAlgorithm::Sha512,
+                DigestAlgorithm::Sha256
+            ]
+        );
+        assert_eq!(digests[0].1, [0, 0, 0]);
+
+        let req = Request::post("/").body(()).unwrap();
+        assert!(parse_digests(req.headers()).is_none());
+    }
+
+    #[tokio::test]
+    async fn sets_digest() {
+        let svc = SetContentDigestLayer::new().layer(service_fn(
+            |req: Request<ContentDigestBody<Body>>| async move {
+                assert_eq!(req.headers()["content-digest"], HELLO_SHA256);
+                assert!(req.headers().get("digest").is_none());
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                assert_eq!(body, "hello");
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            },
+        ));
+        svc.oneshot(Request::post("/").body(Body::from("hello")).unwrap())
+            .await
+            .unwrap();
+
+        let svc = SetContentDigestLayer::new()
+            .algorithm(DigestAlgorithm::Sha512)
+            .legacy_digest(true)
+            .layer(service_fn(
+                |req: Request<ContentDigestBody<Body>>| async move {
+                    let digest = req.headers()["content-digest"].to_str().unwrap();
+                    assert!(digest.starts_with("sha-512=:"));
+                    let digest = req.headers()["digest"].to_str().unwrap();
+                    assert!(digest.starts_with("SHA-512="));
+                    Ok::<_, BoxError>(Response::new(Body::empty()))
+                },
+            ));
+        svc.oneshot(Request::post("/").body(Body::from("hello")).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn streams_large_bodies_with_trailer() {
+        let svc = SetContentDigestLayer::new()
+            .max_body_size(2)
+            .layer(service_fn(
+                |req: Request<ContentDigestBody<Body>>| async move {
+                    assert!(req.headers().get("content-digest").is_none());
+                    assert_eq!(req.headers()["trailer"], "content-digest");
+                    let mut body = req.into_body();
+                    let mut data = Vec::new();
+                    while let Some(chunk) = body.data().await {
+                        data.extend_from_slice(&chunk?);
+                    }
+                    assert_eq!(data, b"hello");
+                    let trailers = body.trailers().await?.unwrap();
+                    assert_eq!(trailers["content-digest"], HELLO_SHA256);
+                    Ok::<_, BoxError>(Response::new(Body::empty()))
+                },
+            ));
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data("hel".into()).await.unwrap();
+            sender.send_data("lo".into()).await.unwrap();
+        });
+        svc.oneshot(Request::post("/").body(body).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let client = SetContentDigestLayer::new().layer(
+            ValidateContentDigestLayer::new()
+                .require(true)
+                .layer(service_fn(
+                    echo::<ContentDigestBody<ContentDigestBody<Body>>>,
+                )),
+        );
+        let res = client
+            .oneshot(Request::post("/").body(Body::from("hello")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            hyper::body::to_bytes(res.into_body()).await.unwrap(),
+            "hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn validates_digests() {
+        let layer = ValidateContentDigestLayer::new;
+        assert_eq!(
+            validate(layer(), request(HELLO_SHA256)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            validate(layer(), request("sha-256=:AAAA:")).await,
+            StatusCode::BAD_REQUEST
+        );
+        // unsupported algorithms are ignored, but at least one digest has to be supported
+        assert_eq!(
+            validate(layer(), request(&format!("md5=:AAAA:, {}", HELLO_SHA256))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            validate(layer(), request("md5=:AAAA:")).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        let legacy = Request::post("/")
+            .header(
+                "digest",
+                "SHA-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=",
+            )
+            .body(Body::from("hello"))
+            .unwrap();
+        assert_eq!(validate(layer(), legacy).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn missing_digests() {
+        let req = || Request::post("/").body(Body::from("hello")).unwrap();
+        assert_eq!(
+            validate(ValidateContentDigestLayer::new(), req()).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            validate(ValidateContentDigestLayer::new().require(true), req()).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_large_bodies() {
+        let layer = ValidateContentDigestLayer::new().max_body_size(2);
+        assert_eq!(
+            validate(layer, request(HELLO_SHA256)).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+}
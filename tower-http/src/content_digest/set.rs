@@ -0,0 +1,279 @@
+use super::{
+    content_digest, legacy_digest, ContentDigestBody, DigestAlgorithm, CONTENT_DIGEST, DIGEST,
+};
+use crate::{
+    buffer::{Buffer, Buffered},
+    ready::take_ready,
+    BoxError,
+};
+use futures_util::ready;
+use http::{header, request::Parts, HeaderValue, Request};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    algorithm: DigestAlgorithm,
+    max_body_size: usize,
+    legacy_digest: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            algorithm: DigestAlgorithm::Sha256,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            legacy_digest: false,
+        }
+    }
+}
+
+/// Layer that applies [`SetContentDigest`] which sets the `Content-Digest` of request bodies.
+///
+/// See the [module docs](crate::content_digest) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct SetContentDigestLayer {
+    config: Config,
+}
+
+impl SetContentDigestLayer {
+    /// Create a new `SetContentDigestLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the algorithm digests are computed with.
+    ///
+    /// Defaults to [`DigestAlgorithm::Sha256`].
+    pub fn algorithm(mut self, algorithm: DigestAlgorithm) -> Self {
+        self.config.algorithm = algorithm;
+        self
+    }
+
+    /// Set the size of the largest body that is buffered to send its digest in a header.
+    /// The digest of larger bodies is sent in a trailer.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+
+    /// Also send the digest in the legacy `Digest` header, for servers that don't support
+    /// `Content-Digest` yet.
+    ///
+    /// Defaults to `false`.
+    pub fn legacy_digest(mut self, legacy_digest: bool) -> Self {
+        self.config.legacy_digest = legacy_digest;
+        self
+    }
+}
+
+impl<S> Layer<S> for SetContentDigestLayer {
+    type Service = SetContentDigest<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetContentDigest {
+            inner,
+            config: self.config,
+        }
+    }
+}
+
+/// Middleware that sets the `Content-Digest` of request bodies.
+///
+/// See the [module docs](crate::content_digest) for more details.
+#[derive(Debug, Clone)]
+pub struct SetContentDigest<S> {
+    inner: S,
+    config: Config,
+}
+
+impl<S> SetContentDigest<S> {
+    /// Create a new `SetContentDigest`.
+    pub fn new(inner: S) -> Self {
+        SetContentDigestLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SetContentDigest` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> SetContentDigestLayer {
+        SetContentDigestLayer::new()
+    }
+
+    /// Set the algorithm digests are computed with.
+    ///
+    /// See [`SetContentDigestLayer::algorithm`] for more details.
+    pub fn algorithm(mut self, algorithm: DigestAlgorithm) -> Self {
+        self.config.algorithm = algorithm;
+        self
+    }
+
+    /// Set the size of the largest body that is buffered to send its digest in a header.
+    ///
+    /// See [`SetContentDigestLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+
+    /// Also send the digest in the legacy `Digest` header.
+    ///
+    /// See [`SetContentDigestLayer::legacy_digest`] for more details.
+    pub fn legacy_digest(mut self, legacy_digest: bool) -> Self {
+        self.config.legacy_digest = legacy_digest;
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for SetContentDigest<S>
+where
+    S: Service<Request<ContentDigestBody<ReqBody>>> + Clone,
+    S::Error: Into<BoxError>,
+    ReqBody: Body,
+    ReqBody::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (parts, body) = req.into_parts();
+
+        if parts.headers.contains_key(CONTENT_DIGEST) {
+            let body = ContentDigestBody::new(Default::default(), Box::pin(body), false);
+            return ResponseFuture {
+                state: State::Called {
+                    future: self.inner.call(Request::from_parts(parts, body)),
+                },
+                config: self.config,
+            };
+        }
+
+        ResponseFuture {
+            state: State::Buffering {
+                buffer: Buffer::new(body, self.config.max_body_size),
+                parts: Some(parts),
+                service: Some(take_ready(&mut self.inner)),
+            },
+            config: self.config,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`SetContentDigest`].
+    pub struct ResponseFuture<S, B>
+    where
+        S: Service<Request<ContentDigestBody<B>>>,
+    {
+        #[pin]
+        state: State<S, B, S::Future>,
+        config: Config,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, B, F> {
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, B> Future for ResponseFuture<S, B>
+where
+    S: Service<Request<ContentDigestBody<B>>>,
+    S::Error: Into<BoxError>,
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<S::Response, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    service,
+                } => {
+                    let (Buffered { data, complete, .. }, rest) = match ready!(buffer.poll(cx)) {
+                        Ok(buffered) => buffered,
+                        Err(err) => return Poll::Ready(Err(err.into())),
+                    };
+
+                    let mut parts = parts.take().expect("future polled after completion");
+                    let algorithm = this.config.algorithm;
+                    // what has been read of bodies that are too large is sent followed by the
+                    // rest, with the digest in the trailers
+                    let body = if complete {
+                        let digest = algorithm.digest(&data);
+                        let value = content_digest(algorithm, &digest);
+                        parts
+                            .headers
+                            .insert(CONTENT_DIGEST, HeaderValue::try_from(value)?);
+                        if this.config.legacy_digest {
+                            let value = legacy_digest(algorithm, &digest);
+                            parts.headers.insert(DIGEST, HeaderValue::try_from(value)?);
+                        }
+                        ContentDigestBody::new(data, rest, true)
+                    } else {
+                        let mut hasher = algorithm.hasher();
+                        hasher.update(&data);
+                        parts
+                            .headers
+                            .append(header::TRAILER, HeaderValue::from_static("content-digest"));
+                        ContentDigestBody::new(data, rest, false).with_trailer(hasher)
+                    };
+
+                    let mut service = service.take().expect("future polled after completion");
+                    State::Called {
+                        future: service.call(Request::from_parts(parts, body)),
+                    }
+                }
+                StateProj::Called { future } => return future.poll(cx).map_err(Into::into),
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<ContentDigestBody<B>>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("config", &self.config)
+            .finish()
+    }
+}
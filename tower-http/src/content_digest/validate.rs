@@ -0,0 +1,274 @@
+use super::{parse_digests, ContentDigestBody, DigestAlgorithm};
+use crate::{
+    buffer::{content_length_exceeds, rejection, Buffer},
+    ready::take_ready,
+};
+use futures_util::ready;
+use http::{request::Parts, Request, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    max_body_size: usize,
+    require: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            require: false,
+        }
+    }
+}
+
+/// Layer that applies [`ValidateContentDigest`] which rejects requests whose body doesn't
+/// match their digest.
+///
+/// See the [module docs](crate::content_digest) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct ValidateContentDigestLayer {
+    config: Config,
+}
+
+impl ValidateContentDigestLayer {
+    /// Create a new `ValidateContentDigestLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the size of the largest request body that is buffered to validate its digest.
+    /// Larger requests are rejected with `413 Payload Too Large`.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+
+    /// Reject requests without a digest with `400 Bad Request`.
+    ///
+    /// Defaults to `false`.
+    pub fn require(mut self, require: bool) -> Self {
+        self.config.require = require;
+        self
+    }
+}
+
+impl<S> Layer<S> for ValidateContentDigestLayer {
+    type Service = ValidateContentDigest<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ValidateContentDigest {
+            inner,
+            config: self.config,
+        }
+    }
+}
+
+/// Middleware that rejects requests whose body doesn't match their digest.
+///
+/// See the [module docs](crate::content_digest) for more details.
+#[derive(Debug, Clone)]
+pub struct ValidateContentDigest<S> {
+    inner: S,
+    config: Config,
+}
+
+impl<S> ValidateContentDigest<S> {
+    /// Create a new `ValidateContentDigest`.
+    pub fn new(inner: S) -> Self {
+        ValidateContentDigestLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ValidateContentDigest` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> ValidateContentDigestLayer {
+        ValidateContentDigestLayer::new()
+    }
+
+    /// Set the size of the largest request body that is buffered to validate its digest.
+    ///
+    /// See [`ValidateContentDigestLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+
+    /// Reject requests without a digest.
+    ///
+    /// See [`ValidateContentDigestLayer::require`] for more details.
+    pub fn require(mut self, require: bool) -> Self {
+        self.config.require = require;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ValidateContentDigest<S>
+where
+    S: Service<Request<ContentDigestBody<ReqBody>>, Response = Response<ResBody>> + Clone,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let digests = match parse_digests(req.headers()) {
+            // at least one of the digests has to be checked
+            Some(digests) if digests.is_empty() => {
+                return ResponseFuture::rejected(StatusCode::BAD_REQUEST)
+            }
+            Some(digests) => digests,
+            None if self.config.require => {
+                return ResponseFuture::rejected(StatusCode::BAD_REQUEST)
+            }
+            None => {
+                let (parts, body) = req.into_parts();
+                let body = ContentDigestBody::new(Default::default(), Box::pin(body), false);
+                return ResponseFuture {
+                    state: State::Called {
+                        future: self.inner.call(Request::from_parts(parts, body)),
+                    },
+                };
+            }
+        };
+
+        if content_length_exceeds(req.headers(), self.config.max_body_size) {
+            return ResponseFuture::rejected(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let (parts, body) = req.into_parts();
+        ResponseFuture {
+            state: State::Buffering {
+                buffer: Buffer::new(body, self.config.max_body_size),
+                parts: Some(parts),
+                digests,
+                service: Some(take_ready(&mut self.inner)),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ValidateContentDigest`].
+    pub struct ResponseFuture<S, B>
+    where
+        S: Service<Request<ContentDigestBody<B>>>,
+    {
+        #[pin]
+        state: State<S, B, S::Future>,
+    }
+}
+
+impl<S, B> ResponseFuture<S, B>
+where
+    S: Service<Request<ContentDigestBody<B>>>,
+{
+    fn rejected(status: StatusCode) -> Self {
+        Self {
+            state: State::Rejected { status },
+        }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, B, F> {
+        Rejected {
+            status: StatusCode,
+        },
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            digests: Vec<(DigestAlgorithm, Vec<u8>)>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, B, ResBody> Future for ResponseFuture<S, B>
+where
+    S: Service<Request<ContentDigestBody<B>>, Response = Response<ResBody>>,
+    B: Body,
+    ResBody: Default,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Rejected { status } => return Poll::Ready(Ok(rejection(*status))),
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    digests,
+                    service,
+                } => match ready!(buffer.poll(cx)) {
+                    Ok((buffered, rest)) if buffered.complete => {
+                        let valid = digests
+                            .iter()
+                            .all(|(algorithm, digest)| algorithm.digest(&buffered.data) == *digest);
+
+                        if valid {
+                            let parts = parts.take().expect("future polled after completion");
+                            let body = ContentDigestBody::new(buffered.data, rest, true);
+                            let mut service =
+                                service.take().expect("future polled after completion");
+                            State::Called {
+                                future: service.call(Request::from_parts(parts, body)),
+                            }
+                        } else {
+                            State::Rejected {
+                                status: StatusCode::BAD_REQUEST,
+                            }
+                        }
+                    }
+                    Ok(_) => State::Rejected {
+                        status: StatusCode::PAYLOAD_TOO_LARGE,
+                    },
+                    Err(_) => State::Rejected {
+                        status: StatusCode::BAD_REQUEST,
+                    },
+                },
+                StateProj::Called { future } => return future.poll(cx),
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<ContentDigestBody<B>>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
@@ -0,0 +1,645 @@
+//! Middleware that checks bodies match their declared `Content-Length`.
+//!
+//! A body that is shorter or longer than its `Content-Length` usually means the peer was
+//! truncated or is attempting to smuggle requests. By default such bodies pass through this
+//! crate's middleware silently. These middleware count the bytes actually streamed and fail the
+//! body with a [`ContentLengthError`] as soon as a mismatch is detected.
+//!
+//! - [`RequestContentLengthLayer`] checks request bodies. Requests with an invalid
+//!   `Content-Length`, conflicting `Content-Length` values or both `Content-Length` and
+//!   `Transfer-Encoding` are rejected with `400 Bad Request` without calling the inner service. If
+//!   the inner service fails after reading a mismatched body the error is converted into a
+//!   `400 Bad Request` response as well.
+//! - [`ResponseContentLengthLayer`] checks response bodies, which is mostly useful for proxies and
+//!   clients. Responses to `HEAD` requests and responses that can't have a body are not checked.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use tower::{BoxError, Service, ServiceBuilder, ServiceExt};
+//! use tower_http::content_length::{ContentLengthBody, RequestContentLengthLayer};
+//!
+//! async fn handle(req: Request<ContentLengthBody<Body>>) -> Result<Response<Body>, BoxError> {
+//!     // Fails if the body doesn't match the `Content-Length` header.
+//!     let body = hyper::body::to_bytes(req.into_body()).await?;
+//!     Ok(Response::new(Body::from(body)))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(RequestContentLengthLayer::new())
+//!     .service_fn(handle);
+//!
+//! let request = Request::builder()
+//!     .header(header::CONTENT_LENGTH, "10")
+//!     .body(Body::from("too short"))?;
+//!
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::BoxError;
+use bytes::Buf;
+use futures_util::ready;
+use http::{header, HeaderMap, Method, Request, Response, StatusCode};
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`RequestContentLength`] middleware which checks request bodies match
+/// their `Content-Length`.
+///
+/// See the [module docs](crate::content_length) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestContentLengthLayer {
+    _priv: (),
+}
+
+impl RequestContentLengthLayer {
+    /// Create a new [`RequestContentLengthLayer`].
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for RequestContentLengthLayer {
+    type Service = RequestContentLength<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestContentLength::new(inner)
+    }
+}
+
+/// Middleware that checks request bodies match their `Content-Length`.
+///
+/// See the [module docs](crate::content_length) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestContentLength<S> {
+    inner: S,
+}
+
+impl<S> RequestContentLength<S> {
+    /// Create a new [`RequestContentLength`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `RequestContentLength` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> RequestContentLengthLayer {
+        RequestContentLengthLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestContentLength<S>
+where
+    S: Service<Request<ContentLengthBody<ReqBody>>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let expected = match parse_content_length(req.headers()) {
+            Ok(Some(_)) if req.headers().contains_key(header::TRANSFER_ENCODING) => {
+                return ResponseFuture::bad_request()
+            }
+            Ok(expected) => expected,
+            Err(()) => return ResponseFuture::bad_request(),
+        };
+
+        let mismatch = Arc::new(AtomicBool::new(false));
+        let req = req.map(|body| ContentLengthBody {
+            inner: body,
+            expected,
+            received: 0,
+            mismatch: Some(mismatch.clone()),
+        });
+
+        ResponseFuture {
+            kind: Kind::Future {
+                future: self.inner.call(req),
+                mismatch,
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`RequestContentLength`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    fn bad_request() -> Self {
+        Self {
+            kind: Kind::BadRequest,
+        }
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        BadRequest,
+        Future {
+            #[pin]
+            future: F,
+            mismatch: Arc<AtomicBool>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::BadRequest => Poll::Ready(Ok(bad_request())),
+            KindProj::Future { future, mismatch } => match ready!(future.poll(cx)) {
+                Ok(res) => Poll::Ready(Ok(res.map(|body| ResponseBody {
+                    kind: BodyKind::Body { body },
+                }))),
+                Err(_) if mismatch.load(Ordering::SeqCst) => Poll::Ready(Ok(bad_request())),
+                Err(err) => Poll::Ready(Err(err)),
+            },
+        }
+    }
+}
+
+fn bad_request<B>() -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut res = Response::new(ResponseBody {
+        kind: BodyKind::BadRequest { body: Empty::new() },
+    });
+    *res.status_mut() = StatusCode::BAD_REQUEST;
+    res
+}
+
+pin_project! {
+    /// Response body for [`RequestContentLength`].
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        BadRequest {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::BadRequest { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body } => body.poll_data(cx),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::BadRequest { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::BadRequest { body } => body.is_end_stream(),
+            BodyKind::Body { body } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::BadRequest { body } => body.size_hint(),
+            BodyKind::Body { body } => body.size_hint(),
+        }
+    }
+}
+
+/// Layer that applies the [`ResponseContentLength`] middleware which checks response bodies
+/// match their `Content-Length`.
+///
+/// See the [module docs](crate::content_length) for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResponseContentLengthLayer {
+    _priv: (),
+}
+
+impl ResponseContentLengthLayer {
+    /// Create a new [`ResponseContentLengthLayer`].
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for ResponseContentLengthLayer {
+    type Service = ResponseContentLength<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseContentLength::new(inner)
+    }
+}
+
+/// Middleware that checks response bodies match their `Content-Length`.
+///
+/// See the [module docs](crate::content_length) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseContentLength<S> {
+    inner: S,
+}
+
+impl<S> ResponseContentLength<S> {
+    /// Create a new [`ResponseContentLength`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ResponseContentLength` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> ResponseContentLengthLayer {
+        ResponseContentLengthLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ResponseContentLength<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ContentLengthBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseContentLengthFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let is_head = req.method() == Method::HEAD;
+        ResponseContentLengthFuture {
+            inner: self.inner.call(req),
+            is_head,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ResponseContentLength`].
+    pub struct ResponseContentLengthFuture<F> {
+        #[pin]
+        inner: F,
+        is_head: bool,
+    }
+}
+
+impl<F, B, E> Future for ResponseContentLengthFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ContentLengthBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx)?);
+
+        let status = res.status();
+        let expected = if *this.is_head
+            || status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            None
+        } else {
+            // hyper rejects responses with invalid `Content-Length` headers so there is no need
+            // to handle them here
+            parse_content_length(res.headers()).unwrap_or(None)
+        };
+
+        Poll::Ready(Ok(res.map(|body| ContentLengthBody {
+            inner: body,
+            expected,
+            received: 0,
+            mismatch: None,
+        })))
+    }
+}
+
+pin_project! {
+    /// Body that fails with a [`ContentLengthError`] if it doesn't match its `Content-Length`.
+    pub struct ContentLengthBody<B> {
+        #[pin]
+        inner: B,
+        expected: Option<u64>,
+        received: u64,
+        mismatch: Option<Arc<AtomicBool>>,
+    }
+}
+
+impl<B> ContentLengthBody<B> {
+    fn mismatch(
+        expected: u64,
+        received: u64,
+        mismatch: &Option<Arc<AtomicBool>>,
+    ) -> Poll<Option<Result<B::Data, BoxError>>>
+    where
+        B: Body,
+    {
+        if let Some(mismatch) = mismatch {
+            mismatch.store(true, Ordering::SeqCst);
+        }
+        Poll::Ready(Some(Err(ContentLengthError { expected, received }.into())))
+    }
+}
+
+impl<B> Body for ContentLengthBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let expected = match *this.expected {
+            Some(expected) => expected,
+            None => return this.inner.poll_data(cx).map_err(Into::into),
+        };
+
+        match ready!(this.inner.poll_data(cx)) {
+            Some(Ok(chunk)) => {
+                *this.received += chunk.remaining() as u64;
+                if *this.received > expected {
+                    Self::mismatch(expected, *this.received, this.mismatch)
+                } else {
+                    Poll::Ready(Some(Ok(chunk)))
+                }
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err.into()))),
+            None if *this.received != expected => {
+                Self::mismatch(expected, *this.received, this.mismatch)
+            }
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx).map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Error produced by [`ContentLengthBody`] when a body doesn't match its `Content-Length`.
+#[derive(Debug)]
+pub struct ContentLengthError {
+    expected: u64,
+    received: u64,
+}
+
+impl ContentLengthError {
+    /// The value of the `Content-Length` header.
+    pub fn expected(&self) -> u64 {
+        self.expected
+    }
+
+    /// The number of bytes received before the mismatch was detected.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+}
+
+impl fmt::Display for ContentLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.received > self.expected {
+            write!(
+                f,
+                "body is longer than its content-length of {} bytes",
+                self.expected
+            )
+        } else {
+            write!(
+                f,
+                "body ended after {} bytes, expected content-length of {} bytes",
+                self.received, self.expected
+            )
+        }
+    }
+}
+
+impl Error for ContentLengthError {}
+
+/// Parse the `Content-Length` headers.
+///
+/// Multiple headers, or comma separated values, are only accepted if they are all equal.
+fn parse_content_length(headers: &HeaderMap) -> Result<Option<u64>, ()> {
+    let mut content_length = None;
+    for value in headers.get_all(header::CONTENT_LENGTH) {
+        let value = value.to_str().map_err(|_| ())?;
+        for value in value.split(',') {
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(());
+            }
+            let value = value.parse::<u64>().map_err(|_| ())?;
+            match content_length {
+                Some(existing) if existing != value => return Err(()),
+                _ => content_length = Some(value),
+            }
+        }
+    }
+    Ok(content_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn request_matching_body() {
+        let svc = ServiceBuilder::new()
+            .layer(RequestContentLengthLayer::new())
+            .service_fn(echo);
+
+        let req = Request::builder()
+            .header(header::CONTENT_LENGTH, "6")
+            .body(Body::from("foobar"))
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "foobar");
+    }
+
+    #[tokio::test]
+    async fn request_mismatched_body() {
+        for (content_length, body) in [("3", "foobar"), ("10", "foobar")] {
+            let svc = ServiceBuilder::new()
+                .layer(RequestContentLengthLayer::new())
+                .service_fn(echo);
+
+            let req = Request::builder()
+                .header(header::CONTENT_LENGTH, content_length)
+                .body(Body::from(body))
+                .unwrap();
+            let res = svc.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn request_invalid_headers() {
+        let requests = vec![
+            Request::builder()
+                .header(header::CONTENT_LENGTH, "+6")
+                .body(Body::from("foobar"))
+                .unwrap(),
+            Request::builder()
+                .header(header::CONTENT_LENGTH, "6")
+                .header(header::CONTENT_LENGTH, "7")
+                .body(Body::from("foobar"))
+                .unwrap(),
+            Request::builder()
+                .header(header::CONTENT_LENGTH, "6")
+                .header(header::TRANSFER_ENCODING, "chunked")
+                .body(Body::from("foobar"))
+                .unwrap(),
+        ];
+
+        for req in requests {
+            let svc = ServiceBuilder::new()
+                .layer(RequestContentLengthLayer::new())
+                .service_fn(|_: Request<ContentLengthBody<Body>>| async {
+                    Ok::<_, BoxError>(Response::new(Body::empty()))
+                });
+            let res = svc.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn response_mismatched_body() {
+        let svc = ServiceBuilder::new()
+            .layer(ResponseContentLengthLayer::new())
+            .service_fn(|_: Request<Body>| async {
+                let res = Response::builder()
+                    .header(header::CONTENT_LENGTH, "10")
+                    .body(Body::from("foobar"))
+                    .unwrap();
+                Ok::<_, BoxError>(res)
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let err = hyper::body::to_bytes(res.into_body()).await.unwrap_err();
+        let err = err.downcast_ref::<ContentLengthError>().unwrap();
+        assert_eq!(err.expected(), 10);
+        assert_eq!(err.received(), 6);
+    }
+
+    #[tokio::test]
+    async fn response_to_head_not_checked() {
+        let svc = ServiceBuilder::new()
+            .layer(ResponseContentLengthLayer::new())
+            .service_fn(|_: Request<Body>| async {
+                let res = Response::builder()
+                    .header(header::CONTENT_LENGTH, "10")
+                    .body(Body::empty())
+                    .unwrap();
+                Ok::<_, BoxError>(res)
+            });
+
+        let req = Request::builder()
+            .method(Method::HEAD)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+    }
+
+    async fn echo(req: Request<ContentLengthBody<Body>>) -> Result<Response<Body>, BoxError> {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok(Response::new(Body::from(body)))
+    }
+}
@@ -0,0 +1,581 @@
+use crate::sync::lock_ignore_poison;
+use http::{header, HeaderMap, HeaderValue, Uri};
+use std::{
+    convert::TryFrom,
+    fmt,
+    io::{self, BufRead, Write},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The longest a cookie is kept for, as recommended by RFC 6265bis.
+const MAX_COOKIE_AGE: Duration = Duration::from_secs(400 * 24 * 60 * 60);
+
+/// A store of cookies, shared by all clones of the jar.
+///
+/// See the [module docs](crate::cookie_jar) for more details.
+#[derive(Clone, Default)]
+pub struct CookieJar {
+    store: Arc<Mutex<Store>>,
+}
+
+#[derive(Default)]
+struct Store {
+    cookies: Vec<Cookie>,
+    // incremented for each cookie to order cookies by creation time
+    next_created: u64,
+}
+
+impl Store {
+    /// Insert a cookie, replacing the cookie with the same name, domain and path.
+    fn insert(&mut self, mut cookie: Cookie) {
+        match self
+            .cookies
+            .iter_mut()
+            .find(|stored| stored.same_key(&cookie))
+        {
+            Some(stored) => {
+                // the replacement keeps the creation time of the old cookie
+                cookie.created = stored.created;
+                *stored = cookie;
+            }
+            None => {
+                cookie.created = self.next_created;
+                self.next_created += 1;
+                self.cookies.push(cookie);
+            }
+        }
+    }
+
+    fn remove_expired(&mut self, now: SystemTime) {
+        self.cookies.retain(|cookie| !cookie.is_expired(now));
+    }
+}
+
+/// The URL a request is sent to.
+#[derive(Debug)]
+pub(super) struct Target {
+    secure: bool,
+    // in lowercase
+    host: String,
+    path: String,
+}
+
+impl Target {
+    pub(super) fn new(uri: &Uri, headers: Option<&HeaderMap>) -> Option<Self> {
+        let host = match uri.host() {
+            Some(host) => host,
+            None => {
+                let host = headers?.get(header::HOST)?.to_str().ok()?;
+                // strip the port, keeping IPv6 addresses in brackets intact
+                match host.rfind(':') {
+                    Some(i) if !host[i..].contains(']') => &host[..i],
+                    _ => host,
+                }
+            }
+        };
+        Some(Self {
+            secure: uri.scheme_str() == Some("https"),
+            host: host.to_ascii_lowercase(),
+            path: uri.path().to_owned(),
+        })
+    }
+}
+
+impl CookieJar {
+    /// Create a new empty `CookieJar`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a cookie from a `Set-Cookie` header of a response to a request to `uri`.
+    ///
+    /// Returns `false` if the cookie was invalid or rejected, for example because its `Domain`
+    /// doesn't match the host of `uri`. Cookies that have expired remove the cookie they
+    /// replace.
+    pub fn add_set_cookie(&self, uri: &Uri, set_cookie: &str) -> bool {
+        match Target::new(uri, None) {
+            Some(target) => self.add(&target, set_cookie, SystemTime::now()),
+            None => false,
+        }
+    }
+
+    /// The value of the `Cookie` header for a request to `uri`, or `None` if no cookies match.
+    pub fn cookie_header(&self, uri: &Uri) -> Option<HeaderValue> {
+        self.header(&Target::new(uri, None)?, SystemTime::now())
+    }
+
+    /// The cookies in the jar that haven't expired.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let mut store = lock_ignore_poison(&self.store);
+        store.remove_expired(SystemTime::now());
+        store.cookies.clone()
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        lock_ignore_poison(&self.store).cookies.clear();
+    }
+
+    /// Write the cookies to `writer` in the Netscape `cookies.txt` format used by curl and
+    /// others.
+    ///
+    /// Session cookies are written with an expiry time of `0`.
+    pub fn save<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        writeln!(writer, "# Netscape HTTP Cookie File")?;
+        for cookie in self.cookies() {
+            let expires = cookie
+                .expires
+                .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |expires| expires.as_secs());
+            writeln!(
+                writer,
+                "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                cookie.domain,
+                if cookie.host_only { "FALSE" } else { "TRUE" },
+                cookie.path,
+                if cookie.secure { "TRUE" } else { "FALSE" },
+                expires,
+                cookie.name,
+                cookie.value,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Read cookies in the Netscape `cookies.txt` format from `reader` and add them to the
+    /// jar, as written by [`CookieJar::save`].
+    ///
+    /// Lines that aren't valid cookies are skipped.
+    pub fn load<R>(&self, reader: R) -> io::Result<()>
+    where
+        R: BufRead,
+    {
+        let now = SystemTime::now();
+        let mut store = lock_ignore_poison(&self.store);
+        for line in reader.lines() {
+            let line = line?;
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None if line.starts_with('#') => continue,
+                None => (line.as_str(), false),
+            };
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let (domain, include_subdomains, path, secure, expires, name, value) = match fields[..]
+            {
+                [domain, include_subdomains, path, secure, expires, name, value] => (
+                    domain,
+                    include_subdomains,
+                    path,
+                    secure,
+                    expires,
+                    name,
+                    value,
+                ),
+                _ => continue,
+            };
+            let expires = match expires.parse::<u64>() {
+                Ok(0) => None,
+                Ok(secs) => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+                Err(_) => continue,
+            };
+            let cookie = Cookie {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+                host_only: include_subdomains != "TRUE",
+                path: path.to_owned(),
+                secure: secure == "TRUE",
+                http_only,
+                expires,
+                created: 0,
+            };
+            if !cookie.is_expired(now) {
+                store.insert(cookie);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn add(&self, target: &Target, set_cookie: &str, now: SystemTime) -> bool {
+        let cookie = match Cookie::parse(target, set_cookie, now) {
+            Some(cookie) => cookie,
+            None => return false,
+        };
+        let mut store = lock_ignore_poison(&self.store);
+        if cookie.is_expired(now) {
+            store.cookies.retain(|stored| !stored.same_key(&cookie));
+        } else {
+            store.insert(cookie);
+        }
+        true
+    }
+
+    pub(super) fn header(&self, target: &Target, now: SystemTime) -> Option<HeaderValue> {
+        let mut store = lock_ignore_poison(&self.store);
+        store.remove_expired(now);
+        let mut cookies = store
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.matches(target))
+            .collect::<Vec<_>>();
+        if cookies.is_empty() {
+            return None;
+        }
+        // cookies with longer paths are listed first
+        cookies.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.created.cmp(&b.created))
+        });
+        let value = cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::try_from(value).ok()
+    }
+}
+
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("cookies", &lock_ignore_poison(&self.store).cookies)
+            .finish()
+    }
+}
+
+/// A cookie stored in a [`CookieJar`].
+#[derive(Clone)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    expires: Option<SystemTime>,
+    created: u64,
+}
+
+impl Cookie {
+    /// The name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The domain the cookie is sent to.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Whether the cookie is only sent to its domain and not to its subdomains, which is the
+    /// case for cookies without a `Domain` attribute.
+    pub fn host_only(&self) -> bool {
+        self.host_only
+    }
+
+    /// The path the cookie is sent to, including its subpaths.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether the cookie is only sent over HTTPS.
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Whether the cookie had the `HttpOnly` attribute.
+    pub fn http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// When the cookie expires, or `None` for session cookies.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Parse a `Set-Cookie` header value received from `target`.
+    fn parse(target: &Target, set_cookie: &str, now: SystemTime) -> Option<Self> {
+        let mut attributes = set_cookie.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() {
+            return None;
+        }
+
+        let host = &target.host;
+        let mut cookie = Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(&target.path).to_owned(),
+            secure: false,
+            http_only: false,
+            expires: None,
+            created: 0,
+        };
+        let mut max_age = None;
+        let mut expires = None;
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("domain") {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if !domain.is_empty() {
+                    if !domain_matches(host, &domain) || is_top_level(host, &domain) {
+                        return None;
+                    }
+                    cookie.host_only = false;
+                    cookie.domain = domain;
+                }
+            } else if key.eq_ignore_ascii_case("path") {
+                if value.starts_with('/') {
+                    cookie.path = value.to_owned();
+                }
+            } else if key.eq_ignore_ascii_case("max-age") {
+                if let Ok(secs) = value.parse::<i64>() {
+                    max_age = Some(secs);
+                }
+            } else if key.eq_ignore_ascii_case("expires") {
+                // some servers separate the parts of the date with dashes
+                if let Ok(date) = httpdate::parse_http_date(&value.replace('-', " ")) {
+                    expires = Some(date);
+                }
+            } else if key.eq_ignore_ascii_case("secure") {
+                cookie.secure = true;
+            } else if key.eq_ignore_ascii_case("httponly") {
+                cookie.http_only = true;
+            }
+        }
+
+        // secure cookies can only be set over secure connections
+        if cookie.secure && !target.secure {
+            return None;
+        }
+        cookie.expires = match max_age {
+            Some(secs) if secs <= 0 => Some(UNIX_EPOCH),
+            Some(secs) => {
+                let max_age = Duration::from_secs(secs as u64).min(MAX_COOKIE_AGE);
+                Some(now.checked_add(max_age)?)
+            }
+            None => expires,
+        };
+        Some(cookie)
+    }
+
+    fn same_key(&self, other: &Self) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    fn matches(&self, target: &Target) -> bool {
+        let domain_matches = if self.host_only {
+            target.host == self.domain
+        } else {
+            domain_matches(&target.host, &self.domain)
+        };
+        domain_matches && path_matches(&target.path, &self.path) && (!self.secure || target.secure)
+    }
+}
+
+impl fmt::Debug for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cookie")
+            .field("name", &self.name)
+            .field("domain", &self.domain)
+            .field("host_only", &self.host_only)
+            .field("path", &self.path)
+            .field("secure", &self.secure)
+            .field("http_only", &self.http_only)
+            .field("expires", &self.expires)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The directory of the request path, which is the default path of cookies.
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_ok();
+    !is_ip
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+/// Cookies can't be set for top level domains such as `com`.
+///
+/// This doesn't know about other public suffixes such as `co.uk`.
+fn is_top_level(host: &str, domain: &str) -> bool {
+    domain != host && !domain.contains('.')
+}
+
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    let request_path = if request_path.is_empty() {
+        "/"
+    } else {
+        request_path
+    };
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path.as_bytes()[cookie_path.len()] == b'/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(uri: &'static str) -> Uri {
+        Uri::from_static(uri)
+    }
+
+    fn header(jar: &CookieJar, uri: &'static str) -> Option<String> {
+        jar.cookie_header(&self::uri(uri))
+            .map(|value| value.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn matches_domains() {
+        let jar = CookieJar::new();
+        assert!(jar.add_set_cookie(&uri("http://example.com/"), "host=1"));
+        assert!(jar.add_set_cookie(&uri("http://a.example.com/"), "sub=1; Domain=example.com"));
+        assert!(!jar.add_set_cookie(&uri("http://example.com/"), "other=1; Domain=example.org"));
+        assert!(!jar.add_set_cookie(&uri("http://example.com/"), "tld=1; Domain=com"));
+
+        assert_eq!(
+            header(&jar, "http://example.com/").unwrap(),
+            "host=1; sub=1"
+        );
+        assert_eq!(header(&jar, "http://b.example.com/").unwrap(), "sub=1");
+        assert_eq!(header(&jar, "http://notexample.com/"), None);
+    }
+
+    #[test]
+    fn matches_paths() {
+        let jar = CookieJar::new();
+        jar.add_set_cookie(&uri("http://example.com/docs/page"), "default=1");
+        jar.add_set_cookie(&uri("http://example.com/"), "root=1; Path=/");
+        jar.add_set_cookie(&uri("http://example.com/"), "deep=1; Path=/docs/api");
+
+        assert_eq!(header(&jar, "http://example.com/").unwrap(), "root=1");
+        assert_eq!(
+            header(&jar, "http://example.com/docs/api/x").unwrap(),
+            "deep=1; default=1; root=1"
+        );
+        assert_eq!(header(&jar, "http://example.com/docsx").unwrap(), "root=1");
+    }
+
+    #[test]
+    fn secure_cookies() {
+        let jar = CookieJar::new();
+        assert!(!jar.add_set_cookie(&uri("http://example.com/"), "a=1; Secure"));
+        assert!(jar.add_set_cookie(&uri("https://example.com/"), "a=1; Secure; HttpOnly"));
+        assert_eq!(header(&jar, "http://example.com/"), None);
+        assert_eq!(header(&jar, "https://example.com/").unwrap(), "a=1");
+    }
+
+    #[test]
+    fn expiry() {
+        let jar = CookieJar::new();
+        let uri = uri("http://example.com/");
+        jar.add_set_cookie(&uri, "a=1; Max-Age=60");
+        jar.add_set_cookie(&uri, "b=1; Expires=Wed, 21-Oct-2015 07:28:00 GMT");
+        jar.add_set_cookie(
+            &uri,
+            "c=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=60",
+        );
+        assert_eq!(jar.cookie_header(&uri).unwrap(), "a=1; c=1");
+
+        // replacing a cookie with an expired one removes it
+        jar.add_set_cookie(&uri, "a=2; Max-Age=0");
+        assert_eq!(jar.cookie_header(&uri).unwrap(), "c=1");
+        let expires = jar.cookies()[0].expires().unwrap();
+        assert!(expires > SystemTime::now());
+    }
+
+    #[test]
+    fn caps_max_age() {
+        let jar = CookieJar::new();
+        let uri = uri("http://example.com/");
+        assert!(jar.add_set_cookie(&uri, "a=1; Max-Age=9223372036854775807"));
+        let expires = jar.cookies()[0].expires().unwrap();
+        assert!(expires <= SystemTime::now() + MAX_COOKIE_AGE);
+        assert!(expires > SystemTime::now() + MAX_COOKIE_AGE - Duration::from_secs(60));
+    }
+
+    #[test]
+    fn replaces_cookies() {
+        let jar = CookieJar::new();
+        let uri = uri("http://example.com/");
+        jar.add_set_cookie(&uri, "a=1");
+        jar.add_set_cookie(&uri, "b=1");
+        jar.add_set_cookie(&uri, "a=2");
+        assert_eq!(jar.cookie_header(&uri).unwrap(), "a=2; b=1");
+        assert_eq!(jar.cookies().len(), 2);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let jar = CookieJar::new();
+        jar.add_set_cookie(&uri("https://example.com/"), "a=1; Secure; HttpOnly");
+        jar.add_set_cookie(
+            &uri("http://example.com/docs"),
+            "b=2; Domain=example.com; Path=/docs; Max-Age=3600",
+        );
+
+        let mut saved = Vec::new();
+        jar.save(&mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+        let mut lines = saved.lines();
+        assert_eq!(lines.next(), Some("# Netscape HTTP Cookie File"));
+        assert_eq!(
+            lines.next(),
+            Some("#HttpOnly_example.com\tFALSE\t/\tTRUE\t0\ta\t1")
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("example.com\tTRUE\t/docs\tFALSE\t"));
+
+        let loaded = CookieJar::new();
+        loaded.load(saved.as_bytes()).unwrap();
+        assert_eq!(
+            header(&loaded, "https://example.com/docs").unwrap(),
+            "b=2; a=1"
+        );
+        assert_eq!(
+            header(&loaded, "http://www.example.com/docs").unwrap(),
+            "b=2"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Middleware that keeps cookies between requests made by HTTP clients.
+//!
+//! [`ManageCookies`] adds the cookies in a [`CookieJar`] that match a request to its `Cookie`
+//! header, after any cookies the request already has. The cookies of `Set-Cookie` headers in
+//! responses are stored in the jar.
+//!
+//! Cookies are matched as described in [RFC 6265]:
+//!
+//! - Cookies without a `Domain` attribute are only sent to the host that set them. Cookies
+//!   with one are also sent to its subdomains, and are rejected unless the host is the domain
+//!   or one of its subdomains. Top level domains such as `com` are rejected, but the public
+//!   suffix list isn't consulted, so a cookie could still be set for `co.uk`.
+//! - Cookies are sent to their `Path` and its subpaths, which defaults to the directory of
+//!   the request path.
+//! - Cookies expire according to their `Max-Age` or `Expires` attributes, and `Max-Age` is
+//!   capped at 400 days.
+//! - Cookies with the `Secure` attribute are only set and sent over HTTPS.
+//!
+//! The host of a request is taken from its URI, or its `Host` header if the URI is relative.
+//!
+//! A [`CookieJar`] can be cloned to share it between clients, or to inspect it while it is in
+//! use. Its cookies can be saved and loaded in the Netscape `cookies.txt` format with
+//! [`CookieJar::save`] and [`CookieJar::load`].
+//!
+//! When used with [`FollowRedirect`], this middleware should be added inside it so that
+//! cookies are stored and sent for every redirect.
+//!
+//! [RFC 6265]: https://www.rfc-editor.org/rfc/rfc6265
+//! [`FollowRedirect`]: crate::follow_redirect::FollowRedirect
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::cookie_jar::{CookieJar, ManageCookiesLayer};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // a server that counts the requests of each client
+//!     let count = req
+//!         .headers()
+//!         .get(header::COOKIE)
+//!         .and_then(|cookie| cookie.to_str().ok()?.strip_prefix("count=")?.parse().ok())
+//!         .unwrap_or(0)
+//!         + 1;
+//!     let res = Response::builder()
+//!         .header(header::SET_COOKIE, format!("count={}; Path=/", count))
+//!         .body(Body::empty())
+//!         .unwrap();
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Infallible> {
+//! let jar = CookieJar::new();
+//! let mut client = ServiceBuilder::new()
+//!     .layer(ManageCookiesLayer::new(jar.clone()))
+//!     .service_fn(handle);
+//!
+//! for _ in 0..3 {
+//!     let request = Request::get("https://example.com/").body(Body::empty()).unwrap();
+//!     client.ready().await?.call(request).await?;
+//! }
+//!
+//! let uri = "https://example.com/".parse().unwrap();
+//! assert_eq!(jar.cookie_header(&uri).unwrap(), "count=3");
+//! # Ok(())
+//! # }
+//! ```
+
+mod jar;
+
+pub use self::jar::{Cookie, CookieJar};
+
+use self::jar::Target;
+use futures_util::ready;
+use http::{header, HeaderValue, Request, Response};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies [`ManageCookies`] which keeps cookies between requests.
+///
+/// See the [module docs](crate::cookie_jar) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct ManageCookiesLayer {
+    jar: CookieJar,
+}
+
+impl ManageCookiesLayer {
+    /// Create a new `ManageCookiesLayer` keeping cookies in `jar`.
+    pub fn new(jar: CookieJar) -> Self {
+        Self { jar }
+    }
+}
+
+impl<S> Layer<S> for ManageCookiesLayer {
+    type Service = ManageCookies<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ManageCookies {
+            inner,
+            jar: self.jar.clone(),
+        }
+    }
+}
+
+/// Middleware that keeps cookies between requests.
+///
+/// See the [module docs](crate::cookie_jar) for more details.
+#[derive(Debug, Clone)]
+pub struct ManageCookies<S> {
+    inner: S,
+    jar: CookieJar,
+}
+
+impl<S> ManageCookies<S> {
+    /// Create a new `ManageCookies` keeping cookies in `jar`.
+    pub fn new(inner: S, jar: CookieJar) -> Self {
+        Self { inner, jar }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ManageCookies` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(jar: CookieJar) -> ManageCookiesLayer {
+        ManageCookiesLayer::new(jar)
+    }
+
+    /// Returns the jar cookies are kept in.
+    pub fn jar(&self) -> &CookieJar {
+        &self.jar
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ManageCookies<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let target = Target::new(req.uri(), Some(req.headers()));
+        let cookies = target
+            .as_ref()
+            .and_then(|target| self.jar.header(target, SystemTime::now()));
+
+        if let Some(cookies) = cookies {
+            let headers = req.headers_mut();
+            let cookies = match headers.get(header::COOKIE) {
+                Some(existing) => {
+                    let mut combined = existing.as_bytes().to_vec();
+                    combined.extend_from_slice(b"; ");
+                    combined.extend_from_slice(cookies.as_bytes());
+                    HeaderValue::from_bytes(&combined).unwrap_or(cookies)
+                }
+                None => cookies,
+            };
+            headers.insert(header::COOKIE, cookies);
+        }
+
+        ResponseFuture {
+            future: self.inner.call(req),
+            jar: self.jar.clone(),
+            target,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ManageCookies`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        future: F,
+        jar: CookieJar,
+        target: Option<Target>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.future.poll(cx))?;
+        if let Some(target) = this.target.as_ref() {
+            let now = SystemTime::now();
+            for set_cookie in res.headers().get_all(header::SET_COOKIE) {
+                if let Ok(set_cookie) = set_cookie.to_str() {
+                    this.jar.add(target, set_cookie, now);
+                }
+            }
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    #[tokio::test]
+    async fn sends_and_stores_cookies() {
+        let jar = CookieJar::new();
+        jar.add_set_cookie(&"http://example.com/".parse().unwrap(), "a=1");
+
+        let svc = ManageCookies::new(
+            service_fn(|req: Request<Body>| async move {
+                assert_eq!(req.headers()[header::COOKIE], "own=1; a=1");
+                let res = Response::builder()
+                    .header(header::SET_COOKIE, "b=2; Path=/")
+                    .header(header::SET_COOKIE, "a=; Max-Age=0")
+                    .header(header::SET_COOKIE, "c=3; Domain=other.com")
+                    .body(Body::empty())
+                    .unwrap();
+                Ok::<_, BoxError>(res)
+            }),
+            jar.clone(),
+        );
+        // relative URIs get their host from the host header
+        let req = Request::get("/")
+            .header(header::HOST, "Example.com:8080")
+            .header(header::COOKIE, "own=1")
+            .body(Body::empty())
+            .unwrap();
+        svc.oneshot(req).await.unwrap();
+
+        let uri = "http://example.com/".parse().unwrap();
+        assert_eq!(jar.cookie_header(&uri).unwrap(), "b=2");
+    }
+}
@@ -0,0 +1,609 @@
+//! Middleware that parses request cookies and sets response cookies.
+//!
+//! [`Cookies`] parses the `Cookie` headers of requests into a [`RequestCookies`] extension,
+//! with percent-encoded values decoded. It also adds a [`ResponseCookies`] extension to requests,
+//! which handlers and other middleware use to queue [`SetCookie`]s. Once the response is ready,
+//! the queued cookies are written to it as `Set-Cookie` headers.
+//!
+//! Values of [`SetCookie`]s are percent-encoded where necessary, so they can contain any text.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::cookies::{CookiesLayer, RequestCookies, ResponseCookies, SetCookie};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let cookies = req.extensions().get::<RequestCookies>().unwrap();
+//!     let visits = cookies
+//!         .get("visits")
+//!         .and_then(|visits| visits.parse().ok())
+//!         .unwrap_or(0)
+//!         + 1;
+//!
+//!     let response_cookies = req.extensions().get::<ResponseCookies>().unwrap();
+//!     response_cookies.add(
+//!         SetCookie::new("visits", visits.to_string()).max_age(Duration::from_secs(3600)),
+//!     );
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Infallible> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(CookiesLayer::new())
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/")
+//!     .header(header::COOKIE, "visits=2; theme=dark")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(
+//!     response.headers()[header::SET_COOKIE],
+//!     "visits=3; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax",
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sync::lock_ignore_poison;
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use pin_project_lite::pin_project;
+use std::{
+    borrow::Cow,
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The characters that aren't allowed in cookie values, and `%` which starts escapes.
+const VALUE_ENCODE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b',')
+    .add(b';')
+    .add(b'\\')
+    .add(b'%');
+
+/// The cookies of a request, added to its extensions by [`Cookies`].
+///
+/// Values are percent-decoded. A request may have several cookies with the same name, for
+/// example set for different paths, in which case [`RequestCookies::get`] returns the first.
+#[derive(Clone, Default)]
+pub struct RequestCookies {
+    cookies: Vec<(String, String)>,
+}
+
+impl RequestCookies {
+    /// Parse the `Cookie` headers in `headers`.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let cookies = parse(headers)
+            .map(|(name, value)| (name.to_owned(), value.into_owned()))
+            .collect();
+        Self { cookies }
+    }
+
+    /// Returns the value of the first cookie called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter()
+            .find(|(cookie, _)| *cookie == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the values of all cookies called `name`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.iter()
+            .filter(move |(cookie, _)| *cookie == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns `true` if there is a cookie called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Returns an iterator over the names and values of the cookies, in the order they were
+    /// sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of cookies.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Returns `true` if there are no cookies.
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+/// Parse the `Cookie` headers in `headers` into the names and percent-decoded values of the
+/// cookies.
+pub(crate) fn parse(headers: &HeaderMap) -> impl Iterator<Item = (&str, Cow<'_, str>)> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            if name.is_empty() {
+                return None;
+            }
+            let value = value.trim();
+            // values may be quoted
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            Some((name, percent_decode_str(value).decode_utf8_lossy()))
+        })
+}
+
+impl fmt::Debug for RequestCookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // cookie values are often credentials, so only show the names
+        f.debug_list()
+            .entries(self.cookies.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SameSite {
+    /// Only send the cookie with same-site requests.
+    Strict,
+    /// Send the cookie with same-site requests and top-level navigations.
+    Lax,
+    /// Send the cookie with all requests. Requires the cookie to be `Secure`.
+    None,
+}
+
+impl SameSite {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A cookie to set with a `Set-Cookie` header.
+///
+/// Cookies are created with `Path=/`, `HttpOnly` and `SameSite=Lax` by default.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    /// Create a new `SetCookie`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't a valid cookie name.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: cookie_name(name.into()),
+            value: value.into(),
+            path: Some("/".to_owned()),
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: true,
+            same_site: Some(SameSite::Lax),
+        }
+    }
+
+    /// Create a `SetCookie` that removes the cookie called `name` from the client.
+    ///
+    /// The path and domain have to match those of the cookie that is removed.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "")
+            .max_age(Duration::from_secs(0))
+            .expires(SystemTime::UNIX_EPOCH)
+    }
+
+    /// Returns the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the cookie, before it is percent-encoded.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Set the `Path` attribute, or don't send it if `None`.
+    ///
+    /// Defaults to `/`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` contains control characters or `;`.
+    pub fn path(mut self, path: Option<&str>) -> Self {
+        self.path = path.map(|path| attribute_value(path).to_owned());
+        self
+    }
+
+    /// Set the `Domain` attribute.
+    ///
+    /// By default the cookie is only sent to the host that set it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `domain` contains control characters or `;`.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(attribute_value(domain).to_owned());
+        self
+    }
+
+    /// Set the `Max-Age` attribute.
+    ///
+    /// By default the cookie is removed when the browser session ends.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Set the `Expires` attribute, for clients that don't support `Max-Age`.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Set whether the cookie has the `Secure` attribute.
+    ///
+    /// Defaults to `false`.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set whether the cookie has the `HttpOnly` attribute.
+    ///
+    /// Defaults to `true`.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute, or don't send it if `None`.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn same_site(mut self, same_site: Option<SameSite>) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Serialize the cookie as the value of a `Set-Cookie` header.
+    pub fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string())
+            .expect("cookie names, encoded values and attributes are valid header values")
+    }
+}
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}={}",
+            self.name,
+            utf8_percent_encode(&self.value, VALUE_ENCODE)
+        )?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetCookie")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("domain", &self.domain)
+            .field("max_age", &self.max_age)
+            .field("expires", &self.expires)
+            .field("secure", &self.secure)
+            .field("http_only", &self.http_only)
+            .field("same_site", &self.same_site)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Check that `name` is a valid cookie name.
+pub(crate) fn cookie_name<T>(name: T) -> T
+where
+    T: AsRef<str>,
+{
+    let valid = !name.as_ref().is_empty()
+        && name
+            .as_ref()
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b));
+    assert!(valid, "invalid cookie name: {:?}", name.as_ref());
+    name
+}
+
+/// Check that `value` can be used as the value of a cookie attribute.
+pub(crate) fn attribute_value(value: &str) -> &str {
+    assert!(
+        !value.bytes().any(|b| b.is_ascii_control() || b == b';'),
+        "invalid cookie attribute: {:?}",
+        value
+    );
+    value
+}
+
+/// The cookies to set on a response, added to the extensions of requests by [`Cookies`].
+///
+/// Clones share the same queue, so cookies can be added from anywhere that has access to the
+/// request.
+#[derive(Clone, Default)]
+pub struct ResponseCookies {
+    queue: Arc<Mutex<Vec<SetCookie>>>,
+}
+
+impl ResponseCookies {
+    /// Queue a cookie to be set on the response.
+    ///
+    /// A cookie queued earlier with the same name, path and domain is replaced.
+    pub fn add(&self, cookie: SetCookie) {
+        let mut queue = lock_ignore_poison(&self.queue);
+        queue.retain(|queued| {
+            queued.name != cookie.name
+                || queued.path != cookie.path
+                || queued.domain != cookie.domain
+        });
+        queue.push(cookie);
+    }
+
+    /// Queue the removal of the cookie called `name` with the default path.
+    ///
+    /// Use [`SetCookie::removal`] for cookies with another path or a domain.
+    pub fn remove(&self, name: &str) {
+        self.add(SetCookie::removal(name));
+    }
+
+    /// Returns the cookies that are queued.
+    pub fn queued(&self) -> Vec<SetCookie> {
+        lock_ignore_poison(&self.queue).clone()
+    }
+
+    fn take(&self) -> Vec<SetCookie> {
+        mem::take(&mut *lock_ignore_poison(&self.queue))
+    }
+}
+
+impl fmt::Debug for ResponseCookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(lock_ignore_poison(&self.queue).iter())
+            .finish()
+    }
+}
+
+/// Layer that applies [`Cookies`] which parses request cookies and sets response cookies.
+///
+/// See the [module docs](crate::cookies) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CookiesLayer {
+    _priv: (),
+}
+
+impl CookiesLayer {
+    /// Create a new `CookiesLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for CookiesLayer {
+    type Service = Cookies<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Cookies { inner }
+    }
+}
+
+/// Middleware that parses request cookies and sets response cookies.
+///
+/// See the [module docs](crate::cookies) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct Cookies<S> {
+    inner: S,
+}
+
+impl<S> Cookies<S> {
+    /// Create a new `Cookies`.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Cookies` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> CookiesLayer {
+        CookiesLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Cookies<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let cookies = RequestCookies::from_headers(req.headers());
+        let response_cookies = ResponseCookies::default();
+        req.extensions_mut().insert(cookies);
+        req.extensions_mut().insert(response_cookies.clone());
+        ResponseFuture {
+            future: self.inner.call(req),
+            cookies: response_cookies,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`Cookies`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        future: F,
+        cookies: ResponseCookies,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.future.poll(cx))?;
+        for cookie in this.cookies.take() {
+            res.headers_mut()
+                .append(header::SET_COOKIE, cookie.to_header_value());
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    #[test]
+    fn parses_cookies() {
+        let req = Request::get("/")
+            .header(header::COOKIE, "a=1; b=\"hello%20world\"; =x; c")
+            .header(header::COOKIE, "a=2;d=%FF")
+            .body(())
+            .unwrap();
+        let cookies = RequestCookies::from_headers(req.headers());
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies.get("a"), Some("1"));
+        assert_eq!(cookies.get_all("a").collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(cookies.get("b"), Some("hello world"));
+        assert_eq!(cookies.get("d"), Some("\u{FFFD}"));
+        assert!(!cookies.contains("c"));
+        assert_eq!(format!("{:?}", cookies), r#"["a", "b", "a", "d"]"#);
+    }
+
+    #[test]
+    fn serializes_set_cookie() {
+        let cookie = SetCookie::new("session", "a b;c%")
+            .path(Some("/app"))
+            .domain("example.com")
+            .max_age(Duration::from_secs(60))
+            .expires(SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777))
+            .secure(true)
+            .http_only(false)
+            .same_site(Some(SameSite::Strict));
+        assert_eq!(
+            cookie.to_header_value(),
+            "session=a%20b%3Bc%25; Path=/app; Domain=example.com; Max-Age=60; \
+             Expires=Sun, 06 Nov 1994 08:49:37 GMT; Secure; SameSite=Strict"
+        );
+
+        let cookie = SetCookie::new("a", "é").path(None).same_site(None);
+        assert_eq!(cookie.to_string(), "a=%C3%A9; HttpOnly");
+
+        assert_eq!(
+            SetCookie::removal("a").to_string(),
+            "a=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT; HttpOnly; SameSite=Lax"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cookie name")]
+    fn rejects_invalid_names() {
+        SetCookie::new("a;b", "");
+    }
+
+    #[tokio::test]
+    async fn sets_queued_cookies() {
+        let svc = Cookies::new(service_fn(|req: Request<Body>| async move {
+            let cookies = req.extensions().get::<ResponseCookies>().unwrap();
+            cookies.add(SetCookie::new("a", "1"));
+            cookies.add(SetCookie::new("b", "1"));
+            cookies.add(SetCookie::new("a", "2"));
+            cookies.remove("old");
+            Ok::<_, BoxError>(Response::new(Body::empty()))
+        }));
+        let res = svc
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let set_cookies = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().split(';').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(set_cookies, ["b=1", "a=2", "old="]);
+    }
+}
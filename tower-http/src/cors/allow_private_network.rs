@@ -71,7 +71,7 @@ impl AllowPrivateNetwork {
             AllowPrivateNetworkInner::Predicate(c) => c(origin?, parts),
         };
 
-        allow_private_network.then(|| (ALLOW_PRIVATE_NETWORK, TRUE))
+        allow_private_network.then_some((ALLOW_PRIVATE_NETWORK, TRUE))
     }
 }
 
@@ -94,21 +94,16 @@ impl fmt::Debug for AllowPrivateNetwork {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 enum AllowPrivateNetworkInner {
     Yes,
+    #[default]
     No,
     Predicate(
         Arc<dyn for<'a> Fn(&'a HeaderValue, &'a RequestParts) -> bool + Send + Sync + 'static>,
     ),
 }
 
-impl Default for AllowPrivateNetworkInner {
-    fn default() -> Self {
-        Self::No
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::AllowPrivateNetwork;
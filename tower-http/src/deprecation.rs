@@ -0,0 +1,490 @@
+//! Middleware that announces the deprecation and retirement of endpoints.
+//!
+//! [`Deprecation`] adds lifecycle headers to the responses of requests that match a
+//! [`DeprecationRule`]:
+//!
+//! - `Deprecation` ([RFC 9745]) with the time the endpoint was, or will be, deprecated, such as
+//!   `@1688169599`.
+//! - `Sunset` ([RFC 8594]) with the time the endpoint will stop working, such as
+//!   `Sun, 30 Jun 2024 23:59:59 GMT`.
+//! - `Link` headers pointing to documentation about the deprecation, the sunset or the
+//!   successor of the endpoint.
+//!
+//! Rules match requests by path prefix and optionally by the value of a header carrying the
+//! API version. The first matching rule is applied. Headers the inner service set itself are
+//! left alone, except for `Link` headers which are added to.
+//!
+//! Rules can also reject requests made after the sunset time with `410 Gone`, see
+//! [`DeprecationRule::reject_after_sunset`].
+//!
+//! [RFC 9745]: https://www.rfc-editor.org/rfc/rfc9745
+//! [RFC 8594]: https://www.rfc-editor.org/rfc/rfc8594
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::{
+//!     convert::Infallible,
+//!     time::{Duration, UNIX_EPOCH},
+//! };
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::deprecation::{DeprecationLayer, DeprecationRule};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Infallible> {
+//! let deprecated_at = UNIX_EPOCH + Duration::from_secs(1_688_169_599);
+//! let sunset_at = UNIX_EPOCH + Duration::from_secs(1_719_791_999);
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         DeprecationLayer::new().rule(
+//!             DeprecationRule::path_prefix("/v1/")
+//!                 .deprecated_at(deprecated_at)
+//!                 .sunset_at(sunset_at)
+//!                 .successor_version("https://example.com/v2/"),
+//!         ),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/v1/users").body(Body::empty()).unwrap();
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.headers()["deprecation"], "@1688169599");
+//! assert_eq!(response.headers()["sunset"], "Sun, 30 Jun 2024 23:59:59 GMT");
+//! assert_eq!(
+//!     response.headers()["link"],
+//!     "<https://example.com/v2/>; rel=\"successor-version\"",
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// A rule describing the lifecycle of the endpoints it matches.
+///
+/// See the [module docs](crate::deprecation) for an example.
+#[derive(Debug, Clone)]
+pub struct DeprecationRule {
+    path_prefix: String,
+    version: Option<(HeaderName, HeaderValue)>,
+    deprecation: Option<HeaderValue>,
+    sunset: Option<HeaderValue>,
+    sunset_at: Option<SystemTime>,
+    links: Vec<HeaderValue>,
+    reject_after_sunset: bool,
+}
+
+impl DeprecationRule {
+    /// Create a new `DeprecationRule` matching requests whose path starts with `prefix`.
+    pub fn path_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            path_prefix: prefix.into(),
+            version: None,
+            deprecation: None,
+            sunset: None,
+            sunset_at: None,
+            links: Vec::new(),
+            reject_after_sunset: false,
+        }
+    }
+
+    /// Create a new `DeprecationRule` matching all requests.
+    pub fn any() -> Self {
+        Self::path_prefix("")
+    }
+
+    /// Only match requests whose `header` has the value `version`, such as an `Api-Version`
+    /// header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `version` isn't a valid header value.
+    pub fn version(mut self, header: HeaderName, version: &str) -> Self {
+        let version = HeaderValue::try_from(version).expect("invalid version");
+        self.version = Some((header, version));
+        self
+    }
+
+    /// Set the time the endpoints were, or will be, deprecated, which is sent in the
+    /// `Deprecation` header.
+    pub fn deprecated_at(mut self, time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        self.deprecation = Some(header_value(format!("@{}", secs)));
+        self
+    }
+
+    /// Set the time the endpoints will stop working, which is sent in the `Sunset` header.
+    pub fn sunset_at(mut self, time: SystemTime) -> Self {
+        self.sunset = Some(header_value(httpdate::fmt_http_date(time)));
+        self.sunset_at = Some(time);
+        self
+    }
+
+    /// Add a `Link` to documentation about the deprecation, with `rel="deprecation"`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` contains characters that aren't allowed in headers or `>`.
+    pub fn deprecation_link(self, url: &str) -> Self {
+        self.link(url, "deprecation")
+    }
+
+    /// Add a `Link` to documentation about the sunset, with `rel="sunset"`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` contains characters that aren't allowed in headers or `>`.
+    pub fn sunset_link(self, url: &str) -> Self {
+        self.link(url, "sunset")
+    }
+
+    /// Add a `Link` to the version replacing the endpoints, with `rel="successor-version"`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` contains characters that aren't allowed in headers or `>`.
+    pub fn successor_version(self, url: &str) -> Self {
+        self.link(url, "successor-version")
+    }
+
+    /// Respond with `410 Gone` to requests made after the sunset time, instead of calling the
+    /// inner service.
+    ///
+    /// Defaults to `false`.
+    pub fn reject_after_sunset(mut self, reject: bool) -> Self {
+        self.reject_after_sunset = reject;
+        self
+    }
+
+    fn link(mut self, url: &str, rel: &str) -> Self {
+        assert!(!url.contains('>'), "invalid link: {:?}", url);
+        let link = format!("<{}>; rel=\"{}\"", url, rel);
+        self.links
+            .push(HeaderValue::try_from(link).expect("invalid link"));
+        self
+    }
+
+    fn matches<B>(&self, req: &Request<B>) -> bool {
+        req.uri().path().starts_with(&self.path_prefix)
+            && self.version.as_ref().map_or(true, |(header, version)| {
+                req.headers().get(header) == Some(version)
+            })
+    }
+
+    fn is_sunset(&self, now: SystemTime) -> bool {
+        self.sunset_at.map_or(false, |sunset_at| sunset_at <= now)
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        if let Some(deprecation) = &self.deprecation {
+            if !headers.contains_key(DEPRECATION) {
+                headers.insert(DEPRECATION, deprecation.clone());
+            }
+        }
+        if let Some(sunset) = &self.sunset {
+            if !headers.contains_key(SUNSET) {
+                headers.insert(SUNSET, sunset.clone());
+            }
+        }
+        for link in &self.links {
+            headers.append(header::LINK, link.clone());
+        }
+    }
+}
+
+fn header_value(value: String) -> HeaderValue {
+    HeaderValue::try_from(value).expect("dates are valid header values")
+}
+
+/// Layer that applies [`Deprecation`] which adds lifecycle headers to the responses of
+/// deprecated endpoints.
+///
+/// See the [module docs](crate::deprecation) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct DeprecationLayer {
+    rules: Arc<Vec<DeprecationRule>>,
+}
+
+impl DeprecationLayer {
+    /// Create a new `DeprecationLayer` without any rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule. Rules are checked in the order they were added.
+    pub fn rule(mut self, rule: DeprecationRule) -> Self {
+        Arc::make_mut(&mut self.rules).push(rule);
+        self
+    }
+}
+
+impl<S> Layer<S> for DeprecationLayer {
+    type Service = Deprecation<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Deprecation {
+            inner,
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+/// Middleware that adds lifecycle headers to the responses of deprecated endpoints.
+///
+/// See the [module docs](crate::deprecation) for an example.
+#[derive(Debug, Clone)]
+pub struct Deprecation<S> {
+    inner: S,
+    rules: Arc<Vec<DeprecationRule>>,
+}
+
+impl<S> Deprecation<S> {
+    /// Create a new `Deprecation` without any rules.
+    pub fn new(inner: S) -> Self {
+        DeprecationLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Deprecation` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> DeprecationLayer {
+        DeprecationLayer::new()
+    }
+
+    /// Add a rule.
+    ///
+    /// See [`DeprecationLayer::rule`] for more details.
+    pub fn rule(mut self, rule: DeprecationRule) -> Self {
+        Arc::make_mut(&mut self.rules).push(rule);
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Deprecation<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let rule = self
+            .rules
+            .iter()
+            .position(|rule| rule.matches(&req))
+            .map(|index| (self.rules.clone(), index));
+
+        if let Some((rules, index)) = &rule {
+            let rule = &rules[*index];
+            if rule.reject_after_sunset && rule.is_sunset(SystemTime::now()) {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::GONE;
+                rule.apply(res.headers_mut());
+                return ResponseFuture {
+                    kind: Kind::Gone {
+                        response: Some(res),
+                    },
+                };
+            }
+        }
+
+        ResponseFuture {
+            kind: Kind::Future {
+                future: self.inner.call(req),
+                rule,
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`Deprecation`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        Future {
+            #[pin]
+            future: F,
+            // the rules and the index of the rule that matched
+            rule: Option<(Arc<Vec<DeprecationRule>>, usize)>,
+        },
+        Gone {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future, rule } => {
+                let mut res = futures_util::ready!(future.poll(cx))?;
+                if let Some((rules, index)) = rule.take() {
+                    rules[index].apply(res.headers_mut());
+                }
+                Poll::Ready(Ok(res))
+            }
+            KindProj::Gone { response } => {
+                let response = response.take().expect("future polled after completion");
+                Poll::Ready(Ok(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::time::Duration;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(_req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    async fn call(layer: &DeprecationLayer, req: Request<Body>) -> Response<Body> {
+        layer.layer(service_fn(handle)).oneshot(req).await.unwrap()
+    }
+
+    fn time(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[tokio::test]
+    async fn matches_paths_and_versions() {
+        let layer = DeprecationLayer::new()
+            .rule(
+                DeprecationRule::path_prefix("/items")
+                    .version(HeaderName::from_static("api-version"), "1")
+                    .deprecated_at(time(1)),
+            )
+            .rule(DeprecationRule::path_prefix("/old").deprecated_at(time(2)));
+
+        let req = Request::get("/items")
+            .header("api-version", "1")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(call(&layer, req).await.headers()["deprecation"], "@1");
+
+        let req = Request::get("/items")
+            .header("api-version", "2")
+            .body(Body::empty())
+            .unwrap();
+        assert!(call(&layer, req)
+            .await
+            .headers()
+            .get("deprecation")
+            .is_none());
+
+        let req = Request::get("/old/x").body(Body::empty()).unwrap();
+        assert_eq!(call(&layer, req).await.headers()["deprecation"], "@2");
+
+        let req = Request::get("/new").body(Body::empty()).unwrap();
+        assert!(call(&layer, req).await.headers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn adds_links() {
+        let layer = DeprecationLayer::new().rule(
+            DeprecationRule::any()
+                .deprecation_link("https://example.com/deprecation")
+                .sunset_link("https://example.com/sunset"),
+        );
+        let res = call(&layer, Request::get("/").body(Body::empty()).unwrap()).await;
+        let links = res
+            .headers()
+            .get_all(header::LINK)
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            [
+                "<https://example.com/deprecation>; rel=\"deprecation\"",
+                "<https://example.com/sunset>; rel=\"sunset\"",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_headers_of_inner_service() {
+        let svc = DeprecationLayer::new()
+            .rule(DeprecationRule::any().deprecated_at(time(1)))
+            .layer(service_fn(|_req: Request<Body>| async {
+                let res = Response::builder()
+                    .header("deprecation", "@5")
+                    .body(Body::empty())
+                    .unwrap();
+                Ok::<_, BoxError>(res)
+            }));
+        let res = svc
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["deprecation"], "@5");
+    }
+
+    #[tokio::test]
+    async fn rejects_after_sunset() {
+        let past = DeprecationLayer::new().rule(
+            DeprecationRule::any()
+                .sunset_at(time(1_719_791_999))
+                .reject_after_sunset(true),
+        );
+        let res = call(&past, Request::get("/").body(Body::empty()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::GONE);
+        assert_eq!(res.headers()["sunset"], "Sun, 30 Jun 2024 23:59:59 GMT");
+
+        let future = DeprecationLayer::new().rule(
+            DeprecationRule::any()
+                .sunset_at(SystemTime::now() + Duration::from_secs(3600))
+                .reject_after_sunset(true),
+        );
+        let res = call(&future, Request::get("/").body(Body::empty()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().contains_key("sunset"));
+    }
+}
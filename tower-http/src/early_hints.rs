@@ -0,0 +1,434 @@
+//! Middleware that lets handlers send `103 Early Hints` responses.
+//!
+//! While a handler is still working on a response, it can send [RFC 8297] early hints with `Link`
+//! headers, so browsers start preloading stylesheets and scripts or connecting to other origins
+//! before the final response arrives.
+//!
+//! [`SendEarlyHints`] inserts an [`EarlyHints`] handle into the extensions of every request,
+//! which handlers use to send the hints. Informational responses can only be sent by the
+//! transport, so servers supporting them insert an [`InformationalSender`] into the request
+//! extensions that the hints are forwarded to. Without one, or for HTTP/1.0 requests, which can't
+//! receive informational responses, sending fails with [`EarlyHintsError::Unsupported`].
+//!
+//! Since intermediaries may drop informational responses, the `Link` headers of the hints are
+//! also added to the final response by default, whether the hints could be sent or not.
+//!
+//! [RFC 8297]: https://www.rfc-editor.org/rfc/rfc8297
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, HeaderValue, Request, Response};
+//! use hyper::Body;
+//! use std::{
+//!     convert::Infallible,
+//!     sync::{Arc, Mutex},
+//! };
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::early_hints::{EarlyHints, InformationalSender, SendEarlyHintsLayer};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let hints = req.extensions().get::<EarlyHints>().unwrap();
+//!     let link = HeaderValue::from_static("</style.css>; rel=preload; as=style");
+//!     hints.send_link(link).ok();
+//!
+//!     // Render the page...
+//!     Ok(Response::new(Body::from("<!doctype html>")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(SendEarlyHintsLayer::new())
+//!     .service_fn(handle);
+//!
+//! // A transport that supports informational responses, here recording them.
+//! let sent = Arc::new(Mutex::new(Vec::new()));
+//! let sender = InformationalSender::new({
+//!     let sent = sent.clone();
+//!     move |res| {
+//!         sent.lock().unwrap().push(res);
+//!         true
+//!     }
+//! });
+//!
+//! let mut request = Request::new(Body::empty());
+//! request.extensions_mut().insert(sender);
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! let hints = sent.lock().unwrap();
+//! assert_eq!(hints[0].status().as_u16(), 103);
+//! assert_eq!(hints[0].headers()[header::LINK], "</style.css>; rel=preload; as=style");
+//! assert_eq!(response.headers()[header::LINK], "</style.css>; rel=preload; as=style");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sync::lock_ignore_poison;
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode, Version};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Sends informational responses on the connection a request was received on.
+///
+/// Servers that support informational responses insert it into the request extensions. See the
+/// [module docs](crate::early_hints) for an example.
+#[derive(Clone)]
+pub struct InformationalSender {
+    send: Arc<dyn Fn(Response<()>) -> bool + Send + Sync>,
+}
+
+impl InformationalSender {
+    /// Create a new `InformationalSender` from a function sending an informational response,
+    /// which returns `false` if the response couldn't be sent.
+    pub fn new<F>(send: F) -> Self
+    where
+        F: Fn(Response<()>) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            send: Arc::new(send),
+        }
+    }
+
+    fn send(&self, res: Response<()>) -> bool {
+        (self.send)(res)
+    }
+}
+
+impl fmt::Debug for InformationalSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InformationalSender").finish()
+    }
+}
+
+/// Error returned by [`EarlyHints`] if the hints couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EarlyHintsError {
+    /// The transport doesn't support informational responses.
+    Unsupported,
+    /// The final response has already been produced.
+    AlreadyResponded,
+    /// The transport couldn't send the hints, for example because the connection was closed.
+    Closed,
+}
+
+impl fmt::Display for EarlyHintsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => f.write_str("informational responses are not supported"),
+            Self::AlreadyResponded => f.write_str("the final response has already been produced"),
+            Self::Closed => f.write_str("the early hints could not be sent"),
+        }
+    }
+}
+
+impl std::error::Error for EarlyHintsError {}
+
+/// Handle for sending `103 Early Hints` responses, inserted into the request extensions by
+/// [`SendEarlyHints`].
+#[derive(Clone)]
+pub struct EarlyHints {
+    state: Arc<Mutex<State>>,
+    sender: Option<InformationalSender>,
+}
+
+#[derive(Default)]
+struct State {
+    responded: bool,
+    links: Vec<HeaderValue>,
+}
+
+impl EarlyHints {
+    fn new(sender: Option<InformationalSender>) -> Self {
+        Self {
+            state: Arc::default(),
+            sender,
+        }
+    }
+
+    /// Send a `103 Early Hints` response with the given headers.
+    ///
+    /// Its `Link` headers are added to the final response, if enabled with
+    /// [`SendEarlyHintsLayer::copy_links`], even if the hints couldn't be sent.
+    pub fn send(&self, headers: HeaderMap) -> Result<(), EarlyHintsError> {
+        {
+            let mut state = lock_ignore_poison(&self.state);
+            if state.responded {
+                return Err(EarlyHintsError::AlreadyResponded);
+            }
+            state
+                .links
+                .extend(headers.get_all(header::LINK).iter().cloned());
+        }
+
+        let sender = self.sender.as_ref().ok_or(EarlyHintsError::Unsupported)?;
+        let mut res = Response::new(());
+        *res.status_mut() =
+            StatusCode::from_u16(103).expect("103 Early Hints is a valid status code");
+        *res.headers_mut() = headers;
+        if sender.send(res) {
+            Ok(())
+        } else {
+            Err(EarlyHintsError::Closed)
+        }
+    }
+
+    /// Send a `103 Early Hints` response with a single `Link` header.
+    ///
+    /// See [`EarlyHints::send`] for more details.
+    pub fn send_link(&self, link: HeaderValue) -> Result<(), EarlyHintsError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LINK, link);
+        self.send(headers)
+    }
+
+    /// Returns `true` if the transport supports sending the hints.
+    pub fn is_supported(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Mark the final response as produced, returning the `Link` headers that were sent.
+    fn finish(&self) -> Vec<HeaderValue> {
+        let mut state = lock_ignore_poison(&self.state);
+        state.responded = true;
+        std::mem::take(&mut state.links)
+    }
+}
+
+impl fmt::Debug for EarlyHints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EarlyHints")
+            .field("supported", &self.is_supported())
+            .finish()
+    }
+}
+
+/// Layer that applies [`SendEarlyHints`] which lets handlers send `103 Early Hints` responses.
+///
+/// See the [module docs](crate::early_hints) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct SendEarlyHintsLayer {
+    copy_links: bool,
+}
+
+impl SendEarlyHintsLayer {
+    /// Create a new `SendEarlyHintsLayer`.
+    pub fn new() -> Self {
+        Self { copy_links: true }
+    }
+
+    /// Set whether the `Link` headers of the hints are added to the final response.
+    ///
+    /// Links the final response already contains aren't added again. Defaults to `true`.
+    pub fn copy_links(mut self, copy_links: bool) -> Self {
+        self.copy_links = copy_links;
+        self
+    }
+}
+
+impl Default for SendEarlyHintsLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for SendEarlyHintsLayer {
+    type Service = SendEarlyHints<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SendEarlyHints {
+            inner,
+            copy_links: self.copy_links,
+        }
+    }
+}
+
+/// Middleware that lets handlers send `103 Early Hints` responses.
+///
+/// See the [module docs](crate::early_hints) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct SendEarlyHints<S> {
+    inner: S,
+    copy_links: bool,
+}
+
+impl<S> SendEarlyHints<S> {
+    /// Create a new `SendEarlyHints`.
+    pub fn new(inner: S) -> Self {
+        SendEarlyHintsLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SendEarlyHints` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> SendEarlyHintsLayer {
+        SendEarlyHintsLayer::new()
+    }
+
+    /// Set whether the `Link` headers of the hints are added to the final response.
+    ///
+    /// See [`SendEarlyHintsLayer::copy_links`] for more details.
+    pub fn copy_links(mut self, copy_links: bool) -> Self {
+        self.copy_links = copy_links;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SendEarlyHints<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // HTTP/1.0 clients don't expect informational responses
+        let sender = match req.version() {
+            Version::HTTP_09 | Version::HTTP_10 => None,
+            _ => req.extensions().get::<InformationalSender>().cloned(),
+        };
+        let hints = EarlyHints::new(sender);
+        req.extensions_mut().insert(hints.clone());
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            hints,
+            copy_links: self.copy_links,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`SendEarlyHints`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        hints: EarlyHints,
+        copy_links: bool,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+
+        let links = this.hints.finish();
+        if *this.copy_links {
+            let headers = res.headers_mut();
+            for link in links {
+                if !headers
+                    .get_all(header::LINK)
+                    .iter()
+                    .any(|value| *value == link)
+                {
+                    headers.append(header::LINK, link);
+                }
+            }
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("hints", &self.hints)
+            .field("copy_links", &self.copy_links)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    const LINK: &str = "</style.css>; rel=preload; as=style";
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let hints = req.extensions().get::<EarlyHints>().unwrap().clone();
+        let result = hints.send_link(HeaderValue::from_static(LINK));
+        let mut res = Response::builder()
+            .header("x-result", format!("{:?}", result))
+            .body(Body::empty())
+            .unwrap();
+        res.extensions_mut().insert(hints);
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn sends_hints() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sender = InformationalSender::new({
+            let sent = sent.clone();
+            move |res| {
+                sent.lock().unwrap().push(res);
+                true
+            }
+        });
+        let svc = SendEarlyHintsLayer::new().layer(service_fn(handle));
+
+        let mut req = Request::new(Body::empty());
+        req.extensions_mut().insert(sender.clone());
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-result"], "Ok(())");
+        assert_eq!(res.headers()[header::LINK], LINK);
+        assert_eq!(sent.lock().unwrap()[0].headers()[header::LINK], LINK);
+
+        // hints can't be sent after the final response
+        let hints = res.extensions().get::<EarlyHints>().unwrap();
+        assert_eq!(
+            hints.send_link(HeaderValue::from_static(LINK)),
+            Err(EarlyHintsError::AlreadyResponded)
+        );
+
+        // HTTP/1.0 clients don't get the hints
+        let mut req = Request::builder()
+            .version(Version::HTTP_10)
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(sender);
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-result"], "Err(Unsupported)");
+        assert_eq!(sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn copies_links_without_transport_support() {
+        let svc = SendEarlyHintsLayer::new().layer(service_fn(handle));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()["x-result"], "Err(Unsupported)");
+        assert_eq!(res.headers()[header::LINK], LINK);
+
+        let svc = SendEarlyHintsLayer::new()
+            .copy_links(false)
+            .layer(service_fn(handle));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert!(!res.headers().contains_key(header::LINK));
+    }
+}
@@ -0,0 +1,20 @@
+//! Helpers for entity tags.
+
+use http::HeaderValue;
+
+/// Whether the `If-None-Match` header `values` match the entity tag `etag`, using the weak
+/// comparison.
+///
+/// `*` matches any representation, even one without an entity tag.
+pub(crate) fn if_none_match<'a, I>(values: I, etag: Option<&str>) -> bool
+where
+    I: IntoIterator<Item = &'a HeaderValue>,
+{
+    let etag = etag.map(|etag| etag.trim_start_matches("W/"));
+    values
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || Some(tag.trim_start_matches("W/")) == etag)
+}
@@ -0,0 +1,176 @@
+use bytes::{Buf, Bytes};
+use futures_core::ready;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response body for [`Etag`].
+    ///
+    /// [`Etag`]: super::Etag
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        inner: ResponseBodyInner<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyProj]
+    enum ResponseBodyInner<B>
+    where
+        B: Body,
+    {
+        Body {
+            #[pin]
+            body: B,
+        },
+        Buffered {
+            data: Option<Bytes>,
+            // the rest of the body, or its trailers if `data_done` is set
+            rest: Option<Pin<Box<B>>>,
+            data_done: bool,
+            error: Option<B::Error>,
+        },
+    }
+}
+
+impl<B> ResponseBody<B>
+where
+    B: Body,
+{
+    pub(super) fn new(body: B) -> Self {
+        Self {
+            inner: ResponseBodyInner::Body { body },
+        }
+    }
+
+    pub(super) fn empty() -> Self {
+        Self::buffered(Bytes::new(), None, true, None)
+    }
+
+    pub(super) fn buffered(
+        data: Bytes,
+        rest: Option<Pin<Box<B>>>,
+        data_done: bool,
+        error: Option<B::Error>,
+    ) -> Self {
+        Self {
+            inner: ResponseBodyInner::Buffered {
+                data: Some(data).filter(|data| !data.is_empty()),
+                rest,
+                data_done,
+                error,
+            },
+        }
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().inner.project() {
+            BodyProj::Body { body } => match ready!(body.poll_data(cx)) {
+                Some(Ok(mut data)) => {
+                    let data = data.copy_to_bytes(data.remaining());
+                    Poll::Ready(Some(Ok(data)))
+                }
+                Some(Err(err)) => Poll::Ready(Some(Err(err))),
+                None => Poll::Ready(None),
+            },
+            BodyProj::Buffered {
+                data,
+                rest,
+                data_done,
+                error,
+            } => {
+                if let Some(data) = data.take() {
+                    return Poll::Ready(Some(Ok(data)));
+                }
+                if let Some(err) = error.take() {
+                    return Poll::Ready(Some(Err(err)));
+                }
+                match rest {
+                    Some(rest) if !*data_done => match ready!(rest.as_mut().poll_data(cx)) {
+                        Some(Ok(mut data)) => {
+                            let data = data.copy_to_bytes(data.remaining());
+                            Poll::Ready(Some(Ok(data)))
+                        }
+                        Some(Err(err)) => Poll::Ready(Some(Err(err))),
+                        None => {
+                            *data_done = true;
+                            Poll::Ready(None)
+                        }
+                    },
+                    _ => Poll::Ready(None),
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().inner.project() {
+            BodyProj::Body { body } => body.poll_trailers(cx),
+            BodyProj::Buffered { rest, .. } => match rest {
+                Some(rest) => rest.as_mut().poll_trailers(cx),
+                None => Poll::Ready(Ok(None)),
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            ResponseBodyInner::Body { body } => body.is_end_stream(),
+            ResponseBodyInner::Buffered {
+                data, rest, error, ..
+            } => {
+                data.is_none()
+                    && error.is_none()
+                    && rest.as_ref().map_or(true, |rest| rest.is_end_stream())
+            }
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            ResponseBodyInner::Body { body } => body.size_hint(),
+            ResponseBodyInner::Buffered {
+                data,
+                rest,
+                data_done,
+                ..
+            } => {
+                let buffered = data.as_ref().map_or(0, |data| data.len() as u64);
+                match rest {
+                    Some(rest) if !*data_done => {
+                        let rest = rest.size_hint();
+                        let mut hint = SizeHint::new();
+                        hint.set_lower(rest.lower() + buffered);
+                        if let Some(upper) = rest.upper() {
+                            hint.set_upper(upper + buffered);
+                        }
+                        hint
+                    }
+                    _ => SizeHint::with_exact(buffered),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::ResponseBody;
+use crate::{
+    entity_tag,
+    fnv::{self, fnv1a_update},
+};
+use bytes::{Buf, BytesMut};
+use futures_core::ready;
+use http::{header, response::Parts, HeaderValue, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response future for [`Etag`].
+    ///
+    /// [`Etag`]: super::Etag
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        future: F,
+        // the `If-None-Match` headers, `None` if no `ETag` is generated for the request
+        if_none_match: Option<Vec<HeaderValue>>,
+        max_body_size: usize,
+        buffering: Option<Buffering<B>>,
+    }
+}
+
+struct Buffering<B> {
+    parts: Parts,
+    body: Pin<Box<B>>,
+    buf: BytesMut,
+    hash: u64,
+}
+
+impl<F, B> ResponseFuture<F, B> {
+    pub(super) fn new(
+        future: F,
+        if_none_match: Option<Vec<HeaderValue>>,
+        max_body_size: usize,
+    ) -> Self {
+        Self {
+            future,
+            if_none_match,
+            max_body_size,
+            buffering: None,
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if this.buffering.is_none() {
+            let res = ready!(this.future.poll(cx))?;
+            if this.if_none_match.is_none() || !should_buffer(&res, *this.max_body_size) {
+                return Poll::Ready(Ok(res.map(ResponseBody::new)));
+            }
+            let (parts, body) = res.into_parts();
+            *this.buffering = Some(Buffering {
+                parts,
+                body: Box::pin(body),
+                buf: BytesMut::new(),
+                hash: fnv::OFFSET_BASIS,
+            });
+        }
+
+        loop {
+            let buffering = this.buffering.as_mut().unwrap();
+            match ready!(buffering.body.as_mut().poll_data(cx)) {
+                Some(Ok(mut data)) => {
+                    if buffering.buf.len() + data.remaining() > *this.max_body_size {
+                        let Buffering {
+                            parts,
+                            body,
+                            mut buf,
+                            ..
+                        } = this.buffering.take().unwrap();
+                        buf.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                        let body = ResponseBody::buffered(buf.freeze(), Some(body), false, None);
+                        return Poll::Ready(Ok(Response::from_parts(parts, body)));
+                    }
+                    while data.has_remaining() {
+                        let chunk = data.chunk();
+                        buffering.hash = fnv1a_update(buffering.hash, chunk);
+                        buffering.buf.extend_from_slice(chunk);
+                        let len = chunk.len();
+                        data.advance(len);
+                    }
+                }
+                Some(Err(err)) => {
+                    // send what was read so far and then the error, without an `ETag`
+                    let Buffering { parts, buf, .. } = this.buffering.take().unwrap();
+                    let body = ResponseBody::buffered(buf.freeze(), None, true, Some(err));
+                    return Poll::Ready(Ok(Response::from_parts(parts, body)));
+                }
+                None => break,
+            }
+        }
+
+        let Buffering {
+            mut parts,
+            body,
+            buf,
+            hash,
+        } = this.buffering.take().unwrap();
+        let etag = format!("\"{:016x}\"", hash);
+        parts.headers.insert(
+            header::ETAG,
+            HeaderValue::from_str(&etag).expect("entity tags are valid header values"),
+        );
+
+        let if_none_match = this.if_none_match.take().unwrap_or_default();
+        if entity_tag::if_none_match(&if_none_match, Some(&etag)) {
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(header::CONTENT_LENGTH);
+            return Poll::Ready(Ok(Response::from_parts(parts, ResponseBody::empty())));
+        }
+
+        let body = ResponseBody::buffered(buf.freeze(), Some(body), true, None);
+        Poll::Ready(Ok(Response::from_parts(parts, body)))
+    }
+}
+
+/// Whether to buffer the body of the response to generate an `ETag` for it.
+fn should_buffer<B>(res: &Response<B>, max_body_size: usize) -> bool
+where
+    B: Body,
+{
+    if res.status() != StatusCode::OK || res.headers().contains_key(header::ETAG) {
+        return false;
+    }
+
+    // streams of events are never complete
+    let is_event_stream = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("text/event-stream"));
+    if is_event_stream {
+        return false;
+    }
+
+    res.body().size_hint().lower() <= max_body_size as u64
+}
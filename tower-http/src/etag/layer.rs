@@ -0,0 +1,46 @@
+use super::{Etag, DEFAULT_MAX_BODY_SIZE};
+use tower_layer::Layer;
+
+/// Layer that applies the [`Etag`] middleware, which adds `ETag` headers to responses and
+/// answers matching conditional requests with `304 Not Modified`.
+///
+/// See the [module docs](crate::etag) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct EtagLayer {
+    max_body_size: usize,
+}
+
+impl EtagLayer {
+    /// Create a new [`EtagLayer`].
+    pub fn new() -> Self {
+        Self {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the size of the largest response body an `ETag` is generated for.
+    ///
+    /// Larger bodies are sent without an `ETag` once this many bytes have been buffered.
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl Default for EtagLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for EtagLayer {
+    type Service = Etag<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Etag {
+            inner,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Middleware that generates `ETag` headers for responses and handles conditional `GET`
+//! requests.
+//!
+//! [`Etag`] buffers the bodies of `200 OK` responses to `GET` requests, adds a strong `ETag`
+//! header with a hash of the body and responds with `304 Not Modified` and an empty body if the
+//! request has a matching `If-None-Match` header. This gives dynamic responses the same
+//! conditional request support [`ServeDir`] has for files, saving bandwidth when clients already
+//! have the current version of a response.
+//!
+//! Responses that already have an `ETag` header, event streams and responses with bodies larger
+//! than [`EtagLayer::max_body_size`] are passed through unchanged. Since the body is buffered
+//! before the response is sent, the middleware shouldn't be used for responses that are
+//! streamed slowly.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::etag::EtagLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("report")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(EtagLayer::new())
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/report").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! let etag = response.headers()[header::ETAG].clone();
+//!
+//! let request = Request::get("/report")
+//!     .header(header::IF_NONE_MATCH, etag)
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ServeDir`]: crate::services::ServeDir
+
+mod body;
+mod future;
+mod layer;
+mod service;
+
+pub use self::{body::ResponseBody, future::ResponseFuture, layer::EtagLayer, service::Etag};
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, HeaderMap, Method, Request, Response, StatusCode};
+    use http_body::Body as _;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceBuilder, ServiceExt};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let res = match req.uri().path() {
+            "/etag" => Response::builder()
+                .header(header::ETAG, "\"custom\"")
+                .body(Body::from("hello"))
+                .unwrap(),
+            "/large" => Response::new(Body::from("larger than sixteen bytes")),
+            "/events" => Response::builder()
+                .header(header::CONTENT_TYPE, "text/event-stream")
+                .body(Body::from("data: hello\n\n"))
+                .unwrap(),
+            "/stream" => {
+                let (mut tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    tx.send_data("0123456789".into()).await.unwrap();
+                    tx.send_data("0123456789".into()).await.unwrap();
+                });
+                Response::new(body)
+            }
+            "/trailers" => {
+                let (mut tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    tx.send_data("hello".into()).await.unwrap();
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("x-trailer", "1".parse().unwrap());
+                    tx.send_trailers(trailers).await.unwrap();
+                });
+                Response::new(body)
+            }
+            _ => Response::new(Body::from("hello")),
+        };
+        Ok(res)
+    }
+
+    async fn send(req: Request<Body>) -> (Response<()>, Bytes) {
+        let svc = ServiceBuilder::new()
+            .layer(EtagLayer::new().max_body_size(16))
+            .service_fn(handle);
+        let res = svc.oneshot(req).await.unwrap();
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        (Response::from_parts(parts, ()), body)
+    }
+
+    fn get(uri: &str, if_none_match: Option<&str>) -> Request<Body> {
+        let mut req = Request::get(uri);
+        if let Some(if_none_match) = if_none_match {
+            req = req.header(header::IF_NONE_MATCH, if_none_match);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn generates_etags() {
+        let (res, body) = send(get("/", None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body, "hello");
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+        for if_none_match in [
+            etag.clone(),
+            format!("W/{}", etag),
+            format!("\"a\", {}", etag),
+        ] {
+            let (res, body) = send(get("/", Some(&if_none_match))).await;
+            assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(res.headers()[header::ETAG], etag);
+            assert!(body.is_empty());
+        }
+
+        let (res, body) = send(get("/", Some("\"other\""))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body, "hello");
+
+        // the same body has the same tag
+        let (res, _) = send(get("/other", None)).await;
+        assert_eq!(res.headers()[header::ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn skips_responses() {
+        let (res, _) = send(get("/etag", Some("\"custom\""))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ETAG], "\"custom\"");
+
+        for path in ["/large", "/events"] {
+            let (res, body) = send(get(path, Some("*"))).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get(header::ETAG).is_none());
+            assert!(!body.is_empty());
+        }
+
+        // bodies without a known size are sent once they turn out to be too large
+        let (res, body) = send(get("/stream", Some("*"))).await;
+        assert!(res.headers().get(header::ETAG).is_none());
+        assert_eq!(body, "01234567890123456789");
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .body(Body::empty())
+            .unwrap();
+        let (res, _) = send(req).await;
+        assert!(res.headers().get(header::ETAG).is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_trailers() {
+        let svc = Etag::new(service_fn(handle));
+        let res = svc.oneshot(get("/trailers", None)).await.unwrap();
+        assert!(res.headers().contains_key(header::ETAG));
+
+        let mut body = res.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["x-trailer"], "1");
+    }
+}
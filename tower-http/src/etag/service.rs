@@ -0,0 +1,74 @@
+use super::{EtagLayer, ResponseBody, ResponseFuture, DEFAULT_MAX_BODY_SIZE};
+use http::{header, Method, Request, Response};
+use http_body::Body;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Middleware that adds `ETag` headers to responses and answers matching conditional requests
+/// with `304 Not Modified`.
+///
+/// See the [module docs](crate::etag) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct Etag<S> {
+    pub(crate) inner: S,
+    pub(crate) max_body_size: usize,
+}
+
+impl<S> Etag<S> {
+    /// Create a new [`Etag`].
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with an `Etag` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> EtagLayer {
+        EtagLayer::new()
+    }
+
+    /// Set the size of the largest response body an `ETag` is generated for.
+    ///
+    /// See [`EtagLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Etag<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // `HEAD` responses don't have a body to generate the tag from
+        let if_none_match = if req.method() == Method::GET {
+            Some(
+                req.headers()
+                    .get_all(header::IF_NONE_MATCH)
+                    .iter()
+                    .cloned()
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        ResponseFuture::new(self.inner.call(req), if_none_match, self.max_body_size)
+    }
+}
@@ -0,0 +1,432 @@
+//! Middleware that decides whether to accept requests with `Expect: 100-continue` before their
+//! body is read.
+//!
+//! A client sending a request with `Expect: 100-continue` waits for a `100 Continue` response
+//! before uploading the body, so a server can reject requests that are doomed anyway without the
+//! client wasting time and bandwidth on the body. Hyper sends `100 Continue` when the request body
+//! is first polled, so rejecting a request before reading its body is enough to skip the upload.
+//!
+//! [`ExpectContinue`] runs a [`ContinuePolicy`] on requests with `Expect: 100-continue`. If the
+//! policy accepts the request it is passed to the inner service, otherwise the middleware responds
+//! immediately with the status code returned by the policy, for example `401 Unauthorized` or
+//! `413 Payload Too Large`. Requests with any other expectation get a
+//! `417 Expectation Failed` response, and requests without an `Expect` header are always passed
+//! to the inner service.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::expect_continue::ExpectContinueLayer;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(ExpectContinueLayer::new(|req: &Request<Body>| {
+//!         if req.headers().contains_key(header::AUTHORIZATION) {
+//!             Ok(())
+//!         } else {
+//!             Err(StatusCode::UNAUTHORIZED)
+//!         }
+//!     }))
+//!     .service_fn(handle);
+//!
+//! let request = Request::put("/upload")
+//!     .header(header::EXPECT, "100-continue")
+//!     .header(header::CONTENT_LENGTH, "1000000")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Limiting the size of uploads:
+//!
+//! ```
+//! use tower_http::expect_continue::ExpectContinueLayer;
+//!
+//! // Reject requests announcing a body larger than 1 MiB with `413 Payload Too Large`
+//! let layer = ExpectContinueLayer::max_content_length(1024 * 1024);
+//! ```
+
+use futures_util::ready;
+use http::{header, HeaderMap, Request, Response, StatusCode};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Decides whether a request with `Expect: 100-continue` may upload its body.
+///
+/// This is implemented for closures with the signature
+/// `FnMut(&Request<B>) -> Result<(), StatusCode>`.
+pub trait ContinuePolicy<B> {
+    /// Check the request before its body is read.
+    ///
+    /// Returning an error responds immediately with the status code, without reading the body.
+    fn check(&mut self, request: &Request<B>) -> Result<(), StatusCode>;
+}
+
+impl<B, F> ContinuePolicy<B> for F
+where
+    F: FnMut(&Request<B>) -> Result<(), StatusCode>,
+{
+    fn check(&mut self, request: &Request<B>) -> Result<(), StatusCode> {
+        self(request)
+    }
+}
+
+/// [`ContinuePolicy`] that rejects requests whose `Content-Length` exceeds a limit with
+/// `413 Payload Too Large`.
+///
+/// Requests without a `Content-Length` are accepted, so the body should still be limited with
+/// something like [`RequestBodyLimit`](crate::limit::RequestBodyLimit).
+#[derive(Debug, Clone, Copy)]
+pub struct MaxContentLength {
+    limit: u64,
+}
+
+impl MaxContentLength {
+    /// Create a new `MaxContentLength` accepting bodies of at most `limit` bytes.
+    pub fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+}
+
+impl<B> ContinuePolicy<B> for MaxContentLength {
+    fn check(&mut self, request: &Request<B>) -> Result<(), StatusCode> {
+        let content_length = request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+        match content_length {
+            Some(len) if len > self.limit => Err(StatusCode::PAYLOAD_TOO_LARGE),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Layer that applies [`ExpectContinue`] which decides whether to accept requests with
+/// `Expect: 100-continue` before their body is read.
+///
+/// See the [module docs](crate::expect_continue) for an example.
+#[derive(Debug, Clone)]
+pub struct ExpectContinueLayer<P> {
+    policy: P,
+}
+
+impl<P> ExpectContinueLayer<P> {
+    /// Create a new `ExpectContinueLayer` that checks requests with `policy`.
+    pub fn new(policy: P) -> Self {
+        Self { policy }
+    }
+}
+
+impl ExpectContinueLayer<MaxContentLength> {
+    /// Create a new `ExpectContinueLayer` that rejects requests with a `Content-Length` larger
+    /// than `limit`.
+    ///
+    /// See [`MaxContentLength`] for more details.
+    pub fn max_content_length(limit: u64) -> Self {
+        Self::new(MaxContentLength::new(limit))
+    }
+}
+
+impl<S, P> Layer<S> for ExpectContinueLayer<P>
+where
+    P: Clone,
+{
+    type Service = ExpectContinue<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExpectContinue {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// Middleware that decides whether to accept requests with `Expect: 100-continue` before their
+/// body is read.
+///
+/// See the [module docs](crate::expect_continue) for an example.
+#[derive(Debug, Clone)]
+pub struct ExpectContinue<S, P> {
+    inner: S,
+    policy: P,
+}
+
+impl<S, P> ExpectContinue<S, P> {
+    /// Create a new `ExpectContinue` that checks requests with `policy`.
+    pub fn new(inner: S, policy: P) -> Self {
+        Self { inner, policy }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `ExpectContinue` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(policy: P) -> ExpectContinueLayer<P> {
+        ExpectContinueLayer::new(policy)
+    }
+}
+
+impl<S> ExpectContinue<S, MaxContentLength> {
+    /// Create a new `ExpectContinue` that rejects requests with a `Content-Length` larger than
+    /// `limit`.
+    ///
+    /// See [`MaxContentLength`] for more details.
+    pub fn max_content_length(inner: S, limit: u64) -> Self {
+        Self::new(inner, MaxContentLength::new(limit))
+    }
+}
+
+impl<S, P, ReqBody, ResBody> Service<Request<ReqBody>> for ExpectContinue<S, P>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    P: ContinuePolicy<ReqBody>,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let checked = match req.headers().get(header::EXPECT) {
+            None => Ok(()),
+            Some(value) if value.as_bytes().eq_ignore_ascii_case(b"100-continue") => {
+                self.policy.check(&req)
+            }
+            Some(_) => Err(StatusCode::EXPECTATION_FAILED),
+        };
+
+        let kind = match checked {
+            Ok(()) => Kind::Future {
+                future: self.inner.call(req),
+            },
+            // the body is dropped without being read, so `100 Continue` is never sent
+            Err(status) => Kind::Reject { status },
+        };
+        ResponseFuture { kind }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ExpectContinue`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        Reject {
+            status: StatusCode,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future } => {
+                let res = ready!(future.poll(cx))?;
+                Poll::Ready(Ok(res.map(|body| ResponseBody {
+                    kind: BodyKind::Body { body },
+                })))
+            }
+            KindProj::Reject { status } => {
+                let mut res = Response::new(ResponseBody {
+                    kind: BodyKind::Empty,
+                });
+                *res.status_mut() = *status;
+                Poll::Ready(Ok(res))
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Response body for [`ExpectContinue`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B> {
+        Body {
+            #[pin]
+            body: B,
+        },
+        Empty,
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_data(cx),
+            BodyKindProj::Empty => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+            BodyKindProj::Empty => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Body { body } => body.is_end_stream(),
+            BodyKind::Empty => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Body { body } => body.size_hint(),
+            BodyKind::Empty => SizeHint::with_exact(0),
+        }
+    }
+}
+
+impl<B> fmt::Debug for ResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(_: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(Body::from("ok")))
+    }
+
+    fn request(expect: Option<&str>, content_length: u64) -> Request<Body> {
+        let mut req = Request::put("/")
+            .header(header::CONTENT_LENGTH, content_length)
+            .body(Body::empty())
+            .unwrap();
+        if let Some(expect) = expect {
+            req.headers_mut()
+                .insert(header::EXPECT, expect.parse().unwrap());
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn max_content_length() {
+        let svc = ExpectContinueLayer::max_content_length(10).layer(service_fn(handle));
+
+        let res = svc
+            .clone()
+            .oneshot(request(Some("100-continue"), 10))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = svc
+            .clone()
+            .oneshot(request(Some("100-Continue"), 11))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        // the policy only applies to requests expecting `100 Continue`
+        let res = svc.oneshot(request(None, 11)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn unknown_expectation() {
+        let svc = ExpectContinueLayer::max_content_length(10).layer(service_fn(handle));
+
+        let res = svc.oneshot(request(Some("something"), 0)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn custom_policy() {
+        let checks = Arc::new(AtomicUsize::new(0));
+        let policy = {
+            let checks = checks.clone();
+            move |req: &Request<Body>| {
+                checks.fetch_add(1, Ordering::SeqCst);
+                if req.uri().path() == "/" {
+                    Err(StatusCode::FORBIDDEN)
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        let svc = ExpectContinueLayer::new(policy).layer(service_fn(handle));
+
+        let res = svc
+            .clone()
+            .oneshot(request(Some("100-continue"), 0))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        svc.oneshot(request(None, 0)).await.unwrap();
+        assert_eq!(checks.load(Ordering::SeqCst), 1);
+    }
+}
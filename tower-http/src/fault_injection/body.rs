@@ -0,0 +1,136 @@
+use super::{random, InjectedFault};
+use crate::BoxError;
+use bytes::{Buf, Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum BodyFault {
+    None,
+    Truncate { remaining: usize },
+    Corrupt,
+    // a chunk was cut short so the next poll fails
+    Failing,
+    // the body was truncated and has failed
+    Truncated,
+}
+
+pin_project! {
+    /// Response body for [`FaultInjection`].
+    ///
+    /// [`FaultInjection`]: super::FaultInjection
+    #[derive(Debug)]
+    pub struct FaultBody<B> {
+        // `None` for synthetic error responses
+        #[pin]
+        inner: Option<B>,
+        fault: BodyFault,
+    }
+}
+
+impl<B> FaultBody<B> {
+    pub(super) fn new(inner: B, fault: BodyFault) -> Self {
+        Self {
+            inner: Some(inner),
+            fault,
+        }
+    }
+
+    pub(super) fn empty() -> Self {
+        Self {
+            inner: None,
+            fault: BodyFault::None,
+        }
+    }
+}
+
+impl<B> Body for FaultBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let inner = match this.inner.as_pin_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(None),
+        };
+        match this.fault {
+            BodyFault::Failing => {
+                *this.fault = BodyFault::Truncated;
+                return Poll::Ready(Some(Err(InjectedFault::Truncated.into())));
+            }
+            BodyFault::Truncated => return Poll::Ready(None),
+            _ => {}
+        }
+
+        let mut data = match futures_util::ready!(inner.poll_data(cx)) {
+            Some(Ok(mut data)) => data.copy_to_bytes(data.remaining()),
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => return Poll::Ready(None),
+        };
+
+        match this.fault {
+            BodyFault::None | BodyFault::Failing | BodyFault::Truncated => {}
+            BodyFault::Truncate { remaining } => {
+                if data.len() <= *remaining {
+                    *remaining -= data.len();
+                } else if *remaining == 0 {
+                    *this.fault = BodyFault::Truncated;
+                    return Poll::Ready(Some(Err(InjectedFault::Truncated.into())));
+                } else {
+                    data.truncate(*remaining);
+                    *this.fault = BodyFault::Failing;
+                }
+            }
+            BodyFault::Corrupt => {
+                if !data.is_empty() {
+                    let mut corrupted = BytesMut::from(&data[..]);
+                    let index = (random() * data.len() as f64) as usize;
+                    corrupted[index.min(data.len() - 1)] ^= 0xff;
+                    data = corrupted.freeze();
+                }
+            }
+        }
+        Poll::Ready(Some(Ok(data)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        match (this.inner.as_pin_mut(), this.fault) {
+            (Some(_), BodyFault::Failing | BodyFault::Truncated) | (None, _) => {
+                Poll::Ready(Ok(None))
+            }
+            (Some(inner), _) => inner.poll_trailers(cx).map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match (&self.inner, self.fault) {
+            (Some(_), BodyFault::Truncated) | (None, _) => true,
+            (Some(_), BodyFault::Failing) => false,
+            (Some(inner), _) => inner.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            Some(inner) => inner.size_hint(),
+            None => SizeHint::with_exact(0),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::{body::BodyFault, FaultBody, InjectedFault};
+use crate::BoxError;
+use futures_util::ready;
+use http::{Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Sleep;
+
+pin_project! {
+    /// Response future for [`FaultInjection`].
+    ///
+    /// [`FaultInjection`]: super::FaultInjection
+    pub struct ResponseFuture<F> {
+        #[pin]
+        sleep: Option<Sleep>,
+        #[pin]
+        kind: Kind<F>,
+        body: BodyFault,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    pub(super) enum Kind<F> {
+        Call {
+            #[pin]
+            future: F,
+        },
+        Status {
+            status: StatusCode,
+        },
+        Abort,
+    }
+}
+
+impl<F> Kind<F> {
+    pub(super) fn call(future: F) -> Self {
+        Self::Call { future }
+    }
+
+    pub(super) fn status(status: StatusCode) -> Self {
+        Self::Status { status }
+    }
+
+    pub(super) fn abort() -> Self {
+        Self::Abort
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    pub(super) fn new(latency: Option<Duration>, kind: Kind<F>, body: BodyFault) -> Self {
+        Self {
+            sleep: latency.map(tokio::time::sleep),
+            kind,
+            body,
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    E: Into<BoxError>,
+{
+    type Output = Result<Response<FaultBody<B>>, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        if let Some(sleep) = this.sleep.as_mut().as_pin_mut() {
+            ready!(sleep.poll(cx));
+            this.sleep.set(None);
+        }
+
+        match this.kind.project() {
+            KindProj::Call { future } => {
+                let res = ready!(future.poll(cx)).map_err(Into::into)?;
+                let body = *this.body;
+                Poll::Ready(Ok(res.map(|inner| FaultBody::new(inner, body))))
+            }
+            KindProj::Status { status } => {
+                let mut res = Response::new(FaultBody::empty());
+                *res.status_mut() = *status;
+                Poll::Ready(Ok(res))
+            }
+            KindProj::Abort => Poll::Ready(Err(InjectedFault::Aborted.into())),
+        }
+    }
+}
@@ -0,0 +1,506 @@
+//! Middleware that injects faults for resilience testing.
+//!
+//! [`FaultInjection`] makes requests fail in the ways real services and networks do, to test
+//! how clients, retries, timeouts and circuit breakers cope. With configurable probabilities
+//! it can:
+//!
+//! - add latency before the request is handled,
+//! - respond with a synthetic error status without calling the inner service,
+//! - abort the request with an [`InjectedFault::Aborted`] error,
+//! - truncate response bodies, failing them with [`InjectedFault::Truncated`] after some
+//!   bytes,
+//! - corrupt response bodies by flipping a byte of every chunk.
+//!
+//! The faults are configured with [`Faults`] through a [`FaultControl`] handle, which can be
+//! cloned and changed at runtime, for example from an admin endpoint. No faults are injected
+//! until some are set. Faults can be restricted to some requests with
+//! [`FaultInjectionLayer::when`], such as requests carrying a test header, so the middleware
+//! can run in shared environments without affecting other traffic.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{HeaderMap, Method, Request, Response, StatusCode, Uri};
+//! use hyper::Body;
+//! use tower::{BoxError, Service, ServiceBuilder, ServiceExt};
+//! use tower_http::fault_injection::{FaultControl, FaultInjectionLayer, Faults};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+//!     Ok(Response::new(Body::from("Hello, World!")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let control = FaultControl::new();
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         FaultInjectionLayer::new(control.clone())
+//!             // only inject faults into requests that opt in
+//!             .when(|_: &Method, _: &Uri, headers: &HeaderMap| headers.contains_key("x-chaos")),
+//!     )
+//!     .service_fn(handle);
+//!
+//! // fail every request with `503 Service Unavailable`
+//! control.set(Faults::new().error(1.0, StatusCode::SERVICE_UNAVAILABLE));
+//!
+//! let request = Request::get("/").header("x-chaos", "1").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+//!
+//! let request = Request::get("/").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod future;
+
+pub use self::{body::FaultBody, future::ResponseFuture};
+
+use self::body::BodyFault;
+use crate::sync::lock_ignore_poison;
+use crate::BoxError;
+use http::{HeaderMap, Method, Request, Response, StatusCode, Uri};
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The faults to inject and their probabilities.
+///
+/// Each fault is decided independently for every request. A request that is aborted or gets
+/// an error status isn't passed to the inner service, so its response body can't be truncated
+/// or corrupted. Aborting takes precedence over error statuses.
+///
+/// # Panics
+///
+/// The builder methods panic if a probability isn't between `0.0` and `1.0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Faults {
+    latency: Option<(f64, Duration)>,
+    error: Option<(f64, StatusCode)>,
+    abort: Option<f64>,
+    truncate_body: Option<(f64, usize)>,
+    corrupt_body: Option<f64>,
+}
+
+impl Faults {
+    /// Create a new `Faults` that doesn't inject any faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay requests by `latency` with the given probability.
+    pub fn latency(mut self, probability: f64, latency: Duration) -> Self {
+        self.latency = Some((check(probability), latency));
+        self
+    }
+
+    /// Respond with `status` instead of calling the inner service with the given probability.
+    pub fn error(mut self, probability: f64, status: StatusCode) -> Self {
+        self.error = Some((check(probability), status));
+        self
+    }
+
+    /// Fail requests with [`InjectedFault::Aborted`] with the given probability.
+    pub fn abort(mut self, probability: f64) -> Self {
+        self.abort = Some(check(probability));
+        self
+    }
+
+    /// Fail response bodies with [`InjectedFault::Truncated`] after `len` bytes with the given
+    /// probability.
+    pub fn truncate_body(mut self, probability: f64, len: usize) -> Self {
+        self.truncate_body = Some((check(probability), len));
+        self
+    }
+
+    /// Flip a byte in every chunk of response bodies with the given probability.
+    pub fn corrupt_body(mut self, probability: f64) -> Self {
+        self.corrupt_body = Some(check(probability));
+        self
+    }
+
+    fn decide(&self) -> Decision {
+        let latency = self
+            .latency
+            .and_then(|(probability, latency)| happens(probability).then_some(latency));
+        let outcome = if self.abort.map_or(false, happens) {
+            Outcome::Abort
+        } else if let Some(status) = self
+            .error
+            .and_then(|(probability, status)| happens(probability).then_some(status))
+        {
+            Outcome::Status(status)
+        } else {
+            Outcome::Call
+        };
+        let body = if let Some(len) = self
+            .truncate_body
+            .and_then(|(probability, len)| happens(probability).then_some(len))
+        {
+            BodyFault::Truncate { remaining: len }
+        } else if self.corrupt_body.map_or(false, happens) {
+            BodyFault::Corrupt
+        } else {
+            BodyFault::None
+        };
+        Decision {
+            latency,
+            outcome,
+            body,
+        }
+    }
+}
+
+fn check(probability: f64) -> f64 {
+    assert!(
+        (0.0..=1.0).contains(&probability),
+        "probability must be between 0.0 and 1.0"
+    );
+    probability
+}
+
+fn happens(probability: f64) -> bool {
+    probability > 0.0 && random() < probability
+}
+
+/// A random number in `[0, 1)`.
+///
+/// Faults don't need to be unpredictable, only spread out, which the randomly seeded hasher of
+/// the standard library does without pulling in a random number generator.
+fn random() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+struct Decision {
+    latency: Option<Duration>,
+    outcome: Outcome,
+    body: BodyFault,
+}
+
+enum Outcome {
+    Call,
+    Status(StatusCode),
+    Abort,
+}
+
+/// Handle for changing the injected faults at runtime.
+///
+/// Clones share the same faults. See the [module docs](crate::fault_injection) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct FaultControl {
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl FaultControl {
+    /// Create a new `FaultControl` that doesn't inject any faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the faults injected into subsequent requests.
+    pub fn set(&self, faults: Faults) {
+        *lock_ignore_poison(&self.faults) = faults;
+    }
+
+    /// Returns the faults currently injected.
+    pub fn get(&self) -> Faults {
+        *lock_ignore_poison(&self.faults)
+    }
+
+    /// Stop injecting faults.
+    pub fn disable(&self) {
+        self.set(Faults::new());
+    }
+}
+
+/// Error returned by [`FaultInjection`] and [`FaultBody`] for injected failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InjectedFault {
+    /// The request was aborted.
+    Aborted,
+    /// The response body was truncated.
+    Truncated,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Aborted => f.write_str("request aborted by fault injection"),
+            Self::Truncated => f.write_str("response body truncated by fault injection"),
+        }
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+type When = Arc<dyn Fn(&Method, &Uri, &HeaderMap) -> bool + Send + Sync>;
+
+/// Layer that applies [`FaultInjection`] which injects faults for resilience testing.
+///
+/// See the [module docs](crate::fault_injection) for an example.
+#[derive(Clone)]
+pub struct FaultInjectionLayer {
+    control: FaultControl,
+    when: Option<When>,
+}
+
+impl FaultInjectionLayer {
+    /// Create a new `FaultInjectionLayer` injecting the faults set with `control`.
+    pub fn new(control: FaultControl) -> Self {
+        Self {
+            control,
+            when: None,
+        }
+    }
+
+    /// Only inject faults into requests for which `predicate` returns `true`.
+    ///
+    /// Faults are injected into all requests by default.
+    pub fn when<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Method, &Uri, &HeaderMap) -> bool + Send + Sync + 'static,
+    {
+        self.when = Some(Arc::new(predicate));
+        self
+    }
+}
+
+impl fmt::Debug for FaultInjectionLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultInjectionLayer")
+            .field("control", &self.control)
+            .field("when", &self.when.is_some())
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for FaultInjectionLayer {
+    type Service = FaultInjection<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FaultInjection {
+            inner,
+            control: self.control.clone(),
+            when: self.when.clone(),
+        }
+    }
+}
+
+/// Middleware that injects faults for resilience testing.
+///
+/// See the [module docs](crate::fault_injection) for an example.
+#[derive(Clone)]
+pub struct FaultInjection<S> {
+    inner: S,
+    control: FaultControl,
+    when: Option<When>,
+}
+
+impl<S> FaultInjection<S> {
+    /// Create a new `FaultInjection` injecting the faults set with `control`.
+    pub fn new(inner: S, control: FaultControl) -> Self {
+        FaultInjectionLayer::new(control).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `FaultInjection` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(control: FaultControl) -> FaultInjectionLayer {
+        FaultInjectionLayer::new(control)
+    }
+
+    /// Only inject faults into requests for which `predicate` returns `true`.
+    ///
+    /// See [`FaultInjectionLayer::when`] for more details.
+    pub fn when<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Method, &Uri, &HeaderMap) -> bool + Send + Sync + 'static,
+    {
+        self.when = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Returns the handle the injected faults are set with.
+    pub fn control(&self) -> &FaultControl {
+        &self.control
+    }
+}
+
+impl<S> fmt::Debug for FaultInjection<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultInjection")
+            .field("inner", &self.inner)
+            .field("control", &self.control)
+            .field("when", &self.when.is_some())
+            .finish()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for FaultInjection<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+{
+    type Response = Response<FaultBody<ResBody>>;
+    type Error = BoxError;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let applies = self
+            .when
+            .as_ref()
+            .map_or(true, |when| when(req.method(), req.uri(), req.headers()));
+        if !applies {
+            return ResponseFuture::new(
+                None,
+                future::Kind::call(self.inner.call(req)),
+                BodyFault::None,
+            );
+        }
+
+        let decision = self.control.get().decide();
+        let kind = match decision.outcome {
+            Outcome::Call => future::Kind::call(self.inner.call(req)),
+            Outcome::Status(status) => future::Kind::status(status),
+            Outcome::Abort => future::Kind::abort(),
+        };
+        ResponseFuture::new(decision.latency, kind, decision.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+    use hyper::Body;
+    use std::time::Instant;
+    use tower::{service_fn, ServiceExt};
+
+    async fn handle(_req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(Body::from("Hello, World!")))
+    }
+
+    async fn call(control: &FaultControl) -> Result<Response<FaultBody<Body>>, BoxError> {
+        FaultInjection::new(service_fn(handle), control.clone())
+            .oneshot(Request::new(Body::empty()))
+            .await
+    }
+
+    async fn read(body: FaultBody<Body>) -> Result<Vec<u8>, BoxError> {
+        let mut body = Box::pin(body);
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk?);
+        }
+        Ok(data)
+    }
+
+    #[tokio::test]
+    async fn injects_nothing_by_default() {
+        let control = FaultControl::new();
+        let res = call(&control).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read(res.into_body()).await.unwrap(), b"Hello, World!");
+    }
+
+    #[tokio::test]
+    async fn injects_errors_and_aborts() {
+        let control = FaultControl::new();
+        control.set(Faults::new().error(1.0, StatusCode::BAD_GATEWAY));
+        let res = call(&control).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+        assert!(read(res.into_body()).await.unwrap().is_empty());
+
+        control.set(control.get().abort(1.0));
+        let err = call(&control).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InjectedFault>(),
+            Some(&InjectedFault::Aborted)
+        );
+
+        control.disable();
+        assert_eq!(call(&control).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn injects_latency() {
+        let control = FaultControl::new();
+        control.set(Faults::new().latency(1.0, Duration::from_millis(50)));
+        let start = Instant::now();
+        call(&control).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn truncates_and_corrupts_bodies() {
+        let control = FaultControl::new();
+        control.set(Faults::new().truncate_body(1.0, 5));
+        let mut body = Box::pin(call(&control).await.unwrap().into_body());
+        assert_eq!(body.data().await.unwrap().unwrap(), "Hello");
+        let err = body.data().await.unwrap().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InjectedFault>(),
+            Some(&InjectedFault::Truncated)
+        );
+
+        control.set(Faults::new().corrupt_body(1.0));
+        let data = read(call(&control).await.unwrap().into_body())
+            .await
+            .unwrap();
+        assert_eq!(data.len(), 13);
+        let changed = data
+            .iter()
+            .zip(b"Hello, World!")
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(changed, 1);
+    }
+
+    #[tokio::test]
+    async fn only_injects_when_predicate_matches() {
+        let control = FaultControl::new();
+        control.set(Faults::new().abort(1.0));
+        let svc = FaultInjectionLayer::new(control)
+            .when(|method: &Method, _: &Uri, _: &HeaderMap| method == Method::POST)
+            .layer(service_fn(handle));
+
+        let res = svc
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await;
+        assert!(res.is_ok());
+        let res = svc
+            .oneshot(Request::post("/").body(Body::empty()).unwrap())
+            .await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,18 @@
+//! 64-bit FNV-1a hashing, for hashes that have to be stable across processes and releases.
+
+pub(crate) const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Hash `bytes` with 64-bit FNV-1a.
+#[cfg(any(feature = "fs", feature = "sticky-session"))]
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_update(OFFSET_BASIS, bytes)
+}
+
+/// Update a 64-bit FNV-1a hash, starting at [`OFFSET_BASIS`], with `bytes`.
+pub(crate) fn fnv1a_update(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
@@ -12,6 +12,11 @@
 //! implementation of the body type to create a new request body. If you know that the body can be
 //! cloned in some way, you can tell the middleware to clone it by configuring a [`policy`].
 //!
+//! Streaming bodies that can't be cloned can be wrapped in a [`ReplayBody`], which buffers them
+//! up to a size limit so they can be replayed to the targets of `307 Temporary Redirect` and
+//! `308 Permanent Redirect` responses when used with the [`ReplayBodies`][policy::ReplayBodies]
+//! policy.
+//!
 //! # Examples
 //!
 //! ## Basic usage
@@ -20,7 +25,7 @@
 //! use http::{Request, Response};
 //! use hyper::Body;
 //! use tower::{Service, ServiceBuilder, ServiceExt};
-//! use tower_http::follow_redirect::{FollowRedirectLayer, RequestUri};
+//! use tower_http::follow_redirect::{FollowRedirectLayer, RedirectChain, RequestUri};
 //!
 //! # #[tokio::main]
 //! # async fn main() -> Result<(), std::convert::Infallible> {
@@ -46,6 +51,11 @@
 //! let response = client.ready().await?.call(request).await?;
 //! // Get the final request URI.
 //! assert_eq!(response.extensions().get::<RequestUri>().unwrap().0, "https://www.rust-lang.org/");
+//!
+//! // The redirections that were followed are recorded in a `RedirectChain`.
+//! let chain = response.extensions().get::<RedirectChain>().unwrap();
+//! assert_eq!(chain.len(), 1);
+//! assert_eq!(chain.hops()[0].status(), http::StatusCode::MOVED_PERMANENTLY);
 //! # Ok(())
 //! # }
 //! ```
@@ -92,7 +102,10 @@
 
 pub mod policy;
 
+pub use crate::replay_body::ReplayBody;
+
 use self::policy::{Action, Attempt, Policy, Standard};
+use crate::ready::take_ready;
 use futures_core::ready;
 use futures_util::future::Either;
 use http::{
@@ -205,8 +218,7 @@ where
     }
 
     fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
-        let service = self.inner.clone();
-        let mut service = mem::replace(&mut self.inner, service);
+        let mut service = take_ready(&mut self.inner);
         let mut policy = self.policy.clone();
         let mut body = BodyRepr::None;
         body.try_clone_from(req.body(), &policy);
@@ -217,6 +229,7 @@ where
             version: req.version(),
             headers: req.headers().clone(),
             body,
+            chain: Vec::new(),
             future: Either::Left(service.call(req)),
             service,
             policy,
@@ -240,6 +253,7 @@ pin_project! {
         version: Version,
         headers: HeaderMap<HeaderValue>,
         body: BodyRepr<B>,
+        chain: Vec<Hop>,
     }
 }
 
@@ -255,6 +269,9 @@ where
         let mut this = self.project();
         let mut res = ready!(this.future.as_mut().poll(cx)?);
         res.extensions_mut().insert(RequestUri(this.uri.clone()));
+        res.extensions_mut().insert(RedirectChain {
+            hops: this.chain.clone(),
+        });
 
         match res.status() {
             StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => {
@@ -299,7 +316,10 @@ where
         };
         match this.policy.redirect(&attempt)? {
             Action::Follow => {
-                *this.uri = location;
+                this.chain.push(Hop {
+                    uri: mem::replace(this.uri, location),
+                    status: res.status(),
+                });
                 this.body.try_clone_from(&body, &this.policy);
 
                 let mut req = Request::new(body);
@@ -326,6 +346,57 @@ where
 /// redirections.
 pub struct RequestUri(pub Uri);
 
+/// Response [`Extensions`][http::Extensions] value that holds the redirections followed by a
+/// [`FollowRedirect`] middleware to get to the response.
+///
+/// Together with [`RequestUri`] this can be used to log where a request ended up, or to detect
+/// redirection loops and unexpected hosts.
+#[derive(Clone, Debug, Default)]
+pub struct RedirectChain {
+    hops: Vec<Hop>,
+}
+
+impl RedirectChain {
+    /// Returns the redirections that were followed, in order.
+    pub fn hops(&self) -> &[Hop] {
+        &self.hops
+    }
+
+    /// Returns the number of redirections that were followed.
+    pub fn len(&self) -> usize {
+        self.hops.len()
+    }
+
+    /// Returns `true` if no redirections were followed.
+    pub fn is_empty(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    /// Returns `true` if a request to `uri` was redirected.
+    pub fn contains(&self, uri: &Uri) -> bool {
+        self.hops.iter().any(|hop| hop.uri() == uri)
+    }
+}
+
+/// A redirection followed by a [`FollowRedirect`] middleware.
+#[derive(Clone, Debug)]
+pub struct Hop {
+    uri: Uri,
+    status: StatusCode,
+}
+
+impl Hop {
+    /// Returns the URI of the request that was redirected.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Returns the status code of the redirection response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
 #[derive(Debug)]
 enum BodyRepr<B> {
     Some(B),
@@ -386,6 +457,7 @@ fn resolve_uri(relative: &str, base: &Uri) -> Option<Uri> {
 #[cfg(test)]
 mod tests {
     use super::{policy::*, *};
+    use crate::BoxError;
     use hyper::{header::LOCATION, Body};
     use std::convert::Infallible;
     use tower::{ServiceBuilder, ServiceExt};
@@ -406,6 +478,13 @@ mod tests {
             res.extensions().get::<RequestUri>().unwrap().0,
             "http://example.com/0"
         );
+
+        let chain = res.extensions().get::<RedirectChain>().unwrap();
+        assert_eq!(chain.len(), 42);
+        assert_eq!(chain.hops()[0].uri(), "http://example.com/42");
+        assert_eq!(chain.hops()[41].uri(), "http://example.com/1");
+        assert!(chain.contains(&Uri::from_static("http://example.com/7")));
+        assert!(!chain.contains(&Uri::from_static("http://example.com/0")));
     }
 
     #[tokio::test]
@@ -456,4 +535,51 @@ mod tests {
         }
         Ok::<_, Infallible>(res.body(n).unwrap())
     }
+
+    #[tokio::test]
+    async fn replays_bodies() {
+        async fn handle(req: Request<ReplayBody<Body>>) -> Result<Response<String>, BoxError> {
+            let uri = req.uri().clone();
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            let mut res = Response::new(String::from_utf8(body.to_vec()).unwrap());
+            if uri.path() == "/old" {
+                *res.status_mut() = StatusCode::TEMPORARY_REDIRECT;
+                res.headers_mut().insert(LOCATION, "/new".parse().unwrap());
+            }
+            Ok(res)
+        }
+
+        let svc = ServiceBuilder::new()
+            .map_request(|req: Request<Body>| req.map(|body| ReplayBody::new(body, 8)))
+            .layer(FollowRedirectLayer::with_policy(ReplayBodies::new()))
+            .service_fn(handle);
+
+        let req = Request::put("http://example.com/old")
+            .body(Body::wrap_stream(futures_util::stream::iter(vec![Ok::<
+                _,
+                Infallible,
+            >(
+                "small",
+            )])))
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.extensions().get::<RequestUri>().unwrap().0,
+            "http://example.com/new"
+        );
+        assert_eq!(res.into_body(), "small");
+
+        // Bodies over the limit can't be replayed so the redirection is returned.
+        let req = Request::put("http://example.com/old")
+            .body(Body::wrap_stream(futures_util::stream::iter(vec![Ok::<
+                _,
+                Infallible,
+            >(
+                "too large",
+            )])))
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
 }
@@ -6,6 +6,8 @@ mod filter_credentials;
 mod limited;
 mod or;
 mod redirect_fn;
+mod replay_bodies;
+mod same_host;
 mod same_origin;
 
 pub use self::{
@@ -15,6 +17,8 @@ pub use self::{
     limited::Limited,
     or::Or,
     redirect_fn::{redirect_fn, RedirectFn},
+    replay_bodies::ReplayBodies,
+    same_host::SameHost,
     same_origin::SameOrigin,
 };
 
@@ -0,0 +1,93 @@
+use super::{Action, Attempt, Policy, Standard};
+use crate::{
+    replay_body::{ReplayBody, Shared},
+    sync::lock_ignore_poison,
+};
+use http::{Method, Request, StatusCode};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// A redirection [`Policy`] that replays [`ReplayBody`] request bodies.
+///
+/// Redirections that keep the request body, such as `307 Temporary Redirect` and
+/// `308 Permanent Redirect`, are only followed if the body was sent completely and didn't
+/// exceed the buffer limit of the `ReplayBody`. Otherwise the redirection response is returned.
+/// All other decisions are delegated to the inner policy.
+///
+/// See [`ReplayBody`] for an example.
+#[derive(Clone, Default)]
+pub struct ReplayBodies<P = Standard> {
+    inner: P,
+    method: Method,
+    shared: Option<Arc<Mutex<Shared>>>,
+}
+
+impl ReplayBodies {
+    /// Create a new [`ReplayBodies`] delegating to a [`Standard`] policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<P> ReplayBodies<P> {
+    /// Create a new [`ReplayBodies`] delegating to `inner`.
+    pub fn with_policy(inner: P) -> Self {
+        ReplayBodies {
+            inner,
+            method: Method::GET,
+            shared: None,
+        }
+    }
+
+    fn is_replayable(&self) -> bool {
+        self.shared
+            .as_ref()
+            .map_or(true, |shared| lock_ignore_poison(shared).is_replayable())
+    }
+}
+
+impl<P> fmt::Debug for ReplayBodies<P>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayBodies")
+            .field("inner", &self.inner)
+            .field("method", &self.method)
+            .finish()
+    }
+}
+
+impl<P, B, E> Policy<ReplayBody<B>, E> for ReplayBodies<P>
+where
+    P: Policy<ReplayBody<B>, E>,
+{
+    fn redirect(&mut self, attempt: &Attempt<'_>) -> Result<Action, E> {
+        // These are the redirections where `FollowRedirect` drops the request body.
+        let drops_body = match attempt.status() {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.method == Method::POST,
+            StatusCode::SEE_OTHER => true,
+            _ => false,
+        };
+
+        if !drops_body && !self.is_replayable() {
+            return Ok(Action::Stop);
+        }
+
+        self.inner.redirect(attempt)
+    }
+
+    fn on_request(&mut self, request: &mut Request<ReplayBody<B>>) {
+        self.method = request.method().clone();
+        if self.shared.is_none() {
+            self.shared = request.body().shared();
+        }
+        self.inner.on_request(request)
+    }
+
+    fn clone_body(&self, body: &ReplayBody<B>) -> Option<ReplayBody<B>> {
+        Some(body.replay())
+    }
+}
@@ -0,0 +1,70 @@
+use super::{Action, Attempt, Policy};
+use std::fmt;
+
+/// A redirection [`Policy`] that stops redirections to other hosts.
+///
+/// Unlike [`SameOrigin`](super::SameOrigin), the scheme and port may change, so redirections from
+/// `http://example.com` to `https://example.com` are followed.
+#[derive(Clone, Copy, Default)]
+pub struct SameHost {
+    _priv: (),
+}
+
+impl SameHost {
+    /// Create a new [`SameHost`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for SameHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SameHost").finish()
+    }
+}
+
+impl<B, E> Policy<B, E> for SameHost {
+    fn redirect(&mut self, attempt: &Attempt<'_>) -> Result<Action, E> {
+        match (attempt.previous().host(), attempt.location().host()) {
+            (Some(previous), Some(location)) if previous.eq_ignore_ascii_case(location) => {
+                Ok(Action::Follow)
+            }
+            _ => Ok(Action::Stop),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Uri;
+
+    #[test]
+    fn works() {
+        let mut policy = SameHost::default();
+
+        let previous = Uri::from_static("http://example.com/old");
+
+        for (location, follow) in [
+            ("https://example.com:8443/new", true),
+            ("http://EXAMPLE.com/new", true),
+            ("http://www.example.com/new", false),
+            ("http://example.org/new", false),
+        ] {
+            let location = location.parse::<Uri>().unwrap();
+            let attempt = Attempt {
+                status: Default::default(),
+                location: &location,
+                previous: &previous,
+            };
+            assert_eq!(
+                Policy::<(), ()>::redirect(&mut policy, &attempt)
+                    .unwrap()
+                    .is_follow(),
+                follow,
+                "{}",
+                location
+            );
+        }
+    }
+}
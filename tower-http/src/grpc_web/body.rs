@@ -0,0 +1,247 @@
+use super::Encoding;
+use crate::BoxError;
+use base64::Engine as _;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::ready;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// The flag marking a gRPC-Web frame as trailers.
+const TRAILERS_FLAG: u8 = 0x80;
+
+pin_project! {
+    /// Request body for [`GrpcWeb`].
+    ///
+    /// The bodies of `application/grpc-web-text` requests are decoded from base64.
+    ///
+    /// [`GrpcWeb`]: super::GrpcWeb
+    pub struct RequestBody<B> {
+        #[pin]
+        inner: B,
+        // base64 that hasn't been decoded yet, if the body is encoded
+        pending: Option<BytesMut>,
+    }
+}
+
+impl<B> RequestBody<B> {
+    pub(super) fn new(inner: B, encoding: Option<Encoding>) -> Self {
+        Self {
+            inner,
+            pending: match encoding {
+                Some(Encoding::Text) => Some(BytesMut::new()),
+                Some(Encoding::Binary) | None => None,
+            },
+        }
+    }
+}
+
+impl<B> Body for RequestBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            let mut data = match ready!(this.inner.as_mut().poll_data(cx)) {
+                Some(Ok(data)) => data,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => match this.pending {
+                    Some(pending) if !pending.is_empty() => {
+                        return Poll::Ready(Some(Err("truncated base64 in request body".into())));
+                    }
+                    _ => return Poll::Ready(None),
+                },
+            };
+            let pending = match this.pending {
+                Some(pending) => pending,
+                None => return Poll::Ready(Some(Ok(data.copy_to_bytes(data.remaining())))),
+            };
+
+            pending.put(data);
+            let complete = pending.len() - pending.len() % 4;
+            if complete == 0 {
+                continue;
+            }
+            let chunk = pending.split_to(complete);
+            return Poll::Ready(Some(decode(&chunk).map(Bytes::from)));
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx).map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.pending {
+            Some(_) => SizeHint::default(),
+            None => self.inner.size_hint(),
+        }
+    }
+}
+
+/// Decode base64 made up of complete groups.
+///
+/// Clients may encode every message separately, so padding can appear in the middle.
+fn decode(data: &[u8]) -> Result<Vec<u8>, BoxError> {
+    let mut decoded = Vec::with_capacity(data.len() / 4 * 3);
+    let mut start = 0;
+    for end in (4..=data.len()).step_by(4) {
+        if data[end - 1] == b'=' || end == data.len() {
+            BASE64.decode_vec(&data[start..end], &mut decoded)?;
+            start = end;
+        }
+    }
+    Ok(decoded)
+}
+
+pin_project! {
+    /// Response body for [`GrpcWeb`].
+    ///
+    /// The trailers of gRPC-Web responses are sent as the last frame of the body, and the bodies
+    /// of `application/grpc-web-text` responses are encoded as base64.
+    ///
+    /// [`GrpcWeb`]: super::GrpcWeb
+    pub struct ResponseBody<B> {
+        #[pin]
+        inner: B,
+        encoding: Option<Encoding>,
+        // bytes that haven't been encoded yet since base64 encodes groups of three
+        pending: BytesMut,
+        done: bool,
+    }
+}
+
+impl<B> ResponseBody<B> {
+    pub(super) fn new(inner: B, encoding: Option<Encoding>) -> Self {
+        Self {
+            inner,
+            encoding,
+            pending: BytesMut::new(),
+            done: false,
+        }
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        let encoding = match this.encoding {
+            Some(encoding) => *encoding,
+            None => {
+                return Poll::Ready(
+                    ready!(this.inner.poll_data(cx))
+                        .map(|data| data.map(|mut data| data.copy_to_bytes(data.remaining()))),
+                )
+            }
+        };
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match ready!(this.inner.as_mut().poll_data(cx)) {
+                Some(Ok(mut data)) => match encoding {
+                    Encoding::Binary => {
+                        return Poll::Ready(Some(Ok(data.copy_to_bytes(data.remaining()))))
+                    }
+                    Encoding::Text => {
+                        this.pending.put(data);
+                        let complete = this.pending.len() - this.pending.len() % 3;
+                        if complete == 0 {
+                            continue;
+                        }
+                        let chunk = this.pending.split_to(complete);
+                        return Poll::Ready(Some(Ok(BASE64.encode(chunk).into())));
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => break,
+            }
+        }
+
+        let trailers = ready!(this.inner.poll_trailers(cx))?;
+        *this.done = true;
+        if let Some(trailers) = trailers {
+            encode_trailers(&trailers, this.pending);
+        }
+        let frame = this.pending.split().freeze();
+        let frame = match encoding {
+            Encoding::Binary => frame,
+            Encoding::Text => BASE64.encode(frame).into(),
+        };
+        if frame.is_empty() {
+            return Poll::Ready(None);
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        match this.encoding {
+            // the trailers have been sent in the body
+            Some(_) => Poll::Ready(Ok(None)),
+            None => this.inner.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.encoding {
+            Some(_) => self.done,
+            None => self.inner.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.encoding {
+            Some(_) => SizeHint::default(),
+            None => self.inner.size_hint(),
+        }
+    }
+}
+
+/// Append the gRPC-Web frame containing `trailers`, which are encoded like HTTP/1 headers.
+fn encode_trailers(trailers: &HeaderMap, buf: &mut BytesMut) {
+    let mut block = Vec::new();
+    for (name, value) in trailers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.push(b':');
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+    buf.put_u8(TRAILERS_FLAG);
+    buf.put_u32(block.len() as u32);
+    buf.put_slice(&block);
+}
@@ -0,0 +1,57 @@
+use super::{Encoding, ResponseBody};
+use futures_util::ready;
+use http::{header, HeaderValue, Response};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response future for [`GrpcWeb`].
+    ///
+    /// [`GrpcWeb`]: super::GrpcWeb
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        encoding: Option<Encoding>,
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    pub(super) fn new(inner: F, encoding: Option<Encoding>) -> Self {
+        Self { inner, encoding }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+
+        let encoding = (*this.encoding).and_then(|encoding| {
+            let content_type = res.headers().get(header::CONTENT_TYPE)?.to_str().ok()?;
+            let subtype = content_type.strip_prefix("application/grpc")?;
+            if !subtype.is_empty() && !subtype.starts_with('+') && !subtype.starts_with(';') {
+                return None;
+            }
+            let content_type = format!("{}{}", encoding.content_type(), subtype);
+            Some((encoding, HeaderValue::from_str(&content_type).ok()?))
+        });
+        let encoding = encoding.map(|(encoding, content_type)| {
+            let headers = res.headers_mut();
+            headers.insert(header::CONTENT_TYPE, content_type);
+            // the body grows by the trailers frame and base64
+            headers.remove(header::CONTENT_LENGTH);
+            encoding
+        });
+
+        Poll::Ready(Ok(res.map(|body| ResponseBody::new(body, encoding))))
+    }
+}
@@ -0,0 +1,203 @@
+//! Middleware that lets browsers call gRPC services with [gRPC-Web].
+//!
+//! Browsers can't read HTTP/2 trailers, which gRPC uses for the status of a call, so gRPC-Web
+//! clients send requests with an `application/grpc-web` content type and expect the trailers as
+//! the last frame of the response body. [`GrpcWeb`] translates these requests to gRPC for the
+//! inner service, for example a [tonic] server, and the responses back to gRPC-Web, so no
+//! separate proxy is needed.
+//!
+//! Both the binary `application/grpc-web` and the base64 encoded `application/grpc-web-text`
+//! formats are supported, with any subtype such as `+proto`. Other requests are passed through
+//! unchanged. Browsers usually call gRPC-Web services cross-origin, so this is typically combined
+//! with [`CorsLayer`] exposing the `grpc-status` and `grpc-message` headers.
+//!
+//! [gRPC-Web]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md
+//! [tonic]: https://crates.io/crates/tonic
+//! [`CorsLayer`]: crate::cors::CorsLayer
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{HeaderMap, Request, Response};
+//! use http_body::Body as _;
+//! use hyper::Body;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::grpc_web::{GrpcWebLayer, RequestBody};
+//!
+//! // A gRPC service that responds with an empty message.
+//! async fn grpc(req: Request<RequestBody<Body>>) -> Result<Response<Body>, tower::BoxError> {
+//!     assert_eq!(req.headers()["content-type"], "application/grpc+proto");
+//!
+//!     let (mut sender, body) = Body::channel();
+//!     tokio::spawn(async move {
+//!         sender.send_data(Bytes::from_static(&[0, 0, 0, 0, 0])).await?;
+//!         let mut trailers = HeaderMap::new();
+//!         trailers.insert("grpc-status", "0".parse().unwrap());
+//!         sender.send_trailers(trailers).await
+//!     });
+//!     let res = Response::builder()
+//!         .header("content-type", "application/grpc+proto")
+//!         .body(body)?;
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(GrpcWebLayer::new())
+//!     .service_fn(grpc);
+//!
+//! let request = Request::post("/helloworld.Greeter/SayHello")
+//!     .header("content-type", "application/grpc-web+proto")
+//!     .body(Body::from(vec![0, 0, 0, 0, 0]))?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.headers()["content-type"], "application/grpc-web+proto");
+//!
+//! // The trailers are sent in the body.
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert_eq!(&body[..5], &[0, 0, 0, 0, 0]);
+//! assert_eq!(&body[5..], b"\x80\0\0\0\x0fgrpc-status:0\r\n");
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod future;
+mod service;
+
+use self::service::Encoding;
+
+pub use self::{
+    body::{RequestBody, ResponseBody},
+    future::ResponseFuture,
+    service::{GrpcWeb, GrpcWebLayer},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, HeaderMap, Request, Response, Version};
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, Layer, ServiceExt};
+
+    /// A gRPC service echoing the request body, followed by `grpc-status: 0` trailers.
+    async fn echo(req: Request<RequestBody<Body>>) -> Result<Response<Body>, BoxError> {
+        assert_eq!(req.version(), Version::HTTP_2);
+        assert_eq!(req.headers()[header::TE], "trailers");
+        let content_type = req.headers()[header::CONTENT_TYPE].clone();
+        let data = hyper::body::to_bytes(req.into_body()).await?;
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data(data).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", "0".parse().unwrap());
+            sender.send_trailers(trailers).await.unwrap();
+        });
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body)
+            .unwrap();
+        Ok(res)
+    }
+
+    const MESSAGE: &[u8] = b"\0\0\0\0\x02hi";
+    const TRAILERS: &[u8] = b"\x80\0\0\0\x0fgrpc-status:0\r\n";
+
+    #[tokio::test]
+    async fn binary() {
+        let svc = GrpcWebLayer::new().layer(service_fn(echo));
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/grpc-web+proto")
+            .header(header::CONTENT_LENGTH, MESSAGE.len())
+            .body(Body::from(MESSAGE))
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/grpc-web+proto"
+        );
+
+        let mut body = res.into_body();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(data, [MESSAGE, TRAILERS].concat());
+        assert!(body.trailers().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn text() {
+        // messages may be encoded separately, with padding in the middle of the body
+        let (mut sender, req_body) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in ["AAAAAAJo", "aQ==AAAA", "AAA="] {
+                sender.send_data(Bytes::from(chunk)).await.unwrap();
+            }
+        });
+        let svc = GrpcWebLayer::new().layer(service_fn(echo));
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/grpc-web-text")
+            .body(req_body)
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/grpc-web-text"
+        );
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body =
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, body).unwrap();
+        assert_eq!(body, [MESSAGE, b"\0\0\0\0\0", TRAILERS].concat());
+    }
+
+    #[tokio::test]
+    async fn invalid_base64() {
+        let svc = service_fn(|req: Request<RequestBody<Body>>| async move {
+            let res = hyper::body::to_bytes(req.into_body()).await;
+            assert!(res.is_err());
+            Ok::<_, BoxError>(Response::new(Body::empty()))
+        });
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/grpc-web-text")
+            .body(Body::from("AAAAAAJ"))
+            .unwrap();
+        GrpcWebLayer::new().layer(svc).oneshot(req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn passes_through_other_requests() {
+        let svc =
+            GrpcWebLayer::new().layer(service_fn(|req: Request<RequestBody<Body>>| async move {
+                let (mut sender, body) = Body::channel();
+                let content_type = req.headers()[header::CONTENT_TYPE].clone();
+                tokio::spawn(async move {
+                    sender.send_data(Bytes::from("data")).await.unwrap();
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("grpc-status", "0".parse().unwrap());
+                    sender.send_trailers(trailers).await.unwrap();
+                });
+                let res = Response::builder()
+                    .header(header::CONTENT_TYPE, content_type)
+                    .body(body)
+                    .unwrap();
+                Ok::<_, BoxError>(res)
+            }));
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/grpc");
+
+        let mut body = res.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "data");
+        assert!(body.data().await.is_none());
+        assert_eq!(body.trailers().await.unwrap().unwrap()["grpc-status"], "0");
+    }
+}
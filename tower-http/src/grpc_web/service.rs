@@ -0,0 +1,128 @@
+use super::{RequestBody, ResponseBody, ResponseFuture};
+use http::{header, HeaderValue, Request, Response, Version};
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// How the body of a gRPC-Web request or response is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Encoding {
+    /// `application/grpc-web`, the same framing as gRPC.
+    Binary,
+    /// `application/grpc-web-text`, the framing encoded as base64.
+    Text,
+}
+
+impl Encoding {
+    /// Parse the `Content-Type` of a gRPC-Web request, returning the encoding and the subtype,
+    /// such as `+proto`.
+    fn from_content_type(content_type: &str) -> Option<(Self, &str)> {
+        let (encoding, subtype) =
+            if let Some(subtype) = content_type.strip_prefix("application/grpc-web-text") {
+                (Self::Text, subtype)
+            } else {
+                (
+                    Self::Binary,
+                    content_type.strip_prefix("application/grpc-web")?,
+                )
+            };
+        if subtype.is_empty() || subtype.starts_with('+') || subtype.starts_with(';') {
+            Some((encoding, subtype))
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn content_type(self) -> &'static str {
+        match self {
+            Self::Binary => "application/grpc-web",
+            Self::Text => "application/grpc-web-text",
+        }
+    }
+}
+
+/// Layer that applies the [`GrpcWeb`] middleware which translates gRPC-Web requests to gRPC.
+///
+/// See the [module docs](crate::grpc_web) for more details.
+#[derive(Clone, Debug, Default)]
+pub struct GrpcWebLayer {
+    _priv: (),
+}
+
+impl GrpcWebLayer {
+    /// Create a new `GrpcWebLayer`.
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for GrpcWebLayer {
+    type Service = GrpcWeb<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcWeb { inner }
+    }
+}
+
+/// Middleware that translates gRPC-Web requests to gRPC and the responses back to gRPC-Web.
+///
+/// See the [module docs](crate::grpc_web) for more details.
+#[derive(Clone, Debug)]
+pub struct GrpcWeb<S> {
+    inner: S,
+}
+
+impl<S> GrpcWeb<S> {
+    /// Create a new `GrpcWeb`.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `GrpcWeb` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> GrpcWebLayer {
+        GrpcWebLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for GrpcWeb<S>
+where
+    S: Service<Request<RequestBody<ReqBody>>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let grpc = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Encoding::from_content_type)
+            .and_then(|(encoding, subtype)| {
+                let content_type = format!("application/grpc{}", subtype);
+                Some((encoding, HeaderValue::from_str(&content_type).ok()?))
+            });
+
+        let encoding = grpc.map(|(encoding, content_type)| {
+            let headers = req.headers_mut();
+            headers.insert(header::CONTENT_TYPE, content_type);
+            headers.insert(header::TE, HeaderValue::from_static("trailers"));
+            // the length changes when base64 is decoded
+            headers.remove(header::CONTENT_LENGTH);
+            *req.version_mut() = Version::HTTP_2;
+            encoding
+        });
+
+        let req = req.map(|body| RequestBody::new(body, encoding));
+        ResponseFuture::new(self.inner.call(req), encoding)
+    }
+}
@@ -0,0 +1,371 @@
+//! Middleware that converts errors from the inner service into responses.
+//!
+//! Middleware such as tower's [`Timeout`] or [`LoadShed`] signal failure with errors, but HTTP
+//! servers generally want a response for every request. [`HandleError`] calls a function with the
+//! error and responds with the response it returns, so the resulting service's error type is
+//! [`Infallible`].
+//!
+//! Errors from the inner service's [`Service::poll_ready`] are converted as well. To do so the
+//! inner service is cloned and driven to readiness for each request, so it must implement
+//! [`Clone`].
+//!
+//! The function can either return a response directly, using [`HandleErrorLayer::new`], or a
+//! future that resolves to one, using [`HandleErrorLayer::new_async`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::time::Duration;
+//! use tower::{BoxError, Service, ServiceBuilder, ServiceExt};
+//! use tower_http::handle_error::HandleErrorLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, BoxError> {
+//!     Err("something went wrong".into())
+//! }
+//!
+//! fn handle_error(err: BoxError) -> Response<Body> {
+//!     let mut res = Response::new(Body::from(err.to_string()));
+//!     *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+//!     res
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(HandleErrorLayer::new(handle_error))
+//!     .service_fn(handle);
+//!
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Timeout`]: https://docs.rs/tower/latest/tower/timeout/struct.Timeout.html
+//! [`LoadShed`]: https://docs.rs/tower/latest/tower/load_shed/struct.LoadShed.html
+//! [`Infallible`]: std::convert::Infallible
+//! [`Service::poll_ready`]: tower_service::Service::poll_ready
+
+use crate::{ready::take_ready, BoxError};
+use bytes::Bytes;
+use futures_util::{
+    future::{ready, Ready},
+    ready,
+};
+use http::{Request, Response};
+use http_body::{combinators::UnsyncBoxBody, Body};
+use pin_project_lite::pin_project;
+use std::{
+    convert::Infallible,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::util::Oneshot;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Trait for converting errors into responses.
+///
+/// Implemented for functions returning responses directly, and for [`Async`] wrapping
+/// functions returning futures.
+pub trait ErrorHandler<E> {
+    /// The body type of the responses.
+    type ResponseBody;
+
+    /// The future resolving to the response.
+    type Future: Future<Output = Response<Self::ResponseBody>>;
+
+    /// Convert the error into a response.
+    fn handle_error(&mut self, err: E) -> Self::Future;
+}
+
+impl<F, E, B> ErrorHandler<E> for F
+where
+    F: FnMut(E) -> Response<B>,
+{
+    type ResponseBody = B;
+    type Future = Ready<Response<B>>;
+
+    fn handle_error(&mut self, err: E) -> Self::Future {
+        ready(self(err))
+    }
+}
+
+/// An [`ErrorHandler`] calling an async function.
+///
+/// Created with [`HandleErrorLayer::new_async`] or [`HandleError::new_async`].
+#[derive(Clone, Copy)]
+pub struct Async<F>(F);
+
+impl<F> fmt::Debug for Async<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Async")
+            .field(&std::any::type_name::<F>())
+            .finish()
+    }
+}
+
+impl<F, Fut, E, B> ErrorHandler<E> for Async<F>
+where
+    F: FnMut(E) -> Fut,
+    Fut: Future<Output = Response<B>>,
+{
+    type ResponseBody = B;
+    type Future = Fut;
+
+    fn handle_error(&mut self, err: E) -> Self::Future {
+        (self.0)(err)
+    }
+}
+
+/// Layer that applies [`HandleError`] which converts errors from the inner service into
+/// responses.
+///
+/// See the [module docs](crate::handle_error) for an example.
+#[derive(Clone, Copy)]
+pub struct HandleErrorLayer<H> {
+    handler: H,
+}
+
+impl<H> fmt::Debug for HandleErrorLayer<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandleErrorLayer")
+            .field("handler", &std::any::type_name::<H>())
+            .finish()
+    }
+}
+
+impl<H> HandleErrorLayer<H> {
+    /// Create a new `HandleErrorLayer` from a function that converts errors into responses.
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+}
+
+impl<F> HandleErrorLayer<Async<F>> {
+    /// Create a new `HandleErrorLayer` from an async function that converts errors into
+    /// responses.
+    pub fn new_async(handler: F) -> Self {
+        Self {
+            handler: Async(handler),
+        }
+    }
+}
+
+impl<S, H> Layer<S> for HandleErrorLayer<H>
+where
+    H: Clone,
+{
+    type Service = HandleError<S, H>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HandleError {
+            inner,
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+/// Middleware that converts errors from the inner service into responses.
+///
+/// See the [module docs](crate::handle_error) for an example.
+#[derive(Clone, Copy)]
+pub struct HandleError<S, H> {
+    inner: S,
+    handler: H,
+}
+
+impl<S, H> fmt::Debug for HandleError<S, H>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandleError")
+            .field("inner", &self.inner)
+            .field("handler", &std::any::type_name::<H>())
+            .finish()
+    }
+}
+
+impl<S, H> HandleError<S, H> {
+    /// Create a new `HandleError` from a function that converts errors into responses.
+    pub fn new(inner: S, handler: H) -> Self {
+        Self { inner, handler }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `HandleError` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(handler: H) -> HandleErrorLayer<H> {
+        HandleErrorLayer::new(handler)
+    }
+}
+
+impl<S, F> HandleError<S, Async<F>> {
+    /// Create a new `HandleError` from an async function that converts errors into responses.
+    pub fn new_async(inner: S, handler: F) -> Self {
+        Self {
+            inner,
+            handler: Async(handler),
+        }
+    }
+}
+
+impl<S, H, ReqBody, ResBody> Service<Request<ReqBody>> for HandleError<S, H>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+    H: ErrorHandler<S::Error> + Clone,
+    H::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <H::ResponseBody as Body>::Error: Into<BoxError>,
+{
+    type Response = Response<UnsyncBoxBody<Bytes, BoxError>>;
+    type Error = Infallible;
+    type Future = ResponseFuture<Oneshot<S, Request<ReqBody>>, H, S::Error>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The inner service is driven to readiness in `call` so its errors can be converted
+        // into responses.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let inner = take_ready(&mut self.inner);
+
+        ResponseFuture {
+            state: State::Inner {
+                future: Oneshot::new(inner, req),
+                handler: Some(self.handler.clone()),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`HandleError`].
+    pub struct ResponseFuture<F, H, E>
+    where
+        H: ErrorHandler<E>,
+    {
+        #[pin]
+        state: State<F, H, E>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<F, H, E>
+    where
+        H: ErrorHandler<E>,
+    {
+        Inner {
+            #[pin]
+            future: F,
+            handler: Option<H>,
+        },
+        Handling {
+            #[pin]
+            future: H::Future,
+        },
+    }
+}
+
+impl<F, H, E, B> Future for ResponseFuture<F, H, E>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    H: ErrorHandler<E>,
+    H::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <H::ResponseBody as Body>::Error: Into<BoxError>,
+{
+    type Output = Result<Response<UnsyncBoxBody<Bytes, BoxError>>, Infallible>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            match this.state.as_mut().project() {
+                StateProj::Inner { future, handler } => match ready!(future.poll(cx)) {
+                    Ok(res) => {
+                        return Poll::Ready(Ok(
+                            res.map(|body| body.map_err(Into::into).boxed_unsync())
+                        ))
+                    }
+                    Err(err) => {
+                        let mut handler = handler.take().expect("future polled after completion");
+                        let future = handler.handle_error(err);
+                        this.state.set(State::Handling { future });
+                    }
+                },
+                StateProj::Handling { future } => {
+                    let res = ready!(future.poll(cx));
+                    return Poll::Ready(
+                        Ok(res.map(|body| body.map_err(Into::into).boxed_unsync())),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use hyper::Body;
+    use std::time::Duration;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    async fn fail(_: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Err("boom".into())
+    }
+
+    #[tokio::test]
+    async fn sync_handler() {
+        let svc = ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(|err: BoxError| {
+                let mut res = Response::new(Body::from(err.to_string()));
+                *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                res
+            }))
+            .service_fn(fail);
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        assert_eq!(body, "boom");
+    }
+
+    #[tokio::test]
+    async fn async_handler_with_tower_timeout() {
+        let svc = ServiceBuilder::new()
+            .layer(HandleErrorLayer::new_async(|err: BoxError| async move {
+                let status = if err.is::<tower::timeout::error::Elapsed>() {
+                    StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                };
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = status;
+                res
+            }))
+            .timeout(Duration::from_millis(10))
+            .service_fn(|_: Request<Body>| async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
@@ -0,0 +1,54 @@
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Request body for [`SignRequest`] and [`VerifySignature`].
+///
+/// The bodies of signed requests have been buffered to compute their digest.
+///
+/// [`SignRequest`]: super::SignRequest
+/// [`VerifySignature`]: super::VerifySignature
+#[derive(Debug, Default)]
+pub struct SignedBody {
+    data: Option<Bytes>,
+}
+
+impl SignedBody {
+    pub(super) fn new(data: Bytes) -> Self {
+        Self {
+            data: Some(data).filter(|data| !data.is_empty()),
+        }
+    }
+}
+
+impl Body for SignedBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.data.as_ref().map_or(0, |data| data.len() as u64))
+    }
+}
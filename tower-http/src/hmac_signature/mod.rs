@@ -0,0 +1,306 @@
+//! Middleware that signs requests with an HMAC and verifies their signatures.
+//!
+//! [`SignRequest`] is used by clients. It computes an HMAC-SHA256 with a shared key over a
+//! canonical representation of the request and sets it in a header, `X-Signature` by default.
+//! [`VerifySignature`] is used by servers. It recomputes the HMAC and rejects requests whose
+//! signature doesn't match, or that were signed too long ago, with `401 Unauthorized`.
+//!
+//! The canonical representation consists of these lines, joined by `\n`:
+//!
+//! 1. The method, such as `POST`.
+//! 2. The path and query of the URI, such as `/items?page=2`.
+//! 3. The time the request was signed, in seconds since the Unix epoch.
+//! 4. One line per value of the signed headers, `name:value` with the name in lowercase and
+//!    whitespace trimmed from both ends of the value. Missing headers don't add a line. Only
+//!    `Content-Type` is signed by default, which can be changed with
+//!    [`SignRequestLayer::signed_headers`].
+//! 5. The base64 encoded SHA-256 digest of the body.
+//!
+//! The signature header contains the time and the base64 encoded HMAC, such as
+//! `t=1700000000,s=...`. Both sides have to use the same key, header and signed headers.
+//!
+//! Bodies are buffered to compute their digest, so the inner services receive a [`SignedBody`].
+//! [`VerifySignature`] responds with `413 Payload Too Large` to requests whose body is larger
+//! than [`VerifySignatureLayer::max_body_size`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, ServiceExt};
+//! use tower_http::hmac_signature::{SignRequestLayer, SignedBody, VerifySignatureLayer};
+//!
+//! async fn handle(req: Request<SignedBody>) -> Result<Response<Body>, Infallible> {
+//!     // only requests with a valid signature get here
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! let server = ServiceBuilder::new()
+//!     .layer(VerifySignatureLayer::new(b"secret"))
+//!     .service_fn(handle);
+//!
+//! // clients sign requests with the same key
+//! let client = ServiceBuilder::new()
+//!     .layer(SignRequestLayer::new(b"secret"))
+//!     .service(server);
+//!
+//! let request = Request::post("/items").body(Body::from("item"))?;
+//! let response = client.oneshot(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod sign;
+mod verify;
+
+pub use self::{
+    body::SignedBody,
+    sign::{ResponseFuture as SignResponseFuture, SignRequest, SignRequestLayer},
+    verify::{ResponseFuture as VerifyResponseFuture, VerifySignature, VerifySignatureLayer},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use http::{header, request::Parts, HeaderName};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const DEFAULT_HEADER: HeaderName = HeaderName::from_static("x-signature");
+
+/// The configuration shared by [`SignRequest`] and [`VerifySignature`].
+#[derive(Clone)]
+struct Config {
+    mac: Hmac<Sha256>,
+    header: HeaderName,
+    signed_headers: Arc<[HeaderName]>,
+}
+
+impl Config {
+    fn new(key: &[u8]) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            header: DEFAULT_HEADER,
+            signed_headers: Arc::new([header::CONTENT_TYPE]),
+        }
+    }
+
+    /// Compute the HMAC of the canonical representation of a request.
+    fn mac(&self, parts: &Parts, timestamp: u64, body: &Bytes) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        mac.update(parts.method.as_str().as_bytes());
+        mac.update(b"\n");
+        let path_and_query = parts.uri.path_and_query().map_or_else(
+            || parts.uri.path(),
+            |path_and_query| path_and_query.as_str(),
+        );
+        mac.update(path_and_query.as_bytes());
+        mac.update(format!("\n{}\n", timestamp).as_bytes());
+        // header values can't contain newlines, so every value gets its own unambiguous line
+        for name in self.signed_headers.iter() {
+            for value in parts.headers.get_all(name) {
+                mac.update(name.as_str().as_bytes());
+                mac.update(b":");
+                mac.update(trim(value.as_bytes()));
+                mac.update(b"\n");
+            }
+        }
+        mac.update(STANDARD.encode(Sha256::digest(body)).as_bytes());
+        mac
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("header", &self.header)
+            .field("signed_headers", &self.signed_headers)
+            .finish()
+    }
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let start = value
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+/// The current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{header, HeaderValue, Request, Response, StatusCode};
+    use hyper::Body;
+    use std::time::Duration;
+    use tower::{service_fn, BoxError, Layer, ServiceExt};
+
+    async fn echo(req: Request<SignedBody>) -> Result<Response<Body>, BoxError> {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok(Response::new(Body::from(body)))
+    }
+
+    /// Sign `req` and return the signed request.
+    async fn sign(layer: SignRequestLayer, req: Request<Body>) -> Request<Body> {
+        let svc = layer.layer(service_fn(|req: Request<SignedBody>| async move {
+            let (parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            Ok::<_, BoxError>(Request::from_parts(parts, Body::from(body)))
+        }));
+        svc.oneshot(req).await.unwrap()
+    }
+
+    async fn verify(layer: VerifySignatureLayer, req: Request<Body>) -> (StatusCode, Bytes) {
+        let res = layer.layer(service_fn(echo)).oneshot(req).await.unwrap();
+        let status = res.status();
+        (
+            status,
+            hyper::body::to_bytes(res.into_body()).await.unwrap(),
+        )
+    }
+
+    fn request() -> Request<Body> {
+        Request::post("http://example.com/items?page=2")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header("x-tenant", "a")
+            .body(Body::from("hello"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let req = sign(SignRequestLayer::new(b"key"), request()).await;
+        let signature = req.headers()["x-signature"].to_str().unwrap();
+        assert!(signature.starts_with("t="));
+        assert!(signature.contains(",s="));
+
+        let (status, body) = verify(VerifySignatureLayer::new(b"key"), req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_requests() {
+        let signed = || async { sign(SignRequestLayer::new(b"key"), request()).await };
+
+        let (status, _) = verify(VerifySignatureLayer::new(b"other"), signed().await).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (parts, _) = signed().await.into_parts();
+        let req = Request::from_parts(parts, Body::from("tampered"));
+        let (status, _) = verify(VerifySignatureLayer::new(b"key"), req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let mut req = signed().await;
+        *req.uri_mut() = "/items?page=3".parse().unwrap();
+        let (status, _) = verify(VerifySignatureLayer::new(b"key"), req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let mut req = signed().await;
+        req.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        let (status, _) = verify(VerifySignatureLayer::new(b"key"), req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let mut req = request();
+        req.headers_mut()
+            .insert("x-signature", HeaderValue::from_static("t=1,s=AAAA"));
+        let (status, _) = verify(VerifySignatureLayer::new(b"key"), req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = verify(VerifySignatureLayer::new(b"key"), request()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn signed_headers() {
+        let headers = [header::CONTENT_TYPE, HeaderName::from_static("x-tenant")];
+        let mut req = sign(
+            SignRequestLayer::new(b"key").signed_headers(headers.clone()),
+            request(),
+        )
+        .await;
+        req.headers_mut()
+            .insert("x-tenant", HeaderValue::from_static("b"));
+
+        // the change is only noticed if the header is signed
+        let (status, _) = verify(
+            VerifySignatureLayer::new(b"key").signed_headers(headers),
+            req,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn canonical_headers_are_unambiguous() {
+        let mut config = Config::new(b"key");
+        config.signed_headers = Arc::new([HeaderName::from_static("x-a")]);
+        let mac = |values: &[&'static str]| {
+            let mut req = Request::new(());
+            for value in values {
+                req.headers_mut()
+                    .append("x-a", HeaderValue::from_static(value));
+            }
+            let (parts, ()) = req.into_parts();
+            config.mac(&parts, 0, &Bytes::new()).finalize().into_bytes()
+        };
+
+        assert_ne!(mac(&["a,b"]), mac(&["a", "b"]));
+        assert_ne!(mac(&[]), mac(&[""]));
+        assert_eq!(mac(&[" a "]), mac(&["a"]));
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_requests() {
+        // a request signed ten minutes ago
+        let stale = || async {
+            let config = Config::new(b"key");
+            let (mut parts, body) = request().into_parts();
+            let body = hyper::body::to_bytes(body).await.unwrap();
+            let timestamp = now() - 600;
+            let signature = config.mac(&parts, timestamp, &body).finalize().into_bytes();
+            let value = format!("t={},s={}", timestamp, STANDARD.encode(signature));
+            parts
+                .headers
+                .insert("x-signature", HeaderValue::from_str(&value).unwrap());
+            Request::from_parts(parts, Body::from(body))
+        };
+
+        let layer = VerifySignatureLayer::new(b"key");
+        let (status, _) = verify(layer.clone(), stale().await).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let layer = layer.max_age(Duration::from_secs(900));
+        let (status, _) = verify(layer, stale().await).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_large_bodies() {
+        let req = sign(SignRequestLayer::new(b"key"), request()).await;
+        let (status, _) = verify(VerifySignatureLayer::new(b"key").max_body_size(4), req).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
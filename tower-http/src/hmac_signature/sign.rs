@@ -0,0 +1,234 @@
+use super::{now, Config, SignedBody};
+use crate::{buffer::Buffer, ready::take_ready, BoxError};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::ready;
+use hmac::Mac;
+use http::{request::Parts, HeaderName, HeaderValue, Request};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies [`SignRequest`] which signs requests with an HMAC.
+///
+/// See the [module docs](crate::hmac_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct SignRequestLayer {
+    config: Config,
+}
+
+impl SignRequestLayer {
+    /// Create a new `SignRequestLayer` signing requests with `key`.
+    pub fn new<K>(key: K) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        Self {
+            config: Config::new(key.as_ref()),
+        }
+    }
+
+    /// Set the header the signature is sent in.
+    ///
+    /// Defaults to `X-Signature`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.config.header = header;
+        self
+    }
+
+    /// Set the headers that are signed.
+    ///
+    /// Defaults to `Content-Type`.
+    pub fn signed_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.config.signed_headers = headers.into_iter().collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for SignRequestLayer {
+    type Service = SignRequest<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SignRequest {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that signs requests with an HMAC.
+///
+/// See the [module docs](crate::hmac_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct SignRequest<S> {
+    inner: S,
+    config: Config,
+}
+
+impl<S> SignRequest<S> {
+    /// Create a new `SignRequest` signing requests with `key`.
+    pub fn new<K>(inner: S, key: K) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        SignRequestLayer::new(key).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SignRequest` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<K>(key: K) -> SignRequestLayer
+    where
+        K: AsRef<[u8]>,
+    {
+        SignRequestLayer::new(key)
+    }
+
+    /// Set the header the signature is sent in.
+    ///
+    /// See [`SignRequestLayer::header`] for more details.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.config.header = header;
+        self
+    }
+
+    /// Set the headers that are signed.
+    ///
+    /// See [`SignRequestLayer::signed_headers`] for more details.
+    pub fn signed_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.config.signed_headers = headers.into_iter().collect();
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for SignRequest<S>
+where
+    S: Service<Request<SignedBody>> + Clone,
+    S::Error: Into<BoxError>,
+    ReqBody: Body,
+    ReqBody::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (parts, body) = req.into_parts();
+        ResponseFuture {
+            state: State::Buffering {
+                // the whole body is signed
+                buffer: Buffer::new(body, usize::MAX),
+                parts: Some(parts),
+                service: Some(take_ready(&mut self.inner)),
+            },
+            config: self.config.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`SignRequest`].
+    pub struct ResponseFuture<S, B>
+    where
+        S: Service<Request<SignedBody>>,
+    {
+        #[pin]
+        state: State<S, B, S::Future>,
+        config: Config,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, B, F> {
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, B> Future for ResponseFuture<S, B>
+where
+    S: Service<Request<SignedBody>>,
+    S::Error: Into<BoxError>,
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<S::Response, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    service,
+                } => match ready!(buffer.poll(cx)) {
+                    Ok((buffered, _)) => {
+                        let mut parts = parts.take().expect("future polled after completion");
+                        let data = buffered.data;
+                        let timestamp = now();
+                        let signature = this.config.mac(&parts, timestamp, &data).finalize();
+                        let value = format!(
+                            "t={},s={}",
+                            timestamp,
+                            STANDARD.encode(signature.into_bytes())
+                        );
+                        parts.headers.insert(
+                            this.config.header.clone(),
+                            HeaderValue::try_from(value).expect("signature is a valid header"),
+                        );
+
+                        let mut service = service.take().expect("future polled after completion");
+                        let req = Request::from_parts(parts, SignedBody::new(data));
+                        State::Called {
+                            future: service.call(req),
+                        }
+                    }
+                    Err(err) => return Poll::Ready(Err(err.into())),
+                },
+                StateProj::Called { future } => return future.poll(cx).map_err(Into::into),
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<SignedBody>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("config", &self.config)
+            .finish()
+    }
+}
@@ -0,0 +1,334 @@
+use super::{now, Config, SignedBody};
+use crate::{
+    buffer::{content_length_exceeds, rejection, Buffer},
+    ready::take_ready,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::ready;
+use hmac::Mac;
+use http::{request::Parts, HeaderMap, HeaderName, Request, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Layer that applies [`VerifySignature`] which rejects requests without a valid HMAC signature.
+///
+/// See the [module docs](crate::hmac_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct VerifySignatureLayer {
+    config: Config,
+    max_age: Duration,
+    max_body_size: usize,
+}
+
+impl VerifySignatureLayer {
+    /// Create a new `VerifySignatureLayer` verifying signatures with `key`.
+    pub fn new<K>(key: K) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        Self {
+            config: Config::new(key.as_ref()),
+            max_age: DEFAULT_MAX_AGE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the header the signature is read from.
+    ///
+    /// Defaults to `X-Signature`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.config.header = header;
+        self
+    }
+
+    /// Set the headers that are signed.
+    ///
+    /// Defaults to `Content-Type`.
+    pub fn signed_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.config.signed_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Set how far the time a request was signed at may be from the current time, in either
+    /// direction to allow for clock skew.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Set the size of the largest request body that is buffered to verify its digest.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for VerifySignatureLayer {
+    type Service = VerifySignature<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifySignature {
+            inner,
+            config: self.config.clone(),
+            max_age: self.max_age,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+/// Middleware that rejects requests without a valid HMAC signature.
+///
+/// See the [module docs](crate::hmac_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct VerifySignature<S> {
+    inner: S,
+    config: Config,
+    max_age: Duration,
+    max_body_size: usize,
+}
+
+impl<S> VerifySignature<S> {
+    /// Create a new `VerifySignature` verifying signatures with `key`.
+    pub fn new<K>(inner: S, key: K) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        VerifySignatureLayer::new(key).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `VerifySignature` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<K>(key: K) -> VerifySignatureLayer
+    where
+        K: AsRef<[u8]>,
+    {
+        VerifySignatureLayer::new(key)
+    }
+
+    /// Set the header the signature is read from.
+    ///
+    /// See [`VerifySignatureLayer::header`] for more details.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.config.header = header;
+        self
+    }
+
+    /// Set the headers that are signed.
+    ///
+    /// See [`VerifySignatureLayer::signed_headers`] for more details.
+    pub fn signed_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.config.signed_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Set how far the time a request was signed at may be from the current time.
+    ///
+    /// See [`VerifySignatureLayer::max_age`] for more details.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Set the size of the largest request body that is buffered to verify its digest.
+    ///
+    /// See [`VerifySignatureLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+}
+
+/// Parse a signature header of the form `t=<timestamp>,s=<base64 signature>`.
+fn parse_signature(headers: &HeaderMap, name: &HeaderName) -> Option<(u64, Vec<u8>)> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let mut timestamp = None;
+    let mut signature = None;
+    for param in value.split(',') {
+        match param.trim().split_once('=')? {
+            ("t", value) => timestamp = Some(value.parse().ok()?),
+            ("s", value) => signature = Some(STANDARD.decode(value).ok()?),
+            _ => {}
+        }
+    }
+    Some((timestamp?, signature?))
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for VerifySignature<S>
+where
+    S: Service<Request<SignedBody>, Response = Response<ResBody>> + Clone,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (timestamp, signature) = match parse_signature(req.headers(), &self.config.header) {
+            Some((timestamp, signature)) if now().abs_diff(timestamp) <= self.max_age.as_secs() => {
+                (timestamp, signature)
+            }
+            _ => return ResponseFuture::rejected(StatusCode::UNAUTHORIZED),
+        };
+
+        if content_length_exceeds(req.headers(), self.max_body_size) {
+            return ResponseFuture::rejected(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let (parts, body) = req.into_parts();
+        ResponseFuture {
+            state: State::Buffering {
+                buffer: Buffer::new(body, self.max_body_size),
+                parts: Some(parts),
+                timestamp,
+                signature,
+                service: Some(take_ready(&mut self.inner)),
+            },
+            config: Some(self.config.clone()),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`VerifySignature`].
+    pub struct ResponseFuture<S, B>
+    where
+        S: Service<Request<SignedBody>>,
+    {
+        #[pin]
+        state: State<S, B, S::Future>,
+        config: Option<Config>,
+    }
+}
+
+impl<S, B> ResponseFuture<S, B>
+where
+    S: Service<Request<SignedBody>>,
+{
+    fn rejected(status: StatusCode) -> Self {
+        Self {
+            state: State::Rejected { status },
+            config: None,
+        }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, B, F> {
+        Rejected {
+            status: StatusCode,
+        },
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            timestamp: u64,
+            signature: Vec<u8>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<S, B, ResBody> Future for ResponseFuture<S, B>
+where
+    S: Service<Request<SignedBody>, Response = Response<ResBody>>,
+    B: Body,
+    ResBody: Default,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Rejected { status } => return Poll::Ready(Ok(rejection(*status))),
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    timestamp,
+                    signature,
+                    service,
+                } => match ready!(buffer.poll(cx)) {
+                    Ok((buffered, _)) if buffered.complete => {
+                        let parts = parts.take().expect("future polled after completion");
+                        let config = this
+                            .config
+                            .as_ref()
+                            .expect("future polled after completion");
+                        let valid = config
+                            .mac(&parts, *timestamp, &buffered.data)
+                            .verify_slice(signature)
+                            .is_ok();
+
+                        if valid {
+                            let mut service =
+                                service.take().expect("future polled after completion");
+                            let req = Request::from_parts(parts, SignedBody::new(buffered.data));
+                            State::Called {
+                                future: service.call(req),
+                            }
+                        } else {
+                            State::Rejected {
+                                status: StatusCode::UNAUTHORIZED,
+                            }
+                        }
+                    }
+                    Ok(_) => State::Rejected {
+                        status: StatusCode::PAYLOAD_TOO_LARGE,
+                    },
+                    Err(_) => State::Rejected {
+                        status: StatusCode::BAD_REQUEST,
+                    },
+                },
+                StateProj::Called { future } => return future.poll(cx),
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<SignedBody>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("config", &self.config)
+            .finish()
+    }
+}
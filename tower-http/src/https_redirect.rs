@@ -0,0 +1,504 @@
+//! Middleware that redirects plain HTTP requests to HTTPS.
+//!
+//! [`HttpsRedirect`] responds to requests made over plain HTTP with a redirect to the same host,
+//! path, and query over HTTPS. Requests made over HTTPS are passed on to the inner service.
+//!
+//! Whether a request was made over plain HTTP is decided by:
+//!
+//! 1. The `X-Forwarded-Proto` header, if [`HttpsRedirectLayer::trust_forwarded_proto`] is
+//!    enabled. Only enable this behind a proxy that sets the header, since clients can send
+//!    anything.
+//! 2. The scheme of the request URI, if it has one.
+//! 3. Otherwise [`HttpsRedirectLayer::assume_http`], which defaults to `true`. Servers usually
+//!    don't see the scheme in the request URI, and this middleware is usually only applied to
+//!    the listener accepting plain HTTP.
+//!
+//! Some requests have to be served over plain HTTP, such as ACME HTTP-01 challenges used to
+//! obtain certificates. Those paths can be exempted with [`HttpsRedirectLayer::exempt_prefix`] or
+//! [`HttpsRedirectLayer::exempt`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::https_redirect::HttpsRedirectLayer;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // only ACME challenges get here
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(HttpsRedirectLayer::new().exempt_prefix("/.well-known/acme-challenge/"))
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/docs?page=2")
+//!     .header(header::HOST, "example.com")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+//! assert_eq!(response.headers()[header::LOCATION], "https://example.com/docs?page=2");
+//!
+//! let request = Request::get("/.well-known/acme-challenge/token")
+//!     .header(header::HOST, "example.com")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{
+    header::{self, HeaderName},
+    uri::Authority,
+    HeaderMap, HeaderValue, Request, Response, StatusCode, Uri,
+};
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+type Exemption = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+struct Config {
+    status_code: StatusCode,
+    trust_forwarded_proto: bool,
+    assume_http: bool,
+    https_port: Option<u16>,
+    hsts: Option<HeaderValue>,
+    exemptions: Vec<Exemption>,
+}
+
+impl Config {
+    fn is_plain_http(&self, uri: &Uri, headers: &HeaderMap) -> bool {
+        if self.trust_forwarded_proto {
+            // proxies append to the header, so the last value was set by the nearest proxy while
+            // earlier ones may have been sent by the client
+            let proto = headers
+                .get_all(X_FORWARDED_PROTO)
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map(str::trim);
+            if let Some(proto) = proto {
+                return proto.eq_ignore_ascii_case("http");
+            }
+        }
+
+        match uri.scheme_str() {
+            Some(scheme) => scheme.eq_ignore_ascii_case("http"),
+            None => self.assume_http,
+        }
+    }
+
+    fn is_exempt(&self, path: &str) -> bool {
+        self.exemptions.iter().any(|exempt| exempt(path))
+    }
+
+    fn location(&self, uri: &Uri, headers: &HeaderMap) -> Option<HeaderValue> {
+        // requests to servers usually only have the authority in the `Host` header
+        let authority = uri.authority().cloned().or_else(|| {
+            headers
+                .get(header::HOST)
+                .and_then(|host| Authority::try_from(host.as_bytes()).ok())
+        })?;
+
+        let mut location = format!("https://{}", authority.host());
+        if let Some(port) = self.https_port.filter(|&port| port != 443) {
+            location.push_str(&format!(":{}", port));
+        }
+        location.push_str(
+            uri.path_and_query()
+                .map_or_else(|| uri.path(), |path_and_query| path_and_query.as_str()),
+        );
+
+        HeaderValue::try_from(location).ok()
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("status_code", &self.status_code)
+            .field("trust_forwarded_proto", &self.trust_forwarded_proto)
+            .field("assume_http", &self.assume_http)
+            .field("https_port", &self.https_port)
+            .field("hsts", &self.hsts)
+            .field("exemptions", &self.exemptions.len())
+            .finish()
+    }
+}
+
+/// Layer that applies [`HttpsRedirect`] which redirects plain HTTP requests to HTTPS.
+///
+/// See the [module docs](crate::https_redirect) for an example.
+#[derive(Debug, Clone)]
+pub struct HttpsRedirectLayer {
+    config: Arc<Config>,
+}
+
+impl Default for HttpsRedirectLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpsRedirectLayer {
+    /// Create a new `HttpsRedirectLayer`.
+    pub fn new() -> Self {
+        Self {
+            config: Arc::new(Config {
+                status_code: StatusCode::PERMANENT_REDIRECT,
+                trust_forwarded_proto: false,
+                assume_http: true,
+                https_port: None,
+                hsts: None,
+                exemptions: Vec::new(),
+            }),
+        }
+    }
+
+    /// Set the status code of redirects.
+    ///
+    /// Defaults to `308 Permanent Redirect`, which makes clients repeat the request with the same
+    /// method and body. `301 Moved Permanently` is understood by older clients, but they may
+    /// change the method to `GET`.
+    ///
+    /// # Panics
+    ///
+    /// If `status_code` isn't a redirection status code (3xx).
+    pub fn status_code(mut self, status_code: StatusCode) -> Self {
+        assert!(
+            status_code.is_redirection(),
+            "not a redirection status code"
+        );
+        Arc::make_mut(&mut self.config).status_code = status_code;
+        self
+    }
+
+    /// Use the `X-Forwarded-Proto` header, if present, to decide whether a request was made over
+    /// plain HTTP.
+    ///
+    /// Only the last value is used, which was set by the nearest proxy.
+    ///
+    /// Defaults to `false`.
+    pub fn trust_forwarded_proto(mut self, trust: bool) -> Self {
+        Arc::make_mut(&mut self.config).trust_forwarded_proto = trust;
+        self
+    }
+
+    /// Treat requests whose URI doesn't have a scheme as plain HTTP, unless `X-Forwarded-Proto`
+    /// is trusted and present.
+    ///
+    /// Defaults to `true`.
+    pub fn assume_http(mut self, assume_http: bool) -> Self {
+        Arc::make_mut(&mut self.config).assume_http = assume_http;
+        self
+    }
+
+    /// Set the port of redirect locations. Any port of the request is dropped.
+    ///
+    /// Defaults to none, which redirects to the default HTTPS port.
+    pub fn https_port(mut self, port: u16) -> Self {
+        Arc::make_mut(&mut self.config).https_port = Some(port);
+        self
+    }
+
+    /// Set a `Strict-Transport-Security` header on redirects.
+    ///
+    /// Browsers ignore the header on plain HTTP responses, so it mostly matters for clients
+    /// reaching a TLS-terminating proxy. The HTTPS service should send it as well, for example
+    /// with [`SetResponseHeader`](crate::set_header::SetResponseHeader).
+    ///
+    /// Defaults to none.
+    pub fn hsts(mut self, value: HeaderValue) -> Self {
+        Arc::make_mut(&mut self.config).hsts = Some(value);
+        self
+    }
+
+    /// Pass requests whose path starts with `prefix` on to the inner service instead of
+    /// redirecting them, such as `/.well-known/acme-challenge/`.
+    pub fn exempt_prefix(self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.exempt(move |path| path.starts_with(&prefix))
+    }
+
+    /// Pass requests whose path matches `predicate` on to the inner service instead of
+    /// redirecting them.
+    pub fn exempt<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config)
+            .exemptions
+            .push(Arc::new(predicate));
+        self
+    }
+}
+
+impl<S> Layer<S> for HttpsRedirectLayer {
+    type Service = HttpsRedirect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpsRedirect {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that redirects plain HTTP requests to HTTPS.
+///
+/// See the [module docs](crate::https_redirect) for an example.
+#[derive(Debug, Clone)]
+pub struct HttpsRedirect<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> HttpsRedirect<S> {
+    /// Create a new `HttpsRedirect`.
+    pub fn new(inner: S) -> Self {
+        HttpsRedirectLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `HttpsRedirect` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> HttpsRedirectLayer {
+        HttpsRedirectLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for HttpsRedirect<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let config = &self.config;
+        if !config.is_plain_http(req.uri(), req.headers()) || config.is_exempt(req.uri().path()) {
+            return ResponseFuture {
+                kind: Kind::Future {
+                    future: self.inner.call(req),
+                },
+            };
+        }
+
+        let mut res = Response::new(ResBody::default());
+        match config.location(req.uri(), req.headers()) {
+            Some(location) => {
+                *res.status_mut() = config.status_code;
+                res.headers_mut().insert(header::LOCATION, location);
+                if let Some(hsts) = &config.hsts {
+                    res.headers_mut()
+                        .insert(header::STRICT_TRANSPORT_SECURITY, hsts.clone());
+                }
+            }
+            // without a host there is nowhere to redirect to
+            None => *res.status_mut() = StatusCode::BAD_REQUEST,
+        }
+        ResponseFuture {
+            kind: Kind::Redirect {
+                response: Some(res),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`HttpsRedirect`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        Redirect {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future } => future.poll(cx),
+            KindProj::Redirect { response } => {
+                let response = response.take().expect("future polled after completion");
+                Poll::Ready(Ok(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(_req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    async fn call(
+        layer: &HttpsRedirectLayer,
+        uri: &str,
+        headers: &[(&'static str, &str)],
+    ) -> Response<Body> {
+        let mut req = Request::get(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        layer.layer(service_fn(handle)).oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn redirects_to_https() {
+        let layer = HttpsRedirectLayer::new();
+
+        let res = call(&layer, "/a?b=c", &[("host", "example.com:8080")]).await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "https://example.com/a?b=c");
+        assert!(!res
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        let res = call(&layer, "http://example.com/a", &[]).await;
+        assert_eq!(res.headers()[header::LOCATION], "https://example.com/a");
+
+        let res = call(&layer, "https://example.com/a", &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn status_port_and_hsts() {
+        let layer = HttpsRedirectLayer::new()
+            .status_code(StatusCode::MOVED_PERMANENTLY)
+            .https_port(8443)
+            .hsts(HeaderValue::from_static("max-age=31536000"));
+
+        let res = call(&layer, "/", &[("host", "example.com:8080")]).await;
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "https://example.com:8443/");
+        assert_eq!(
+            res.headers()[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000"
+        );
+    }
+
+    #[tokio::test]
+    async fn forwarded_proto() {
+        let layer = HttpsRedirectLayer::new();
+        let res = call(
+            &layer,
+            "/",
+            &[("host", "example.com"), ("x-forwarded-proto", "https")],
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+
+        let layer = HttpsRedirectLayer::new()
+            .trust_forwarded_proto(true)
+            .assume_http(false);
+        let res = call(
+            &layer,
+            "/",
+            &[("host", "example.com"), ("x-forwarded-proto", "https")],
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = call(
+            &layer,
+            "/",
+            &[
+                ("host", "example.com"),
+                ("x-forwarded-proto", "https, HTTP"),
+            ],
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+
+        // values sent by the client are ignored
+        let res = call(
+            &layer,
+            "/",
+            &[
+                ("host", "example.com"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-proto", "http"),
+            ],
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+
+        let res = call(&layer, "/", &[("host", "example.com")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn exemptions() {
+        let layer = HttpsRedirectLayer::new()
+            .exempt_prefix("/.well-known/acme-challenge/")
+            .exempt(|path| path == "/health");
+
+        for path in ["/.well-known/acme-challenge/token", "/health"] {
+            let res = call(&layer, path, &[("host", "example.com")]).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = call(&layer, "/.well-known/other", &[("host", "example.com")]).await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn missing_host() {
+        let res = call(&HttpsRedirectLayer::new(), "/", &[]).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(!res.headers().contains_key(header::LOCATION));
+    }
+}
@@ -0,0 +1,320 @@
+use super::{IdempotencyStore, StoredResponse};
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+use http::{HeaderMap, Response, StatusCode};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+pin_project! {
+    /// Request body for [`Idempotency`].
+    ///
+    /// The bodies of requests with an idempotency key have been buffered to fingerprint them.
+    ///
+    /// [`Idempotency`]: super::Idempotency
+    pub struct RequestBody<B> {
+        #[pin]
+        kind: RequestBodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = RequestBodyKindProj]
+    enum RequestBodyKind<B> {
+        Buffered {
+            data: Option<Bytes>,
+        },
+        Body {
+            #[pin]
+            body: B,
+        },
+    }
+}
+
+impl<B> RequestBody<B> {
+    pub(super) fn buffered(data: Bytes) -> Self {
+        Self {
+            kind: RequestBodyKind::Buffered {
+                data: Some(data).filter(|data| !data.is_empty()),
+            },
+        }
+    }
+
+    pub(super) fn new(body: B) -> Self {
+        Self {
+            kind: RequestBodyKind::Body { body },
+        }
+    }
+}
+
+impl<B> Body for RequestBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            RequestBodyKindProj::Buffered { data } => Poll::Ready(data.take().map(Ok)),
+            RequestBodyKindProj::Body { body } => Poll::Ready(
+                ready!(body.poll_data(cx))
+                    .map(|data| data.map(|mut data| data.copy_to_bytes(data.remaining()))),
+            ),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            RequestBodyKindProj::Buffered { .. } => Poll::Ready(Ok(None)),
+            RequestBodyKindProj::Body { body } => body.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            RequestBodyKind::Buffered { data } => data.is_none(),
+            RequestBodyKind::Body { body } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            RequestBodyKind::Buffered { data } => {
+                SizeHint::with_exact(data.as_ref().map_or(0, |data| data.len() as u64))
+            }
+            RequestBodyKind::Body { body } => body.size_hint(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response body for [`Idempotency`].
+    ///
+    /// [`Idempotency`]: super::Idempotency
+    pub struct ResponseBody<B, T>
+    where
+        T: IdempotencyStore,
+    {
+        #[pin]
+        kind: ResponseBodyKind<B, T>,
+    }
+}
+
+pin_project! {
+    #[project = ResponseBodyKindProj]
+    enum ResponseBodyKind<B, T>
+    where
+        T: IdempotencyStore,
+    {
+        Stored {
+            data: Option<Bytes>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            recorder: Option<Recorder<T>>,
+        },
+    }
+}
+
+impl<B, T> ResponseBody<B, T>
+where
+    T: IdempotencyStore,
+{
+    pub(super) fn stored(data: Bytes) -> Self {
+        Self {
+            kind: ResponseBodyKind::Stored {
+                data: Some(data).filter(|data| !data.is_empty()),
+            },
+        }
+    }
+
+    pub(super) fn new(body: B, recorder: Option<Recorder<T>>) -> Self {
+        Self {
+            kind: ResponseBodyKind::Body { body, recorder },
+        }
+    }
+}
+
+/// An idempotency key reserved for a request, which is released unless a response is stored.
+pub(super) struct Reservation<T>
+where
+    T: IdempotencyStore,
+{
+    store: T,
+    // taken once the response has been stored or the key turned out to be in use
+    key: Option<String>,
+}
+
+impl<T> Reservation<T>
+where
+    T: IdempotencyStore,
+{
+    pub(super) fn new(store: T, key: String) -> Self {
+        Self {
+            store,
+            key: Some(key),
+        }
+    }
+
+    /// Give up the reservation without releasing the key, which is used by another request.
+    pub(super) fn disarm(mut self) {
+        self.key = None;
+    }
+
+    fn complete(mut self, response: StoredResponse, ttl: Duration) {
+        if let Some(key) = self.key.take() {
+            self.store.complete(&key, response, ttl);
+        }
+    }
+}
+
+impl<T> Drop for Reservation<T>
+where
+    T: IdempotencyStore,
+{
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.release(&key);
+        }
+    }
+}
+
+/// Records the response to a request with an idempotency key so it can be stored once it is
+/// complete.
+///
+/// The key is released if the response can't be stored.
+pub(super) struct Recorder<T>
+where
+    T: IdempotencyStore,
+{
+    reservation: Reservation<T>,
+    ttl: Duration,
+    status: StatusCode,
+    headers: HeaderMap,
+    chunks: Vec<Bytes>,
+    size: usize,
+    max_size: usize,
+}
+
+impl<T> Recorder<T>
+where
+    T: IdempotencyStore,
+{
+    pub(super) fn new<B>(
+        reservation: Reservation<T>,
+        ttl: Duration,
+        res: &Response<B>,
+        max_size: usize,
+    ) -> Self {
+        Self {
+            reservation,
+            ttl,
+            status: res.status(),
+            headers: res.headers().clone(),
+            chunks: Vec::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Record a chunk, returning `false` once the body has become too large to be stored.
+    fn record(&mut self, chunk: &Bytes) -> bool {
+        self.size += chunk.len();
+        self.chunks.push(chunk.clone());
+        self.size <= self.max_size
+    }
+
+    pub(super) fn finish(self) {
+        let mut body = Vec::with_capacity(self.size);
+        for chunk in &self.chunks {
+            body.extend_from_slice(chunk);
+        }
+        let response = StoredResponse::new(self.status, self.headers, body.into());
+        self.reservation.complete(response, self.ttl);
+    }
+}
+
+impl<B, T> Body for ResponseBody<B, T>
+where
+    B: Body,
+    T: IdempotencyStore,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            ResponseBodyKindProj::Stored { data } => Poll::Ready(data.take().map(Ok)),
+            ResponseBodyKindProj::Body { mut body, recorder } => {
+                match ready!(body.as_mut().poll_data(cx)) {
+                    Some(Ok(mut data)) => {
+                        let data = data.copy_to_bytes(data.remaining());
+                        let storable = recorder.as_mut().map(|recorder| recorder.record(&data));
+                        if storable == Some(false) {
+                            recorder.take();
+                        }
+                        // hyper stops polling once the body says it has ended, so it might never
+                        // see the final `None`
+                        if body.is_end_stream() {
+                            if let Some(recorder) = recorder.take() {
+                                recorder.finish();
+                            }
+                        }
+                        Poll::Ready(Some(Ok(data)))
+                    }
+                    Some(Err(err)) => {
+                        recorder.take();
+                        Poll::Ready(Some(Err(err)))
+                    }
+                    None => {
+                        if let Some(recorder) = recorder.take() {
+                            recorder.finish();
+                        }
+                        Poll::Ready(None)
+                    }
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            ResponseBodyKindProj::Stored { .. } => Poll::Ready(Ok(None)),
+            ResponseBodyKindProj::Body { body, .. } => body.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            ResponseBodyKind::Stored { data } => data.is_none(),
+            ResponseBodyKind::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            ResponseBodyKind::Stored { data } => {
+                SizeHint::with_exact(data.as_ref().map_or(0, |data| data.len() as u64))
+            }
+            ResponseBodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
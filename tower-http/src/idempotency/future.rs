@@ -0,0 +1,240 @@
+use super::{
+    body::{Recorder, Reservation},
+    service::Config,
+    IdempotencyStore, Lookup, RequestBody, ResponseBody,
+};
+use crate::buffer::Buffer;
+use bytes::Bytes;
+use futures_util::ready;
+use http::{request::Parts, HeaderName, HeaderValue, Request, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    future::Future,
+    hash::{Hash, Hasher},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future for [`Idempotency`].
+    ///
+    /// [`Idempotency`]: super::Idempotency
+    pub struct ResponseFuture<S, B, T>
+    where
+        S: Service<Request<RequestBody<B>>>,
+        T: IdempotencyStore,
+    {
+        #[pin]
+        state: State<S, B, T, S::Future, T::Future>,
+        config: Config,
+        store: T,
+    }
+}
+
+impl<S, B, T> ResponseFuture<S, B, T>
+where
+    S: Service<Request<RequestBody<B>>>,
+    T: IdempotencyStore,
+{
+    pub(super) fn new(
+        state: State<S, B, T, S::Future, T::Future>,
+        config: Config,
+        store: T,
+    ) -> Self {
+        Self {
+            state,
+            config,
+            store,
+        }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    pub(super) enum State<S, B, T, F, L>
+    where
+        T: IdempotencyStore,
+    {
+        Rejected {
+            status: StatusCode,
+        },
+        Buffering {
+            #[pin]
+            buffer: Buffer<B>,
+            parts: Option<Parts>,
+            key: Option<String>,
+            service: Option<S>,
+        },
+        LookingUp {
+            #[pin]
+            lookup: L,
+            request: Option<Request<RequestBody<B>>>,
+            reservation: Option<Reservation<T>>,
+            fingerprint: u64,
+            service: Option<S>,
+        },
+        Ready {
+            request: Option<Request<RequestBody<B>>>,
+            reservation: Option<Reservation<T>>,
+            service: Option<S>,
+        },
+        Called {
+            #[pin]
+            future: F,
+            reservation: Option<Reservation<T>>,
+        },
+    }
+}
+
+impl<S, B, T, ResBody> Future for ResponseFuture<S, B, T>
+where
+    S: Service<Request<RequestBody<B>>, Response = Response<ResBody>>,
+    B: Body,
+    T: IdempotencyStore + Clone,
+    ResBody: Body,
+{
+    type Output = Result<Response<ResponseBody<ResBody, T>>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Rejected { status } => return Poll::Ready(Ok(rejection(*status))),
+                StateProj::Buffering {
+                    buffer,
+                    parts,
+                    key,
+                    service,
+                } => match ready!(buffer.poll(cx)) {
+                    Ok((buffered, _)) if buffered.complete => {
+                        let parts = parts.take().expect("future polled after completion");
+                        let data = buffered.data;
+                        let fingerprint = fingerprint(&parts, &data);
+                        let key = key.take().expect("future polled after completion");
+                        let lookup = this.store.begin(&key, fingerprint, this.config.ttl);
+                        State::LookingUp {
+                            lookup,
+                            request: Some(Request::from_parts(parts, RequestBody::buffered(data))),
+                            // released if the future is dropped before the response is stored
+                            reservation: Some(Reservation::new(this.store.clone(), key)),
+                            fingerprint,
+                            service: service.take(),
+                        }
+                    }
+                    Ok(_) => return Poll::Ready(Ok(rejection(StatusCode::PAYLOAD_TOO_LARGE))),
+                    Err(_) => return Poll::Ready(Ok(rejection(StatusCode::BAD_REQUEST))),
+                },
+                StateProj::LookingUp {
+                    lookup,
+                    request,
+                    reservation,
+                    fingerprint,
+                    service,
+                } => {
+                    let lookup = ready!(lookup.poll(cx));
+                    if !matches!(lookup, Lookup::Started) {
+                        // the key belongs to another request
+                        if let Some(reservation) = reservation.take() {
+                            reservation.disarm();
+                        }
+                    }
+                    match lookup {
+                        Lookup::Started => State::Ready {
+                            request: request.take(),
+                            reservation: reservation.take(),
+                            service: service.take(),
+                        },
+                        Lookup::InProgress { fingerprint: other } if other == *fingerprint => {
+                            return Poll::Ready(Ok(rejection(StatusCode::CONFLICT)));
+                        }
+                        Lookup::Completed {
+                            fingerprint: other,
+                            response,
+                        } if other == *fingerprint => {
+                            let body = ResponseBody::stored(response.body().clone());
+                            let mut res = response.response(body);
+                            res.headers_mut().insert(
+                                HeaderName::from_static("idempotent-replayed"),
+                                HeaderValue::from_static("true"),
+                            );
+                            return Poll::Ready(Ok(res));
+                        }
+                        // the key has been used for a different request
+                        Lookup::InProgress { .. } | Lookup::Completed { .. } => {
+                            return Poll::Ready(Ok(rejection(StatusCode::UNPROCESSABLE_ENTITY)));
+                        }
+                    }
+                }
+                StateProj::Ready {
+                    request,
+                    reservation,
+                    service,
+                } => {
+                    let inner = service.as_mut().expect("future polled after completion");
+                    // dropping the reservation releases the key
+                    ready!(inner.poll_ready(cx))?;
+                    let request = request.take().expect("future polled after completion");
+                    State::Called {
+                        future: inner.call(request),
+                        reservation: reservation.take(),
+                    }
+                }
+                StateProj::Called {
+                    future,
+                    reservation,
+                } => {
+                    let res = ready!(future.poll(cx))?;
+                    let mut recorder = reservation.take().map(|reservation| {
+                        Recorder::new(
+                            reservation,
+                            this.config.ttl,
+                            &res,
+                            this.config.max_body_size,
+                        )
+                    });
+                    if res.body().is_end_stream() {
+                        // the body may never be polled
+                        if let Some(recorder) = recorder.take() {
+                            recorder.finish();
+                        }
+                    }
+                    return Poll::Ready(Ok(res.map(|body| ResponseBody::new(body, recorder))));
+                }
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B, T> fmt::Debug for ResponseFuture<S, B, T>
+where
+    S: Service<Request<RequestBody<B>>>,
+    T: IdempotencyStore,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+/// Identify a request by its method, URI and body, to tell retries apart from different requests
+/// reusing an idempotency key.
+fn fingerprint(parts: &Parts, body: &Bytes) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parts.method.as_str().hash(&mut hasher);
+    parts.uri.to_string().hash(&mut hasher);
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn rejection<B, T>(status: StatusCode) -> Response<ResponseBody<B, T>>
+where
+    T: IdempotencyStore,
+{
+    let mut res = Response::new(ResponseBody::stored(Bytes::new()));
+    *res.status_mut() = status;
+    res
+}
@@ -0,0 +1,93 @@
+use super::{service::Config, Idempotency, MemoryStore};
+use http::HeaderName;
+use std::time::Duration;
+use tower_layer::Layer;
+
+/// Layer that applies the [`Idempotency`] middleware, which replays the responses to requests
+/// with an `Idempotency-Key` header for their retries.
+///
+/// See the [module docs](crate::idempotency) for an example.
+#[derive(Clone, Debug)]
+pub struct IdempotencyLayer<T = MemoryStore> {
+    config: Config,
+    store: T,
+}
+
+impl IdempotencyLayer {
+    /// Create a new [`IdempotencyLayer`] keeping responses in a new [`MemoryStore`].
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            store: MemoryStore::new(),
+        }
+    }
+}
+
+impl Default for IdempotencyLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IdempotencyLayer<T> {
+    /// Set the [`IdempotencyStore`] that keeps the responses.
+    ///
+    /// [`IdempotencyStore`]: super::IdempotencyStore
+    pub fn store<NewT>(self, store: NewT) -> IdempotencyLayer<NewT> {
+        IdempotencyLayer {
+            config: self.config,
+            store,
+        }
+    }
+
+    /// Set how long responses are kept to be replayed.
+    ///
+    /// Defaults to 24 hours.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.ttl = ttl;
+        self
+    }
+
+    /// Set the header containing the idempotency key.
+    ///
+    /// Defaults to `Idempotency-Key`.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.config.header = name;
+        self
+    }
+
+    /// Also scope idempotency keys to the value of the given header.
+    ///
+    /// Responses are only replayed for requests with the same values of the scope headers as the
+    /// original request, so clients can't replay the responses of other clients by reusing their
+    /// keys. The keys are scoped to the `Authorization` and `Cookie` headers by default.
+    pub fn scope_header(mut self, name: HeaderName) -> Self {
+        self.config.scope_headers.push(name);
+        self
+    }
+
+    /// Set the maximum size of the request and response bodies of requests with an idempotency
+    /// key.
+    ///
+    /// Larger requests are rejected with `413 Payload Too Large`, and larger responses aren't
+    /// stored, so their key can be used again. Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+}
+
+impl<S, T> Layer<S> for IdempotencyLayer<T>
+where
+    T: Clone,
+{
+    type Service = Idempotency<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Idempotency {
+            inner,
+            config: self.config.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
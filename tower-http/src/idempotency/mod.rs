@@ -0,0 +1,284 @@
+//! Middleware that makes retries of `POST` and `PATCH` requests safe with idempotency keys.
+//!
+//! Clients that want to retry a non-idempotent request, for example after a timeout, send it
+//! with a unique key in the `Idempotency-Key` header. [`Idempotency`] stores the response to the
+//! first request with a key and replays it for every retry instead of calling the inner service
+//! again, as described in the IETF draft [The Idempotency-Key HTTP Header Field].
+//!
+//! Requests with a key are buffered to tell retries apart from different requests reusing the
+//! key, which are identified by their method, URI and body. The middleware responds with
+//!
+//! - `400 Bad Request` if the key is empty, longer than 255 characters or the body fails,
+//! - `409 Conflict` if the original request is still being processed,
+//! - `413 Payload Too Large` if the body is larger than [`IdempotencyLayer::max_body_size`],
+//! - `422 Unprocessable Entity` if the key has been used for a different request.
+//!
+//! Keys are scoped to the `Authorization` and `Cookie` headers of the request, so clients can't
+//! replay the responses to other clients, and more headers can be added with
+//! [`IdempotencyLayer::scope_header`].
+//!
+//! Replayed responses have an `Idempotent-Replayed: true` header. If the inner service fails or
+//! the response body fails, is too large, or isn't consumed, nothing is stored and the key can
+//! be used again. Response extensions and trailers aren't stored.
+//!
+//! Responses are kept in a [`MemoryStore`] by default. Services running on several machines can
+//! share them through their own [`IdempotencyStore`].
+//!
+//! [The Idempotency-Key HTTP Header Field]: https://datatracker.ietf.org/doc/draft-ietf-httpapi-idempotency-key-header/
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{
+//!     convert::Infallible,
+//!     sync::atomic::{AtomicUsize, Ordering},
+//!     time::Duration,
+//! };
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::idempotency::{IdempotencyLayer, RequestBody};
+//!
+//! static PAYMENTS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! async fn pay(_: Request<RequestBody<Body>>) -> Result<Response<Body>, Infallible> {
+//!     let id = PAYMENTS.fetch_add(1, Ordering::SeqCst);
+//!     let res = Response::builder()
+//!         .status(StatusCode::CREATED)
+//!         .body(Body::from(format!("payment {}", id)))
+//!         .unwrap();
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(IdempotencyLayer::new().ttl(Duration::from_secs(60 * 60)))
+//!     .service_fn(pay);
+//!
+//! for _ in 0..2 {
+//!     let request = Request::post("/payments")
+//!         .header("idempotency-key", "\"8e03978e-40d5-43e8-bc93-6894a57f9324\"")
+//!         .body(Body::from("amount=10"))?;
+//!     let response = service.ready().await?.call(request).await?;
+//!     assert_eq!(response.status(), StatusCode::CREATED);
+//!     let body = hyper::body::to_bytes(response.into_body()).await?;
+//!     assert_eq!(body, "payment 0");
+//! }
+//!
+//! // The handler only ran once.
+//! assert_eq!(PAYMENTS.load(Ordering::SeqCst), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod future;
+mod layer;
+mod service;
+mod store;
+
+pub use self::{
+    body::{RequestBody, ResponseBody},
+    future::ResponseFuture,
+    layer::IdempotencyLayer,
+    service::Idempotency,
+    store::{IdempotencyStore, Lookup, MemoryStore, StoredResponse},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{HeaderMap, HeaderName, Request, Response, StatusCode};
+    use http_body::Body as _;
+    use hyper::Body;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tower::{service_fn, BoxError, Layer, Service, ServiceExt};
+
+    /// A service that counts its calls and echoes the request body after `delay`.
+    fn service(
+        calls: Arc<AtomicUsize>,
+        delay: Duration,
+    ) -> impl Service<Request<RequestBody<Body>>, Response = Response<Body>, Error = BoxError> + Clone
+    {
+        service_fn(move |req: Request<RequestBody<Body>>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(delay).await;
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                let res = Response::builder()
+                    .status(StatusCode::CREATED)
+                    .header("x-call", call)
+                    .body(Body::from(body))
+                    .unwrap();
+                Ok(res)
+            }
+        })
+    }
+
+    async fn send<S, B>(svc: S, req: Request<Body>) -> (StatusCode, HeaderMap, Bytes)
+    where
+        S: Service<Request<Body>, Response = Response<B>>,
+        S::Error: std::fmt::Debug,
+        B: http_body::Body,
+        B::Error: std::fmt::Debug,
+    {
+        let (parts, body) = svc.oneshot(req).await.unwrap().into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        (parts.status, parts.headers, body)
+    }
+
+    fn post(key: &str, body: &'static str) -> Request<Body> {
+        Request::post("/")
+            .header("idempotency-key", key)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = IdempotencyLayer::new().layer(service(calls.clone(), Duration::ZERO));
+
+        let (status, headers, body) = send(svc.clone(), post("\"a\"", "hello")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(!headers.contains_key("idempotent-replayed"));
+        assert_eq!(body, "hello");
+
+        // quoted and unquoted keys are the same
+        let (status, headers, body) = send(svc.clone(), post("a", "hello")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(headers["idempotent-replayed"], "true");
+        assert_eq!(headers["x-call"], "0");
+        assert_eq!(body, "hello");
+
+        let (status, _, _) = send(svc.clone(), post("b", "hello")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_concurrent_and_mismatched_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = IdempotencyLayer::new().layer(service(calls.clone(), Duration::from_millis(50)));
+
+        let ((first, _, _), (duplicate, mismatch)) =
+            tokio::join!(send(svc.clone(), post("a", "hello")), async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let (duplicate, _, _) = send(svc.clone(), post("a", "hello")).await;
+                let (mismatch, _, _) = send(svc.clone(), post("a", "other")).await;
+                (duplicate, mismatch)
+            },);
+        assert_eq!(first, StatusCode::CREATED);
+        assert_eq!(duplicate, StatusCode::CONFLICT);
+        assert_eq!(mismatch, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, _, _) = send(svc.clone(), post("a", "other")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn scopes_keys_to_credentials() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = IdempotencyLayer::new()
+            .scope_header(HeaderName::from_static("x-tenant"))
+            .layer(service(calls.clone(), Duration::ZERO));
+
+        let with = |name: &'static str, value: &'static str| {
+            let mut req = post("a", "hello");
+            req.headers_mut().insert(name, value.parse().unwrap());
+            req
+        };
+
+        send(svc.clone(), with("authorization", "Bearer alice")).await;
+        let (_, headers, _) = send(svc.clone(), with("authorization", "Bearer alice")).await;
+        assert_eq!(headers["idempotent-replayed"], "true");
+
+        // the same key from other clients is a different key
+        let (_, headers, _) = send(svc.clone(), with("authorization", "Bearer mallory")).await;
+        assert!(!headers.contains_key("idempotent-replayed"));
+        let (_, headers, _) = send(svc.clone(), with("cookie", "session=alice")).await;
+        assert!(!headers.contains_key("idempotent-replayed"));
+        let (_, headers, _) = send(svc.clone(), with("x-tenant", "1")).await;
+        assert!(!headers.contains_key("idempotent-replayed"));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn passes_through_other_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = IdempotencyLayer::new()
+            .max_body_size(8)
+            .layer(service(calls.clone(), Duration::ZERO));
+
+        // requests without a key or with a safe method aren't stored
+        for _ in 0..2 {
+            let req = Request::post("/").body(Body::from("hello")).unwrap();
+            send(svc.clone(), req).await;
+            let req = Request::get("/")
+                .header("idempotency-key", "a")
+                .body(Body::empty())
+                .unwrap();
+            send(svc.clone(), req).await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        let (status, _, _) = send(svc.clone(), post("\"\"", "hello")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = send(svc.clone(), post(&"a".repeat(256), "hello")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = send(svc.clone(), post("a", "too large")).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn releases_keys_without_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = IdempotencyLayer::new().layer(service(calls.clone(), Duration::ZERO));
+
+        // the response body isn't consumed
+        svc.clone().oneshot(post("a", "hello")).await.unwrap();
+        let (_, headers, _) = send(svc.clone(), post("a", "hello")).await;
+        assert!(!headers.contains_key("idempotent-replayed"));
+
+        let failing =
+            IdempotencyLayer::new().layer(service_fn(|_: Request<RequestBody<Body>>| async {
+                Err::<Response<Body>, BoxError>("failed".into())
+            }));
+        assert!(failing.clone().oneshot(post("a", "hello")).await.is_err());
+        assert!(failing.oneshot(post("a", "hello")).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stores_responses_that_are_not_polled_to_the_end() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = IdempotencyLayer::new().layer(service(calls.clone(), Duration::ZERO));
+
+        // hyper stops polling a body once `is_end_stream` returns true
+        let mut body = svc
+            .clone()
+            .oneshot(post("a", "hello"))
+            .await
+            .unwrap()
+            .into_body();
+        while !body.is_end_stream() {
+            body.data().await.unwrap().unwrap();
+        }
+        drop(body);
+
+        let (_, headers, body) = send(svc, post("a", "hello")).await;
+        assert_eq!(headers["idempotent-replayed"], "true");
+        assert_eq!(body, "hello");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,191 @@
+use super::{
+    future::State, IdempotencyLayer, IdempotencyStore, MemoryStore, RequestBody, ResponseBody,
+    ResponseFuture,
+};
+use crate::{buffer::Buffer, ready::take_ready};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::Body;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The longest idempotency key that is accepted.
+const MAX_KEY_LEN: usize = 255;
+
+/// The settings of the middleware.
+#[derive(Clone, Debug)]
+pub(super) struct Config {
+    pub(super) header: HeaderName,
+    pub(super) scope_headers: Vec<HeaderName>,
+    pub(super) ttl: Duration,
+    pub(super) max_body_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            header: HeaderName::from_static("idempotency-key"),
+            scope_headers: vec![header::AUTHORIZATION, header::COOKIE],
+            ttl: Duration::from_secs(24 * 60 * 60),
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Middleware that replays the responses to `POST` and `PATCH` requests with an
+/// `Idempotency-Key` header for their retries.
+///
+/// See the [module docs](crate::idempotency) for an example.
+#[derive(Clone, Debug)]
+pub struct Idempotency<S, T = MemoryStore> {
+    pub(super) inner: S,
+    pub(super) config: Config,
+    pub(super) store: T,
+}
+
+impl<S> Idempotency<S> {
+    /// Create a new [`Idempotency`] keeping responses in a new [`MemoryStore`].
+    pub fn new(inner: S) -> Self {
+        IdempotencyLayer::new().layer(inner)
+    }
+
+    /// Returns a new [`Layer`] that wraps services with an `Idempotency` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> IdempotencyLayer {
+        IdempotencyLayer::new()
+    }
+}
+
+impl<S, T> Idempotency<S, T> {
+    define_inner_service_accessors!();
+
+    /// Set the [`IdempotencyStore`] that keeps the responses.
+    pub fn store<NewT>(self, store: NewT) -> Idempotency<S, NewT> {
+        Idempotency {
+            inner: self.inner,
+            config: self.config,
+            store,
+        }
+    }
+
+    /// Set how long responses are kept to be replayed.
+    ///
+    /// See [`IdempotencyLayer::ttl`] for more details.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.ttl = ttl;
+        self
+    }
+
+    /// Set the header containing the idempotency key.
+    ///
+    /// See [`IdempotencyLayer::header`] for more details.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.config.header = name;
+        self
+    }
+
+    /// Also scope idempotency keys to the value of the given header.
+    ///
+    /// See [`IdempotencyLayer::scope_header`] for more details.
+    pub fn scope_header(mut self, name: HeaderName) -> Self {
+        self.config.scope_headers.push(name);
+        self
+    }
+
+    /// Set the maximum size of the request and response bodies of requests with an idempotency
+    /// key.
+    ///
+    /// See [`IdempotencyLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for Idempotency<S, T>
+where
+    S: Service<Request<RequestBody<ReqBody>>, Response = Response<ResBody>> + Clone,
+    T: IdempotencyStore + Clone,
+    ReqBody: Body,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody, T>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody, T>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let applies = matches!(*req.method(), Method::POST | Method::PATCH);
+        let state = match req.headers().get(&self.config.header) {
+            Some(value) if applies => match parse_key(value) {
+                Some(key) => {
+                    let key = self.config.store_key(req.headers(), &key);
+                    let (parts, body) = req.into_parts();
+                    State::Buffering {
+                        buffer: Buffer::new(body, self.config.max_body_size),
+                        parts: Some(parts),
+                        key: Some(key),
+                        service: Some(take_ready(&mut self.inner)),
+                    }
+                }
+                None => State::Rejected {
+                    status: StatusCode::BAD_REQUEST,
+                },
+            },
+            _ => State::Called {
+                future: self.inner.call(req.map(RequestBody::new)),
+                reservation: None,
+            },
+        };
+        ResponseFuture::new(state, self.config.clone(), self.store.clone())
+    }
+}
+
+impl Config {
+    /// The key responses are stored under, which is the idempotency key prefixed with a digest
+    /// of the scope headers, so keys sent with different credentials don't collide.
+    fn store_key(&self, headers: &HeaderMap, key: &str) -> String {
+        let mut scope = Sha256::new();
+        for name in &self.scope_headers {
+            for value in headers.get_all(name) {
+                scope.update((value.len() as u64).to_be_bytes());
+                scope.update(value.as_bytes());
+            }
+            // separate the values of the headers
+            scope.update([0xff; 8]);
+        }
+
+        let mut store_key = String::with_capacity(64 + 1 + key.len());
+        for byte in scope.finalize() {
+            let _ = write!(store_key, "{:02x}", byte);
+        }
+        store_key.push(':');
+        store_key.push_str(key);
+        store_key
+    }
+}
+
+/// Parse an idempotency key, which the IETF draft defines as a quoted string.
+///
+/// Unquoted keys are accepted as well.
+fn parse_key(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?.trim();
+    let key = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    if key.is_empty() || key.len() > MAX_KEY_LEN || key.contains('"') {
+        return None;
+    }
+    Some(key.to_owned())
+}
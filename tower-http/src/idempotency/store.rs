@@ -0,0 +1,247 @@
+use crate::sync::lock_ignore_poison;
+use bytes::Bytes;
+use http::{HeaderMap, Response, StatusCode};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{self, Future, Ready},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A response stored to be replayed for retries of a request.
+#[derive(Clone, Debug)]
+pub struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl StoredResponse {
+    /// Create a new `StoredResponse`.
+    pub fn new(status: StatusCode, headers: HeaderMap, body: Bytes) -> Self {
+        Self {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    pub(super) fn response<B>(&self, body: B) -> Response<B> {
+        let mut res = Response::new(body);
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers.clone();
+        res
+    }
+}
+
+/// What an [`IdempotencyStore`] knows about an idempotency key.
+#[derive(Clone, Debug)]
+pub enum Lookup {
+    /// The key hasn't been used yet and is now reserved for the request.
+    Started,
+    /// A request with the key is still being processed.
+    InProgress {
+        /// The fingerprint of the request being processed.
+        fingerprint: u64,
+    },
+    /// A request with the key has been processed.
+    Completed {
+        /// The fingerprint of the processed request.
+        fingerprint: u64,
+        /// The response to the processed request.
+        response: StoredResponse,
+    },
+}
+
+/// Storage for the responses of requests with idempotency keys.
+///
+/// Requests are identified by a fingerprint of their method, URI and body, so retries can be told
+/// apart from different requests reusing a key. The keys passed to the store are the
+/// idempotency keys prefixed with a digest of the [scope headers], so they are unique per client.
+///
+/// Stores are cloned for every service produced by an [`IdempotencyLayer`], so clones should
+/// share their state. [`complete`](Self::complete) and [`release`](Self::release) are called
+/// while the response is being sent, so stores backed by a database should write in the
+/// background.
+///
+/// [`IdempotencyLayer`]: super::IdempotencyLayer
+/// [scope headers]: super::IdempotencyLayer::scope_header
+pub trait IdempotencyStore {
+    /// The future returned by [`begin`](Self::begin).
+    type Future: Future<Output = Lookup>;
+
+    /// Look up `key`, reserving it for the request with `fingerprint` for `ttl` if it is unused.
+    fn begin(&self, key: &str, fingerprint: u64, ttl: Duration) -> Self::Future;
+
+    /// Store the response to the request that reserved `key` for `ttl`.
+    fn complete(&self, key: &str, response: StoredResponse, ttl: Duration);
+
+    /// Free up `key` after the request that reserved it failed without a response to store.
+    fn release(&self, key: &str);
+}
+
+impl<T> IdempotencyStore for Arc<T>
+where
+    T: IdempotencyStore + ?Sized,
+{
+    type Future = T::Future;
+
+    fn begin(&self, key: &str, fingerprint: u64, ttl: Duration) -> Self::Future {
+        (**self).begin(key, fingerprint, ttl)
+    }
+
+    fn complete(&self, key: &str, response: StoredResponse, ttl: Duration) {
+        (**self).complete(key, response, ttl)
+    }
+
+    fn release(&self, key: &str) {
+        (**self).release(key)
+    }
+}
+
+/// An [`IdempotencyStore`] keeping responses in memory.
+///
+/// Clones share the same state.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    calls: u64,
+}
+
+struct Entry {
+    fingerprint: u64,
+    response: Option<StoredResponse>,
+    expires: Instant,
+}
+
+/// How often expired entries are removed, in calls to `begin`.
+const CLEANUP_INTERVAL: u64 = 1024;
+
+impl MemoryStore {
+    /// Create a new, empty [`MemoryStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdempotencyStore for MemoryStore {
+    type Future = Ready<Lookup>;
+
+    fn begin(&self, key: &str, fingerprint: u64, ttl: Duration) -> Self::Future {
+        let now = Instant::now();
+        let mut inner = lock_ignore_poison(&self.inner);
+
+        inner.calls += 1;
+        if inner.calls % CLEANUP_INTERVAL == 0 {
+            inner.entries.retain(|_, entry| entry.expires > now);
+        }
+
+        let lookup = match inner.entries.get(key) {
+            Some(entry) if entry.expires > now => match &entry.response {
+                Some(response) => Lookup::Completed {
+                    fingerprint: entry.fingerprint,
+                    response: response.clone(),
+                },
+                None => Lookup::InProgress {
+                    fingerprint: entry.fingerprint,
+                },
+            },
+            _ => {
+                let entry = Entry {
+                    fingerprint,
+                    response: None,
+                    expires: now + ttl,
+                };
+                inner.entries.insert(key.to_owned(), entry);
+                Lookup::Started
+            }
+        };
+        future::ready(lookup)
+    }
+
+    fn complete(&self, key: &str, response: StoredResponse, ttl: Duration) {
+        if let Some(entry) = lock_ignore_poison(&self.inner).entries.get_mut(key) {
+            entry.response = Some(response);
+            entry.expires = Instant::now() + ttl;
+        }
+    }
+
+    fn release(&self, key: &str) {
+        lock_ignore_poison(&self.inner).entries.remove(key);
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("entries", &lock_ignore_poison(&self.inner).entries.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn begin(store: &MemoryStore, key: &str, fingerprint: u64, ttl: Duration) -> Lookup {
+        store.begin(key, fingerprint, ttl).now_or_never().unwrap()
+    }
+
+    #[test]
+    fn memory_store() {
+        let store = MemoryStore::new();
+        let ttl = Duration::from_secs(60);
+
+        assert!(matches!(begin(&store, "a", 1, ttl), Lookup::Started));
+        assert!(matches!(
+            begin(&store, "a", 2, ttl),
+            Lookup::InProgress { fingerprint: 1 }
+        ));
+
+        let response = StoredResponse::new(StatusCode::CREATED, HeaderMap::new(), "ok".into());
+        store.complete("a", response, ttl);
+        match begin(&store, "a", 1, ttl) {
+            Lookup::Completed {
+                fingerprint,
+                response,
+            } => {
+                assert_eq!(fingerprint, 1);
+                assert_eq!(response.status(), StatusCode::CREATED);
+                assert_eq!(response.body(), "ok");
+            }
+            lookup => panic!("unexpected lookup: {:?}", lookup),
+        }
+
+        // released and expired keys can be used again
+        assert!(matches!(begin(&store, "b", 1, ttl), Lookup::Started));
+        store.release("b");
+        assert!(matches!(begin(&store, "b", 1, ttl), Lookup::Started));
+        assert!(matches!(
+            begin(&store, "c", 1, Duration::ZERO),
+            Lookup::Started
+        ));
+        assert!(matches!(begin(&store, "c", 1, ttl), Lookup::Started));
+    }
+}
@@ -228,12 +228,18 @@ pub(crate) mod macros;
 #[cfg(feature = "auth")]
 pub mod auth;
 
+#[cfg(feature = "body")]
+pub mod body;
+
 #[cfg(feature = "set-header")]
 pub mod set_header;
 
 #[cfg(feature = "propagate-header")]
 pub mod propagate_header;
 
+#[cfg(feature = "rate-limit")]
+pub mod rate_limit;
+
 #[cfg(any(
     feature = "compression-br",
     feature = "compression-deflate",
@@ -281,6 +287,73 @@ mod content_encoding;
 ))]
 mod compression_utils;
 
+#[cfg(any(feature = "follow-redirect", feature = "mirror", feature = "retry"))]
+mod replay_body;
+
+#[cfg(any(
+    feature = "aws-sigv4",
+    feature = "buffer-request-body",
+    feature = "content-digest",
+    feature = "hmac-signature",
+    feature = "idempotency",
+))]
+mod buffer;
+
+#[cfg(any(feature = "aws-sigv4", feature = "record-replay"))]
+mod date;
+
+#[cfg(any(feature = "cache", feature = "etag", feature = "fs"))]
+mod entity_tag;
+
+#[cfg(any(feature = "etag", feature = "fs", feature = "sticky-session"))]
+mod fnv;
+
+#[cfg(any(feature = "cache", feature = "fs"))]
+mod lru;
+
+#[cfg(any(
+    feature = "aws-sigv4",
+    feature = "buffer-request-body",
+    feature = "cache",
+    feature = "coalesce",
+    feature = "concurrency-limit",
+    feature = "content-digest",
+    feature = "follow-redirect",
+    feature = "fs",
+    feature = "handle-error",
+    feature = "hmac-signature",
+    feature = "idempotency",
+    feature = "prioritize",
+    feature = "rate-limit",
+    feature = "retry",
+    feature = "steer-by-host",
+))]
+mod ready;
+
+#[cfg(any(
+    feature = "cache",
+    feature = "circuit-breaker",
+    feature = "coalesce",
+    feature = "cookie-jar",
+    feature = "cookies",
+    feature = "early-hints",
+    feature = "fault-injection",
+    feature = "follow-redirect",
+    feature = "fs",
+    feature = "health-check",
+    feature = "idempotency",
+    feature = "load-shed",
+    feature = "metrics",
+    feature = "mirror",
+    feature = "prioritize",
+    feature = "rate-limit",
+    feature = "record-replay",
+    feature = "retry",
+    feature = "test-util",
+    feature = "throttle",
+))]
+mod sync;
+
 #[cfg(any(
     feature = "compression-br",
     feature = "compression-deflate",
@@ -302,33 +375,183 @@ pub mod map_request_body;
 #[cfg(feature = "trace")]
 pub mod trace;
 
+#[cfg(feature = "etag")]
+pub mod etag;
+
 #[cfg(feature = "follow-redirect")]
 pub mod follow_redirect;
 
+#[cfg(feature = "handle-error")]
+pub mod handle_error;
+
 #[cfg(feature = "limit")]
 pub mod limit;
 
 #[cfg(feature = "metrics")]
 pub mod metrics;
 
+#[cfg(any(feature = "metrics", feature = "trace"))]
+pub mod matched_route;
+
 #[cfg(feature = "cors")]
 pub mod cors;
 
 #[cfg(feature = "request-id")]
 pub mod request_id;
 
+#[cfg(feature = "rewrite-uri")]
+pub mod rewrite_uri;
+
+#[cfg(feature = "cache")]
+pub mod cache;
+
 #[cfg(feature = "catch-panic")]
 pub mod catch_panic;
 
+#[cfg(feature = "box-body")]
+pub mod box_body;
+
+#[cfg(feature = "buffer-request-body")]
+pub mod buffer_request_body;
+
+#[cfg(feature = "content-length")]
+pub mod content_length;
+
+#[cfg(feature = "concurrency-limit")]
+pub mod concurrency_limit;
+
+#[cfg(feature = "conditional")]
+pub mod conditional;
+
+#[cfg(feature = "load-shed")]
+pub mod load_shed;
+
+#[cfg(feature = "prioritize")]
+pub mod prioritize;
+
+#[cfg(feature = "readiness")]
+pub mod readiness;
+
+#[cfg(feature = "record-replay")]
+pub mod record_replay;
+
+#[cfg(feature = "retry")]
+pub mod retry;
+
+#[cfg(feature = "circuit-breaker")]
+pub mod circuit_breaker;
+
+#[cfg(feature = "coalesce")]
+pub mod coalesce;
+
+#[cfg(feature = "idempotency")]
+pub mod idempotency;
+
+#[cfg(feature = "grpc-web")]
+pub mod grpc_web;
+
+#[cfg(feature = "map-grpc-status")]
+pub mod map_grpc_status;
+
+#[cfg(feature = "early-hints")]
+pub mod early_hints;
+
+#[cfg(feature = "auto-head")]
+pub mod auto_head;
+
+#[cfg(feature = "allowed-methods")]
+pub mod allowed_methods;
+
+#[cfg(feature = "method-override")]
+pub mod method_override;
+
+#[cfg(feature = "expect-continue")]
+pub mod expect_continue;
+
+#[cfg(feature = "fault-injection")]
+pub mod fault_injection;
+
+#[cfg(feature = "sse-keep-alive")]
+pub mod sse_keep_alive;
+
+#[cfg(feature = "websocket-upgrade")]
+pub mod websocket_upgrade;
+
+#[cfg(feature = "negotiate")]
+pub mod negotiate;
+
+#[cfg(feature = "client-ip")]
+pub mod client_ip;
+
+#[cfg(feature = "add-forwarded")]
+pub mod add_forwarded;
+
+#[cfg(feature = "remove-hop-by-hop")]
+pub mod remove_hop_by_hop;
+
+#[cfg(feature = "https-redirect")]
+pub mod https_redirect;
+
+#[cfg(feature = "sticky-session")]
+pub mod sticky_session;
+
+#[cfg(feature = "mirror")]
+pub mod mirror;
+
+#[cfg(feature = "hmac-signature")]
+pub mod hmac_signature;
+
+#[cfg(feature = "aws-sigv4")]
+pub mod aws_sigv4;
+
+#[cfg(feature = "message-signature")]
+pub mod message_signature;
+
+#[cfg(feature = "content-digest")]
+pub mod content_digest;
+
+#[cfg(feature = "cookie-jar")]
+pub mod cookie_jar;
+
+#[cfg(feature = "cookies")]
+pub mod cookies;
+
+#[cfg(feature = "deprecation")]
+pub mod deprecation;
+
+#[cfg(feature = "preload-links")]
+pub mod preload_links;
+
 #[cfg(feature = "set-status")]
 pub mod set_status;
 
+#[cfg(feature = "steer-by-host")]
+pub mod steer_by_host;
+
+#[cfg(feature = "throttle")]
+pub mod throttle;
+
 #[cfg(feature = "timeout")]
 pub mod timeout;
 
+#[cfg(feature = "test-util")]
+pub mod test;
+
+#[cfg(feature = "modify-query")]
+pub mod modify_query;
+
 #[cfg(feature = "normalize-path")]
 pub mod normalize_path;
 
+#[cfg(feature = "normalize-percent-encoding")]
+pub mod normalize_percent_encoding;
+
+#[cfg(feature = "path-prefix")]
+pub mod path_prefix;
+
+#[cfg(feature = "problem-details")]
+pub mod problem_details;
+
 pub mod classify;
 pub mod services;
 
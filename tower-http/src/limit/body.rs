@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use http::{HeaderMap, HeaderValue, Response, StatusCode};
+use http::{HeaderMap, Response};
 use http_body::{Body, Full, SizeHint};
 use pin_project_lite::pin_project;
 use std::pin::Pin;
@@ -16,11 +16,9 @@ pin_project! {
 }
 
 impl<B> ResponseBody<B> {
-    fn payload_too_large() -> Self {
+    fn payload_too_large(body: Full<Bytes>) -> Self {
         Self {
-            inner: ResponseBodyInner::PayloadTooLarge {
-                body: Full::from(BODY),
-            },
+            inner: ResponseBodyInner::PayloadTooLarge { body },
         }
     }
 
@@ -89,19 +87,9 @@ where
     }
 }
 
-const BODY: &[u8] = b"length limit exceeded";
-
-pub(crate) fn create_error_response<B>() -> Response<ResponseBody<B>>
+pub(crate) fn create_error_response<B>(response: Response<Full<Bytes>>) -> Response<ResponseBody<B>>
 where
     B: Body,
 {
-    let mut res = Response::new(ResponseBody::payload_too_large());
-    *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
-
-    #[allow(clippy::declare_interior_mutable_const)]
-    const TEXT_PLAIN: HeaderValue = HeaderValue::from_static("text/plain; charset=utf-8");
-    res.headers_mut()
-        .insert(http::header::CONTENT_TYPE, TEXT_PLAIN);
-
-    res
+    response.map(ResponseBody::payload_too_large)
 }
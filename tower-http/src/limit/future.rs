@@ -1,8 +1,9 @@
 use super::body::create_error_response;
 use super::ResponseBody;
+use bytes::Bytes;
 use futures_core::ready;
 use http::Response;
-use http_body::Body;
+use http_body::{Body, Full};
 use pin_project_lite::pin_project;
 use std::future::Future;
 use std::pin::Pin;
@@ -19,9 +20,11 @@ pin_project! {
 }
 
 impl<F> ResponseFuture<F> {
-    pub(crate) fn payload_too_large() -> Self {
+    pub(crate) fn payload_too_large(response: Response<Full<Bytes>>) -> Self {
         Self {
-            inner: ResponseFutureInner::PayloadTooLarge,
+            inner: ResponseFutureInner::PayloadTooLarge {
+                response: Some(response),
+            },
         }
     }
 
@@ -35,7 +38,9 @@ impl<F> ResponseFuture<F> {
 pin_project! {
     #[project = ResFutProj]
     enum ResponseFutureInner<F> {
-        PayloadTooLarge,
+        PayloadTooLarge {
+            response: Option<Response<Full<Bytes>>>,
+        },
         Future {
             #[pin]
             future: F,
@@ -52,7 +57,9 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let res = match self.project().inner.project() {
-            ResFutProj::PayloadTooLarge => create_error_response(),
+            ResFutProj::PayloadTooLarge { response } => {
+                create_error_response(response.take().expect("future polled after completion"))
+            }
             ResFutProj::Future { future } => ready!(future.poll(cx))?.map(ResponseBody::new),
         };
 
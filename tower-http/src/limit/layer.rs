@@ -1,4 +1,4 @@
-use super::RequestBodyLimit;
+use super::{DefaultPayloadTooLarge, RequestBodyLimit};
 use tower_layer::Layer;
 
 /// Layer that applies the [`RequestBodyLimit`] middleware that intercepts requests
@@ -9,24 +9,100 @@ use tower_layer::Layer;
 ///
 /// [`RequestBodyLimit`]: super::RequestBodyLimit
 #[derive(Clone, Copy, Debug)]
-pub struct RequestBodyLimitLayer {
+pub struct RequestBodyLimitLayer<P = DefaultPayloadTooLarge> {
     limit: usize,
+    payload_too_large: P,
 }
 
 impl RequestBodyLimitLayer {
     /// Create a new `RequestBodyLimitLayer` with the given body length limit.
     pub fn new(limit: usize) -> Self {
-        Self { limit }
+        Self {
+            limit,
+            payload_too_large: DefaultPayloadTooLarge::new(),
+        }
+    }
+}
+
+impl<P> RequestBodyLimitLayer<P> {
+    /// Customize the response sent when the `Content-Length` of a request is larger than the
+    /// limit.
+    ///
+    /// `NewP` is expected to implement [`MakePayloadTooLarge`]. Defaults to
+    /// [`DefaultPayloadTooLarge`].
+    ///
+    /// Only requests whose `Content-Length` is larger than the limit get this response, before
+    /// their body is read. Bodies without a `Content-Length`, such as chunked ones, are only
+    /// found to be too large while the inner service reads them. The body then fails with a
+    /// [`LengthLimitError`] and the inner service creates the response itself, so it has to
+    /// respond with `413 Payload Too Large` for those requests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use http::{header, Request, Response, StatusCode};
+    /// use http_body::{Full, Limited};
+    /// use hyper::Body;
+    /// use std::convert::Infallible;
+    /// use tower::{Service, ServiceBuilder, ServiceExt};
+    /// use tower_http::limit::RequestBodyLimitLayer;
+    ///
+    /// async fn handle(_: Request<Limited<Body>>) -> Result<Response<Full<Bytes>>, Infallible> {
+    ///     // ...
+    ///     # Ok(Response::new(Full::default()))
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut svc = ServiceBuilder::new()
+    ///     .layer(
+    ///         RequestBodyLimitLayer::new(4096).payload_too_large_with(|limit: usize| {
+    ///             let body = format!(r#"{{"error":"body larger than {} bytes"}}"#, limit);
+    ///             let mut res = Response::new(Full::from(Bytes::from(body)));
+    ///             *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+    ///             res.headers_mut()
+    ///                 .insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+    ///             res
+    ///         }),
+    ///     )
+    ///     .service_fn(handle);
+    ///
+    /// let request = Request::builder()
+    ///     .header(header::CONTENT_LENGTH, "5000")
+    ///     .body(Body::empty())?;
+    ///
+    /// let response = svc.ready().await?.call(request).await?;
+    /// assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    /// assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`MakePayloadTooLarge`]: super::MakePayloadTooLarge
+    /// [`LengthLimitError`]: http_body::LengthLimitError
+    pub fn payload_too_large_with<NewP>(
+        self,
+        payload_too_large: NewP,
+    ) -> RequestBodyLimitLayer<NewP> {
+        RequestBodyLimitLayer {
+            limit: self.limit,
+            payload_too_large,
+        }
     }
 }
 
-impl<S> Layer<S> for RequestBodyLimitLayer {
-    type Service = RequestBodyLimit<S>;
+impl<S, P> Layer<S> for RequestBodyLimitLayer<P>
+where
+    P: Clone,
+{
+    type Service = RequestBodyLimit<S, P>;
 
     fn layer(&self, inner: S) -> Self::Service {
         RequestBodyLimit {
             inner,
             limit: self.limit,
+            payload_too_large: self.payload_too_large.clone(),
         }
     }
 }
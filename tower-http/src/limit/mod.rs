@@ -1,8 +1,10 @@
-//! Middleware for limiting request bodies.
+//! Middleware for limiting request and response bodies.
 //!
 //! This layer will also intercept requests with a `Content-Length` header
 //! larger than the allowable limit and return an immediate error response
-//! before reading any of the body.
+//! before reading any of the body. The status, headers and body of that
+//! response can be customized with
+//! [`RequestBodyLimitLayer::payload_too_large_with`].
 //!
 //! Note that payload length errors can be used by adversaries in an attempt
 //! to smuggle requests. When an incoming stream is dropped due to an
@@ -126,15 +128,51 @@
 //! [`http_body::Limited`] and checking for [`http_body::LengthLimitError`]
 //! like in the previous example.
 //!
+//! ## Limiting response bodies
+//!
+//! Clients can use [`ResponseBodyLimitLayer`] to protect themselves from upstreams that send
+//! more data than expected. Response bodies are wrapped in [`http_body::Limited`] which fails
+//! with a [`http_body::LengthLimitError`] once the limit is exceeded.
+//!
+//! ```rust
+//! use http::{Request, Response};
+//! use http_body::LengthLimitError;
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{BoxError, Service, ServiceExt, ServiceBuilder};
+//! use tower_http::limit::ResponseBodyLimitLayer;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! # let client = tower::service_fn(|_: Request<Body>| async {
+//! #     Ok::<_, Infallible>(Response::new(Body::from(vec![0u8; 8192])))
+//! # });
+//! let mut client = ServiceBuilder::new()
+//!     // Read at most 4096 bytes from response bodies.
+//!     .layer(ResponseBodyLimitLayer::new(4096))
+//!     .service(client);
+//!
+//! let response = client.ready().await?.call(Request::new(Body::empty())).await?;
+//!
+//! let err = hyper::body::to_bytes(response.into_body()).await.unwrap_err();
+//! assert!(err.downcast_ref::<LengthLimitError>().is_some());
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! [`MapRequestBody`]: crate::map_request_body
 //! [hyper]: https://crates.io/crates/hyper
 
 mod body;
 mod future;
 mod layer;
+mod payload_too_large;
+mod response;
 mod service;
 
 pub use body::ResponseBody;
 pub use future::ResponseFuture;
 pub use layer::RequestBodyLimitLayer;
+pub use payload_too_large::{DefaultPayloadTooLarge, MakePayloadTooLarge};
+pub use response::{ResponseBodyLimit, ResponseBodyLimitFuture, ResponseBodyLimitLayer};
 pub use service::RequestBodyLimit;
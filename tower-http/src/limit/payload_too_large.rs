@@ -0,0 +1,57 @@
+use bytes::Bytes;
+use http::{header, HeaderValue, Response, StatusCode};
+use http_body::Full;
+
+/// Trait for producing the response sent when a request body is larger than the limit.
+///
+/// This trait is implemented for closures with the signature
+/// `FnMut(usize) -> Response<Full<Bytes>>`, which receive the configured limit.
+///
+/// See [`RequestBodyLimitLayer::payload_too_large_with`] for an example.
+///
+/// [`RequestBodyLimitLayer::payload_too_large_with`]: super::RequestBodyLimitLayer::payload_too_large_with
+pub trait MakePayloadTooLarge {
+    /// Make the response.
+    fn make_payload_too_large(&mut self, limit: usize) -> Response<Full<Bytes>>;
+}
+
+impl<F> MakePayloadTooLarge for F
+where
+    F: FnMut(usize) -> Response<Full<Bytes>>,
+{
+    fn make_payload_too_large(&mut self, limit: usize) -> Response<Full<Bytes>> {
+        self(limit)
+    }
+}
+
+/// The default [`MakePayloadTooLarge`] used by [`RequestBodyLimit`].
+///
+/// Produces a `413 Payload Too Large` response with a plain text body.
+///
+/// [`RequestBodyLimit`]: super::RequestBodyLimit
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultPayloadTooLarge {
+    _priv: (),
+}
+
+impl DefaultPayloadTooLarge {
+    /// Create a new `DefaultPayloadTooLarge`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MakePayloadTooLarge for DefaultPayloadTooLarge {
+    fn make_payload_too_large(&mut self, _limit: usize) -> Response<Full<Bytes>> {
+        let mut res = Response::new(Full::from(BODY));
+        *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+
+        #[allow(clippy::declare_interior_mutable_const)]
+        const TEXT_PLAIN: HeaderValue = HeaderValue::from_static("text/plain; charset=utf-8");
+        res.headers_mut().insert(header::CONTENT_TYPE, TEXT_PLAIN);
+
+        res
+    }
+}
+
+const BODY: &[u8] = b"length limit exceeded";
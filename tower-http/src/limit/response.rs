@@ -0,0 +1,154 @@
+use futures_core::ready;
+use http::{Request, Response};
+use http_body::Limited;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`ResponseBodyLimit`] middleware that limits the size of response
+/// bodies.
+///
+/// See the [module docs](crate::limit) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseBodyLimitLayer {
+    limit: usize,
+}
+
+impl ResponseBodyLimitLayer {
+    /// Create a new `ResponseBodyLimitLayer` with the given body length limit.
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for ResponseBodyLimitLayer {
+    type Service = ResponseBodyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseBodyLimit {
+            inner,
+            limit: self.limit,
+        }
+    }
+}
+
+/// Middleware that limits the size of response bodies.
+///
+/// Response bodies are wrapped in [`Limited`] which fails with a
+/// [`LengthLimitError`] once more than the configured number of bytes has been yielded. This is
+/// mostly useful for clients, to protect against upstreams that send unbounded amounts of data.
+///
+/// See the [module docs](crate::limit) for an example.
+///
+/// [`LengthLimitError`]: http_body::LengthLimitError
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseBodyLimit<S> {
+    inner: S,
+    limit: usize,
+}
+
+impl<S> ResponseBodyLimit<S> {
+    /// Create a new `ResponseBodyLimit` with the given body length limit.
+    pub fn new(inner: S, limit: usize) -> Self {
+        Self { inner, limit }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ResponseBodyLimit` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(limit: usize) -> ResponseBodyLimitLayer {
+        ResponseBodyLimitLayer::new(limit)
+    }
+}
+
+impl<ReqBody, ResBody, S> Service<Request<ReqBody>> for ResponseBodyLimit<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<Limited<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseBodyLimitFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseBodyLimitFuture {
+            inner: self.inner.call(req),
+            limit: self.limit,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ResponseBodyLimit`].
+    pub struct ResponseBodyLimitFuture<F> {
+        #[pin]
+        inner: F,
+        limit: usize,
+    }
+}
+
+impl<F, B, E> Future for ResponseBodyLimitFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<Limited<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx)?);
+        let limit = *this.limit;
+        Poll::Ready(Ok(res.map(|body| Limited::new(body, limit))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http_body::LengthLimitError;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn errors_body_over_limit() {
+        let svc = ServiceBuilder::new()
+            .layer(ResponseBodyLimitLayer::new(4))
+            .service_fn(|_: Request<Body>| async {
+                let (mut tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    tx.send_data(Bytes::from_static(b"foo")).await.unwrap();
+                    tx.send_data(Bytes::from_static(b"bar")).await.unwrap();
+                });
+                Ok::<_, Infallible>(Response::new(body))
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let err = hyper::body::to_bytes(res.into_body()).await.unwrap_err();
+        assert!(err.downcast_ref::<LengthLimitError>().is_some());
+    }
+
+    #[tokio::test]
+    async fn passes_body_under_limit() {
+        let svc = ServiceBuilder::new()
+            .layer(ResponseBodyLimitLayer::new(6))
+            .service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::from("foobar")))
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "foobar");
+    }
+}
@@ -1,4 +1,7 @@
-use super::{RequestBodyLimitLayer, ResponseBody, ResponseFuture};
+use super::{
+    DefaultPayloadTooLarge, MakePayloadTooLarge, RequestBodyLimitLayer, ResponseBody,
+    ResponseFuture,
+};
 use http::{Request, Response};
 use http_body::{Body, Limited};
 use std::task::{Context, Poll};
@@ -9,19 +12,22 @@ use tower_service::Service;
 ///
 /// See the [module docs](crate::limit) for an example.
 #[derive(Clone, Copy, Debug)]
-pub struct RequestBodyLimit<S> {
+pub struct RequestBodyLimit<S, P = DefaultPayloadTooLarge> {
     pub(crate) inner: S,
     pub(crate) limit: usize,
+    pub(crate) payload_too_large: P,
 }
 
 impl<S> RequestBodyLimit<S> {
     /// Create a new `RequestBodyLimit` with the given body length limit.
     pub fn new(inner: S, limit: usize) -> Self {
-        Self { inner, limit }
+        Self {
+            inner,
+            limit,
+            payload_too_large: DefaultPayloadTooLarge::new(),
+        }
     }
 
-    define_inner_service_accessors!();
-
     /// Returns a new [`Layer`] that wraps services with a `RequestBodyLimit` middleware.
     ///
     /// [`Layer`]: tower_layer::Layer
@@ -30,10 +36,35 @@ impl<S> RequestBodyLimit<S> {
     }
 }
 
-impl<ReqBody, ResBody, S> Service<Request<ReqBody>> for RequestBodyLimit<S>
+impl<S, P> RequestBodyLimit<S, P> {
+    define_inner_service_accessors!();
+
+    /// Customize the response sent when the `Content-Length` of a request is larger than the
+    /// limit.
+    ///
+    /// Bodies without a `Content-Length` that turn out to be too large fail with a
+    /// [`LengthLimitError`] instead, which the inner service has to handle.
+    ///
+    /// See [`RequestBodyLimitLayer::payload_too_large_with`] for more details.
+    ///
+    /// [`LengthLimitError`]: http_body::LengthLimitError
+    pub fn payload_too_large_with<NewP>(
+        self,
+        payload_too_large: NewP,
+    ) -> RequestBodyLimit<S, NewP> {
+        RequestBodyLimit {
+            inner: self.inner,
+            limit: self.limit,
+            payload_too_large,
+        }
+    }
+}
+
+impl<ReqBody, ResBody, S, P> Service<Request<ReqBody>> for RequestBodyLimit<S, P>
 where
     ResBody: Body,
     S: Service<Request<Limited<ReqBody>>, Response = Response<ResBody>>,
+    P: MakePayloadTooLarge,
 {
     type Response = Response<ResponseBody<ResBody>>;
     type Error = S::Error;
@@ -51,7 +82,10 @@ where
             .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
 
         let body_limit = match content_length {
-            Some(len) if len > self.limit => return ResponseFuture::payload_too_large(),
+            Some(len) if len > self.limit => {
+                let response = self.payload_too_large.make_payload_too_large(self.limit);
+                return ResponseFuture::payload_too_large(response);
+            }
             Some(len) => self.limit.min(len),
             None => self.limit,
         };
@@ -61,3 +95,49 @@ where
         ResponseFuture::new(self.inner.call(req))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, StatusCode};
+    use http_body::{Full, LengthLimitError};
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn streamed_bodies_are_left_to_the_inner_service() {
+        let svc = ServiceBuilder::new()
+            .layer(RequestBodyLimitLayer::new(4).payload_too_large_with(|_| {
+                let mut res = Response::new(Full::from("configured"));
+                *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                res
+            }))
+            .service_fn(|req: Request<Limited<Body>>| async move {
+                let res = match hyper::body::to_bytes(req.into_body()).await {
+                    Ok(body) => Response::new(Full::new(body)),
+                    Err(err) => {
+                        assert!(err.is::<LengthLimitError>());
+                        let mut res = Response::new(Full::from("inner"));
+                        *res.status_mut() = StatusCode::BAD_REQUEST;
+                        res
+                    }
+                };
+                Ok::<_, Infallible>(res)
+            });
+
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data(Bytes::from_static(b"foo")).await.unwrap();
+            tx.send_data(Bytes::from_static(b"bar")).await.unwrap();
+        });
+        let req = Request::new(body);
+        assert!(!req.headers().contains_key(header::CONTENT_LENGTH));
+
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "inner");
+    }
+}
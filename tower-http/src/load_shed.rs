@@ -0,0 +1,613 @@
+//! Middleware that sheds load once an adaptive concurrency limit is exceeded.
+//!
+//! Unlike [`ConcurrencyLimit`](crate::concurrency_limit::ConcurrencyLimit), which enforces a
+//! fixed limit, [`LoadShed`] continuously estimates how many concurrent requests the service can
+//! handle from the latency of recent responses. Requests that would exceed the estimated limit
+//! are answered with `503 Service Unavailable` right away, so an overloaded service degrades
+//! gracefully instead of queueing requests until they time out.
+//!
+//! Two algorithms are available to estimate the limit:
+//!
+//! - A gradient based one, used by default, which compares the latency of each response to the
+//!   long term average latency. The limit grows while latency stays stable and shrinks as soon as
+//!   latency starts to rise, as requests queue up somewhere in the service.
+//! - AIMD (additive increase, multiplicative decrease), enabled with [`LoadShedLayer::aimd`],
+//!   which grows the limit by one for every fast response and shrinks it by a factor for every
+//!   response slower than a threshold, every error and every `5xx` response.
+//!
+//! A request counts as in flight until its response body has been consumed or dropped. The limit
+//! is shared by all services produced by the same [`LoadShedLayer`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::load_shed::LoadShedLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         // Start at 20 concurrent requests and never go below 5 or above 200.
+//!         LoadShedLayer::new()
+//!             .initial_limit(20)
+//!             .min_limit(5)
+//!             .max_limit(200)
+//!             .retry_after(Duration::from_secs(1)),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sync::lock_ignore_poison;
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`LoadShed`] middleware which sheds requests exceeding an adaptive
+/// concurrency limit.
+///
+/// See the [module docs](crate::load_shed) for an example.
+#[derive(Clone, Debug)]
+pub struct LoadShedLayer {
+    limiter: Arc<Mutex<Limiter>>,
+    retry_after: Option<HeaderValue>,
+}
+
+impl LoadShedLayer {
+    /// Create a new `LoadShedLayer` that estimates the limit with the gradient algorithm.
+    pub fn new() -> Self {
+        Self {
+            limiter: Arc::new(Mutex::new(Limiter::new())),
+            retry_after: None,
+        }
+    }
+
+    /// Estimate the limit with AIMD instead of the gradient algorithm.
+    ///
+    /// Responses slower than `latency_threshold`, errors and `5xx` responses shrink the limit by
+    /// 10% while all other responses grow it by one.
+    pub fn aimd(self, latency_threshold: Duration) -> Self {
+        self.configure(|limiter| {
+            limiter.strategy = Strategy::Aimd {
+                latency_threshold,
+                backoff_ratio: 0.9,
+            }
+        })
+    }
+
+    /// Set the limit used before any responses have been observed.
+    ///
+    /// Defaults to `20`.
+    pub fn initial_limit(self, limit: usize) -> Self {
+        self.configure(|limiter| limiter.limit = limit as f64)
+    }
+
+    /// Set the lowest value the limit can shrink to.
+    ///
+    /// Defaults to `1`.
+    pub fn min_limit(self, limit: usize) -> Self {
+        self.configure(|limiter| limiter.min_limit = limit.max(1) as f64)
+    }
+
+    /// Set the highest value the limit can grow to.
+    ///
+    /// Defaults to `1000`.
+    pub fn max_limit(self, limit: usize) -> Self {
+        self.configure(|limiter| limiter.max_limit = limit as f64)
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// The duration is rounded down to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    fn configure<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Limiter),
+    {
+        // replace the limiter so clones of the layer made before keep their own
+        let mut limiter = lock_ignore_poison(&self.limiter).clone();
+        f(&mut limiter);
+        limiter.limit = limiter.clamp(limiter.limit);
+        self.limiter = Arc::new(Mutex::new(limiter));
+        self
+    }
+}
+
+impl Default for LoadShedLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShed {
+            inner,
+            limiter: self.limiter.clone(),
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+/// Middleware that sheds requests exceeding an adaptive concurrency limit with
+/// `503 Service Unavailable`.
+///
+/// See the [module docs](crate::load_shed) for an example.
+#[derive(Clone, Debug)]
+pub struct LoadShed<S> {
+    inner: S,
+    limiter: Arc<Mutex<Limiter>>,
+    retry_after: Option<HeaderValue>,
+}
+
+impl<S> LoadShed<S> {
+    /// Create a new `LoadShed` that estimates the limit with the gradient algorithm.
+    pub fn new(inner: S) -> Self {
+        LoadShedLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `LoadShed` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> LoadShedLayer {
+        LoadShedLayer::new()
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// See [`LoadShedLayer::retry_after`] for more details.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    /// Returns the current estimated concurrency limit.
+    pub fn limit(&self) -> usize {
+        lock_ignore_poison(&self.limiter).limit as usize
+    }
+
+    /// Returns the number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        lock_ignore_poison(&self.limiter).in_flight
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for LoadShed<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let kind = match InFlightGuard::new(&self.limiter) {
+            Some(guard) => Kind::Called {
+                future: self.inner.call(req),
+                guard: Some(guard),
+                start: Instant::now(),
+            },
+            None => Kind::Shed,
+        };
+
+        ResponseFuture {
+            kind,
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Limiter {
+    strategy: Strategy,
+    limit: f64,
+    min_limit: f64,
+    max_limit: f64,
+    in_flight: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Strategy {
+    Gradient {
+        // exponential moving average of the latency, in seconds
+        long_rtt: Option<f64>,
+    },
+    Aimd {
+        latency_threshold: Duration,
+        backoff_ratio: f64,
+    },
+}
+
+/// How much the latency may exceed the long term average before the limit shrinks.
+const TOLERANCE: f64 = 1.5;
+/// The number of samples the long term average latency roughly covers.
+const LONG_WINDOW: f64 = 600.0;
+/// How quickly the limit moves towards the newly estimated one.
+const SMOOTHING: f64 = 0.2;
+
+impl Limiter {
+    fn new() -> Self {
+        Self {
+            strategy: Strategy::Gradient { long_rtt: None },
+            limit: 20.0,
+            min_limit: 1.0,
+            max_limit: 1000.0,
+            in_flight: 0,
+        }
+    }
+
+    fn clamp(&self, limit: f64) -> f64 {
+        limit.clamp(self.min_limit, self.max_limit.max(self.min_limit))
+    }
+
+    fn on_sample(&mut self, latency: Duration, failed: bool) {
+        // when most of the limit is unused the latency says nothing about whether it is too high
+        let app_limited = (self.in_flight as f64) < self.limit / 2.0;
+
+        let limit = match &mut self.strategy {
+            Strategy::Gradient { long_rtt } => {
+                if failed {
+                    return;
+                }
+                let rtt = latency.as_secs_f64().max(1e-6);
+                let long = match *long_rtt {
+                    Some(long) => {
+                        let long = long + (rtt - long) / LONG_WINDOW;
+                        // recover quickly once latency drops after a period of overload
+                        if long / rtt > 2.0 {
+                            long * 0.95
+                        } else {
+                            long
+                        }
+                    }
+                    None => rtt,
+                };
+                *long_rtt = Some(long);
+                if app_limited {
+                    return;
+                }
+
+                let gradient = (TOLERANCE * long / rtt).clamp(0.5, 1.0);
+                let queue = self.limit.sqrt();
+                let estimate = self.limit.mul_add(gradient, queue);
+                self.limit.mul_add(1.0 - SMOOTHING, estimate * SMOOTHING)
+            }
+            Strategy::Aimd {
+                latency_threshold,
+                backoff_ratio,
+            } => {
+                if failed || latency > *latency_threshold {
+                    self.limit * *backoff_ratio
+                } else if app_limited {
+                    return;
+                } else {
+                    self.limit + 1.0
+                }
+            }
+        };
+        self.limit = self.clamp(limit);
+    }
+}
+
+struct InFlightGuard {
+    limiter: Arc<Mutex<Limiter>>,
+}
+
+impl InFlightGuard {
+    fn new(limiter: &Arc<Mutex<Limiter>>) -> Option<Self> {
+        let mut locked = lock_ignore_poison(limiter);
+        if locked.in_flight as f64 >= locked.limit.floor() {
+            return None;
+        }
+        locked.in_flight += 1;
+        Some(Self {
+            limiter: limiter.clone(),
+        })
+    }
+
+    fn on_sample(&self, latency: Duration, failed: bool) {
+        lock_ignore_poison(&self.limiter).on_sample(latency, failed);
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        lock_ignore_poison(&self.limiter).in_flight -= 1;
+    }
+}
+
+pin_project! {
+    /// Response future for [`LoadShed`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+        retry_after: Option<HeaderValue>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Shed,
+        Called {
+            #[pin]
+            future: F,
+            guard: Option<InFlightGuard>,
+            start: Instant,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.kind.project() {
+            KindProj::Shed => Poll::Ready(Ok(service_unavailable(this.retry_after.clone()))),
+            KindProj::Called {
+                future,
+                guard,
+                start,
+            } => {
+                let result = ready!(future.poll(cx));
+                let guard = guard.take();
+                if let Some(guard) = &guard {
+                    let failed = match &result {
+                        Ok(res) => res.status().is_server_error(),
+                        Err(_) => true,
+                    };
+                    guard.on_sample(start.elapsed(), failed);
+                }
+                Poll::Ready(result.map(|res| {
+                    res.map(|body| ResponseBody {
+                        kind: BodyKind::Body { body, guard },
+                    })
+                }))
+            }
+        }
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+fn service_unavailable<B>(retry_after: Option<HeaderValue>) -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut res = Response::new(ResponseBody {
+        kind: BodyKind::Shed { body: Empty::new() },
+    });
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    if let Some(retry_after) = retry_after {
+        res.headers_mut().insert(header::RETRY_AFTER, retry_after);
+    }
+    res
+}
+
+pin_project! {
+    /// Response body for [`LoadShed`].
+    ///
+    /// Counts the request as in flight until the body is consumed or dropped.
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        Shed {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            guard: Option<InFlightGuard>,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Shed { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { mut body, guard } => {
+                let data = ready!(body.as_mut().poll_data(cx));
+                if data.is_none() && body.is_end_stream() {
+                    guard.take();
+                }
+                Poll::Ready(data)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Shed { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body, guard } => {
+                let trailers = ready!(body.poll_trailers(cx));
+                guard.take();
+                Poll::Ready(trailers)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Shed { body } => body.is_end_stream(),
+            BodyKind::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Shed { body } => body.size_hint(),
+            BodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn sheds_requests_over_the_limit() {
+        let mut svc = ServiceBuilder::new()
+            .layer(
+                LoadShedLayer::new()
+                    .initial_limit(1)
+                    .retry_after(Duration::from_secs(2)),
+            )
+            .service_fn(echo);
+
+        let first = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::from("foo")))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(svc.in_flight(), 1);
+
+        let second = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(second.headers()[header::RETRY_AFTER], "2");
+
+        // consuming the body of the first response frees up capacity
+        hyper::body::to_bytes(first.into_body()).await.unwrap();
+        assert_eq!(svc.in_flight(), 0);
+        let third = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn gradient_shrinks_limit_when_latency_rises() {
+        let mut limiter = Limiter::new();
+        limiter.in_flight = 20;
+
+        for _ in 0..10 {
+            limiter.on_sample(Duration::from_millis(10), false);
+        }
+        let grown = limiter.limit;
+        assert!(grown > 20.0);
+
+        for _ in 0..10 {
+            limiter.on_sample(Duration::from_millis(100), false);
+        }
+        assert!(limiter.limit < grown);
+    }
+
+    #[test]
+    fn aimd_backs_off_on_slow_and_failed_responses() {
+        let mut limiter = Limiter::new();
+        limiter.strategy = Strategy::Aimd {
+            latency_threshold: Duration::from_millis(50),
+            backoff_ratio: 0.9,
+        };
+        limiter.in_flight = 20;
+
+        limiter.on_sample(Duration::from_millis(10), false);
+        assert_eq!(limiter.limit, 21.0);
+        limiter.on_sample(Duration::from_millis(100), false);
+        assert_eq!(limiter.limit, 21.0 * 0.9);
+        limiter.on_sample(Duration::from_millis(10), true);
+        assert_eq!(limiter.limit, 21.0 * 0.9 * 0.9);
+
+        // the limit isn't grown while most of it is unused
+        limiter.in_flight = 1;
+        limiter.on_sample(Duration::from_millis(10), false);
+        assert_eq!(limiter.limit, 21.0 * 0.9 * 0.9);
+
+        limiter.min_limit = 16.0;
+        limiter.on_sample(Duration::from_millis(100), false);
+        assert_eq!(limiter.limit, 16.0);
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(req.into_body()))
+    }
+}
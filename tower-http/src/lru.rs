@@ -0,0 +1,153 @@
+//! Least recently used cache with a size budget.
+
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// Map evicting its least recently used entries once the sizes of the entries add up to more
+/// than `max_size`.
+///
+/// What the size of an entry means is up to the caller: the number of bytes it holds, or `1` to
+/// limit the number of entries.
+pub(crate) struct Lru<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    // last use to key, the first entry is the least recently used one
+    recency: BTreeMap<u64, K>,
+    next_use: u64,
+    size: usize,
+    max_size: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    last_use: u64,
+}
+
+impl<K, V> Lru<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_use: 0,
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Get the value for `key`, marking it as the most recently used one.
+    pub(crate) fn get<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.next_use += 1;
+        let entry = self.entries.get_mut(key)?;
+        let (_, key) = self.recency.remove_entry(&entry.last_use)?;
+        self.recency.insert(self.next_use, key);
+        entry.last_use = self.next_use;
+        Some(&mut entry.value)
+    }
+
+    /// Insert `value`, evicting the least recently used entries until it fits.
+    ///
+    /// Values larger than `max_size` aren't inserted.
+    pub(crate) fn insert(&mut self, key: K, value: V, size: usize) {
+        self.remove(&key);
+        if size > self.max_size {
+            return;
+        }
+        while self.size + size > self.max_size {
+            let least_recently_used = match self.recency.values().next() {
+                Some(key) => key.clone(),
+                None => return,
+            };
+            self.remove(&least_recently_used);
+        }
+
+        self.next_use += 1;
+        self.size += size;
+        self.recency.insert(self.next_use, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                last_use: self.next_use,
+            },
+        );
+    }
+
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.size -= entry.size;
+        self.recency.remove(&entry.last_use);
+        Some(entry.value)
+    }
+
+    #[cfg(feature = "cache")]
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.size = 0;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The sum of the sizes of all entries.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn max_size(&self) -> usize {
+        self.max_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut lru = Lru::new(3);
+        lru.insert("a", 1, 1);
+        lru.insert("b", 2, 1);
+        lru.insert("c", 3, 1);
+        assert_eq!(lru.get("a"), Some(&mut 1));
+
+        lru.insert("d", 4, 2);
+        assert_eq!(lru.get("b"), None);
+        assert_eq!(lru.get("c"), None);
+        assert_eq!(lru.get("a"), Some(&mut 1));
+        assert_eq!(lru.get("d"), Some(&mut 4));
+        assert_eq!(lru.size(), 3);
+    }
+
+    #[test]
+    fn replaces_and_rejects_oversized_values() {
+        let mut lru = Lru::new(3);
+        lru.insert("a", 1, 2);
+        lru.insert("a", 2, 1);
+        assert_eq!(lru.len(), 1);
+        assert_eq!(lru.size(), 1);
+
+        lru.insert("b", 3, 4);
+        assert_eq!(lru.get("b"), None);
+        assert_eq!(lru.get("a"), Some(&mut 2));
+
+        assert_eq!(lru.remove("a"), Some(2));
+        assert_eq!(lru.size(), 0);
+    }
+}
@@ -0,0 +1,659 @@
+//! Middleware that maps the `grpc-status` of gRPC responses to HTTP status codes.
+//!
+//! gRPC services respond with `200 OK` and report the outcome of a call in the `grpc-status`
+//! header or trailer. [`MapGrpcStatus`] sets the HTTP status of these responses from the
+//! `grpc-status`, so gateways exposing gRPC services to HTTP clients that don't speak gRPC
+//! respond with `404 Not Found` rather than `200 OK` for [`GrpcCode::NotFound`], for example.
+//!
+//! Since the HTTP status is sent before the body, responses that report their status in the
+//! trailers are buffered until the trailers have been received. Responses with bodies larger than
+//! [`MapGrpcStatusLayer::max_body_size`] or failing bodies are sent as they are.
+//!
+//! The default mapping follows the [gRPC HTTP mapping]:
+//!
+//! | gRPC code | HTTP status |
+//! |-----------|-------------|
+//! | `OK` | `200 OK` |
+//! | `CANCELLED` | `499 Client Closed Request` |
+//! | `UNKNOWN`, `INTERNAL`, `DATA_LOSS` | `500 Internal Server Error` |
+//! | `INVALID_ARGUMENT`, `FAILED_PRECONDITION`, `OUT_OF_RANGE` | `400 Bad Request` |
+//! | `DEADLINE_EXCEEDED` | `504 Gateway Timeout` |
+//! | `NOT_FOUND` | `404 Not Found` |
+//! | `ALREADY_EXISTS`, `ABORTED` | `409 Conflict` |
+//! | `PERMISSION_DENIED` | `403 Forbidden` |
+//! | `UNAUTHENTICATED` | `401 Unauthorized` |
+//! | `RESOURCE_EXHAUSTED` | `429 Too Many Requests` |
+//! | `UNIMPLEMENTED` | `501 Not Implemented` |
+//! | `UNAVAILABLE` | `503 Service Unavailable` |
+//!
+//! Individual codes can be mapped differently with [`MapGrpcStatusLayer::status_for`]. With
+//! [`MapGrpcStatusLayer::json_errors`], the bodies of failed calls are replaced by a JSON
+//! document containing the code and the `grpc-message`.
+//!
+//! [gRPC HTTP mapping]: https://github.com/googleapis/googleapis/blob/master/google/rpc/code.proto
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::map_grpc_status::MapGrpcStatusLayer;
+//!
+//! // A gRPC service responding without a message, with the status in the headers.
+//! async fn grpc(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let res = Response::builder()
+//!         .header("content-type", "application/grpc")
+//!         .header("grpc-status", "5")
+//!         .header("grpc-message", "no%20such%20user")
+//!         .body(Body::empty())
+//!         .unwrap();
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(MapGrpcStatusLayer::new().json_errors())
+//!     .service_fn(grpc);
+//!
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::NOT_FOUND);
+//! assert_eq!(response.headers()["content-type"], "application/json");
+//!
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! let json: serde_json::Value = serde_json::from_slice(&body)?;
+//! assert_eq!(json, serde_json::json!({
+//!     "code": 5,
+//!     "status": "NOT_FOUND",
+//!     "message": "no such user",
+//! }));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::classify::GrpcCode;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::ready;
+use http::{header, response::Parts, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The settings of the middleware.
+#[derive(Clone, Copy)]
+struct Config {
+    // indexed by the gRPC code
+    statuses: [StatusCode; 17],
+    json_errors: bool,
+    max_body_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut statuses = [StatusCode::INTERNAL_SERVER_ERROR; 17];
+        for (code, status) in statuses.iter_mut().enumerate() {
+            *status = default_status(GrpcCode::from_i32(code as i32).unwrap());
+        }
+        Self {
+            statuses,
+            json_errors: false,
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("json_errors", &self.json_errors)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+fn default_status(code: GrpcCode) -> StatusCode {
+    match code {
+        GrpcCode::Ok => StatusCode::OK,
+        GrpcCode::Cancelled => {
+            StatusCode::from_u16(499).expect("499 Client Closed Request is a valid status code")
+        }
+        GrpcCode::Unknown | GrpcCode::Internal | GrpcCode::DataLoss => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        GrpcCode::InvalidArgument | GrpcCode::FailedPrecondition | GrpcCode::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        GrpcCode::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        GrpcCode::NotFound => StatusCode::NOT_FOUND,
+        GrpcCode::AlreadyExists | GrpcCode::Aborted => StatusCode::CONFLICT,
+        GrpcCode::PermissionDenied => StatusCode::FORBIDDEN,
+        GrpcCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+        GrpcCode::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        GrpcCode::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        GrpcCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+/// Layer that applies [`MapGrpcStatus`] which maps the `grpc-status` of gRPC responses to HTTP
+/// status codes.
+///
+/// See the [module docs](crate::map_grpc_status) for an example.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MapGrpcStatusLayer {
+    config: Config,
+}
+
+impl MapGrpcStatusLayer {
+    /// Create a new `MapGrpcStatusLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond with `status` to calls that fail with `code`.
+    ///
+    /// See the [module docs](crate::map_grpc_status) for the default mapping.
+    pub fn status_for(mut self, code: GrpcCode, status: StatusCode) -> Self {
+        self.config.statuses[code as usize] = status;
+        self
+    }
+
+    /// Replace the bodies of failed calls with a JSON document such as
+    /// `{"code": 5, "status": "NOT_FOUND", "message": "no such user"}`.
+    ///
+    /// The message is taken from the `grpc-message` and omitted if there is none.
+    pub fn json_errors(mut self) -> Self {
+        self.config.json_errors = true;
+        self
+    }
+
+    /// Set the maximum size of bodies that are buffered to read the `grpc-status` trailer.
+    ///
+    /// Larger responses are sent with their original status. Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for MapGrpcStatusLayer {
+    type Service = MapGrpcStatus<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapGrpcStatus {
+            inner,
+            config: self.config,
+        }
+    }
+}
+
+/// Middleware that maps the `grpc-status` of gRPC responses to HTTP status codes.
+///
+/// See the [module docs](crate::map_grpc_status) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct MapGrpcStatus<S> {
+    inner: S,
+    config: Config,
+}
+
+impl<S> MapGrpcStatus<S> {
+    /// Create a new `MapGrpcStatus`.
+    pub fn new(inner: S) -> Self {
+        MapGrpcStatusLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `MapGrpcStatus` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> MapGrpcStatusLayer {
+        MapGrpcStatusLayer::new()
+    }
+
+    /// Respond with `status` to calls that fail with `code`.
+    ///
+    /// See [`MapGrpcStatusLayer::status_for`] for more details.
+    pub fn status_for(mut self, code: GrpcCode, status: StatusCode) -> Self {
+        self.config.statuses[code as usize] = status;
+        self
+    }
+
+    /// Replace the bodies of failed calls with a JSON document.
+    ///
+    /// See [`MapGrpcStatusLayer::json_errors`] for more details.
+    pub fn json_errors(mut self) -> Self {
+        self.config.json_errors = true;
+        self
+    }
+
+    /// Set the maximum size of bodies that are buffered to read the `grpc-status` trailer.
+    ///
+    /// See [`MapGrpcStatusLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body_size = limit;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MapGrpcStatus<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            future: self.inner.call(req),
+            config: self.config,
+            buffering: None,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`MapGrpcStatus`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        future: F,
+        config: Config,
+        buffering: Option<Buffering<B>>,
+    }
+}
+
+struct Buffering<B> {
+    parts: Parts,
+    body: Pin<Box<B>>,
+    buf: BytesMut,
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let config = this.config;
+
+        if this.buffering.is_none() {
+            let res = ready!(this.future.poll(cx))?;
+            if !is_grpc(res.headers()) {
+                return Poll::Ready(Ok(res.map(ResponseBody::new)));
+            }
+            let (mut parts, body) = res.into_parts();
+            // a trailers-only response
+            if let Some(code) = grpc_code(&parts.headers) {
+                parts.status = config.statuses[code as usize];
+                if config.json_errors && !matches!(code, GrpcCode::Ok) {
+                    let message = parts.headers.get("grpc-message").cloned();
+                    return Poll::Ready(Ok(json_error(parts, code, message.as_ref())));
+                }
+                return Poll::Ready(Ok(Response::from_parts(parts, ResponseBody::new(body))));
+            }
+            *this.buffering = Some(Buffering {
+                parts,
+                body: Box::pin(body),
+                buf: BytesMut::new(),
+            });
+        }
+
+        let buffering = this.buffering.as_mut().unwrap();
+        loop {
+            match ready!(buffering.body.as_mut().poll_data(cx)) {
+                Some(Ok(mut data)) => {
+                    if buffering.buf.len() + data.remaining() > config.max_body_size {
+                        // send the response as it is
+                        let Buffering {
+                            parts,
+                            body,
+                            mut buf,
+                        } = this.buffering.take().unwrap();
+                        buf.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                        let body = ResponseBody::buffered(buf.freeze(), Some(body), None, None);
+                        return Poll::Ready(Ok(Response::from_parts(parts, body)));
+                    }
+                    while data.has_remaining() {
+                        let chunk = data.chunk();
+                        buffering.buf.extend_from_slice(chunk);
+                        let len = chunk.len();
+                        data.advance(len);
+                    }
+                }
+                Some(Err(err)) => {
+                    let Buffering { parts, buf, .. } = this.buffering.take().unwrap();
+                    let body = ResponseBody::buffered(buf.freeze(), None, None, Some(err));
+                    return Poll::Ready(Ok(Response::from_parts(parts, body)));
+                }
+                None => break,
+            }
+        }
+
+        let trailers = match ready!(buffering.body.as_mut().poll_trailers(cx)) {
+            Ok(trailers) => trailers,
+            Err(err) => {
+                let Buffering { parts, buf, .. } = this.buffering.take().unwrap();
+                let body = ResponseBody::buffered(buf.freeze(), None, None, Some(err));
+                return Poll::Ready(Ok(Response::from_parts(parts, body)));
+            }
+        };
+        let Buffering { mut parts, buf, .. } = this.buffering.take().unwrap();
+
+        if let Some(code) = trailers.as_ref().and_then(grpc_code) {
+            parts.status = config.statuses[code as usize];
+            if config.json_errors && !matches!(code, GrpcCode::Ok) {
+                let message = trailers.as_ref().and_then(|map| map.get("grpc-message"));
+                return Poll::Ready(Ok(json_error(parts, code, message)));
+            }
+        }
+        let body = ResponseBody::buffered(buf.freeze(), None, trailers, None);
+        Poll::Ready(Ok(Response::from_parts(parts, body)))
+    }
+}
+
+impl<F, B> fmt::Debug for ResponseFuture<F, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+fn is_grpc(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("application/grpc"))
+        .map_or(false, |subtype| {
+            subtype.is_empty() || subtype.starts_with('+') || subtype.starts_with(';')
+        })
+}
+
+fn grpc_code(headers: &HeaderMap) -> Option<GrpcCode> {
+    let status = headers.get("grpc-status")?.to_str().ok()?;
+    // unknown codes are treated as `UNKNOWN`
+    Some(GrpcCode::from_i32(status.trim().parse().ok()?).unwrap_or(GrpcCode::Unknown))
+}
+
+/// Replace the body of a failed call with a JSON document.
+fn json_error<B>(
+    mut parts: Parts,
+    code: GrpcCode,
+    message: Option<&HeaderValue>,
+) -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut json = serde_json::Map::new();
+    json.insert("code".to_owned(), (code as i32).into());
+    json.insert("status".to_owned(), code.as_str().into());
+    if let Some(message) = message {
+        // messages are percent-encoded
+        let message = percent_encoding::percent_decode(message.as_bytes()).decode_utf8_lossy();
+        json.insert("message".to_owned(), message.into());
+    }
+    let body = serde_json::to_vec(&json).expect("serializing JSON cannot fail");
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, ResponseBody::buffered(body.into(), None, None, None))
+}
+
+pin_project! {
+    /// Response body for [`MapGrpcStatus`].
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        inner: ResponseBodyInner<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyProj]
+    enum ResponseBodyInner<B>
+    where
+        B: Body,
+    {
+        Body {
+            #[pin]
+            body: B,
+        },
+        Buffered {
+            data: Option<Bytes>,
+            // the rest of the body if it was too large to be buffered
+            rest: Option<Pin<Box<B>>>,
+            trailers: Option<HeaderMap>,
+            error: Option<B::Error>,
+        },
+    }
+}
+
+impl<B> ResponseBody<B>
+where
+    B: Body,
+{
+    fn new(body: B) -> Self {
+        Self {
+            inner: ResponseBodyInner::Body { body },
+        }
+    }
+
+    fn buffered(
+        data: Bytes,
+        rest: Option<Pin<Box<B>>>,
+        trailers: Option<HeaderMap>,
+        error: Option<B::Error>,
+    ) -> Self {
+        Self {
+            inner: ResponseBodyInner::Buffered {
+                data: Some(data).filter(|data| !data.is_empty()),
+                rest,
+                trailers,
+                error,
+            },
+        }
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = match self.project().inner.project() {
+            BodyProj::Body { body } => ready!(body.poll_data(cx)),
+            BodyProj::Buffered {
+                data, rest, error, ..
+            } => {
+                if let Some(data) = data.take() {
+                    return Poll::Ready(Some(Ok(data)));
+                }
+                if let Some(err) = error.take() {
+                    return Poll::Ready(Some(Err(err)));
+                }
+                match rest {
+                    Some(rest) => ready!(rest.as_mut().poll_data(cx)),
+                    None => None,
+                }
+            }
+        };
+        Poll::Ready(data.map(|data| data.map(|mut data| data.copy_to_bytes(data.remaining()))))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().inner.project() {
+            BodyProj::Body { body } => body.poll_trailers(cx),
+            BodyProj::Buffered { rest, trailers, .. } => match rest {
+                Some(rest) => rest.as_mut().poll_trailers(cx),
+                None => Poll::Ready(Ok(trailers.take())),
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            ResponseBodyInner::Body { body } => body.is_end_stream(),
+            ResponseBodyInner::Buffered {
+                data,
+                rest,
+                trailers,
+                error,
+            } => {
+                data.is_none()
+                    && error.is_none()
+                    && trailers.is_none()
+                    && rest.as_ref().map_or(true, |rest| rest.is_end_stream())
+            }
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            ResponseBodyInner::Body { body } => body.size_hint(),
+            ResponseBodyInner::Buffered { data, rest, .. } => {
+                let buffered = data.as_ref().map_or(0, |data| data.len() as u64);
+                match rest {
+                    Some(rest) => {
+                        let mut hint = rest.size_hint();
+                        hint.set_lower(hint.lower() + buffered);
+                        if let Some(upper) = hint.upper() {
+                            hint.set_upper(upper + buffered);
+                        }
+                        hint
+                    }
+                    None => SizeHint::with_exact(buffered),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    /// A gRPC service sending a message followed by `trailers`.
+    fn grpc(
+        trailers: &'static [(&'static str, &'static str)],
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError> {
+        service_fn(move |_: Request<Body>| async move {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                sender.send_data(Bytes::from("message")).await.unwrap();
+                let mut map = HeaderMap::new();
+                for (name, value) in trailers {
+                    map.insert(*name, value.parse().unwrap());
+                }
+                sender.send_trailers(map).await.unwrap();
+            });
+            let res = Response::builder()
+                .header(header::CONTENT_TYPE, "application/grpc+proto")
+                .body(body)
+                .unwrap();
+            Ok(res)
+        })
+    }
+
+    #[tokio::test]
+    async fn maps_trailers() {
+        let svc = MapGrpcStatusLayer::new().layer(grpc(&[("grpc-status", "7")]));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let mut body = res.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "message");
+        assert!(body.data().await.is_none());
+        assert_eq!(body.trailers().await.unwrap().unwrap()["grpc-status"], "7");
+    }
+
+    #[tokio::test]
+    async fn json_errors() {
+        let svc = MapGrpcStatusLayer::new()
+            .json_errors()
+            .status_for(
+                GrpcCode::FailedPrecondition,
+                StatusCode::PRECONDITION_FAILED,
+            )
+            .layer(grpc(&[
+                ("grpc-status", "9"),
+                ("grpc-message", "version%20mismatch"),
+            ]));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            body,
+            r#"{"code":9,"message":"version mismatch","status":"FAILED_PRECONDITION"}"#
+        );
+
+        // successful calls are sent as they are
+        let svc = MapGrpcStatusLayer::new()
+            .json_errors()
+            .layer(grpc(&[("grpc-status", "0")]));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "message");
+    }
+
+    #[tokio::test]
+    async fn large_and_non_grpc_responses_are_unchanged() {
+        let svc = MapGrpcStatusLayer::new()
+            .max_body_size(4)
+            .layer(grpc(&[("grpc-status", "5")]));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let mut body = res.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "message");
+        assert!(body.data().await.is_none());
+        assert_eq!(body.trailers().await.unwrap().unwrap()["grpc-status"], "5");
+
+        let svc = MapGrpcStatusLayer::new().layer(service_fn(|_: Request<Body>| async {
+            let res = Response::builder()
+                .header("grpc-status", "5")
+                .body(Body::from("not grpc"))
+                .unwrap();
+            Ok::<_, BoxError>(res)
+        }));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,70 @@
+//! Normalized route templates for metrics and traces.
+//!
+//! Using the raw request path as a metric label or span field leads to unbounded cardinality
+//! since paths often contain identifiers, for example `/users/123`. Routers can instead supply the
+//! route template that matched the request, for example `/users/:id`, by inserting a
+//! [`MatchedRoute`] into the request or response extensions.
+//!
+//! The route is picked up by:
+//!
+//! - The metrics middleware, through [`DefaultMakeLabels`], as the `route` label.
+//! - [`DefaultMakeSpan`] as the `route` span field.
+//!
+//! Routers that run inside these middleware can't modify the request they see, so the route is
+//! also read from the response extensions. A route found in the response takes precedence over
+//! one found in the request.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower_http::matched_route::MatchedRoute;
+//!
+//! async fn router(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let mut res = Response::new(Body::empty());
+//!     if req.uri().path().starts_with("/users/") {
+//!         res.extensions_mut().insert(MatchedRoute::new("/users/:id"));
+//!     }
+//!     Ok(res)
+//! }
+//! ```
+//!
+//! [`DefaultMakeLabels`]: crate::metrics::DefaultMakeLabels
+//! [`DefaultMakeSpan`]: crate::trace::DefaultMakeSpan
+
+use std::{fmt, sync::Arc};
+
+/// The route template that matched a request.
+///
+/// See the [module docs](crate::matched_route) for more details.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct MatchedRoute(Arc<str>);
+
+impl MatchedRoute {
+    /// Create a new `MatchedRoute`.
+    pub fn new<T>(route: T) -> Self
+    where
+        T: Into<Arc<str>>,
+    {
+        Self(route.into())
+    }
+
+    /// Returns the route template as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for MatchedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MatchedRoute").field(&self.as_str()).finish()
+    }
+}
+
+impl fmt::Display for MatchedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,268 @@
+//! Signature bases and the structured fields of the `Signature-Input` and `Signature` headers.
+
+use http::{header, HeaderMap, HeaderName, Request};
+use std::{fmt::Write, str::FromStr};
+
+/// Returns `true` if `component` is a derived component this crate supports or a valid header
+/// name in lowercase.
+pub(super) fn is_supported(component: &str) -> bool {
+    match component {
+        "@method" | "@authority" | "@path" | "@query" | "@request-target" => true,
+        _ => HeaderName::from_str(component).map_or(false, |name| name.as_str() == component),
+    }
+}
+
+/// The value of a covered component, or `None` if the request doesn't have it.
+fn component_value<B>(req: &Request<B>, component: &str) -> Option<String> {
+    let uri = req.uri();
+    let value = match component {
+        "@method" => req.method().as_str().to_owned(),
+        "@authority" => authority(req)?,
+        "@path" => uri.path().to_owned(),
+        "@query" => format!("?{}", uri.query().unwrap_or("")),
+        "@request-target" => uri
+            .path_and_query()
+            .map_or_else(|| uri.path(), |path_and_query| path_and_query.as_str())
+            .to_owned(),
+        name => header_value(req.headers(), name)?,
+    };
+    Some(value)
+}
+
+/// The authority of the request, from the `Host` header or the URI.
+fn authority<B>(req: &Request<B>) -> Option<String> {
+    let authority = match req.headers().get(header::HOST) {
+        Some(host) => host.to_str().ok()?,
+        None => req.uri().authority()?.as_str(),
+    };
+    Some(authority.trim().to_ascii_lowercase())
+}
+
+/// The values of a header, trimmed and joined by `, `.
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    let mut values = headers.get_all(name).iter().peekable();
+    values.peek()?;
+    let values = values
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).trim().to_owned())
+        .collect::<Vec<_>>();
+    Some(values.join(", "))
+}
+
+/// Serialize the value of the `@signature-params` component: the inner list of covered
+/// components followed by the signature parameters, such as `("@method");created=1`.
+///
+/// Parameter values are written as they are, so strings have to be quoted already.
+pub(super) fn signature_params<'a, C, P>(components: C, params: P) -> String
+where
+    C: IntoIterator<Item = &'a str>,
+    P: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let components = components
+        .into_iter()
+        .map(|component| format!("\"{}\"", component))
+        .collect::<Vec<_>>();
+    let mut value = format!("({})", components.join(" "));
+    for (name, param) in params {
+        let _ = write!(value, ";{}={}", name, param);
+    }
+    value
+}
+
+/// Quote a string as a structured field string.
+pub(super) fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The signature base of a request, or the first missing component.
+pub(super) fn signature_base<'a, B, C>(
+    req: &Request<B>,
+    components: C,
+    signature_params: &str,
+) -> Result<String, String>
+where
+    C: IntoIterator<Item = &'a str>,
+{
+    let mut base = String::new();
+    for component in components {
+        let value = component_value(req, component).ok_or_else(|| component.to_owned())?;
+        let _ = writeln!(base, "\"{}\": {}", component, value);
+    }
+    let _ = write!(base, "\"@signature-params\": {}", signature_params);
+    Ok(base)
+}
+
+/// A member of the `Signature-Input` header.
+#[derive(Debug, PartialEq)]
+pub(super) struct SignatureInput {
+    pub(super) label: String,
+    pub(super) components: Vec<String>,
+    /// The parameters with their values as they were sent, e.g. strings are quoted.
+    pub(super) params: Vec<(String, String)>,
+}
+
+impl SignatureInput {
+    pub(super) fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(super) fn integer_param(&self, name: &str) -> Option<u64> {
+        self.param(name)?.parse().ok()
+    }
+
+    pub(super) fn string_param(&self, name: &str) -> Option<String> {
+        unquote(self.param(name)?)
+    }
+
+    /// The value of the `@signature-params` component.
+    pub(super) fn signature_params(&self) -> String {
+        signature_params(
+            self.components.iter().map(String::as_str),
+            self.params
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        )
+    }
+}
+
+/// Parse the `Signature-Input` headers.
+///
+/// Members that aren't inner lists of strings without parameters are skipped, since component
+/// parameters aren't supported.
+pub(super) fn parse_signature_input(headers: &HeaderMap) -> Vec<SignatureInput> {
+    dictionary(headers, &HeaderName::from_static("signature-input"))
+        .into_iter()
+        .filter_map(|(label, value)| {
+            let value = value.strip_prefix('(')?;
+            let end = value.find(')')?;
+            let components = value[..end]
+                .split(' ')
+                .filter(|component| !component.is_empty())
+                .map(unquote)
+                .collect::<Option<Vec<_>>>()?;
+            let params = value[end + 1..]
+                .split(';')
+                .filter(|param| !param.trim().is_empty())
+                .map(|param| {
+                    let (name, value) = param.trim().split_once('=')?;
+                    Some((name.to_owned(), value.to_owned()))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(SignatureInput {
+                label,
+                components,
+                params,
+            })
+        })
+        .collect()
+}
+
+/// Parse the `Signature` headers into labels and byte sequences.
+pub(super) fn parse_signature(headers: &HeaderMap) -> Vec<(String, String)> {
+    dictionary(headers, &HeaderName::from_static("signature"))
+        .into_iter()
+        .filter_map(|(label, value)| {
+            let value = value.strip_prefix(':')?.strip_suffix(':')?;
+            Some((label, value.to_owned()))
+        })
+        .collect()
+}
+
+/// Split the members of a structured field dictionary spread over the headers called `name`.
+fn dictionary(headers: &HeaderMap, name: &HeaderName) -> Vec<(String, String)> {
+    let mut members = Vec::new();
+    for value in headers.get_all(name) {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let mut start = 0;
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut push = |member: &str| {
+            if let Some((label, value)) = member.trim().split_once('=') {
+                members.push((label.trim().to_owned(), value.trim().to_owned()));
+            }
+        };
+        for (i, c) in value.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '(' if !in_string => depth += 1,
+                ')' if !in_string => depth -= 1,
+                ',' if !in_string && depth == 0 => {
+                    push(&value[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        push(&value[start..]);
+    }
+    members
+}
+
+/// Unquote a structured field string.
+fn unquote(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unquoted = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(chars.next()?),
+            '"' => return None,
+            c => unquoted.push(c),
+        }
+    }
+    Some(unquoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signature_input() {
+        let req = Request::get("/")
+            .header(
+                "signature-input",
+                "sig1=(\"@method\" \"@path\");created=1618884473;keyid=\"a,b\", sig2=()",
+            )
+            .header("signature-input", "sig3=(\"x\";sf)")
+            .body(())
+            .unwrap();
+
+        let inputs = parse_signature_input(req.headers());
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[0].label, "sig1");
+        assert_eq!(inputs[0].components, ["@method", "@path"]);
+        assert_eq!(inputs[0].integer_param("created"), Some(1618884473));
+        assert_eq!(inputs[0].string_param("keyid").as_deref(), Some("a,b"));
+        assert_eq!(
+            inputs[0].signature_params(),
+            "(\"@method\" \"@path\");created=1618884473;keyid=\"a,b\""
+        );
+        assert!(inputs[1].components.is_empty());
+    }
+
+    #[test]
+    fn supported_components() {
+        assert!(is_supported("@method"));
+        assert!(is_supported("content-type"));
+        assert!(!is_supported("Content-Type"));
+        assert!(!is_supported("@target-uri"));
+    }
+}
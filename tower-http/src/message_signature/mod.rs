@@ -0,0 +1,463 @@
+//! Middleware that signs requests and verifies their signatures with HTTP Message Signatures
+//! ([RFC 9421]).
+//!
+//! [`SignMessage`] is used by clients. It builds the signature base of a request from a list of
+//! covered components, signs it with a [`SigningKey`] and adds the `Signature-Input` and
+//! `Signature` headers, such as:
+//!
+//! ```text
+//! Signature-Input: sig1=("@method" "@authority" "@path" "@query");created=1700000000;keyid="my-key";alg="hmac-sha256"
+//! Signature: sig1=:...:
+//! ```
+//!
+//! [`VerifyMessage`] is used by servers. It looks up the key of each signature by its `keyid`
+//! with a [`ResolveKey`] and rejects requests with `401 Unauthorized` unless one of their
+//! signatures
+//!
+//! - covers all the required components,
+//! - was created recently and hasn't expired, and
+//! - is valid for the key and its algorithm.
+//!
+//! Requests that pass have a [`VerifiedSignature`] extension telling which key signed them.
+//!
+//! The derived components `@method`, `@authority`, `@path`, `@query` and `@request-target` are
+//! supported, as well as header fields. Component parameters such as `;sf` or `;req` aren't.
+//!
+//! [`HmacSha256Key`] implements the `hmac-sha256` algorithm. Other algorithms, such as
+//! `ed25519`, can be used by implementing [`SigningKey`] and [`VerifyingKey`].
+//!
+//! The body isn't covered by signatures directly. To protect it, cover the `Content-Digest`
+//! header, which can be set and validated with the [`content_digest`] middleware.
+//!
+//! [RFC 9421]: https://www.rfc-editor.org/rfc/rfc9421
+//! [`content_digest`]: crate::content_digest
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, ServiceExt};
+//! use tower_http::message_signature::{
+//!     HmacSha256Key, SignMessageLayer, VerifiedSignature, VerifyMessageLayer,
+//! };
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // only requests with a valid signature get here
+//!     let signature = req.extensions().get::<VerifiedSignature>().unwrap();
+//!     Ok(Response::new(Body::from(signature.key_id.clone())))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! let server = ServiceBuilder::new()
+//!     .layer(VerifyMessageLayer::new(|key_id: &str| match key_id {
+//!         "partner-a" => Some(HmacSha256Key::new(b"secret")),
+//!         _ => None,
+//!     }))
+//!     .service_fn(handle);
+//!
+//! let client = ServiceBuilder::new()
+//!     .layer(SignMessageLayer::new("partner-a", HmacSha256Key::new(b"secret")))
+//!     .service(server);
+//!
+//! let request = Request::post("https://example.com/payments").body(Body::empty())?;
+//! let response = client.oneshot(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+mod base;
+mod sign;
+mod verify;
+
+pub use self::{
+    sign::{ResponseFuture as SignResponseFuture, SignMessage, SignMessageLayer},
+    verify::{ResponseFuture as VerifyResponseFuture, VerifyMessage, VerifyMessageLayer},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The components covered by default.
+const DEFAULT_COMPONENTS: [&str; 4] = ["@method", "@authority", "@path", "@query"];
+
+/// A key that signs signature bases.
+pub trait SigningKey {
+    /// The name of the algorithm, sent in the `alg` parameter, such as `hmac-sha256`.
+    fn algorithm(&self) -> &str;
+
+    /// Sign `data`.
+    fn sign(&self, data: &[u8]) -> Vec<u8>;
+}
+
+/// A key that verifies signatures.
+pub trait VerifyingKey {
+    /// The name of the algorithm. Signatures with a different `alg` parameter are rejected.
+    fn algorithm(&self) -> &str;
+
+    /// Returns `true` if `signature` is a valid signature of `data`.
+    fn verify(&self, data: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Trait for looking up the keys signatures are verified with.
+///
+/// This is implemented for closures of the form `Fn(&str) -> Option<K>`, which are given the
+/// `keyid` parameter of a signature.
+pub trait ResolveKey {
+    /// The key type.
+    type Key: VerifyingKey;
+
+    /// Look up the key with the id `key_id`, or `None` if it isn't known.
+    fn resolve_key(&self, key_id: &str) -> Option<Self::Key>;
+}
+
+impl<F, K> ResolveKey for F
+where
+    F: Fn(&str) -> Option<K>,
+    K: VerifyingKey,
+{
+    type Key = K;
+
+    fn resolve_key(&self, key_id: &str) -> Option<Self::Key> {
+        self(key_id)
+    }
+}
+
+/// A shared secret for the `hmac-sha256` algorithm.
+#[derive(Clone)]
+pub struct HmacSha256Key {
+    mac: Hmac<Sha256>,
+}
+
+impl HmacSha256Key {
+    /// Create a new `HmacSha256Key` from a shared secret.
+    pub fn new<K>(secret: K) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        Self {
+            mac: Hmac::new_from_slice(secret.as_ref()).expect("HMAC accepts keys of any length"),
+        }
+    }
+}
+
+impl SigningKey for HmacSha256Key {
+    fn algorithm(&self) -> &str {
+        "hmac-sha256"
+    }
+
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = self.mac.clone();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl VerifyingKey for HmacSha256Key {
+    fn algorithm(&self) -> &str {
+        "hmac-sha256"
+    }
+
+    fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        let mut mac = self.mac.clone();
+        mac.update(data);
+        mac.verify_slice(signature).is_ok()
+    }
+}
+
+impl fmt::Debug for HmacSha256Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HmacSha256Key").finish_non_exhaustive()
+    }
+}
+
+/// Request extension added by [`VerifyMessage`] with the signature that was verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerifiedSignature {
+    /// The label of the signature, such as `sig1`.
+    pub label: String,
+    /// The `keyid` parameter of the signature.
+    pub key_id: String,
+    /// The `tag` parameter of the signature, if any.
+    pub tag: Option<String>,
+}
+
+/// The current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use http::{header, Request, Response, StatusCode};
+    use hyper::Body;
+    use std::time::Duration;
+    use tower::{service_fn, BoxError, Layer, ServiceExt};
+
+    fn resolve(key_id: &str) -> Option<HmacSha256Key> {
+        (key_id == "key").then(|| HmacSha256Key::new(b"secret"))
+    }
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let signature = req.extensions().get::<VerifiedSignature>().unwrap();
+        Ok(Response::new(Body::from(signature.key_id.clone())))
+    }
+
+    /// Sign `req` and return the signed request.
+    async fn sign(layer: SignMessageLayer, req: Request<Body>) -> Request<Body> {
+        let svc = layer.layer(service_fn(|req: Request<Body>| async move {
+            Ok::<_, BoxError>(req)
+        }));
+        svc.oneshot(req).await.unwrap()
+    }
+
+    async fn verify<R>(layer: VerifyMessageLayer<R>, req: Request<Body>) -> StatusCode
+    where
+        R: ResolveKey + Clone,
+    {
+        let res = layer.layer(service_fn(handle)).oneshot(req).await.unwrap();
+        res.status()
+    }
+
+    fn signer() -> SignMessageLayer {
+        SignMessageLayer::new("key", HmacSha256Key::new(b"secret"))
+    }
+
+    fn request() -> Request<Body> {
+        Request::post("https://example.com/payments?id=1")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    // the hmac-sha256 example of RFC 9421, appendix B.2.5
+    #[test]
+    fn signs_example_request() {
+        let req = Request::post("/foo?param=Value&Pet=dog")
+            .header(header::HOST, "example.com")
+            .header(header::DATE, "Tue, 20 Apr 2021 02:07:55 GMT")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(())
+            .unwrap();
+        let components = ["date", "@authority", "content-type"];
+        let params = base::signature_params(
+            components,
+            [
+                ("created", "1618884473"),
+                ("keyid", "\"test-shared-secret\""),
+            ],
+        );
+        let base = base::signature_base(&req, components, &params).unwrap();
+        assert_eq!(
+            base,
+            "\"date\": Tue, 20 Apr 2021 02:07:55 GMT\n\
+             \"@authority\": example.com\n\
+             \"content-type\": application/json\n\
+             \"@signature-params\": (\"date\" \"@authority\" \"content-type\")\
+             ;created=1618884473;keyid=\"test-shared-secret\""
+        );
+
+        let secret = STANDARD
+            .decode(
+                "uzvJfB4u3N0Jy4T7NZ75MDVcr8zSTInedJtkgcu46YW4XByzNJjxBdtjUkdJPBtbmHhIDi6pcl8jsasjlTMtDQ==",
+            )
+            .unwrap();
+        let signature = HmacSha256Key::new(secret).sign(base.as_bytes());
+        assert_eq!(
+            STANDARD.encode(signature),
+            "pxcQw6G3AjtMBQjwo8XzkZf/bws5LelbaMk5rGIGtE8="
+        );
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let req = sign(signer().tag("payments"), request()).await;
+        let input = req.headers()["signature-input"].to_str().unwrap();
+        assert!(
+            input.starts_with("sig1=(\"@method\" \"@authority\" \"@path\" \"@query\");created=")
+        );
+        assert!(input.ends_with(";keyid=\"key\";alg=\"hmac-sha256\";tag=\"payments\""));
+        assert!(req.headers()["signature"]
+            .to_str()
+            .unwrap()
+            .starts_with("sig1=:"));
+
+        let res = VerifyMessageLayer::new(resolve)
+            .layer(service_fn(|req: Request<Body>| async move {
+                let signature = req.extensions().get::<VerifiedSignature>().unwrap();
+                assert_eq!(signature.label, "sig1");
+                assert_eq!(signature.tag.as_deref(), Some("payments"));
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }))
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn covers_headers() {
+        let signer = signer().covered_components(["@method", "@path", "content-type"]);
+        let verifier =
+            || VerifyMessageLayer::new(resolve).required_components(["@method", "content-type"]);
+
+        let req = sign(signer.clone(), request()).await;
+        assert_eq!(verify(verifier(), req).await, StatusCode::OK);
+
+        let mut req = sign(signer.clone(), request()).await;
+        req.headers_mut()
+            .insert(header::CONTENT_TYPE, "text/plain".parse().unwrap());
+        assert_eq!(verify(verifier(), req).await, StatusCode::UNAUTHORIZED);
+
+        // the default required components aren't all covered
+        let req = sign(signer, request()).await;
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_component_is_an_error() {
+        let svc = signer().covered_components(["digest"]).layer(service_fn(
+            |req: Request<Body>| async move { Ok::<_, BoxError>(req) },
+        ));
+        let err = svc.oneshot(request()).await.unwrap_err();
+        assert!(err.to_string().contains("digest"));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_signatures() {
+        let req = sign(signer(), request()).await;
+        assert_eq!(
+            verify(
+                VerifyMessageLayer::new(|_: &str| Some(HmacSha256Key::new(b"other"))),
+                req
+            )
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // unknown key
+        let req = sign(
+            SignMessageLayer::new("other", HmacSha256Key::new(b"secret")),
+            request(),
+        )
+        .await;
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // tampered path
+        let mut req = sign(signer(), request()).await;
+        *req.uri_mut() = "https://example.com/payments?id=2".parse().unwrap();
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // unsigned
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), request()).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    /// Replace the signature of `req` with a valid one with the given parameters.
+    fn resign(req: &mut Request<Body>, created: u64, expires: Option<u64>) {
+        let created = created.to_string();
+        let expires = expires.map(|expires| expires.to_string());
+        let mut params = vec![("created", created.as_str())];
+        if let Some(expires) = &expires {
+            params.push(("expires", expires.as_str()));
+        }
+        params.push(("keyid", "\"key\""));
+        let params = base::signature_params(DEFAULT_COMPONENTS, params);
+        let base = base::signature_base(req, DEFAULT_COMPONENTS, &params).unwrap();
+        let signature = STANDARD.encode(HmacSha256Key::new(b"secret").sign(base.as_bytes()));
+        req.headers_mut().insert(
+            "signature-input",
+            format!("sig1={}", params).parse().unwrap(),
+        );
+        req.headers_mut().insert(
+            "signature",
+            format!("sig1=:{}:", signature).parse().unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_signatures() {
+        let req = sign(signer().expires_in(Duration::from_secs(60)), request()).await;
+        let input = req.headers()["signature-input"].to_str().unwrap();
+        assert!(input.contains(";expires="));
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::OK
+        );
+
+        let mut req = request();
+        resign(&mut req, now() - 10, Some(now() + 10));
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::OK
+        );
+
+        let mut req = request();
+        resign(&mut req, now() - 120, Some(now() - 60));
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_old_signatures() {
+        let mut req = request();
+        resign(&mut req, now() - 600, None);
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let mut req = request();
+        resign(&mut req, now() - 600, None);
+        let verifier = VerifyMessageLayer::new(resolve).max_age(Some(Duration::from_secs(900)));
+        assert_eq!(verify(verifier, req).await, StatusCode::OK);
+
+        // signatures from the future
+        let mut req = request();
+        resign(&mut req, now() + 600, None);
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_any_label() {
+        let req = sign(signer().label("partner"), request()).await;
+        let req = sign(
+            SignMessageLayer::new("other", HmacSha256Key::new(b"other")),
+            req,
+        )
+        .await;
+        assert_eq!(req.headers().get_all("signature-input").iter().count(), 2);
+        assert_eq!(
+            verify(VerifyMessageLayer::new(resolve), req).await,
+            StatusCode::OK
+        );
+    }
+}
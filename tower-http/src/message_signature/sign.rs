@@ -0,0 +1,326 @@
+use super::{base, now, SigningKey, DEFAULT_COMPONENTS};
+use crate::BoxError;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use http::{HeaderName, HeaderValue, Request};
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const SIGNATURE_INPUT: HeaderName = HeaderName::from_static("signature-input");
+const SIGNATURE: HeaderName = HeaderName::from_static("signature");
+
+#[derive(Clone)]
+struct Config {
+    key_id: String,
+    key: Arc<dyn SigningKey + Send + Sync>,
+    label: String,
+    components: Vec<String>,
+    expires_in: Option<Duration>,
+    tag: Option<String>,
+}
+
+impl Config {
+    fn new(key_id: &str, key: Arc<dyn SigningKey + Send + Sync>) -> Self {
+        Self {
+            key_id: key_id.to_owned(),
+            key,
+            label: "sig1".to_owned(),
+            components: DEFAULT_COMPONENTS.iter().map(|&c| c.to_owned()).collect(),
+            expires_in: None,
+            tag: None,
+        }
+    }
+
+    fn set_label(&mut self, label: &str) {
+        let valid = label.starts_with(|c: char| c.is_ascii_lowercase() || c == '*')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-.*".contains(c));
+        assert!(valid, "invalid signature label: {:?}", label);
+        self.label = label.to_owned();
+    }
+
+    fn set_components<'a, I>(&mut self, components: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        self.components = components
+            .into_iter()
+            .map(|component| {
+                assert!(
+                    base::is_supported(component),
+                    "unsupported covered component: {:?}",
+                    component
+                );
+                component.to_owned()
+            })
+            .collect();
+    }
+
+    /// Add the `Signature-Input` and `Signature` headers to a request.
+    fn sign<B>(&self, req: &mut Request<B>, created: u64) -> Result<(), BoxError> {
+        let expires = self
+            .expires_in
+            .map(|expires_in| (created + expires_in.as_secs()).to_string());
+        let created = created.to_string();
+        let key_id = base::quote(&self.key_id);
+        let alg = base::quote(self.key.algorithm());
+        let tag = self.tag.as_deref().map(base::quote);
+
+        let mut params = vec![("created", created.as_str())];
+        if let Some(expires) = &expires {
+            params.push(("expires", expires));
+        }
+        params.push(("keyid", &key_id));
+        params.push(("alg", &alg));
+        if let Some(tag) = &tag {
+            params.push(("tag", tag));
+        }
+        let components = self.components.iter().map(String::as_str);
+        let signature_params = base::signature_params(components.clone(), params);
+
+        let signature_base = base::signature_base(req, components, &signature_params)
+            .map_err(|component| format!("covered component {:?} is missing", component))?;
+        let signature = STANDARD.encode(self.key.sign(signature_base.as_bytes()));
+
+        let input = format!("{}={}", self.label, signature_params);
+        let signature = format!("{}=:{}:", self.label, signature);
+        // append the headers to keep existing signatures
+        let headers = req.headers_mut();
+        headers.append(SIGNATURE_INPUT, HeaderValue::try_from(input)?);
+        headers.append(SIGNATURE, HeaderValue::try_from(signature)?);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("key_id", &self.key_id)
+            .field("label", &self.label)
+            .field("components", &self.components)
+            .field("expires_in", &self.expires_in)
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
+
+/// Layer that applies [`SignMessage`] which signs requests with HTTP Message Signatures.
+///
+/// See the [module docs](crate::message_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct SignMessageLayer {
+    config: Arc<Config>,
+}
+
+impl SignMessageLayer {
+    /// Create a new `SignMessageLayer` signing requests with `key`, identified by `key_id`.
+    pub fn new<K>(key_id: &str, key: K) -> Self
+    where
+        K: SigningKey + Send + Sync + 'static,
+    {
+        Self {
+            config: Arc::new(Config::new(key_id, Arc::new(key))),
+        }
+    }
+
+    /// Set the label of the signature.
+    ///
+    /// Defaults to `sig1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `label` isn't a valid structured field key.
+    pub fn label(mut self, label: &str) -> Self {
+        Arc::make_mut(&mut self.config).set_label(label);
+        self
+    }
+
+    /// Set the components covered by the signature, in order.
+    ///
+    /// These are derived components, such as `@method`, or header names in lowercase. Requests
+    /// that don't have a covered header fail with an error.
+    ///
+    /// Defaults to `@method`, `@authority`, `@path` and `@query`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component isn't supported, see the [module docs](crate::message_signature).
+    pub fn covered_components<'a, I>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Arc::make_mut(&mut self.config).set_components(components);
+        self
+    }
+
+    /// Set how long signatures are valid for, which is sent in the `expires` parameter.
+    ///
+    /// By default signatures don't have an expiry time.
+    pub fn expires_in(mut self, expires_in: Duration) -> Self {
+        Arc::make_mut(&mut self.config).expires_in = Some(expires_in);
+        self
+    }
+
+    /// Set the `tag` parameter, which names the application or protocol the signature is for.
+    pub fn tag(mut self, tag: &str) -> Self {
+        Arc::make_mut(&mut self.config).tag = Some(tag.to_owned());
+        self
+    }
+}
+
+impl<S> Layer<S> for SignMessageLayer {
+    type Service = SignMessage<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SignMessage {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that signs requests with HTTP Message Signatures.
+///
+/// See the [module docs](crate::message_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct SignMessage<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> SignMessage<S> {
+    /// Create a new `SignMessage` signing requests with `key`, identified by `key_id`.
+    pub fn new<K>(inner: S, key_id: &str, key: K) -> Self
+    where
+        K: SigningKey + Send + Sync + 'static,
+    {
+        SignMessageLayer::new(key_id, key).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SignMessage` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<K>(key_id: &str, key: K) -> SignMessageLayer
+    where
+        K: SigningKey + Send + Sync + 'static,
+    {
+        SignMessageLayer::new(key_id, key)
+    }
+
+    /// Set the label of the signature.
+    ///
+    /// See [`SignMessageLayer::label`] for more details.
+    pub fn label(mut self, label: &str) -> Self {
+        Arc::make_mut(&mut self.config).set_label(label);
+        self
+    }
+
+    /// Set the components covered by the signature, in order.
+    ///
+    /// See [`SignMessageLayer::covered_components`] for more details.
+    pub fn covered_components<'a, I>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Arc::make_mut(&mut self.config).set_components(components);
+        self
+    }
+
+    /// Set how long signatures are valid for.
+    ///
+    /// See [`SignMessageLayer::expires_in`] for more details.
+    pub fn expires_in(mut self, expires_in: Duration) -> Self {
+        Arc::make_mut(&mut self.config).expires_in = Some(expires_in);
+        self
+    }
+
+    /// Set the `tag` parameter.
+    ///
+    /// See [`SignMessageLayer::tag`] for more details.
+    pub fn tag(mut self, tag: &str) -> Self {
+        Arc::make_mut(&mut self.config).tag = Some(tag.to_owned());
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for SignMessage<S>
+where
+    S: Service<Request<ReqBody>>,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let kind = match self.config.sign(&mut req, now()) {
+            Ok(()) => Kind::Future {
+                future: self.inner.call(req),
+            },
+            Err(error) => Kind::Error { error: Some(error) },
+        };
+        ResponseFuture { kind }
+    }
+}
+
+pin_project! {
+    /// Response future for [`SignMessage`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        Error {
+            error: Option<BoxError>,
+        },
+    }
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<BoxError>,
+{
+    type Output = Result<T, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future } => future.poll(cx).map_err(Into::into),
+            KindProj::Error { error } => {
+                let error = error.take().expect("future polled after completion");
+                Poll::Ready(Err(error))
+            }
+        }
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
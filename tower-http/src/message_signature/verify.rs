@@ -0,0 +1,327 @@
+use super::{
+    base::{self, SignatureInput},
+    now, ResolveKey, VerifiedSignature, VerifyingKey, DEFAULT_COMPONENTS,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use http::{Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+struct Config {
+    label: Option<String>,
+    required_components: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            label: None,
+            required_components: DEFAULT_COMPONENTS.iter().map(|&c| c.to_owned()).collect(),
+            max_age: Some(DEFAULT_MAX_AGE),
+        }
+    }
+}
+
+impl Config {
+    /// Verify the signature `input` of a request, returning the signature that was verified.
+    fn verify<B, R>(
+        &self,
+        req: &Request<B>,
+        input: &SignatureInput,
+        signatures: &[(String, String)],
+        resolver: &R,
+        now: u64,
+    ) -> Option<VerifiedSignature>
+    where
+        R: ResolveKey,
+    {
+        if self
+            .label
+            .as_ref()
+            .map_or(false, |label| *label != input.label)
+        {
+            return None;
+        }
+
+        let covers_required = self
+            .required_components
+            .iter()
+            .all(|component| input.components.contains(component));
+        let supported = input
+            .components
+            .iter()
+            .all(|component| base::is_supported(component));
+        if !covers_required || !supported {
+            return None;
+        }
+
+        let created = input.integer_param("created");
+        if let Some(max_age) = self.max_age {
+            // allow for as much clock skew as the maximum age
+            if created?.abs_diff(now) > max_age.as_secs() {
+                return None;
+            }
+        }
+        if input.param("expires").is_some() && input.integer_param("expires")? < now {
+            return None;
+        }
+
+        let key_id = input.string_param("keyid")?;
+        let key = resolver.resolve_key(&key_id)?;
+        if let Some(alg) = input.param("alg") {
+            if base::quote(key.algorithm()) != alg {
+                return None;
+            }
+        }
+
+        let (_, signature) = signatures.iter().find(|(label, _)| *label == input.label)?;
+        let signature = STANDARD.decode(signature).ok()?;
+        let components = input.components.iter().map(String::as_str);
+        let signature_base =
+            base::signature_base(req, components, &input.signature_params()).ok()?;
+        if !key.verify(signature_base.as_bytes(), &signature) {
+            return None;
+        }
+
+        Some(VerifiedSignature {
+            label: input.label.clone(),
+            key_id,
+            tag: input.string_param("tag"),
+        })
+    }
+}
+
+/// Layer that applies [`VerifyMessage`] which rejects requests without a valid HTTP Message
+/// Signature.
+///
+/// See the [module docs](crate::message_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct VerifyMessageLayer<R> {
+    resolver: R,
+    config: Arc<Config>,
+}
+
+impl<R> VerifyMessageLayer<R> {
+    /// Create a new `VerifyMessageLayer` looking up keys with `resolver`.
+    pub fn new(resolver: R) -> Self
+    where
+        R: ResolveKey,
+    {
+        Self {
+            resolver,
+            config: Arc::new(Config::default()),
+        }
+    }
+
+    /// Only verify the signature with this label.
+    ///
+    /// By default any signature is accepted as long as it is valid.
+    pub fn label(mut self, label: &str) -> Self {
+        Arc::make_mut(&mut self.config).label = Some(label.to_owned());
+        self
+    }
+
+    /// Set the components signatures have to cover.
+    ///
+    /// Defaults to `@method`, `@authority`, `@path` and `@query`.
+    pub fn required_components<'a, I>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Arc::make_mut(&mut self.config).required_components =
+            components.into_iter().map(str::to_owned).collect();
+        self
+    }
+
+    /// Set how far the `created` parameter of signatures may be from the current time, in
+    /// either direction to allow for clock skew.
+    ///
+    /// Signatures without a `created` parameter are rejected unless this is `None`. Signatures
+    /// whose `expires` parameter is in the past are always rejected.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn max_age(mut self, max_age: Option<Duration>) -> Self {
+        Arc::make_mut(&mut self.config).max_age = max_age;
+        self
+    }
+}
+
+impl<S, R> Layer<S> for VerifyMessageLayer<R>
+where
+    R: Clone,
+{
+    type Service = VerifyMessage<S, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyMessage {
+            inner,
+            resolver: self.resolver.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that rejects requests without a valid HTTP Message Signature.
+///
+/// See the [module docs](crate::message_signature) for more details.
+#[derive(Debug, Clone)]
+pub struct VerifyMessage<S, R> {
+    inner: S,
+    resolver: R,
+    config: Arc<Config>,
+}
+
+impl<S, R> VerifyMessage<S, R> {
+    /// Create a new `VerifyMessage` looking up keys with `resolver`.
+    pub fn new(inner: S, resolver: R) -> Self
+    where
+        R: ResolveKey,
+    {
+        Self {
+            inner,
+            resolver,
+            config: Arc::new(Config::default()),
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `VerifyMessage` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(resolver: R) -> VerifyMessageLayer<R>
+    where
+        R: ResolveKey,
+    {
+        VerifyMessageLayer::new(resolver)
+    }
+
+    /// Only verify the signature with this label.
+    ///
+    /// See [`VerifyMessageLayer::label`] for more details.
+    pub fn label(mut self, label: &str) -> Self {
+        Arc::make_mut(&mut self.config).label = Some(label.to_owned());
+        self
+    }
+
+    /// Set the components signatures have to cover.
+    ///
+    /// See [`VerifyMessageLayer::required_components`] for more details.
+    pub fn required_components<'a, I>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Arc::make_mut(&mut self.config).required_components =
+            components.into_iter().map(str::to_owned).collect();
+        self
+    }
+
+    /// Set how far the `created` parameter of signatures may be from the current time.
+    ///
+    /// See [`VerifyMessageLayer::max_age`] for more details.
+    pub fn max_age(mut self, max_age: Option<Duration>) -> Self {
+        Arc::make_mut(&mut self.config).max_age = max_age;
+        self
+    }
+}
+
+impl<S, R, ReqBody, ResBody> Service<Request<ReqBody>> for VerifyMessage<S, R>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    R: ResolveKey,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let signatures = base::parse_signature(req.headers());
+        let now = now();
+        let verified = base::parse_signature_input(req.headers())
+            .iter()
+            .find_map(|input| {
+                self.config
+                    .verify(&req, input, &signatures, &self.resolver, now)
+            });
+
+        let kind = match verified {
+            Some(verified) => {
+                req.extensions_mut().insert(verified);
+                Kind::Future {
+                    future: self.inner.call(req),
+                }
+            }
+            None => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::UNAUTHORIZED;
+                Kind::Rejected {
+                    response: Some(res),
+                }
+            }
+        };
+        ResponseFuture { kind }
+    }
+}
+
+pin_project! {
+    /// Response future for [`VerifyMessage`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future } => future.poll(cx),
+            KindProj::Rejected { response } => {
+                let response = response.take().expect("future polled after completion");
+                Poll::Ready(Ok(response))
+            }
+        }
+    }
+}
+
+impl<F, B> fmt::Debug for ResponseFuture<F, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
@@ -0,0 +1,303 @@
+//! Middleware that overrides the method of `POST` requests.
+//!
+//! Some clients, such as HTML forms or proxies that only pass `GET` and `POST`, cannot send
+//! requests with other methods. [`MethodOverride`] lets them send a `POST` request with the
+//! intended method in the `X-HTTP-Method-Override` header, and optionally in a `_method` query
+//! string field, and rewrites the request method before it reaches the inner service.
+//!
+//! Only `POST` requests are rewritten, and only to one of the allowed methods, which are `PUT`,
+//! `PATCH`, and `DELETE` by default. Requests with any other override are passed to the inner
+//! service unchanged. The override header is removed from rewritten requests.
+//!
+//! The form field is read from the query string only, since reading it from a form body would
+//! require buffering the body.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Method, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::method_override::MethodOverrideLayer;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     assert_eq!(req.method(), Method::DELETE);
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(MethodOverrideLayer::new().query_field("_method"))
+//!     .service_fn(handle);
+//!
+//! let request = Request::post("/users/1")
+//!     .header("x-http-method-override", "DELETE")
+//!     .body(Body::empty())?;
+//! service.ready().await?.call(request).await?;
+//!
+//! let request = Request::post("/users/1?_method=delete").body(Body::empty())?;
+//! service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header::HeaderName, Method, Request};
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_HEADER: &str = "x-http-method-override";
+
+/// Layer that applies [`MethodOverride`] which overrides the method of `POST` requests.
+///
+/// See the [module docs](crate::method_override) for an example.
+#[derive(Debug, Clone)]
+pub struct MethodOverrideLayer {
+    config: Arc<Config>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    header: HeaderName,
+    query_field: Option<String>,
+    allowed: Vec<Method>,
+}
+
+impl Config {
+    fn target(&self, uri: &http::Uri, value: Option<&[u8]>) -> Option<Method> {
+        let method = match value {
+            Some(value) => Method::from_bytes(&value.to_ascii_uppercase()).ok()?,
+            None => {
+                let field = self.query_field.as_deref()?;
+                let (_, value) = form_urlencoded::parse(uri.query()?.as_bytes())
+                    .find(|(name, _)| name == field)?;
+                Method::from_bytes(value.to_ascii_uppercase().as_bytes()).ok()?
+            }
+        };
+
+        if self.allowed.contains(&method) {
+            Some(method)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for MethodOverrideLayer {
+    fn default() -> Self {
+        Self {
+            config: Arc::new(Config {
+                header: HeaderName::from_static(DEFAULT_HEADER),
+                query_field: None,
+                allowed: vec![Method::PUT, Method::PATCH, Method::DELETE],
+            }),
+        }
+    }
+}
+
+impl MethodOverrideLayer {
+    /// Create a new `MethodOverrideLayer` that reads the `X-HTTP-Method-Override` header and
+    /// allows overriding with `PUT`, `PATCH`, and `DELETE`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the method from `header` instead of `X-HTTP-Method-Override`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        Arc::make_mut(&mut self.config).header = header;
+        self
+    }
+
+    /// Also read the method from the query string field `field`, for example `_method`.
+    ///
+    /// The header takes precedence if both are present.
+    pub fn query_field(mut self, field: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).query_field = Some(field.into());
+        self
+    }
+
+    /// Set the methods requests may be overridden with.
+    ///
+    /// Defaults to `PUT`, `PATCH`, and `DELETE`.
+    pub fn allowed_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        Arc::make_mut(&mut self.config).allowed = methods.into_iter().collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for MethodOverrideLayer {
+    type Service = MethodOverride<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MethodOverride {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that overrides the method of `POST` requests.
+///
+/// See the [module docs](crate::method_override) for an example.
+#[derive(Debug, Clone)]
+pub struct MethodOverride<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> MethodOverride<S> {
+    /// Create a new `MethodOverride` that reads the `X-HTTP-Method-Override` header and allows
+    /// overriding with `PUT`, `PATCH`, and `DELETE`.
+    pub fn new(inner: S) -> Self {
+        MethodOverrideLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `MethodOverride` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> MethodOverrideLayer {
+        MethodOverrideLayer::new()
+    }
+
+    /// Read the method from `header` instead of `X-HTTP-Method-Override`.
+    ///
+    /// See [`MethodOverrideLayer::header`] for more details.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        Arc::make_mut(&mut self.config).header = header;
+        self
+    }
+
+    /// Also read the method from the query string field `field`.
+    ///
+    /// See [`MethodOverrideLayer::query_field`] for more details.
+    pub fn query_field(mut self, field: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).query_field = Some(field.into());
+        self
+    }
+
+    /// Set the methods requests may be overridden with.
+    ///
+    /// See [`MethodOverrideLayer::allowed_methods`] for more details.
+    pub fn allowed_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        Arc::make_mut(&mut self.config).allowed = methods.into_iter().collect();
+        self
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for MethodOverride<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if req.method() == Method::POST {
+            let value = req
+                .headers()
+                .get(&self.config.header)
+                .map(|value| value.as_bytes());
+            if let Some(method) = self.config.target(req.uri(), value) {
+                *req.method_mut() = method;
+                req.headers_mut().remove(&self.config.header);
+            }
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Response;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let res = Response::builder()
+            .header("x-method", req.method().as_str())
+            .header(
+                "x-override-seen",
+                req.headers().contains_key(DEFAULT_HEADER).to_string(),
+            )
+            .body(Body::empty())
+            .unwrap();
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn overrides_post_from_header() {
+        let svc = MethodOverrideLayer::new().layer(service_fn(handle));
+
+        let req = Request::post("/")
+            .header(DEFAULT_HEADER, "patch")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "PATCH");
+        assert_eq!(res.headers()["x-override-seen"], "false");
+
+        // only `POST` requests are rewritten
+        let req = Request::get("/")
+            .header(DEFAULT_HEADER, "DELETE")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "GET");
+
+        // only to allowed methods
+        let req = Request::post("/")
+            .header(DEFAULT_HEADER, "CONNECT")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "POST");
+        assert_eq!(res.headers()["x-override-seen"], "true");
+    }
+
+    #[tokio::test]
+    async fn overrides_post_from_query_field() {
+        let svc = MethodOverrideLayer::new()
+            .query_field("_method")
+            .allowed_methods([Method::DELETE])
+            .layer(service_fn(handle));
+
+        let req = Request::post("/?a=b&_method=DELETE")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "DELETE");
+
+        let req = Request::post("/?_method=PUT").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "POST");
+
+        // the query field is ignored unless configured
+        let svc = MethodOverrideLayer::new().layer(service_fn(handle));
+        let req = Request::post("/?_method=DELETE")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-method"], "POST");
+    }
+}
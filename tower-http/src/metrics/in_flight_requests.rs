@@ -4,6 +4,12 @@
 //! of a request starts when it is received by the service (`tower::Service::call` is called) and
 //! is considered complete when the response body is consumed, dropped, or an error happens.
 //!
+//! The current count can be read at any time through [`InFlightRequestsCounter::get`], for example
+//! from a health check or metrics endpoint. The layer can also call hooks when processing of a
+//! request starts and ends, see [`InFlightRequestsLayer::on_request_start`] and
+//! [`InFlightRequestsLayer::on_request_end`]. This is useful for draining connections before
+//! shutting down or for feeding autoscaling signals.
+//!
 //! # Example
 //!
 //! ```
@@ -67,12 +73,63 @@ use std::{
 use tower_layer::Layer;
 use tower_service::Service;
 
+/// Trait used to tell [`InFlightRequests`] what to do when processing of a request starts.
+///
+/// This trait is implemented for closures with the signature `FnMut(usize)`, which receive the
+/// number of in-flight requests including the new one.
+pub trait OnRequestStart {
+    /// Do the thing.
+    fn on_request_start(&mut self, in_flight: usize);
+}
+
+impl OnRequestStart for () {
+    #[inline]
+    fn on_request_start(&mut self, _: usize) {}
+}
+
+impl<F> OnRequestStart for F
+where
+    F: FnMut(usize),
+{
+    fn on_request_start(&mut self, in_flight: usize) {
+        self(in_flight)
+    }
+}
+
+/// Trait used to tell [`InFlightRequests`] what to do when processing of a request ends.
+///
+/// Processing ends when the response body has been consumed or dropped, or when the inner service
+/// fails. It does not end when the response headers are returned.
+///
+/// This trait is implemented for closures with the signature `FnMut(usize)`, which receive the
+/// number of in-flight requests remaining.
+pub trait OnRequestEnd {
+    /// Do the thing.
+    fn on_request_end(&mut self, in_flight: usize);
+}
+
+impl OnRequestEnd for () {
+    #[inline]
+    fn on_request_end(&mut self, _: usize) {}
+}
+
+impl<F> OnRequestEnd for F
+where
+    F: FnMut(usize),
+{
+    fn on_request_end(&mut self, in_flight: usize) {
+        self(in_flight)
+    }
+}
+
 /// Layer for applying [`InFlightRequests`] which counts the number of in-flight requests.
 ///
 /// See the [module docs](crate::metrics::in_flight_requests) for more details.
 #[derive(Clone, Debug)]
-pub struct InFlightRequestsLayer {
+pub struct InFlightRequestsLayer<OnStart = (), OnEnd = ()> {
     counter: InFlightRequestsCounter,
+    on_request_start: OnStart,
+    on_request_end: OnEnd,
 }
 
 impl InFlightRequestsLayer {
@@ -85,17 +142,58 @@ impl InFlightRequestsLayer {
 
     /// Create a new `InFlightRequestsLayer` that will update the given counter.
     pub fn new(counter: InFlightRequestsCounter) -> Self {
-        Self { counter }
+        Self {
+            counter,
+            on_request_start: (),
+            on_request_end: (),
+        }
     }
 }
 
-impl<S> Layer<S> for InFlightRequestsLayer {
-    type Service = InFlightRequests<S>;
+impl<OnStart, OnEnd> InFlightRequestsLayer<OnStart, OnEnd> {
+    /// Customize what to do when processing of a request starts.
+    ///
+    /// `NewOnStart` is expected to implement [`OnRequestStart`].
+    pub fn on_request_start<NewOnStart>(
+        self,
+        new_on_request_start: NewOnStart,
+    ) -> InFlightRequestsLayer<NewOnStart, OnEnd> {
+        InFlightRequestsLayer {
+            counter: self.counter,
+            on_request_start: new_on_request_start,
+            on_request_end: self.on_request_end,
+        }
+    }
+
+    /// Customize what to do when processing of a request ends.
+    ///
+    /// `NewOnEnd` is expected to implement [`OnRequestEnd`] and [`Clone`], since it's cloned for
+    /// each request.
+    pub fn on_request_end<NewOnEnd>(
+        self,
+        new_on_request_end: NewOnEnd,
+    ) -> InFlightRequestsLayer<OnStart, NewOnEnd> {
+        InFlightRequestsLayer {
+            counter: self.counter,
+            on_request_start: self.on_request_start,
+            on_request_end: new_on_request_end,
+        }
+    }
+}
+
+impl<S, OnStart, OnEnd> Layer<S> for InFlightRequestsLayer<OnStart, OnEnd>
+where
+    OnStart: Clone,
+    OnEnd: Clone,
+{
+    type Service = InFlightRequests<S, OnStart, OnEnd>;
 
     fn layer(&self, inner: S) -> Self::Service {
         InFlightRequests {
             inner,
             counter: self.counter.clone(),
+            on_request_start: self.on_request_start.clone(),
+            on_request_end: self.on_request_end.clone(),
         }
     }
 }
@@ -104,9 +202,11 @@ impl<S> Layer<S> for InFlightRequestsLayer {
 ///
 /// See the [module docs](crate::metrics::in_flight_requests) for more details.
 #[derive(Clone, Debug)]
-pub struct InFlightRequests<S> {
+pub struct InFlightRequests<S, OnStart = (), OnEnd = ()> {
     inner: S,
     counter: InFlightRequestsCounter,
+    on_request_start: OnStart,
+    on_request_end: OnEnd,
 }
 
 impl<S> InFlightRequests<S> {
@@ -119,10 +219,48 @@ impl<S> InFlightRequests<S> {
 
     /// Create a new `InFlightRequests` that will update the given counter.
     pub fn new(inner: S, counter: InFlightRequestsCounter) -> Self {
-        Self { inner, counter }
+        Self {
+            inner,
+            counter,
+            on_request_start: (),
+            on_request_end: (),
+        }
     }
+}
 
+impl<S, OnStart, OnEnd> InFlightRequests<S, OnStart, OnEnd> {
     define_inner_service_accessors!();
+
+    /// Customize what to do when processing of a request starts.
+    ///
+    /// `NewOnStart` is expected to implement [`OnRequestStart`].
+    pub fn on_request_start<NewOnStart>(
+        self,
+        new_on_request_start: NewOnStart,
+    ) -> InFlightRequests<S, NewOnStart, OnEnd> {
+        InFlightRequests {
+            inner: self.inner,
+            counter: self.counter,
+            on_request_start: new_on_request_start,
+            on_request_end: self.on_request_end,
+        }
+    }
+
+    /// Customize what to do when processing of a request ends.
+    ///
+    /// `NewOnEnd` is expected to implement [`OnRequestEnd`] and [`Clone`], since it's cloned for
+    /// each request.
+    pub fn on_request_end<NewOnEnd>(
+        self,
+        new_on_request_end: NewOnEnd,
+    ) -> InFlightRequests<S, OnStart, NewOnEnd> {
+        InFlightRequests {
+            inner: self.inner,
+            counter: self.counter,
+            on_request_start: self.on_request_start,
+            on_request_end: new_on_request_end,
+        }
+    }
 }
 
 /// An atomic counter that keeps track of the number of in-flight requests.
@@ -145,11 +283,16 @@ impl InFlightRequestsCounter {
         self.count.load(Ordering::Relaxed)
     }
 
-    fn increment(&self) -> IncrementGuard {
-        self.count.fetch_add(1, Ordering::Relaxed);
-        IncrementGuard {
+    fn increment<OnEnd>(&self, on_request_end: OnEnd) -> (usize, IncrementGuard<OnEnd>)
+    where
+        OnEnd: OnRequestEnd,
+    {
+        let in_flight = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        let guard = IncrementGuard {
             count: self.count.clone(),
-        }
+            on_request_end,
+        };
+        (in_flight, guard)
     }
 
     /// Run a future every `interval` which receives the current number of in-flight requests.
@@ -194,30 +337,41 @@ impl InFlightRequestsCounter {
     }
 }
 
-struct IncrementGuard {
+struct IncrementGuard<OnEnd>
+where
+    OnEnd: OnRequestEnd,
+{
     count: Arc<AtomicUsize>,
+    on_request_end: OnEnd,
 }
 
-impl Drop for IncrementGuard {
+impl<OnEnd> Drop for IncrementGuard<OnEnd>
+where
+    OnEnd: OnRequestEnd,
+{
     fn drop(&mut self) {
-        self.count.fetch_sub(1, Ordering::Relaxed);
+        let in_flight = self.count.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.on_request_end.on_request_end(in_flight);
     }
 }
 
-impl<S, R, ResBody> Service<Request<R>> for InFlightRequests<S>
+impl<S, R, ResBody, OnStart, OnEnd> Service<Request<R>> for InFlightRequests<S, OnStart, OnEnd>
 where
     S: Service<Request<R>, Response = Response<ResBody>>,
+    OnStart: OnRequestStart,
+    OnEnd: OnRequestEnd + Clone,
 {
-    type Response = Response<ResponseBody<ResBody>>;
+    type Response = Response<ResponseBody<ResBody, OnEnd>>;
     type Error = S::Error;
-    type Future = ResponseFuture<S::Future>;
+    type Future = ResponseFuture<S::Future, OnEnd>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
     }
 
     fn call(&mut self, req: Request<R>) -> Self::Future {
-        let guard = self.counter.increment();
+        let (in_flight, guard) = self.counter.increment(self.on_request_end.clone());
+        self.on_request_start.on_request_start(in_flight);
         ResponseFuture {
             inner: self.inner.call(req),
             guard: Some(guard),
@@ -227,23 +381,27 @@ where
 
 pin_project! {
     /// Response future for [`InFlightRequests`].
-    pub struct ResponseFuture<F> {
+    pub struct ResponseFuture<F, OnEnd = ()>
+    where
+        OnEnd: OnRequestEnd,
+    {
         #[pin]
         inner: F,
-        guard: Option<IncrementGuard>,
+        guard: Option<IncrementGuard<OnEnd>>,
     }
 }
 
-impl<F, B, E> Future for ResponseFuture<F>
+impl<F, B, E, OnEnd> Future for ResponseFuture<F, OnEnd>
 where
     F: Future<Output = Result<Response<B>, E>>,
+    OnEnd: OnRequestEnd,
 {
-    type Output = Result<Response<ResponseBody<B>>, E>;
+    type Output = Result<Response<ResponseBody<B, OnEnd>>, E>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
         let response = ready!(this.inner.poll(cx))?;
-        let guard = this.guard.take().unwrap();
+        let guard = this.guard.take();
         let response = response.map(move |body| ResponseBody { inner: body, guard });
 
         Poll::Ready(Ok(response))
@@ -252,34 +410,45 @@ where
 
 pin_project! {
     /// Response body for [`InFlightRequests`].
-    pub struct ResponseBody<B> {
+    pub struct ResponseBody<B, OnEnd = ()>
+    where
+        OnEnd: OnRequestEnd,
+    {
         #[pin]
         inner: B,
-        guard: IncrementGuard,
+        guard: Option<IncrementGuard<OnEnd>>,
     }
 }
 
-impl<B> Body for ResponseBody<B>
+impl<B, OnEnd> Body for ResponseBody<B, OnEnd>
 where
     B: Body,
+    OnEnd: OnRequestEnd,
 {
     type Data = B::Data;
     type Error = B::Error;
 
-    #[inline]
     fn poll_data(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
-        self.project().inner.poll_data(cx)
+        let mut this = self.project();
+        let data = ready!(this.inner.as_mut().poll_data(cx));
+        // end processing as soon as the body completes, rather than when it's dropped
+        if data.is_none() && this.inner.is_end_stream() {
+            this.guard.take();
+        }
+        Poll::Ready(data)
     }
 
-    #[inline]
     fn poll_trailers(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
-        self.project().inner.poll_trailers(cx)
+        let this = self.project();
+        let trailers = ready!(this.inner.poll_trailers(cx));
+        this.guard.take();
+        Poll::Ready(trailers)
     }
 
     #[inline]
@@ -298,7 +467,7 @@ mod tests {
     #[allow(unused_imports)]
     use super::*;
     use http::Request;
-    use hyper::Body;
+    use hyper::{body::HttpBody, Body};
     use tower::{BoxError, ServiceBuilder};
 
     #[tokio::test]
@@ -329,6 +498,41 @@ mod tests {
         assert_eq!(counter.get(), 0);
     }
 
+    #[tokio::test]
+    async fn hooks() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (layer, counter) = InFlightRequestsLayer::pair();
+        let layer = layer
+            .on_request_start({
+                let events = events.clone();
+                move |in_flight| events.lock().unwrap().push(format!("start {}", in_flight))
+            })
+            .on_request_end({
+                let events = events.clone();
+                move |in_flight| events.lock().unwrap().push(format!("end {}", in_flight))
+            });
+
+        let mut service = ServiceBuilder::new().layer(layer).service_fn(echo);
+
+        let first = service.call(Request::new(Body::from("foo"))).await.unwrap();
+        let second = service.call(Request::new(Body::from("bar"))).await.unwrap();
+        assert_eq!(counter.get(), 2);
+        assert_eq!(*events.lock().unwrap(), vec!["start 1", "start 2"]);
+
+        // the end hook fires once the body completes, even if it's not dropped yet
+        let mut first = first.into_body();
+        while first.data().await.is_some() {}
+        assert_eq!(counter.get(), 1);
+        drop(second);
+        assert_eq!(counter.get(), 0);
+        drop(first);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["start 1", "start 2", "end 1", "end 0"]
+        );
+    }
+
     async fn echo(req: Request<Body>) -> Result<Response<Body>, BoxError> {
         Ok(Response::new(req.into_body()))
     }
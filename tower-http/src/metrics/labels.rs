@@ -0,0 +1,163 @@
+//! Labels attached to recorded metrics.
+
+use crate::matched_route::MatchedRoute;
+use http::{Request, Response};
+
+/// An ordered set of metric labels.
+///
+/// Produced for each request by a [`MakeLabels`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Labels {
+    pairs: Vec<(&'static str, String)>,
+}
+
+impl Labels {
+    /// Create an empty set of labels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of a label, replacing any previous value.
+    pub fn insert<V>(&mut self, name: &'static str, value: V)
+    where
+        V: Into<String>,
+    {
+        let value = value.into();
+        if let Some((_, existing)) = self.pairs.iter_mut().find(|(n, _)| *n == name) {
+            *existing = value;
+        } else {
+            self.pairs.push((name, value));
+        }
+    }
+
+    /// Set the value of a label, replacing any previous value.
+    pub fn with<V>(mut self, name: &'static str, value: V) -> Self
+    where
+        V: Into<String>,
+    {
+        self.insert(name, value);
+        self
+    }
+
+    /// Get the value of a label.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over the labels in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+    }
+
+    /// Replace a `method` label that isn't a standard method with `OTHER`, since clients can send
+    /// any number of extension methods.
+    pub(crate) fn bound_method(mut self) -> Self {
+        if let Some((_, method)) = self.pairs.iter_mut().find(|(n, _)| *n == "method") {
+            if method_label(method) == "OTHER" {
+                *method = "OTHER".to_owned();
+            }
+        }
+        self
+    }
+
+    /// Returns `true` if there are no labels.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Set the `route` label from a [`MatchedRoute`] in the response extensions, if any.
+    pub(crate) fn update_from_response<B>(&mut self, response: &Response<B>) {
+        if let Some(route) = response.extensions().get::<MatchedRoute>() {
+            self.insert("route", route.as_str());
+        }
+    }
+}
+
+/// Trait used to produce the [`Labels`] for a request.
+///
+/// Keep the number of distinct label values low. Prefer route templates over raw paths, see
+/// [`MatchedRoute`].
+pub trait MakeLabels: Clone {
+    /// Make the labels for a request.
+    fn make_labels<B>(&mut self, request: &Request<B>) -> Labels;
+}
+
+/// The default [`MakeLabels`].
+///
+/// Produces a `method` label, which is `OTHER` for methods that aren't standard, and, if the
+/// request extensions contain a [`MatchedRoute`], a `route` label. Regardless of the make labels
+/// used, the `route` label is set from a [`MatchedRoute`] in the response extensions once the
+/// response is produced.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultMakeLabels {
+    _priv: (),
+}
+
+impl DefaultMakeLabels {
+    /// Create a new `DefaultMakeLabels`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MakeLabels for DefaultMakeLabels {
+    fn make_labels<B>(&mut self, request: &Request<B>) -> Labels {
+        let mut labels = Labels::new().with("method", method_label(request.method().as_str()));
+        if let Some(route) = request.extensions().get::<MatchedRoute>() {
+            labels.insert("route", route.as_str());
+        }
+        labels
+    }
+}
+
+/// The label for a method, which is `OTHER` for methods that aren't standard.
+fn method_label(method: &str) -> &str {
+    match method {
+        "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "CONNECT" | "OPTIONS" | "TRACE" | "PATCH" => {
+            method
+        }
+        _ => "OTHER",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+
+    #[test]
+    fn default_make_labels() {
+        let mut req = Request::new(());
+        assert_eq!(
+            DefaultMakeLabels::new().make_labels(&req),
+            Labels::new().with("method", "GET")
+        );
+
+        req.extensions_mut().insert(MatchedRoute::new("/users/:id"));
+        let mut labels = DefaultMakeLabels::new().make_labels(&req);
+        assert_eq!(labels.get("route"), Some("/users/:id"));
+
+        let mut res = Response::new(());
+        res.extensions_mut()
+            .insert(MatchedRoute::new("/users/:name"));
+        labels.update_from_response(&res);
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![("method", "GET"), ("route", "/users/:name")]
+        );
+
+        let req = Request::builder()
+            .method(Method::from_bytes(b"FOO").unwrap())
+            .body(())
+            .unwrap();
+        assert_eq!(
+            DefaultMakeLabels::new().make_labels(&req),
+            Labels::new().with("method", "OTHER")
+        );
+    }
+}
@@ -3,10 +3,24 @@
 //! Supported metrics:
 //!
 //! - [In-flight requests][]: Measure the number of requests a service is currently processing.
+//! - [Prometheus][]: Record request counts, durations and response sizes and expose them in the
+//!   Prometheus text format.
+//! - [Recorder][]: Report the lifecycle of requests to a pluggable [`HttpMetricsRecorder`].
 //!
 //! [In-flight requests]: in_flight_requests
+//! [Prometheus]: prometheus
+//! [Recorder]: recorder
+
+mod labels;
 
 pub mod in_flight_requests;
+pub mod prometheus;
+pub mod recorder;
 
 #[doc(inline)]
 pub use self::in_flight_requests::{InFlightRequests, InFlightRequestsLayer};
+
+pub use self::labels::{DefaultMakeLabels, Labels, MakeLabels};
+
+#[doc(inline)]
+pub use self::recorder::{BodySize, HttpMetricsRecorder, MetricsLayer, RequestBodyMetricsLayer};
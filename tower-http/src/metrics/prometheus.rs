@@ -0,0 +1,938 @@
+//! Record HTTP metrics and expose them in the Prometheus text format.
+//!
+//! [`PrometheusLayer`] records the following metrics into a [`Registry`]:
+//!
+//! - `http_requests_total`: Counter of completed requests.
+//! - `http_request_duration_seconds`: Histogram of the time until the response head was
+//!   produced.
+//! - `http_requests_in_flight`: Gauge of requests currently being processed. A request is in
+//!   flight until its response body has been consumed or dropped.
+//! - `http_response_size_bytes`: Histogram of response body sizes.
+//!
+//! Metrics are labeled with the [`Labels`] produced by a [`MakeLabels`], [`DefaultMakeLabels`]
+//! by default, which includes the `method` and the `route` if a [`MatchedRoute`] is available.
+//! All metrics except `http_requests_in_flight` are additionally labeled by `status`.
+//!
+//! [`PrometheusService`] renders a [`Registry`] in the [Prometheus text format] and is meant to
+//! be mounted at a `/metrics` endpoint.
+//!
+//! Requests whose inner service returns an error are only tracked by the in-flight gauge since
+//! they don't have a status code.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::metrics::prometheus::{PrometheusLayer, PrometheusService, Registry};
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::from("hello")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let registry = Registry::new();
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(PrometheusLayer::new(registry.clone()))
+//!     .service_fn(handle);
+//!
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! hyper::body::to_bytes(response.into_body()).await?;
+//!
+//! // Serve the metrics, for example at `/metrics`.
+//! let metrics = PrometheusService::new(registry);
+//! let response = metrics.oneshot(Request::new(Body::empty())).await?;
+//! let text = hyper::body::to_bytes(response.into_body()).await?;
+//! assert!(std::str::from_utf8(&text)?.contains(r#"http_requests_total{method="GET",status="200"} 1"#));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+//! [`MatchedRoute`]: crate::matched_route::MatchedRoute
+
+use super::{DefaultMakeLabels, Labels, MakeLabels};
+use crate::sync::lock_ignore_poison;
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+use http::{header, HeaderValue, Request, Response};
+use http_body::{Body, Full};
+use pin_project_lite::pin_project;
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::{self, Write},
+    future::{ready as ready_future, Future, Ready},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const DEFAULT_SIZE_BUCKETS: &[f64] = &[
+    100.0,
+    1_000.0,
+    10_000.0,
+    100_000.0,
+    1_000_000.0,
+    10_000_000.0,
+];
+
+/// A registry of HTTP metrics.
+///
+/// Cloning a `Registry` is cheap and all clones share the same metrics.
+///
+/// See the [module docs](crate::metrics::prometheus) for more details.
+#[derive(Clone)]
+pub struct Registry {
+    inner: Arc<Mutex<Metrics>>,
+}
+
+impl Registry {
+    /// Create a new `Registry` with the default histogram buckets.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Metrics {
+                duration_buckets: DEFAULT_DURATION_BUCKETS.to_vec(),
+                size_buckets: DEFAULT_SIZE_BUCKETS.to_vec(),
+                requests: BTreeMap::new(),
+                in_flight: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Set the buckets, in seconds, used for the request duration histogram.
+    ///
+    /// Defaults to `[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1, 2.5, 5, 10]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a request has already been recorded, since its histograms use the old buckets.
+    pub fn with_duration_buckets<I>(self, buckets: I) -> Self
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        let mut metrics = lock_ignore_poison(&self.inner);
+        metrics.assert_unrecorded();
+        metrics.duration_buckets = sorted_buckets(buckets);
+        drop(metrics);
+        self
+    }
+
+    /// Set the buckets, in bytes, used for the response size histogram.
+    ///
+    /// Defaults to `[100, 1000, 10000, 100000, 1000000, 10000000]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a request has already been recorded, since its histograms use the old buckets.
+    pub fn with_size_buckets<I>(self, buckets: I) -> Self
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        let mut metrics = lock_ignore_poison(&self.inner);
+        metrics.assert_unrecorded();
+        metrics.size_buckets = sorted_buckets(buckets);
+        drop(metrics);
+        self
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let metrics = lock_ignore_poison(&self.inner);
+        let mut out = String::new();
+        metrics
+            .render(&mut out)
+            .expect("writing to a string cannot fail");
+        out
+    }
+
+    fn start(&self, labels: Labels) -> InFlightGuard {
+        let labels = labels.bound_method();
+        *lock_ignore_poison(&self.inner)
+            .in_flight
+            .entry(labels.clone())
+            .or_insert(0) += 1;
+
+        InFlightGuard {
+            registry: self.clone(),
+            labels,
+        }
+    }
+
+    fn record(&self, labels: Labels, status: u16, duration: Duration, size: u64) {
+        let labels = labels.bound_method();
+        let mut metrics = lock_ignore_poison(&self.inner);
+        let Metrics {
+            duration_buckets,
+            size_buckets,
+            requests,
+            ..
+        } = &mut *metrics;
+
+        let series = requests
+            .entry((labels, status))
+            .or_insert_with(|| RequestSeries {
+                count: 0,
+                duration: Histogram::new(duration_buckets.len()),
+                size: Histogram::new(size_buckets.len()),
+            });
+        series.count += 1;
+        series
+            .duration
+            .observe(duration_buckets, duration.as_secs_f64());
+        series.size.observe(size_buckets, size as f64);
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry").finish()
+    }
+}
+
+fn sorted_buckets<I>(buckets: I) -> Vec<f64>
+where
+    I: IntoIterator<Item = f64>,
+{
+    let mut buckets = buckets
+        .into_iter()
+        .filter(|bucket| bucket.is_finite())
+        .collect::<Vec<_>>();
+    buckets.sort_by(|a, b| a.partial_cmp(b).expect("buckets are finite"));
+    buckets.dedup();
+    buckets
+}
+
+struct Metrics {
+    duration_buckets: Vec<f64>,
+    size_buckets: Vec<f64>,
+    requests: BTreeMap<(Labels, u16), RequestSeries>,
+    in_flight: BTreeMap<Labels, i64>,
+}
+
+struct RequestSeries {
+    count: u64,
+    duration: Histogram,
+    size: Histogram,
+}
+
+struct Histogram {
+    // cumulative counts are computed when rendering
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: usize) -> Self {
+        Self {
+            buckets: vec![0; buckets],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, bounds: &[f64], value: f64) {
+        if let Some(idx) = bounds.iter().position(|bound| value <= *bound) {
+            if let Some(bucket) = self.buckets.get_mut(idx) {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    fn assert_unrecorded(&self) {
+        assert!(
+            self.requests.is_empty(),
+            "histogram buckets cannot be changed after requests have been recorded"
+        );
+    }
+
+    fn render(&self, out: &mut String) -> fmt::Result {
+        writeln!(
+            out,
+            "# HELP http_requests_total Total number of HTTP requests."
+        )?;
+        writeln!(out, "# TYPE http_requests_total counter")?;
+        for ((labels, status), series) in &self.requests {
+            writeln!(
+                out,
+                "http_requests_total{{{}}} {}",
+                LabelSet(labels, Some(*status), None),
+                series.count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP http_request_duration_seconds Time until the response head was produced."
+        )?;
+        writeln!(out, "# TYPE http_request_duration_seconds histogram")?;
+        for ((labels, status), series) in &self.requests {
+            render_histogram(
+                out,
+                "http_request_duration_seconds",
+                labels,
+                *status,
+                &self.duration_buckets,
+                &series.duration,
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP http_requests_in_flight Number of HTTP requests currently being processed."
+        )?;
+        writeln!(out, "# TYPE http_requests_in_flight gauge")?;
+        for (labels, count) in &self.in_flight {
+            writeln!(
+                out,
+                "http_requests_in_flight{{{}}} {}",
+                LabelSet(labels, None, None),
+                count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP http_response_size_bytes Size of HTTP response bodies."
+        )?;
+        writeln!(out, "# TYPE http_response_size_bytes histogram")?;
+        for ((labels, status), series) in &self.requests {
+            render_histogram(
+                out,
+                "http_response_size_bytes",
+                labels,
+                *status,
+                &self.size_buckets,
+                &series.size,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn render_histogram(
+    out: &mut String,
+    name: &str,
+    labels: &Labels,
+    status: u16,
+    bounds: &[f64],
+    histogram: &Histogram,
+) -> fmt::Result {
+    let mut cumulative = 0;
+    for (bound, count) in bounds.iter().zip(&histogram.buckets) {
+        cumulative += count;
+        writeln!(
+            out,
+            "{}_bucket{{{}}} {}",
+            name,
+            LabelSet(labels, Some(status), Some(*bound)),
+            cumulative
+        )?;
+    }
+    writeln!(
+        out,
+        "{}_bucket{{{}}} {}",
+        name,
+        LabelSet(labels, Some(status), Some(f64::INFINITY)),
+        histogram.count
+    )?;
+    writeln!(
+        out,
+        "{}_sum{{{}}} {}",
+        name,
+        LabelSet(labels, Some(status), None),
+        histogram.sum
+    )?;
+    writeln!(
+        out,
+        "{}_count{{{}}} {}",
+        name,
+        LabelSet(labels, Some(status), None),
+        histogram.count
+    )
+}
+
+/// Formats labels, followed by the optional `status` and `le` labels.
+struct LabelSet<'a>(&'a Labels, Option<u16>, Option<f64>);
+
+impl fmt::Display for LabelSet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut sep = |f: &mut fmt::Formatter<'_>| {
+            if !std::mem::take(&mut first) {
+                f.write_char(',')?;
+            }
+            Ok(())
+        };
+
+        for (name, value) in self.0.iter() {
+            sep(f)?;
+            write!(f, "{}=\"{}\"", name, Escaped(value))?;
+        }
+        if let Some(status) = self.1 {
+            sep(f)?;
+            write!(f, "status=\"{}\"", status)?;
+        }
+        if let Some(le) = self.2 {
+            sep(f)?;
+            if le.is_infinite() {
+                f.write_str("le=\"+Inf\"")?;
+            } else {
+                write!(f, "le=\"{}\"", le)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a label value as required by the text format.
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct InFlightGuard {
+    registry: Registry,
+    labels: Labels,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(count) = lock_ignore_poison(&self.registry.inner)
+            .in_flight
+            .get_mut(&self.labels)
+        {
+            *count -= 1;
+        }
+    }
+}
+
+/// Layer that applies the [`Prometheus`] middleware which records HTTP metrics.
+///
+/// See the [module docs](crate::metrics::prometheus) for more details.
+#[derive(Clone, Debug)]
+pub struct PrometheusLayer<M = DefaultMakeLabels> {
+    registry: Registry,
+    make_labels: M,
+}
+
+impl PrometheusLayer {
+    /// Create a new `PrometheusLayer` that records into `registry`.
+    pub fn new(registry: Registry) -> Self {
+        Self {
+            registry,
+            make_labels: DefaultMakeLabels::new(),
+        }
+    }
+}
+
+impl<M> PrometheusLayer<M> {
+    /// Customize the labels recorded for each request.
+    ///
+    /// Defaults to [`DefaultMakeLabels`].
+    pub fn make_labels_with<NewM>(self, make_labels: NewM) -> PrometheusLayer<NewM>
+    where
+        NewM: MakeLabels,
+    {
+        PrometheusLayer {
+            registry: self.registry,
+            make_labels,
+        }
+    }
+}
+
+impl<S, M> Layer<S> for PrometheusLayer<M>
+where
+    M: Clone,
+{
+    type Service = Prometheus<S, M>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Prometheus {
+            inner,
+            registry: self.registry.clone(),
+            make_labels: self.make_labels.clone(),
+        }
+    }
+}
+
+/// Middleware that records HTTP metrics into a [`Registry`].
+///
+/// See the [module docs](crate::metrics::prometheus) for more details.
+#[derive(Clone, Debug)]
+pub struct Prometheus<S, M = DefaultMakeLabels> {
+    inner: S,
+    registry: Registry,
+    make_labels: M,
+}
+
+impl<S> Prometheus<S> {
+    /// Create a new `Prometheus` that records into `registry`.
+    pub fn new(inner: S, registry: Registry) -> Self {
+        Self {
+            inner,
+            registry,
+            make_labels: DefaultMakeLabels::new(),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `Prometheus` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(registry: Registry) -> PrometheusLayer {
+        PrometheusLayer::new(registry)
+    }
+}
+
+impl<S, M> Prometheus<S, M> {
+    define_inner_service_accessors!();
+
+    /// Customize the labels recorded for each request.
+    ///
+    /// Defaults to [`DefaultMakeLabels`].
+    pub fn make_labels_with<NewM>(self, make_labels: NewM) -> Prometheus<S, NewM>
+    where
+        NewM: MakeLabels,
+    {
+        Prometheus {
+            inner: self.inner,
+            registry: self.registry,
+            make_labels,
+        }
+    }
+}
+
+impl<S, M, ReqBody, ResBody> Service<Request<ReqBody>> for Prometheus<S, M>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    M: MakeLabels,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let labels = self.make_labels.make_labels(&req);
+        let guard = self.registry.start(labels.clone());
+        ResponseFuture {
+            inner: self.inner.call(req),
+            state: Some(FutureState {
+                guard,
+                labels,
+                start: Instant::now(),
+            }),
+        }
+    }
+}
+
+struct FutureState {
+    guard: InFlightGuard,
+    labels: Labels,
+    start: Instant,
+}
+
+pin_project! {
+    /// Response future for [`Prometheus`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        state: Option<FutureState>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.inner.poll(cx))?;
+        let state = this.state.take().expect("future polled after completion");
+
+        let mut labels = state.labels;
+        labels.update_from_response(&response);
+
+        let recorder = Recorder {
+            guard: state.guard,
+            labels,
+            status: response.status().as_u16(),
+            duration: state.start.elapsed(),
+            size: 0,
+        };
+
+        Poll::Ready(Ok(response.map(|body| ResponseBody {
+            inner: body,
+            recorder: Some(recorder),
+        })))
+    }
+}
+
+struct Recorder {
+    guard: InFlightGuard,
+    labels: Labels,
+    status: u16,
+    duration: Duration,
+    size: u64,
+}
+
+impl Recorder {
+    fn finish(self) {
+        self.guard
+            .registry
+            .record(self.labels, self.status, self.duration, self.size);
+    }
+}
+
+pin_project! {
+    /// Response body for [`Prometheus`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        inner: B,
+        recorder: Option<Recorder>,
+    }
+
+    impl<B> PinnedDrop for ResponseBody<B> {
+        fn drop(this: Pin<&mut Self>) {
+            if let Some(recorder) = this.project().recorder.take() {
+                recorder.finish();
+            }
+        }
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = ready!(this.inner.poll_data(cx));
+        match &data {
+            Some(Ok(chunk)) => {
+                if let Some(recorder) = this.recorder.as_mut() {
+                    recorder.size += chunk.remaining() as u64;
+                }
+            }
+            Some(Err(_)) => {}
+            None => {
+                if let Some(recorder) = this.recorder.take() {
+                    recorder.finish();
+                }
+            }
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let trailers = ready!(this.inner.poll_trailers(cx));
+        if let Some(recorder) = this.recorder.take() {
+            recorder.finish();
+        }
+        Poll::Ready(trailers)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Service that renders a [`Registry`] in the Prometheus text format.
+///
+/// See the [module docs](crate::metrics::prometheus) for an example.
+#[derive(Clone, Debug)]
+pub struct PrometheusService {
+    registry: Registry,
+}
+
+impl PrometheusService {
+    /// Create a new `PrometheusService` that renders `registry`.
+    pub fn new(registry: Registry) -> Self {
+        Self { registry }
+    }
+}
+
+impl<B> Service<Request<B>> for PrometheusService {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _req: Request<B>) -> Self::Future {
+        let mut res = Response::new(Full::from(self.registry.render()));
+
+        #[allow(clippy::declare_interior_mutable_const)]
+        const TEXT_FORMAT: HeaderValue = HeaderValue::from_static("text/plain; version=0.0.4");
+        res.headers_mut().insert(header::CONTENT_TYPE, TEXT_FORMAT);
+
+        ready_future(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matched_route::MatchedRoute;
+    use http::Method;
+    use hyper::Body;
+    use tower::{BoxError, ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn records_requests() {
+        let registry = Registry::new().with_size_buckets(vec![5.0, 10.0]);
+        let mut svc = ServiceBuilder::new()
+            .layer(PrometheusLayer::new(registry.clone()))
+            .service_fn(echo);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::from("foobar")))
+            .await
+            .unwrap();
+
+        // still in flight until the body is consumed
+        assert!(registry
+            .render()
+            .contains("http_requests_in_flight{method=\"GET\"} 1"));
+        assert!(!registry.render().contains("http_requests_total{"));
+
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+        let text = registry.render();
+        assert!(text.contains("http_requests_in_flight{method=\"GET\"} 0"));
+        assert!(text.contains("http_requests_total{method=\"GET\",status=\"200\"} 1"));
+        assert!(
+            text.contains("http_request_duration_seconds_count{method=\"GET\",status=\"200\"} 1")
+        );
+        assert!(text
+            .contains("http_response_size_bytes_bucket{method=\"GET\",status=\"200\",le=\"5\"} 0"));
+        assert!(text.contains(
+            "http_response_size_bytes_bucket{method=\"GET\",status=\"200\",le=\"10\"} 1"
+        ));
+        assert!(text.contains("http_response_size_bytes_sum{method=\"GET\",status=\"200\"} 6"));
+    }
+
+    #[tokio::test]
+    async fn records_when_body_is_dropped() {
+        let registry = Registry::new();
+        let svc = ServiceBuilder::new()
+            .layer(PrometheusLayer::new(registry.clone()))
+            .service_fn(echo);
+
+        let res = svc
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .body(Body::from("foobar"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        drop(res);
+
+        let text = registry.render();
+        assert!(text.contains("http_requests_in_flight{method=\"POST\"} 0"));
+        assert!(text.contains("http_requests_total{method=\"POST\",status=\"200\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn bounds_methods() {
+        #[derive(Clone)]
+        struct RawMethod;
+
+        impl MakeLabels for RawMethod {
+            fn make_labels<B>(&mut self, request: &Request<B>) -> Labels {
+                Labels::new().with("method", request.method().as_str())
+            }
+        }
+
+        let registry = Registry::new();
+        for make_labels in [None, Some(RawMethod)] {
+            let layer = PrometheusLayer::new(registry.clone());
+            let req = Request::builder()
+                .method(Method::from_bytes(b"FOO").unwrap())
+                .body(Body::empty())
+                .unwrap();
+            let res = match make_labels {
+                Some(make_labels) => ServiceBuilder::new()
+                    .layer(layer.make_labels_with(make_labels))
+                    .service_fn(echo)
+                    .oneshot(req)
+                    .await
+                    .unwrap(),
+                None => ServiceBuilder::new()
+                    .layer(layer)
+                    .service_fn(echo)
+                    .oneshot(req)
+                    .await
+                    .unwrap(),
+            };
+            hyper::body::to_bytes(res.into_body()).await.unwrap();
+        }
+
+        let text = registry.render();
+        assert!(text.contains("http_requests_in_flight{method=\"OTHER\"} 0"));
+        assert!(text.contains("http_requests_total{method=\"OTHER\",status=\"200\"} 2"));
+        assert!(!text.contains("FOO"));
+    }
+
+    #[tokio::test]
+    async fn labels_route_from_response() {
+        let registry = Registry::new();
+        let svc = ServiceBuilder::new()
+            .layer(PrometheusLayer::new(registry.clone()))
+            .service_fn(|_: Request<Body>| async {
+                let mut res = Response::new(Body::empty());
+                res.extensions_mut().insert(MatchedRoute::new("/users/:id"));
+                Ok::<_, BoxError>(res)
+            });
+
+        let res = svc
+            .oneshot(
+                Request::builder()
+                    .uri("/users/1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+        let text = registry.render();
+        assert!(text
+            .contains("http_requests_total{method=\"GET\",route=\"/users/:id\",status=\"200\"} 1"));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/users/:id\",status=\"200\",le=\"+Inf\"} 1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn custom_make_labels() {
+        #[derive(Clone)]
+        struct Tenant;
+
+        impl MakeLabels for Tenant {
+            fn make_labels<B>(&mut self, request: &Request<B>) -> Labels {
+                let tenant = request
+                    .headers()
+                    .get("x-tenant")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("unknown");
+                Labels::new().with("tenant", tenant)
+            }
+        }
+
+        let registry = Registry::new();
+        let svc = ServiceBuilder::new()
+            .layer(PrometheusLayer::new(registry.clone()).make_labels_with(Tenant))
+            .service_fn(echo);
+
+        let req = Request::builder()
+            .header("x-tenant", "acme")
+            .body(Body::empty())
+            .unwrap();
+        drop(svc.oneshot(req).await.unwrap());
+
+        assert!(registry
+            .render()
+            .contains("http_requests_total{tenant=\"acme\",status=\"200\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn exposition_service() {
+        let registry = Registry::new();
+        let svc = PrometheusService::new(registry);
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()["content-type"], "text/plain; version=0.0.4");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(std::str::from_utf8(&body)
+            .unwrap()
+            .contains("# TYPE http_requests_total counter"));
+    }
+
+    #[tokio::test]
+    #[should_panic(
+        expected = "histogram buckets cannot be changed after requests have been recorded"
+    )]
+    async fn buckets_are_frozen_once_recorded() {
+        let registry = Registry::new();
+        let svc = ServiceBuilder::new()
+            .layer(PrometheusLayer::new(registry.clone()))
+            .service_fn(echo);
+        drop(svc.oneshot(Request::new(Body::empty())).await.unwrap());
+
+        registry.with_size_buckets(vec![5.0, 10.0]);
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(Escaped("a\"b\\c\nd").to_string(), "a\\\"b\\\\c\\nd");
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -0,0 +1,768 @@
+//! Drive a pluggable metrics backend.
+//!
+//! [`MetricsLayer`] calls an [`HttpMetricsRecorder`] at each step of a request's lifecycle. The
+//! recorder decides what to do with that information, which makes it possible to report metrics
+//! to any backend (the [`metrics`] crate, StatsD, a custom registry, ...) without this crate
+//! depending on it.
+//!
+//! The recorder is cloned for each request so it can keep per-request state, such as labels
+//! derived from the request, between callbacks.
+//!
+//! The response body is wrapped so the number of bytes written to the response can be reported.
+//! Once it has been fully streamed its total size is reported as a [`BodySize`], together with
+//! the declared `Content-Length`, so recorders can also detect bodies that didn't match their
+//! declared length. The request body is passed through unchanged; add a
+//! [`RequestBodyMetricsLayer`] to report how much of it the inner service read.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::metrics::{HttpMetricsRecorder, MetricsLayer};
+//!
+//! #[derive(Clone, Default)]
+//! struct StatsdRecorder {
+//!     method: Option<String>,
+//! }
+//!
+//! impl HttpMetricsRecorder for StatsdRecorder {
+//!     fn on_request<B>(&mut self, request: &Request<B>) {
+//!         self.method = Some(request.method().to_string());
+//!     }
+//!
+//!     fn on_response(&mut self, status: StatusCode, latency: Duration) {
+//!         // send `self.method`, `status` and `latency` to StatsD
+//!     }
+//! }
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(MetricsLayer::new(StatsdRecorder::default()))
+//!     .service_fn(handle);
+//!
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`metrics`]: https://crates.io/crates/metrics
+
+use super::{DefaultMakeLabels, Labels, MakeLabels};
+use bytes::Buf;
+use futures_util::ready;
+use http::{header, HeaderMap, Request, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Trait for recording metrics about HTTP requests.
+///
+/// All methods have default implementations that do nothing so implementors only have to
+/// implement the events they're interested in.
+///
+/// A recorder is cloned for each request, before [`on_request`] is called.
+///
+/// [`on_request`]: HttpMetricsRecorder::on_request
+pub trait HttpMetricsRecorder: Clone {
+    /// Called when the request is received, before it's passed to the inner service.
+    fn on_request<B>(&mut self, request: &Request<B>) {
+        let _ = request;
+    }
+
+    /// Called with the final labels for the request, right before [`on_response`] or, if the
+    /// inner service fails, [`on_failure`].
+    ///
+    /// The labels are produced by the layer's [`MakeLabels`] and updated with the `route` from a
+    /// [`MatchedRoute`] in the response extensions, if any.
+    ///
+    /// [`on_response`]: HttpMetricsRecorder::on_response
+    /// [`on_failure`]: HttpMetricsRecorder::on_failure
+    /// [`MatchedRoute`]: crate::matched_route::MatchedRoute
+    fn on_labels(&mut self, labels: &Labels) {
+        let _ = labels;
+    }
+
+    /// Called when the inner service has produced a response.
+    ///
+    /// `latency` is the time from when the request was received until the response head was
+    /// produced.
+    fn on_response(&mut self, status: StatusCode, latency: Duration) {
+        let _ = (status, latency);
+    }
+
+    /// Called when the inner service or the response body fails.
+    ///
+    /// `latency` is the time from when the request was received until the failure.
+    fn on_failure(&mut self, kind: FailureKind, latency: Duration) {
+        let _ = (kind, latency);
+    }
+
+    /// Called for each chunk of the response body, with the size of the chunk.
+    fn on_body_bytes(&mut self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// Called when the response body has been fully streamed.
+    fn on_response_body_end(&mut self, size: BodySize) {
+        let _ = size;
+    }
+
+    /// Called for each chunk of the request body read by the inner service, with the size of the
+    /// chunk.
+    ///
+    /// Only called by [`RequestBodyMetrics`], which gives each request body its own clone of the
+    /// recorder, taken right after [`on_request`] is called.
+    ///
+    /// [`on_request`]: HttpMetricsRecorder::on_request
+    fn on_request_body_bytes(&mut self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// Called when the request body has been fully read by the inner service.
+    ///
+    /// Only called by [`RequestBodyMetrics`], and not if the inner service doesn't read the whole
+    /// body.
+    fn on_request_body_end(&mut self, size: BodySize) {
+        let _ = size;
+    }
+}
+
+/// The size of a fully streamed body, reported by [`HttpMetricsRecorder::on_request_body_end`]
+/// and [`HttpMetricsRecorder::on_response_body_end`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodySize {
+    bytes: u64,
+    content_length: Option<u64>,
+}
+
+impl BodySize {
+    /// The number of bytes streamed.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The value of the `Content-Length` header, if any.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Returns `false` if the body had a `Content-Length` header that didn't match the number of
+    /// bytes streamed.
+    pub fn matches_content_length(&self) -> bool {
+        self.content_length.map_or(true, |len| len == self.bytes)
+    }
+}
+
+/// Where a failure reported by [`HttpMetricsRecorder::on_failure`] happened.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The inner service returned an error.
+    Service,
+    /// The request body returned an error.
+    RequestBody,
+    /// The response body returned an error.
+    ResponseBody,
+}
+
+/// Layer that applies the [`Metrics`] middleware which drives an [`HttpMetricsRecorder`].
+///
+/// See the [module docs](crate::metrics::recorder) for an example.
+#[derive(Clone, Debug)]
+pub struct MetricsLayer<R, M = DefaultMakeLabels> {
+    recorder: R,
+    make_labels: M,
+}
+
+impl<R> MetricsLayer<R> {
+    /// Create a new `MetricsLayer` that reports to `recorder`.
+    pub fn new(recorder: R) -> Self
+    where
+        R: HttpMetricsRecorder,
+    {
+        Self {
+            recorder,
+            make_labels: DefaultMakeLabels::new(),
+        }
+    }
+}
+
+impl<R, M> MetricsLayer<R, M> {
+    /// Customize the labels passed to [`HttpMetricsRecorder::on_labels`].
+    ///
+    /// Defaults to [`DefaultMakeLabels`].
+    pub fn make_labels_with<NewM>(self, make_labels: NewM) -> MetricsLayer<R, NewM>
+    where
+        NewM: MakeLabels,
+    {
+        MetricsLayer {
+            recorder: self.recorder,
+            make_labels,
+        }
+    }
+}
+
+impl<S, R, M> Layer<S> for MetricsLayer<R, M>
+where
+    R: Clone,
+    M: Clone,
+{
+    type Service = Metrics<S, R, M>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Metrics {
+            inner,
+            recorder: self.recorder.clone(),
+            make_labels: self.make_labels.clone(),
+        }
+    }
+}
+
+/// Middleware that drives an [`HttpMetricsRecorder`].
+///
+/// See the [module docs](crate::metrics::recorder) for an example.
+#[derive(Clone, Debug)]
+pub struct Metrics<S, R, M = DefaultMakeLabels> {
+    inner: S,
+    recorder: R,
+    make_labels: M,
+}
+
+impl<S, R> Metrics<S, R> {
+    /// Create a new `Metrics` that reports to `recorder`.
+    pub fn new(inner: S, recorder: R) -> Self
+    where
+        R: HttpMetricsRecorder,
+    {
+        Self {
+            inner,
+            recorder,
+            make_labels: DefaultMakeLabels::new(),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `Metrics` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(recorder: R) -> MetricsLayer<R>
+    where
+        R: HttpMetricsRecorder,
+    {
+        MetricsLayer::new(recorder)
+    }
+}
+
+impl<S, R, M> Metrics<S, R, M> {
+    define_inner_service_accessors!();
+
+    /// Customize the labels passed to [`HttpMetricsRecorder::on_labels`].
+    ///
+    /// Defaults to [`DefaultMakeLabels`].
+    pub fn make_labels_with<NewM>(self, make_labels: NewM) -> Metrics<S, R, NewM>
+    where
+        NewM: MakeLabels,
+    {
+        Metrics {
+            inner: self.inner,
+            recorder: self.recorder,
+            make_labels,
+        }
+    }
+}
+
+impl<S, R, M, ReqBody, ResBody> Service<Request<ReqBody>> for Metrics<S, R, M>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    R: HttpMetricsRecorder,
+    M: MakeLabels,
+{
+    type Response = Response<ResponseBody<ResBody, R>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, R>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let start = Instant::now();
+        let mut recorder = self.recorder.clone();
+        recorder.on_request(&req);
+        let labels = self.make_labels.make_labels(&req);
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            recorder: Some((recorder, labels)),
+            start,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`Metrics`].
+    pub struct ResponseFuture<F, R> {
+        #[pin]
+        inner: F,
+        recorder: Option<(R, Labels)>,
+        start: Instant,
+    }
+}
+
+impl<F, R, B, E> Future for ResponseFuture<F, R>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    R: HttpMetricsRecorder,
+{
+    type Output = Result<Response<ResponseBody<B, R>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        let (mut recorder, mut labels) = this
+            .recorder
+            .take()
+            .expect("future polled after completion");
+        let latency = this.start.elapsed();
+
+        match result {
+            Ok(response) => {
+                labels.update_from_response(&response);
+                recorder.on_labels(&labels);
+                recorder.on_response(response.status(), latency);
+                let start = *this.start;
+                let content_length = content_length(response.headers());
+                Poll::Ready(Ok(response.map(|body| ResponseBody {
+                    inner: body,
+                    recorder,
+                    start,
+                    size: BodySize {
+                        bytes: 0,
+                        content_length,
+                    },
+                    done: false,
+                })))
+            }
+            Err(err) => {
+                recorder.on_labels(&labels);
+                recorder.on_failure(FailureKind::Service, latency);
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Response body for [`Metrics`].
+    pub struct ResponseBody<B, R> {
+        #[pin]
+        inner: B,
+        recorder: R,
+        start: Instant,
+        size: BodySize,
+        done: bool,
+    }
+}
+
+impl<B, R> Body for ResponseBody<B, R>
+where
+    B: Body,
+    R: HttpMetricsRecorder,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = ready!(this.inner.poll_data(cx));
+        match &data {
+            Some(Ok(chunk)) => {
+                let len = chunk.remaining();
+                this.size.bytes += len as u64;
+                this.recorder.on_body_bytes(len);
+            }
+            Some(Err(_)) => this
+                .recorder
+                .on_failure(FailureKind::ResponseBody, this.start.elapsed()),
+            None => {
+                if !std::mem::replace(this.done, true) {
+                    this.recorder.on_response_body_end(*this.size);
+                }
+            }
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let trailers = ready!(this.inner.poll_trailers(cx));
+        if trailers.is_err() {
+            this.recorder
+                .on_failure(FailureKind::ResponseBody, this.start.elapsed());
+        }
+        Poll::Ready(trailers)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer that applies the [`RequestBodyMetrics`] middleware which reports the size of request
+/// bodies to an [`HttpMetricsRecorder`].
+///
+/// This is separate from [`MetricsLayer`] so the request body type only changes for services that
+/// need request body metrics. Both layers can share a recorder.
+#[derive(Clone, Debug)]
+pub struct RequestBodyMetricsLayer<R> {
+    recorder: R,
+}
+
+impl<R> RequestBodyMetricsLayer<R> {
+    /// Create a new `RequestBodyMetricsLayer` that reports to `recorder`.
+    pub fn new(recorder: R) -> Self
+    where
+        R: HttpMetricsRecorder,
+    {
+        Self { recorder }
+    }
+}
+
+impl<S, R> Layer<S> for RequestBodyMetricsLayer<R>
+where
+    R: Clone,
+{
+    type Service = RequestBodyMetrics<S, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestBodyMetrics {
+            inner,
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+/// Middleware that reports the size of request bodies to an [`HttpMetricsRecorder`].
+///
+/// For each request the recorder is cloned and [`on_request`] is called, then the request body
+/// reports to [`on_request_body_bytes`], [`on_request_body_end`] and, if it fails,
+/// [`on_failure`] with [`FailureKind::RequestBody`].
+///
+/// [`on_request`]: HttpMetricsRecorder::on_request
+/// [`on_request_body_bytes`]: HttpMetricsRecorder::on_request_body_bytes
+/// [`on_request_body_end`]: HttpMetricsRecorder::on_request_body_end
+/// [`on_failure`]: HttpMetricsRecorder::on_failure
+#[derive(Clone, Debug)]
+pub struct RequestBodyMetrics<S, R> {
+    inner: S,
+    recorder: R,
+}
+
+impl<S, R> RequestBodyMetrics<S, R> {
+    /// Create a new `RequestBodyMetrics` that reports to `recorder`.
+    pub fn new(inner: S, recorder: R) -> Self
+    where
+        R: HttpMetricsRecorder,
+    {
+        Self { inner, recorder }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `RequestBodyMetrics` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(recorder: R) -> RequestBodyMetricsLayer<R>
+    where
+        R: HttpMetricsRecorder,
+    {
+        RequestBodyMetricsLayer::new(recorder)
+    }
+}
+
+impl<S, R, ReqBody> Service<Request<ReqBody>> for RequestBodyMetrics<S, R>
+where
+    S: Service<Request<RequestBody<ReqBody, R>>>,
+    R: HttpMetricsRecorder,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let start = Instant::now();
+        let mut recorder = self.recorder.clone();
+        recorder.on_request(&req);
+
+        let content_length = content_length(req.headers());
+        let req = req.map(|body| RequestBody {
+            inner: body,
+            recorder,
+            start,
+            size: BodySize {
+                bytes: 0,
+                content_length,
+            },
+            done: false,
+        });
+        self.inner.call(req)
+    }
+}
+
+pin_project! {
+    /// Request body for [`RequestBodyMetrics`].
+    pub struct RequestBody<B, R> {
+        #[pin]
+        inner: B,
+        recorder: R,
+        start: Instant,
+        size: BodySize,
+        done: bool,
+    }
+}
+
+impl<B, R> Body for RequestBody<B, R>
+where
+    B: Body,
+    R: HttpMetricsRecorder,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = ready!(this.inner.poll_data(cx));
+        match &data {
+            Some(Ok(chunk)) => {
+                let len = chunk.remaining();
+                this.size.bytes += len as u64;
+                this.recorder.on_request_body_bytes(len);
+            }
+            Some(Err(_)) => this
+                .recorder
+                .on_failure(FailureKind::RequestBody, this.start.elapsed()),
+            None => {
+                if !std::mem::replace(this.done, true) {
+                    this.recorder.on_request_body_end(*this.size);
+                }
+            }
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let trailers = ready!(this.inner.poll_trailers(cx));
+        if trailers.is_err() {
+            this.recorder
+                .on_failure(FailureKind::RequestBody, this.start.elapsed());
+        }
+        Poll::Ready(trailers)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::sync::{Arc, Mutex};
+    use tower::{BoxError, ServiceBuilder, ServiceExt};
+
+    #[derive(Clone, Default)]
+    struct TestRecorder {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl HttpMetricsRecorder for TestRecorder {
+        fn on_request<B>(&mut self, request: &Request<B>) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("request {}", request.uri()));
+        }
+
+        fn on_labels(&mut self, labels: &Labels) {
+            let labels = labels
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>();
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("labels {}", labels.join(",")));
+        }
+
+        fn on_response(&mut self, status: StatusCode, _latency: Duration) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("response {}", status.as_u16()));
+        }
+
+        fn on_failure(&mut self, kind: FailureKind, _latency: Duration) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("failure {:?}", kind));
+        }
+
+        fn on_body_bytes(&mut self, bytes: usize) {
+            self.events.lock().unwrap().push(format!("bytes {}", bytes));
+        }
+
+        fn on_response_body_end(&mut self, size: BodySize) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("response body end {}", size.bytes()));
+        }
+
+        fn on_request_body_bytes(&mut self, bytes: usize) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("request bytes {}", bytes));
+        }
+
+        fn on_request_body_end(&mut self, size: BodySize) {
+            self.events.lock().unwrap().push(format!(
+                "request body end {} {:?} {}",
+                size.bytes(),
+                size.content_length(),
+                size.matches_content_length()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn records_lifecycle() {
+        let recorder = TestRecorder::default();
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder.clone()))
+            .service_fn(echo);
+
+        let res = svc
+            .oneshot(
+                Request::builder()
+                    .uri("/foo")
+                    .body(Body::from("foobar"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                "request /foo",
+                "labels method=GET",
+                "response 200",
+                "bytes 6",
+                "response body end 6",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_service_failure() {
+        let recorder = TestRecorder::default();
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder.clone()))
+            .service_fn(|_: Request<Body>| async { Err::<Response<Body>, _>("oh no") });
+
+        assert!(svc.oneshot(Request::new(Body::empty())).await.is_err());
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec!["request /", "labels method=GET", "failure Service"]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_request_body_size() {
+        let recorder = TestRecorder::default();
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder.clone()))
+            .layer(RequestBodyMetricsLayer::new(recorder.clone()))
+            .service_fn(|req: Request<_>| async move {
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                Ok::<_, BoxError>(Response::new(Body::from(body)))
+            });
+
+        let req = Request::builder()
+            .header(header::CONTENT_LENGTH, "10")
+            .body(Body::from("foobar"))
+            .unwrap();
+        svc.oneshot(req).await.unwrap();
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                "request /",
+                "request /",
+                "request bytes 6",
+                "request body end 6 Some(10) false",
+                "labels method=GET",
+                "response 200",
+            ]
+        );
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -0,0 +1,510 @@
+//! Middleware that mirrors requests to a shadow service.
+//!
+//! [`Mirror`] sends a copy of a fraction of requests to a secondary "shadow" service, such as a
+//! new version of a backend, to test it with production traffic. The responses and errors of the
+//! shadow service are discarded and it never delays the primary service: copies are sent from a
+//! task spawned with [`tokio::spawn`] once the primary service has responded, so this middleware
+//! requires a Tokio runtime.
+//!
+//! Request bodies are wrapped in a [`ReplayBody`] that buffers them as they are sent to the
+//! primary service. Requests whose body is larger than [`MirrorLayer::max_body_size`], or that
+//! the primary service responded to before their body was sent completely, aren't mirrored.
+//! Request extensions aren't copied, since they can't be cloned.
+//!
+//! Requests are mirrored at an even rate set by [`MirrorLayer::fraction`], e.g. every tenth
+//! request for `0.1`, rather than at random. To keep a slow shadow service from piling up
+//! tasks, at most [`MirrorLayer::max_in_flight`] copies are sent at a time, and requests are
+//! not mirrored while that many are in flight. [`MirrorLayer::timeout`] additionally abandons
+//! copies that take too long.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::mirror::{MirrorLayer, ReplayBody};
+//!
+//! async fn backend(req: Request<ReplayBody<Body>>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! async fn new_backend(req: Request<ReplayBody<Body>>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         MirrorLayer::new(tower::service_fn(new_backend))
+//!             .fraction(0.25)
+//!             .max_body_size(16 * 1024)
+//!             .max_in_flight(32)
+//!             .timeout(Duration::from_secs(5)),
+//!     )
+//!     .service_fn(backend);
+//!
+//! // every fourth request is also sent to `new_backend`
+//! let request = Request::post("/items").body(Body::from("item"))?;
+//! let response = service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::replay_body::ReplayBody;
+
+use http::{Request, Response};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::ServiceExt;
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_MAX_BODY_SIZE: usize = 64 * 1024;
+const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+/// Decides which requests are mirrored.
+#[derive(Debug)]
+struct Sampler {
+    fraction: f64,
+    count: AtomicU64,
+}
+
+impl Sampler {
+    fn new(fraction: f64) -> Self {
+        Self {
+            fraction,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `true` if the next request should be mirrored.
+    fn sample(&self) -> bool {
+        if self.fraction >= 1.0 {
+            return true;
+        }
+        // mirror the request if it moves the expected number of mirrored requests past an integer
+        let n = self.count.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.fraction).floor() > (n * self.fraction).floor()
+    }
+}
+
+/// Layer that applies [`Mirror`] which sends copies of requests to a shadow service.
+///
+/// See the [module docs](crate::mirror) for an example.
+#[derive(Debug, Clone)]
+pub struct MirrorLayer<M> {
+    shadow: M,
+    fraction: f64,
+    max_body_size: usize,
+    in_flight: Arc<Semaphore>,
+    timeout: Option<Duration>,
+}
+
+impl<M> MirrorLayer<M> {
+    /// Create a new `MirrorLayer` that mirrors all requests to `shadow`.
+    pub fn new(shadow: M) -> Self {
+        Self {
+            shadow,
+            fraction: 1.0,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            in_flight: Arc::new(Semaphore::new(DEFAULT_MAX_IN_FLIGHT)),
+            timeout: None,
+        }
+    }
+
+    /// Set the fraction of requests that are mirrored, between `0.0` and `1.0`.
+    ///
+    /// Defaults to `1.0`.
+    pub fn fraction(mut self, fraction: f64) -> Self {
+        self.fraction = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the size of the largest request body that is buffered to be mirrored.
+    ///
+    /// Defaults to 64 KiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+
+    /// Set the maximum number of copies sent to the shadow service at a time.
+    ///
+    /// Requests aren't mirrored while the limit is reached. The limit is shared by all services
+    /// produced by this layer. Defaults to 64.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.in_flight = Arc::new(Semaphore::new(max));
+        self
+    }
+
+    /// Set how long the shadow service has to respond before the copy is abandoned.
+    ///
+    /// Defaults to no timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl<S, M> Layer<S> for MirrorLayer<M>
+where
+    M: Clone,
+{
+    type Service = Mirror<S, M>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Mirror {
+            inner,
+            shadow: self.shadow.clone(),
+            sampler: Arc::new(Sampler::new(self.fraction)),
+            max_body_size: self.max_body_size,
+            in_flight: self.in_flight.clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// Middleware that sends copies of requests to a shadow service.
+///
+/// See the [module docs](crate::mirror) for an example.
+#[derive(Debug, Clone)]
+pub struct Mirror<S, M> {
+    inner: S,
+    shadow: M,
+    sampler: Arc<Sampler>,
+    max_body_size: usize,
+    in_flight: Arc<Semaphore>,
+    timeout: Option<Duration>,
+}
+
+impl<S, M> Mirror<S, M> {
+    /// Create a new `Mirror` that mirrors all requests to `shadow`.
+    pub fn new(inner: S, shadow: M) -> Self {
+        Self {
+            inner,
+            shadow,
+            sampler: Arc::new(Sampler::new(1.0)),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            in_flight: Arc::new(Semaphore::new(DEFAULT_MAX_IN_FLIGHT)),
+            timeout: None,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Mirror` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(shadow: M) -> MirrorLayer<M> {
+        MirrorLayer::new(shadow)
+    }
+
+    /// Set the fraction of requests that are mirrored.
+    ///
+    /// See [`MirrorLayer::fraction`] for more details.
+    pub fn fraction(mut self, fraction: f64) -> Self {
+        self.sampler = Arc::new(Sampler::new(fraction.clamp(0.0, 1.0)));
+        self
+    }
+
+    /// Set the size of the largest request body that is buffered to be mirrored.
+    ///
+    /// See [`MirrorLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+
+    /// Set the maximum number of copies sent to the shadow service at a time.
+    ///
+    /// See [`MirrorLayer::max_in_flight`] for more details.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.in_flight = Arc::new(Semaphore::new(max));
+        self
+    }
+
+    /// Set how long the shadow service has to respond before the copy is abandoned.
+    ///
+    /// See [`MirrorLayer::timeout`] for more details.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl<S, M, ReqBody, ResBody> Service<Request<ReqBody>> for Mirror<S, M>
+where
+    S: Service<Request<ReplayBody<ReqBody>>, Response = Response<ResBody>>,
+    M: Service<Request<ReplayBody<ReqBody>>> + Clone + Send + 'static,
+    M::Future: Send,
+    ReqBody: Body + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, M, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let permit = if self.sampler.sample() {
+            self.in_flight.clone().try_acquire_owned().ok()
+        } else {
+            None
+        };
+        let permit = match permit {
+            Some(permit) => permit,
+            None => {
+                // nothing is buffered if the limit is zero
+                let req = req.map(|body| ReplayBody::new(body, 0));
+                return ResponseFuture {
+                    inner: self.inner.call(req),
+                    shadow: None,
+                };
+            }
+        };
+        let empty = req.body().size_hint().exact() == Some(0);
+        let req = req.map(|body| ReplayBody::new(body, self.max_body_size));
+        // empty bodies may never be polled, which would keep them from being replayed
+        let mut copy = Request::new(if empty {
+            ReplayBody::default()
+        } else {
+            req.body().replay()
+        });
+        *copy.method_mut() = req.method().clone();
+        *copy.uri_mut() = req.uri().clone();
+        *copy.version_mut() = req.version();
+        *copy.headers_mut() = req.headers().clone();
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            shadow: Some(Shadow {
+                service: self.shadow.clone(),
+                request: copy,
+                permit,
+                timeout: self.timeout,
+            }),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`Mirror`].
+    pub struct ResponseFuture<F, M, B> {
+        #[pin]
+        inner: F,
+        shadow: Option<Shadow<M, B>>,
+    }
+}
+
+/// A copy of a request waiting to be sent to the shadow service.
+struct Shadow<M, B> {
+    service: M,
+    request: Request<ReplayBody<B>>,
+    // released once the shadow service is done with the copy
+    permit: OwnedSemaphorePermit,
+    timeout: Option<Duration>,
+}
+
+impl<F, M, B, ResBody, E> Future for ResponseFuture<F, M, B>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+    M: Service<Request<ReplayBody<B>>> + Send + 'static,
+    M::Future: Send,
+    B: Send + 'static,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = match this.inner.poll(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        if let Some(shadow) = this.shadow.take() {
+            if shadow.request.body().is_replayable() {
+                tokio::spawn(async move {
+                    let Shadow {
+                        service,
+                        request,
+                        permit,
+                        timeout,
+                    } = shadow;
+                    let response = service.oneshot(request);
+                    match timeout {
+                        Some(timeout) => {
+                            let _ = tokio::time::timeout(timeout, response).await;
+                        }
+                        None => {
+                            let _ = response.await;
+                        }
+                    }
+                    drop(permit);
+                });
+            }
+        }
+        Poll::Ready(res)
+    }
+}
+
+impl<F, M, B> fmt::Debug for ResponseFuture<F, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("mirrored", &self.shadow.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use hyper::Body;
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+    use tower::{service_fn, BoxError};
+
+    async fn primary(req: Request<ReplayBody<Body>>) -> Result<Response<Body>, BoxError> {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok(Response::new(Body::from(body)))
+    }
+
+    type Mirrored = mpsc::UnboundedReceiver<(String, Bytes)>;
+
+    fn shadow() -> (
+        impl Service<
+                Request<ReplayBody<Body>>,
+                Response = Response<Body>,
+                Error = BoxError,
+                Future = impl Send,
+            > + Clone,
+        Mirrored,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let tx = Arc::new(Mutex::new(tx));
+        let svc = service_fn(move |req: Request<ReplayBody<Body>>| {
+            let tx = tx.clone();
+            async move {
+                let uri = req.uri().to_string();
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                tx.lock().unwrap().send((uri, body)).unwrap();
+                Ok::<_, BoxError>(Response::new(Body::from("ignored")))
+            }
+        });
+        (svc, rx)
+    }
+
+    #[tokio::test]
+    async fn mirrors_requests() {
+        let (shadow, mut mirrored) = shadow();
+        let svc = MirrorLayer::new(shadow).layer(service_fn(primary));
+
+        let req = Request::post("/a").body(Body::from("hello")).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello");
+
+        let (uri, body) = mirrored.recv().await.unwrap();
+        assert_eq!(uri, "/a");
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn mirrors_fraction() {
+        let (shadow, mut mirrored) = shadow();
+        let svc = MirrorLayer::new(shadow)
+            .fraction(0.25)
+            .layer(service_fn(primary));
+
+        for i in 0..8 {
+            let req = Request::get(format!("/{}", i)).body(Body::empty()).unwrap();
+            svc.clone().oneshot(req).await.unwrap();
+        }
+        drop(svc);
+
+        let mut uris = Vec::new();
+        while let Some((uri, _)) = mirrored.recv().await {
+            uris.push(uri);
+        }
+        assert_eq!(uris.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn skips_large_bodies() {
+        let (shadow, mut mirrored) = shadow();
+        let svc = MirrorLayer::new(shadow)
+            .max_body_size(4)
+            .layer(service_fn(primary));
+
+        let req = Request::post("/large").body(Body::from("hello")).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello");
+
+        let req = Request::post("/small").body(Body::from("hi")).unwrap();
+        svc.oneshot(req).await.unwrap();
+
+        let (uri, body) = mirrored.recv().await.unwrap();
+        assert_eq!(uri, "/small");
+        assert_eq!(body, "hi");
+    }
+
+    #[tokio::test]
+    async fn limits_in_flight_copies() {
+        let (tx, mut started) = mpsc::unbounded_channel();
+        let tx = Arc::new(Mutex::new(tx));
+        // a shadow service that never responds
+        let hanging = service_fn(move |req: Request<ReplayBody<Body>>| {
+            tx.lock().unwrap().send(req.uri().to_string()).unwrap();
+            futures_util::future::pending::<Result<Response<Body>, BoxError>>()
+        });
+        let svc = MirrorLayer::new(hanging)
+            .max_in_flight(1)
+            .timeout(Duration::from_millis(20))
+            .layer(service_fn(primary));
+
+        for uri in ["/1", "/2"] {
+            let req = Request::get(uri).body(Body::empty()).unwrap();
+            svc.clone().oneshot(req).await.unwrap();
+        }
+        assert_eq!(started.recv().await.unwrap(), "/1");
+        tokio::task::yield_now().await;
+        assert!(started.try_recv().is_err());
+
+        // the timeout releases the slot
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let req = Request::get("/3").body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap();
+        assert_eq!(started.recv().await.unwrap(), "/3");
+    }
+
+    #[test]
+    fn sampler_spreads_requests() {
+        let sampler = Sampler::new(0.1);
+        let sampled = (0..100).filter(|_| sampler.sample()).count();
+        assert_eq!(sampled, 10);
+
+        let sampler = Sampler::new(0.0);
+        assert!(!(0..100).any(|_| sampler.sample()));
+    }
+}
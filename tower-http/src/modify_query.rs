@@ -0,0 +1,332 @@
+//! Middleware that modifies the query parameters of requests.
+//!
+//! [`ModifyQueryLayer`] can remove parameters, for example to strip tracking parameters, set
+//! parameters, add defaults for missing ones, and rename them. The rest of the URI is left as
+//! is.
+//!
+//! The resulting parameters are also stored in a [`QueryParams`] request extension so inner
+//! services don't have to parse the query again.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::modify_query::{ModifyQueryLayer, QueryParams};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     assert_eq!(req.uri(), "/search?query=tower&page=1");
+//!
+//!     let params = req.extensions().get::<QueryParams>().unwrap();
+//!     assert_eq!(params.get("query"), Some("tower"));
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         ModifyQueryLayer::new()
+//!             // Strip tracking parameters.
+//!             .remove_if(|name: &str| name.starts_with("utm_"))
+//!             .rename("q", "query")
+//!             .set_default("page", "1"),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/search?q=tower&utm_source=newsletter").body(Body::empty())?;
+//! service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{uri::PathAndQuery, Request, Uri};
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The query parameters of a request, after [`ModifyQuery`] modified them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryParams(Vec<(String, String)>);
+
+impl QueryParams {
+    /// Parse the parameters of a `application/x-www-form-urlencoded` query string.
+    pub fn parse(query: &str) -> Self {
+        Self(
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect(),
+        )
+    }
+
+    /// The value of the first parameter named `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The values of all parameters named `name`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.iter()
+            .filter(move |(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Whether there's a parameter named `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Iterate over the names and values of the parameters, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Whether there are no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Serialize the parameters as a `application/x-www-form-urlencoded` query string.
+    pub fn to_query_string(&self) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&self.0)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+enum Op {
+    Remove(String),
+    RemoveIf(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+    Set(String, String),
+    SetDefault(String, String),
+    Rename(String, String),
+}
+
+impl fmt::Debug for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Remove(name) => f.debug_tuple("Remove").field(name).finish(),
+            Op::RemoveIf(_) => f.debug_tuple("RemoveIf").finish(),
+            Op::Set(name, value) => f.debug_tuple("Set").field(name).field(value).finish(),
+            Op::SetDefault(name, value) => f
+                .debug_tuple("SetDefault")
+                .field(name)
+                .field(value)
+                .finish(),
+            Op::Rename(from, to) => f.debug_tuple("Rename").field(from).field(to).finish(),
+        }
+    }
+}
+
+impl Op {
+    fn apply(&self, params: &mut Vec<(String, String)>) {
+        match self {
+            Op::Remove(name) => params.retain(|(n, _)| n != name),
+            Op::RemoveIf(predicate) => params.retain(|(n, _)| !predicate(n)),
+            Op::Set(name, value) => {
+                params.retain(|(n, _)| n != name);
+                params.push((name.clone(), value.clone()));
+            }
+            Op::SetDefault(name, value) => {
+                if !params.iter().any(|(n, _)| n == name) {
+                    params.push((name.clone(), value.clone()));
+                }
+            }
+            Op::Rename(from, to) => {
+                for (name, _) in params.iter_mut().filter(|(n, _)| n == from) {
+                    *name = to.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Layer that applies [`ModifyQuery`] which modifies the query parameters of requests.
+///
+/// See the [module docs](crate::modify_query) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct ModifyQueryLayer {
+    ops: Vec<Op>,
+}
+
+impl ModifyQueryLayer {
+    /// Create a new `ModifyQueryLayer` that doesn't modify the query.
+    ///
+    /// Modifications are applied in the order they're added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove all parameters named `name`.
+    pub fn remove(mut self, name: impl Into<String>) -> Self {
+        self.ops.push(Op::Remove(name.into()));
+        self
+    }
+
+    /// Remove all parameters whose name matches `predicate`.
+    pub fn remove_if<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.ops.push(Op::RemoveIf(Arc::new(predicate)));
+        self
+    }
+
+    /// Set the parameter `name` to `value`, replacing any existing values.
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.ops.push(Op::Set(name.into(), value.into()));
+        self
+    }
+
+    /// Add the parameter `name` with `value` if there is no parameter named `name`.
+    pub fn set_default(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.ops.push(Op::SetDefault(name.into(), value.into()));
+        self
+    }
+
+    /// Rename all parameters named `from` to `to`.
+    pub fn rename(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.ops.push(Op::Rename(from.into(), to.into()));
+        self
+    }
+}
+
+impl<S> Layer<S> for ModifyQueryLayer {
+    type Service = ModifyQuery<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ModifyQuery {
+            inner,
+            ops: self.ops.clone().into(),
+        }
+    }
+}
+
+/// Middleware that modifies the query parameters of requests.
+///
+/// See the [module docs](crate::modify_query) for an example.
+#[derive(Debug, Clone)]
+pub struct ModifyQuery<S> {
+    inner: S,
+    ops: Arc<[Op]>,
+}
+
+impl<S> ModifyQuery<S> {
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ModifyQuery` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> ModifyQueryLayer {
+        ModifyQueryLayer::new()
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ModifyQuery<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let original = QueryParams::parse(req.uri().query().unwrap_or_default());
+
+        let mut params = original.clone();
+        for op in self.ops.iter() {
+            op.apply(&mut params.0);
+        }
+
+        if params != original {
+            if let Some(uri) = set_query(req.uri(), &params) {
+                *req.uri_mut() = uri;
+            }
+        }
+
+        req.extensions_mut().insert(params);
+        self.inner.call(req)
+    }
+}
+
+fn set_query(uri: &Uri, params: &QueryParams) -> Option<Uri> {
+    let path_and_query = if params.is_empty() {
+        uri.path().parse::<PathAndQuery>()
+    } else {
+        format!("{}?{}", uri.path(), params.to_query_string()).parse::<PathAndQuery>()
+    }
+    .ok()?;
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query);
+    Uri::from_parts(parts).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    async fn call(layer: ModifyQueryLayer, uri: &str) -> Request<()> {
+        ServiceBuilder::new()
+            .layer(layer)
+            .service_fn(|req: Request<()>| async move { Ok::<_, Infallible>(req) })
+            .oneshot(Request::builder().uri(uri).body(()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn modifies_query() {
+        let layer = ModifyQueryLayer::new()
+            .remove("a")
+            .set("b", "new value")
+            .set_default("c", "3")
+            .set_default("d", "4")
+            .rename("e", "f");
+
+        let req = call(layer, "http://example.com/x?a=1&b=2&b=3&d=x&e=5").await;
+
+        assert_eq!(req.uri(), "http://example.com/x?d=x&f=5&b=new+value&c=3");
+        let params = req.extensions().get::<QueryParams>().unwrap();
+        assert_eq!(params.get("b"), Some("new value"));
+    }
+
+    #[tokio::test]
+    async fn keeps_query_if_unchanged() {
+        let layer = ModifyQueryLayer::new().remove("missing");
+
+        let req = call(layer, "/x?a=%20b").await;
+
+        assert_eq!(req.uri(), "/x?a=%20b");
+        let params = req.extensions().get::<QueryParams>().unwrap();
+        assert_eq!(params.get("a"), Some(" b"));
+    }
+
+    #[tokio::test]
+    async fn removes_empty_query() {
+        let layer = ModifyQueryLayer::new().remove_if(|name: &str| name.starts_with("utm_"));
+
+        let req = call(layer, "/x?utm_source=a&utm_medium=b").await;
+
+        assert_eq!(req.uri(), "/x");
+    }
+}
@@ -0,0 +1,595 @@
+//! Middleware that negotiates the media type of responses from the `Accept` header.
+//!
+//! [`Negotiate`] is configured with the media types the inner service can produce, in order of
+//! preference. For every request it picks the type the client prefers according to the q-values
+//! in its `Accept` header, using the configured order to break ties, and stores it in the request
+//! extensions as a [`Negotiated<MediaType>`] for the inner service to respond with. Requests
+//! without an `Accept` header get the first configured type.
+//!
+//! If the client accepts none of the types the request gets a `406 Not Acceptable` response,
+//! listing the supported types in a `text/plain` body, without reaching the inner service. All
+//! responses get `Vary: Accept`, since they depend on the `Accept` header.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::negotiate::{MediaType, Negotiated, NegotiateLayer};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let media_type = req.extensions().get::<Negotiated<MediaType>>().unwrap();
+//!     let body = match media_type.essence() {
+//!         "application/json" => r#"{"hello":"world"}"#,
+//!         _ => "<p>hello world</p>",
+//!     };
+//!     Ok(Response::builder()
+//!         .header(header::CONTENT_TYPE, media_type.to_header_value())
+//!         .body(Body::from(body))
+//!         .unwrap())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(NegotiateLayer::new(["application/json", "text/html; charset=utf-8"]))
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/")
+//!     .header(header::ACCEPT, "text/html, application/json;q=0.9")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+//! assert_eq!(response.headers()[header::VARY], "accept");
+//!
+//! let request = Request::get("/")
+//!     .header(header::ACCEPT, "image/png")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::{Body, SizeHint};
+use mime::Mime;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    ops::Deref,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The outcome of a negotiation, stored in the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated<T>(pub T);
+
+impl<T> Negotiated<T> {
+    /// Consume `self`, returning the negotiated value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Negotiated<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// A media type the inner service can produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    mime: Mime,
+    value: HeaderValue,
+}
+
+impl MediaType {
+    /// The media type without parameters, such as `text/html`.
+    pub fn essence(&self) -> &str {
+        self.mime.essence_str()
+    }
+
+    /// The parsed media type.
+    pub fn as_mime(&self) -> &Mime {
+        &self.mime
+    }
+
+    /// The media type as configured, for use as the `Content-Type` of the response.
+    pub fn to_header_value(&self) -> HeaderValue {
+        self.value.clone()
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.mime.fmt(f)
+    }
+}
+
+/// A media range from the `Accept` header with its q-value, between 0 and 1000.
+#[derive(Debug)]
+struct MediaRange<'a> {
+    type_: &'a str,
+    subtype: &'a str,
+    q: u16,
+}
+
+impl<'a> MediaRange<'a> {
+    fn parse(s: &'a str) -> Option<Self> {
+        let mut params = s.split(';');
+        let (type_, subtype) = params.next()?.trim().split_once('/')?;
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return None;
+        }
+
+        let mut q = 1000;
+        for param in params {
+            let (name, value) = param.trim().split_once('=')?;
+            if name.trim().eq_ignore_ascii_case("q") {
+                q = parse_q(value.trim())?;
+                // parameters after the q-value are accept extensions
+                break;
+            }
+        }
+        Some(Self { type_, subtype, q })
+    }
+
+    /// How specifically this range matches `mime`, if at all.
+    fn specificity(&self, mime: &Mime) -> Option<u8> {
+        if self.type_ == "*" {
+            Some(0)
+        } else if !self.type_.eq_ignore_ascii_case(mime.type_().as_str()) {
+            None
+        } else if self.subtype == "*" {
+            Some(1)
+        } else if self.subtype.eq_ignore_ascii_case(mime.subtype().as_str()) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+// Parse a q-value as specified in RFC 9110 section 12.4.2, as thousandths.
+fn parse_q(s: &str) -> Option<u16> {
+    let (int, frac) = match s.split_once('.') {
+        Some((int, frac)) => (int, frac),
+        None => (s, ""),
+    };
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let int = match int {
+        "0" => 0,
+        "1" => 1000,
+        _ => return None,
+    };
+    let frac = frac
+        .bytes()
+        .zip([100, 10, 1])
+        .map(|(b, factor)| u16::from(b - b'0') * factor)
+        .sum::<u16>();
+    Some(int + frac).filter(|q| *q <= 1000)
+}
+
+#[derive(Debug)]
+struct Config {
+    media_types: Vec<MediaType>,
+    not_acceptable: Bytes,
+}
+
+impl Config {
+    fn negotiate(&self, headers: &HeaderMap) -> Option<&MediaType> {
+        let ranges = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter(|range| !range.trim().is_empty())
+            .filter_map(MediaRange::parse)
+            .collect::<Vec<_>>();
+        if ranges.is_empty() {
+            return self.media_types.first();
+        }
+
+        let mut best: Option<(&MediaType, u16)> = None;
+        for media_type in &self.media_types {
+            // the q-value of the most specific range matching the type
+            let q = ranges
+                .iter()
+                .filter_map(|range| Some((range.specificity(&media_type.mime)?, range.q)))
+                .max_by_key(|(specificity, _)| *specificity)
+                .map_or(0, |(_, q)| q);
+            if q > 0 && best.map_or(true, |(_, best_q)| q > best_q) {
+                best = Some((media_type, q));
+            }
+        }
+        best.map(|(media_type, _)| media_type)
+    }
+}
+
+/// Layer that applies [`Negotiate`] which negotiates the media type of responses from the
+/// `Accept` header.
+///
+/// See the [module docs](crate::negotiate) for an example.
+#[derive(Debug, Clone)]
+pub struct NegotiateLayer {
+    config: Arc<Config>,
+}
+
+impl NegotiateLayer {
+    /// Create a new `NegotiateLayer` for the media types the inner service can produce, in order
+    /// of preference.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `media_types` is empty or contains an invalid media type.
+    pub fn new<I>(media_types: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let media_types = media_types
+            .into_iter()
+            .map(|media_type| {
+                let media_type = media_type.as_ref();
+                MediaType {
+                    mime: media_type.parse().expect("invalid media type"),
+                    value: HeaderValue::from_str(media_type).expect("invalid media type"),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert!(!media_types.is_empty(), "no media types to negotiate");
+
+        let mut not_acceptable = String::from("supported media types:\n");
+        for media_type in &media_types {
+            not_acceptable.push_str(media_type.essence());
+            not_acceptable.push('\n');
+        }
+
+        Self {
+            config: Arc::new(Config {
+                media_types,
+                not_acceptable: not_acceptable.into(),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for NegotiateLayer {
+    type Service = Negotiate<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Negotiate {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that negotiates the media type of responses from the `Accept` header.
+///
+/// See the [module docs](crate::negotiate) for an example.
+#[derive(Debug, Clone)]
+pub struct Negotiate<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> Negotiate<S> {
+    /// Create a new `Negotiate` for the media types the inner service can produce, in order of
+    /// preference.
+    ///
+    /// See [`NegotiateLayer::new`] for more details.
+    pub fn new<I>(inner: S, media_types: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        NegotiateLayer::new(media_types).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Negotiate` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<I>(media_types: I) -> NegotiateLayer
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        NegotiateLayer::new(media_types)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Negotiate<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let kind = match self.config.negotiate(req.headers()).cloned() {
+            Some(media_type) => {
+                req.extensions_mut().insert(Negotiated(media_type));
+                Kind::Future {
+                    future: self.inner.call(req),
+                }
+            }
+            None => Kind::NotAcceptable {
+                body: self.config.not_acceptable.clone(),
+            },
+        };
+        ResponseFuture { kind }
+    }
+}
+
+pin_project! {
+    /// Response future for [`Negotiate`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        NotAcceptable {
+            body: Bytes,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut res = match self.project().kind.project() {
+            KindProj::Future { future } => ready!(future.poll(cx))?.map(|body| ResponseBody {
+                kind: BodyKind::Body { body },
+            }),
+            KindProj::NotAcceptable { body } => {
+                let mut res = Response::new(ResponseBody {
+                    kind: BodyKind::NotAcceptable {
+                        data: Some(body.clone()),
+                    },
+                });
+                *res.status_mut() = StatusCode::NOT_ACCEPTABLE;
+                res.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                );
+                res
+            }
+        };
+
+        let varies_on_accept = res
+            .headers()
+            .get_all(header::VARY)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|name| {
+                let name = name.trim();
+                name == "*" || name.eq_ignore_ascii_case(header::ACCEPT.as_str())
+            });
+        if !varies_on_accept {
+            res.headers_mut()
+                .append(header::VARY, HeaderValue::from_static("accept"));
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+pin_project! {
+    /// Response body for [`Negotiate`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B> {
+        Body {
+            #[pin]
+            body: B,
+        },
+        NotAcceptable {
+            data: Option<Bytes>,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => {
+                let data = ready!(body.poll_data(cx));
+                Poll::Ready(
+                    data.map(|data| data.map(|mut data| data.copy_to_bytes(data.remaining()))),
+                )
+            }
+            BodyKindProj::NotAcceptable { data } => Poll::Ready(data.take().map(Ok)),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+            BodyKindProj::NotAcceptable { .. } => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Body { body } => body.is_end_stream(),
+            BodyKind::NotAcceptable { data } => data.is_none(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Body { body } => body.size_hint(),
+            BodyKind::NotAcceptable { data } => {
+                SizeHint::with_exact(data.as_ref().map_or(0, |data| data.len() as u64))
+            }
+        }
+    }
+}
+
+impl<B> fmt::Debug for ResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let media_type = req.extensions().get::<Negotiated<MediaType>>().unwrap();
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, media_type.to_header_value())
+            .body(Body::empty())
+            .unwrap();
+        Ok(res)
+    }
+
+    async fn negotiate(accept: &[&str]) -> Response<ResponseBody<Body>> {
+        let svc = NegotiateLayer::new(["application/json", "text/html", "text/plain"])
+            .layer(service_fn(handle));
+        let mut req = Request::new(Body::empty());
+        for accept in accept {
+            req.headers_mut()
+                .append(header::ACCEPT, accept.parse().unwrap());
+        }
+        svc.oneshot(req).await.unwrap()
+    }
+
+    async fn negotiated(accept: &[&str]) -> String {
+        let res = negotiate(accept).await;
+        assert_eq!(res.status(), StatusCode::OK, "{:?}", accept);
+        res.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn picks_preferred_media_type() {
+        assert_eq!(negotiated(&[]).await, "application/json");
+        assert_eq!(negotiated(&["*/*"]).await, "application/json");
+        assert_eq!(negotiated(&["text/*"]).await, "text/html");
+        assert_eq!(
+            negotiated(&["text/plain, text/*;q=0.5"]).await,
+            "text/plain"
+        );
+        assert_eq!(
+            negotiated(&["application/json;q=0.5", "text/*;q=0.8"]).await,
+            "text/html"
+        );
+        assert_eq!(
+            negotiated(&["text/html;q=0, TEXT/*;q=0.1"]).await,
+            "text/plain"
+        );
+        assert_eq!(
+            negotiated(&["*/*;q=0.1, application/json;q=0"]).await,
+            "text/html"
+        );
+        // invalid ranges are ignored
+        assert_eq!(
+            negotiated(&["text/plain;q=2, text/html;q=0.5"]).await,
+            "text/html"
+        );
+    }
+
+    #[tokio::test]
+    async fn not_acceptable() {
+        let res = negotiate(&["image/*, application/json;q=0"]).await;
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(res.headers()[header::VARY], "accept");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            body,
+            "supported media types:\napplication/json\ntext/html\ntext/plain\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn sets_vary() {
+        let svc = NegotiateLayer::new(["text/plain"]).layer(service_fn(|_: Request<Body>| async {
+            let res = Response::builder()
+                .header(header::VARY, "Accept-Encoding, Accept")
+                .body(Body::empty())
+                .unwrap();
+            Ok::<_, BoxError>(res)
+        }));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(
+            res.headers().get_all(header::VARY).iter().count(),
+            1,
+            "accept is not added twice"
+        );
+
+        let res = negotiate(&[]).await;
+        assert_eq!(res.headers()[header::VARY], "accept");
+    }
+
+    #[test]
+    fn parse_q_values() {
+        assert_eq!(parse_q("1"), Some(1000));
+        assert_eq!(parse_q("1.000"), Some(1000));
+        assert_eq!(parse_q("0.5"), Some(500));
+        assert_eq!(parse_q("0.123"), Some(123));
+        assert_eq!(parse_q("1.001"), None);
+        assert_eq!(parse_q("0.1234"), None);
+        assert_eq!(parse_q("2"), None);
+        assert_eq!(parse_q(""), None);
+    }
+}
@@ -1,7 +1,19 @@
 //! Middleware that normalizes paths.
 //!
-//! Any trailing slashes from request paths will be removed. For example, a request with `/foo/`
-//! will be changed to `/foo` before reaching the inner service.
+//! Paths can be normalized by either removing or appending trailing slashes:
+//!
+//! - [`NormalizePathLayer::trim_trailing_slash`] changes a request with `/foo/` to `/foo`.
+//! - [`NormalizePathLayer::append_trailing_slash`] changes a request with `/foo` to `/foo/`.
+//!
+//! Runs of slashes can also be merged with [`NormalizePathLayer::merge_slashes`], and `.` and
+//! `..` segments resolved with [`NormalizePathLayer::resolve_dot_segments`]. That way routing
+//! and [`ServeDir`] see the same path.
+//!
+//! By default the request is rewritten before reaching the inner service. With
+//! [`NormalizePathLayer::redirect`] the client is instead redirected to the normalized path.
+//! Since the middleware then responds on its own, which requires the response body to implement
+//! [`Default`], this changes the mode of the middleware from [`Rewrite`] to [`Respond`], as does
+//! [`NormalizePathLayer::reject_escaping_root`].
 //!
 //! # Example
 //!
@@ -35,10 +47,52 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! Redirecting to the normalized path:
+//!
+//! ```
+//! use tower_http::normalize_path::NormalizePathLayer;
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, Service, ServiceExt};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // `req.uri().path()` will always have a trailing slash
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         NormalizePathLayer::append_trailing_slash()
+//!             .redirect(StatusCode::PERMANENT_REDIRECT),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::builder()
+//!     .uri("/foo?a=1")
+//!     .body(Body::empty())?;
+//!
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+//! assert_eq!(response.headers()[header::LOCATION], "/foo/?a=1");
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ServeDir`]: crate::services::ServeDir
 
-use http::{Request, Response, Uri};
+use http::{header, HeaderValue, Request, Response, StatusCode, Uri};
+use pin_project_lite::pin_project;
 use std::{
     borrow::Cow,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
     task::{Context, Poll},
 };
 use tower_layer::Layer;
@@ -48,7 +102,87 @@ use tower_service::Service;
 ///
 /// See the [module docs](self) for more details.
 #[derive(Debug, Copy, Clone)]
-pub struct NormalizePathLayer {}
+pub struct NormalizePathLayer<M = Rewrite> {
+    config: Config,
+    _mode: PhantomData<M>,
+}
+
+/// Mode of [`NormalizePath`] in which requests are only rewritten.
+///
+/// The inner service is always called, and its future is returned as is.
+#[derive(Debug, Copy, Clone)]
+pub struct Rewrite(());
+
+/// Mode of [`NormalizePath`] in which it can respond without calling the inner service.
+///
+/// Used by [`NormalizePathLayer::redirect`] and [`NormalizePathLayer::reject_escaping_root`].
+#[derive(Debug, Copy, Clone)]
+pub struct Respond(());
+
+#[derive(Debug, Copy, Clone)]
+struct Config {
+    mode: Mode,
+    merge_slashes: bool,
+    dot_segments: Option<DotSegments>,
+    redirect: Option<StatusCode>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Mode {
+    TrimTrailingSlash,
+    AppendTrailingSlash,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum DotSegments {
+    Resolve,
+    RejectEscapingRoot,
+}
+
+impl Config {
+    fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            merge_slashes: false,
+            dot_segments: None,
+            redirect: None,
+        }
+    }
+
+    fn redirect(mut self, status: StatusCode) -> Self {
+        assert!(
+            status.is_redirection(),
+            "`NormalizePath` can only redirect with 3xx status codes"
+        );
+        self.redirect = Some(status);
+        self
+    }
+
+    /// Normalize `uri`, returning whether it was changed or `Err` if it should be rejected.
+    fn normalize(&self, uri: &mut Uri) -> Result<bool, EscapesRoot> {
+        let mut changed = false;
+
+        if self.merge_slashes && uri.path().contains("//") {
+            let new_path = merge_slashes(uri.path());
+            changed |= set_path(uri, &new_path);
+        }
+
+        if let Some(dot_segments) = self.dot_segments {
+            let (new_path, escapes_root) = remove_dot_segments(uri.path());
+            if escapes_root && dot_segments == DotSegments::RejectEscapingRoot {
+                return Err(EscapesRoot);
+            }
+            changed |= set_path(uri, &new_path);
+        }
+
+        changed |= match self.mode {
+            Mode::TrimTrailingSlash => normalize_trailing_slash(uri),
+            Mode::AppendTrailingSlash => append_trailing_slash(uri),
+        };
+
+        Ok(changed)
+    }
+}
 
 impl NormalizePathLayer {
     /// Create a new [`NormalizePathLayer`].
@@ -56,15 +190,85 @@ impl NormalizePathLayer {
     /// Any trailing slashes from request paths will be removed. For example, a request with `/foo/`
     /// will be changed to `/foo` before reaching the inner service.
     pub fn trim_trailing_slash() -> Self {
-        NormalizePathLayer {}
+        NormalizePathLayer {
+            config: Config::new(Mode::TrimTrailingSlash),
+            _mode: PhantomData,
+        }
+    }
+
+    /// Create a new [`NormalizePathLayer`].
+    ///
+    /// A trailing slash will be appended to request paths that don't have one, and multiple
+    /// trailing slashes will be merged into one. For example, a request with `/foo` will be
+    /// changed to `/foo/` before reaching the inner service.
+    pub fn append_trailing_slash() -> Self {
+        NormalizePathLayer {
+            config: Config::new(Mode::AppendTrailingSlash),
+            _mode: PhantomData,
+        }
+    }
+}
+
+impl<M> NormalizePathLayer<M> {
+    /// Merge runs of slashes into one. For example, `/foo//bar` will be changed to `/foo/bar`.
+    pub fn merge_slashes(mut self) -> Self {
+        self.config.merge_slashes = true;
+        self
+    }
+
+    /// Resolve `.` and `..` segments as described in [RFC 3986 section 5.2.4]. For example,
+    /// `/foo/./bar/../baz` will be changed to `/foo/baz`.
+    ///
+    /// `..` segments that would go above the root are dropped, so `/../foo` will be changed to
+    /// `/foo`. Use [`reject_escaping_root`](Self::reject_escaping_root) to reject such paths
+    /// instead.
+    ///
+    /// Percent-encoded dots (`%2E`) are treated like dots.
+    ///
+    /// [RFC 3986 section 5.2.4]: https://www.rfc-editor.org/rfc/rfc3986#section-5.2.4
+    pub fn resolve_dot_segments(mut self) -> Self {
+        self.config.dot_segments = Some(DotSegments::Resolve);
+        self
+    }
+
+    /// Resolve `.` and `..` segments like [`resolve_dot_segments`](Self::resolve_dot_segments),
+    /// but respond with `400 Bad Request` if a `..` segment would go above the root.
+    ///
+    /// This requires the response body to implement [`Default`].
+    pub fn reject_escaping_root(mut self) -> NormalizePathLayer<Respond> {
+        self.config.dot_segments = Some(DotSegments::RejectEscapingRoot);
+        NormalizePathLayer {
+            config: self.config,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Redirect requests to the normalized path instead of rewriting them.
+    ///
+    /// `status` would usually be `301 Moved Permanently` or `308 Permanent Redirect`. Requests
+    /// whose path is already normalized are passed to the inner service. This requires the
+    /// response body to implement [`Default`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` isn't a redirection status code.
+    pub fn redirect(self, status: StatusCode) -> NormalizePathLayer<Respond> {
+        NormalizePathLayer {
+            config: self.config.redirect(status),
+            _mode: PhantomData,
+        }
     }
 }
 
-impl<S> Layer<S> for NormalizePathLayer {
-    type Service = NormalizePath<S>;
+impl<S, M> Layer<S> for NormalizePathLayer<M> {
+    type Service = NormalizePath<S, M>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        NormalizePath::trim_trailing_slash(inner)
+        NormalizePath {
+            inner,
+            config: self.config,
+            _mode: PhantomData,
+        }
     }
 }
 
@@ -72,8 +276,10 @@ impl<S> Layer<S> for NormalizePathLayer {
 ///
 /// See the [module docs](self) for more details.
 #[derive(Debug, Copy, Clone)]
-pub struct NormalizePath<S> {
+pub struct NormalizePath<S, M = Rewrite> {
     inner: S,
+    config: Config,
+    _mode: PhantomData<M>,
 }
 
 impl<S> NormalizePath<S> {
@@ -82,15 +288,65 @@ impl<S> NormalizePath<S> {
     /// Any trailing slashes from request paths will be removed. For example, a request with `/foo/`
     /// will be changed to `/foo` before reaching the inner service.
     pub fn trim_trailing_slash(inner: S) -> Self {
-        Self { inner }
+        NormalizePathLayer::trim_trailing_slash().layer(inner)
+    }
+
+    /// Create a new [`NormalizePath`].
+    ///
+    /// A trailing slash will be appended to request paths that don't have one, and multiple
+    /// trailing slashes will be merged into one. For example, a request with `/foo` will be
+    /// changed to `/foo/` before reaching the inner service.
+    pub fn append_trailing_slash(inner: S) -> Self {
+        NormalizePathLayer::append_trailing_slash().layer(inner)
+    }
+}
+
+impl<S, M> NormalizePath<S, M> {
+    /// Merge runs of slashes into one.
+    ///
+    /// See [`NormalizePathLayer::merge_slashes`] for more details.
+    pub fn merge_slashes(mut self) -> Self {
+        self.config.merge_slashes = true;
+        self
+    }
+
+    /// Resolve `.` and `..` segments.
+    ///
+    /// See [`NormalizePathLayer::resolve_dot_segments`] for more details.
+    pub fn resolve_dot_segments(mut self) -> Self {
+        self.config.dot_segments = Some(DotSegments::Resolve);
+        self
+    }
+
+    /// Resolve `.` and `..` segments, rejecting paths that would go above the root.
+    ///
+    /// See [`NormalizePathLayer::reject_escaping_root`] for more details.
+    pub fn reject_escaping_root(mut self) -> NormalizePath<S, Respond> {
+        self.config.dot_segments = Some(DotSegments::RejectEscapingRoot);
+        NormalizePath {
+            inner: self.inner,
+            config: self.config,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Redirect requests to the normalized path instead of rewriting them.
+    ///
+    /// See [`NormalizePathLayer::redirect`] for more details.
+    pub fn redirect(self, status: StatusCode) -> NormalizePath<S, Respond> {
+        NormalizePath {
+            inner: self.inner,
+            config: self.config.redirect(status),
+            _mode: PhantomData,
+        }
     }
 
     define_inner_service_accessors!();
 }
 
-impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for NormalizePath<S>
+impl<S, ReqBody> Service<Request<ReqBody>> for NormalizePath<S, Rewrite>
 where
-    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S: Service<Request<ReqBody>>,
 {
     type Response = S::Response;
     type Error = S::Error;
@@ -102,17 +358,190 @@ where
     }
 
     fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
-        normalize_trailing_slash(req.uri_mut());
+        // paths are only rejected in the `Respond` mode
+        let _ = self.config.normalize(req.uri_mut());
         self.inner.call(req)
     }
 }
 
-fn normalize_trailing_slash(uri: &mut Uri) {
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for NormalizePath<S, Respond>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let mut uri = req.uri().clone();
+        let changed = match self.config.normalize(&mut uri) {
+            Ok(changed) => changed,
+            Err(EscapesRoot) => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::BAD_REQUEST;
+                return ResponseFuture::respond(res);
+            }
+        };
+
+        if changed {
+            if let Some(status) = self.config.redirect {
+                if let Some(location) = uri
+                    .path_and_query()
+                    .and_then(|path_and_query| HeaderValue::from_str(path_and_query.as_str()).ok())
+                {
+                    let mut res = Response::new(ResBody::default());
+                    *res.status_mut() = status;
+                    res.headers_mut().insert(header::LOCATION, location);
+                    return ResponseFuture::respond(res);
+                }
+            }
+            *req.uri_mut() = uri;
+        }
+
+        ResponseFuture {
+            kind: Kind::Future {
+                future: self.inner.call(req),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`NormalizePath`] in the [`Respond`] mode.
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        Respond {
+            response: Option<Response<B>>,
+        },
+        Future {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<F, B> ResponseFuture<F, B> {
+    fn respond(response: Response<B>) -> Self {
+        Self {
+            kind: Kind::Respond {
+                response: Some(response),
+            },
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Respond { response } => {
+                let response = response.take().expect("future polled after completion");
+                Poll::Ready(Ok(response))
+            }
+            KindProj::Future { future } => future.poll(cx),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EscapesRoot;
+
+fn merge_slashes(path: &str) -> String {
+    let mut merged = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c == '/' && merged.ends_with('/') {
+            continue;
+        }
+        merged.push(c);
+    }
+    merged
+}
+
+/// Remove `.` and `..` segments, also returning whether a `..` segment went above the root.
+fn remove_dot_segments(path: &str) -> (String, bool) {
+    fn is_dot(segment: &str) -> bool {
+        segment == "." || segment.eq_ignore_ascii_case("%2e")
+    }
+
+    fn is_dot_dot(segment: &str) -> bool {
+        matches!(
+            segment.to_ascii_lowercase().as_str(),
+            ".." | ".%2e" | "%2e." | "%2e%2e"
+        )
+    }
+
+    let mut escapes_root = false;
+    let mut output: Vec<&str> = Vec::new();
+    let mut segments = path.strip_prefix('/').unwrap_or(path).split('/').peekable();
+
+    while let Some(segment) = segments.next() {
+        let is_last = segments.peek().is_none();
+        if is_dot(segment) {
+            if is_last {
+                output.push("");
+            }
+        } else if is_dot_dot(segment) {
+            if output.pop().is_none() {
+                escapes_root = true;
+            }
+            if is_last {
+                output.push("");
+            }
+        } else {
+            output.push(segment);
+        }
+    }
+
+    (format!("/{}", output.join("/")), escapes_root)
+}
+
+/// Remove trailing slashes, returning whether `uri` was changed.
+fn normalize_trailing_slash(uri: &mut Uri) -> bool {
     if !uri.path().ends_with('/') && !uri.path().starts_with("//") {
-        return;
+        return false;
     }
 
     let new_path = format!("/{}", uri.path().trim_matches('/'));
+    set_path(uri, &new_path)
+}
+
+/// Append a trailing slash, returning whether `uri` was changed.
+fn append_trailing_slash(uri: &mut Uri) -> bool {
+    let path = uri.path();
+    if path.ends_with('/') && !path.ends_with("//") && !path.starts_with("//") {
+        return false;
+    }
+
+    let trimmed = path.trim_matches('/');
+    let new_path = if trimmed.is_empty() {
+        "/".to_owned()
+    } else {
+        format!("/{}/", trimmed)
+    };
+    set_path(uri, &new_path)
+}
+
+fn set_path(uri: &mut Uri, new_path: &str) -> bool {
+    if uri.path() == new_path {
+        return false;
+    }
 
     let mut parts = uri.clone().into_parts();
 
@@ -133,6 +562,9 @@ fn normalize_trailing_slash(uri: &mut Uri) {
     parts.path_and_query = new_path_and_query;
     if let Ok(new_uri) = Uri::from_parts(parts) {
         *uri = new_uri;
+        true
+    } else {
+        false
     }
 }
 
@@ -164,6 +596,24 @@ mod tests {
         assert_eq!(body, "/foo");
     }
 
+    #[tokio::test]
+    async fn rewrites_without_default_body() {
+        struct NoDefault(String);
+
+        let svc = NormalizePath::append_trailing_slash(tower::service_fn(
+            |request: Request<()>| async move {
+                Ok::<_, Infallible>(Response::new(NoDefault(request.uri().to_string())))
+            },
+        ))
+        .merge_slashes();
+
+        let res = svc
+            .oneshot(Request::builder().uri("//foo//bar").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.into_body().0, "/foo/bar/");
+    }
+
     #[test]
     fn is_noop_if_no_trailing_slash() {
         let mut uri = "/foo".parse::<Uri>().unwrap();
@@ -226,4 +676,112 @@ mod tests {
         normalize_trailing_slash(&mut uri);
         assert_eq!(uri, "/foo");
     }
+
+    #[test]
+    fn appends_trailing_slash() {
+        for (before, after) in [
+            ("/foo", "/foo/"),
+            ("/foo/", "/foo/"),
+            ("/foo///?a=a", "/foo/?a=a"),
+            ("///foo", "/foo/"),
+            ("/", "/"),
+            ("///", "/"),
+        ] {
+            let mut uri = before.parse::<Uri>().unwrap();
+            append_trailing_slash(&mut uri);
+            assert_eq!(uri, after);
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_to_normalized_path() {
+        async fn handle(request: Request<()>) -> Result<Response<String>, Infallible> {
+            Ok(Response::new(request.uri().to_string()))
+        }
+
+        let mut svc = ServiceBuilder::new()
+            .layer(
+                NormalizePathLayer::trim_trailing_slash().redirect(StatusCode::MOVED_PERMANENTLY),
+            )
+            .service_fn(handle);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::builder().uri("/foo/?a=a").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "/foo?a=a");
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::builder().uri("/foo?a=a").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body(), "/foo?a=a");
+    }
+
+    #[test]
+    fn merges_slashes() {
+        assert_eq!(merge_slashes("//foo///bar//"), "/foo/bar/");
+        assert_eq!(merge_slashes("/foo/bar"), "/foo/bar");
+    }
+
+    #[test]
+    fn removes_dot_segments() {
+        for (before, after, escapes_root) in [
+            ("/foo/./bar/../baz", "/foo/baz", false),
+            ("/foo/bar/..", "/foo/", false),
+            ("/foo/.", "/foo/", false),
+            ("/foo/%2E%2e/bar", "/bar", false),
+            ("/../foo", "/foo", true),
+            ("/foo/../../bar", "/bar", true),
+            ("/", "/", false),
+        ] {
+            assert_eq!(
+                remove_dot_segments(before),
+                (after.to_owned(), escapes_root),
+                "{}",
+                before
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_escaping_root() {
+        async fn handle(request: Request<()>) -> Result<Response<String>, Infallible> {
+            Ok(Response::new(request.uri().to_string()))
+        }
+
+        let mut svc = ServiceBuilder::new()
+            .layer(
+                NormalizePathLayer::trim_trailing_slash()
+                    .merge_slashes()
+                    .reject_escaping_root(),
+            )
+            .service_fn(handle);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::builder().uri("/foo//./bar/..").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.into_body(), "/foo");
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::builder().uri("/foo/../../etc").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
 }
@@ -0,0 +1,339 @@
+//! Middleware that normalizes percent-encoding in request paths.
+//!
+//! Following [RFC 3986 section 6.2.2], percent-encoded unreserved characters (letters, digits,
+//! `-`, `.`, `_` and `~`) are decoded and the hex digits of the remaining percent-encodings are
+//! uppercased. For example, a request with `/%7Efoo/a%2fb` will be changed to `/~foo/a%2Fb`
+//! before reaching the inner service. That way routing, cache keys and logs all see the same
+//! form of equivalent paths.
+//!
+//! Reserved characters, such as `/`, are never decoded since that would change the meaning of
+//! the path.
+//!
+//! # Example
+//!
+//! ```
+//! use tower_http::normalize_percent_encoding::NormalizePercentEncodingLayer;
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, Service, ServiceExt};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // `handle` will see `/~foo/a%2Fb`
+//!     # assert_eq!(req.uri().path(), "/~foo/a%2Fb");
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! let mut service = ServiceBuilder::new()
+//!     // Respond with `400 Bad Request` to paths with invalid percent-encodings, such as `%zz`.
+//!     .layer(NormalizePercentEncodingLayer::new().reject_invalid())
+//!     .service_fn(handle);
+//!
+//! let request = Request::builder()
+//!     .uri("/%7efoo/a%2fb")
+//!     .body(Body::empty())?;
+//!
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [RFC 3986 section 6.2.2]: https://www.rfc-editor.org/rfc/rfc3986#section-6.2.2
+
+use http::{uri::PathAndQuery, Request, Response, StatusCode, Uri};
+use pin_project_lite::pin_project;
+use std::{
+    borrow::Cow,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies [`NormalizePercentEncoding`] which normalizes percent-encoding in request
+/// paths.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NormalizePercentEncodingLayer {
+    reject_invalid: bool,
+}
+
+impl NormalizePercentEncodingLayer {
+    /// Create a new [`NormalizePercentEncodingLayer`].
+    ///
+    /// Invalid percent-encodings, such as `%zz`, are left as is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond with `400 Bad Request` if the path contains invalid percent-encodings.
+    pub fn reject_invalid(mut self) -> Self {
+        self.reject_invalid = true;
+        self
+    }
+}
+
+impl<S> Layer<S> for NormalizePercentEncodingLayer {
+    type Service = NormalizePercentEncoding<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NormalizePercentEncoding {
+            inner,
+            reject_invalid: self.reject_invalid,
+        }
+    }
+}
+
+/// Middleware that normalizes percent-encoding in request paths.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Copy, Clone)]
+pub struct NormalizePercentEncoding<S> {
+    inner: S,
+    reject_invalid: bool,
+}
+
+impl<S> NormalizePercentEncoding<S> {
+    /// Create a new [`NormalizePercentEncoding`].
+    ///
+    /// Invalid percent-encodings, such as `%zz`, are left as is.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            reject_invalid: false,
+        }
+    }
+
+    /// Respond with `400 Bad Request` if the path contains invalid percent-encodings.
+    pub fn reject_invalid(mut self) -> Self {
+        self.reject_invalid = true;
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `NormalizePercentEncoding` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> NormalizePercentEncodingLayer {
+        NormalizePercentEncodingLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for NormalizePercentEncoding<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let (path, valid) = normalize_percent_encoding(req.uri().path());
+
+        if !valid && self.reject_invalid {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::BAD_REQUEST;
+            return ResponseFuture {
+                kind: Kind::BadRequest {
+                    response: Some(res),
+                },
+            };
+        }
+
+        if let Cow::Owned(path) = path {
+            set_path(req.uri_mut(), &path);
+        }
+
+        ResponseFuture {
+            kind: Kind::Future {
+                future: self.inner.call(req),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`NormalizePercentEncoding`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        BadRequest {
+            response: Option<Response<B>>,
+        },
+        Future {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::BadRequest { response } => {
+                let response = response.take().expect("future polled after completion");
+                Poll::Ready(Ok(response))
+            }
+            KindProj::Future { future } => future.poll(cx),
+        }
+    }
+}
+
+/// Normalize the percent-encodings in `path`, also returning whether they were all valid.
+fn normalize_percent_encoding(path: &str) -> (Cow<'_, str>, bool) {
+    let bytes = path.as_bytes();
+    if !bytes.contains(&b'%') {
+        return (Cow::Borrowed(path), true);
+    }
+
+    let mut valid = true;
+    let mut normalized = String::with_capacity(path.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            // Only the ASCII `%` is handled specially so multi-byte characters are copied as is.
+            let next = path[i..].find('%').map_or(bytes.len(), |n| i + n);
+            normalized.push_str(&path[i..next]);
+            i = next;
+            continue;
+        }
+
+        match (
+            bytes.get(i + 1).and_then(|&b| hex_value(b)),
+            bytes.get(i + 2).and_then(|&b| hex_value(b)),
+        ) {
+            (Some(hi), Some(lo)) => {
+                let decoded = hi << 4 | lo;
+                if is_unreserved(decoded) {
+                    normalized.push(decoded as char);
+                } else {
+                    normalized.push('%');
+                    normalized.push(char::from(bytes[i + 1]).to_ascii_uppercase());
+                    normalized.push(char::from(bytes[i + 2]).to_ascii_uppercase());
+                }
+                i += 3;
+            }
+            _ => {
+                valid = false;
+                normalized.push('%');
+                i += 1;
+            }
+        }
+    }
+
+    if normalized == path {
+        (Cow::Borrowed(path), valid)
+    } else {
+        (Cow::Owned(normalized), valid)
+    }
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+fn set_path(uri: &mut Uri, new_path: &str) {
+    let mut parts = uri.clone().into_parts();
+
+    let new_path_and_query = match parts.path_and_query.as_ref().and_then(|pq| pq.query()) {
+        Some(query) => format!("{}?{}", new_path, query).parse::<PathAndQuery>(),
+        None => new_path.parse::<PathAndQuery>(),
+    };
+
+    if let Ok(new_path_and_query) = new_path_and_query {
+        parts.path_and_query = Some(new_path_and_query);
+        if let Ok(new_uri) = Uri::from_parts(parts) {
+            *uri = new_uri;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[test]
+    fn normalizes() {
+        for (before, after, valid) in [
+            ("/foo", "/foo", true),
+            ("/%7efoo/%41%2d", "/~foo/A-", true),
+            ("/a%2fb%3f", "/a%2Fb%3F", true),
+            ("/caf%C3%A9", "/caf%C3%A9", true),
+            ("/%zz/%4", "/%zz/%4", false),
+            ("/%", "/%", false),
+        ] {
+            assert_eq!(
+                normalize_percent_encoding(before),
+                (Cow::Borrowed(after), valid),
+                "{}",
+                before
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rewrites_path_and_keeps_query() {
+        async fn handle(request: Request<()>) -> Result<Response<String>, Infallible> {
+            Ok(Response::new(request.uri().to_string()))
+        }
+
+        let svc = ServiceBuilder::new()
+            .layer(NormalizePercentEncodingLayer::new())
+            .service_fn(handle);
+
+        let res = svc
+            .oneshot(Request::builder().uri("/%61%2f?q=%61").body(()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(res.into_body(), "/a%2F?q=%61");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid() {
+        async fn handle(_: Request<()>) -> Result<Response<String>, Infallible> {
+            Ok(Response::new(String::new()))
+        }
+
+        let svc = ServiceBuilder::new()
+            .layer(NormalizePercentEncodingLayer::new().reject_invalid())
+            .service_fn(handle);
+
+        let res = svc
+            .oneshot(Request::builder().uri("/%zz").body(()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,333 @@
+//! Middleware for removing path prefixes from incoming requests and adding them to outgoing
+//! requests.
+//!
+//! [`StripPrefix`] removes a prefix such as `/api/v1` from request paths, so services can be
+//! mounted under a prefix without knowing about it. The original URI is stored in an
+//! [`OriginalUri`] request extension.
+//!
+//! [`AddPrefix`] is the client side counterpart. It resolves relative request URIs against a
+//! base URI, so clients can be pointed at a base URL such as `https://example.com/api`.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::path_prefix::{OriginalUri, StripPrefixLayer};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     assert_eq!(req.uri(), "/users?page=2");
+//!     assert_eq!(req.extensions().get::<OriginalUri>().unwrap().0, "/api/v1/users?page=2");
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(StripPrefixLayer::new("/api/v1"))
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/api/v1/users?page=2").body(Body::empty())?;
+//! service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Adding a base URI to outgoing requests:
+//!
+//! ```
+//! use http::{Request, Response, Uri};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::path_prefix::AddPrefixLayer;
+//!
+//! # async fn send(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//! #     assert_eq!(req.uri(), "https://example.com/api/users?page=2");
+//! #     Ok(Response::new(Body::empty()))
+//! # }
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut client = ServiceBuilder::new()
+//!     .layer(AddPrefixLayer::new(Uri::from_static("https://example.com/api")))
+//!     // `send` would usually be a HTTP client such as `hyper::Client`.
+//!     .service_fn(send);
+//!
+//! // Sent to `https://example.com/api/users?page=2`.
+//! let request = Request::get("/users?page=2").body(Body::empty())?;
+//! client.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{uri::PathAndQuery, Request, Uri};
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The request's URI before [`StripPrefix`] changed it.
+///
+/// If several `StripPrefix` middleware are nested this is the URI the outermost one received.
+#[derive(Debug, Clone)]
+pub struct OriginalUri(pub Uri);
+
+/// Layer that applies [`StripPrefix`] which removes a prefix from request paths.
+///
+/// See the [module docs](crate::path_prefix) for an example.
+#[derive(Debug, Clone)]
+pub struct StripPrefixLayer {
+    prefix: Arc<str>,
+}
+
+impl StripPrefixLayer {
+    /// Create a new `StripPrefixLayer`.
+    ///
+    /// The prefix is only removed from paths where it's followed by `/` or nothing, so
+    /// `/api` is removed from `/api/users` but not from `/apiusers`. Requests whose path doesn't
+    /// start with the prefix are passed to the inner service unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` doesn't start with `/`.
+    pub fn new(prefix: &str) -> Self {
+        assert!(prefix.starts_with('/'), "prefix must start with `/`");
+        Self {
+            prefix: prefix.trim_end_matches('/').into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for StripPrefixLayer {
+    type Service = StripPrefix<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StripPrefix {
+            inner,
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+/// Middleware that removes a prefix from request paths.
+///
+/// See the [module docs](crate::path_prefix) for an example.
+#[derive(Debug, Clone)]
+pub struct StripPrefix<S> {
+    inner: S,
+    prefix: Arc<str>,
+}
+
+impl<S> StripPrefix<S> {
+    /// Create a new `StripPrefix`.
+    ///
+    /// See [`StripPrefixLayer::new`] for more details.
+    pub fn new(inner: S, prefix: &str) -> Self {
+        StripPrefixLayer::new(prefix).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `StripPrefix` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(prefix: &str) -> StripPrefixLayer {
+        StripPrefixLayer::new(prefix)
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for StripPrefix<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if let Some(uri) = strip_prefix(req.uri(), &self.prefix) {
+            if req.extensions().get::<OriginalUri>().is_none() {
+                let original = req.uri().clone();
+                req.extensions_mut().insert(OriginalUri(original));
+            }
+            *req.uri_mut() = uri;
+        }
+
+        self.inner.call(req)
+    }
+}
+
+fn strip_prefix(uri: &Uri, prefix: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(prefix)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+
+    let new_path = if rest.is_empty() { "/" } else { rest };
+    let new_path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", new_path, query).parse::<PathAndQuery>(),
+        None => new_path.parse::<PathAndQuery>(),
+    }
+    .ok()?;
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(new_path_and_query);
+    Uri::from_parts(parts).ok()
+}
+
+/// Layer that applies [`AddPrefix`] which resolves relative request URIs against a base URI.
+///
+/// See the [module docs](crate::path_prefix) for an example.
+#[derive(Debug, Clone)]
+pub struct AddPrefixLayer {
+    base: Uri,
+}
+
+impl AddPrefixLayer {
+    /// Create a new `AddPrefixLayer`.
+    ///
+    /// The base URI's scheme and authority, if any, are added to requests without a scheme and
+    /// its path is prepended to their path. Requests that already have a scheme are passed to
+    /// the inner service unchanged.
+    pub fn new(base: Uri) -> Self {
+        Self { base }
+    }
+}
+
+impl<S> Layer<S> for AddPrefixLayer {
+    type Service = AddPrefix<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AddPrefix {
+            inner,
+            base: self.base.clone(),
+        }
+    }
+}
+
+/// Middleware that resolves relative request URIs against a base URI.
+///
+/// See the [module docs](crate::path_prefix) for an example.
+#[derive(Debug, Clone)]
+pub struct AddPrefix<S> {
+    inner: S,
+    base: Uri,
+}
+
+impl<S> AddPrefix<S> {
+    /// Create a new `AddPrefix`.
+    ///
+    /// See [`AddPrefixLayer::new`] for more details.
+    pub fn new(inner: S, base: Uri) -> Self {
+        Self { inner, base }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `AddPrefix` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(base: Uri) -> AddPrefixLayer {
+        AddPrefixLayer::new(base)
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for AddPrefix<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if req.uri().scheme().is_none() {
+            if let Some(uri) = add_prefix(&self.base, req.uri()) {
+                *req.uri_mut() = uri;
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+fn add_prefix(base: &Uri, uri: &Uri) -> Option<Uri> {
+    let base_path = base.path().trim_end_matches('/');
+    let path = uri.path();
+    let new_path = if path.starts_with('/') {
+        format!("{}{}", base_path, path)
+    } else {
+        format!("{}/{}", base_path, path)
+    };
+    let new_path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", new_path, query).parse::<PathAndQuery>(),
+        None => new_path.parse::<PathAndQuery>(),
+    }
+    .ok()?;
+
+    let mut parts = uri.clone().into_parts();
+    if let (Some(scheme), Some(authority)) = (base.scheme(), base.authority()) {
+        parts.scheme = Some(scheme.clone());
+        parts.authority = Some(authority.clone());
+    }
+    parts.path_and_query = Some(new_path_and_query);
+    Uri::from_parts(parts).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_prefix() {
+        let prefix = "/api/v1";
+        for (before, after) in [
+            ("/api/v1/users?a=b", Some("/users?a=b")),
+            ("/api/v1", Some("/")),
+            ("/api/v1?a=b", Some("/?a=b")),
+            ("http://example.com/api/v1/x", Some("http://example.com/x")),
+            ("/api/v1x", None),
+            ("/other", None),
+        ] {
+            let uri = before.parse::<Uri>().unwrap();
+            assert_eq!(
+                strip_prefix(&uri, prefix).map(|uri| uri.to_string()),
+                after.map(ToOwned::to_owned),
+                "{}",
+                before
+            );
+        }
+    }
+
+    #[test]
+    fn adds_prefix() {
+        for (base, before, after) in [
+            (
+                "https://example.com/api/",
+                "/users?a=b",
+                "https://example.com/api/users?a=b",
+            ),
+            ("https://example.com", "/users", "https://example.com/users"),
+            ("/api", "/users", "/api/users"),
+        ] {
+            let base = base.parse::<Uri>().unwrap();
+            let uri = before.parse::<Uri>().unwrap();
+            assert_eq!(add_prefix(&base, &uri).unwrap(), after);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! Middleware that adds `Link` headers telling browsers what to preload.
+//!
+//! [`PreloadLinks`] adds `Link` headers with `rel=preload` or `rel=preconnect` to HTML
+//! responses, so browsers start fetching stylesheets, scripts and fonts or connecting to other
+//! origins before they have parsed the page. The links are configured with [`PreloadRule`]s
+//! that match requests by path prefix. The links of all matching rules are added, in the order
+//! the rules were added.
+//!
+//! Only responses whose `Content-Type` is `text/html` get the links by default, which can be
+//! changed with [`PreloadLinksLayer::content_type`]. Links the response already contains
+//! aren't added again.
+//!
+//! With the `early-hints` feature, the links can also be sent in a `103 Early Hints` response
+//! before the inner service is called, see [`PreloadLinksLayer::early_hints`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::preload_links::{PreloadLinksLayer, PreloadRule};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let res = Response::builder()
+//!         .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+//!         .body(Body::from("<!doctype html>"))
+//!         .unwrap();
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Infallible> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         PreloadLinksLayer::new()
+//!             .rule(PreloadRule::any().preconnect("https://fonts.example.com"))
+//!             .rule(PreloadRule::path_prefix("/app").preload("/app.js", "script")),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/app/settings").body(Body::empty()).unwrap();
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! let links = response.headers().get_all(header::LINK).iter().collect::<Vec<_>>();
+//! assert_eq!(
+//!     links,
+//!     [
+//!         "<https://fonts.example.com>; rel=preconnect",
+//!         "</app.js>; rel=preload; as=script",
+//!     ],
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response};
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A set of links added to the responses of the requests it matches.
+///
+/// See the [module docs](crate::preload_links) for an example.
+#[derive(Debug, Clone)]
+pub struct PreloadRule {
+    path_prefix: String,
+    links: Vec<HeaderValue>,
+}
+
+impl PreloadRule {
+    /// Create a new `PreloadRule` matching requests whose path starts with `prefix`.
+    pub fn path_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            path_prefix: prefix.into(),
+            links: Vec::new(),
+        }
+    }
+
+    /// Create a new `PreloadRule` matching all requests.
+    pub fn any() -> Self {
+        Self::path_prefix("")
+    }
+
+    /// Add a link preloading `url`, with `rel=preload` and the kind of resource in `as`, such
+    /// as `style`, `script` or `font`.
+    ///
+    /// Fonts are preloaded with `crossorigin`, which browsers require to use them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` or `as` contain characters that aren't allowed in headers, or `url`
+    /// contains `>`.
+    pub fn preload(self, url: &str, r#as: &str) -> Self {
+        let crossorigin = if r#as == "font" { "; crossorigin" } else { "" };
+        let params = format!("rel=preload; as={}{}", r#as, crossorigin);
+        self.link(url, &params)
+    }
+
+    /// Add a link connecting to the origin `url` early, with `rel=preconnect`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` contains characters that aren't allowed in headers or `>`.
+    pub fn preconnect(self, url: &str) -> Self {
+        self.link(url, "rel=preconnect")
+    }
+
+    /// Add a `Link` header value as is, for links with other relations or parameters.
+    pub fn raw(mut self, link: HeaderValue) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    fn link(self, url: &str, params: &str) -> Self {
+        assert!(!url.contains('>'), "invalid link: {:?}", url);
+        let link = format!("<{}>; {}", url, params);
+        self.raw(HeaderValue::try_from(link).expect("invalid link"))
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        path.starts_with(&self.path_prefix)
+    }
+}
+
+#[derive(Clone)]
+struct Config {
+    rules: Vec<PreloadRule>,
+    content_type: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+    #[cfg(feature = "early-hints")]
+    early_hints: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            content_type: Arc::new(is_html),
+            #[cfg(feature = "early-hints")]
+            early_hints: false,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("Config");
+        f.field("rules", &self.rules);
+        #[cfg(feature = "early-hints")]
+        f.field("early_hints", &self.early_hints);
+        f.finish_non_exhaustive()
+    }
+}
+
+impl Config {
+    fn links(&self, path: &str) -> Vec<HeaderValue> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(path))
+            .flat_map(|rule| rule.links.iter().cloned())
+            .collect()
+    }
+}
+
+fn is_html(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default();
+    essence.trim().eq_ignore_ascii_case("text/html")
+}
+
+/// Layer that applies [`PreloadLinks`] which adds `Link` headers to HTML responses.
+///
+/// See the [module docs](crate::preload_links) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct PreloadLinksLayer {
+    config: Arc<Config>,
+}
+
+impl PreloadLinksLayer {
+    /// Create a new `PreloadLinksLayer` without any rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule.
+    pub fn rule(mut self, rule: PreloadRule) -> Self {
+        Arc::make_mut(&mut self.config).rules.push(rule);
+        self
+    }
+
+    /// Set the predicate deciding which responses get the links, from their `Content-Type`.
+    ///
+    /// Responses without a `Content-Type` never get the links. Defaults to `text/html`.
+    pub fn content_type<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).content_type = Arc::new(predicate);
+        self
+    }
+
+    /// Send the links of the matching rules in a `103 Early Hints` response before calling the
+    /// inner service.
+    ///
+    /// The hints are sent with the [`EarlyHints`] handle of the request, so
+    /// [`SendEarlyHints`] must be added outside of this middleware. Since the response isn't
+    /// known yet when the hints are sent, they are sent for every matching request and,
+    /// unless disabled with [`SendEarlyHintsLayer::copy_links`], end up in the final response
+    /// whatever its content type.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [`EarlyHints`]: crate::early_hints::EarlyHints
+    /// [`SendEarlyHints`]: crate::early_hints::SendEarlyHints
+    /// [`SendEarlyHintsLayer::copy_links`]: crate::early_hints::SendEarlyHintsLayer::copy_links
+    #[cfg(feature = "early-hints")]
+    pub fn early_hints(mut self, early_hints: bool) -> Self {
+        Arc::make_mut(&mut self.config).early_hints = early_hints;
+        self
+    }
+}
+
+impl<S> Layer<S> for PreloadLinksLayer {
+    type Service = PreloadLinks<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PreloadLinks {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that adds `Link` headers to HTML responses.
+///
+/// See the [module docs](crate::preload_links) for an example.
+#[derive(Debug, Clone)]
+pub struct PreloadLinks<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> PreloadLinks<S> {
+    /// Create a new `PreloadLinks` without any rules.
+    pub fn new(inner: S) -> Self {
+        PreloadLinksLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `PreloadLinks` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> PreloadLinksLayer {
+        PreloadLinksLayer::new()
+    }
+
+    /// Add a rule.
+    ///
+    /// See [`PreloadLinksLayer::rule`] for more details.
+    pub fn rule(mut self, rule: PreloadRule) -> Self {
+        Arc::make_mut(&mut self.config).rules.push(rule);
+        self
+    }
+
+    /// Set the predicate deciding which responses get the links.
+    ///
+    /// See [`PreloadLinksLayer::content_type`] for more details.
+    pub fn content_type<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).content_type = Arc::new(predicate);
+        self
+    }
+
+    /// Send the links in a `103 Early Hints` response before calling the inner service.
+    ///
+    /// See [`PreloadLinksLayer::early_hints`] for more details.
+    #[cfg(feature = "early-hints")]
+    pub fn early_hints(mut self, early_hints: bool) -> Self {
+        Arc::make_mut(&mut self.config).early_hints = early_hints;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for PreloadLinks<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let links = self.config.links(req.uri().path());
+
+        #[cfg(feature = "early-hints")]
+        if self.config.early_hints && !links.is_empty() {
+            if let Some(hints) = req.extensions().get::<crate::early_hints::EarlyHints>() {
+                let mut headers = HeaderMap::new();
+                for link in &links {
+                    headers.append(header::LINK, link.clone());
+                }
+                // the links are still added to the final response if the hints can't be sent
+                let _ = hints.send(headers);
+            }
+        }
+
+        ResponseFuture {
+            future: self.inner.call(req),
+            links,
+            config: self.config.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`PreloadLinks`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        future: F,
+        links: Vec<HeaderValue>,
+        config: Arc<Config>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.future.poll(cx))?;
+        let config = &**this.config;
+
+        let content_type = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        if content_type.map_or(false, |value| (config.content_type)(value)) {
+            append_links(res.headers_mut(), std::mem::take(this.links));
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("links", &self.links)
+            .finish()
+    }
+}
+
+fn append_links(headers: &mut HeaderMap, links: Vec<HeaderValue>) {
+    for link in links {
+        if !headers
+            .get_all(header::LINK)
+            .iter()
+            .any(|value| *value == link)
+        {
+            headers.append(header::LINK, link);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let content_type = if req.uri().path().ends_with(".json") {
+            "application/json"
+        } else {
+            "text/html"
+        };
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::LINK, "</app.css>; rel=preload; as=style")
+            .body(Body::empty())
+            .unwrap();
+        Ok(res)
+    }
+
+    fn links(res: &Response<Body>) -> Vec<&HeaderValue> {
+        res.headers().get_all(header::LINK).iter().collect()
+    }
+
+    fn layer() -> PreloadLinksLayer {
+        PreloadLinksLayer::new()
+            .rule(PreloadRule::any().preload("/app.css", "style"))
+            .rule(
+                PreloadRule::path_prefix("/docs")
+                    .preload("/font.woff2", "font")
+                    .preconnect("https://cdn.example.com"),
+            )
+    }
+
+    #[tokio::test]
+    async fn adds_links_of_matching_rules() {
+        let svc = layer().layer(service_fn(handle));
+
+        let res = svc
+            .clone()
+            .oneshot(Request::get("/docs/intro").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            links(&res),
+            [
+                "</app.css>; rel=preload; as=style",
+                "</font.woff2>; rel=preload; as=font; crossorigin",
+                "<https://cdn.example.com>; rel=preconnect",
+            ]
+        );
+
+        let res = svc
+            .clone()
+            .oneshot(Request::get("/docs/data.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(links(&res), ["</app.css>; rel=preload; as=style"]);
+
+        let svc = layer()
+            .content_type(|content_type| content_type.starts_with("application/json"))
+            .layer(service_fn(handle));
+        let res = svc
+            .oneshot(Request::get("/docs/intro").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(links(&res), ["</app.css>; rel=preload; as=style"]);
+    }
+
+    #[cfg(feature = "early-hints")]
+    #[tokio::test]
+    async fn sends_early_hints() {
+        use crate::early_hints::{InformationalSender, SendEarlyHintsLayer};
+        use std::sync::Mutex;
+        use tower::ServiceBuilder;
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sender = InformationalSender::new({
+            let sent = sent.clone();
+            move |res| {
+                sent.lock().unwrap().push(res);
+                true
+            }
+        });
+        let svc = ServiceBuilder::new()
+            .layer(SendEarlyHintsLayer::new())
+            .layer(layer().early_hints(true))
+            .service_fn(handle);
+
+        let mut req = Request::get("/docs").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(sender);
+        let res = svc.oneshot(req).await.unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].status().as_u16(), 103);
+        assert_eq!(sent[0].headers().get_all(header::LINK).iter().count(), 3);
+        assert_eq!(links(&res).len(), 3);
+    }
+}
@@ -0,0 +1,199 @@
+use http::{header::HeaderName, Request};
+use std::{fmt, str::FromStr};
+
+/// The priority class of a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Requests that can be delayed or shed first, such as batch jobs.
+    Low,
+    /// Regular requests.
+    #[default]
+    Normal,
+    /// Requests that should be admitted even when the service is under pressure, such as health
+    /// checks or requests from paying customers.
+    High,
+}
+
+impl Priority {
+    /// All priorities, from the highest to the lowest.
+    pub(super) const DESCENDING: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub(super) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Priority {
+    type Err = ParsePriorityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("low") {
+            Ok(Priority::Low)
+        } else if s.eq_ignore_ascii_case("normal") {
+            Ok(Priority::Normal)
+        } else if s.eq_ignore_ascii_case("high") {
+            Ok(Priority::High)
+        } else {
+            Err(ParsePriorityError { _priv: () })
+        }
+    }
+}
+
+/// Error returned when parsing a [`Priority`] fails.
+#[derive(Debug)]
+pub struct ParsePriorityError {
+    _priv: (),
+}
+
+impl fmt::Display for ParsePriorityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected `low`, `normal` or `high`")
+    }
+}
+
+impl std::error::Error for ParsePriorityError {}
+
+/// Trait for classifying requests into a [`Priority`].
+///
+/// This trait is implemented for closures with the signature `FnMut(&Request<B>) -> Priority`.
+pub trait ClassifyPriority<B> {
+    /// Classify the request.
+    fn classify(&mut self, request: &Request<B>) -> Priority;
+}
+
+impl<B, F> ClassifyPriority<B> for F
+where
+    F: FnMut(&Request<B>) -> Priority,
+{
+    fn classify(&mut self, request: &Request<B>) -> Priority {
+        self(request)
+    }
+}
+
+/// A [`ClassifyPriority`] reading the priority from a header containing `low`, `normal` or
+/// `high`.
+///
+/// Requests without the header, or with another value, have [`Priority::Normal`].
+///
+/// Only use this for headers set by trusted parties, such as a proxy, since otherwise every
+/// client can claim a high priority.
+#[derive(Clone, Debug)]
+pub struct PriorityHeader {
+    name: HeaderName,
+}
+
+impl PriorityHeader {
+    /// Create a new [`PriorityHeader`] reading the header called `name`.
+    pub fn new(name: HeaderName) -> Self {
+        Self { name }
+    }
+}
+
+impl<B> ClassifyPriority<B> for PriorityHeader {
+    fn classify(&mut self, request: &Request<B>) -> Priority {
+        request
+            .headers()
+            .get(&self.name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// A [`ClassifyPriority`] reading the priority from a [`Priority`] request extension, such as
+/// one added by an earlier middleware after authenticating the request.
+///
+/// Requests without the extension have [`Priority::Normal`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PriorityExtension;
+
+impl<B> ClassifyPriority<B> for PriorityExtension {
+    fn classify(&mut self, request: &Request<B>) -> Priority {
+        request
+            .extensions()
+            .get::<Priority>()
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// A [`ClassifyPriority`] assigning priorities to requests by the prefix of their path.
+///
+/// The first matching prefix wins. Requests not matching any prefix have [`Priority::Normal`].
+#[derive(Clone, Debug, Default)]
+pub struct PathPriority {
+    prefixes: Vec<(String, Priority)>,
+}
+
+impl PathPriority {
+    /// Create a new [`PathPriority`] without any prefixes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give requests whose path starts with `prefix` the priority `priority`.
+    ///
+    /// The prefix is matched against whole segments, so `/api` matches `/api` and `/api/users`
+    /// but not `/apis`.
+    pub fn prefix(mut self, prefix: &str, priority: Priority) -> Self {
+        self.prefixes
+            .push((prefix.trim_end_matches('/').to_owned(), priority));
+        self
+    }
+}
+
+impl<B> ClassifyPriority<B> for PathPriority {
+    fn classify(&mut self, request: &Request<B>) -> Priority {
+        let path = request.uri().path();
+        self.prefixes
+            .iter()
+            .find(|(prefix, _)| match path.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            })
+            .map(|(_, priority)| *priority)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifiers() {
+        let req = Request::builder()
+            .uri("/admin/users")
+            .header("x-priority", "HIGH")
+            .body(())
+            .unwrap();
+
+        let mut header = PriorityHeader::new(HeaderName::from_static("x-priority"));
+        assert_eq!(header.classify(&req), Priority::High);
+        assert_eq!(header.classify(&Request::new(())), Priority::Normal);
+
+        let mut path = PathPriority::new()
+            .prefix("/health", Priority::High)
+            .prefix("/admin/", Priority::Low);
+        assert_eq!(path.classify(&req), Priority::Low);
+        let req = Request::builder().uri("/healthz").body(()).unwrap();
+        assert_eq!(path.classify(&req), Priority::Normal);
+
+        let mut req = Request::new(());
+        assert_eq!(PriorityExtension.classify(&req), Priority::Normal);
+        req.extensions_mut().insert(Priority::Low);
+        assert_eq!(PriorityExtension.classify(&req), Priority::Low);
+    }
+}
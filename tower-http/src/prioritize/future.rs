@@ -0,0 +1,204 @@
+use super::scheduler::{Permit, Ticket};
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future for [`Prioritize`].
+    ///
+    /// [`Prioritize`]: super::Prioritize
+    pub struct ResponseFuture<S, R>
+    where
+        S: Service<R>,
+    {
+        #[pin]
+        state: State<S, R, S::Future>,
+        retry_after: Option<HeaderValue>,
+    }
+}
+
+impl<S, R> ResponseFuture<S, R>
+where
+    S: Service<R>,
+{
+    pub(super) fn new(state: State<S, R, S::Future>, retry_after: Option<HeaderValue>) -> Self {
+        Self { state, retry_after }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    pub(super) enum State<S, R, F> {
+        Rejected,
+        Waiting {
+            ticket: Ticket,
+            service: S,
+            request: Option<R>,
+            permit: Option<Permit>,
+        },
+        Called {
+            #[pin]
+            future: F,
+            permit: Option<Permit>,
+        },
+    }
+}
+
+impl<S, R, B> Future for ResponseFuture<S, R>
+where
+    S: Service<R, Response = Response<B>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Rejected => {
+                    return Poll::Ready(Ok(service_unavailable(this.retry_after.clone())));
+                }
+                StateProj::Waiting {
+                    ticket,
+                    service,
+                    request,
+                    permit,
+                } => {
+                    if permit.is_none() {
+                        *permit = Some(ready!(ticket.poll_admitted(cx)));
+                    }
+                    ready!(service.poll_ready(cx))?;
+                    let request = request.take().expect("future polled after completion");
+                    State::Called {
+                        future: service.call(request),
+                        permit: permit.take(),
+                    }
+                }
+                StateProj::Called { future, permit } => {
+                    let res = ready!(future.poll(cx))?;
+                    let permit = permit.take();
+                    return Poll::Ready(Ok(res.map(|body| ResponseBody {
+                        kind: BodyKind::Body { body, permit },
+                    })));
+                }
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, R> fmt::Debug for ResponseFuture<S, R>
+where
+    S: Service<R>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+fn service_unavailable<B>(retry_after: Option<HeaderValue>) -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut res = Response::new(ResponseBody {
+        kind: BodyKind::Rejected { body: Empty::new() },
+    });
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    if let Some(retry_after) = retry_after {
+        res.headers_mut().insert(header::RETRY_AFTER, retry_after);
+    }
+    res
+}
+
+pin_project! {
+    /// Response body for [`Prioritize`].
+    ///
+    /// Holds on to the request's share of the capacity until the body is consumed or dropped.
+    ///
+    /// [`Prioritize`]: super::Prioritize
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        Rejected {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            permit: Option<Permit>,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Rejected { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { mut body, permit } => {
+                let data = ready!(body.as_mut().poll_data(cx));
+                if data.is_none() && body.is_end_stream() {
+                    permit.take();
+                }
+                Poll::Ready(data)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Rejected { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body, permit } => {
+                let trailers = ready!(body.poll_trailers(cx));
+                permit.take();
+                Poll::Ready(trailers)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Rejected { body } => body.is_end_stream(),
+            BodyKind::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Rejected { body } => body.size_hint(),
+            BodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Middleware that admits requests by priority under concurrency pressure.
+//!
+//! Requests are classified into a [`Priority`] with a [`ClassifyPriority`], such as
+//! [`PriorityHeader`], [`PathPriority`], [`PriorityExtension`] or a closure. [`Prioritize`]
+//! limits the number of concurrent requests, and each priority can be given a lower limit of its
+//! own, reserving the remaining capacity for higher priorities. Requests over their limit wait in
+//! a bounded queue per priority, from which the highest priorities are admitted first, or are
+//! shed with `503 Service Unavailable` once the queue is full.
+//!
+//! The depth of the queues can be recorded with [`PrioritizeLayer::on_queue_change`].
+//!
+//! A request counts against the limit until its response body has been consumed or dropped. The
+//! limit is shared by all services produced by the same [`PrioritizeLayer`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::prioritize::{PathPriority, Priority, PrioritizeLayer};
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let classifier = PathPriority::new()
+//!     .prefix("/health", Priority::High)
+//!     .prefix("/reports", Priority::Low);
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         // Process at most 100 requests at a time, but only 50 reports, and let some of the
+//!         // excess requests wait for capacity.
+//!         PrioritizeLayer::new(classifier, 100)
+//!             .limit(Priority::Low, 50)
+//!             .queue(Priority::Normal, 100)
+//!             .queue(Priority::Low, 10)
+//!             .on_queue_change(|priority, depth| {
+//!                 // record `depth` as a metric
+//!             }),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/reports/daily").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+mod classify;
+mod future;
+mod scheduler;
+mod service;
+
+pub use self::{
+    classify::{
+        ClassifyPriority, ParsePriorityError, PathPriority, Priority, PriorityExtension,
+        PriorityHeader,
+    },
+    future::{ResponseBody, ResponseFuture},
+    service::{Prioritize, PrioritizeLayer},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{header, Request, Response, StatusCode};
+    use hyper::Body;
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tower::{Service, ServiceBuilder, ServiceExt};
+
+    fn request(priority: Priority) -> Request<Body> {
+        let mut req = Request::new(Body::empty());
+        req.extensions_mut().insert(priority);
+        req
+    }
+
+    #[tokio::test]
+    async fn queues_and_sheds_by_priority() {
+        let depths = Arc::new(Mutex::new(Vec::new()));
+        let recorded = depths.clone();
+        let mut svc = ServiceBuilder::new()
+            .layer(
+                PrioritizeLayer::new(PriorityExtension, 2)
+                    .limit(Priority::Low, 1)
+                    .queue(Priority::Low, 1)
+                    .retry_after(Duration::from_secs(1))
+                    .on_queue_change(move |priority, depth| {
+                        recorded.lock().unwrap().push((priority, depth))
+                    }),
+            )
+            .service_fn(echo);
+
+        let first = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Priority::Low))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+
+        // only one low priority request may run at a time
+        let queued = svc.ready().await.unwrap().call(request(Priority::Low));
+        assert_eq!(svc.queue_depth(Priority::Low), 1);
+        let shed = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Priority::Low))
+            .await
+            .unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(shed.headers()[header::RETRY_AFTER], "1");
+
+        // while the remaining capacity is available to higher priorities
+        let high = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Priority::High))
+            .await
+            .unwrap();
+        assert_eq!(high.status(), StatusCode::OK);
+        assert_eq!(svc.in_flight(), 2);
+
+        drop(first);
+        drop(high);
+        let queued = queued.await.unwrap();
+        assert_eq!(queued.status(), StatusCode::OK);
+        assert_eq!(svc.queue_depth(Priority::Low), 0);
+
+        assert_eq!(
+            *depths.lock().unwrap(),
+            vec![(Priority::Low, 1), (Priority::Low, 0)]
+        );
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -0,0 +1,280 @@
+use super::Priority;
+use crate::sync::lock_ignore_poison;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+pub(super) type OnQueueChange = Box<dyn FnMut(Priority, usize) + Send>;
+
+/// The state shared by all services produced by the same layer.
+pub(super) struct Scheduler {
+    // indexed by `Priority::index`
+    pub(super) limits: [usize; 3],
+    pub(super) queue_limits: [usize; 3],
+    pub(super) on_queue_change: Option<OnQueueChange>,
+    in_flight: usize,
+    queues: [VecDeque<u64>; 3],
+    waiters: HashMap<u64, Waiter>,
+    next_id: u64,
+}
+
+struct Waiter {
+    admitted: bool,
+    waker: Option<Waker>,
+}
+
+/// The result of trying to admit a request.
+pub(super) enum Admission {
+    Admitted(Permit),
+    Queued(Ticket),
+    Rejected,
+}
+
+impl Scheduler {
+    pub(super) fn new(max: usize) -> Self {
+        Self {
+            limits: [max; 3],
+            queue_limits: [0; 3],
+            on_queue_change: None,
+            in_flight: 0,
+            queues: Default::default(),
+            waiters: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub(super) fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    pub(super) fn queue_depth(&self, priority: Priority) -> usize {
+        self.queues[priority.index()].len()
+    }
+
+    fn report(&mut self, priority: Priority) {
+        let depth = self.queue_depth(priority);
+        if let Some(on_queue_change) = &mut self.on_queue_change {
+            on_queue_change(priority, depth);
+        }
+    }
+
+    /// Admit queued requests, highest priority first, while there is capacity for them.
+    fn schedule(&mut self) {
+        for priority in Priority::DESCENDING {
+            let i = priority.index();
+            let mut changed = false;
+            while self.in_flight < self.limits[i] {
+                let id = match self.queues[i].pop_front() {
+                    Some(id) => id,
+                    None => break,
+                };
+                let waiter = self
+                    .waiters
+                    .get_mut(&id)
+                    .expect("queued requests have a waiter");
+                waiter.admitted = true;
+                if let Some(waker) = waiter.waker.take() {
+                    waker.wake();
+                }
+                self.in_flight += 1;
+                changed = true;
+            }
+            if changed {
+                self.report(priority);
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        self.in_flight -= 1;
+        self.schedule();
+    }
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("limits", &self.limits)
+            .field("queue_limits", &self.queue_limits)
+            .field("in_flight", &self.in_flight)
+            .field(
+                "queues",
+                &self.queues.iter().map(VecDeque::len).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+pub(super) fn admit(scheduler: &Arc<Mutex<Scheduler>>, priority: Priority) -> Admission {
+    let mut locked = lock_ignore_poison(scheduler);
+    let i = priority.index();
+
+    // requests of the same priority are admitted in order
+    if locked.queues[i].is_empty() && locked.in_flight < locked.limits[i] {
+        locked.in_flight += 1;
+        return Admission::Admitted(Permit {
+            scheduler: scheduler.clone(),
+        });
+    }
+
+    if locked.queues[i].len() < locked.queue_limits[i] {
+        let id = locked.next_id;
+        locked.next_id += 1;
+        locked.waiters.insert(
+            id,
+            Waiter {
+                admitted: false,
+                waker: None,
+            },
+        );
+        locked.queues[i].push_back(id);
+        locked.report(priority);
+        return Admission::Queued(Ticket {
+            scheduler: scheduler.clone(),
+            priority,
+            id,
+        });
+    }
+
+    Admission::Rejected
+}
+
+/// A request's share of the capacity, released when dropped.
+pub(super) struct Permit {
+    scheduler: Arc<Mutex<Scheduler>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        lock_ignore_poison(&self.scheduler).release();
+    }
+}
+
+/// A request's place in the queue, given up when dropped.
+pub(super) struct Ticket {
+    scheduler: Arc<Mutex<Scheduler>>,
+    priority: Priority,
+    id: u64,
+}
+
+impl Ticket {
+    pub(super) fn poll_admitted(&mut self, cx: &mut Context<'_>) -> Poll<Permit> {
+        let mut locked = lock_ignore_poison(&self.scheduler);
+        let waiter = locked
+            .waiters
+            .get_mut(&self.id)
+            .expect("ticket polled after admission");
+        if !waiter.admitted {
+            waiter.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        locked.waiters.remove(&self.id);
+        Poll::Ready(Permit {
+            scheduler: self.scheduler.clone(),
+        })
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut locked = lock_ignore_poison(&self.scheduler);
+        match locked.waiters.remove(&self.id) {
+            // the request was admitted but gave up before using its capacity
+            Some(Waiter { admitted: true, .. }) => locked.release(),
+            Some(Waiter {
+                admitted: false, ..
+            }) => {
+                let id = self.id;
+                locked.queues[self.priority.index()].retain(|queued| *queued != id);
+                locked.report(self.priority);
+            }
+            // turned into a permit
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admitted(admission: Admission) -> Permit {
+        match admission {
+            Admission::Admitted(permit) => permit,
+            _ => panic!("request wasn't admitted"),
+        }
+    }
+
+    fn queued(admission: Admission) -> Ticket {
+        match admission {
+            Admission::Queued(ticket) => ticket,
+            _ => panic!("request wasn't queued"),
+        }
+    }
+
+    fn ready(poll: Poll<Permit>) -> Permit {
+        match poll {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => panic!("request wasn't admitted"),
+        }
+    }
+
+    #[test]
+    fn admits_higher_priorities_first() {
+        let mut scheduler = Scheduler::new(2);
+        scheduler.limits[Priority::Low.index()] = 1;
+        scheduler.queue_limits = [1, 1, 1];
+        let scheduler = Arc::new(Mutex::new(scheduler));
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let first = admitted(admit(&scheduler, Priority::Normal));
+        // capacity is reserved for higher priorities
+        let mut low = queued(admit(&scheduler, Priority::Low));
+        assert!(matches!(
+            admit(&scheduler, Priority::Low),
+            Admission::Rejected
+        ));
+        let second = admitted(admit(&scheduler, Priority::High));
+        let mut high = queued(admit(&scheduler, Priority::High));
+
+        drop(first);
+        assert!(low.poll_admitted(&mut cx).is_pending());
+        let third = ready(high.poll_admitted(&mut cx));
+
+        drop(second);
+        assert!(low.poll_admitted(&mut cx).is_pending());
+        drop(third);
+        let _low = ready(low.poll_admitted(&mut cx));
+        assert_eq!(lock_ignore_poison(&scheduler).in_flight(), 1);
+    }
+
+    #[test]
+    fn dropped_tickets_leave_the_queue() {
+        let mut scheduler = Scheduler::new(1);
+        scheduler.queue_limits = [1, 1, 1];
+        let scheduler = Arc::new(Mutex::new(scheduler));
+
+        let permit = admitted(admit(&scheduler, Priority::Normal));
+        let ticket = queued(admit(&scheduler, Priority::Normal));
+        assert_eq!(
+            lock_ignore_poison(&scheduler).queue_depth(Priority::Normal),
+            1
+        );
+        drop(ticket);
+        assert_eq!(
+            lock_ignore_poison(&scheduler).queue_depth(Priority::Normal),
+            0
+        );
+
+        // admitted tickets that are never polled give their capacity back
+        let ticket = queued(admit(&scheduler, Priority::Normal));
+        drop(permit);
+        assert_eq!(lock_ignore_poison(&scheduler).in_flight(), 1);
+        drop(ticket);
+        assert_eq!(lock_ignore_poison(&scheduler).in_flight(), 0);
+    }
+}
@@ -0,0 +1,181 @@
+use super::{
+    future::State,
+    scheduler::{self, Admission, Scheduler},
+    ClassifyPriority, Priority, ResponseBody, ResponseFuture,
+};
+use crate::{ready::take_ready, sync::lock_ignore_poison};
+use http::{HeaderValue, Request, Response};
+use http_body::Body;
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`Prioritize`] middleware which admits requests by priority under
+/// concurrency pressure.
+///
+/// See the [module docs](crate::prioritize) for an example.
+#[derive(Clone, Debug)]
+pub struct PrioritizeLayer<C> {
+    classifier: C,
+    scheduler: Arc<Mutex<Scheduler>>,
+    retry_after: Option<HeaderValue>,
+}
+
+impl<C> PrioritizeLayer<C> {
+    /// Create a new `PrioritizeLayer` that classifies requests with `classifier` and allows at
+    /// most `max` concurrent requests.
+    pub fn new(classifier: C, max: usize) -> Self {
+        Self {
+            classifier,
+            scheduler: Arc::new(Mutex::new(Scheduler::new(max))),
+            retry_after: None,
+        }
+    }
+
+    /// Only admit requests of `priority` while fewer than `limit` requests are in flight.
+    ///
+    /// Setting lower limits for lower priorities reserves the remaining capacity for higher
+    /// priorities. Defaults to the maximum set in [`PrioritizeLayer::new`].
+    pub fn limit(self, priority: Priority, limit: usize) -> Self {
+        lock_ignore_poison(&self.scheduler).limits[priority.index()] = limit;
+        self
+    }
+
+    /// Let up to `queue` requests of `priority` wait for capacity before shedding them.
+    ///
+    /// Waiting requests are admitted highest priority first. Defaults to `0`, meaning requests
+    /// are shed as soon as their limit is reached.
+    pub fn queue(self, priority: Priority, queue: usize) -> Self {
+        lock_ignore_poison(&self.scheduler).queue_limits[priority.index()] = queue;
+        self
+    }
+
+    /// Call `f` with the priority and the new depth of a queue whenever it changes.
+    ///
+    /// This can be used to record the queue depths as metrics. `f` is called while the state
+    /// shared by the services is locked, so it mustn't block.
+    pub fn on_queue_change<F>(self, f: F) -> Self
+    where
+        F: FnMut(Priority, usize) + Send + 'static,
+    {
+        lock_ignore_poison(&self.scheduler).on_queue_change = Some(Box::new(f));
+        self
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// The duration is rounded down to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+}
+
+impl<S, C> Layer<S> for PrioritizeLayer<C>
+where
+    C: Clone,
+{
+    type Service = Prioritize<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Prioritize {
+            inner,
+            classifier: self.classifier.clone(),
+            scheduler: self.scheduler.clone(),
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+/// Middleware that admits requests by priority under concurrency pressure, and queues or
+/// responds with `503 Service Unavailable` to the rest.
+///
+/// See the [module docs](crate::prioritize) for an example.
+#[derive(Clone, Debug)]
+pub struct Prioritize<S, C> {
+    inner: S,
+    classifier: C,
+    scheduler: Arc<Mutex<Scheduler>>,
+    retry_after: Option<HeaderValue>,
+}
+
+impl<S, C> Prioritize<S, C> {
+    /// Create a new `Prioritize` that classifies requests with `classifier` and allows at most
+    /// `max` concurrent requests.
+    pub fn new(inner: S, classifier: C, max: usize) -> Self
+    where
+        C: Clone,
+    {
+        PrioritizeLayer::new(classifier, max).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Prioritize` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(classifier: C, max: usize) -> PrioritizeLayer<C> {
+        PrioritizeLayer::new(classifier, max)
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// See [`PrioritizeLayer::retry_after`] for more details.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    /// Returns the number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        lock_ignore_poison(&self.scheduler).in_flight()
+    }
+
+    /// Returns the number of requests of `priority` waiting for capacity.
+    pub fn queue_depth(&self, priority: Priority) -> usize {
+        lock_ignore_poison(&self.scheduler).queue_depth(priority)
+    }
+}
+
+impl<S, C, ReqBody, ResBody> Service<Request<ReqBody>> for Prioritize<S, C>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    C: ClassifyPriority<ReqBody>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, Request<ReqBody>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let priority = self.classifier.classify(&req);
+        let state = match scheduler::admit(&self.scheduler, priority) {
+            Admission::Admitted(permit) => State::Called {
+                future: self.inner.call(req),
+                permit: Some(permit),
+            },
+            Admission::Queued(ticket) => {
+                // the request is processed later
+                let service = take_ready(&mut self.inner);
+                State::Waiting {
+                    ticket,
+                    service,
+                    request: Some(req),
+                    permit: None,
+                }
+            }
+            Admission::Rejected => State::Rejected,
+        };
+
+        ResponseFuture::new(state, self.retry_after.clone())
+    }
+}
@@ -0,0 +1,538 @@
+//! Middleware that converts error responses into [RFC 9457] problem details.
+//!
+//! [`ProblemDetailsLayer`] replaces the body of error responses with an `application/problem+json`
+//! document containing the `type`, `title`, `status` and, if set, `detail` and `instance` members.
+//!
+//! By default server errors, and client errors with no `Content-Type` or a `text/plain` body, are
+//! converted. That covers the rejections produced by the middleware in this crate, such as
+//! `413 Payload Too Large` from [`limit`](crate::limit) or `408 Request Timeout` from
+//! [`timeout`](crate::timeout), while leaving error bodies your application already formats alone.
+//! Responses that are already `application/problem+json` are never converted.
+//!
+//! Which responses are converted, and what the problem details contain, can be customized with
+//! [`ProblemDetailsLayer::make_problem_with`].
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response, StatusCode};
+//! use http_body::Full;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::problem_details::{
+//!     DefaultMakeProblem, MakeProblem, ProblemDetailsLayer,
+//! };
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let mut res = Response::new(Full::default());
+//!     *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         ProblemDetailsLayer::new().make_problem_with(|parts: &http::response::Parts| {
+//!             // Add a custom member to the default problem details.
+//!             DefaultMakeProblem
+//!                 .make_problem(parts)
+//!                 .map(|problem| problem.with_extension("retryable", true))
+//!         }),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let response = service.ready().await?.call(Request::new(Full::default())).await?;
+//!
+//! assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+//!
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! let json: serde_json::Value = serde_json::from_slice(&body)?;
+//! assert_eq!(json, serde_json::json!({
+//!     "type": "about:blank",
+//!     "title": "Service Unavailable",
+//!     "status": 503,
+//!     "retryable": true,
+//! }));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [RFC 9457]: https://www.rfc-editor.org/rfc/rfc9457
+
+use bytes::Bytes;
+use futures_util::ready;
+use http::{header, response::Parts, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::{Body, Full, SizeHint};
+use pin_project_lite::pin_project;
+use serde_json::{Map, Value};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// A problem details object, as defined by [RFC 9457].
+///
+/// Created by [`MakeProblem`] for the responses converted by [`ProblemDetails`].
+///
+/// [RFC 9457]: https://www.rfc-editor.org/rfc/rfc9457
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    status: StatusCode,
+    type_: String,
+    title: Option<String>,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// Create a new `Problem` for a status code.
+    ///
+    /// The type defaults to `about:blank` and the title to the status code's canonical reason.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            type_: "about:blank".to_owned(),
+            title: status.canonical_reason().map(ToOwned::to_owned),
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Set the URI reference identifying the problem type.
+    pub fn with_type(mut self, type_: impl Into<String>) -> Self {
+        self.type_ = type_.into();
+        self
+    }
+
+    /// Set the short, human-readable summary of the problem type.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Set the human-readable explanation specific to this occurrence of the problem.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Set the URI reference identifying this occurrence of the problem.
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Add a custom member.
+    ///
+    /// Members named like the standard members are ignored when serializing.
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extensions.insert(name.into(), value.into());
+        self
+    }
+
+    /// The status code.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Serialize the problem details to JSON.
+    pub fn to_json(&self) -> Value {
+        let mut members = Map::new();
+        for (name, value) in &self.extensions {
+            if !matches!(
+                name.as_str(),
+                "type" | "title" | "status" | "detail" | "instance"
+            ) {
+                members.insert(name.clone(), value.clone());
+            }
+        }
+
+        members.insert("type".to_owned(), self.type_.clone().into());
+        if let Some(title) = &self.title {
+            members.insert("title".to_owned(), title.clone().into());
+        }
+        members.insert("status".to_owned(), self.status.as_u16().into());
+        if let Some(detail) = &self.detail {
+            members.insert("detail".to_owned(), detail.clone().into());
+        }
+        if let Some(instance) = &self.instance {
+            members.insert("instance".to_owned(), instance.clone().into());
+        }
+
+        Value::Object(members)
+    }
+
+    /// Convert the problem details into an `application/problem+json` response.
+    pub fn into_response(self) -> Response<Full<Bytes>> {
+        let body = serde_json::to_vec(&self.to_json()).expect("serializing JSON cannot fail");
+
+        let mut res = Response::new(Full::from(body));
+        *res.status_mut() = self.status;
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        res
+    }
+}
+
+/// Trait for deciding which responses are converted and creating their problem details.
+///
+/// Returning `None` leaves the response unchanged.
+pub trait MakeProblem {
+    /// Create problem details for a response.
+    fn make_problem(&mut self, parts: &Parts) -> Option<Problem>;
+}
+
+impl<F> MakeProblem for F
+where
+    F: FnMut(&Parts) -> Option<Problem>,
+{
+    fn make_problem(&mut self, parts: &Parts) -> Option<Problem> {
+        self(parts)
+    }
+}
+
+/// The default [`MakeProblem`] used by [`ProblemDetails`].
+///
+/// Converts server errors, and client errors with no `Content-Type` or a `text/plain` body,
+/// unless they are already `application/problem+json`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultMakeProblem;
+
+impl MakeProblem for DefaultMakeProblem {
+    fn make_problem(&mut self, parts: &Parts) -> Option<Problem> {
+        let content_type = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_ascii_lowercase()
+            });
+
+        let convert = match content_type.as_deref() {
+            Some(PROBLEM_JSON) => false,
+            _ if parts.status.is_server_error() => true,
+            None | Some("text/plain") => parts.status.is_client_error(),
+            Some(_) => false,
+        };
+
+        if convert {
+            Some(Problem::new(parts.status))
+        } else {
+            None
+        }
+    }
+}
+
+/// Layer that applies [`ProblemDetails`] which converts error responses into problem
+/// details.
+///
+/// See the [module docs](crate::problem_details) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProblemDetailsLayer<M = DefaultMakeProblem> {
+    make_problem: M,
+}
+
+impl ProblemDetailsLayer {
+    /// Create a new `ProblemDetailsLayer`.
+    pub fn new() -> Self {
+        Self {
+            make_problem: DefaultMakeProblem,
+        }
+    }
+}
+
+impl<M> ProblemDetailsLayer<M> {
+    /// Customize which responses are converted and what their problem details contain.
+    pub fn make_problem_with<NewM>(self, make_problem: NewM) -> ProblemDetailsLayer<NewM>
+    where
+        NewM: MakeProblem,
+    {
+        ProblemDetailsLayer { make_problem }
+    }
+}
+
+impl<S, M> Layer<S> for ProblemDetailsLayer<M>
+where
+    M: Clone,
+{
+    type Service = ProblemDetails<S, M>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProblemDetails {
+            inner,
+            make_problem: self.make_problem.clone(),
+        }
+    }
+}
+
+/// Middleware that converts error responses into problem details.
+///
+/// See the [module docs](crate::problem_details) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct ProblemDetails<S, M = DefaultMakeProblem> {
+    inner: S,
+    make_problem: M,
+}
+
+impl<S> ProblemDetails<S> {
+    /// Create a new `ProblemDetails`.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            make_problem: DefaultMakeProblem,
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `ProblemDetailsLayer` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> ProblemDetailsLayer {
+        ProblemDetailsLayer::new()
+    }
+}
+
+impl<S, M> ProblemDetails<S, M> {
+    define_inner_service_accessors!();
+
+    /// Customize which responses are converted and what their problem details contain.
+    pub fn make_problem_with<NewM>(self, make_problem: NewM) -> ProblemDetails<S, NewM>
+    where
+        NewM: MakeProblem,
+    {
+        ProblemDetails {
+            inner: self.inner,
+            make_problem,
+        }
+    }
+}
+
+impl<S, M, ReqBody, ResBody> Service<Request<ReqBody>> for ProblemDetails<S, M>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body<Data = Bytes>,
+    M: MakeProblem + Clone,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, M>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            make_problem: self.make_problem.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ProblemDetails`].
+    pub struct ResponseFuture<F, M> {
+        #[pin]
+        inner: F,
+        make_problem: M,
+    }
+}
+
+impl<F, M, B, E> Future for ResponseFuture<F, M>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes>,
+    M: MakeProblem,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx)?);
+
+        let (parts, body) = res.into_parts();
+        let problem = match this.make_problem.make_problem(&parts) {
+            Some(problem) => problem,
+            None => {
+                return Poll::Ready(Ok(Response::from_parts(
+                    parts,
+                    ResponseBody {
+                        kind: BodyKind::Body { body },
+                    },
+                )))
+            }
+        };
+
+        let (problem_parts, problem_body) = problem.into_response().into_parts();
+        let mut parts = parts;
+        parts.status = problem_parts.status;
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.remove(header::CONTENT_ENCODING);
+        parts.headers.extend(problem_parts.headers);
+
+        Poll::Ready(Ok(Response::from_parts(
+            parts,
+            ResponseBody {
+                kind: BodyKind::Problem { body: problem_body },
+            },
+        )))
+    }
+}
+
+pin_project! {
+    /// Response body for [`ProblemDetails`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B> {
+        Problem {
+            #[pin]
+            body: Full<Bytes>,
+        },
+        Body {
+            #[pin]
+            body: B,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Problem { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body } => body.poll_data(cx),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Problem { body } => body.poll_trailers(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Problem { body } => body.is_end_stream(),
+            BodyKind::Body { body } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Problem { body } => body.size_hint(),
+            BodyKind::Body { body } => body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    async fn respond(status: StatusCode, content_type: Option<&'static str>) -> Response<Body> {
+        let svc = ServiceBuilder::new()
+            .layer(ProblemDetailsLayer::new())
+            .service_fn(move |_: Request<Body>| async move {
+                let mut res = Response::new(Body::from("original"));
+                *res.status_mut() = status;
+                if let Some(content_type) = content_type {
+                    res.headers_mut()
+                        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+                }
+                Ok::<_, Infallible>(res)
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        Response::from_parts(parts, Body::from(body))
+    }
+
+    #[tokio::test]
+    async fn converts_error_responses() {
+        let res = respond(StatusCode::PAYLOAD_TOO_LARGE, Some("text/plain")).await;
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Payload Too Large",
+                "status": 413,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_other_responses_alone() {
+        for (status, content_type) in [
+            (StatusCode::OK, None),
+            (StatusCode::BAD_REQUEST, Some("application/json")),
+            (StatusCode::INTERNAL_SERVER_ERROR, Some(PROBLEM_JSON)),
+        ] {
+            let res = respond(status, content_type).await;
+
+            assert_eq!(res.status(), status);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(body, "original");
+        }
+    }
+
+    #[test]
+    fn standard_members_take_precedence() {
+        let json = Problem::new(StatusCode::NOT_FOUND)
+            .with_detail("no such user")
+            .with_extension("status", 200)
+            .with_extension("user", "bob")
+            .to_json();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "no such user",
+                "user": "bob",
+            })
+        );
+    }
+}
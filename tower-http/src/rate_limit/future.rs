@@ -0,0 +1,237 @@
+use super::{Decision, Quota};
+use futures_core::ready;
+use http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future for [`RateLimit`].
+    ///
+    /// [`RateLimit`]: super::RateLimit
+    pub struct ResponseFuture<S, R, A>
+    where
+        S: Service<R>,
+    {
+        #[pin]
+        state: State<S, R, S::Future, A>,
+        quota: Quota,
+    }
+}
+
+impl<S, R, A> ResponseFuture<S, R, A>
+where
+    S: Service<R>,
+{
+    pub(super) fn new(state: State<S, R, S::Future, A>, quota: Quota) -> Self {
+        Self { state, quota }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    pub(super) enum State<S, R, F, A> {
+        Acquiring {
+            #[pin]
+            acquire: A,
+            service: S,
+            request: Option<R>,
+        },
+        Called {
+            #[pin]
+            future: F,
+            decision: Option<Decision>,
+        },
+    }
+}
+
+impl<S, R, A, B> Future for ResponseFuture<S, R, A>
+where
+    S: Service<R, Response = Response<B>>,
+    A: Future<Output = Decision>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Acquiring {
+                    acquire,
+                    service,
+                    request,
+                } => {
+                    let decision = ready!(acquire.poll(cx));
+                    if !decision.is_allowed() {
+                        return Poll::Ready(Ok(too_many_requests(&decision, this.quota)));
+                    }
+                    ready!(service.poll_ready(cx))?;
+                    let request = request.take().expect("future polled after completion");
+                    State::Called {
+                        future: service.call(request),
+                        decision: Some(decision),
+                    }
+                }
+                StateProj::Called { future, decision } => {
+                    let mut res = ready!(future.poll(cx))?;
+                    if let Some(decision) = decision.take() {
+                        set_headers(res.headers_mut(), &decision, this.quota);
+                    }
+                    return Poll::Ready(Ok(res.map(|body| ResponseBody {
+                        kind: BodyKind::Body { body },
+                    })));
+                }
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, R, A> fmt::Debug for ResponseFuture<S, R, A>
+where
+    S: Service<R>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("quota", &self.quota)
+            .finish()
+    }
+}
+
+fn too_many_requests<B>(decision: &Decision, quota: &Quota) -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut res = Response::new(ResponseBody {
+        kind: BodyKind::TooManyRequests { body: Empty::new() },
+    });
+    *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    set_headers(res.headers_mut(), decision, quota);
+    res.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(seconds(decision.retry_after())),
+    );
+    res
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+#[allow(clippy::declare_interior_mutable_const)]
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+#[allow(clippy::declare_interior_mutable_const)]
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+#[allow(clippy::declare_interior_mutable_const)]
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+#[allow(clippy::declare_interior_mutable_const)]
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+#[allow(clippy::declare_interior_mutable_const)]
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+#[allow(clippy::declare_interior_mutable_const)]
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision, quota: &Quota) {
+    let limit = HeaderValue::from(decision.limit());
+    let remaining = HeaderValue::from(decision.remaining());
+    let reset = HeaderValue::from(seconds(decision.reset()));
+    let policy = format!("{};w={}", quota.limit(), seconds(quota.period()));
+
+    headers.insert(X_RATELIMIT_LIMIT, limit.clone());
+    headers.insert(X_RATELIMIT_REMAINING, remaining.clone());
+    headers.insert(X_RATELIMIT_RESET, reset.clone());
+    headers.insert(RATELIMIT_LIMIT, limit);
+    headers.insert(RATELIMIT_REMAINING, remaining);
+    headers.insert(RATELIMIT_RESET, reset);
+    headers.insert(
+        RATELIMIT_POLICY,
+        HeaderValue::from_str(&policy).expect("policy is a valid header value"),
+    );
+}
+
+/// Round up to whole seconds, so clients don't retry too early.
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+pin_project! {
+    /// Response body for [`RateLimit`].
+    ///
+    /// [`RateLimit`]: super::RateLimit
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        TooManyRequests {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::TooManyRequests { body } => {
+                body.poll_data(cx).map_err(|err| match err {})
+            }
+            BodyKindProj::Body { body } => body.poll_data(cx),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::TooManyRequests { body } => {
+                body.poll_trailers(cx).map_err(|err| match err {})
+            }
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::TooManyRequests { body } => body.is_end_stream(),
+            BodyKind::Body { body } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::TooManyRequests { body } => body.size_hint(),
+            BodyKind::Body { body } => body.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use http::{header::HeaderName, Request};
+use std::{fmt, marker::PhantomData, net::SocketAddr};
+
+/// Trait for extracting the key requests are rate limited by.
+///
+/// Requests with the same key share a limit. Requests for which `None` is returned aren't rate
+/// limited.
+///
+/// This trait is implemented for closures with the correct signature:
+///
+/// ```
+/// use http::Request;
+/// use std::time::Duration;
+/// use tower_http::rate_limit::{Quota, RateLimitLayer};
+///
+/// // limit requests by their path
+/// let layer = RateLimitLayer::new(Quota::per_second(10))
+///     .key(|req: &Request<hyper::Body>| Some(req.uri().path().to_owned()));
+/// ```
+pub trait KeyExtractor<B> {
+    /// Extract the key from the request.
+    fn extract(&mut self, request: &Request<B>) -> Option<String>;
+}
+
+impl<B, F> KeyExtractor<B> for F
+where
+    F: FnMut(&Request<B>) -> Option<String>,
+{
+    fn extract(&mut self, request: &Request<B>) -> Option<String> {
+        self(request)
+    }
+}
+
+/// A [`KeyExtractor`] that puts all requests under the same limit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobalKey;
+
+impl<B> KeyExtractor<B> for GlobalKey {
+    fn extract(&mut self, _request: &Request<B>) -> Option<String> {
+        Some(String::new())
+    }
+}
+
+/// A [`KeyExtractor`] that limits requests by the IP address of the client.
+///
+/// The address is read from a [`SocketAddr`] request extension, which has to be added by the
+/// server, for example with [`AddExtension`]. Requests without the extension aren't rate
+/// limited.
+///
+/// Behind a proxy the address is the one of the proxy, so use a closure reading a header set by
+/// the proxy instead.
+///
+/// [`AddExtension`]: crate::add_extension::AddExtension
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PeerIp;
+
+impl<B> KeyExtractor<B> for PeerIp {
+    fn extract(&mut self, request: &Request<B>) -> Option<String> {
+        let addr = request.extensions().get::<SocketAddr>()?;
+        Some(addr.ip().to_string())
+    }
+}
+
+/// A [`KeyExtractor`] that limits requests by the value of a header, such as an API key.
+///
+/// Requests without the header, or with a value that isn't valid UTF-8, aren't rate limited.
+#[derive(Clone, Debug)]
+pub struct HeaderKey {
+    name: HeaderName,
+}
+
+impl HeaderKey {
+    /// Create a new [`HeaderKey`] reading the header called `name`.
+    pub fn new(name: HeaderName) -> Self {
+        Self { name }
+    }
+}
+
+impl<B> KeyExtractor<B> for HeaderKey {
+    fn extract(&mut self, request: &Request<B>) -> Option<String> {
+        let value = request.headers().get(&self.name)?.to_str().ok()?;
+        Some(value.to_owned())
+    }
+}
+
+/// A [`KeyExtractor`] that limits requests by a request extension of type `T`, such as the
+/// authenticated user added by an earlier middleware.
+///
+/// Requests without the extension aren't rate limited.
+pub struct ExtensionKey<T> {
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T> ExtensionKey<T> {
+    /// Create a new [`ExtensionKey`].
+    pub fn new() -> Self {
+        Self { _ty: PhantomData }
+    }
+}
+
+impl<T> Default for ExtensionKey<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for ExtensionKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ExtensionKey<T> {}
+
+impl<T> fmt::Debug for ExtensionKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionKey")
+            .field("type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+impl<B, T> KeyExtractor<B> for ExtensionKey<T>
+where
+    T: fmt::Display + Send + Sync + 'static,
+{
+    fn extract(&mut self, request: &Request<B>) -> Option<String> {
+        request.extensions().get::<T>().map(ToString::to_string)
+    }
+}
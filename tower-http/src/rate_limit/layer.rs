@@ -0,0 +1,67 @@
+use super::{GlobalKey, MemoryStore, Quota, RateLimit};
+use tower_layer::Layer;
+
+/// Layer that applies the [`RateLimit`] middleware, which limits the rate of requests per key.
+///
+/// See the [module docs](crate::rate_limit) for an example.
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer<K = GlobalKey, T = MemoryStore> {
+    quota: Quota,
+    key: K,
+    store: T,
+}
+
+impl RateLimitLayer {
+    /// Create a new [`RateLimitLayer`] enforcing `quota`.
+    ///
+    /// By default all requests share the same limit and the state is kept in a new
+    /// [`MemoryStore`].
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            key: GlobalKey,
+            store: MemoryStore::new(),
+        }
+    }
+}
+
+impl<K, T> RateLimitLayer<K, T> {
+    /// Set the [`KeyExtractor`] that determines which requests share a limit.
+    ///
+    /// [`KeyExtractor`]: super::KeyExtractor
+    pub fn key<NewK>(self, key: NewK) -> RateLimitLayer<NewK, T> {
+        RateLimitLayer {
+            quota: self.quota,
+            key,
+            store: self.store,
+        }
+    }
+
+    /// Set the [`RateLimitStore`] that keeps the state of the limits.
+    ///
+    /// [`RateLimitStore`]: super::RateLimitStore
+    pub fn store<NewT>(self, store: NewT) -> RateLimitLayer<K, NewT> {
+        RateLimitLayer {
+            quota: self.quota,
+            key: self.key,
+            store,
+        }
+    }
+}
+
+impl<S, K, T> Layer<S> for RateLimitLayer<K, T>
+where
+    K: Clone,
+    T: Clone,
+{
+    type Service = RateLimit<S, K, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            quota: self.quota,
+            key: self.key.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Middleware that limits the rate of requests per key.
+//!
+//! Requests are grouped by a key, such as the client's IP address or an API key, extracted with a
+//! [`KeyExtractor`]. Each key may make the number of requests allowed by a [`Quota`], enforced
+//! with either a [token bucket](Algorithm::TokenBucket) or a
+//! [sliding window](Algorithm::SlidingWindow). Once the quota is used up requests are rejected
+//! with `429 Too Many Requests` and a `Retry-After` header.
+//!
+//! Responses include the state of the limit in the `X-RateLimit-Limit`, `X-RateLimit-Remaining`
+//! and `X-RateLimit-Reset` headers, as well as the `RateLimit-Limit`, `RateLimit-Remaining`,
+//! `RateLimit-Reset` and `RateLimit-Policy` headers from the [IETF draft]. Resets are given in
+//! seconds from now.
+//!
+//! The state of the limits is kept in a [`RateLimitStore`]. The default [`MemoryStore`] is shared
+//! by all services produced by the same [`RateLimitLayer`]. Deployments with multiple instances
+//! can implement [`RateLimitStore`] on top of a shared database to enforce a common limit.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header::HeaderName, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::rate_limit::{HeaderKey, Quota, RateLimitLayer};
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         // Allow 60 requests per minute for each API key.
+//!         RateLimitLayer::new(Quota::per_minute(60))
+//!             .key(HeaderKey::new(HeaderName::from_static("x-api-key"))),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::builder()
+//!     .header("x-api-key", "secret")
+//!     .body(Body::empty())?;
+//!
+//! let response = service.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! assert_eq!(response.headers()["ratelimit-remaining"], "59");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [IETF draft]: https://datatracker.ietf.org/doc/draft-ietf-httpapi-ratelimit-headers/
+
+mod future;
+mod key;
+mod layer;
+mod service;
+mod store;
+
+pub use self::{
+    future::{ResponseBody, ResponseFuture},
+    key::{ExtensionKey, GlobalKey, HeaderKey, KeyExtractor, PeerIp},
+    layer::RateLimitLayer,
+    service::RateLimit,
+    store::{Algorithm, Decision, MemoryStore, Quota, RateLimitStore},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{header, Request, Response, StatusCode};
+    use hyper::Body;
+    use std::{convert::Infallible, net::SocketAddr};
+    use tower::{service_fn, Layer, Service, ServiceBuilder, ServiceExt};
+
+    async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(Body::from("hello")))
+    }
+
+    fn request(ip: [u8; 4]) -> Request<Body> {
+        let mut req = Request::new(Body::empty());
+        req.extensions_mut().insert(SocketAddr::from((ip, 8080)));
+        req
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_over_the_limit() {
+        let mut svc = ServiceBuilder::new()
+            .layer(RateLimitLayer::new(Quota::per_minute(2)))
+            .service_fn(handle);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::default())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["x-ratelimit-limit"], "2");
+        assert_eq!(res.headers()["x-ratelimit-remaining"], "1");
+        assert_eq!(res.headers()["x-ratelimit-reset"], "30");
+        assert_eq!(res.headers()["ratelimit-limit"], "2");
+        assert_eq!(res.headers()["ratelimit-remaining"], "1");
+        assert_eq!(res.headers()["ratelimit-reset"], "30");
+        assert_eq!(res.headers()["ratelimit-policy"], "2;w=60");
+        assert!(!res.headers().contains_key(header::RETRY_AFTER));
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello");
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::default())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["ratelimit-remaining"], "0");
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::default())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["ratelimit-remaining"], "0");
+        assert_eq!(res.headers()["ratelimit-reset"], "60");
+        assert_eq!(res.headers()[header::RETRY_AFTER], "30");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn limits_per_key() {
+        let layer = RateLimitLayer::new(Quota::per_minute(1).sliding_window()).key(PeerIp);
+        let mut svc = layer.layer(service_fn(handle));
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request([10, 0, 0, 1]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request([10, 0, 0, 1]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request([10, 0, 0, 2]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // services from the same layer share the limits
+        let mut svc = layer.layer(service_fn(handle));
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(request([10, 0, 0, 2]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn requests_without_key_are_not_limited() {
+        let mut svc = ServiceBuilder::new()
+            .layer(RateLimitLayer::new(Quota::per_minute(1)).key(PeerIp))
+            .service_fn(handle);
+
+        for _ in 0..3 {
+            let res = svc
+                .ready()
+                .await
+                .unwrap()
+                .call(Request::default())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(!res.headers().contains_key("ratelimit-limit"));
+        }
+    }
+}
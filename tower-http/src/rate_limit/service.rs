@@ -0,0 +1,104 @@
+use super::{
+    future::State, GlobalKey, KeyExtractor, MemoryStore, Quota, RateLimitLayer, RateLimitStore,
+    ResponseBody, ResponseFuture,
+};
+use crate::ready::take_ready;
+use http::{Request, Response};
+use http_body::Body;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Middleware that limits the rate of requests per key and responds with
+/// `429 Too Many Requests` once the limit is reached.
+///
+/// See the [module docs](crate::rate_limit) for an example.
+#[derive(Clone, Debug)]
+pub struct RateLimit<S, K = GlobalKey, T = MemoryStore> {
+    pub(crate) inner: S,
+    pub(crate) quota: Quota,
+    pub(crate) key: K,
+    pub(crate) store: T,
+}
+
+impl<S> RateLimit<S> {
+    /// Create a new [`RateLimit`] enforcing `quota`.
+    ///
+    /// See [`RateLimitLayer::new`] for more details.
+    pub fn new(inner: S, quota: Quota) -> Self {
+        Self {
+            inner,
+            quota,
+            key: GlobalKey,
+            store: MemoryStore::new(),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `RateLimit` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(quota: Quota) -> RateLimitLayer {
+        RateLimitLayer::new(quota)
+    }
+}
+
+impl<S, K, T> RateLimit<S, K, T> {
+    define_inner_service_accessors!();
+
+    /// Set the [`KeyExtractor`] that determines which requests share a limit.
+    pub fn key<NewK>(self, key: NewK) -> RateLimit<S, NewK, T> {
+        RateLimit {
+            inner: self.inner,
+            quota: self.quota,
+            key,
+            store: self.store,
+        }
+    }
+
+    /// Set the [`RateLimitStore`] that keeps the state of the limits.
+    pub fn store<NewT>(self, store: NewT) -> RateLimit<S, K, NewT> {
+        RateLimit {
+            inner: self.inner,
+            quota: self.quota,
+            key: self.key,
+            store,
+        }
+    }
+}
+
+impl<S, K, T, ReqBody, ResBody> Service<Request<ReqBody>> for RateLimit<S, K, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    K: KeyExtractor<ReqBody>,
+    T: RateLimitStore,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, Request<ReqBody>, T::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match self.key.extract(&req) {
+            Some(key) => {
+                let acquire = self.store.acquire(&key, &self.quota);
+                // the request is processed once the store has decided
+                let service = take_ready(&mut self.inner);
+                State::Acquiring {
+                    acquire,
+                    service,
+                    request: Some(req),
+                }
+            }
+            None => State::Called {
+                future: self.inner.call(req),
+                decision: None,
+            },
+        };
+
+        ResponseFuture::new(state, self.quota)
+    }
+}
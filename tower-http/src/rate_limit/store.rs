@@ -0,0 +1,403 @@
+use crate::sync::lock_ignore_poison;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{self, Future, Ready},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A limit on the number of requests in a period of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    limit: u32,
+    period: Duration,
+    algorithm: Algorithm,
+}
+
+impl Quota {
+    /// Allow `limit` requests per `period`, using the [token bucket](Algorithm::TokenBucket)
+    /// algorithm.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` or `period` is zero.
+    pub fn new(limit: u32, period: Duration) -> Self {
+        assert!(limit > 0, "rate limit must be greater than zero");
+        assert!(
+            period > Duration::ZERO,
+            "rate limit period must be greater than zero"
+        );
+        Self {
+            limit,
+            period,
+            algorithm: Algorithm::TokenBucket,
+        }
+    }
+
+    /// Allow `limit` requests per second.
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    /// Allow `limit` requests per minute.
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Allow `limit` requests per hour.
+    pub fn per_hour(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60 * 60))
+    }
+
+    /// Enforce the quota with the [sliding window](Algorithm::SlidingWindow) algorithm.
+    pub fn sliding_window(mut self) -> Self {
+        self.algorithm = Algorithm::SlidingWindow;
+        self
+    }
+
+    /// The number of requests allowed per period.
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// The period of the quota.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The algorithm the quota is enforced with.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+/// The algorithm used to enforce a [`Quota`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// A bucket of `limit` tokens that is refilled evenly over the period, allowing bursts of up
+    /// to `limit` requests.
+    TokenBucket,
+    /// At most `limit` requests in any window of the period's length.
+    ///
+    /// Stores may approximate the window from the number of requests in the current and
+    /// previous fixed windows, like [`MemoryStore`] does.
+    SlidingWindow,
+}
+
+/// The result of counting a request against a [`Quota`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    reset: Duration,
+    retry_after: Duration,
+}
+
+impl Decision {
+    /// Allow the request.
+    ///
+    /// `remaining` is the number of requests still allowed and `reset` the time until the
+    /// whole quota is available again.
+    pub fn allow(limit: u32, remaining: u32, reset: Duration) -> Self {
+        Self {
+            allowed: true,
+            limit,
+            remaining,
+            reset,
+            retry_after: Duration::ZERO,
+        }
+    }
+
+    /// Reject the request because the quota has been used up.
+    ///
+    /// `retry_after` is the time until the next request is allowed and `reset` the time until
+    /// the whole quota is available again.
+    pub fn reject(limit: u32, reset: Duration, retry_after: Duration) -> Self {
+        Self {
+            allowed: false,
+            limit,
+            remaining: 0,
+            reset,
+            retry_after,
+        }
+    }
+
+    /// Whether the request is allowed.
+    pub fn is_allowed(&self) -> bool {
+        self.allowed
+    }
+
+    /// The number of requests allowed per period.
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// The number of requests still allowed.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// The time until the whole quota is available again.
+    pub fn reset(&self) -> Duration {
+        self.reset
+    }
+
+    /// The time until the next request is allowed, zero if the request was allowed.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+}
+
+/// Storage for the state of rate limits.
+///
+/// Stores count requests against a [`Quota`] and decide whether they are allowed. Using a store
+/// shared by multiple instances of a service, such as one backed by a database, makes the
+/// instances enforce a common limit.
+///
+/// Stores are cloned for every service produced by a [`RateLimitLayer`], so clones should share
+/// their state.
+///
+/// [`RateLimitLayer`]: super::RateLimitLayer
+pub trait RateLimitStore {
+    /// The future returned by [`acquire`](Self::acquire).
+    type Future: Future<Output = Decision>;
+
+    /// Count a request with `key` against `quota`.
+    ///
+    /// Stores that fail to reach their backend decide themselves whether to allow requests.
+    fn acquire(&self, key: &str, quota: &Quota) -> Self::Future;
+}
+
+impl<T> RateLimitStore for Arc<T>
+where
+    T: RateLimitStore + ?Sized,
+{
+    type Future = T::Future;
+
+    fn acquire(&self, key: &str, quota: &Quota) -> Self::Future {
+        (**self).acquire(key, quota)
+    }
+}
+
+/// A [`RateLimitStore`] keeping the state of rate limits in memory.
+///
+/// Clones share the same state. Keys are expected to be used with a single [`Quota`], so use a
+/// separate store for each layer with a different quota.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    calls: u64,
+}
+
+enum Entry {
+    TokenBucket {
+        // the theoretical arrival time of the next request, when the bucket has one more token
+        // than it has now
+        tat: Instant,
+    },
+    SlidingWindow {
+        start: Instant,
+        previous: u32,
+        current: u32,
+    },
+}
+
+/// How often expired entries are removed, in calls to `acquire`.
+const CLEANUP_INTERVAL: u64 = 1024;
+
+impl MemoryStore {
+    /// Create a new, empty [`MemoryStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    type Future = Ready<Decision>;
+
+    fn acquire(&self, key: &str, quota: &Quota) -> Self::Future {
+        let now = Instant::now();
+        let mut inner = lock_ignore_poison(&self.inner);
+
+        inner.calls += 1;
+        if inner.calls % CLEANUP_INTERVAL == 0 {
+            inner
+                .entries
+                .retain(|_, entry| !entry.is_expired(now, quota.period));
+        }
+
+        let entry = inner
+            .entries
+            .entry(key.to_owned())
+            .or_insert_with(|| match quota.algorithm {
+                Algorithm::TokenBucket => Entry::TokenBucket { tat: now },
+                Algorithm::SlidingWindow => Entry::SlidingWindow {
+                    start: now,
+                    previous: 0,
+                    current: 0,
+                },
+            });
+
+        let decision = match entry {
+            Entry::TokenBucket { tat } => token_bucket(tat, now, quota),
+            Entry::SlidingWindow {
+                start,
+                previous,
+                current,
+            } => sliding_window(start, previous, current, now, quota),
+        };
+        future::ready(decision)
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("entries", &lock_ignore_poison(&self.inner).entries.len())
+            .finish()
+    }
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant, period: Duration) -> bool {
+        match self {
+            Entry::TokenBucket { tat } => *tat <= now,
+            Entry::SlidingWindow { start, .. } => now.duration_since(*start) >= period * 2,
+        }
+    }
+}
+
+/// The generic cell rate algorithm, which is equivalent to a token bucket.
+fn token_bucket(tat: &mut Instant, now: Instant, quota: &Quota) -> Decision {
+    let interval = quota.period / quota.limit;
+    let current = (*tat).max(now);
+    let next = current + interval;
+
+    // the bucket is empty if the next request would be more than a period ahead
+    if let Some(allow_at) = next.checked_sub(quota.period) {
+        if allow_at > now {
+            return Decision::reject(quota.limit, current - now, allow_at - now);
+        }
+    }
+
+    *tat = next;
+    let ahead = next - now;
+    let remaining = (quota.period.saturating_sub(ahead).as_nanos() / interval.as_nanos().max(1))
+        .min(u128::from(quota.limit)) as u32;
+    Decision::allow(quota.limit, remaining, ahead)
+}
+
+/// A sliding window approximated by weighting the count of the previous fixed window by how
+/// much of it overlaps the sliding window.
+fn sliding_window(
+    start: &mut Instant,
+    previous: &mut u32,
+    current: &mut u32,
+    now: Instant,
+    quota: &Quota,
+) -> Decision {
+    let period = quota.period;
+    let elapsed = now.duration_since(*start);
+    if elapsed >= period {
+        *previous = if elapsed < period * 2 { *current } else { 0 };
+        *current = 0;
+        let offset = elapsed.as_nanos() % period.as_nanos();
+        *start = now - Duration::from_nanos(offset as u64);
+    }
+
+    let elapsed = now.duration_since(*start);
+    let remaining_in_window = period - elapsed;
+    let weight = remaining_in_window.as_secs_f64() / period.as_secs_f64();
+    let estimate = f64::from(*previous).mul_add(weight, f64::from(*current));
+    let limit = f64::from(quota.limit);
+    // the whole quota is available once the current window has passed out of the sliding one
+    let reset = remaining_in_window + period;
+
+    if estimate + 1.0 > limit {
+        let retry_after = if *current >= quota.limit {
+            remaining_in_window
+        } else {
+            // the weight of the previous window decreases evenly over the current one
+            let excess = estimate + 1.0 - limit;
+            period.mul_f64(excess / f64::from(*previous))
+        };
+        return Decision::reject(quota.limit, reset, retry_after);
+    }
+
+    *current += 1;
+    let remaining = (limit - estimate - 1.0).floor() as u32;
+    Decision::allow(quota.limit, remaining, reset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_allows_bursts() {
+        let quota = Quota::new(2, Duration::from_secs(2));
+        let now = Instant::now();
+        let mut tat = now;
+
+        let decision = token_bucket(&mut tat, now, &quota);
+        assert_eq!(decision, Decision::allow(2, 1, Duration::from_secs(1)));
+        let decision = token_bucket(&mut tat, now, &quota);
+        assert_eq!(decision, Decision::allow(2, 0, Duration::from_secs(2)));
+
+        let decision = token_bucket(&mut tat, now, &quota);
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.retry_after(), Duration::from_secs(1));
+        assert_eq!(decision.reset(), Duration::from_secs(2));
+
+        // a token is added every second
+        let later = now + Duration::from_secs(1);
+        assert!(token_bucket(&mut tat, later, &quota).is_allowed());
+        assert!(!token_bucket(&mut tat, later, &quota).is_allowed());
+    }
+
+    #[test]
+    fn sliding_window_weights_previous_window() {
+        let quota = Quota::new(4, Duration::from_secs(10)).sliding_window();
+        let now = Instant::now();
+        let (mut start, mut previous, mut current) = (now, 0, 0);
+        let mut acquire =
+            |at: Instant| sliding_window(&mut start, &mut previous, &mut current, at, &quota);
+
+        for remaining in (0..4).rev() {
+            assert_eq!(acquire(now).remaining(), remaining);
+        }
+        let decision = acquire(now);
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.retry_after(), Duration::from_secs(10));
+
+        // half of the previous window still counts
+        let later = now + Duration::from_secs(15);
+        assert!(acquire(later).is_allowed());
+        assert!(acquire(later).is_allowed());
+        let decision = acquire(later);
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.retry_after(), Duration::from_millis(2500));
+
+        // the previous window is forgotten after two periods
+        assert_eq!(acquire(now + Duration::from_secs(40)).remaining(), 3);
+    }
+
+    #[test]
+    fn memory_store_keys() {
+        let store = MemoryStore::new();
+        let quota = Quota::per_minute(1);
+
+        let acquire = |key| futures::executor::block_on(store.acquire(key, &quota));
+        assert!(acquire("a").is_allowed());
+        assert!(!acquire("a").is_allowed());
+        assert!(acquire("b").is_allowed());
+    }
+}
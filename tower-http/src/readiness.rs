@@ -0,0 +1,553 @@
+//! Middleware that gates requests on the readiness of the service and drains them on shutdown.
+//!
+//! [`ReadinessGate`] is driven by a shared [`Readiness`] handle. Until the handle is marked
+//! ready, for example once caches have been warmed up, and again once draining has started, the
+//! middleware responds with `503 Service Unavailable` and `Connection: close`, so load balancers
+//! and clients move on to other instances. A `Retry-After` header can be added to those
+//! responses.
+//!
+//! [`Readiness::drain`] starts draining and completes once all requests that were admitted
+//! before have finished, including consuming or dropping their response bodies, after which the
+//! server can shut down without cutting off any responses.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::readiness::{Readiness, ReadinessGateLayer};
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let readiness = Readiness::new();
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(ReadinessGateLayer::new(readiness.clone()).retry_after(Duration::from_secs(5)))
+//!     .service_fn(handle);
+//!
+//! // Requests are rejected until the service is ready.
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+//!
+//! readiness.mark_ready();
+//! let response = service.ready().await?.call(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! drop(response);
+//!
+//! // On shutdown, wait for in-flight requests to finish.
+//! readiness.drain().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::{Body, Empty, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tower_layer::Layer;
+use tower_service::Service;
+
+const NOT_READY: u8 = 0;
+const READY: u8 = 1;
+const DRAINING: u8 = 2;
+
+/// Handle controlling whether [`ReadinessGate`] admits requests.
+///
+/// Clones share the same state, so one clone can be given to the middleware and another kept to
+/// mark the service ready and drain it.
+#[derive(Clone)]
+pub struct Readiness {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: AtomicU8,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl Readiness {
+    /// Create a new `Readiness` that isn't ready yet.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: AtomicU8::new(NOT_READY),
+                in_flight: AtomicUsize::new(0),
+                idle: Notify::new(),
+            }),
+        }
+    }
+
+    /// Start admitting requests.
+    ///
+    /// Does nothing once draining has started.
+    pub fn mark_ready(&self) {
+        let _ = self.inner.state.compare_exchange(
+            NOT_READY,
+            READY,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+    }
+
+    /// Stop admitting requests until [`mark_ready`](Self::mark_ready) is called again, for
+    /// example while a dependency is unavailable.
+    ///
+    /// Does nothing once draining has started.
+    pub fn mark_not_ready(&self) {
+        let _ = self.inner.state.compare_exchange(
+            READY,
+            NOT_READY,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+    }
+
+    /// Returns whether requests are being admitted.
+    pub fn is_ready(&self) -> bool {
+        self.inner.state.load(Ordering::Acquire) == READY
+    }
+
+    /// Returns whether draining has started.
+    pub fn is_draining(&self) -> bool {
+        self.inner.state.load(Ordering::Acquire) == DRAINING
+    }
+
+    /// Returns the number of admitted requests that haven't finished yet.
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Stop admitting requests for good and wait for the in-flight requests to finish.
+    ///
+    /// Requests finish once their response body has been consumed or dropped.
+    pub async fn drain(&self) {
+        self.inner.state.store(DRAINING, Ordering::SeqCst);
+        loop {
+            // register for the notification before checking to not miss it
+            let idle = self.inner.idle.notified();
+            if self.inner.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    fn admit(&self) -> Option<InFlightGuard> {
+        if !self.is_ready() {
+            return None;
+        }
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        // draining may have started in the meantime, in which case `drain` may have seen no
+        // requests in flight already
+        if self.inner.state.load(Ordering::SeqCst) != READY {
+            drop(InFlightGuard {
+                inner: self.inner.clone(),
+            });
+            return None;
+        }
+        Some(InFlightGuard {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Readiness")
+            .field("ready", &self.is_ready())
+            .field("draining", &self.is_draining())
+            .field("in_flight", &self.in_flight())
+            .finish()
+    }
+}
+
+struct InFlightGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// Layer that applies the [`ReadinessGate`] middleware which only admits requests while the
+/// service is ready.
+///
+/// See the [module docs](crate::readiness) for an example.
+#[derive(Clone, Debug)]
+pub struct ReadinessGateLayer {
+    readiness: Readiness,
+    retry_after: Option<HeaderValue>,
+}
+
+impl ReadinessGateLayer {
+    /// Create a new `ReadinessGateLayer` controlled by `readiness`.
+    pub fn new(readiness: Readiness) -> Self {
+        Self {
+            readiness,
+            retry_after: None,
+        }
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// The duration is rounded down to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+}
+
+impl<S> Layer<S> for ReadinessGateLayer {
+    type Service = ReadinessGate<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ReadinessGate {
+            inner,
+            readiness: self.readiness.clone(),
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+/// Middleware that only admits requests while the service is ready and responds with
+/// `503 Service Unavailable` otherwise.
+///
+/// See the [module docs](crate::readiness) for an example.
+#[derive(Clone, Debug)]
+pub struct ReadinessGate<S> {
+    inner: S,
+    readiness: Readiness,
+    retry_after: Option<HeaderValue>,
+}
+
+impl<S> ReadinessGate<S> {
+    /// Create a new `ReadinessGate` controlled by `readiness`.
+    pub fn new(inner: S, readiness: Readiness) -> Self {
+        ReadinessGateLayer::new(readiness).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ReadinessGate` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(readiness: Readiness) -> ReadinessGateLayer {
+        ReadinessGateLayer::new(readiness)
+    }
+
+    /// Add a `Retry-After` header to `503 Service Unavailable` responses.
+    ///
+    /// See [`ReadinessGateLayer::retry_after`] for more details.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    /// Returns the [`Readiness`] controlling the middleware.
+    pub fn readiness(&self) -> &Readiness {
+        &self.readiness
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ReadinessGate<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let kind = match self.readiness.admit() {
+            Some(guard) => Kind::Called {
+                future: self.inner.call(req),
+                guard: Some(guard),
+            },
+            None => Kind::Unavailable,
+        };
+
+        ResponseFuture {
+            kind,
+            retry_after: self.retry_after.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ReadinessGate`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+        retry_after: Option<HeaderValue>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Unavailable,
+        Called {
+            #[pin]
+            future: F,
+            guard: Option<InFlightGuard>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.kind.project() {
+            KindProj::Unavailable => Poll::Ready(Ok(service_unavailable(this.retry_after.clone()))),
+            KindProj::Called { future, guard } => {
+                let res = ready!(future.poll(cx))?;
+                let guard = guard.take();
+                Poll::Ready(Ok(res.map(|body| ResponseBody {
+                    kind: BodyKind::Body { body, guard },
+                })))
+            }
+        }
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish()
+    }
+}
+
+fn service_unavailable<B>(retry_after: Option<HeaderValue>) -> Response<ResponseBody<B>>
+where
+    B: Body,
+{
+    let mut res = Response::new(ResponseBody {
+        kind: BodyKind::Unavailable { body: Empty::new() },
+    });
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    res.headers_mut()
+        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+    if let Some(retry_after) = retry_after {
+        res.headers_mut().insert(header::RETRY_AFTER, retry_after);
+    }
+    res
+}
+
+pin_project! {
+    /// Response body for [`ReadinessGate`].
+    ///
+    /// Counts the request as in flight until the body is consumed or dropped.
+    pub struct ResponseBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B>
+    where
+        B: Body,
+    {
+        Unavailable {
+            #[pin]
+            body: Empty<B::Data>,
+        },
+        Body {
+            #[pin]
+            body: B,
+            guard: Option<InFlightGuard>,
+        },
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Unavailable { body } => body.poll_data(cx).map_err(|err| match err {}),
+            BodyKindProj::Body { mut body, guard } => {
+                let data = ready!(body.as_mut().poll_data(cx));
+                if data.is_none() && body.is_end_stream() {
+                    guard.take();
+                }
+                Poll::Ready(data)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Unavailable { body } => {
+                body.poll_trailers(cx).map_err(|err| match err {})
+            }
+            BodyKindProj::Body { body, guard } => {
+                let trailers = ready!(body.poll_trailers(cx));
+                guard.take();
+                Poll::Ready(trailers)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Unavailable { body } => body.is_end_stream(),
+            BodyKind::Body { body, .. } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Unavailable { body } => body.size_hint(),
+            BodyKind::Body { body, .. } => body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn gates_requests_until_ready() {
+        let readiness = Readiness::new();
+        let mut svc = ServiceBuilder::new()
+            .layer(ReadinessGateLayer::new(readiness.clone()).retry_after(Duration::from_secs(5)))
+            .service_fn(echo);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::CONNECTION], "close");
+        assert_eq!(res.headers()[header::RETRY_AFTER], "5");
+
+        readiness.mark_ready();
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::CONNECTION).is_none());
+
+        readiness.mark_not_ready();
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_in_flight_requests() {
+        let readiness = Readiness::new();
+        readiness.mark_ready();
+        let mut svc = ServiceBuilder::new()
+            .layer(ReadinessGateLayer::new(readiness.clone()))
+            .service_fn(echo);
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::from("foo")))
+            .await
+            .unwrap();
+        assert_eq!(readiness.in_flight(), 1);
+
+        let drain = readiness.drain();
+        futures::pin_mut!(drain);
+        assert!(drain.as_mut().now_or_never().is_none());
+        assert!(readiness.is_draining());
+
+        // no new requests are admitted while draining
+        let rejected = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        readiness.mark_ready();
+        assert!(!readiness.is_ready());
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "foo");
+        assert_eq!(readiness.in_flight(), 0);
+        drain.await;
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -0,0 +1,15 @@
+//! Helpers for services that call their inner service after `call` has returned.
+
+use std::mem;
+
+/// Take the service that was driven to ready, leaving a clone in its place.
+///
+/// `poll_ready` only prepares the service it was called on, so that service has to be the one
+/// that handles the request, while the clone is driven to ready for the next one.
+pub(crate) fn take_ready<S>(inner: &mut S) -> S
+where
+    S: Clone,
+{
+    let clone = inner.clone();
+    mem::replace(inner, clone)
+}
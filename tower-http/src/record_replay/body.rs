@@ -0,0 +1,183 @@
+use super::{Exchange, RecordSink, RecordedRequest, RecordedResponse};
+use crate::sync::lock_ignore_poison;
+use bytes::{Buf, Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+pin_project! {
+    /// Request and response body for [`Record`], which captures the data read from it.
+    ///
+    /// [`Record`]: super::Record
+    #[derive(Debug)]
+    pub struct RecordBody<B> {
+        #[pin]
+        inner: B,
+        capture: Capture,
+        limit: usize,
+        // set for response bodies, which record the exchange when they are done
+        pending: Option<Pending>,
+    }
+
+    impl<B> PinnedDrop for RecordBody<B> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if let Some(pending) = this.pending.take() {
+                pending.finish(this.capture);
+            }
+        }
+    }
+}
+
+impl<B> RecordBody<B> {
+    pub(super) fn request(inner: B, capture: Capture, limit: usize) -> Self {
+        Self {
+            inner,
+            capture,
+            limit,
+            pending: None,
+        }
+    }
+
+    pub(super) fn response(inner: B, pending: Pending, limit: usize) -> Self {
+        Self {
+            inner,
+            capture: Capture::default(),
+            limit,
+            pending: Some(pending),
+        }
+    }
+}
+
+impl<B> Body for RecordBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        match futures_util::ready!(this.inner.poll_data(cx)) {
+            Some(Ok(mut data)) => {
+                let data = data.copy_to_bytes(data.remaining());
+                this.capture.push(&data, *this.limit);
+                Poll::Ready(Some(Ok(data)))
+            }
+            Some(Err(err)) => {
+                if let Some(pending) = this.pending.take() {
+                    pending.finish(this.capture);
+                }
+                Poll::Ready(Some(Err(err)))
+            }
+            None => {
+                if let Some(pending) = this.pending.take() {
+                    pending.finish(this.capture);
+                }
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The data read from a body so far, shared between the request body and the response future.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Capture {
+    inner: Arc<Mutex<Captured>>,
+}
+
+#[derive(Debug, Default)]
+struct Captured {
+    data: BytesMut,
+    truncated: bool,
+}
+
+impl Capture {
+    fn push(&self, chunk: &[u8], limit: usize) {
+        let mut captured = lock_ignore_poison(&self.inner);
+        let room = limit.saturating_sub(captured.data.len());
+        if chunk.len() > room {
+            captured.truncated = true;
+        }
+        captured
+            .data
+            .extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+
+    fn take(&self) -> (Bytes, bool) {
+        let mut captured = lock_ignore_poison(&self.inner);
+        let truncated = captured.truncated;
+        (captured.data.split().freeze(), truncated)
+    }
+}
+
+/// An exchange waiting for its response body to be done.
+pub(super) struct Pending {
+    pub(super) sink: Arc<dyn RecordSink>,
+    pub(super) request: RecordedRequest,
+    pub(super) request_body: Capture,
+    pub(super) response: Option<RecordedResponse>,
+    pub(super) started: SystemTime,
+    pub(super) start: Instant,
+    pub(super) wait: Duration,
+}
+
+impl Pending {
+    fn finish(self, response_body: &Capture) {
+        let mut response = match self.response {
+            Some(response) => response,
+            None => return,
+        };
+        let mut request = self.request;
+        let (body, truncated) = self.request_body.take();
+        request.body = body;
+        request.body_truncated = truncated;
+        let (body, truncated) = response_body.take();
+        response.body = body;
+        response.body_truncated = truncated;
+
+        self.sink.record(Exchange {
+            request,
+            response,
+            started: self.started,
+            wait: self.wait,
+            duration: self.start.elapsed(),
+        });
+    }
+}
+
+impl fmt::Debug for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pending")
+            .field("request", &self.request)
+            .field("response", &self.response)
+            .field("started", &self.started)
+            .field("wait", &self.wait)
+            .finish()
+    }
+}
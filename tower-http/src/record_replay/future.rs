@@ -0,0 +1,62 @@
+use super::{body::Pending, record::Redact, RecordBody, RecordedResponse};
+use bytes::Bytes;
+use futures_util::ready;
+use http::Response;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Response future for [`Record`].
+    ///
+    /// [`Record`]: super::Record
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        pending: Option<Pending>,
+        limit: usize,
+        redact: Arc<Redact>,
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    pub(super) fn new(inner: F, pending: Pending, limit: usize, redact: Arc<Redact>) -> Self {
+        Self {
+            inner,
+            pending: Some(pending),
+            limit,
+            redact,
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<RecordBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+
+        let mut pending = this.pending.take().expect("future polled after completion");
+        pending.wait = pending.start.elapsed();
+        pending.response = Some(RecordedResponse {
+            status: res.status(),
+            version: res.version(),
+            headers: this.redact.headers(res.headers()),
+            body: Bytes::new(),
+            body_truncated: false,
+        });
+
+        let limit = *this.limit;
+        Poll::Ready(Ok(
+            res.map(|body| RecordBody::response(body, pending, limit))
+        ))
+    }
+}
@@ -0,0 +1,195 @@
+use super::Exchange;
+use crate::date::civil_from_days;
+use http::{header, HeaderMap, Uri};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime};
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// Export exchanges as a [HAR] log.
+///
+/// The log can be serialized with `serde_json` and opened in browser developer tools and other
+/// HTTP tooling. Bodies that aren't valid UTF-8 are exported base64 encoded, and bodies that
+/// were truncated when recording are exported truncated. Cookies aren't parsed out of the
+/// headers.
+///
+/// [HAR]: http://www.softwareishard.com/blog/har-12-spec/
+pub fn to_har(exchanges: &[Exchange]) -> Value {
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": {
+                "name": "tower-http",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "entries": exchanges.iter().map(entry).collect::<Vec<_>>(),
+        }
+    })
+}
+
+fn entry(exchange: &Exchange) -> Value {
+    let request = &exchange.request;
+    let response = &exchange.response;
+
+    let mut har_request = json!({
+        "method": request.method.as_str(),
+        "url": request.uri.to_string(),
+        "httpVersion": format!("{:?}", request.version),
+        "cookies": [],
+        "headers": headers(&request.headers),
+        "queryString": query_string(&request.uri),
+        "headersSize": -1,
+        "bodySize": request.body.len(),
+    });
+    if !request.body.is_empty() {
+        let mut post_data = text(&request.body);
+        post_data["mimeType"] = mime_type(&request.headers).into();
+        har_request["postData"] = post_data;
+    }
+
+    let mut content = text(&response.body);
+    content["size"] = response.body.len().into();
+    content["mimeType"] = mime_type(&response.headers).into();
+
+    let receive = exchange.duration.saturating_sub(exchange.wait);
+    json!({
+        "startedDateTime": rfc3339(exchange.started),
+        "time": millis(exchange.duration),
+        "request": har_request,
+        "response": {
+            "status": response.status.as_u16(),
+            "statusText": response.status.canonical_reason().unwrap_or(""),
+            "httpVersion": format!("{:?}", response.version),
+            "cookies": [],
+            "headers": headers(&response.headers),
+            "content": content,
+            "redirectURL": response
+                .headers
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or(""),
+            "headersSize": -1,
+            "bodySize": response.body.len(),
+        },
+        "cache": {},
+        "timings": {
+            "send": 0,
+            "wait": millis(exchange.wait),
+            "receive": millis(receive),
+        },
+    })
+}
+
+fn headers(headers: &HeaderMap) -> Value {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            json!({
+                "name": name.as_str(),
+                "value": String::from_utf8_lossy(value.as_bytes()),
+            })
+        })
+        .collect()
+}
+
+fn query_string(uri: &Uri) -> Value {
+    uri.query()
+        .map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect()
+        })
+        .unwrap_or_else(|| json!([]))
+}
+
+fn mime_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+}
+
+fn text(body: &[u8]) -> Value {
+    match std::str::from_utf8(body) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "text": base64::Engine::encode(&BASE64, body), "encoding": "base64" }),
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC, such as `2023-05-01T12:30:45.123Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{Request, Response, StatusCode};
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(rfc3339(time), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[test]
+    fn exports_entries() {
+        let request = Request::post("http://example.com/upload?a=1&b=two%20words")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Bytes::from_static(&[0xff, 0x00]))
+            .unwrap();
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Bytes::from_static(b"done"))
+            .unwrap();
+        let har = to_har(&[Exchange::new(request, response)]);
+
+        assert_eq!(har["log"]["version"], "1.2");
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(entry["request"]["method"], "POST");
+        assert_eq!(entry["request"]["httpVersion"], "HTTP/1.1");
+        assert_eq!(
+            entry["request"]["queryString"],
+            json!([{ "name": "a", "value": "1" }, { "name": "b", "value": "two words" }])
+        );
+        assert_eq!(
+            entry["request"]["postData"],
+            json!({
+                "mimeType": "application/octet-stream",
+                "text": "/wA=",
+                "encoding": "base64",
+            })
+        );
+        assert_eq!(entry["response"]["status"], 201);
+        assert_eq!(entry["response"]["statusText"], "Created");
+        assert_eq!(
+            entry["response"]["content"],
+            json!({ "size": 4, "mimeType": "text/plain", "text": "done" })
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Middleware for recording exchanges and replaying them.
+//!
+//! [`Record`] captures every exchange that passes through it, with the method, URI, headers
+//! and bodies of the request and response and how long it took, and hands it to a
+//! [`RecordSink`]. [`Recordings`] is a sink that keeps exchanges in memory and can export them
+//! as [HAR], the format used by browsers and HTTP tooling.
+//!
+//! [`Replay`] is a service that responds with recorded responses, matching requests by method,
+//! URI and optionally some headers. Together they give a VCR-style workflow for integration
+//! tests: record the exchanges with a real backend once, then replay them so tests run without
+//! it.
+//!
+//! Bodies are captured up to a limit, 1 MiB by default, and marked as truncated if they are
+//! longer. Request bodies are captured as far as the inner service read them. An exchange is
+//! recorded when the response body ends, fails or is dropped, so exchanges whose inner service
+//! fails aren't recorded.
+//!
+//! The values of the `Authorization`, `Cookie` and `Set-Cookie` headers, and of headers marked
+//! as sensitive, are recorded as `[REDACTED]` so credentials don't leak into recordings and HAR
+//! files. Use [`RecordLayer::redact_header`] to redact more headers and
+//! [`RecordLayer::keep_sensitive_headers`] to record everything. Matching replayed requests on a
+//! redacted header with [`Replay::match_header`] can't tell its values apart.
+//!
+//! [HAR]: http://www.softwareishard.com/blog/har-12-spec/
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use tower::{BoxError, Service, ServiceBuilder, ServiceExt};
+//! use tower_http::record_replay::{RecordBody, RecordLayer, Recordings, Replay};
+//!
+//! async fn backend(req: Request<RecordBody<Body>>) -> Result<Response<Body>, BoxError> {
+//!     Ok(Response::new(Body::from("Hello, World!")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let recordings = Recordings::new();
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(RecordLayer::new(recordings.clone()))
+//!     .service_fn(backend);
+//!
+//! let request = Request::get("/greeting").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//! // the exchange is recorded once the response body has been read
+//! hyper::body::to_bytes(response.into_body()).await?;
+//!
+//! // export the exchanges as HAR
+//! let har = recordings.to_har();
+//! assert_eq!(har["log"]["entries"][0]["request"]["url"], "/greeting");
+//!
+//! // and serve them without the backend
+//! let replay = Replay::new(recordings.exchanges());
+//!
+//! let request = Request::get("/greeting").body(Body::empty())?;
+//! let response = replay.oneshot(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! assert_eq!(
+//!     hyper::body::to_bytes(response.into_body()).await?,
+//!     Bytes::from("Hello, World!"),
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod future;
+mod har;
+mod record;
+mod replay;
+
+pub use self::{
+    body::RecordBody,
+    future::ResponseFuture,
+    har::to_har,
+    record::{Record, RecordLayer},
+    replay::{NotRecorded, Replay},
+};
+
+use crate::sync::lock_ignore_poison;
+use bytes::Bytes;
+use http::{HeaderMap, Method, Request, Response, StatusCode, Uri, Version};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// A recorded request and response.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Exchange {
+    /// The request.
+    pub request: RecordedRequest,
+    /// The response.
+    pub response: RecordedResponse,
+    /// When the request was received.
+    pub started: SystemTime,
+    /// How long the inner service took to respond, without reading the response body.
+    pub wait: Duration,
+    /// How long the whole exchange took, until the response body ended.
+    pub duration: Duration,
+}
+
+impl Exchange {
+    /// Create a new `Exchange` from a request and response, for example to replay responses
+    /// that weren't recorded.
+    ///
+    /// The exchange is started now and took no time.
+    pub fn new(request: Request<Bytes>, response: Response<Bytes>) -> Self {
+        let (request, request_body) = request.into_parts();
+        let (response, response_body) = response.into_parts();
+        Self {
+            request: RecordedRequest {
+                method: request.method,
+                uri: request.uri,
+                version: request.version,
+                headers: request.headers,
+                body: request_body,
+                body_truncated: false,
+            },
+            response: RecordedResponse {
+                status: response.status,
+                version: response.version,
+                headers: response.headers,
+                body: response_body,
+                body_truncated: false,
+            },
+            started: SystemTime::now(),
+            wait: Duration::ZERO,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// A recorded request.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RecordedRequest {
+    /// The request method.
+    pub method: Method,
+    /// The request URI.
+    pub uri: Uri,
+    /// The HTTP version of the request.
+    pub version: Version,
+    /// The request headers.
+    pub headers: HeaderMap,
+    /// The request body, up to the limit.
+    pub body: Bytes,
+    /// Whether the request body was longer than the limit.
+    pub body_truncated: bool,
+}
+
+/// A recorded response.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RecordedResponse {
+    /// The response status.
+    pub status: StatusCode,
+    /// The HTTP version of the response.
+    pub version: Version,
+    /// The response headers.
+    pub headers: HeaderMap,
+    /// The response body, up to the limit.
+    pub body: Bytes,
+    /// Whether the response body was longer than the limit.
+    pub body_truncated: bool,
+}
+
+/// Trait for storing recorded exchanges.
+///
+/// This is implemented for [`Recordings`] and for closures with the signature
+/// `Fn(Exchange)`, for example to write exchanges to a file or send them over a channel.
+pub trait RecordSink: Send + Sync + 'static {
+    /// Store a recorded exchange.
+    fn record(&self, exchange: Exchange);
+}
+
+impl<F> RecordSink for F
+where
+    F: Fn(Exchange) + Send + Sync + 'static,
+{
+    fn record(&self, exchange: Exchange) {
+        self(exchange)
+    }
+}
+
+/// A [`RecordSink`] that keeps exchanges in memory.
+///
+/// Clones share the same exchanges. See the [module docs](crate::record_replay) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct Recordings {
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+impl Recordings {
+    /// Create a new empty `Recordings`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the recorded exchanges, in the order they completed.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        lock_ignore_poison(&self.exchanges).clone()
+    }
+
+    /// Returns the number of recorded exchanges.
+    pub fn len(&self) -> usize {
+        lock_ignore_poison(&self.exchanges).len()
+    }
+
+    /// Returns `true` if no exchanges have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all recorded exchanges.
+    pub fn clear(&self) {
+        lock_ignore_poison(&self.exchanges).clear();
+    }
+
+    /// Export the recorded exchanges as a [HAR] log.
+    ///
+    /// See [`to_har`] for more details.
+    ///
+    /// [HAR]: http://www.softwareishard.com/blog/har-12-spec/
+    pub fn to_har(&self) -> serde_json::Value {
+        to_har(&lock_ignore_poison(&self.exchanges))
+    }
+}
+
+impl RecordSink for Recordings {
+    fn record(&self, exchange: Exchange) {
+        lock_ignore_poison(&self.exchanges).push(exchange);
+    }
+}
@@ -0,0 +1,420 @@
+use super::{
+    body::{Capture, Pending},
+    RecordBody, RecordSink, RecordedRequest, ResponseFuture,
+};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderName, HeaderValue, Request, Response};
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The headers whose values aren't recorded.
+#[derive(Debug, Clone)]
+pub(super) struct Redact {
+    headers: Vec<HeaderName>,
+    // whether to redact values marked as sensitive, see `HeaderValue::set_sensitive`
+    sensitive: bool,
+}
+
+impl Default for Redact {
+    fn default() -> Self {
+        Self {
+            headers: vec![header::AUTHORIZATION, header::COOKIE, header::SET_COOKIE],
+            sensitive: true,
+        }
+    }
+}
+
+impl Redact {
+    /// Copy `headers`, replacing the redacted values with `[REDACTED]`.
+    pub(super) fn headers(&self, headers: &HeaderMap) -> HeaderMap {
+        let mut redacted = headers.clone();
+        for value in redacted.values_mut() {
+            if self.sensitive && value.is_sensitive() {
+                *value = redacted_value();
+            }
+        }
+        for name in &self.headers {
+            if let header::Entry::Occupied(mut entry) = redacted.entry(name) {
+                for value in entry.iter_mut() {
+                    *value = redacted_value();
+                }
+            }
+        }
+        redacted
+    }
+}
+
+fn redacted_value() -> HeaderValue {
+    let mut value = HeaderValue::from_static("[REDACTED]");
+    value.set_sensitive(true);
+    value
+}
+
+/// Layer that applies [`Record`] which records exchanges into a [`RecordSink`].
+///
+/// See the [module docs](crate::record_replay) for an example.
+#[derive(Clone)]
+pub struct RecordLayer {
+    sink: Arc<dyn RecordSink>,
+    max_body_size: usize,
+    redact: Arc<Redact>,
+}
+
+impl RecordLayer {
+    /// Create a new `RecordLayer` recording exchanges into `sink`.
+    pub fn new<T>(sink: T) -> Self
+    where
+        T: RecordSink,
+    {
+        Self {
+            sink: Arc::new(sink),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            redact: Arc::new(Redact::default()),
+        }
+    }
+
+    /// Set the number of bytes of request and response bodies to record.
+    ///
+    /// Longer bodies are still passed through in full but only recorded up to the limit, and
+    /// marked as truncated. Defaults to 1 MiB.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Don't record the values of the given header.
+    ///
+    /// The values of the `Authorization`, `Cookie` and `Set-Cookie` headers, and values marked
+    /// as [sensitive], are replaced with `[REDACTED]` by default, so credentials don't end up
+    /// in recordings and HAR files.
+    ///
+    /// [sensitive]: http::HeaderValue::set_sensitive
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        Arc::make_mut(&mut self.redact).headers.push(name);
+        self
+    }
+
+    /// Record the values of all headers, including credentials.
+    ///
+    /// Only headers added with [`redact_header`](Self::redact_header) afterwards are redacted.
+    pub fn keep_sensitive_headers(mut self) -> Self {
+        self.redact = Arc::new(Redact {
+            headers: Vec::new(),
+            sensitive: false,
+        });
+        self
+    }
+}
+
+impl fmt::Debug for RecordLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordLayer")
+            .field("max_body_size", &self.max_body_size)
+            .field("redact", &self.redact)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for RecordLayer {
+    type Service = Record<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Record {
+            inner,
+            sink: self.sink.clone(),
+            max_body_size: self.max_body_size,
+            redact: self.redact.clone(),
+        }
+    }
+}
+
+/// Middleware that records exchanges into a [`RecordSink`].
+///
+/// See the [module docs](crate::record_replay) for an example.
+#[derive(Clone)]
+pub struct Record<S> {
+    inner: S,
+    sink: Arc<dyn RecordSink>,
+    max_body_size: usize,
+    redact: Arc<Redact>,
+}
+
+impl<S> Record<S> {
+    /// Create a new `Record` recording exchanges into `sink`.
+    pub fn new<T>(inner: S, sink: T) -> Self
+    where
+        T: RecordSink,
+    {
+        RecordLayer::new(sink).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Record` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer<T>(sink: T) -> RecordLayer
+    where
+        T: RecordSink,
+    {
+        RecordLayer::new(sink)
+    }
+
+    /// Set the number of bytes of request and response bodies to record.
+    ///
+    /// See [`RecordLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Don't record the values of the given header.
+    ///
+    /// See [`RecordLayer::redact_header`] for more details.
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        Arc::make_mut(&mut self.redact).headers.push(name);
+        self
+    }
+
+    /// Record the values of all headers, including credentials.
+    ///
+    /// See [`RecordLayer::keep_sensitive_headers`] for more details.
+    pub fn keep_sensitive_headers(mut self) -> Self {
+        self.redact = Arc::new(Redact {
+            headers: Vec::new(),
+            sensitive: false,
+        });
+        self
+    }
+}
+
+impl<S> fmt::Debug for Record<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Record")
+            .field("inner", &self.inner)
+            .field("max_body_size", &self.max_body_size)
+            .field("redact", &self.redact)
+            .finish()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Record<S>
+where
+    S: Service<Request<RecordBody<ReqBody>>, Response = Response<ResBody>>,
+{
+    type Response = Response<RecordBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let request_body = Capture::default();
+        let pending = Pending {
+            sink: self.sink.clone(),
+            request: RecordedRequest {
+                method: req.method().clone(),
+                uri: req.uri().clone(),
+                version: req.version(),
+                headers: self.redact.headers(req.headers()),
+                body: Bytes::new(),
+                body_truncated: false,
+            },
+            request_body: request_body.clone(),
+            response: None,
+            started: SystemTime::now(),
+            start: Instant::now(),
+            wait: Duration::ZERO,
+        };
+
+        let limit = self.max_body_size;
+        let req = req.map(|body| RecordBody::request(body, request_body, limit));
+        ResponseFuture::new(self.inner.call(req), pending, limit, self.redact.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_replay::Recordings;
+    use http::{header, StatusCode};
+    use hyper::Body;
+    use tower::{BoxError, ServiceBuilder, ServiceExt};
+
+    async fn echo(req: Request<RecordBody<Body>>) -> Result<Response<Body>, BoxError> {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok(Response::builder()
+            .status(StatusCode::CREATED)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from(body))
+            .unwrap())
+    }
+
+    #[tokio::test]
+    async fn records_exchange_when_body_ends() {
+        let recordings = Recordings::new();
+        let svc = ServiceBuilder::new()
+            .layer(RecordLayer::new(recordings.clone()))
+            .service_fn(echo);
+
+        let req = Request::post("/echo?x=1")
+            .header("x-test", "1")
+            .body(Body::from("hello"))
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert!(recordings.is_empty());
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello");
+
+        let exchanges = recordings.exchanges();
+        assert_eq!(exchanges.len(), 1);
+        let exchange = &exchanges[0];
+        assert_eq!(exchange.request.method, http::Method::POST);
+        assert_eq!(exchange.request.uri, "/echo?x=1");
+        assert_eq!(exchange.request.headers["x-test"], "1");
+        assert_eq!(exchange.request.body, "hello");
+        assert_eq!(exchange.response.status, StatusCode::CREATED);
+        assert_eq!(
+            exchange.response.headers[header::CONTENT_TYPE],
+            "text/plain"
+        );
+        assert_eq!(exchange.response.body, "hello");
+        assert!(exchange.duration >= exchange.wait);
+    }
+
+    #[tokio::test]
+    async fn redacts_credentials() {
+        async fn login(_: Request<RecordBody<Body>>) -> Result<Response<Body>, BoxError> {
+            let mut token = HeaderValue::from_static("secret");
+            token.set_sensitive(true);
+            Ok(Response::builder()
+                .header(header::SET_COOKIE, "session=abc")
+                .header("x-token", token)
+                .body(Body::empty())
+                .unwrap())
+        }
+
+        let recordings = Recordings::new();
+        let svc = ServiceBuilder::new()
+            .layer(
+                RecordLayer::new(recordings.clone())
+                    .redact_header(HeaderName::from_static("x-api-key")),
+            )
+            .service_fn(login);
+        let req = Request::post("/login")
+            .header(header::AUTHORIZATION, "Basic Zm9vOmJhcg==")
+            .header(header::COOKIE, "a=1")
+            .header(header::COOKIE, "b=2")
+            .header("x-api-key", "key")
+            .header(header::ACCEPT, "*/*")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        drop(res);
+
+        let exchange = &recordings.exchanges()[0];
+        let request = &exchange.request.headers;
+        assert_eq!(request[header::AUTHORIZATION], "[REDACTED]");
+        assert!(request
+            .get_all(header::COOKIE)
+            .iter()
+            .all(|v| v == "[REDACTED]"));
+        assert_eq!(request.get_all(header::COOKIE).iter().count(), 2);
+        assert_eq!(request["x-api-key"], "[REDACTED]");
+        assert_eq!(request[header::ACCEPT], "*/*");
+        let response = &exchange.response.headers;
+        assert_eq!(response[header::SET_COOKIE], "[REDACTED]");
+        assert_eq!(response["x-token"], "[REDACTED]");
+
+        let har = recordings.to_har().to_string();
+        assert!(!har.contains("Zm9vOmJhcg=="));
+        assert!(!har.contains("session=abc"));
+        assert!(!har.contains("secret"));
+
+        // opting out
+        let recordings = Recordings::new();
+        let svc = ServiceBuilder::new()
+            .layer(RecordLayer::new(recordings.clone()).keep_sensitive_headers())
+            .service_fn(login);
+        let req = Request::post("/login")
+            .header(header::AUTHORIZATION, "Basic Zm9vOmJhcg==")
+            .body(Body::empty())
+            .unwrap();
+        drop(svc.oneshot(req).await.unwrap());
+        let exchange = &recordings.exchanges()[0];
+        assert_eq!(
+            exchange.request.headers[header::AUTHORIZATION],
+            "Basic Zm9vOmJhcg=="
+        );
+        assert_eq!(exchange.response.headers["x-token"], "secret");
+    }
+
+    #[tokio::test]
+    async fn truncates_long_bodies() {
+        let recordings = Recordings::new();
+        let svc = ServiceBuilder::new()
+            .layer(RecordLayer::new(recordings.clone()).max_body_size(4))
+            .service_fn(echo);
+
+        let req = Request::post("/").body(Body::from("hello world")).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello world");
+
+        let exchange = &recordings.exchanges()[0];
+        assert_eq!(exchange.request.body, "hell");
+        assert!(exchange.request.body_truncated);
+        assert_eq!(exchange.response.body, "hell");
+        assert!(exchange.response.body_truncated);
+    }
+
+    #[tokio::test]
+    async fn records_when_body_is_dropped() {
+        let recordings = Recordings::new();
+        let svc = ServiceBuilder::new()
+            .layer(RecordLayer::new(recordings.clone()))
+            .service_fn(echo);
+
+        let req = Request::post("/").body(Body::from("hello")).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        drop(res);
+
+        let exchange = &recordings.exchanges()[0];
+        assert_eq!(exchange.request.body, "hello");
+        assert!(exchange.response.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn records_into_closures() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        let svc = ServiceBuilder::new()
+            .layer(RecordLayer::new(move |exchange| {
+                tx.lock().unwrap().send(exchange).unwrap();
+            }))
+            .service_fn(echo);
+
+        let res = svc
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+        assert_eq!(rx.try_recv().unwrap().request.uri, "/");
+    }
+}
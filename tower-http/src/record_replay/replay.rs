@@ -0,0 +1,234 @@
+use super::Exchange;
+use crate::sync::lock_ignore_poison;
+use bytes::Bytes;
+use http::{header, header::HeaderName, Method, Request, Response, Uri};
+use http_body::Full;
+use std::{
+    fmt,
+    future::{ready, Ready},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+/// Service that responds with recorded responses.
+///
+/// Requests are matched to exchanges by method and URI, and by the values of the headers added
+/// with [`Replay::match_header`]. If several exchanges match a request, they are replayed in
+/// order and the last one is repeated once all have been replayed. Clones share which
+/// exchanges have been replayed.
+///
+/// Responses whose body was truncated when recording are replayed with the truncated body and
+/// without a `Content-Length` header. Requests that don't match any exchange fail with
+/// [`NotRecorded`].
+///
+/// See the [module docs](crate::record_replay) for an example.
+#[derive(Clone)]
+pub struct Replay {
+    exchanges: Arc<[Exchange]>,
+    replayed: Arc<Mutex<Vec<bool>>>,
+    match_headers: Vec<HeaderName>,
+}
+
+impl Replay {
+    /// Create a new `Replay` responding with the responses of `exchanges`.
+    pub fn new<I>(exchanges: I) -> Self
+    where
+        I: IntoIterator<Item = Exchange>,
+    {
+        let exchanges: Arc<[Exchange]> = exchanges.into_iter().collect();
+        Self {
+            replayed: Arc::new(Mutex::new(vec![false; exchanges.len()])),
+            exchanges,
+            match_headers: Vec::new(),
+        }
+    }
+
+    /// Also match requests to exchanges by the values of the `name` header.
+    ///
+    /// A request without the header only matches exchanges whose request didn't have it
+    /// either.
+    pub fn match_header(mut self, name: HeaderName) -> Self {
+        self.match_headers.push(name);
+        self
+    }
+
+    fn find<B>(&self, req: &Request<B>) -> Option<&Exchange> {
+        let matches = |exchange: &Exchange| {
+            exchange.request.method == req.method()
+                && exchange.request.uri == *req.uri()
+                && self.match_headers.iter().all(|name| {
+                    exchange
+                        .request
+                        .headers
+                        .get_all(name)
+                        .iter()
+                        .eq(req.headers().get_all(name).iter())
+                })
+        };
+
+        let mut replayed = lock_ignore_poison(&self.replayed);
+        let mut last = None;
+        for (index, exchange) in self.exchanges.iter().enumerate() {
+            if !matches(exchange) {
+                continue;
+            }
+            if !replayed[index] {
+                replayed[index] = true;
+                return Some(exchange);
+            }
+            last = Some(exchange);
+        }
+        last
+    }
+}
+
+impl fmt::Debug for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replay")
+            .field("exchanges", &self.exchanges.len())
+            .field("match_headers", &self.match_headers)
+            .finish()
+    }
+}
+
+impl<B> Service<Request<B>> for Replay {
+    type Response = Response<Full<Bytes>>;
+    type Error = NotRecorded;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let exchange = match self.find(&req) {
+            Some(exchange) => exchange,
+            None => {
+                return ready(Err(NotRecorded {
+                    method: req.method().clone(),
+                    uri: req.uri().clone(),
+                }))
+            }
+        };
+
+        let recorded = &exchange.response;
+        let mut res = Response::new(Full::new(recorded.body.clone()));
+        *res.status_mut() = recorded.status;
+        *res.version_mut() = recorded.version;
+        *res.headers_mut() = recorded.headers.clone();
+        if recorded.body_truncated {
+            res.headers_mut().remove(header::CONTENT_LENGTH);
+        }
+        ready(Ok(res))
+    }
+}
+
+/// Error returned by [`Replay`] for requests that don't match any recorded exchange.
+#[derive(Debug, Clone)]
+pub struct NotRecorded {
+    method: Method,
+    uri: Uri,
+}
+
+impl NotRecorded {
+    /// Returns the method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the URI of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+}
+
+impl fmt::Display for NotRecorded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no recorded exchange for {} {}", self.method, self.uri)
+    }
+}
+
+impl std::error::Error for NotRecorded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use hyper::Body;
+    use tower::ServiceExt;
+
+    fn exchange(uri: &str, tenant: Option<&str>, body: &'static str) -> Exchange {
+        let mut req = Request::get(uri);
+        if let Some(tenant) = tenant {
+            req = req.header("x-tenant", tenant);
+        }
+        Exchange::new(
+            req.body(Bytes::new()).unwrap(),
+            Response::new(Bytes::from_static(body.as_bytes())),
+        )
+    }
+
+    async fn body(replay: &Replay, req: Request<Body>) -> Result<Bytes, NotRecorded> {
+        let res = replay.clone().oneshot(req).await?;
+        Ok(hyper::body::to_bytes(res.into_body()).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn matches_method_and_uri() {
+        let replay = Replay::new(vec![exchange("/a", None, "a"), exchange("/b", None, "b")]);
+
+        let res = body(&replay, Request::get("/b").body(Body::empty()).unwrap()).await;
+        assert_eq!(res.unwrap(), "b");
+
+        let err = body(&replay, Request::post("/b").body(Body::empty()).unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.method(), Method::POST);
+        assert_eq!(err.to_string(), "no recorded exchange for POST /b");
+    }
+
+    #[tokio::test]
+    async fn replays_in_order_then_repeats_last() {
+        let replay = Replay::new(vec![exchange("/", None, "1"), exchange("/", None, "2")]);
+
+        for expected in ["1", "2", "2"] {
+            let res = body(&replay, Request::get("/").body(Body::empty()).unwrap()).await;
+            assert_eq!(res.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn matches_headers() {
+        let replay = Replay::new(vec![
+            exchange("/", Some("a"), "a"),
+            exchange("/", Some("b"), "b"),
+        ])
+        .match_header(HeaderName::from_static("x-tenant"));
+
+        let req = Request::get("/")
+            .header("x-tenant", "b")
+            .body(Body::empty());
+        assert_eq!(body(&replay, req.unwrap()).await.unwrap(), "b");
+
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        assert!(body(&replay, req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn removes_content_length_of_truncated_bodies() {
+        let mut exchange = exchange("/", None, "hel");
+        exchange
+            .response
+            .headers
+            .insert(header::CONTENT_LENGTH, "5".parse().unwrap());
+        exchange.response.body_truncated = true;
+
+        let res = Replay::new(vec![exchange])
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+    }
+}
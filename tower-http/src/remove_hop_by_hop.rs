@@ -0,0 +1,268 @@
+//! Middleware that removes hop-by-hop headers from requests and responses.
+//!
+//! Hop-by-hop headers describe a single connection and must not be forwarded by proxies and
+//! gateways. [`RemoveHopByHopHeaders`] removes them from requests before they are passed to the
+//! inner service, typically a client sending them on to a backend, and from responses before they
+//! are returned. The removed headers are:
+//!
+//! - `Connection`, and every header named in it.
+//! - `Keep-Alive`, `TE`, `Trailer`, `Transfer-Encoding`, and `Upgrade`.
+//! - Headers starting with `Proxy-`, such as `Proxy-Authorization` and `Proxy-Connection`.
+//!
+//! Proxies forwarding gRPC requests need to keep `TE: trailers`, which can be done with
+//! [`RemoveHopByHopHeadersLayer::keep_te_trailers`]. Forwarding protocol upgrades, such as
+//! WebSockets, has to be handled separately, since `Upgrade` and `Connection` are removed.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::remove_hop_by_hop::RemoveHopByHopHeadersLayer;
+//!
+//! async fn forward(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     assert!(!req.headers().contains_key(header::CONNECTION));
+//!     assert!(!req.headers().contains_key("x-session"));
+//!     assert!(!req.headers().contains_key(header::PROXY_AUTHORIZATION));
+//!     assert!(req.headers().contains_key(header::AUTHORIZATION));
+//!     // send the request to the backend...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(RemoveHopByHopHeadersLayer::new())
+//!     .service_fn(forward);
+//!
+//! let request = Request::get("/")
+//!     .header(header::CONNECTION, "keep-alive, x-session")
+//!     .header("x-session", "abc")
+//!     .header(header::PROXY_AUTHORIZATION, "Basic Zm9vOmJhcg==")
+//!     .header(header::AUTHORIZATION, "Bearer token")
+//!     .body(Body::empty())?;
+//! service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::ready;
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Request, Response,
+};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const HOP_BY_HOP_HEADERS: [HeaderName; 6] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Remove the hop-by-hop headers from `headers`.
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap, keep_te_trailers: bool) {
+    let keep_te = keep_te_trailers
+        && headers
+            .get_all(header::TE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case("trailers"));
+
+    let mut remove = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+    remove.extend(
+        headers
+            .keys()
+            .filter(|name| name.as_str().starts_with("proxy-"))
+            .cloned(),
+    );
+    remove.extend(HOP_BY_HOP_HEADERS.iter().cloned());
+
+    for name in remove {
+        headers.remove(name);
+    }
+    if keep_te {
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    }
+}
+
+/// Layer that applies [`RemoveHopByHopHeaders`] which removes hop-by-hop headers from requests
+/// and responses.
+///
+/// See the [module docs](crate::remove_hop_by_hop) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveHopByHopHeadersLayer {
+    keep_te_trailers: bool,
+}
+
+impl RemoveHopByHopHeadersLayer {
+    /// Create a new `RemoveHopByHopHeadersLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `TE: trailers` in requests whose `TE` header contains `trailers`, as required by
+    /// gRPC. Defaults to `false`.
+    pub fn keep_te_trailers(mut self, keep: bool) -> Self {
+        self.keep_te_trailers = keep;
+        self
+    }
+}
+
+impl<S> Layer<S> for RemoveHopByHopHeadersLayer {
+    type Service = RemoveHopByHopHeaders<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RemoveHopByHopHeaders {
+            inner,
+            keep_te_trailers: self.keep_te_trailers,
+        }
+    }
+}
+
+/// Middleware that removes hop-by-hop headers from requests and responses.
+///
+/// See the [module docs](crate::remove_hop_by_hop) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct RemoveHopByHopHeaders<S> {
+    inner: S,
+    keep_te_trailers: bool,
+}
+
+impl<S> RemoveHopByHopHeaders<S> {
+    /// Create a new `RemoveHopByHopHeaders`.
+    pub fn new(inner: S) -> Self {
+        RemoveHopByHopHeadersLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `RemoveHopByHopHeaders` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> RemoveHopByHopHeadersLayer {
+        RemoveHopByHopHeadersLayer::new()
+    }
+
+    /// Keep `TE: trailers` in requests whose `TE` header contains `trailers`.
+    ///
+    /// See [`RemoveHopByHopHeadersLayer::keep_te_trailers`] for more details.
+    pub fn keep_te_trailers(mut self, keep: bool) -> Self {
+        self.keep_te_trailers = keep;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RemoveHopByHopHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        remove_hop_by_hop_headers(req.headers_mut(), self.keep_te_trailers);
+        ResponseFuture {
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`RemoveHopByHopHeaders`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut res = ready!(self.project().inner.poll(cx))?;
+        remove_hop_by_hop_headers(res.headers_mut(), false);
+        Poll::Ready(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let mut res = Response::new(Body::empty());
+        *res.headers_mut() = req.headers().clone();
+        res.headers_mut().insert(
+            header::PROXY_AUTHENTICATE,
+            HeaderValue::from_static("Basic"),
+        );
+        Ok(res)
+    }
+
+    fn request() -> Request<Body> {
+        Request::get("/")
+            .header(header::CONNECTION, "Keep-Alive, X-Foo")
+            .header(header::CONNECTION, "upgrade")
+            .header("keep-alive", "timeout=5")
+            .header("x-foo", "bar")
+            .header(header::TE, "gzip, trailers")
+            .header(header::TRANSFER_ENCODING, "chunked")
+            .header(header::UPGRADE, "websocket")
+            .header("proxy-connection", "keep-alive")
+            .header(header::PROXY_AUTHORIZATION, "Basic Zm9vOmJhcg==")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn removes_hop_by_hop_headers() {
+        let svc = RemoveHopByHopHeadersLayer::new().layer(service_fn(echo));
+
+        let res = svc.oneshot(request()).await.unwrap();
+        let names = res.headers().keys().collect::<Vec<_>>();
+        assert_eq!(names, [header::CONTENT_TYPE]);
+    }
+
+    #[tokio::test]
+    async fn keep_te_trailers() {
+        let svc = RemoveHopByHopHeadersLayer::new()
+            .keep_te_trailers(true)
+            .layer(service_fn(|req: Request<Body>| async move {
+                assert_eq!(req.headers()[header::TE], "trailers");
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }));
+
+        svc.oneshot(request()).await.unwrap();
+    }
+}
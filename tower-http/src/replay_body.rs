@@ -0,0 +1,330 @@
+use crate::sync::lock_ignore_poison;
+use crate::BoxError;
+use bytes::{Buf, Bytes};
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+/// A request body that buffers its data as it is sent so it can be replayed, when following
+/// `307 Temporary Redirect` and `308 Permanent Redirect` responses, when retrying requests or
+/// when mirroring requests.
+///
+/// At most `limit` bytes are buffered. If the body is larger, or it hasn't been sent completely
+/// when the response arrives, it can't be replayed. The
+/// [`ReplayBodies`](crate::follow_redirect::policy::ReplayBodies) policy then stops at the
+/// redirection response, [`Retry`](crate::retry::Retry) returns the response instead of
+/// retrying and [`Mirror`](crate::mirror::Mirror) doesn't mirror the request.
+///
+/// # Example
+///
+/// ```
+/// use http::{Request, Response};
+/// use hyper::Body;
+/// use tower::{Service, ServiceBuilder, ServiceExt};
+/// use tower_http::follow_redirect::{policy::ReplayBodies, FollowRedirectLayer, ReplayBody};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), tower::BoxError> {
+/// # let http_client = tower::service_fn(|req: Request<ReplayBody<Body>>| async move {
+/// #     Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+/// # });
+/// let mut client = ServiceBuilder::new()
+///     .map_request(|req: Request<Body>| req.map(|body| ReplayBody::new(body, 64 * 1024)))
+///     .layer(FollowRedirectLayer::with_policy(ReplayBodies::new()))
+///     .service(http_client);
+///
+/// let request = Request::put("https://example.com/upload").body(Body::from("data"))?;
+/// let response = client.ready().await?.call(request).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReplayBody<B> {
+    kind: Kind<B>,
+}
+
+enum Kind<B> {
+    Source {
+        body: Pin<Box<B>>,
+        shared: Arc<Mutex<Shared>>,
+    },
+    Replay {
+        shared: Arc<Mutex<Shared>>,
+        position: usize,
+        trailers_sent: bool,
+    },
+    Empty,
+}
+
+pub(crate) struct Shared {
+    chunks: Vec<Bytes>,
+    len: usize,
+    limit: usize,
+    capture: Capture,
+    trailers: Option<HeaderMap>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Capture {
+    Reading,
+    Complete,
+    Unavailable,
+}
+
+impl Shared {
+    fn push(&mut self, chunk: &Bytes) {
+        if self.capture != Capture::Reading {
+            return;
+        }
+
+        if self.len + chunk.len() > self.limit {
+            self.discard();
+        } else {
+            self.len += chunk.len();
+            self.chunks.push(chunk.clone());
+        }
+    }
+
+    fn discard(&mut self) {
+        self.capture = Capture::Unavailable;
+        self.chunks = Vec::new();
+        self.trailers = None;
+    }
+
+    pub(crate) fn is_replayable(&self) -> bool {
+        self.capture == Capture::Complete
+    }
+}
+
+impl<B> ReplayBody<B> {
+    /// Create a new `ReplayBody` buffering at most `limit` bytes of `body`.
+    pub fn new(body: B, limit: usize) -> Self {
+        Self {
+            kind: Kind::Source {
+                body: Box::pin(body),
+                shared: Arc::new(Mutex::new(Shared {
+                    chunks: Vec::new(),
+                    len: 0,
+                    limit,
+                    capture: Capture::Reading,
+                    trailers: None,
+                })),
+            },
+        }
+    }
+
+    /// Create a new `ReplayBody` that replays the data of this one.
+    ///
+    /// This always succeeds but the returned body fails with an error if polled before this body
+    /// has been sent completely, or if it was larger than the limit. Use
+    /// [`ReplayBody::is_replayable`] to check.
+    pub fn replay(&self) -> Self {
+        let kind = match &self.kind {
+            Kind::Source { shared, .. } | Kind::Replay { shared, .. } => Kind::Replay {
+                shared: shared.clone(),
+                position: 0,
+                trailers_sent: false,
+            },
+            Kind::Empty => Kind::Empty,
+        };
+        Self { kind }
+    }
+
+    /// Returns `true` if the body has been sent completely and can be replayed.
+    pub fn is_replayable(&self) -> bool {
+        self.shared()
+            .map_or(true, |shared| lock_ignore_poison(&shared).is_replayable())
+    }
+
+    pub(crate) fn shared(&self) -> Option<Arc<Mutex<Shared>>> {
+        match &self.kind {
+            Kind::Source { shared, .. } | Kind::Replay { shared, .. } => Some(shared.clone()),
+            Kind::Empty => None,
+        }
+    }
+}
+
+impl<B> Default for ReplayBody<B> {
+    fn default() -> Self {
+        Self { kind: Kind::Empty }
+    }
+}
+
+impl<B> fmt::Debug for ReplayBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match &self.kind {
+            Kind::Source { .. } => "Source",
+            Kind::Replay { .. } => "Replay",
+            Kind::Empty => "Empty",
+        };
+        f.debug_struct("ReplayBody").field("kind", &kind).finish()
+    }
+}
+
+#[derive(Debug)]
+struct Unreplayable;
+
+impl fmt::Display for Unreplayable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request body cannot be replayed")
+    }
+}
+
+impl std::error::Error for Unreplayable {}
+
+impl<B> Body for ReplayBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match &mut self.get_mut().kind {
+            Kind::Source { body, shared } => match body.as_mut().poll_data(cx) {
+                Poll::Ready(Some(Ok(mut data))) => {
+                    let chunk = data.copy_to_bytes(data.remaining());
+                    lock_ignore_poison(shared).push(&chunk);
+                    Poll::Ready(Some(Ok(chunk)))
+                }
+                Poll::Ready(Some(Err(err))) => {
+                    lock_ignore_poison(shared).discard();
+                    Poll::Ready(Some(Err(err.into())))
+                }
+                Poll::Ready(None) => {
+                    let mut shared = lock_ignore_poison(shared);
+                    if shared.capture == Capture::Reading {
+                        shared.capture = Capture::Complete;
+                    }
+                    Poll::Ready(None)
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            Kind::Replay {
+                shared, position, ..
+            } => {
+                let shared = lock_ignore_poison(shared);
+                if !shared.is_replayable() {
+                    return Poll::Ready(Some(Err(Unreplayable.into())));
+                }
+                let chunk = shared.chunks.get(*position).cloned();
+                *position += 1;
+                Poll::Ready(chunk.map(Ok))
+            }
+            Kind::Empty => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match &mut self.get_mut().kind {
+            Kind::Source { body, shared } => match body.as_mut().poll_trailers(cx) {
+                Poll::Ready(Ok(trailers)) => {
+                    let mut shared = lock_ignore_poison(shared);
+                    if shared.is_replayable() {
+                        shared.trailers = trailers.clone();
+                    }
+                    Poll::Ready(Ok(trailers))
+                }
+                Poll::Ready(Err(err)) => {
+                    lock_ignore_poison(shared).discard();
+                    Poll::Ready(Err(err.into()))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            Kind::Replay {
+                shared,
+                trailers_sent,
+                ..
+            } => {
+                if *trailers_sent {
+                    return Poll::Ready(Ok(None));
+                }
+                *trailers_sent = true;
+                Poll::Ready(Ok(lock_ignore_poison(shared).trailers.clone()))
+            }
+            Kind::Empty => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            Kind::Source { body, .. } => body.is_end_stream(),
+            Kind::Replay { .. } => false,
+            Kind::Empty => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            Kind::Source { body, .. } => body.size_hint(),
+            Kind::Replay { shared, .. } => {
+                let shared = lock_ignore_poison(shared);
+                if shared.is_replayable() {
+                    SizeHint::with_exact(shared.len as u64)
+                } else {
+                    SizeHint::default()
+                }
+            }
+            Kind::Empty => SizeHint::with_exact(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_data_and_trailers() {
+        let (mut tx, body) = hyper::Body::channel();
+        let mut body = ReplayBody::new(body, 16);
+        let replay = body.replay();
+
+        tokio::spawn(async move {
+            tx.send_data("hello ".into()).await.unwrap();
+            tx.send_data("world".into()).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-trailer", "1".parse().unwrap());
+            tx.send_trailers(trailers).await.unwrap();
+        });
+
+        assert!(!replay.is_replayable());
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(data, b"hello world");
+        assert!(body.trailers().await.unwrap().is_some());
+        assert!(replay.is_replayable());
+
+        assert_eq!(
+            hyper::body::to_bytes(replay.replay()).await.unwrap(),
+            "hello world"
+        );
+        let mut replay = replay;
+        while replay.data().await.is_some() {}
+        assert_eq!(replay.trailers().await.unwrap().unwrap()["x-trailer"], "1");
+    }
+
+    #[tokio::test]
+    async fn bodies_over_limit_are_not_replayable() {
+        let body = ReplayBody::new(hyper::Body::from("too large"), 4);
+        let replay = body.replay();
+
+        hyper::body::to_bytes(body).await.unwrap();
+
+        assert!(!replay.is_replayable());
+        assert!(hyper::body::to_bytes(replay).await.is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use super::{
+    policy::{self, Config},
+    ReplayBody, RetryError,
+};
+use futures_core::ready;
+use http::{HeaderMap, Method, Request, Response, Uri, Version};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::time::{sleep, Sleep};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future for [`Retry`].
+    ///
+    /// [`Retry`]: super::Retry
+    pub struct ResponseFuture<S, B, P>
+    where
+        S: Service<Request<ReplayBody<B>>>,
+    {
+        #[pin]
+        state: State<S::Future>,
+        service: S,
+        template: Option<Template<B>>,
+        policy: P,
+        config: Config,
+        attempts: u32,
+    }
+}
+
+impl<S, B, P> ResponseFuture<S, B, P>
+where
+    S: Service<Request<ReplayBody<B>>>,
+{
+    pub(super) fn new(
+        state: State<S::Future>,
+        service: S,
+        template: Option<Template<B>>,
+        policy: P,
+        config: Config,
+    ) -> Self {
+        Self {
+            state,
+            service,
+            template,
+            policy,
+            config,
+            attempts: 1,
+        }
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    pub(super) enum State<F> {
+        Called {
+            #[pin]
+            future: F,
+        },
+        Sleeping {
+            #[pin]
+            sleep: Sleep,
+        },
+        Ready,
+    }
+}
+
+/// The parts of the original request retries are built from.
+///
+/// Extensions can't be cloned so they are only sent with the first attempt.
+pub(super) struct Template<B> {
+    pub(super) method: Method,
+    pub(super) uri: Uri,
+    pub(super) version: Version,
+    pub(super) headers: HeaderMap,
+    pub(super) body: ReplayBody<B>,
+}
+
+impl<B> Template<B> {
+    fn request(&self) -> Request<ReplayBody<B>> {
+        let mut req = Request::new(self.body.replay());
+        *req.method_mut() = self.method.clone();
+        *req.uri_mut() = self.uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers.clone();
+        req
+    }
+}
+
+impl<S, B, P, ResBody> Future for ResponseFuture<S, B, P>
+where
+    S: Service<Request<ReplayBody<B>>, Response = Response<ResBody>>,
+    P: RetryError<S::Error>,
+{
+    type Output = Result<Response<ResBody>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let next = match this.state.as_mut().project() {
+                StateProj::Called { future } => {
+                    let result = ready!(future.poll(cx));
+                    let template = match this.template {
+                        Some(template) if *this.attempts < this.config.max_attempts => template,
+                        _ => return Poll::Ready(result),
+                    };
+
+                    let delay = match &result {
+                        Ok(res) if policy::is_retryable(res.status()) => {
+                            match policy::retry_after(res.headers(), SystemTime::now()) {
+                                Some(delay) if delay > this.config.max_delay => None,
+                                Some(delay) => Some(delay),
+                                None => Some(this.config.backoff(*this.attempts)),
+                            }
+                        }
+                        Ok(_) => None,
+                        Err(err) if this.policy.should_retry(err) => {
+                            Some(this.config.backoff(*this.attempts))
+                        }
+                        Err(_) => None,
+                    };
+                    let delay = match delay {
+                        // the body may not have been sent completely or was too large
+                        Some(delay) if template.body.is_replayable() => delay,
+                        _ => return Poll::Ready(result),
+                    };
+
+                    if delay == Duration::ZERO {
+                        State::Ready
+                    } else {
+                        State::Sleeping {
+                            sleep: sleep(delay),
+                        }
+                    }
+                }
+                StateProj::Sleeping { sleep } => {
+                    ready!(sleep.poll(cx));
+                    State::Ready
+                }
+                StateProj::Ready => {
+                    ready!(this.service.poll_ready(cx))?;
+                    let template = this
+                        .template
+                        .as_ref()
+                        .expect("only requests with a template are retried");
+                    *this.attempts += 1;
+                    State::Called {
+                        future: this.service.call(template.request()),
+                    }
+                }
+            };
+            this.state.set(next);
+        }
+    }
+}
+
+impl<S, B, P> fmt::Debug for ResponseFuture<S, B, P>
+where
+    S: Service<Request<ReplayBody<B>>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("attempts", &self.attempts)
+            .finish()
+    }
+}
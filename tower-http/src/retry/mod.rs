@@ -0,0 +1,201 @@
+//! Middleware for HTTP clients that retries failed requests.
+//!
+//! [`Retry`] retries requests that failed with an error, such as a refused connection, or with a
+//! `502 Bad Gateway`, `503 Service Unavailable` or `504 Gateway Timeout` response. Only requests
+//! with idempotent methods (`GET`, `HEAD`, `OPTIONS`, `TRACE`, `PUT` and `DELETE`) are retried,
+//! since sending other requests twice may have unintended effects.
+//!
+//! Retries are delayed with exponential backoff with jitter, or by the duration asked for with
+//! a `Retry-After` header. The number of attempts and the delays can be capped.
+//!
+//! Request bodies are wrapped in a [`ReplayBody`] that buffers them as they are sent so they can
+//! be sent again. Requests whose body is larger than [`RetryLayer::max_body_size`], or that
+//! failed before their body was sent completely, aren't retried. Request extensions are only sent
+//! with the first attempt, since they can't be cloned.
+//!
+//! Compared to [`tower::retry`], which works with any request type, this middleware knows which
+//! HTTP requests and responses can be retried and how to replay request bodies.
+//!
+//! [`tower::retry`]: https://docs.rs/tower/latest/tower/retry/index.html
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::{
+//!     sync::atomic::{AtomicUsize, Ordering},
+//!     time::Duration,
+//! };
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::retry::{ReplayBody, RetryLayer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower::BoxError> {
+//! # static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+//! # let http_client = tower::service_fn(|req: Request<ReplayBody<Body>>| async move {
+//! #     hyper::body::to_bytes(req.into_body()).await?;
+//! #     let status = if ATTEMPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+//! #         StatusCode::SERVICE_UNAVAILABLE
+//! #     } else {
+//! #         StatusCode::OK
+//! #     };
+//! #     let res = Response::builder().status(status).body(Body::empty()).unwrap();
+//! #     Ok::<_, tower::BoxError>(res)
+//! # });
+//! let mut client = ServiceBuilder::new()
+//!     .layer(
+//!         RetryLayer::new()
+//!             .max_attempts(4)
+//!             .base_delay(Duration::from_millis(10))
+//!             .max_delay(Duration::from_secs(5)),
+//!     )
+//!     .service(http_client);
+//!
+//! // The first attempt fails with `503 Service Unavailable`.
+//! let request = Request::put("https://example.com/items/1").body(Body::from("item"))?;
+//! let response = client.ready().await?.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+mod future;
+mod policy;
+mod service;
+
+pub use crate::replay_body::ReplayBody;
+
+pub use self::{
+    future::ResponseFuture,
+    policy::{AnyError, RetryError},
+    service::{Retry, RetryLayer},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{header, Method, Request, Response, StatusCode};
+    use hyper::Body;
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+    use tower::{service_fn, util::BoxCloneService, BoxError, Layer, ServiceExt};
+
+    type Attempts = Arc<Mutex<Vec<Bytes>>>;
+
+    /// A client that responds with the given statuses in turn and records the request bodies.
+    fn client(
+        statuses: Vec<Result<(StatusCode, Option<&'static str>), &'static str>>,
+    ) -> (
+        BoxCloneService<Request<ReplayBody<Body>>, Response<Body>, BoxError>,
+        Attempts,
+    ) {
+        let attempts = Attempts::default();
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+        let recorded = attempts.clone();
+        let svc = service_fn(move |req: Request<ReplayBody<Body>>| {
+            let statuses = statuses.clone();
+            let recorded = recorded.clone();
+            async move {
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                recorded.lock().unwrap().push(body);
+                let (status, retry_after) = statuses.lock().unwrap().next().unwrap()?;
+                let mut res = Response::builder().status(status);
+                if let Some(retry_after) = retry_after {
+                    res = res.header(header::RETRY_AFTER, retry_after);
+                }
+                Ok::<_, BoxError>(res.body(Body::empty()).unwrap())
+            }
+        });
+        (BoxCloneService::new(svc), attempts)
+    }
+
+    fn layer() -> RetryLayer {
+        RetryLayer::new()
+            .base_delay(Duration::from_millis(1))
+            .max_delay(Duration::from_millis(100))
+    }
+
+    #[tokio::test]
+    async fn retries_errors_and_retryable_statuses() {
+        let (svc, attempts) = client(vec![
+            Err("connection refused"),
+            Ok((StatusCode::BAD_GATEWAY, None)),
+            Ok((StatusCode::OK, None)),
+        ]);
+        let svc = layer().layer(svc);
+
+        let req = Request::put("/").body(Body::from("hello")).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*attempts.lock().unwrap(), vec!["hello", "hello", "hello"]);
+    }
+
+    #[tokio::test]
+    async fn caps_attempts() {
+        let (svc, attempts) = client(vec![
+            Ok((StatusCode::SERVICE_UNAVAILABLE, None)),
+            Ok((StatusCode::GATEWAY_TIMEOUT, None)),
+            Ok((StatusCode::OK, None)),
+        ]);
+        let svc = layer().max_attempts(2).layer(svc);
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(attempts.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn honors_retry_after() {
+        let (svc, attempts) = client(vec![
+            Ok((StatusCode::SERVICE_UNAVAILABLE, Some("0"))),
+            Ok((StatusCode::SERVICE_UNAVAILABLE, Some("60"))),
+        ]);
+        let svc = layer().layer(svc);
+
+        // a delay longer than the maximum isn't waited for
+        let start = Instant::now();
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+        assert_eq!(attempts.lock().unwrap().len(), 2);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn only_retries_what_can_be_replayed() {
+        // non-idempotent methods
+        let (svc, attempts) = client(vec![Ok((StatusCode::SERVICE_UNAVAILABLE, None))]);
+        let req = Request::post("/").body(Body::from("hello")).unwrap();
+        let res = layer().layer(svc).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(attempts.lock().unwrap().len(), 1);
+
+        // bodies over the limit
+        let (svc, attempts) = client(vec![Ok((StatusCode::SERVICE_UNAVAILABLE, None))]);
+        let req = Request::put("/").body(Body::from("hello")).unwrap();
+        let res = layer()
+            .max_body_size(4)
+            .layer(svc)
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(attempts.lock().unwrap().len(), 1);
+
+        // errors rejected by the policy
+        let (svc, attempts) = client(vec![Err("bad request")]);
+        let svc = layer()
+            .retry_error(|err: &BoxError| err.to_string() != "bad request")
+            .layer(svc);
+        let req = Request::builder()
+            .method(Method::DELETE)
+            .body(Body::empty())
+            .unwrap();
+        assert!(svc.oneshot(req).await.is_err());
+        assert_eq!(attempts.lock().unwrap().len(), 1);
+    }
+}
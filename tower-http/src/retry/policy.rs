@@ -0,0 +1,171 @@
+use http::{header, HeaderMap, Method, StatusCode};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime},
+};
+
+/// Trait for deciding whether a request that failed with an error should be retried.
+///
+/// Errors are only retried for requests that can be retried at all, see the
+/// [module docs](crate::retry).
+///
+/// This trait is implemented for closures with the signature `Fn(&E) -> bool`:
+///
+/// ```
+/// use tower_http::retry::RetryLayer;
+///
+/// // only retry errors that occurred while connecting, before the request was sent
+/// let layer = RetryLayer::new().retry_error(|err: &hyper::Error| err.is_connect());
+/// ```
+pub trait RetryError<E> {
+    /// Returns `true` if the request should be retried.
+    fn should_retry(&self, error: &E) -> bool;
+}
+
+impl<E, F> RetryError<E> for F
+where
+    F: Fn(&E) -> bool,
+{
+    fn should_retry(&self, error: &E) -> bool {
+        self(error)
+    }
+}
+
+/// A [`RetryError`] that retries all errors.
+///
+/// Errors of HTTP clients usually mean that the connection failed, so this is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnyError;
+
+impl<E> RetryError<E> for AnyError {
+    fn should_retry(&self, _error: &E) -> bool {
+        true
+    }
+}
+
+/// The settings of the middleware shared by the layer, the service and the futures.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Config {
+    pub(super) max_attempts: u32,
+    pub(super) base_delay: Duration,
+    pub(super) max_delay: Duration,
+    pub(super) max_body: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            max_body: 64 * 1024,
+        }
+    }
+}
+
+impl Config {
+    /// The delay before retrying after `attempt` attempts, using exponential backoff with
+    /// jitter: a random duration between half and all of the backoff.
+    pub(super) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let backoff = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        backoff.mul_f64(0.5 + random() / 2.0)
+    }
+}
+
+/// Requests with these methods can be sent again without changing their effect (RFC 9110
+/// section 9.2.2).
+pub(super) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Responses with these statuses usually mean that a gateway couldn't reach the service or that
+/// the service is temporarily overloaded.
+pub(super) fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Parse a `Retry-After` header containing either delay seconds or an HTTP date.
+pub(super) fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// A random number in `[0, 1)`.
+///
+/// The jitter only needs to spread out retries, so the randomly seeded hasher of the standard
+/// library is good enough.
+fn random() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        let config = Config {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            ..Config::default()
+        };
+
+        for (attempt, max) in [
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (40, 1000),
+        ] {
+            let max = Duration::from_millis(max);
+            let backoff = config.backoff(attempt);
+            assert!(backoff >= max / 2 && backoff <= max, "{:?}", backoff);
+        }
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers, now), None);
+
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            header::RETRY_AFTER,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:47 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(10)));
+
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers, now), None);
+    }
+}
@@ -0,0 +1,224 @@
+use super::{
+    future::{State, Template},
+    policy::{self, Config},
+    AnyError, ReplayBody, ResponseFuture, RetryError,
+};
+use crate::ready::take_ready;
+use http::{Request, Response};
+use http_body::Body;
+use std::{
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`Retry`] middleware which retries failed requests.
+///
+/// See the [module docs](crate::retry) for an example.
+#[derive(Clone, Debug)]
+pub struct RetryLayer<P = AnyError> {
+    config: Config,
+    policy: P,
+}
+
+impl RetryLayer {
+    /// Create a new `RetryLayer` with the default settings.
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            policy: AnyError,
+        }
+    }
+}
+
+impl Default for RetryLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> RetryLayer<P> {
+    /// Set the maximum number of attempts, including the first one.
+    ///
+    /// Defaults to `3`.
+    pub fn max_attempts(mut self, max: u32) -> Self {
+        self.config.max_attempts = max;
+        self
+    }
+
+    /// Set the delay before the first retry, which doubles with every further retry.
+    ///
+    /// The actual delay is a random duration between half and all of the delay, to keep clients
+    /// from retrying in lockstep. Defaults to 100 milliseconds.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.config.base_delay = delay;
+        self
+    }
+
+    /// Set the maximum delay between attempts.
+    ///
+    /// Responses asking to retry after a longer delay with `Retry-After` are returned instead of
+    /// being retried. Defaults to 10 seconds.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.config.max_delay = delay;
+        self
+    }
+
+    /// Set the maximum size of request bodies that are buffered to be replayed.
+    ///
+    /// Requests with larger bodies aren't retried. Defaults to 64 KiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body = limit;
+        self
+    }
+
+    /// Set the [`RetryError`] deciding which errors are retried.
+    ///
+    /// Defaults to [`AnyError`].
+    pub fn retry_error<NewP>(self, policy: NewP) -> RetryLayer<NewP> {
+        RetryLayer {
+            config: self.config,
+            policy,
+        }
+    }
+}
+
+impl<S, P> Layer<S> for RetryLayer<P>
+where
+    P: Clone,
+{
+    type Service = Retry<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry {
+            inner,
+            config: self.config,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// Middleware that retries idempotent requests failing with connection errors or with
+/// `502 Bad Gateway`, `503 Service Unavailable` and `504 Gateway Timeout` responses.
+///
+/// See the [module docs](crate::retry) for an example.
+#[derive(Clone, Debug)]
+pub struct Retry<S, P = AnyError> {
+    inner: S,
+    config: Config,
+    policy: P,
+}
+
+impl<S> Retry<S> {
+    /// Create a new `Retry` with the default settings.
+    pub fn new(inner: S) -> Self {
+        RetryLayer::new().layer(inner)
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `Retry` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> RetryLayer {
+        RetryLayer::new()
+    }
+}
+
+impl<S, P> Retry<S, P> {
+    define_inner_service_accessors!();
+
+    /// Set the maximum number of attempts, including the first one.
+    ///
+    /// See [`RetryLayer::max_attempts`] for more details.
+    pub fn max_attempts(mut self, max: u32) -> Self {
+        self.config.max_attempts = max;
+        self
+    }
+
+    /// Set the delay before the first retry, which doubles with every further retry.
+    ///
+    /// See [`RetryLayer::base_delay`] for more details.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.config.base_delay = delay;
+        self
+    }
+
+    /// Set the maximum delay between attempts.
+    ///
+    /// See [`RetryLayer::max_delay`] for more details.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.config.max_delay = delay;
+        self
+    }
+
+    /// Set the maximum size of request bodies that are buffered to be replayed.
+    ///
+    /// See [`RetryLayer::max_body_size`] for more details.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.config.max_body = limit;
+        self
+    }
+
+    /// Set the [`RetryError`] deciding which errors are retried.
+    ///
+    /// See [`RetryLayer::retry_error`] for more details.
+    pub fn retry_error<NewP>(self, policy: NewP) -> Retry<S, NewP> {
+        Retry {
+            inner: self.inner,
+            config: self.config,
+            policy,
+        }
+    }
+}
+
+impl<S, P, ReqBody, ResBody> Service<Request<ReqBody>> for Retry<S, P>
+where
+    S: Service<Request<ReplayBody<ReqBody>>, Response = Response<ResBody>> + Clone,
+    P: RetryError<S::Error> + Clone,
+    ReqBody: Body,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody, P>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // retries are sent later
+        let mut service = take_ready(&mut self.inner);
+
+        let retryable = self.config.max_attempts > 1 && policy::is_idempotent(req.method());
+        let (parts, body) = req.into_parts();
+        let empty = body.size_hint().exact() == Some(0);
+        let body = ReplayBody::new(body, if retryable { self.config.max_body } else { 0 });
+
+        let template = if retryable {
+            Some(Template {
+                method: parts.method.clone(),
+                uri: parts.uri.clone(),
+                version: parts.version,
+                headers: parts.headers.clone(),
+                // empty bodies may never be polled, which would keep them from being replayed
+                body: if empty {
+                    ReplayBody::default()
+                } else {
+                    body.replay()
+                },
+            })
+        } else {
+            None
+        };
+
+        let future = service.call(Request::from_parts(parts, body));
+        ResponseFuture::new(
+            State::Called { future },
+            service,
+            template,
+            self.policy.clone(),
+            self.config,
+        )
+    }
+}
@@ -0,0 +1,358 @@
+//! Middleware that rewrites request URIs.
+//!
+//! [`RewriteUriLayer`] applies an ordered list of rules to the request's path and query. The
+//! first matching rule is applied and the rest are skipped. Rules either match a regular
+//! expression, in which case the matched part is replaced by a template that can refer to
+//! capture groups with `$1` or `$name`, or a literal prefix, which is replaced by another prefix.
+//!
+//! By default the request is rewritten before reaching the inner service. With
+//! [`RewriteUriLayer::redirect`] the client is instead redirected to the rewritten URI.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use regex::Regex;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::rewrite_uri::RewriteUriLayer;
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from(req.uri().to_string())))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         RewriteUriLayer::new()
+//!             .rule(Regex::new(r"^/users/(?P<id>\d+)$").unwrap(), "/v2/users?id=$id")
+//!             .prefix("/api/v1/", "/v1/"),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let response = service
+//!     .ready()
+//!     .await?
+//!     .call(Request::get("/users/42").body(Body::empty())?)
+//!     .await?;
+//!
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert_eq!(body, "/v2/users?id=42");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Redirecting instead of rewriting:
+//!
+//! ```
+//! use http::{header, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::rewrite_uri::RewriteUriLayer;
+//!
+//! # async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//! #     Ok(Response::new(Body::empty()))
+//! # }
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         RewriteUriLayer::new()
+//!             .prefix("/old/", "/new/")
+//!             .redirect(StatusCode::MOVED_PERMANENTLY),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let response = service
+//!     .ready()
+//!     .await?
+//!     .call(Request::get("/old/page").body(Body::empty())?)
+//!     .await?;
+//!
+//! assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+//! assert_eq!(response.headers()[header::LOCATION], "/new/page");
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, uri::PathAndQuery, HeaderValue, Request, Response, StatusCode, Uri};
+use pin_project_lite::pin_project;
+use regex::Regex;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Regex { regex: Regex, template: String },
+    Prefix { prefix: String, replacement: String },
+}
+
+impl Rule {
+    /// Apply the rule to `path_and_query`, returning `None` if it doesn't match.
+    fn apply(&self, path_and_query: &str) -> Option<String> {
+        match self {
+            Rule::Regex { regex, template } => {
+                if regex.is_match(path_and_query) {
+                    Some(
+                        regex
+                            .replace(path_and_query, template.as_str())
+                            .into_owned(),
+                    )
+                } else {
+                    None
+                }
+            }
+            Rule::Prefix {
+                prefix,
+                replacement,
+            } => path_and_query
+                .strip_prefix(prefix.as_str())
+                .map(|rest| format!("{}{}", replacement, rest)),
+        }
+    }
+}
+
+/// Layer that applies [`RewriteUri`] which rewrites request URIs.
+///
+/// See the [module docs](crate::rewrite_uri) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteUriLayer {
+    rules: Vec<Rule>,
+    redirect: Option<StatusCode>,
+}
+
+impl RewriteUriLayer {
+    /// Create a new `RewriteUriLayer` without any rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule replacing the part of the path and query matched by `regex` with `template`.
+    ///
+    /// `template` can refer to capture groups by index, such as `$1`, or by name, such as
+    /// `$name` or `${name}`. See [`Regex::replace`] for more details.
+    pub fn rule(mut self, regex: Regex, template: impl Into<String>) -> Self {
+        self.rules.push(Rule::Regex {
+            regex,
+            template: template.into(),
+        });
+        self
+    }
+
+    /// Add a rule replacing `prefix` at the start of the path with `replacement`.
+    pub fn prefix(mut self, prefix: impl Into<String>, replacement: impl Into<String>) -> Self {
+        self.rules.push(Rule::Prefix {
+            prefix: prefix.into(),
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    /// Redirect requests to the rewritten URI instead of rewriting them.
+    ///
+    /// `status` would usually be `301 Moved Permanently` or `308 Permanent Redirect`. When
+    /// redirecting, rules may produce absolute URIs such as `https://example.com/$1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` isn't a redirection status code.
+    pub fn redirect(mut self, status: StatusCode) -> Self {
+        assert!(
+            status.is_redirection(),
+            "`RewriteUri` can only redirect with 3xx status codes"
+        );
+        self.redirect = Some(status);
+        self
+    }
+}
+
+impl<S> Layer<S> for RewriteUriLayer {
+    type Service = RewriteUri<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RewriteUri {
+            inner,
+            rules: self.rules.clone().into(),
+            redirect: self.redirect,
+        }
+    }
+}
+
+/// Middleware that rewrites request URIs.
+///
+/// See the [module docs](crate::rewrite_uri) for more details.
+#[derive(Debug, Clone)]
+pub struct RewriteUri<S> {
+    inner: S,
+    rules: Arc<[Rule]>,
+    redirect: Option<StatusCode>,
+}
+
+impl<S> RewriteUri<S> {
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `RewriteUri` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> RewriteUriLayer {
+        RewriteUriLayer::new()
+    }
+
+    fn rewrite(&self, uri: &Uri) -> Option<String> {
+        let path_and_query = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+        self.rules
+            .iter()
+            .find_map(|rule| rule.apply(path_and_query))
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RewriteUri<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if let Some(rewritten) = self.rewrite(req.uri()) {
+            if let Some(status) = self.redirect {
+                if let Ok(location) = HeaderValue::from_str(&rewritten) {
+                    let mut res = Response::new(ResBody::default());
+                    *res.status_mut() = status;
+                    res.headers_mut().insert(header::LOCATION, location);
+                    return ResponseFuture {
+                        kind: Kind::Redirect {
+                            response: Some(res),
+                        },
+                    };
+                }
+            } else if let Ok(path_and_query) = rewritten.parse::<PathAndQuery>() {
+                let mut parts = req.uri().clone().into_parts();
+                parts.path_and_query = Some(path_and_query);
+                if let Ok(uri) = Uri::from_parts(parts) {
+                    *req.uri_mut() = uri;
+                }
+            }
+        }
+
+        ResponseFuture {
+            kind: Kind::Future {
+                future: self.inner.call(req),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`RewriteUri`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        Redirect {
+            response: Option<Response<B>>,
+        },
+        Future {
+            #[pin]
+            future: F,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Redirect { response } => {
+                let response = response.take().expect("future polled after completion");
+                Poll::Ready(Ok(response))
+            }
+            KindProj::Future { future } => future.poll(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    async fn echo_uri(req: Request<()>) -> Result<Response<String>, Infallible> {
+        Ok(Response::new(req.uri().to_string()))
+    }
+
+    async fn call(layer: RewriteUriLayer, uri: &str) -> Response<String> {
+        ServiceBuilder::new()
+            .layer(layer)
+            .service_fn(echo_uri)
+            .oneshot(Request::builder().uri(uri).body(()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn first_matching_rule_wins() {
+        let layer = RewriteUriLayer::new()
+            .rule(Regex::new(r"^/a/(\d+)").unwrap(), "/numbers/$1")
+            .prefix("/a/", "/letters/");
+
+        assert_eq!(
+            call(layer.clone(), "/a/1?x=y").await.into_body(),
+            "/numbers/1?x=y"
+        );
+        assert_eq!(call(layer.clone(), "/a/b").await.into_body(), "/letters/b");
+        assert_eq!(call(layer, "/c").await.into_body(), "/c");
+    }
+
+    #[tokio::test]
+    async fn keeps_scheme_and_authority() {
+        let layer = RewriteUriLayer::new().prefix("/old", "/new");
+
+        assert_eq!(
+            call(layer, "http://example.com/old/x").await.into_body(),
+            "http://example.com/new/x"
+        );
+    }
+
+    #[tokio::test]
+    async fn redirects() {
+        let layer = RewriteUriLayer::new()
+            .rule(Regex::new(r"^/(.*)$").unwrap(), "https://example.com/$1")
+            .redirect(StatusCode::PERMANENT_REDIRECT);
+
+        let res = call(layer, "/foo?bar").await;
+
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            res.headers()[header::LOCATION],
+            "https://example.com/foo?bar"
+        );
+    }
+}
@@ -20,9 +20,21 @@ pub use self::{
     serve_dir::{
         future::ResponseFuture as ServeFileSystemResponseFuture,
         DefaultServeDirFallback,
+        DirectoryEntry,
+        DirectoryListing,
+        ETagMode,
+        FileMetadata,
+        FileReader,
+        Filesystem,
+        MimeGuesser,
+        MimeTypes,
+        PathPolicy,
+        PathRejection,
         // The response body and future are used for both ServeDir and ServeFile
         ResponseBody as ServeFileSystemResponseBody,
         ServeDir,
+        TarFilesystem,
+        TokioFilesystem,
     },
     serve_file::ServeFile,
 };
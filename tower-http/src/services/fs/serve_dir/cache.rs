@@ -0,0 +1,113 @@
+use super::{FileMetadata, Filesystem};
+use crate::{fnv::fnv1a, lru::Lru, sync::lock_ignore_poison};
+use bytes::Bytes;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tokio::io::AsyncReadExt;
+
+/// Least recently used cache of file contents, shared between clones of a `ServeDir`.
+#[derive(Clone)]
+pub(super) struct FileCache {
+    inner: Arc<Mutex<Lru<PathBuf, Entry>>>,
+    max_file_size: u64,
+}
+
+struct Entry {
+    contents: Bytes,
+    content_hash: Option<u64>,
+    modified: Option<SystemTime>,
+}
+
+pub(super) struct Cached {
+    pub(super) contents: Bytes,
+    pub(super) content_hash: Option<u64>,
+}
+
+impl FileCache {
+    pub(super) fn new(max_size: usize, max_file_size: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Lru::new(max_size))),
+            max_file_size: max_file_size.min(max_size) as u64,
+        }
+    }
+
+    /// Whether a file with the metadata can be cached.
+    pub(super) fn accepts(&self, meta: &FileMetadata) -> bool {
+        meta.is_file() && meta.len() <= self.max_file_size
+    }
+
+    /// Get the contents of the file at `path` from the cache, reading and caching them if missing
+    /// or if the file has been modified.
+    pub(super) async fn get_or_load(
+        &self,
+        fs: &dyn Filesystem,
+        path: &Path,
+        meta: &FileMetadata,
+        hash_contents: bool,
+    ) -> io::Result<Cached> {
+        let modified = meta.modified();
+
+        {
+            let mut inner = lock_ignore_poison(&self.inner);
+            if let Some(entry) = inner.get(path) {
+                let fresh = modified.is_some()
+                    && entry.modified == modified
+                    && entry.contents.len() as u64 == meta.len()
+                    && (entry.content_hash.is_some() || !hash_contents);
+                if fresh {
+                    return Ok(Cached {
+                        contents: entry.contents.clone(),
+                        content_hash: entry.content_hash,
+                    });
+                }
+                inner.remove(path);
+            }
+        }
+
+        let mut contents = Vec::with_capacity(meta.len() as usize);
+        fs.read(path, None)
+            .await?
+            .read_to_end(&mut contents)
+            .await?;
+        let contents = Bytes::from(contents);
+        let content_hash = if hash_contents {
+            Some(fnv1a(&contents))
+        } else {
+            None
+        };
+
+        // files without a modification time couldn't be invalidated
+        if modified.is_some() {
+            lock_ignore_poison(&self.inner).insert(
+                path.to_owned(),
+                Entry {
+                    contents: contents.clone(),
+                    content_hash,
+                    modified,
+                },
+                contents.len(),
+            );
+        }
+
+        Ok(Cached {
+            contents,
+            content_hash,
+        })
+    }
+}
+
+impl fmt::Debug for FileCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = lock_ignore_poison(&self.inner);
+        f.debug_struct("FileCache")
+            .field("entries", &inner.len())
+            .field("size", &inner.size())
+            .field("max_size", &inner.max_size())
+            .field("max_file_size", &self.max_file_size)
+            .finish()
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    path::{Component, Path},
+    sync::Arc,
+};
+
+/// Glob patterns of paths that [`ServeDir`][super::ServeDir] refuses to serve.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Excludes {
+    patterns: Arc<Vec<String>>,
+}
+
+impl Excludes {
+    pub(super) fn push(&mut self, pattern: &str) {
+        Arc::make_mut(&mut self.patterns).push(pattern.trim_matches('/').to_owned());
+    }
+
+    /// Whether `path`, relative to the served directory, matches any of the patterns.
+    pub(super) fn is_excluded(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+
+        let segments = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(segment) => segment.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains('/') {
+                let pattern = pattern.split('/').collect::<Vec<_>>();
+                match_segments(&pattern, &segments)
+            } else {
+                // patterns without a slash match a file or directory name at any depth
+                segments
+                    .iter()
+                    .any(|segment| match_segment(pattern, segment))
+            }
+        })
+    }
+}
+
+fn match_segments(pattern: &[&str], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => {
+            (0..=segments.len()).any(|skip| match_segments(rest, &segments[skip..]))
+        }
+        Some((first, rest)) => match segments.split_first() {
+            Some((segment, segments)) => {
+                match_segment(first, segment) && match_segments(rest, segments)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Match a single path segment against a pattern where `*` matches any characters and `?` a
+/// single character.
+fn match_segment(pattern: &str, segment: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let segment = segment.chars().collect::<Vec<_>>();
+
+    let (mut p, mut s) = (0, 0);
+    // position of the last `*` in the pattern and the segment position it was tried at
+    let mut backtrack = None;
+    while s < segment.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, s));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == segment[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    // let the `*` match one more character
+                    p = star + 1;
+                    s = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let mut excludes = Excludes::default();
+        excludes.push("*.map");
+        excludes.push("/secret/**");
+        excludes.push("private/?.txt");
+
+        for path in [
+            "app.js.map",
+            "js/app.js.map",
+            "secret",
+            "secret/key.pem",
+            "secret/nested/key.pem",
+            "private/a.txt",
+        ] {
+            assert!(excludes.is_excluded(Path::new(path)), "{}", path);
+        }
+
+        for path in [
+            "app.js",
+            "map",
+            "not-secret/key.pem",
+            "nested/secret/key.pem",
+            "private/ab.txt",
+        ] {
+            assert!(!excludes.is_excluded(Path::new(path)), "{}", path);
+        }
+
+        assert!(!Excludes::default().is_excluded(Path::new("app.js.map")));
+    }
+
+    #[test]
+    fn segment_wildcards() {
+        assert!(match_segment("*", ""));
+        assert!(match_segment("a*b*c", "aXbYbc"));
+        assert!(match_segment("*.tar.*", "x.tar.gz"));
+        assert!(!match_segment("a*b", "aXc"));
+        assert!(!match_segment("?", ""));
+    }
+}
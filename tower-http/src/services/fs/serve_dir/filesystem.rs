@@ -0,0 +1,256 @@
+use super::DirectoryEntry;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::{
+    fmt,
+    io::{self, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+
+/// The contents of a file read from a [`Filesystem`].
+pub type FileReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// The files served by [`ServeDir`][super::ServeDir].
+///
+/// Paths are the directory passed to [`ServeDir::new`][super::ServeDir::new] joined with the
+/// path of the request, so `ServeDir::new("assets")` reads `./assets/index.html` for requests
+/// to `/index.html`. Return errors of kind [`io::ErrorKind::NotFound`] for missing files.
+///
+/// Defaults to [`TokioFilesystem`].
+///
+/// # Example
+///
+/// Serving assets embedded in the binary:
+///
+/// ```
+/// use futures_util::future::{self, BoxFuture, FutureExt};
+/// use std::{collections::HashMap, io, ops::Range, path::Path};
+/// use tower_http::services::{
+///     fs::{FileMetadata, FileReader, Filesystem},
+///     ServeDir,
+/// };
+///
+/// struct Embedded(HashMap<&'static str, &'static [u8]>);
+///
+/// impl Embedded {
+///     fn get(&self, path: &Path) -> io::Result<&'static [u8]> {
+///         path.to_str()
+///             .and_then(|path| self.0.get(path.trim_start_matches("./")))
+///             .copied()
+///             .ok_or_else(|| io::ErrorKind::NotFound.into())
+///     }
+/// }
+///
+/// impl Filesystem for Embedded {
+///     fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+///         let metadata = self
+///             .get(path)
+///             .map(|contents| FileMetadata::file(contents.len() as u64));
+///         future::ready(metadata).boxed()
+///     }
+///
+///     fn read<'a>(
+///         &'a self,
+///         path: &'a Path,
+///         range: Option<Range<u64>>,
+///     ) -> BoxFuture<'a, io::Result<FileReader>> {
+///         let reader = self.get(path).map(|contents| {
+///             let contents = match range {
+///                 Some(range) => &contents[range.start as usize..range.end as usize],
+///                 None => contents,
+///             };
+///             Box::pin(contents) as FileReader
+///         });
+///         future::ready(reader).boxed()
+///     }
+/// }
+///
+/// let assets = HashMap::from([("app.js", &b"console.log('hi')"[..])]);
+/// let service = ServeDir::new("").filesystem(Embedded(assets));
+/// ```
+pub trait Filesystem: Send + Sync {
+    /// Get the metadata of the file or directory at `path`, following symlinks.
+    fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>>;
+
+    /// Read the bytes in `range` of the file at `path`, or the whole file if `range` is `None`.
+    fn read<'a>(
+        &'a self,
+        path: &'a Path,
+        range: Option<Range<u64>>,
+    ) -> BoxFuture<'a, io::Result<FileReader>>;
+
+    /// List the entries of the directory at `path`.
+    ///
+    /// Only used for [directory listings][super::ServeDir::list_directories]. Fails with
+    /// [`io::ErrorKind::Unsupported`] by default.
+    fn read_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Vec<DirectoryEntry>>> {
+        let _ = path;
+        futures_util::future::ready(Err(io::ErrorKind::Unsupported.into())).boxed()
+    }
+
+    /// Whether the entry at `path` is a symlink, without following it.
+    ///
+    /// Only used by [`PathPolicy`][super::PathPolicy]. Defaults to `false` for filesystems
+    /// without symlinks.
+    fn is_symlink<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<bool>> {
+        let _ = path;
+        futures_util::future::ready(Ok(false)).boxed()
+    }
+
+    /// Resolve `path` to an absolute path with all symlinks followed.
+    ///
+    /// Only used by [`PathPolicy`][super::PathPolicy]. Defaults to returning `path` unchanged
+    /// for filesystems without symlinks.
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<PathBuf>> {
+        futures_util::future::ready(Ok(path.to_owned())).boxed()
+    }
+}
+
+/// Metadata of a file or directory in a [`Filesystem`].
+#[derive(Clone, Debug)]
+pub struct FileMetadata {
+    len: u64,
+    is_dir: bool,
+    modified: Option<SystemTime>,
+}
+
+impl FileMetadata {
+    /// Create the metadata of a file of `len` bytes.
+    pub fn file(len: u64) -> Self {
+        Self {
+            len,
+            is_dir: false,
+            modified: None,
+        }
+    }
+
+    /// Create the metadata of a directory.
+    pub fn dir() -> Self {
+        Self {
+            len: 0,
+            is_dir: true,
+            modified: None,
+        }
+    }
+
+    /// Set the time the file was last modified.
+    ///
+    /// This is used for `Last-Modified` and `ETag` headers.
+    pub fn with_modified(mut self, modified: SystemTime) -> Self {
+        self.modified = Some(modified);
+        self
+    }
+
+    /// The size of the file in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether this is the metadata of a directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Whether this is the metadata of a file.
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    /// The time the file was last modified, if available.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+}
+
+impl From<std::fs::Metadata> for FileMetadata {
+    fn from(meta: std::fs::Metadata) -> Self {
+        Self {
+            len: meta.len(),
+            is_dir: meta.is_dir(),
+            modified: meta.modified().ok(),
+        }
+    }
+}
+
+/// A [`Filesystem`] reading from disk with [`tokio::fs`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioFilesystem;
+
+impl Filesystem for TokioFilesystem {
+    fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+        async move { Ok(tokio::fs::metadata(path).await?.into()) }.boxed()
+    }
+
+    fn read<'a>(
+        &'a self,
+        path: &'a Path,
+        range: Option<Range<u64>>,
+    ) -> BoxFuture<'a, io::Result<FileReader>> {
+        async move {
+            let mut file = tokio::fs::File::open(path).await?;
+            let reader: FileReader = match range {
+                Some(range) => {
+                    file.seek(SeekFrom::Start(range.start)).await?;
+                    Box::pin(file.take(range.end - range.start))
+                }
+                None => Box::pin(file),
+            };
+            Ok(reader)
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Vec<DirectoryEntry>>> {
+        async move {
+            let mut entries = Vec::new();
+            let mut read_dir = tokio::fs::read_dir(path).await?;
+            while let Some(entry) = read_dir.next_entry().await? {
+                // names that aren't valid UTF-8 can't be linked to
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+                // follow symlinks, skipping broken ones
+                let meta = match tokio::fs::metadata(entry.path()).await {
+                    Ok(meta) => meta,
+                    Err(_) => continue,
+                };
+                entries.push(DirectoryEntry::new(name, &meta.into()));
+            }
+            Ok(entries)
+        }
+        .boxed()
+    }
+
+    fn is_symlink<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<bool>> {
+        async move {
+            let meta = tokio::fs::symlink_metadata(path).await?;
+            Ok(meta.file_type().is_symlink())
+        }
+        .boxed()
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<PathBuf>> {
+        tokio::fs::canonicalize(path).boxed()
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct SharedFilesystem(pub(super) Arc<dyn Filesystem>);
+
+impl Default for SharedFilesystem {
+    fn default() -> Self {
+        Self(Arc::new(TokioFilesystem))
+    }
+}
+
+impl fmt::Debug for SharedFilesystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedFilesystem").finish()
+    }
+}
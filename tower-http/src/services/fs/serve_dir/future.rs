@@ -1,6 +1,6 @@
 use super::{
-    open_file::{FileOpened, FileRequestExtent, OpenFileOutput},
-    DefaultServeDirFallback, ResponseBody,
+    open_file::{is_not_found_error, FileOpened, FileRequestExtent, OpenFileOutput},
+    DefaultServeDirFallback, PathRejection, ResponseBody,
 };
 use crate::{content_encoding::Encoding, services::fs::AsyncReadBody, BoxError};
 use bytes::Bytes;
@@ -52,6 +52,12 @@ impl<ReqBody, F> ResponseFuture<ReqBody, F> {
         }
     }
 
+    pub(super) fn path_rejected(rejection: PathRejection) -> Self {
+        Self {
+            inner: ResponseFutureInner::PathRejected { rejection },
+        }
+    }
+
     pub(super) fn method_not_allowed() -> Self {
         Self {
             inner: ResponseFutureInner::MethodNotAllowed,
@@ -73,6 +79,9 @@ pin_project! {
         InvalidPath {
             fallback_and_request: Option<(F, Request<ReqBody>)>,
         },
+        PathRejected {
+            rejection: PathRejection,
+        },
         MethodNotAllowed,
     }
 }
@@ -99,13 +108,20 @@ where
                         break Poll::Ready(Ok(build_response(*file_output)));
                     }
 
+                    Ok(OpenFileOutput::DirectoryListing(res)) => {
+                        break Poll::Ready(Ok(res.map(body_from_bytes)));
+                    }
+
                     Ok(OpenFileOutput::Redirect { location }) => {
                         let mut res = response_with_status(StatusCode::TEMPORARY_REDIRECT);
                         res.headers_mut().insert(http::header::LOCATION, location);
                         break Poll::Ready(Ok(res));
                     }
 
-                    Ok(OpenFileOutput::FileNotFound) => {
+                    Ok(
+                        OpenFileOutput::FileNotFound
+                        | OpenFileOutput::PathRejected(PathRejection::NotFound),
+                    ) => {
                         if let Some((mut fallback, request)) = fallback_and_request.take() {
                             call_fallback(&mut fallback, request)
                         } else {
@@ -113,31 +129,36 @@ where
                         }
                     }
 
+                    Ok(OpenFileOutput::PathRejected(rejection)) => {
+                        break Poll::Ready(Ok(response_with_status(rejection.status())));
+                    }
+
                     Ok(OpenFileOutput::PreconditionFailed) => {
                         break Poll::Ready(Ok(response_with_status(
                             StatusCode::PRECONDITION_FAILED,
                         )));
                     }
 
-                    Ok(OpenFileOutput::NotModified) => {
-                        break Poll::Ready(Ok(response_with_status(StatusCode::NOT_MODIFIED)));
+                    Ok(OpenFileOutput::NotModified {
+                        last_modified,
+                        etag,
+                    }) => {
+                        let mut res = response_with_status(StatusCode::NOT_MODIFIED);
+                        if let Some(etag) = etag {
+                            res.headers_mut()
+                                .insert(header::ETAG, etag.to_header_value());
+                        }
+                        if let Some(last_modified) = last_modified {
+                            res.headers_mut().insert(
+                                header::LAST_MODIFIED,
+                                HeaderValue::from_str(&last_modified.0.to_string()).unwrap(),
+                            );
+                        }
+                        break Poll::Ready(Ok(res));
                     }
 
                     Err(err) => {
-                        #[cfg(unix)]
-                        // 20 = libc::ENOTDIR => "not a directory
-                        // when `io_error_more` landed, this can be changed
-                        // to checking for `io::ErrorKind::NotADirectory`.
-                        // https://github.com/rust-lang/rust/issues/86442
-                        let error_is_not_a_directory = err.raw_os_error() == Some(20);
-                        #[cfg(not(unix))]
-                        let error_is_not_a_directory = false;
-
-                        if matches!(
-                            err.kind(),
-                            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
-                        ) || error_is_not_a_directory
-                        {
+                        if is_not_found_error(&err) {
                             if let Some((mut fallback, request)) = fallback_and_request.take() {
                                 call_fallback(&mut fallback, request)
                             } else {
@@ -163,6 +184,10 @@ where
                     }
                 }
 
+                ResponseFutureInnerProj::PathRejected { rejection } => {
+                    break Poll::Ready(Ok(response_with_status(rejection.status())));
+                }
+
                 ResponseFutureInnerProj::MethodNotAllowed => {
                     let mut res = response_with_status(StatusCode::METHOD_NOT_ALLOWED);
                     res.headers_mut()
@@ -216,9 +241,10 @@ where
 }
 
 fn build_response(output: FileOpened) -> Response<ResponseBody> {
-    let (maybe_file, size) = match output.extent {
-        FileRequestExtent::Full(file, meta) => (Some(file), meta.len()),
-        FileRequestExtent::Head(meta) => (None, meta.len()),
+    let (maybe_file, maybe_contents, size) = match output.extent {
+        FileRequestExtent::Full(file, meta) => (Some(file), None, meta.len()),
+        FileRequestExtent::Memory(contents, meta) => (None, Some(contents), meta.len()),
+        FileRequestExtent::Head(meta) => (None, None, meta.len()),
     };
 
     let mut builder = Response::builder()
@@ -232,6 +258,14 @@ fn build_response(output: FileOpened) -> Response<ResponseBody> {
         builder = builder.header(header::CONTENT_ENCODING, encoding.into_header_value());
     }
 
+    if output.vary_accept_encoding {
+        builder = builder.header(header::VARY, header::ACCEPT_ENCODING.as_str());
+    }
+
+    if let Some(etag) = output.etag {
+        builder = builder.header(header::ETAG, etag.to_header_value());
+    }
+
     if let Some(last_modified) = output.last_modified {
         builder = builder.header(header::LAST_MODIFIED, last_modified.0.to_string());
     }
@@ -258,6 +292,10 @@ fn build_response(output: FileOpened) -> Response<ResponseBody> {
                             )
                             .boxed_unsync(),
                         )
+                    } else if let Some(contents) = maybe_contents {
+                        body_from_bytes(
+                            contents.slice(*range.start() as usize..=*range.end() as usize),
+                        )
                     } else {
                         empty_body()
                     };
@@ -295,6 +333,8 @@ fn build_response(output: FileOpened) -> Response<ResponseBody> {
                 ResponseBody::new(
                     AsyncReadBody::with_capacity(file, output.chunk_size).boxed_unsync(),
                 )
+            } else if let Some(contents) = maybe_contents {
+                body_from_bytes(contents)
             } else {
                 empty_body()
             };
@@ -1,7 +1,10 @@
+use super::FileMetadata;
+use crate::entity_tag;
 use http::header::HeaderValue;
 use httpdate::HttpDate;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+#[derive(Clone)]
 pub(super) struct LastModified(pub(super) HttpDate);
 
 impl From<SystemTime> for LastModified {
@@ -43,3 +46,96 @@ impl IfUnmodifiedSince {
             .map(|time| IfUnmodifiedSince(time.into()))
     }
 }
+
+#[derive(Clone)]
+pub(super) struct ETag {
+    weak: bool,
+    // the quoted opaque tag, without the `W/` prefix
+    opaque: String,
+}
+
+impl ETag {
+    /// Create a weak entity tag from the size and modification time of a file.
+    pub(super) fn from_metadata(meta: &FileMetadata) -> Option<ETag> {
+        let modified = meta.modified()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(ETag {
+            weak: true,
+            opaque: format!(
+                "\"{:x}-{:x}.{:x}\"",
+                meta.len(),
+                modified.as_secs(),
+                modified.subsec_nanos()
+            ),
+        })
+    }
+
+    /// Create a strong entity tag from a hash of the contents of a file.
+    pub(super) fn from_content_hash(hash: u64) -> ETag {
+        ETag {
+            weak: false,
+            opaque: format!("\"{:016x}\"", hash),
+        }
+    }
+
+    pub(super) fn to_header_value(&self) -> HeaderValue {
+        let value = if self.weak {
+            format!("W/{}", self.opaque)
+        } else {
+            self.opaque.clone()
+        };
+        HeaderValue::from_str(&value).expect("entity tags are valid header values")
+    }
+}
+
+pub(super) struct IfNoneMatch(Vec<HeaderValue>);
+
+impl IfNoneMatch {
+    /// Check if the entity tag matches any of the listed ones, using the weak comparison.
+    pub(super) fn matches(&self, etag: Option<&ETag>) -> bool {
+        entity_tag::if_none_match(&self.0, etag.map(|etag| etag.opaque.as_str()))
+    }
+
+    /// Collect the header values into a IfNoneMatch, invalid values are silentely ignored
+    pub(super) fn from_header_values<'a, I>(values: I) -> Option<IfNoneMatch>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        let values = values.into_iter().cloned().collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(IfNoneMatch(values))
+        }
+    }
+}
+
+pub(super) enum IfRange {
+    Date(HttpDate),
+    EntityTag(String),
+}
+
+impl IfRange {
+    /// Check if the range request should be served, i.e. the representation hasn't changed.
+    ///
+    /// Entity tags use the strong comparison so they only match strong `ETag`s.
+    pub(super) fn matches(
+        &self,
+        last_modified: Option<&LastModified>,
+        etag: Option<&ETag>,
+    ) -> bool {
+        match (self, last_modified, etag) {
+            (IfRange::Date(date), Some(last_modified), _) => *date == last_modified.0,
+            (IfRange::EntityTag(tag), _, Some(etag)) => !etag.weak && *tag == etag.opaque,
+            _ => false,
+        }
+    }
+
+    /// Convert a header value into a IfRange
+    pub(super) fn from_header_value(value: &HeaderValue) -> IfRange {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        match httpdate::parse_http_date(&value) {
+            Ok(time) => IfRange::Date(time.into()),
+            Err(_) => IfRange::EntityTag(value.trim().to_owned()),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::{FileMetadata, Filesystem};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Response};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::{
+    fmt::{self, Write},
+    io,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An entry of a [`DirectoryListing`].
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl DirectoryEntry {
+    /// Create a new [`DirectoryEntry`] for a [`Filesystem::read_dir`] implementation.
+    pub fn new(name: String, meta: &FileMetadata) -> Self {
+        Self {
+            name,
+            is_dir: meta.is_dir(),
+            size: meta.len(),
+            modified: meta.modified(),
+        }
+    }
+
+    /// The file name of the entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// The size of the entry in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The time the entry was last modified, if available.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+}
+
+/// The entries of a directory requested from [`ServeDir`][super::ServeDir] with directory
+/// listings enabled.
+///
+/// Directories are listed first, and entries are otherwise sorted by name.
+#[derive(Debug, Clone)]
+pub struct DirectoryListing {
+    path: String,
+    entries: Vec<DirectoryEntry>,
+}
+
+impl DirectoryListing {
+    /// The path of the request, such as `/assets/`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The entries of the directory.
+    pub fn entries(&self) -> &[DirectoryEntry] {
+        &self.entries
+    }
+
+    /// Render the listing as an HTML page.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let path = HtmlEscape(&self.path);
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Index of {}</title>\n</head>\n<body>\n<h1>Index of {}</h1>\n<ul>\n",
+            path, path
+        )
+        .unwrap();
+        if self.path != "/" {
+            html.push_str("<li><a href=\"../\">../</a></li>\n");
+        }
+        for entry in &self.entries {
+            let slash = if entry.is_dir { "/" } else { "" };
+            // anchor hrefs to the directory so names like `javascript:..` aren't read as schemes
+            writeln!(
+                html,
+                "<li><a href=\"./{}{}\">{}{}</a></li>",
+                utf8_percent_encode(&entry.name, HREF),
+                slash,
+                HtmlEscape(&entry.name),
+                slash
+            )
+            .unwrap();
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Render the listing as JSON.
+    ///
+    /// The JSON is an object with the request `path` and the `entries` of the directory, each
+    /// with their `name`, `is_dir`, `size` and `modified` time in seconds since the Unix epoch.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"path\":{},\"entries\":[", JsonString(&self.path));
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let modified = entry
+                .modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or_else(|| "null".to_owned(), |time| time.as_secs().to_string());
+            write!(
+                json,
+                "{{\"name\":{},\"is_dir\":{},\"size\":{},\"modified\":{}}}",
+                JsonString(&entry.name),
+                entry.is_dir,
+                entry.size,
+                modified
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+// Characters that must be escaped in relative URLs of file names.
+const HREF: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b':')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+struct HtmlEscape<'a>(&'a str);
+
+impl fmt::Display for HtmlEscape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&#39;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct JsonString<'a>(&'a str);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+type RenderFn = dyn Fn(&DirectoryListing, &HeaderMap) -> Response<Bytes> + Send + Sync;
+type HideFn = dyn Fn(&DirectoryEntry) -> bool + Send + Sync;
+
+#[derive(Clone, Default)]
+pub(super) struct ListingOptions {
+    pub(super) enabled: bool,
+    pub(super) render: Option<Arc<RenderFn>>,
+    pub(super) hide: Option<Arc<HideFn>>,
+}
+
+impl fmt::Debug for ListingOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListingOptions")
+            .field("enabled", &self.enabled)
+            .field("render", &self.render.as_ref().map(|_| ".."))
+            .field("hide", &self.hide.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl ListingOptions {
+    fn is_hidden(&self, entry: &DirectoryEntry) -> bool {
+        match &self.hide {
+            Some(hide) => hide(entry),
+            None => entry.name.starts_with('.'),
+        }
+    }
+
+    pub(super) async fn list(
+        &self,
+        fs: &dyn Filesystem,
+        dir: &Path,
+        request_path: &str,
+        request_headers: &HeaderMap,
+        is_excluded: &(dyn Fn(&str) -> bool + Send + Sync),
+    ) -> io::Result<Response<Bytes>> {
+        let mut entries = fs.read_dir(dir).await?;
+        entries.retain(|entry| !is_excluded(&entry.name) && !self.is_hidden(entry));
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+        let listing = DirectoryListing {
+            path: request_path.to_owned(),
+            entries,
+        };
+
+        Ok(match &self.render {
+            Some(render) => render(&listing, request_headers),
+            None => render_default(&listing, request_headers),
+        })
+    }
+}
+
+/// Render JSON if the client accepts it, otherwise HTML.
+fn render_default(listing: &DirectoryListing, request_headers: &HeaderMap) -> Response<Bytes> {
+    let wants_json = request_headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.trim().starts_with("application/json"));
+
+    let (content_type, body) = if wants_json {
+        ("application/json", listing.to_json())
+    } else {
+        ("text/html; charset=utf-8", listing.to_html())
+    };
+
+    let mut res = Response::new(Bytes::from(body));
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    res.headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hrefs_are_relative_to_the_directory() {
+        let listing = DirectoryListing {
+            path: "/".to_owned(),
+            entries: vec![
+                DirectoryEntry::new("javascript:alert(1)".to_owned(), &FileMetadata::file(0)),
+                DirectoryEntry::new("sub dir".to_owned(), &FileMetadata::dir()),
+            ],
+        };
+        let html = listing.to_html();
+        assert!(
+            html.contains("<li><a href=\"./javascript%3Aalert(1)\">javascript:alert(1)</a></li>")
+        );
+        assert!(html.contains("<li><a href=\"./sub%20dir/\">sub dir/</a></li>"));
+        assert!(!html.contains("href=\"javascript:"));
+    }
+}
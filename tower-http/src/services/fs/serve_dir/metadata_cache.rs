@@ -0,0 +1,135 @@
+use super::{DirectoryEntry, FileMetadata, FileReader, Filesystem};
+use crate::{lru::Lru, sync::lock_ignore_poison};
+use futures_util::future::{BoxFuture, FutureExt};
+use http::HeaderValue;
+use std::{
+    fmt, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Least recently used cache of file metadata and content types, shared between clones of a
+/// `ServeDir`.
+///
+/// Files that don't exist aren't cached, so requests for arbitrary paths can't evict the
+/// metadata of existing files.
+#[derive(Clone)]
+pub(super) struct MetadataCache {
+    inner: Arc<Mutex<Lru<PathBuf, Entry>>>,
+    ttl: Duration,
+}
+
+struct Entry {
+    expires: Instant,
+    metadata: FileMetadata,
+    // guessed from the path on first use
+    content_type: Option<HeaderValue>,
+}
+
+impl MetadataCache {
+    pub(super) fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            // every entry has a size of one, so the size is the number of entries
+            inner: Arc::new(Mutex::new(Lru::new(max_entries))),
+            ttl,
+        }
+    }
+
+    /// Wrap `fs` so metadata is read from this cache.
+    pub(super) fn filesystem<'a>(&'a self, fs: &'a dyn Filesystem) -> CachedMetadata<'a> {
+        CachedMetadata { fs, cache: self }
+    }
+
+    /// Get the content type of the file at `path`, calling `guess` if it isn't cached yet.
+    ///
+    /// Content types are only cached for paths whose metadata is cached.
+    pub(super) fn content_type<F>(&self, path: &Path, guess: F) -> HeaderValue
+    where
+        F: FnOnce() -> HeaderValue,
+    {
+        if let Some(entry) = get_fresh(&mut lock_ignore_poison(&self.inner), path) {
+            if let Some(content_type) = &entry.content_type {
+                return content_type.clone();
+            }
+        }
+
+        let content_type = guess();
+        if let Some(entry) = get_fresh(&mut lock_ignore_poison(&self.inner), path) {
+            entry.content_type = Some(content_type.clone());
+        }
+        content_type
+    }
+
+    async fn metadata(&self, fs: &dyn Filesystem, path: &Path) -> io::Result<FileMetadata> {
+        if let Some(entry) = get_fresh(&mut lock_ignore_poison(&self.inner), path) {
+            return Ok(entry.metadata.clone());
+        }
+
+        let metadata = fs.metadata(path).await?;
+        lock_ignore_poison(&self.inner).insert(
+            path.to_owned(),
+            Entry {
+                expires: Instant::now() + self.ttl,
+                metadata: metadata.clone(),
+                content_type: None,
+            },
+            1,
+        );
+        Ok(metadata)
+    }
+}
+
+impl fmt::Debug for MetadataCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = lock_ignore_poison(&self.inner);
+        f.debug_struct("MetadataCache")
+            .field("entries", &inner.len())
+            .field("ttl", &self.ttl)
+            .field("max_entries", &inner.max_size())
+            .finish()
+    }
+}
+
+/// Get the entry for `path` if it hasn't expired, marking it as used.
+fn get_fresh<'a>(entries: &'a mut Lru<PathBuf, Entry>, path: &Path) -> Option<&'a mut Entry> {
+    let expired = entries.get(path)?.expires <= Instant::now();
+    if expired {
+        entries.remove(path);
+        return None;
+    }
+    entries.get(path)
+}
+
+/// A [`Filesystem`] reading metadata from a [`MetadataCache`].
+pub(super) struct CachedMetadata<'a> {
+    fs: &'a dyn Filesystem,
+    cache: &'a MetadataCache,
+}
+
+impl Filesystem for CachedMetadata<'_> {
+    fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+        self.cache.metadata(self.fs, path).boxed()
+    }
+
+    fn read<'a>(
+        &'a self,
+        path: &'a Path,
+        range: Option<Range<u64>>,
+    ) -> BoxFuture<'a, io::Result<FileReader>> {
+        self.fs.read(path, range)
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Vec<DirectoryEntry>>> {
+        self.fs.read_dir(path)
+    }
+
+    fn is_symlink<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<bool>> {
+        self.fs.is_symlink(path)
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<PathBuf>> {
+        self.fs.canonicalize(path)
+    }
+}
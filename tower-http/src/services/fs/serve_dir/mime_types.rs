@@ -0,0 +1,155 @@
+use http::HeaderValue;
+use mime::Mime;
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+
+/// Trait for guessing the `Content-Type` of files served by [`ServeDir`][super::ServeDir].
+///
+/// Returning `None` makes `ServeDir` respond with `application/octet-stream`.
+///
+/// This trait is implemented for closures with the signature
+/// `Fn(&Path) -> Option<HeaderValue>`. See [`MimeTypes`] for overriding the type of specific
+/// extensions.
+pub trait MimeGuesser {
+    /// Guess the `Content-Type` of the file at `path`.
+    fn guess(&self, path: &Path) -> Option<HeaderValue>;
+}
+
+impl<F> MimeGuesser for F
+where
+    F: Fn(&Path) -> Option<HeaderValue>,
+{
+    fn guess(&self, path: &Path) -> Option<HeaderValue> {
+        self(path)
+    }
+}
+
+/// A [`MimeGuesser`] that guesses from file extensions, with overrides for specific extensions.
+///
+/// Extensions without an override are guessed with [`mime_guess`].
+///
+/// # Example
+///
+/// ```
+/// use tower_http::services::{fs::MimeTypes, ServeDir};
+///
+/// let service = ServeDir::new("assets").mime_guesser(
+///     MimeTypes::new()
+///         .extension("mjs", &mime::TEXT_JAVASCRIPT)
+///         .default_mime(&mime::TEXT_PLAIN)
+///         .text_charset("utf-8"),
+/// );
+/// ```
+///
+/// [`mime_guess`]: https://docs.rs/mime_guess
+#[derive(Clone, Debug)]
+pub struct MimeTypes {
+    overrides: HashMap<String, Mime>,
+    default: Mime,
+    text_charset: Option<String>,
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        Self {
+            overrides: HashMap::new(),
+            default: mime::APPLICATION_OCTET_STREAM,
+            text_charset: None,
+        }
+    }
+}
+
+impl MimeTypes {
+    /// Create a new [`MimeTypes`] without any overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `mime` for files with the extension `extension`, such as `"wasm"`.
+    ///
+    /// Extensions are matched case-insensitively.
+    pub fn extension(mut self, extension: &str, mime: &Mime) -> Self {
+        self.overrides
+            .insert(extension.to_ascii_lowercase(), mime.clone());
+        self
+    }
+
+    /// Use `mime` for files with an unknown extension.
+    ///
+    /// Defaults to `application/octet-stream`.
+    pub fn default_mime(mut self, mime: &Mime) -> Self {
+        self.default = mime.clone();
+        self
+    }
+
+    /// Add a `charset` parameter to `text/*` types that don't already have one.
+    pub fn text_charset(mut self, charset: &str) -> Self {
+        self.text_charset = Some(charset.to_owned());
+        self
+    }
+}
+
+impl MimeGuesser for MimeTypes {
+    fn guess(&self, path: &Path) -> Option<HeaderValue> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let guessed;
+        let mime = match extension
+            .as_ref()
+            .and_then(|extension| self.overrides.get(extension))
+        {
+            Some(mime) => mime,
+            None => {
+                guessed = mime_guess::from_path(path).first();
+                guessed.as_ref().unwrap_or(&self.default)
+            }
+        };
+
+        match &self.text_charset {
+            Some(charset)
+                if mime.type_() == mime::TEXT && mime.get_param(mime::CHARSET).is_none() =>
+            {
+                HeaderValue::from_str(&format!("{}; charset={}", mime, charset)).ok()
+            }
+            _ => HeaderValue::from_str(mime.as_ref()).ok(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct SharedMimeGuesser(pub(super) Arc<dyn MimeGuesser + Send + Sync>);
+
+impl fmt::Debug for SharedMimeGuesser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedMimeGuesser").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_and_charset() {
+        let types = MimeTypes::new()
+            .extension("WASM", &"application/wasm".parse().unwrap())
+            .default_mime(&mime::TEXT_PLAIN)
+            .text_charset("utf-8");
+
+        assert_eq!(
+            types.guess(Path::new("app.wasm")).unwrap(),
+            "application/wasm"
+        );
+        assert_eq!(
+            types.guess(Path::new("index.HTML")).unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            types.guess(Path::new("no-extension")).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(types.guess(Path::new("image.png")).unwrap(), "image/png");
+    }
+}
@@ -1,6 +1,10 @@
-use self::future::ResponseFuture;
+use self::{
+    cache::FileCache, exclude::Excludes, filesystem::SharedFilesystem, future::ResponseFuture,
+    listing::ListingOptions, metadata_cache::MetadataCache, mime_types::SharedMimeGuesser,
+};
 use crate::{
     content_encoding::{encodings, SupportedEncodings},
+    ready::take_ready,
     set_status::SetStatus,
 };
 use bytes::Bytes;
@@ -12,13 +16,31 @@ use std::{
     convert::Infallible,
     io,
     path::{Component, Path, PathBuf},
+    sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 use tower_service::Service;
 
+mod cache;
+mod exclude;
+mod filesystem;
 pub(crate) mod future;
 mod headers;
+mod listing;
+mod metadata_cache;
+mod mime_types;
 mod open_file;
+mod path_policy;
+mod tar;
+
+pub use self::{
+    filesystem::{FileMetadata, FileReader, Filesystem, TokioFilesystem},
+    listing::{DirectoryEntry, DirectoryListing},
+    mime_types::{MimeGuesser, MimeTypes},
+    path_policy::{PathPolicy, PathRejection},
+    tar::TarFilesystem,
+};
 
 #[cfg(test)]
 mod tests;
@@ -67,6 +89,16 @@ pub struct ServeDir<F = DefaultServeDirFallback> {
     variant: ServeVariant,
     fallback: Option<F>,
     call_fallback_on_method_not_allowed: bool,
+    etag_mode: ETagMode,
+    directory_listing: ListingOptions,
+    file_cache: Option<FileCache>,
+    path_policy: PathPolicy,
+    mime_guesser: Option<SharedMimeGuesser>,
+    spa_fallback: Option<PathBuf>,
+    excludes: Excludes,
+    filesystem: SharedFilesystem,
+    other_roots: Arc<Vec<PathBuf>>,
+    metadata_cache: Option<MetadataCache>,
 }
 
 impl ServeDir<DefaultServeDirFallback> {
@@ -87,6 +119,16 @@ impl ServeDir<DefaultServeDirFallback> {
             },
             fallback: None,
             call_fallback_on_method_not_allowed: false,
+            etag_mode: ETagMode::default(),
+            directory_listing: ListingOptions::default(),
+            file_cache: None,
+            path_policy: PathPolicy::default(),
+            mime_guesser: None,
+            spa_fallback: None,
+            excludes: Excludes::default(),
+            filesystem: SharedFilesystem::default(),
+            other_roots: Arc::default(),
+            metadata_cache: None,
         }
     }
 
@@ -101,6 +143,16 @@ impl ServeDir<DefaultServeDirFallback> {
             variant: ServeVariant::SingleFile { mime },
             fallback: None,
             call_fallback_on_method_not_allowed: false,
+            etag_mode: ETagMode::default(),
+            directory_listing: ListingOptions::default(),
+            file_cache: None,
+            path_policy: PathPolicy::default(),
+            mime_guesser: None,
+            spa_fallback: None,
+            excludes: Excludes::default(),
+            filesystem: SharedFilesystem::default(),
+            other_roots: Arc::default(),
+            metadata_cache: None,
         }
     }
 }
@@ -199,6 +251,212 @@ impl<F> ServeDir<F> {
         self
     }
 
+    /// Respond with a listing of the directory's entries when a directory without an
+    /// `index.html` is requested.
+    ///
+    /// The listing is rendered as HTML, or as JSON if the request's `Accept` header allows
+    /// `application/json`. See [`DirectoryListing::to_json`] for the format. Entries whose name
+    /// starts with `.` are hidden.
+    ///
+    /// Disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("assets")
+    ///     .list_directories()
+    ///     // also hide `.txt` files
+    ///     .hide_entries_with(|entry| entry.name().starts_with('.') || entry.name().ends_with(".txt"));
+    /// ```
+    pub fn list_directories(mut self) -> Self {
+        self.directory_listing.enabled = true;
+        self
+    }
+
+    /// Respond with a listing of the directory's entries, rendered by `render`, when a directory
+    /// without an `index.html` is requested.
+    ///
+    /// `render` also receives the request's headers, for example to do content negotiation.
+    /// [`DirectoryListing::to_html`] and [`DirectoryListing::to_json`] can be used to render the
+    /// default formats.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use http::{header, Response};
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("assets").render_directories_with(|listing, _headers| {
+    ///     let names: Vec<&str> = listing.entries().iter().map(|entry| entry.name()).collect();
+    ///     Response::builder()
+    ///         .header(header::CONTENT_TYPE, "text/plain")
+    ///         .body(names.join("\n").into())
+    ///         .unwrap()
+    /// });
+    /// ```
+    pub fn render_directories_with<R>(mut self, render: R) -> Self
+    where
+        R: Fn(&DirectoryListing, &http::HeaderMap) -> Response<Bytes> + Send + Sync + 'static,
+    {
+        self.directory_listing.enabled = true;
+        self.directory_listing.render = Some(Arc::new(render));
+        self
+    }
+
+    /// Hide the directory entries for which `hide` returns `true` from directory listings.
+    ///
+    /// This replaces the default of hiding entries whose name starts with `.`. It has no effect
+    /// unless directory listings are enabled with [`ServeDir::list_directories`] or
+    /// [`ServeDir::render_directories_with`]. Hidden entries can still be requested directly.
+    pub fn hide_entries_with<H>(mut self, hide: H) -> Self
+    where
+        H: Fn(&DirectoryEntry) -> bool + Send + Sync + 'static,
+    {
+        self.directory_listing.hide = Some(Arc::new(hide));
+        self
+    }
+
+    /// Set how `ETag` headers are generated.
+    ///
+    /// Requests with an `If-None-Match` header matching the `ETag` receive a
+    /// `304 Not Modified` response. Defaults to [`ETagMode::Metadata`].
+    pub fn etag_mode(mut self, mode: ETagMode) -> Self {
+        self.etag_mode = mode;
+        self
+    }
+
+    /// Cache the contents of small files in memory.
+    ///
+    /// Files of at most `max_file_size` bytes are kept in a least recently used cache holding at
+    /// most `max_size` bytes in total. Cached contents are served from memory, but the metadata of
+    /// files is still checked on every request and the contents are read again as soon as the
+    /// modification time or size of the file changes. Files without a modification time are never
+    /// cached.
+    ///
+    /// The cache is shared between clones of the `ServeDir`.
+    pub fn with_file_cache(mut self, max_size: usize, max_file_size: usize) -> Self {
+        self.file_cache = Some(FileCache::new(max_size, max_file_size));
+        self
+    }
+
+    /// Set the policy for symlinks, dotfiles and paths trying to escape the directory.
+    ///
+    /// See [`PathPolicy`] for the defaults.
+    pub fn path_policy(mut self, policy: PathPolicy) -> Self {
+        self.path_policy = policy;
+        self
+    }
+
+    /// Set how the `Content-Type` of files is determined.
+    ///
+    /// By default the type is guessed from the file extension, using `application/octet-stream`
+    /// for unknown extensions. Use [`MimeTypes`] to override the type of specific extensions.
+    pub fn mime_guesser<G>(mut self, guesser: G) -> Self
+    where
+        G: MimeGuesser + Send + Sync + 'static,
+    {
+        self.mime_guesser = Some(SharedMimeGuesser(Arc::new(guesser)));
+        self
+    }
+
+    /// Serve the index document of a single-page app for paths that don't match a file.
+    ///
+    /// Requests for missing files whose last path segment has no extension, such as
+    /// `/users/42`, receive the document at `index` relative to the served directory with
+    /// `200 OK`, so the app can handle the route on the client. Requests that look like
+    /// assets, such as `/app.js`, still call the fallback or respond with `404 Not Found`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("dist").with_spa_fallback("index.html");
+    /// ```
+    pub fn with_spa_fallback<P>(mut self, index: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.spa_fallback = Some(self.base.join(index));
+        self
+    }
+
+    /// Refuse to serve paths matching the glob `pattern`, handling them like missing files.
+    ///
+    /// Patterns are matched against the requested path relative to the served directory. `*`
+    /// matches any characters within a path segment, `?` matches a single character and `**`
+    /// matches any number of segments. Patterns without a `/`, such as `*.map`, match file or
+    /// directory names at any depth. Excluded entries are also hidden from directory listings.
+    ///
+    /// Use [`PathPolicy::deny_dotfiles`] to exclude dotfiles.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("assets")
+    ///     .exclude("*.map")
+    ///     .exclude("secret/**");
+    /// ```
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.excludes.push(pattern);
+        self
+    }
+
+    /// Cache the metadata and content types of files for `ttl`.
+    ///
+    /// This avoids reading metadata from the [`Filesystem`] for every request to the same
+    /// paths. Files that don't exist aren't cached. Requests with matching `If-None-Match` or
+    /// `If-Modified-Since` headers are answered with `304 Not Modified` without accessing the
+    /// filesystem, unless [`ETagMode::ContentHash`] is used without
+    /// [`ServeDir::with_file_cache`]. Changes to files may take up to `ttl` to be noticed.
+    ///
+    /// At most `max_entries` paths are cached, evicting the least recently used ones. The cache
+    /// is shared between clones of the `ServeDir`.
+    pub fn with_metadata_cache(mut self, ttl: Duration, max_entries: usize) -> Self {
+        self.metadata_cache = Some(MetadataCache::new(ttl, max_entries));
+        self
+    }
+
+    /// Look up files that don't exist in the previous directories in `path`.
+    ///
+    /// Directories are searched in the order they were added, starting with the one passed to
+    /// [`ServeDir::new`], and the first file found is served. This can be used to overlay a
+    /// directory over another one with defaults. Directory listings only contain the entries
+    /// of the first directory found.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// // serve files from `theme`, and from `defaults` if they are missing there
+    /// let service = ServeDir::new("theme").add_root("defaults");
+    /// ```
+    pub fn add_root<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let mut root = PathBuf::from(".");
+        root.push(path.as_ref());
+        Arc::make_mut(&mut self.other_roots).push(root);
+        self
+    }
+
+    /// Set the [`Filesystem`] files are read from.
+    ///
+    /// Defaults to [`TokioFilesystem`], which reads files from disk.
+    pub fn filesystem<FS>(mut self, filesystem: FS) -> Self
+    where
+        FS: Filesystem + 'static,
+    {
+        self.filesystem = SharedFilesystem(Arc::new(filesystem));
+        self
+    }
+
     /// Set the fallback service.
     ///
     /// This service will be called if there is no file at the path of the request.
@@ -234,6 +492,16 @@ impl<F> ServeDir<F> {
             variant: self.variant,
             fallback: Some(new_fallback),
             call_fallback_on_method_not_allowed: self.call_fallback_on_method_not_allowed,
+            etag_mode: self.etag_mode,
+            directory_listing: self.directory_listing,
+            file_cache: self.file_cache,
+            path_policy: self.path_policy,
+            mime_guesser: self.mime_guesser,
+            spa_fallback: self.spa_fallback,
+            excludes: self.excludes,
+            filesystem: self.filesystem,
+            other_roots: self.other_roots,
+            metadata_cache: self.metadata_cache,
         }
     }
 
@@ -376,21 +644,24 @@ impl<F> ServeDir<F> {
             *fallback_req.headers_mut() = req.headers().clone();
             *fallback_req.extensions_mut() = extensions;
 
-            // get the ready fallback and leave a non-ready clone in its place
-            let clone = fallback.clone();
-            let fallback = std::mem::replace(fallback, clone);
+            let fallback = take_ready(fallback);
 
             (fallback, fallback_req)
         });
 
-        let path_to_file = match self
-            .variant
-            .build_and_validate_path(&self.base, req.uri().path())
-        {
-            Some(path_to_file) => path_to_file,
-            None => {
+        let path_to_file = match self.variant.build_and_validate_path(
+            &self.base,
+            req.uri().path(),
+            &self.path_policy,
+            &self.excludes,
+        ) {
+            Ok(path_to_file) => path_to_file,
+            Err(PathRejection::NotFound) => {
                 return ResponseFuture::invalid_path(fallback_and_request);
             }
+            Err(rejection) => {
+                return ResponseFuture::path_rejected(rejection);
+            }
         };
 
         let buf_chunk_size = self.buf_chunk_size;
@@ -407,13 +678,31 @@ impl<F> ServeDir<F> {
 
         let variant = self.variant.clone();
 
+        let options = open_file::OpenFileOptions {
+            buf_chunk_size,
+            // the response depends on `Accept-Encoding` if there are precompressed variants
+            vary_accept_encoding: self.precompressed_variants.is_some(),
+            etag_mode: self.etag_mode,
+            directory_listing: Some(self.directory_listing.clone())
+                .filter(|directory_listing| directory_listing.enabled),
+            cache: self.file_cache.clone(),
+            base: self.base.clone(),
+            path_policy: self.path_policy,
+            mime_guesser: self.mime_guesser.clone(),
+            spa_fallback: self.spa_fallback.clone(),
+            excludes: self.excludes.clone(),
+            filesystem: self.filesystem.clone(),
+            other_roots: self.other_roots.clone(),
+            metadata_cache: self.metadata_cache.clone(),
+        };
+
         let open_file_future = Box::pin(open_file::open_file(
             variant,
             path_to_file,
             req,
             negotiated_encodings,
             range_header,
-            buf_chunk_size,
+            options,
         ));
 
         ResponseFuture::open_file_future(open_file_future, fallback_and_request)
@@ -483,14 +772,22 @@ enum ServeVariant {
 }
 
 impl ServeVariant {
-    fn build_and_validate_path(&self, base_path: &Path, requested_path: &str) -> Option<PathBuf> {
+    fn build_and_validate_path(
+        &self,
+        base_path: &Path,
+        requested_path: &str,
+        policy: &PathPolicy,
+        excludes: &Excludes,
+    ) -> Result<PathBuf, PathRejection> {
         match self {
             ServeVariant::Directory {
                 append_index_html_on_directories: _,
             } => {
                 let path = requested_path.trim_start_matches('/');
 
-                let path_decoded = percent_decode(path.as_ref()).decode_utf8().ok()?;
+                let path_decoded = percent_decode(path.as_ref())
+                    .decode_utf8()
+                    .map_err(|_| PathRejection::NotFound)?;
                 let path_decoded = Path::new(&*path_decoded);
 
                 let mut path_to_file = base_path.to_path_buf();
@@ -502,20 +799,28 @@ impl ServeVariant {
                                 .components()
                                 .all(|c| matches!(c, Component::Normal(_)))
                             {
+                                if let Some(rejection) = policy.dotfiles() {
+                                    if comp.to_string_lossy().starts_with('.') {
+                                        return Err(rejection);
+                                    }
+                                }
                                 path_to_file.push(comp)
                             } else {
-                                return None;
+                                return Err(policy.escape());
                             }
                         }
                         Component::CurDir => {}
                         Component::Prefix(_) | Component::RootDir | Component::ParentDir => {
-                            return None;
+                            return Err(policy.escape());
                         }
                     }
                 }
-                Some(path_to_file)
+                if excludes.is_excluded(path_decoded) {
+                    return Err(PathRejection::NotFound);
+                }
+                Ok(path_to_file)
             }
-            ServeVariant::SingleFile { mime: _ } => Some(base_path.to_path_buf()),
+            ServeVariant::SingleFile { mime: _ } => Ok(base_path.to_path_buf()),
         }
     }
 }
@@ -547,6 +852,21 @@ where
     }
 }
 
+/// How [`ServeDir`] and [`ServeFile`][super::ServeFile] generate `ETag` headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ETagMode {
+    /// Generate weak `ETag`s from the size and modification time of the file.
+    #[default]
+    Metadata,
+    /// Generate strong `ETag`s from a hash of the contents of the file.
+    ///
+    /// This reads the whole file for every request, so is best suited for small files. Strong
+    /// `ETag`s can be used with `If-Range`.
+    ContentHash,
+    /// Don't generate `ETag`s.
+    Disabled,
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 struct PrecompressedVariants {
     gzip: bool,
@@ -1,27 +1,43 @@
 use super::{
-    headers::{IfModifiedSince, IfUnmodifiedSince, LastModified},
-    ServeVariant,
+    cache::FileCache,
+    exclude::Excludes,
+    filesystem::SharedFilesystem,
+    headers::{ETag, IfModifiedSince, IfNoneMatch, IfRange, IfUnmodifiedSince, LastModified},
+    listing::ListingOptions,
+    metadata_cache::MetadataCache,
+    mime_types::SharedMimeGuesser,
+    ETagMode, FileMetadata, FileReader, Filesystem, PathPolicy, PathRejection, ServeVariant,
+};
+use crate::{
+    content_encoding::{Encoding, QValue},
+    fnv::{self, fnv1a_update},
 };
-use crate::content_encoding::{Encoding, QValue};
 use bytes::Bytes;
-use http::{header, HeaderValue, Method, Request, Uri};
+use http::{header, HeaderValue, Method, Request, Response, Uri};
 use http_body::Empty;
 use http_range_header::RangeUnsatisfiableError;
 use std::{
     ffi::OsStr,
-    fs::Metadata,
-    io::{self, SeekFrom},
+    io,
     ops::RangeInclusive,
     path::{Path, PathBuf},
+    sync::Arc,
 };
-use tokio::{fs::File, io::AsyncSeekExt};
+use tokio::io::AsyncReadExt;
 
 pub(super) enum OpenFileOutput {
     FileOpened(Box<FileOpened>),
-    Redirect { location: HeaderValue },
+    DirectoryListing(Response<Bytes>),
+    Redirect {
+        location: HeaderValue,
+    },
     FileNotFound,
+    PathRejected(PathRejection),
     PreconditionFailed,
-    NotModified,
+    NotModified {
+        last_modified: Option<LastModified>,
+        etag: Option<ETag>,
+    },
 }
 
 pub(super) struct FileOpened {
@@ -31,21 +47,159 @@ pub(super) struct FileOpened {
     pub(super) maybe_encoding: Option<Encoding>,
     pub(super) maybe_range: Option<Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>>,
     pub(super) last_modified: Option<LastModified>,
+    pub(super) etag: Option<ETag>,
+    pub(super) vary_accept_encoding: bool,
 }
 
 pub(super) enum FileRequestExtent {
-    Full(File, Metadata),
-    Head(Metadata),
+    Full(FileReader, FileMetadata),
+    Memory(Bytes, FileMetadata),
+    Head(FileMetadata),
+}
+
+pub(super) struct OpenFileOptions {
+    pub(super) buf_chunk_size: usize,
+    pub(super) vary_accept_encoding: bool,
+    pub(super) etag_mode: ETagMode,
+    pub(super) directory_listing: Option<ListingOptions>,
+    pub(super) cache: Option<FileCache>,
+    pub(super) base: PathBuf,
+    pub(super) other_roots: Arc<Vec<PathBuf>>,
+    pub(super) path_policy: PathPolicy,
+    pub(super) mime_guesser: Option<SharedMimeGuesser>,
+    pub(super) spa_fallback: Option<PathBuf>,
+    pub(super) excludes: Excludes,
+    pub(super) filesystem: SharedFilesystem,
+    pub(super) metadata_cache: Option<MetadataCache>,
 }
 
 pub(super) async fn open_file(
     variant: ServeVariant,
-    mut path_to_file: PathBuf,
+    path_to_file: PathBuf,
     req: Request<Empty<Bytes>>,
     negotiated_encodings: Vec<(Encoding, QValue)>,
     range_header: Option<String>,
-    buf_chunk_size: usize,
+    options: OpenFileOptions,
+) -> io::Result<OpenFileOutput> {
+    let mut result = open_file_at(
+        variant.clone(),
+        &options.base,
+        path_to_file.clone(),
+        &req,
+        negotiated_encodings.clone(),
+        range_header.clone(),
+        &options,
+    )
+    .await;
+
+    // look up missing files in the other roots in order
+    if let Ok(relative) = path_to_file.strip_prefix(&options.base) {
+        for root in options.other_roots.iter() {
+            if !is_not_found(&result) {
+                break;
+            }
+            result = open_file_at(
+                variant.clone(),
+                root,
+                root.join(relative),
+                &req,
+                negotiated_encodings.clone(),
+                range_header.clone(),
+                &options,
+            )
+            .await;
+        }
+    }
+
+    let spa_fallback = match &options.spa_fallback {
+        Some(index) if is_not_found(&result) && is_spa_route(req.uri().path()) => index.clone(),
+        _ => return result,
+    };
+
+    // serve the index document of the single-page app as if it had been requested
+    let variant = ServeVariant::SingleFile {
+        mime: guess_mime(&spa_fallback, &options),
+    };
+    open_file_at(
+        variant,
+        &options.base,
+        spa_fallback,
+        &req,
+        negotiated_encodings,
+        range_header,
+        &options,
+    )
+    .await
+}
+
+fn is_not_found(result: &io::Result<OpenFileOutput>) -> bool {
+    match result {
+        Ok(OpenFileOutput::FileNotFound) => true,
+        Err(err) => is_not_found_error(err),
+        _ => false,
+    }
+}
+
+/// Whether a request path could be a client-side route, rather than an asset such as
+/// `/app.js`.
+fn is_spa_route(path: &str) -> bool {
+    let last_segment = path.rsplit('/').next().unwrap_or_default();
+    !last_segment.contains('.')
+}
+
+/// Whether `err` means that there is no file to serve at the requested path.
+pub(super) fn is_not_found_error(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    // 20 = libc::ENOTDIR => "not a directory
+    // when `io_error_more` landed, this can be changed
+    // to checking for `io::ErrorKind::NotADirectory`.
+    // https://github.com/rust-lang/rust/issues/86442
+    let error_is_not_a_directory = err.raw_os_error() == Some(20);
+    #[cfg(not(unix))]
+    let error_is_not_a_directory = false;
+
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+    ) || error_is_not_a_directory
+}
+
+fn guess_mime(path: &Path, options: &OpenFileOptions) -> HeaderValue {
+    let guess = || {
+        let guess = match &options.mime_guesser {
+            Some(guesser) => guesser.0.guess(path),
+            None => mime_guess::from_path(path)
+                .first_raw()
+                .map(HeaderValue::from_static),
+        };
+        guess.unwrap_or_else(|| {
+            HeaderValue::from_str(mime::APPLICATION_OCTET_STREAM.as_ref()).unwrap()
+        })
+    };
+    match &options.metadata_cache {
+        Some(cache) => cache.content_type(path, guess),
+        None => guess(),
+    }
+}
+
+async fn open_file_at(
+    variant: ServeVariant,
+    base: &Path,
+    mut path_to_file: PathBuf,
+    req: &Request<Empty<Bytes>>,
+    negotiated_encodings: Vec<(Encoding, QValue)>,
+    range_header: Option<String>,
+    options: &OpenFileOptions,
 ) -> io::Result<OpenFileOutput> {
+    let cached_metadata;
+    let fs: &dyn Filesystem = match &options.metadata_cache {
+        Some(cache) => {
+            cached_metadata = cache.filesystem(&*options.filesystem.0);
+            &cached_metadata
+        }
+        None => &*options.filesystem.0,
+    };
+
     let if_unmodified_since = req
         .headers()
         .get(header::IF_UNMODIFIED_SINCE)
@@ -56,6 +210,14 @@ pub(super) async fn open_file(
         .get(header::IF_MODIFIED_SINCE)
         .and_then(IfModifiedSince::from_header_value);
 
+    let if_none_match =
+        IfNoneMatch::from_header_values(req.headers().get_all(header::IF_NONE_MATCH));
+
+    let if_range = req
+        .headers()
+        .get(header::IF_RANGE)
+        .map(IfRange::from_header_value);
+
     let mime = match variant {
         ServeVariant::Directory {
             append_index_html_on_directories,
@@ -63,91 +225,115 @@ pub(super) async fn open_file(
             // Might already at this point know a redirect or not found result should be
             // returned which corresponds to a Some(output). Otherwise the path might be
             // modified and proceed to the open file/metadata future.
+            if let Some(rejection) = options.path_policy.check(fs, base, &path_to_file).await? {
+                return Ok(OpenFileOutput::PathRejected(rejection));
+            }
             if let Some(output) = maybe_redirect_or_append_path(
+                fs,
+                base,
                 &mut path_to_file,
-                req.uri(),
+                req,
                 append_index_html_on_directories,
+                options,
             )
-            .await
+            .await?
             {
                 return Ok(output);
             }
 
-            mime_guess::from_path(&path_to_file)
-                .first_raw()
-                .map(HeaderValue::from_static)
-                .unwrap_or_else(|| {
-                    HeaderValue::from_str(mime::APPLICATION_OCTET_STREAM.as_ref()).unwrap()
-                })
+            guess_mime(&path_to_file, options)
         }
 
         ServeVariant::SingleFile { mime } => mime,
     };
 
-    if req.method() == Method::HEAD {
-        let (meta, maybe_encoding) =
-            file_metadata_with_fallback(path_to_file, negotiated_encodings).await?;
-
-        let last_modified = meta.modified().ok().map(LastModified::from);
-        if let Some(output) = check_modified_headers(
-            last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
-        ) {
-            return Ok(output);
-        }
-
-        let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
-
-        Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
-            extent: FileRequestExtent::Head(meta),
-            chunk_size: buf_chunk_size,
-            mime_header_value: mime,
-            maybe_encoding,
-            maybe_range,
-            last_modified,
-        })))
-    } else {
-        let (mut file, maybe_encoding) =
-            open_file_with_fallback(path_to_file, negotiated_encodings).await?;
-        let meta = file.metadata().await?;
-        let last_modified = meta.modified().ok().map(LastModified::from);
-        if let Some(output) = check_modified_headers(
-            last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
-        ) {
-            return Ok(output);
+    let (meta, maybe_encoding, path_to_file) =
+        file_metadata_with_fallback(fs, path_to_file, negotiated_encodings).await?;
+    // the checked path may have changed to `index.html` or a precompressed variant
+    if let Some(rejection) = options.path_policy.check(fs, base, &path_to_file).await? {
+        return Ok(OpenFileOutput::PathRejected(rejection));
+    }
+    let last_modified = meta.modified().map(LastModified::from);
+
+    // Hashing the contents requires reading the file, even for `HEAD` requests.
+    let hash_contents = options.etag_mode == ETagMode::ContentHash;
+    let cached = match &options.cache {
+        Some(cache) if cache.accepts(&meta) && (req.method() != Method::HEAD || hash_contents) => {
+            Some(
+                cache
+                    .get_or_load(fs, &path_to_file, &meta, hash_contents)
+                    .await?,
+            )
         }
+        _ => None,
+    };
 
-        let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
-        if let Some(Ok(ranges)) = maybe_range.as_ref() {
-            // if there is any other amount of ranges than 1 we'll return an
-            // unsatisfiable later as there isn't yet support for multipart ranges
-            if ranges.len() == 1 {
-                file.seek(SeekFrom::Start(*ranges[0].start())).await?;
+    let etag = match options.etag_mode {
+        ETagMode::Metadata => ETag::from_metadata(&meta),
+        ETagMode::ContentHash => match &cached {
+            Some(cached) => cached.content_hash.map(ETag::from_content_hash),
+            None => {
+                let hash = content_hash(fs, &path_to_file, options.buf_chunk_size).await?;
+                Some(ETag::from_content_hash(hash))
             }
+        },
+        ETagMode::Disabled => None,
+    };
+    if let Some(output) = check_modified_headers(
+        last_modified.as_ref(),
+        etag.as_ref(),
+        if_unmodified_since,
+        if_none_match,
+        if_modified_since,
+    ) {
+        return Ok(output);
+    }
+
+    let range_header = range_header_if_unchanged(
+        range_header,
+        if_range,
+        last_modified.as_ref(),
+        etag.as_ref(),
+    );
+    let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
+
+    let extent = match cached {
+        _ if req.method() == Method::HEAD => FileRequestExtent::Head(meta),
+        Some(cached) => FileRequestExtent::Memory(cached.contents, meta),
+        None => {
+            let range = match maybe_range.as_ref() {
+                // if there is any other amount of ranges than 1 we'll return an
+                // unsatisfiable later as there isn't yet support for multipart ranges
+                Some(Ok(ranges)) if ranges.len() == 1 => {
+                    Some(*ranges[0].start()..*ranges[0].end() + 1)
+                }
+                _ => None,
+            };
+            FileRequestExtent::Full(fs.read(&path_to_file, range).await?, meta)
         }
+    };
 
-        Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
-            extent: FileRequestExtent::Full(file, meta),
-            chunk_size: buf_chunk_size,
-            mime_header_value: mime,
-            maybe_encoding,
-            maybe_range,
-            last_modified,
-        })))
-    }
+    Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
+        extent,
+        chunk_size: options.buf_chunk_size,
+        mime_header_value: mime,
+        maybe_encoding,
+        maybe_range,
+        last_modified,
+        etag,
+        vary_accept_encoding: options.vary_accept_encoding,
+    })))
 }
 
 fn check_modified_headers(
-    modified: Option<&LastModified>,
+    last_modified: Option<&LastModified>,
+    etag: Option<&ETag>,
     if_unmodified_since: Option<IfUnmodifiedSince>,
+    if_none_match: Option<IfNoneMatch>,
     if_modified_since: Option<IfModifiedSince>,
 ) -> Option<OpenFileOutput> {
     if let Some(since) = if_unmodified_since {
-        let precondition = modified
-            .as_ref()
+        let precondition = last_modified
             .map(|time| since.precondition_passes(time))
             .unwrap_or(false);
 
@@ -156,20 +342,43 @@ fn check_modified_headers(
         }
     }
 
-    if let Some(since) = if_modified_since {
-        let unmodified = modified
-            .as_ref()
+    let unmodified = if let Some(if_none_match) = if_none_match {
+        // `If-Modified-Since` is ignored when `If-None-Match` is present
+        if_none_match.matches(etag)
+    } else if let Some(since) = if_modified_since {
+        last_modified
             .map(|time| !since.is_modified(time))
             // no last_modified means its always modified
-            .unwrap_or(false);
-        if unmodified {
-            return Some(OpenFileOutput::NotModified);
-        }
+            .unwrap_or(false)
+    } else {
+        false
+    };
+
+    if unmodified {
+        return Some(OpenFileOutput::NotModified {
+            last_modified: last_modified.cloned(),
+            etag: etag.cloned(),
+        });
     }
 
     None
 }
 
+/// Hash the contents of the file with 64 bit FNV-1a.
+async fn content_hash(fs: &dyn Filesystem, path: &Path, chunk_size: usize) -> io::Result<u64> {
+    let mut file = fs.read(path, None).await?;
+    let mut hash = fnv::OFFSET_BASIS;
+    let mut buf = vec![0; chunk_size.max(1)];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hash = fnv1a_update(hash, &buf[..n]);
+    }
+    Ok(hash)
+}
+
 // Returns the preferred_encoding encoding and modifies the path extension
 // to the corresponding file extension for the encoding.
 fn preferred_encoding(
@@ -196,44 +405,18 @@ fn preferred_encoding(
     preferred_encoding
 }
 
-// Attempts to open the file with any of the possible negotiated_encodings in the
-// preferred order. If none of the negotiated_encodings have a corresponding precompressed
-// file the uncompressed file is used as a fallback.
-async fn open_file_with_fallback(
-    mut path: PathBuf,
-    mut negotiated_encoding: Vec<(Encoding, QValue)>,
-) -> io::Result<(File, Option<Encoding>)> {
-    let (file, encoding) = loop {
-        // Get the preferred encoding among the negotiated ones.
-        let encoding = preferred_encoding(&mut path, &negotiated_encoding);
-        match (File::open(&path).await, encoding) {
-            (Ok(file), maybe_encoding) => break (file, maybe_encoding),
-            (Err(err), Some(encoding)) if err.kind() == io::ErrorKind::NotFound => {
-                // Remove the extension corresponding to a precompressed file (.gz, .br, .zz)
-                // to reset the path before the next iteration.
-                path.set_extension(OsStr::new(""));
-                // Remove the encoding from the negotiated_encodings since the file doesn't exist
-                negotiated_encoding
-                    .retain(|(negotiated_encoding, _)| *negotiated_encoding != encoding);
-                continue;
-            }
-            (Err(err), _) => return Err(err),
-        };
-    };
-    Ok((file, encoding))
-}
-
 // Attempts to get the file metadata with any of the possible negotiated_encodings in the
 // preferred order. If none of the negotiated_encodings have a corresponding precompressed
 // file the uncompressed file is used as a fallback.
 async fn file_metadata_with_fallback(
+    fs: &dyn Filesystem,
     mut path: PathBuf,
     mut negotiated_encoding: Vec<(Encoding, QValue)>,
-) -> io::Result<(Metadata, Option<Encoding>)> {
+) -> io::Result<(FileMetadata, Option<Encoding>, PathBuf)> {
     let (file, encoding) = loop {
         // Get the preferred encoding among the negotiated ones.
         let encoding = preferred_encoding(&mut path, &negotiated_encoding);
-        match (tokio::fs::metadata(&path).await, encoding) {
+        match (fs.metadata(&path).await, encoding) {
             (Ok(file), maybe_encoding) => break (file, maybe_encoding),
             (Err(err), Some(encoding)) if err.kind() == io::ErrorKind::NotFound => {
                 // Remove the extension corresponding to a precompressed file (.gz, .br, .zz)
@@ -247,31 +430,65 @@ async fn file_metadata_with_fallback(
             (Err(err), _) => return Err(err),
         };
     };
-    Ok((file, encoding))
+    Ok((file, encoding, path))
 }
 
 async fn maybe_redirect_or_append_path(
+    fs: &dyn Filesystem,
+    base: &Path,
     path_to_file: &mut PathBuf,
-    uri: &Uri,
+    req: &Request<Empty<Bytes>>,
     append_index_html_on_directories: bool,
-) -> Option<OpenFileOutput> {
+    options: &OpenFileOptions,
+) -> io::Result<Option<OpenFileOutput>> {
+    let directory_listing = options.directory_listing.as_ref();
+    let uri = req.uri();
     if !uri.path().ends_with('/') {
-        if is_dir(path_to_file).await {
+        if is_dir(fs, path_to_file).await {
             let location =
                 HeaderValue::from_str(&append_slash_on_path(uri.clone()).to_string()).unwrap();
-            Some(OpenFileOutput::Redirect { location })
+            Ok(Some(OpenFileOutput::Redirect { location }))
         } else {
-            None
+            Ok(None)
         }
-    } else if is_dir(path_to_file).await {
+    } else if is_dir(fs, path_to_file).await {
         if append_index_html_on_directories {
-            path_to_file.push("index.html");
-            None
+            let index = path_to_file.join("index.html");
+            if directory_listing.is_none() || is_file(fs, &index).await {
+                *path_to_file = index;
+                return Ok(None);
+            }
+        }
+
+        if let Some(directory_listing) = directory_listing {
+            let relative_dir = path_to_file.strip_prefix(base).unwrap_or(path_to_file);
+            let is_excluded = |name: &str| options.excludes.is_excluded(&relative_dir.join(name));
+            let mut res = directory_listing
+                .list(fs, path_to_file, uri.path(), req.headers(), &is_excluded)
+                .await?;
+            if req.method() == Method::HEAD {
+                *res.body_mut() = Bytes::new();
+            }
+            Ok(Some(OpenFileOutput::DirectoryListing(res)))
         } else {
-            Some(OpenFileOutput::FileNotFound)
+            Ok(Some(OpenFileOutput::FileNotFound))
         }
     } else {
-        None
+        Ok(None)
+    }
+}
+
+/// Ignore the range header if `If-Range` is present and the file has changed since, in which case
+/// the full file should be sent.
+fn range_header_if_unchanged(
+    range_header: Option<String>,
+    if_range: Option<IfRange>,
+    last_modified: Option<&LastModified>,
+    etag: Option<&ETag>,
+) -> Option<String> {
+    match if_range {
+        Some(if_range) if !if_range.matches(last_modified, etag) => None,
+        _ => range_header,
     }
 }
 
@@ -285,12 +502,18 @@ fn try_parse_range(
     })
 }
 
-async fn is_dir(path_to_file: &Path) -> bool {
-    tokio::fs::metadata(path_to_file)
+async fn is_dir(fs: &dyn Filesystem, path_to_file: &Path) -> bool {
+    fs.metadata(path_to_file)
         .await
         .map_or(false, |meta_data| meta_data.is_dir())
 }
 
+async fn is_file(fs: &dyn Filesystem, path_to_file: &Path) -> bool {
+    fs.metadata(path_to_file)
+        .await
+        .map_or(false, |meta_data| meta_data.is_file())
+}
+
 fn append_slash_on_path(uri: Uri) -> Uri {
     let http::uri::Parts {
         scheme,
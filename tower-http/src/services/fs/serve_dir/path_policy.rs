@@ -0,0 +1,171 @@
+use super::Filesystem;
+use http::StatusCode;
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+/// Controls how [`ServeDir`][super::ServeDir] handles paths that could reach files outside of
+/// the directory being served.
+///
+/// The default policy follows symlinks wherever they point, serves dotfiles and treats paths
+/// such as `/../secret` as not found, which means the fallback is called if one is set.
+///
+/// # Example
+///
+/// ```
+/// use tower_http::services::{
+///     fs::{PathPolicy, PathRejection},
+///     ServeDir,
+/// };
+///
+/// let service = ServeDir::new("assets").path_policy(
+///     PathPolicy::new()
+///         .follow_symlinks(false)
+///         .deny_dotfiles(PathRejection::Forbidden)
+///         .on_escape(PathRejection::BadRequest),
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathPolicy {
+    follow_symlinks: bool,
+    confine_to_root: bool,
+    dotfiles: Option<PathRejection>,
+    escape: PathRejection,
+}
+
+/// How [`ServeDir`][super::ServeDir] responds to a request rejected by its [`PathPolicy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathRejection {
+    /// Handle the request like a missing file, calling the fallback if one is set.
+    #[default]
+    NotFound,
+    /// Respond with `403 Forbidden`.
+    Forbidden,
+    /// Respond with `400 Bad Request`.
+    BadRequest,
+}
+
+impl PathRejection {
+    pub(super) fn status(self) -> StatusCode {
+        match self {
+            PathRejection::NotFound => StatusCode::NOT_FOUND,
+            PathRejection::Forbidden => StatusCode::FORBIDDEN,
+            PathRejection::BadRequest => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PathPolicy {
+    /// Create the default [`PathPolicy`].
+    pub fn new() -> Self {
+        Self {
+            follow_symlinks: true,
+            confine_to_root: false,
+            dotfiles: None,
+            escape: PathRejection::NotFound,
+        }
+    }
+
+    /// Whether to follow symlinks below the served directory.
+    ///
+    /// If `false`, requests for paths that contain a symlink are rejected with the
+    /// [`PathPolicy::on_escape`] response. Defaults to `true`.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Whether requested files must stay under the served directory after resolving symlinks.
+    ///
+    /// If `true`, requests for paths that resolve to a location outside of the served directory
+    /// are rejected with the [`PathPolicy::on_escape`] response. Defaults to `false`.
+    pub fn confine_to_root(mut self, confine: bool) -> Self {
+        self.confine_to_root = confine;
+        self
+    }
+
+    /// Reject requests for paths with a component starting with `.`, such as `/.env` or
+    /// `/.git/config`.
+    ///
+    /// Dotfiles are served by default.
+    pub fn deny_dotfiles(mut self, rejection: PathRejection) -> Self {
+        self.dotfiles = Some(rejection);
+        self
+    }
+
+    /// Set the response to requests trying to escape the served directory.
+    ///
+    /// This applies to paths containing `..` or absolute components, and to paths rejected by
+    /// [`PathPolicy::follow_symlinks`] or [`PathPolicy::confine_to_root`]. Defaults to
+    /// [`PathRejection::NotFound`].
+    pub fn on_escape(mut self, rejection: PathRejection) -> Self {
+        self.escape = rejection;
+        self
+    }
+
+    pub(super) fn escape(&self) -> PathRejection {
+        self.escape
+    }
+
+    pub(super) fn dotfiles(&self) -> Option<PathRejection> {
+        self.dotfiles
+    }
+
+    fn checks_file_system(&self) -> bool {
+        !self.follow_symlinks || self.confine_to_root
+    }
+
+    /// Check the symlinks of `path`, which must be `base` joined with a relative path.
+    ///
+    /// Paths that don't exist pass so opening them fails as usual.
+    pub(super) async fn check(
+        &self,
+        fs: &dyn Filesystem,
+        base: &Path,
+        path: &Path,
+    ) -> io::Result<Option<PathRejection>> {
+        if !self.checks_file_system() {
+            return Ok(None);
+        }
+
+        if !self.follow_symlinks {
+            let relative = match path.strip_prefix(base) {
+                Ok(relative) => relative,
+                Err(_) => return Ok(Some(self.escape)),
+            };
+            let mut current = base.to_path_buf();
+            for component in relative.components() {
+                if let Component::Normal(component) = component {
+                    current.push(component);
+                    match fs.is_symlink(&current).await {
+                        Ok(true) => return Ok(Some(self.escape)),
+                        Ok(false) => {}
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+        }
+
+        if self.confine_to_root {
+            let resolved = match fs.canonicalize(path).await {
+                Ok(resolved) => resolved,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let base = fs.canonicalize(base).await?;
+            if !resolved.starts_with(base) {
+                return Ok(Some(self.escape));
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,534 @@
+use super::{DirectoryEntry, FileMetadata, FileReader, Filesystem};
+use futures_util::future::{self, BoxFuture, FutureExt};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+const BLOCK_SIZE: u64 = 512;
+
+/// A [`Filesystem`] serving the entries of a tar archive.
+///
+/// The archive is indexed once when opened, and entries are read directly from the archive
+/// file, including ranges. Regular files and directories are supported, other entries such as
+/// links are ignored. The archive must not be modified while it is being served.
+///
+/// Paths are looked up relative to the root of the archive, ignoring `.` components, so the
+/// archive can be served with `ServeDir::new("")`.
+///
+/// # Example
+///
+/// ```no_run
+/// use tower_http::services::{fs::TarFilesystem, ServeDir};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let service = ServeDir::new("").filesystem(TarFilesystem::open("assets.tar")?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TarFilesystem {
+    archive: Arc<PathBuf>,
+    entries: Arc<HashMap<PathBuf, TarEntry>>,
+}
+
+#[derive(Clone, Debug)]
+struct TarEntry {
+    offset: u64,
+    metadata: FileMetadata,
+}
+
+impl TarFilesystem {
+    /// Open and index the tar archive at `path`.
+    ///
+    /// This reads the headers of all entries with blocking IO, so it should be called at
+    /// startup.
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let entries = index(File::open(path)?)?;
+        Ok(Self {
+            archive: Arc::new(path.to_owned()),
+            entries: Arc::new(entries),
+        })
+    }
+
+    fn entry(&self, path: &Path) -> io::Result<&TarEntry> {
+        normalize(path)
+            .and_then(|path| self.entries.get(&path))
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+impl Filesystem for TarFilesystem {
+    fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+        future::ready(self.entry(path).map(|entry| entry.metadata.clone())).boxed()
+    }
+
+    fn read<'a>(
+        &'a self,
+        path: &'a Path,
+        range: Option<Range<u64>>,
+    ) -> BoxFuture<'a, io::Result<FileReader>> {
+        async move {
+            let entry = self.entry(path)?;
+            if entry.metadata.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::Other, "is a directory"));
+            }
+            let range = range.unwrap_or(0..entry.metadata.len());
+            let mut file = tokio::fs::File::open(&*self.archive).await?;
+            file.seek(SeekFrom::Start(entry.offset + range.start))
+                .await?;
+            let reader: FileReader = Box::pin(file.take(range.end - range.start));
+            Ok(reader)
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Vec<DirectoryEntry>>> {
+        let entries = self.entry(path).and_then(|dir| {
+            if !dir.metadata.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::Other, "not a directory"));
+            }
+            let dir = normalize(path).unwrap_or_default();
+            Ok(self
+                .entries
+                .iter()
+                .filter(|(path, _)| path.parent() == Some(&*dir))
+                .filter_map(|(path, entry)| {
+                    let name = path.file_name()?.to_str()?.to_owned();
+                    Some(DirectoryEntry::new(name, &entry.metadata))
+                })
+                .collect())
+        });
+        future::ready(entries).boxed()
+    }
+}
+
+/// Turn `path` into a key of the index, or `None` if it can't be in the archive.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(component) => normalized.push(component),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+fn index<R>(mut archive: R) -> io::Result<HashMap<PathBuf, TarEntry>>
+where
+    R: Read + Seek,
+{
+    let mut entries = HashMap::new();
+    entries.insert(
+        PathBuf::new(),
+        TarEntry {
+            offset: 0,
+            metadata: FileMetadata::dir(),
+        },
+    );
+
+    // name and modification time from GNU long name or pax headers for the next entry
+    let mut next_name = None;
+    let mut next_modified = None;
+
+    let mut offset = 0;
+    let mut header = [0; BLOCK_SIZE as usize];
+    loop {
+        archive.seek(SeekFrom::Start(offset))?;
+        match archive.read_exact(&mut header) {
+            Ok(()) => {}
+            // archives are supposed to end with two empty blocks but some don't
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        if !checksum_matches(&header) {
+            return Err(invalid_data("invalid tar header checksum"));
+        }
+
+        let size = parse_number(&header[124..136])?;
+        let data_offset = offset + BLOCK_SIZE;
+        offset = size
+            .checked_add(BLOCK_SIZE - 1)
+            .map(|size| size / BLOCK_SIZE)
+            .and_then(|blocks| blocks.checked_mul(BLOCK_SIZE))
+            .and_then(|len| data_offset.checked_add(len))
+            .ok_or_else(|| invalid_data("tar entry size out of range"))?;
+
+        match header[156] {
+            b'L' => {
+                let data = read_data(&mut archive, data_offset, size)?;
+                next_name = Some(PathBuf::from(string(&data)));
+                continue;
+            }
+            b'x' => {
+                let data = read_data(&mut archive, data_offset, size)?;
+                for (key, value) in pax_records(&data) {
+                    match key {
+                        "path" => next_name = Some(PathBuf::from(value)),
+                        "mtime" => next_modified = parse_pax_time(value),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let name = next_name.take().unwrap_or_else(|| header_name(&header));
+        let modified = next_modified
+            .take()
+            .or_else(|| parse_number(&header[136..148]).ok());
+        let modified = match modified.map(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+        {
+            Some(Some(modified)) => Some(modified),
+            // entries with a modification time that can't be represented are skipped
+            Some(None) => continue,
+            None => None,
+        };
+
+        let metadata = match header[156] {
+            b'0' | b'\0' | b'7' => FileMetadata::file(size),
+            b'5' => FileMetadata::dir(),
+            // links and special files aren't served
+            _ => continue,
+        };
+        let metadata = match modified {
+            Some(modified) => metadata.with_modified(modified),
+            None => metadata,
+        };
+
+        let path = match normalize(&name) {
+            Some(path) if path.as_os_str().is_empty() => continue,
+            Some(path) => path,
+            None => continue,
+        };
+        for ancestor in path.ancestors().skip(1) {
+            entries
+                .entry(ancestor.to_owned())
+                .or_insert_with(|| TarEntry {
+                    offset: 0,
+                    metadata: FileMetadata::dir(),
+                });
+        }
+        entries.insert(
+            path,
+            TarEntry {
+                offset: data_offset,
+                metadata,
+            },
+        );
+    }
+
+    Ok(entries)
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn checksum_matches(header: &[u8; BLOCK_SIZE as usize]) -> bool {
+    let expected = match parse_number(&header[148..156]) {
+        Ok(checksum) => checksum,
+        Err(_) => return false,
+    };
+    // the checksum is calculated with the checksum field set to spaces
+    let sum = header
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| {
+            if (148..156).contains(&i) {
+                u64::from(b' ')
+            } else {
+                u64::from(*byte)
+            }
+        })
+        .sum::<u64>();
+    sum == expected
+}
+
+/// Parse an octal number, or a base-256 number used by GNU tar for large values.
+fn parse_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |number, byte| {
+                number
+                    .checked_mul(256)
+                    .map(|number| number + u64::from(*byte))
+            })
+            .ok_or_else(|| invalid_data("tar header number too large"));
+    }
+
+    let digits = string(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data("invalid tar header number"))
+}
+
+fn header_name(header: &[u8; BLOCK_SIZE as usize]) -> PathBuf {
+    let name = string(&header[..100]);
+    // only the POSIX format has a prefix, GNU tar uses the field for other data
+    if &header[257..263] == b"ustar\0" {
+        let prefix = string(&header[345..500]);
+        if !prefix.is_empty() {
+            return Path::new(&prefix).join(name);
+        }
+    }
+    PathBuf::from(name)
+}
+
+/// The string up to the first NUL byte.
+fn string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn read_data<R>(archive: &mut R, offset: u64, size: u64) -> io::Result<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    // extended headers are small, refuse to allocate for corrupt archives
+    if size > 1024 * 1024 {
+        return Err(invalid_data("tar extended header too large"));
+    }
+    archive.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0; size as usize];
+    archive.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Parse pax extended header records of the form `<length> <key>=<value>\n`.
+fn pax_records(data: &[u8]) -> Vec<(&str, &str)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let len = rest
+            .iter()
+            .position(|byte| *byte == b' ')
+            .and_then(|space| std::str::from_utf8(&rest[..space]).ok())
+            .and_then(|len| len.parse::<usize>().ok());
+        let len = match len {
+            Some(len) if len > 0 && len <= rest.len() => len,
+            _ => break,
+        };
+        let record = std::str::from_utf8(&rest[..len])
+            .ok()
+            .and_then(|record| record.split_once(' '))
+            .and_then(|(_, record)| record.strip_suffix('\n'))
+            .and_then(|record| record.split_once('='));
+        records.extend(record);
+        rest = &rest[len..];
+    }
+    records
+}
+
+/// Parse a pax `mtime`, which is `None` if it can't be parsed, or `Some(None)` if the time
+/// can't be represented.
+/// Parse a pax time into seconds since the Unix epoch.
+fn parse_pax_time(value: &str) -> Option<u64> {
+    // fractional seconds are ignored as `Last-Modified` has a resolution of seconds
+    value.split('.').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+        let mut header = vec![0; BLOCK_SIZE as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[136..147].copy_from_slice(b"14000000000");
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        set_checksum(&mut header);
+        header
+    }
+
+    fn set_checksum(header: &mut [u8]) {
+        header[148..156].copy_from_slice(b"        ");
+        let sum = header.iter().map(|byte| u64::from(*byte)).sum::<u64>();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+    }
+
+    /// A header with `field` set to `u64::MAX` in the base-256 encoding.
+    fn header_with_max(name: &str, field: std::ops::Range<usize>) -> Vec<u8> {
+        let mut header = header(name, 0, b'0');
+        let end = field.end;
+        header[field].fill(0);
+        header[end - 12] = 0x80;
+        header[end - 8..end].fill(0xff);
+        set_checksum(&mut header);
+        header
+    }
+
+    fn entry(archive: &mut Vec<u8>, name: &str, data: &[u8], typeflag: u8) {
+        archive.extend(header(name, data.len(), typeflag));
+        archive.extend(data);
+        let padding =
+            (BLOCK_SIZE as usize - data.len() % BLOCK_SIZE as usize) % BLOCK_SIZE as usize;
+        archive.extend(vec![0; padding]);
+    }
+
+    pub(super) fn archive() -> Vec<u8> {
+        let long_name = format!("{}/long.txt", "d".repeat(120));
+        let pax = format!("{} path={}\n", 6 + long_name.len() + 1 + 3, long_name);
+
+        let mut archive = Vec::new();
+        entry(&mut archive, "./", b"", b'5');
+        entry(&mut archive, "./index.html", b"<h1>archive</h1>", b'0');
+        entry(&mut archive, "./assets/app.js", b"console.log(1)", b'0');
+        entry(&mut archive, "./assets/link.js", b"", b'2');
+        entry(&mut archive, "PaxHeader", pax.as_bytes(), b'x');
+        entry(&mut archive, "ignored", b"long", b'0');
+        entry(&mut archive, "../escape.txt", b"escape", b'0');
+        archive.extend(vec![0; 2 * BLOCK_SIZE as usize]);
+        archive
+    }
+
+    #[test]
+    fn pax_records_with_lengths() {
+        let records = pax_records(b"16 path=a b.txt\n13 mtime=1.5\n");
+        assert_eq!(records, [("path", "a b.txt"), ("mtime", "1.5")]);
+        assert_eq!(parse_pax_time("1.5"), Some(1));
+        assert_eq!(parse_pax_time("18446744073709551615"), Some(u64::MAX));
+        assert_eq!(parse_pax_time("soon"), None);
+        assert!(pax_records(b"99 path=truncated\n").is_empty());
+    }
+
+    #[test]
+    fn index_entries() {
+        let entries = index(Cursor::new(archive())).unwrap();
+
+        let mut paths = entries
+            .keys()
+            .map(|path| path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        let long_dir = "d".repeat(120);
+        let long_name = format!("{}/long.txt", long_dir);
+        let mut expected = vec![
+            "",
+            "assets",
+            "assets/app.js",
+            "index.html",
+            &long_dir,
+            &long_name,
+        ];
+        expected.sort_unstable();
+        assert_eq!(paths, expected);
+
+        let index = &entries[Path::new("index.html")];
+        assert_eq!(index.metadata.len(), 16);
+        assert_eq!(
+            index.metadata.modified(),
+            Some(UNIX_EPOCH + Duration::from_secs(0o14000000000))
+        );
+        assert!(entries[Path::new("assets")].metadata.is_dir());
+    }
+
+    #[test]
+    fn rejects_invalid_archives() {
+        let mut archive = archive();
+        archive[0] = b'X';
+        assert_eq!(
+            index(Cursor::new(archive)).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_numbers() {
+        let mut archive = header_with_max("huge.txt", 124..136);
+        archive.extend(vec![0; 2 * BLOCK_SIZE as usize]);
+        assert_eq!(
+            index(Cursor::new(archive)).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut archive = header_with_max("future.txt", 136..148);
+        let pax = "30 mtime=18446744073709551615\n";
+        entry(&mut archive, "PaxHeader", pax.as_bytes(), b'x');
+        entry(&mut archive, "pax-future.txt", b"", b'0');
+        entry(&mut archive, "now.txt", b"", b'0');
+        archive.extend(vec![0; 2 * BLOCK_SIZE as usize]);
+        let entries = index(Cursor::new(archive)).unwrap();
+        assert!(entries.contains_key(Path::new("now.txt")));
+        assert!(!entries.contains_key(Path::new("future.txt")));
+        assert!(!entries.contains_key(Path::new("pax-future.txt")));
+    }
+
+    #[tokio::test]
+    async fn serve_dir() {
+        use crate::services::ServeDir;
+        use http::{header, Request, StatusCode};
+        use hyper::Body;
+        use tower::ServiceExt;
+
+        let path = std::env::temp_dir().join(format!("tower-http-{}.tar", std::process::id()));
+        std::fs::write(&path, archive()).unwrap();
+        let svc = ServeDir::new("").filesystem(TarFilesystem::open(&path).unwrap());
+
+        let res = svc
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"<h1>archive</h1>");
+
+        let req = Request::get("/assets/app.js")
+            .header(header::RANGE, "bytes=8-")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"log(1)");
+
+        let uri = format!("/{}/long.txt", "d".repeat(120));
+        let res = svc
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"long");
+
+        for uri in ["/assets/link.js", "/escape.txt", "/missing.txt"] {
+            let res = svc
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
-use crate::services::{ServeDir, ServeFile};
+use crate::services::{
+    fs::{ETagMode, MimeTypes, PathPolicy, PathRejection},
+    ServeDir, ServeFile,
+};
 use brotli::BrotliDecompress;
 use bytes::Bytes;
 use flate2::bufread::{DeflateDecoder, GzDecoder};
@@ -23,6 +26,7 @@ async fn basic() {
 
     assert_eq!(res.status(), StatusCode::OK);
     assert_eq!(res.headers()["content-type"], "text/markdown");
+    assert!(res.headers().get("vary").is_none());
 
     let body = body_into_text(res.into_body()).await;
 
@@ -77,6 +81,7 @@ async fn precompresed_head_request() {
 
     assert_eq!(res.headers()["content-type"], "text/plain");
     assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(res.headers()["vary"], "accept-encoding");
     assert_eq!(res.headers()["content-length"], "59");
 
     let body = res.into_body().data().await;
@@ -164,6 +169,26 @@ async fn precompressed_deflate() {
     assert!(decompressed.starts_with("\"This is a test file!\""));
 }
 
+#[tokio::test]
+async fn precompressed_zstd() {
+    let svc = ServeDir::new("../test-files").precompressed_zstd();
+    let request = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "zstd,br")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(request).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert_eq!(res.headers()["content-encoding"], "zstd");
+    assert_eq!(res.headers()["vary"], "accept-encoding");
+
+    let body = res.into_body().data().await.unwrap().unwrap();
+    let decompressed = zstd::stream::decode_all(&body[..]).unwrap();
+    let decompressed = String::from_utf8(decompressed).unwrap();
+    assert!(decompressed.starts_with("\"This is a test file!\""));
+}
+
 #[tokio::test]
 async fn unsupported_precompression_alogrithm_fallbacks_to_uncompressed() {
     let svc = ServeDir::new("../test-files").precompressed_gzip();
@@ -177,6 +202,7 @@ async fn unsupported_precompression_alogrithm_fallbacks_to_uncompressed() {
 
     assert_eq!(res.headers()["content-type"], "text/plain");
     assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.headers()["vary"], "accept-encoding");
 
     let body = res.into_body().data().await.unwrap().unwrap();
     let body = String::from_utf8(body.to_vec()).unwrap();
@@ -479,6 +505,52 @@ async fn read_partial_in_bounds() {
     assert_eq!(body, source);
 }
 
+#[tokio::test]
+async fn read_partial_with_if_range() {
+    let svc = ServeDir::new("..");
+
+    let last_modified = svc
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/README.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .headers()[header::LAST_MODIFIED]
+        .clone();
+
+    // The file hasn't changed so the range is served.
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::RANGE, "bytes=0-9")
+        .header(header::IF_RANGE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()["content-length"], "10");
+
+    // The file has changed, or the entity tag doesn't match, so the full file is served.
+    let file_contents = std::fs::read("../README.md").unwrap();
+    for if_range in ["Sun, 06 Nov 1994 08:49:37 GMT", "\"some-etag\""] {
+        let req = Request::builder()
+            .uri("/README.md")
+            .header(header::RANGE, "bytes=0-9")
+            .header(header::IF_RANGE, if_range)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::CONTENT_RANGE).is_none());
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body.len(), file_contents.len());
+    }
+}
+
 #[tokio::test]
 async fn read_partial_rejects_out_of_bounds_range() {
     let svc = ServeDir::new("..");
@@ -621,6 +693,296 @@ async fn last_modified() {
     assert!(body.is_none());
 }
 
+#[tokio::test]
+async fn etag() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let etag = res.headers()[header::ETAG].clone();
+    assert!(etag.to_str().unwrap().starts_with("W/\""));
+
+    // -- If-None-Match
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(
+            header::IF_NONE_MATCH,
+            format!("\"other\", {}", etag.to_str().unwrap()),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::ETAG], etag);
+    assert!(res.headers().contains_key(header::LAST_MODIFIED));
+    assert!(res.into_body().data().await.is_none());
+
+    // `If-None-Match` takes precedence over `If-Modified-Since`
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, "\"other\"")
+        .header(header::IF_MODIFIED_SINCE, "Sun, 06 Nov 2994 08:49:37 GMT")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // -- Disabled
+
+    let svc = ServeDir::new("..").etag_mode(ETagMode::Disabled);
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::ETAG).is_none());
+}
+
+#[tokio::test]
+async fn content_hash_etag() {
+    let svc = ServeDir::new("..").etag_mode(ETagMode::ContentHash);
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.into_body().data().await.is_none());
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let etag = res.headers()[header::ETAG].clone();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+
+    // the file is read from the start after hashing it
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body.as_ref(), include_bytes!("../../../../../README.md"));
+
+    // strong `ETag`s can be used with `If-Range`
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::RANGE, "bytes=0-9")
+        .header(header::IF_RANGE, etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(
+        body.as_ref(),
+        &include_bytes!("../../../../../README.md")[..10]
+    );
+}
+
+#[tokio::test]
+async fn directory_listing() {
+    let svc = ServeDir::new("../test-files").list_directories();
+
+    // `index.html` takes precedence over listings
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body, "<b>HTML!</b>\n");
+
+    let svc = ServeDir::new("../test-files")
+        .append_index_html_on_directories(false)
+        .list_directories();
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains(
+        "<li><a href=\"./filename%20with%20space.txt\">filename with space.txt</a></li>"
+    ));
+    assert!(body.contains("<a href=\"./index.html\">"));
+
+    let req = Request::builder()
+        .uri("/")
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "application/json");
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["path"], "/");
+    let entry = json["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["name"] == "precompressed.txt")
+        .unwrap();
+    assert_eq!(entry["is_dir"], false);
+    assert_eq!(entry["size"], 23);
+}
+
+#[tokio::test]
+async fn directory_listing_hide_and_render() {
+    let svc = ServeDir::new("../test-files")
+        .append_index_html_on_directories(false)
+        .hide_entries_with(|entry| !entry.name().starts_with("precompressed."))
+        .render_directories_with(|listing, _| {
+            let names: Vec<&str> = listing.entries().iter().map(|entry| entry.name()).collect();
+            Response::new(names.join(",").into())
+        });
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(
+        body,
+        "precompressed.txt,precompressed.txt.br,precompressed.txt.gz,\
+         precompressed.txt.zst,precompressed.txt.zz"
+    );
+
+    // listings are disabled by default
+    let svc = ServeDir::new("../test-files").append_index_html_on_directories(false);
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn file_cache() {
+    let svc = ServeDir::new("..")
+        .with_file_cache(1024 * 1024, 64 * 1024)
+        .etag_mode(ETagMode::ContentHash);
+    let file_contents = include_bytes!("../../../../../README.md");
+
+    let mut etags = Vec::new();
+    for _ in 0..2 {
+        let req = Request::builder()
+            .uri("/README.md")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()["content-length"],
+            file_contents.len().to_string()
+        );
+        etags.push(res.headers()[header::ETAG].clone());
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body.as_ref(), file_contents);
+    }
+    assert_eq!(etags[0], etags[1]);
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::RANGE, "bytes=10-19")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body.as_ref(), &file_contents[10..20]);
+
+    // files over the size limit are streamed as usual
+    let svc = ServeDir::new("..").with_file_cache(1024 * 1024, 16);
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body.as_ref(), file_contents);
+}
+
+#[tokio::test]
+async fn file_cache_invalidation() {
+    let dir = std::env::temp_dir().join(format!("tower-http-file-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), "first").unwrap();
+
+    let svc = ServeDir::new(&dir).with_file_cache(1024, 1024);
+    let get = |svc: ServeDir| async move {
+        let req = Request::builder()
+            .uri("/file.txt")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        hyper::body::to_bytes(res.into_body()).await.unwrap()
+    };
+
+    assert_eq!(get(svc.clone()).await, "first");
+    assert_eq!(get(svc.clone()).await, "first");
+
+    std::fs::write(dir.join("file.txt"), "second").unwrap();
+    assert_eq!(get(svc.clone()).await, "second");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_cache_serves_from_memory() {
+    use crate::services::fs::{FileMetadata, FileReader, Filesystem, TokioFilesystem};
+    use futures_util::future::BoxFuture;
+    use std::{
+        ops::Range,
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    #[derive(Clone, Default)]
+    struct CountingReads(Arc<AtomicUsize>);
+
+    impl Filesystem for CountingReads {
+        fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+            TokioFilesystem.metadata(path)
+        }
+
+        fn read<'a>(
+            &'a self,
+            path: &'a Path,
+            range: Option<Range<u64>>,
+        ) -> BoxFuture<'a, io::Result<FileReader>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            TokioFilesystem.read(path, range)
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!(
+        "tower-http-file-cache-memory-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), "first").unwrap();
+
+    let fs = CountingReads::default();
+    let svc = ServeDir::new(&dir)
+        .filesystem(fs.clone())
+        .with_file_cache(1024, 1024);
+    for _ in 0..2 {
+        let req = Request::builder()
+            .uri("/file.txt")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "first");
+    }
+    assert_eq!(fs.0.load(Ordering::SeqCst), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[tokio::test]
 async fn with_fallback_svc() {
     async fn fallback<B>(req: Request<B>) -> Result<Response<Body>, Infallible> {
@@ -747,3 +1109,442 @@ async fn calls_fallback_on_invalid_paths() {
 
     assert_eq!(res.headers()["from-fallback"], "1");
 }
+
+#[tokio::test]
+async fn path_policy_rejections() {
+    let get = |svc: ServeDir, uri: &'static str| async move {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap().status()
+    };
+
+    // dotfiles are served by default
+    let svc = ServeDir::new("..");
+    assert_eq!(get(svc.clone(), "/.gitignore").await, StatusCode::OK);
+    assert_eq!(get(svc, "/../README.md").await, StatusCode::NOT_FOUND);
+
+    let svc = ServeDir::new("..").path_policy(
+        PathPolicy::new()
+            .deny_dotfiles(PathRejection::Forbidden)
+            .on_escape(PathRejection::BadRequest),
+    );
+    assert_eq!(get(svc.clone(), "/.gitignore").await, StatusCode::FORBIDDEN);
+    assert_eq!(
+        get(svc.clone(), "/src/../README.md").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        get(svc.clone(), "/%2e%2e/README.md").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(get(svc, "/README.md").await, StatusCode::OK);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn path_policy_symlinks() {
+    let dir = std::env::temp_dir().join(format!("tower-http-path-policy-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("inside")).unwrap();
+    std::fs::write(dir.join("inside/file.txt"), "inside").unwrap();
+    std::fs::write(dir.join("outside.txt"), "outside").unwrap();
+    let root = dir.join("root");
+    std::fs::create_dir_all(&root).unwrap();
+    std::os::unix::fs::symlink(dir.join("outside.txt"), root.join("escape.txt")).unwrap();
+    std::os::unix::fs::symlink("file.txt", root.join("link.txt")).unwrap();
+    std::fs::write(root.join("file.txt"), "file").unwrap();
+
+    let get = |svc: ServeDir, uri: &'static str| async move {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap().status()
+    };
+
+    // symlinks are followed by default
+    let svc = ServeDir::new(&root);
+    assert_eq!(get(svc.clone(), "/escape.txt").await, StatusCode::OK);
+    assert_eq!(get(svc, "/link.txt").await, StatusCode::OK);
+
+    let svc = ServeDir::new(&root).path_policy(PathPolicy::new().confine_to_root(true));
+    assert_eq!(get(svc.clone(), "/escape.txt").await, StatusCode::NOT_FOUND);
+    assert_eq!(get(svc.clone(), "/link.txt").await, StatusCode::OK);
+    assert_eq!(get(svc, "/missing.txt").await, StatusCode::NOT_FOUND);
+
+    let svc = ServeDir::new(&root).path_policy(
+        PathPolicy::new()
+            .follow_symlinks(false)
+            .on_escape(PathRejection::Forbidden),
+    );
+    assert_eq!(get(svc.clone(), "/escape.txt").await, StatusCode::FORBIDDEN);
+    assert_eq!(get(svc.clone(), "/link.txt").await, StatusCode::FORBIDDEN);
+    assert_eq!(get(svc, "/file.txt").await, StatusCode::OK);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn custom_mime_guesser() {
+    let svc = ServeDir::new("..").mime_guesser(
+        MimeTypes::new()
+            .extension("md", &"text/markdown".parse().unwrap())
+            .text_charset("utf-8"),
+    );
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(
+        res.headers()["content-type"],
+        "text/markdown; charset=utf-8"
+    );
+
+    let svc = ServeDir::new("..").mime_guesser(|path: &std::path::Path| {
+        if path.extension()? == "toml" {
+            Some(header::HeaderValue::from_static("application/toml"))
+        } else {
+            None
+        }
+    });
+    let req = Request::builder()
+        .uri("/Cargo.toml")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "application/toml");
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "application/octet-stream");
+}
+
+#[tokio::test]
+async fn spa_fallback() {
+    let svc = ServeDir::new("../test-files").with_spa_fallback("index.html");
+
+    for uri in ["/users/42", "/missing/"] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], "text/html");
+        let body = body_into_text(res.into_body()).await;
+        assert_eq!(body, "<b>HTML!</b>\n");
+    }
+
+    // existing files are served as usual
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "text/plain");
+
+    // missing assets aren't routes
+    let req = Request::builder()
+        .uri("/missing.js")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn excluded_paths() {
+    let svc = ServeDir::new("..")
+        .exclude("*.toml")
+        .exclude("tower-http/src/services/**")
+        .append_index_html_on_directories(false)
+        .list_directories();
+
+    for uri in [
+        "/Cargo.toml",
+        "/tower-http/src/services/mod.rs",
+        "/tower-http/src/services/",
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    let req = Request::builder()
+        .uri("/tower-http/src/lib.rs")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // excluded entries are hidden from listings
+    let req = Request::builder()
+        .uri("/tower-http/src/")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.contains("lib.rs"));
+    assert!(!body.contains("services"));
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.contains("README.md"));
+    assert!(!body.contains("Cargo.toml"));
+}
+
+#[tokio::test]
+async fn custom_filesystem() {
+    use crate::services::fs::{DirectoryEntry, FileMetadata, FileReader, Filesystem};
+    use futures_util::future::{self, BoxFuture, FutureExt};
+    use std::{ops::Range, path::Path};
+
+    struct Memory;
+
+    impl Memory {
+        fn get(path: &Path) -> io::Result<Option<&'static [u8]>> {
+            match path.to_str() {
+                Some("./site") => Ok(None),
+                Some("./site/hello.txt") => Ok(Some(b"hello from memory")),
+                _ => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+    }
+
+    impl Filesystem for Memory {
+        fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+            let meta = Memory::get(path).map(|contents| match contents {
+                Some(contents) => FileMetadata::file(contents.len() as u64),
+                None => FileMetadata::dir(),
+            });
+            future::ready(meta).boxed()
+        }
+
+        fn read<'a>(
+            &'a self,
+            path: &'a Path,
+            range: Option<Range<u64>>,
+        ) -> BoxFuture<'a, io::Result<FileReader>> {
+            let reader = Memory::get(path).and_then(|contents| {
+                let contents = contents.ok_or(io::ErrorKind::Other)?;
+                let contents = match range {
+                    Some(range) => &contents[range.start as usize..range.end as usize],
+                    None => contents,
+                };
+                Ok(Box::pin(contents) as FileReader)
+            });
+            future::ready(reader).boxed()
+        }
+
+        fn read_dir<'a>(
+            &'a self,
+            _path: &'a Path,
+        ) -> BoxFuture<'a, io::Result<Vec<DirectoryEntry>>> {
+            let entries = vec![DirectoryEntry::new(
+                "hello.txt".to_owned(),
+                &FileMetadata::file(17),
+            )];
+            future::ready(Ok(entries)).boxed()
+        }
+    }
+
+    let svc = ServeDir::new("site")
+        .filesystem(Memory)
+        .append_index_html_on_directories(false)
+        .list_directories();
+
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert_eq!(res.headers()["content-length"], "17");
+    assert_eq!(body_into_text(res.into_body()).await, "hello from memory");
+
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .header(header::RANGE, "bytes=6-9")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_into_text(res.into_body()).await, "from");
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert!(body_into_text(res.into_body()).await.contains("hello.txt"));
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn multiple_roots() {
+    let svc = ServeDir::new("../test-files")
+        .add_root("src")
+        .add_root("..");
+
+    // the first root has precedence
+    let req = Request::builder()
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    let req = Request::builder()
+        .uri("/lib.rs")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        include_str!("../../../lib.rs")
+    );
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        include_str!("../../../../../README.md")
+    );
+
+    // directories of later roots are redirected to as usual
+    let req = Request::builder()
+        .uri("/services")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn metadata_cache() {
+    use crate::services::fs::{FileMetadata, FileReader, Filesystem, TokioFilesystem};
+    use futures_util::future::BoxFuture;
+    use std::{
+        ops::Range,
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[derive(Clone, Default)]
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Filesystem for Counting {
+        fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileMetadata>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            TokioFilesystem.metadata(path)
+        }
+
+        fn read<'a>(
+            &'a self,
+            path: &'a Path,
+            range: Option<Range<u64>>,
+        ) -> BoxFuture<'a, io::Result<FileReader>> {
+            TokioFilesystem.read(path, range)
+        }
+    }
+
+    let fs = Counting::default();
+    let guesses = Arc::new(AtomicUsize::new(0));
+    let svc = ServeDir::new("..")
+        .filesystem(fs.clone())
+        .mime_guesser({
+            let guesses = guesses.clone();
+            move |_: &Path| {
+                guesses.fetch_add(1, Ordering::SeqCst);
+                Some(http::HeaderValue::from_static("text/markdown"))
+            }
+        })
+        .with_metadata_cache(Duration::from_secs(60), 16);
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res.headers()[header::ETAG].clone();
+    let calls = fs.0.load(Ordering::SeqCst);
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(fs.0.load(Ordering::SeqCst), calls);
+    // the content type is cached with the metadata
+    assert_eq!(guesses.load(Ordering::SeqCst), 1);
+
+    // missing files aren't cached
+    let mut counts = Vec::new();
+    for _ in 0..2 {
+        let req = Request::builder()
+            .uri("/missing.txt")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        counts.push(fs.0.load(Ordering::SeqCst));
+    }
+    assert!(counts[0] > calls);
+    assert_eq!(counts[1] - counts[0], counts[0] - calls);
+
+    // the least recently used entries are evicted
+    let fs = Counting::default();
+    let svc = ServeDir::new("..")
+        .filesystem(fs.clone())
+        .with_metadata_cache(Duration::from_secs(60), 2);
+    for path in [
+        "/README.md",
+        "/CONTRIBUTING.md",
+        "/README.md",
+        "/Cargo.toml",
+    ] {
+        let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+        svc.clone().oneshot(req).await.unwrap();
+    }
+    let calls = fs.0.load(Ordering::SeqCst);
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(fs.0.load(Ordering::SeqCst), calls);
+
+    // expired entries are read again
+    let fs = Counting::default();
+    let svc = ServeDir::new("..")
+        .filesystem(fs.clone())
+        .with_metadata_cache(Duration::ZERO, 16);
+    for _ in 0..2 {
+        let req = Request::builder()
+            .uri("/README.md")
+            .body(Body::empty())
+            .unwrap();
+        svc.clone().oneshot(req).await.unwrap();
+    }
+    assert_eq!(fs.0.load(Ordering::SeqCst), 4);
+}
@@ -1,6 +1,6 @@
 //! Service that serves a file.
 
-use super::ServeDir;
+use super::{ETagMode, Filesystem, ServeDir};
 use http::{HeaderValue, Request};
 use mime::Mime;
 use std::{
@@ -84,6 +84,44 @@ impl ServeFile {
         Self(self.0.precompressed_deflate())
     }
 
+    /// Informs the service that it should look for a precompressed zstd
+    /// version of the file.
+    ///
+    /// If the client has an `Accept-Encoding` header that allows the zstd encoding,
+    /// the file `foo.txt.zst` will be served instead of `foo.txt`.
+    /// If the precompressed file is not available, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    /// Both the precompressed version and the uncompressed version are expected
+    /// to be present in the same directory. Different precompressed
+    /// variants can be combined.
+    pub fn precompressed_zstd(self) -> Self {
+        Self(self.0.precompressed_zstd())
+    }
+
+    /// Set how `ETag` headers are generated.
+    ///
+    /// See [`ServeDir::etag_mode`] for more details.
+    pub fn etag_mode(self, mode: ETagMode) -> Self {
+        Self(self.0.etag_mode(mode))
+    }
+
+    /// Cache the contents of the file in memory if it is at most `max_size` bytes.
+    ///
+    /// See [`ServeDir::with_file_cache`] for more details.
+    pub fn with_file_cache(self, max_size: usize) -> Self {
+        Self(self.0.with_file_cache(max_size, max_size))
+    }
+
+    /// Set the [`Filesystem`] the file is read from.
+    ///
+    /// See [`ServeDir::filesystem`] for more details.
+    pub fn filesystem<FS>(self, filesystem: FS) -> Self
+    where
+        FS: Filesystem + 'static,
+    {
+        Self(self.0.filesystem(filesystem))
+    }
+
     /// Set a specific read buffer chunk size.
     ///
     /// The default capacity is 64kb.
@@ -345,6 +383,25 @@ mod tests {
         assert!(decompressed.starts_with("\"This is a test file!\""));
     }
 
+    #[tokio::test]
+    async fn precompressed_zstd() {
+        let svc = ServeFile::new("../test-files/precompressed.txt").precompressed_zstd();
+        let request = Request::builder()
+            .header("Accept-Encoding", "zstd")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(request).await.unwrap();
+
+        assert_eq!(res.headers()["content-type"], "text/plain");
+        assert_eq!(res.headers()["content-encoding"], "zstd");
+        assert_eq!(res.headers()["vary"], "accept-encoding");
+
+        let body = res.into_body().data().await.unwrap().unwrap();
+        let decompressed = zstd::stream::decode_all(&body[..]).unwrap();
+        let decompressed = String::from_utf8(decompressed).unwrap();
+        assert!(decompressed.starts_with("\"This is a test file!\""));
+    }
+
     #[tokio::test]
     async fn multi_precompressed() {
         let svc = ServeFile::new("../test-files/precompressed.txt")
@@ -0,0 +1,373 @@
+//! Service that reports the health of an application.
+//!
+//! [`HealthCheck`] runs named async checks, such as pinging a database, and responds with
+//! `200 OK` if all of them pass and `503 Service Unavailable` otherwise. The body is a JSON
+//! document with the result and duration of each check:
+//!
+//! ```json
+//! {
+//!   "status": "fail",
+//!   "checks": {
+//!     "cache": { "status": "pass", "duration_ms": 0.8 },
+//!     "database": { "status": "fail", "duration_ms": 1503.2, "error": "connection refused" }
+//!   }
+//! }
+//! ```
+//!
+//! Checks are registered either for readiness, whether the application can serve traffic, or for
+//! liveness, whether the process is working at all and shouldn't be restarted. A readiness probe
+//! runs all checks while a liveness probe only runs the liveness checks, so an unavailable
+//! database takes an instance out of the load balancer without restarting it.
+//!
+//! # Example
+//!
+//! ```rust
+//! use http::{Request, StatusCode};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::ServiceExt;
+//! use tower_http::services::health_check::{HealthCheck, Probe};
+//!
+//! async fn ping_database() -> Result<(), std::io::Error> {
+//!     // ...
+//!     # Err(std::io::ErrorKind::ConnectionRefused.into())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let readiness = HealthCheck::new()
+//!     .check("database", ping_database)
+//!     .liveness_check("runtime", || async { Ok::<_, Infallible>(()) })
+//!     .cache_ttl(Duration::from_secs(5));
+//! // Clones share the checks and cached results
+//! let liveness = readiness.clone().probe(Probe::Liveness);
+//!
+//! let response = readiness.oneshot(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+//!
+//! let response = liveness.oneshot(Request::new(Body::empty())).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sync::lock_ignore_poison;
+use bytes::Bytes;
+use futures_util::future::{join_all, BoxFuture, FutureExt};
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use http_body::Full;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower_service::Service;
+
+/// Service that runs health checks and reports their results.
+///
+/// See the [module docs](crate::services::health_check) for more details.
+#[derive(Clone)]
+pub struct HealthCheck {
+    checks: Arc<Vec<Check>>,
+    probe: Probe,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<HashMap<&'static str, CachedResult>>>,
+}
+
+/// Which checks a [`HealthCheck`] runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Probe {
+    /// Run all checks.
+    #[default]
+    Readiness,
+    /// Only run the checks registered with [`HealthCheck::liveness_check`].
+    Liveness,
+}
+
+type CheckFn = Arc<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+#[derive(Clone)]
+struct Check {
+    name: &'static str,
+    liveness: bool,
+    run: CheckFn,
+}
+
+#[derive(Clone)]
+struct CheckResult {
+    result: Result<(), String>,
+    duration: Duration,
+}
+
+struct CachedResult {
+    checked_at: Instant,
+    result: CheckResult,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            checks: Arc::new(Vec::new()),
+            probe: Probe::default(),
+            cache_ttl: Duration::ZERO,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl HealthCheck {
+    /// Create a new [`HealthCheck`] without any checks, which always responds with `200 OK`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a readiness check.
+    ///
+    /// The check fails if the future returns an error, and the error is included in the body.
+    pub fn check<F, Fut, E>(self, name: &'static str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: fmt::Display,
+    {
+        self.push(name, false, check)
+    }
+
+    /// Register a liveness check.
+    ///
+    /// Liveness checks are run by both [`Probe::Liveness`] and [`Probe::Readiness`].
+    pub fn liveness_check<F, Fut, E>(self, name: &'static str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: fmt::Display,
+    {
+        self.push(name, true, check)
+    }
+
+    /// Set which checks are run.
+    ///
+    /// Defaults to [`Probe::Readiness`].
+    pub fn probe(mut self, probe: Probe) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Reuse the result of each check for `ttl` instead of running it for every request.
+    ///
+    /// The cache is shared between clones of the service. Defaults to not caching results.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    fn push<F, Fut, E>(mut self, name: &'static str, liveness: bool, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: fmt::Display,
+    {
+        let run: CheckFn = Arc::new(move || {
+            check()
+                .map(|result| result.map_err(|err| err.to_string()))
+                .boxed()
+        });
+        let checks = Arc::make_mut(&mut self.checks);
+        checks.retain(|check| check.name != name);
+        checks.push(Check {
+            name,
+            liveness,
+            run,
+        });
+        self
+    }
+
+    async fn run(self) -> Response<Full<Bytes>> {
+        let checks = self
+            .checks
+            .iter()
+            .filter(|check| check.liveness || self.probe == Probe::Readiness)
+            .map(|check| self.run_check(check));
+        let results = join_all(checks).await;
+
+        let healthy = results.iter().all(|(_, result)| result.result.is_ok());
+        let checks = results
+            .into_iter()
+            .map(|(name, result)| (name.to_owned(), result.to_json()))
+            .collect::<Map<_, _>>();
+        let body = json!({
+            "status": status(healthy),
+            "checks": checks,
+        });
+
+        let mut res = Response::new(Full::from(body.to_string()));
+        if !healthy {
+            *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        }
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        res
+    }
+
+    async fn run_check(&self, check: &Check) -> (&'static str, CheckResult) {
+        if let Some(cached) = lock_ignore_poison(&self.cache).get(check.name) {
+            if cached.checked_at.elapsed() < self.cache_ttl {
+                return (check.name, cached.result.clone());
+            }
+        }
+
+        let start = Instant::now();
+        let result = (check.run)().await;
+        let result = CheckResult {
+            result,
+            duration: start.elapsed(),
+        };
+
+        if !self.cache_ttl.is_zero() {
+            lock_ignore_poison(&self.cache).insert(
+                check.name,
+                CachedResult {
+                    checked_at: Instant::now(),
+                    result: result.clone(),
+                },
+            );
+        }
+
+        (check.name, result)
+    }
+}
+
+impl CheckResult {
+    fn to_json(&self) -> Value {
+        let mut json = json!({
+            "status": status(self.result.is_ok()),
+            "duration_ms": self.duration.as_secs_f64() * 1000.0,
+        });
+        if let Err(err) = &self.result {
+            json["error"] = err.clone().into();
+        }
+        json
+    }
+}
+
+fn status(healthy: bool) -> &'static str {
+    if healthy {
+        "pass"
+    } else {
+        "fail"
+    }
+}
+
+impl fmt::Debug for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthCheck")
+            .field(
+                "checks",
+                &self
+                    .checks
+                    .iter()
+                    .map(|check| check.name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("probe", &self.probe)
+            .field("cache_ttl", &self.cache_ttl)
+            .finish()
+    }
+}
+
+impl<ReqBody> Service<Request<ReqBody>> for HealthCheck {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _req: Request<ReqBody>) -> Self::Future {
+        self.clone().run().map(Ok).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tower::ServiceExt;
+
+    async fn call(svc: &HealthCheck) -> (StatusCode, Value) {
+        let res = svc
+            .clone()
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn aggregates_checks() {
+        let svc = HealthCheck::new()
+            .check("database", || async {
+                Err::<(), _>(io::Error::new(io::ErrorKind::Other, "connection refused"))
+            })
+            .liveness_check("runtime", || async { Ok::<_, Infallible>(()) });
+
+        let (status, body) = call(&svc).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "fail");
+        assert_eq!(body["checks"]["database"]["status"], "fail");
+        assert_eq!(body["checks"]["database"]["error"], "connection refused");
+        assert_eq!(body["checks"]["runtime"]["status"], "pass");
+        assert!(body["checks"]["runtime"]["duration_ms"].is_f64());
+        assert!(body["checks"]["runtime"].get("error").is_none());
+
+        let (status, body) = call(&svc.clone().probe(Probe::Liveness)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "pass");
+        assert!(body["checks"].get("database").is_none());
+
+        let (status, body) = call(&HealthCheck::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "status": "pass", "checks": {} }));
+    }
+
+    #[tokio::test]
+    async fn caches_results() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let check = {
+            let runs = runs.clone();
+            move || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async { Ok::<_, Infallible>(()) }
+            }
+        };
+
+        let svc = HealthCheck::new().check("counted", check.clone());
+        call(&svc).await;
+        call(&svc).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        let svc = svc
+            .check("counted", check)
+            .cache_ttl(Duration::from_secs(60));
+        call(&svc).await;
+        call(&svc.clone()).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+}
@@ -13,9 +13,33 @@ pub mod redirect;
 #[doc(inline)]
 pub use self::redirect::Redirect;
 
+#[cfg(feature = "health-check")]
+pub mod health_check;
+
+#[cfg(feature = "health-check")]
+#[doc(inline)]
+pub use self::health_check::HealthCheck;
+
+#[cfg(feature = "respond-with")]
+pub mod respond_with;
+
+#[cfg(feature = "respond-with")]
+#[doc(inline)]
+pub use self::respond_with::{respond_with, RespondWith};
+
+#[cfg(any(feature = "redirect", feature = "respond-with"))]
+mod template;
+
 #[cfg(feature = "fs")]
 pub mod fs;
 
 #[cfg(feature = "fs")]
 #[doc(inline)]
 pub use self::fs::{ServeDir, ServeFile};
+
+#[cfg(feature = "well-known")]
+pub mod well_known;
+
+#[cfg(feature = "well-known")]
+#[doc(inline)]
+pub use self::well_known::WellKnown;
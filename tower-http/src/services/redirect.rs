@@ -30,27 +30,61 @@
 //! # }
 //! ```
 
-use http::{header, HeaderValue, Response, StatusCode, Uri};
+use super::template::{parse_template, Part};
+use http::{header, uri::Authority, HeaderMap, HeaderValue, Request, Response, StatusCode, Uri};
 use std::{
     convert::{Infallible, TryFrom},
     fmt,
     future::Future,
     marker::PhantomData,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 use tower_service::Service;
 
 /// Service that redirects all requests.
 ///
+/// Redirects to a [`Fixed`] location can be used with any type of request. Redirects to a
+/// location that depends on the request, created with [`Redirect::with_template`] or
+/// [`Redirect::preserve_query`], have a [`Dynamic`] location and require [`Request`]s.
+///
 /// See the [module docs](crate::services::redirect) for more details.
-pub struct Redirect<ResBody> {
+pub struct Redirect<ResBody, L = Fixed> {
     status_code: StatusCode,
-    location: HeaderValue,
+    location: L,
     // Covariant over ResBody, no dropping of ResBody
     _marker: PhantomData<fn() -> ResBody>,
 }
 
+/// The location of a [`Redirect`] that is the same for every request.
+#[derive(Clone, Debug)]
+pub struct Fixed(HeaderValue);
+
+/// The location of a [`Redirect`] that depends on the request.
+#[derive(Clone, Debug)]
+pub struct Dynamic {
+    location: Location,
+    preserve_query: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Location {
+    Fixed(HeaderValue),
+    Template(Arc<[Part<Placeholder>]>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Placeholder {
+    Scheme,
+    Host,
+    Port,
+    Authority,
+    Path,
+    Query,
+    PathAndQuery,
+}
+
 impl<ResBody> Redirect<ResBody> {
     /// Create a new [`Redirect`] that uses a [`307 Temporary Redirect`][mdn] status code.
     ///
@@ -66,6 +100,33 @@ impl<ResBody> Redirect<ResBody> {
         Self::with_status_code(StatusCode::PERMANENT_REDIRECT, uri)
     }
 
+    /// Create a new [`Redirect`] that uses a [`301 Moved Permanently`][mdn] status code.
+    ///
+    /// Clients may change the method of the redirected request to `GET`.
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/301
+    pub fn moved_permanently(uri: Uri) -> Self {
+        Self::with_status_code(StatusCode::MOVED_PERMANENTLY, uri)
+    }
+
+    /// Create a new [`Redirect`] that uses a [`302 Found`][mdn] status code.
+    ///
+    /// Clients may change the method of the redirected request to `GET`.
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/302
+    pub fn found(uri: Uri) -> Self {
+        Self::with_status_code(StatusCode::FOUND, uri)
+    }
+
+    /// Create a new [`Redirect`] that uses a [`303 See Other`][mdn] status code.
+    ///
+    /// Clients follow the redirection with a `GET` request.
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/303
+    pub fn see_other(uri: Uri) -> Self {
+        Self::with_status_code(StatusCode::SEE_OTHER, uri)
+    }
+
     /// Create a new [`Redirect`] that uses the given status code.
     ///
     /// # Panics
@@ -75,6 +136,119 @@ impl<ResBody> Redirect<ResBody> {
     ///
     /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status#redirection_messages
     pub fn with_status_code(status_code: StatusCode, uri: Uri) -> Self {
+        let location =
+            HeaderValue::try_from(uri.to_string()).expect("URI isn't a valid header value");
+        Self::new(status_code, Fixed(location))
+    }
+
+    /// Create a new [`Redirect`] to a location built from the URI of each request.
+    ///
+    /// The template can contain these placeholders:
+    ///
+    /// - `{scheme}`: The scheme of the request URI, or `http` if it doesn't have one.
+    /// - `{host}`: The host of the request URI or `Host` header.
+    /// - `{port}`: The port of the request URI or `Host` header, empty if there is none.
+    /// - `{authority}`: The host and port of the request URI or `Host` header.
+    /// - `{path}`: The path of the request URI.
+    /// - `{query}`: The query of the request URI without the leading `?`, empty if there is none.
+    /// - `{path_and_query}`: The path and query of the request URI.
+    ///
+    /// Literal braces are written as `{{` and `}}`.
+    ///
+    /// # Example
+    ///
+    /// Moving a site to another host while keeping the path and query:
+    ///
+    /// ```
+    /// use http::{Request, StatusCode};
+    /// use hyper::Body;
+    /// use tower::ServiceExt;
+    /// use tower_http::services::{redirect::Dynamic, Redirect};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let service: Redirect<Body, Dynamic> = Redirect::with_template(
+    ///     StatusCode::MOVED_PERMANENTLY,
+    ///     "https://new.example.com{path_and_query}",
+    /// );
+    ///
+    /// let request = Request::builder()
+    ///     .uri("http://old.example.com/docs?page=2")
+    ///     .body(Body::empty())?;
+    /// let response = service.oneshot(request).await?;
+    ///
+    /// assert_eq!(
+    ///     response.headers()["location"],
+    ///     "https://new.example.com/docs?page=2",
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// - If `status_code` isn't a [redirection status code][mdn] (3xx).
+    /// - If `template` contains an unknown placeholder, an unmatched brace or characters that
+    ///   aren't valid in a [`HeaderValue`].
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status#redirection_messages
+    pub fn with_template(status_code: StatusCode, template: &str) -> Redirect<ResBody, Dynamic> {
+        let parts = parse_location_template(template).unwrap_or_else(|err| panic!("{}", err));
+        Redirect::new(
+            status_code,
+            Dynamic {
+                location: Location::Template(parts.into()),
+                preserve_query: false,
+            },
+        )
+    }
+
+    /// Append the query of the request to the location if the location doesn't have a query.
+    ///
+    /// Defaults to `false`.
+    pub fn preserve_query(self, preserve_query: bool) -> Redirect<ResBody, Dynamic> {
+        Redirect {
+            status_code: self.status_code,
+            location: Dynamic {
+                location: Location::Fixed(self.location.0),
+                preserve_query,
+            },
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<ResBody> Redirect<ResBody, Dynamic> {
+    /// Append the query of the request to the location if the location doesn't have a query.
+    ///
+    /// Defaults to `false`.
+    pub fn preserve_query(mut self, preserve_query: bool) -> Self {
+        self.location.preserve_query = preserve_query;
+        self
+    }
+
+    fn location(&self, uri: &Uri, headers: &HeaderMap) -> Option<HeaderValue> {
+        let query = uri.query().filter(|_| self.location.preserve_query);
+        let mut location = match (&self.location.location, query) {
+            (Location::Fixed(location), None) => return Some(location.clone()),
+            (Location::Fixed(location), Some(_)) => location.to_str().ok()?.to_owned(),
+            (Location::Template(parts), _) => render(parts, uri, headers),
+        };
+
+        if let Some(query) = query {
+            // the query goes before any fragment
+            let end = location.find('#').unwrap_or(location.len());
+            if !location[..end].contains('?') {
+                location.insert_str(end, &format!("?{}", query));
+            }
+        }
+
+        HeaderValue::try_from(location).ok()
+    }
+}
+
+impl<ResBody, L> Redirect<ResBody, L> {
+    fn new(status_code: StatusCode, location: L) -> Self {
         assert!(
             status_code.is_redirection(),
             "not a redirection status code"
@@ -82,13 +256,77 @@ impl<ResBody> Redirect<ResBody> {
 
         Self {
             status_code,
-            location: HeaderValue::try_from(uri.to_string())
-                .expect("URI isn't a valid header value"),
+            location,
             _marker: PhantomData,
         }
     }
 }
 
+fn parse_location_template(template: &str) -> Result<Vec<Part<Placeholder>>, String> {
+    let parts = parse_template(template, "redirect", |name| match name {
+        "scheme" => Some(Placeholder::Scheme),
+        "host" => Some(Placeholder::Host),
+        "port" => Some(Placeholder::Port),
+        "authority" => Some(Placeholder::Authority),
+        "path" => Some(Placeholder::Path),
+        "query" => Some(Placeholder::Query),
+        "path_and_query" => Some(Placeholder::PathAndQuery),
+        _ => None,
+    })?;
+
+    for part in &parts {
+        if let Part::Literal(literal) = part {
+            if HeaderValue::from_str(literal).is_err() {
+                return Err(format!(
+                    "redirect template {:?} isn't a valid header value",
+                    template
+                ));
+            }
+        }
+    }
+
+    Ok(parts)
+}
+
+fn render(parts: &[Part<Placeholder>], uri: &Uri, headers: &HeaderMap) -> String {
+    // requests to servers usually only have the authority in the `Host` header
+    let authority = uri.authority().cloned().or_else(|| {
+        headers
+            .get(header::HOST)
+            .and_then(|host| Authority::try_from(host.as_bytes()).ok())
+    });
+
+    let mut location = String::new();
+    for part in parts {
+        let placeholder = match part {
+            Part::Literal(literal) => {
+                location.push_str(literal);
+                continue;
+            }
+            Part::Placeholder(placeholder) => placeholder,
+        };
+        match placeholder {
+            Placeholder::Scheme => location.push_str(uri.scheme_str().unwrap_or("http")),
+            Placeholder::Host => location.push_str(authority.as_ref().map_or("", |a| a.host())),
+            Placeholder::Port => {
+                if let Some(port) = authority.as_ref().and_then(|a| a.port()) {
+                    location.push_str(port.as_str());
+                }
+            }
+            Placeholder::Authority => {
+                location.push_str(authority.as_ref().map_or("", |a| a.as_str()))
+            }
+            Placeholder::Path => location.push_str(uri.path()),
+            Placeholder::Query => location.push_str(uri.query().unwrap_or("")),
+            Placeholder::PathAndQuery => location.push_str(
+                uri.path_and_query()
+                    .map_or_else(|| uri.path(), |path_and_query| path_and_query.as_str()),
+            ),
+        }
+    }
+    location
+}
+
 impl<R, ResBody> Service<R> for Redirect<ResBody>
 where
     ResBody: Default,
@@ -105,13 +343,46 @@ where
     fn call(&mut self, _req: R) -> Self::Future {
         ResponseFuture {
             status_code: self.status_code,
-            location: Some(self.location.clone()),
+            location: Some(self.location.0.clone()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<ReqBody, ResBody> Service<Request<ReqBody>> for Redirect<ResBody, Dynamic>
+where
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = Infallible;
+    type Future = ResponseFuture<ResBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let location = self.location(req.uri(), req.headers());
+        let status_code = if location.is_some() {
+            self.status_code
+        } else {
+            // only happens if the request URI can't be used in a header value
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        ResponseFuture {
+            status_code,
+            location,
             _marker: PhantomData,
         }
     }
 }
 
-impl<ResBody> fmt::Debug for Redirect<ResBody> {
+impl<ResBody, L> fmt::Debug for Redirect<ResBody, L>
+where
+    L: fmt::Debug,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Redirect")
             .field("status_code", &self.status_code)
@@ -120,7 +391,10 @@ impl<ResBody> fmt::Debug for Redirect<ResBody> {
     }
 }
 
-impl<ResBody> Clone for Redirect<ResBody> {
+impl<ResBody, L> Clone for Redirect<ResBody, L>
+where
+    L: Clone,
+{
     fn clone(&self) -> Self {
         Self {
             status_code: self.status_code,
@@ -150,9 +424,116 @@ where
 
         *res.status_mut() = self.status_code;
 
-        res.headers_mut()
-            .insert(header::LOCATION, self.location.take().unwrap());
+        if let Some(location) = self.location.take() {
+            res.headers_mut().insert(header::LOCATION, location);
+        }
 
         Poll::Ready(Ok(res))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::ServiceExt;
+
+    async fn location<L>(svc: &Redirect<Body, L>, req: Request<Body>) -> (StatusCode, HeaderValue)
+    where
+        L: Clone,
+        Redirect<Body, L>: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+    {
+        let res = svc.clone().oneshot(req).await.unwrap();
+        (res.status(), res.headers()[header::LOCATION].clone())
+    }
+
+    #[tokio::test]
+    async fn status_codes() {
+        let uri = Uri::from_static("/target");
+        for (svc, status) in [
+            (Redirect::moved_permanently(uri.clone()), 301),
+            (Redirect::found(uri.clone()), 302),
+            (Redirect::see_other(uri.clone()), 303),
+            (Redirect::temporary(uri.clone()), 307),
+            (Redirect::permanent(uri.clone()), 308),
+        ] {
+            let req = Request::new(Body::empty());
+            let (status_code, location) = location(&svc, req).await;
+            assert_eq!(status_code.as_u16(), status);
+            assert_eq!(location, "/target");
+        }
+    }
+
+    #[tokio::test]
+    async fn preserve_query() {
+        let svc = Redirect::temporary(Uri::from_static("/target")).preserve_query(true);
+        let req = Request::get("/source?a=1&b=2").body(Body::empty()).unwrap();
+        assert_eq!(location(&svc, req).await.1, "/target?a=1&b=2");
+
+        let req = Request::get("/source").body(Body::empty()).unwrap();
+        assert_eq!(location(&svc, req).await.1, "/target");
+
+        // the query of the target takes precedence
+        let svc = Redirect::temporary(Uri::from_static("/target?c=3")).preserve_query(true);
+        let req = Request::get("/source?a=1").body(Body::empty()).unwrap();
+        assert_eq!(location(&svc, req).await.1, "/target?c=3");
+
+        let svc = Redirect::with_template(StatusCode::FOUND, "/target#top").preserve_query(true);
+        let req = Request::get("/source?a=1").body(Body::empty()).unwrap();
+        assert_eq!(location(&svc, req).await.1, "/target?a=1#top");
+    }
+
+    #[tokio::test]
+    async fn template() {
+        let svc = Redirect::with_template(
+            StatusCode::PERMANENT_REDIRECT,
+            "https://{host}:8443/v2{path}?{query}&{{x}}",
+        );
+        let req = Request::get("/docs?page=2")
+            .header(header::HOST, "example.com:8080")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            location(&svc, req).await.1,
+            "https://example.com:8443/v2/docs?page=2&{x}"
+        );
+
+        let svc = Redirect::with_template(
+            StatusCode::FOUND,
+            "{scheme}://{authority}|{port}|{path_and_query}",
+        );
+        let req = Request::get("https://example.com:8080/a?b")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            location(&svc, req).await.1,
+            "https://example.com:8080|8080|/a?b"
+        );
+
+        let req = Request::get("/a").body(Body::empty()).unwrap();
+        assert_eq!(location(&svc, req).await.1, "http://||/a");
+    }
+
+    #[tokio::test]
+    async fn fixed_location_accepts_any_request() {
+        let svc: Redirect<Body> = Redirect::temporary(Uri::from_static("/target"));
+        let res = svc.oneshot(()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "/target");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for template in ["/{nope}", "/{path", "/path}", "/\n"] {
+            assert!(parse_location_template(template).is_err(), "{}", template);
+        }
+        assert_eq!(
+            parse_location_template("{{{path}}}").unwrap(),
+            [
+                Part::Literal("{".to_owned()),
+                Part::Placeholder(Placeholder::Path),
+                Part::Literal("}".to_owned())
+            ]
+        );
+    }
+}
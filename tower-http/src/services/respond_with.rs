@@ -0,0 +1,219 @@
+//! Service that responds to all requests with the same response.
+//!
+//! Useful as a fallback for other services, to put a site into maintenance mode, or as a stand-in
+//! for an application in tests.
+//!
+//! # Example
+//!
+//! ```rust
+//! use http::{header, HeaderValue, Request, StatusCode};
+//! use hyper::Body;
+//! use std::time::Duration;
+//! use tower::ServiceExt;
+//! use tower_http::services::{respond_with, ServeDir};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let maintenance = respond_with(StatusCode::SERVICE_UNAVAILABLE)
+//!     .header(header::RETRY_AFTER, HeaderValue::from_static("3600"))
+//!     .header(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"))
+//!     .template_body("{method} {path} is unavailable during maintenance");
+//!
+//! let request = Request::post("/orders").body(Body::empty())?;
+//! let response = maintenance.clone().oneshot(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+//! assert_eq!(response.headers()["retry-after"], "3600");
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert_eq!(body, "POST /orders is unavailable during maintenance");
+//!
+//! // It can also be used as the fallback of other services
+//! let service = ServeDir::new("assets").fallback(respond_with(StatusCode::NOT_FOUND).body("not found"));
+//! # Ok(())
+//! # }
+//! ```
+
+use super::template::{parse_template, Part};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode};
+use http_body::Full;
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+/// Create a [`RespondWith`] service responding with `status`.
+///
+/// See the [module docs](crate::services::respond_with) for more details.
+pub fn respond_with(status: StatusCode) -> RespondWith {
+    RespondWith::new(status)
+}
+
+/// Service that responds to all requests with the same status, headers and body.
+///
+/// See the [module docs](crate::services::respond_with) for more details.
+#[derive(Clone, Debug)]
+pub struct RespondWith {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: ResponseBody,
+}
+
+#[derive(Clone, Debug)]
+enum ResponseBody {
+    Fixed(Bytes),
+    Template(Arc<[Part<Placeholder>]>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Placeholder {
+    Method,
+    Uri,
+    Path,
+    Query,
+    Status,
+}
+
+impl RespondWith {
+    /// Create a new [`RespondWith`] responding with `status`, no headers and an empty body.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: ResponseBody::Fixed(Bytes::new()),
+        }
+    }
+
+    /// Add a header to the response.
+    ///
+    /// Headers with the same name are all sent.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Set the body of the response.
+    pub fn body<B>(mut self, body: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        self.body = ResponseBody::Fixed(body.into());
+        self
+    }
+
+    /// Set the body of the response to a template filled in from each request.
+    ///
+    /// The template can contain these placeholders:
+    ///
+    /// - `{method}`: The method of the request.
+    /// - `{uri}`: The URI of the request.
+    /// - `{path}`: The path of the request URI.
+    /// - `{query}`: The query of the request URI without the leading `?`, empty if there is none.
+    /// - `{status}`: The status of the response, such as `503 Service Unavailable`.
+    ///
+    /// Literal braces are written as `{{` and `}}`.
+    ///
+    /// The values are inserted as they are, so HTML bodies must not include the URI, path or
+    /// query as that would allow cross-site scripting.
+    ///
+    /// # Panics
+    ///
+    /// If `template` contains an unknown placeholder or an unmatched brace.
+    pub fn template_body(mut self, template: &str) -> Self {
+        let parts = parse_template(template, "body", |name| match name {
+            "method" => Some(Placeholder::Method),
+            "uri" => Some(Placeholder::Uri),
+            "path" => Some(Placeholder::Path),
+            "query" => Some(Placeholder::Query),
+            "status" => Some(Placeholder::Status),
+            _ => None,
+        })
+        .unwrap_or_else(|err| panic!("{}", err));
+        self.body = ResponseBody::Template(parts.into());
+        self
+    }
+
+    fn render_body<B>(&self, req: &Request<B>) -> Bytes {
+        let parts = match &self.body {
+            ResponseBody::Fixed(body) => return body.clone(),
+            ResponseBody::Template(parts) => parts,
+        };
+
+        let mut body = String::new();
+        for part in parts.iter() {
+            match part {
+                Part::Literal(literal) => body.push_str(literal),
+                Part::Placeholder(Placeholder::Method) => body.push_str(req.method().as_str()),
+                Part::Placeholder(Placeholder::Uri) => body.push_str(&req.uri().to_string()),
+                Part::Placeholder(Placeholder::Path) => body.push_str(req.uri().path()),
+                Part::Placeholder(Placeholder::Query) => {
+                    body.push_str(req.uri().query().unwrap_or(""))
+                }
+                Part::Placeholder(Placeholder::Status) => body.push_str(&self.status.to_string()),
+            }
+        }
+        body.into()
+    }
+}
+
+impl<ReqBody> Service<Request<ReqBody>> for RespondWith {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let mut res = Response::new(Full::new(self.render_body(&req)));
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers.clone();
+        ready(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header;
+    use hyper::Body;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn fixed_response() {
+        let svc = respond_with(StatusCode::NOT_FOUND)
+            .header(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))
+            .header(header::VARY, HeaderValue::from_static("accept"))
+            .header(header::VARY, HeaderValue::from_static("origin"))
+            .body("nothing here");
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+        assert_eq!(res.headers().get_all(header::VARY).iter().count(), 2);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "nothing here");
+    }
+
+    #[tokio::test]
+    async fn templated_body() {
+        let svc = respond_with(StatusCode::GONE)
+            .template_body("{{{status}}} {method} {uri} path={path} query={query}");
+
+        let req = Request::delete("/a/b?c=d").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "{410 Gone} DELETE /a/b?c=d path=/a/b query=c=d");
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown placeholder `{nope}` in body template")]
+    fn invalid_template() {
+        respond_with(StatusCode::OK).template_body("{nope}");
+    }
+}
@@ -0,0 +1,111 @@
+//! Templates with placeholders filled in from each request, used by [`Redirect`] and
+//! [`RespondWith`].
+//!
+//! [`Redirect`]: super::Redirect
+//! [`RespondWith`]: super::RespondWith
+
+/// A part of a parsed template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Part<P> {
+    Literal(String),
+    Placeholder(P),
+}
+
+/// Parse a template containing `{name}` placeholders, where literal braces are written as `{{`
+/// and `}}`.
+///
+/// `placeholder` maps the names of the placeholders, and `kind` describes the template in error
+/// messages.
+pub(crate) fn parse_template<P, F>(
+    template: &str,
+    kind: &str,
+    placeholder: F,
+) -> Result<Vec<Part<P>>, String>
+where
+    F: Fn(&str) -> Option<P>,
+{
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("unmatched `{{` in {} template {:?}", kind, template))?;
+                let name = &rest[..end];
+                let part = placeholder(name).ok_or_else(|| {
+                    format!(
+                        "unknown placeholder `{{{}}}` in {} template {:?}",
+                        name, kind, template
+                    )
+                })?;
+                chars = rest[end + 1..].chars();
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(Part::Placeholder(part));
+            }
+            '}' => {
+                return Err(format!(
+                    "unmatched `}}` in {} template {:?}",
+                    kind, template
+                ))
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(template: &str) -> Result<Vec<Part<()>>, String> {
+        parse_template(template, "test", |name| (name == "x").then_some(()))
+    }
+
+    #[test]
+    fn parses_templates() {
+        assert_eq!(
+            parse("a{x}{{{x}}}").unwrap(),
+            [
+                Part::Literal("a".to_owned()),
+                Part::Placeholder(()),
+                Part::Literal("{".to_owned()),
+                Part::Placeholder(()),
+                Part::Literal("}".to_owned()),
+            ]
+        );
+        assert_eq!(parse("").unwrap(), []);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert_eq!(
+            parse("{y}").unwrap_err(),
+            "unknown placeholder `{y}` in test template \"{y}\""
+        );
+        assert_eq!(
+            parse("{x").unwrap_err(),
+            "unmatched `{` in test template \"{x\""
+        );
+        assert_eq!(
+            parse("x}").unwrap_err(),
+            "unmatched `}` in test template \"x}\""
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Service for `/.well-known/*` and `robots.txt` style endpoints.
+//!
+//! [`WellKnown`] serves static bodies at fixed paths, such as `/robots.txt` and
+//! `/.well-known/security.txt`, and can generate bodies for paths below a well-known name with a
+//! provider, such as the tokens of ACME `http-01` challenges. Other paths get a
+//! `404 Not Found` response, so requests for these paths should be routed to this service.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use http::{header, HeaderValue, Request, StatusCode};
+//! use hyper::Body;
+//! use std::{collections::HashMap, sync::{Arc, Mutex}};
+//! use tower::ServiceExt;
+//! use tower_http::services::WellKnown;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! // Filled in by the ACME client when it requests a certificate
+//! let challenges = Arc::new(Mutex::new(HashMap::<String, String>::new()));
+//! challenges.lock().unwrap().insert("token".to_owned(), "token.key".to_owned());
+//!
+//! let service = WellKnown::new()
+//!     .robots_txt("User-agent: *\nDisallow: /admin/\n")
+//!     .security_txt("Contact: mailto:security@example.com\n")
+//!     .well_known(
+//!         "apple-app-site-association",
+//!         HeaderValue::from_static("application/json"),
+//!         r#"{"applinks":{}}"#,
+//!     )
+//!     .acme_challenge(move |token| {
+//!         let key = challenges.lock().unwrap().get(&token).cloned();
+//!         async move { key.map(Bytes::from) }
+//!     });
+//!
+//! let request = Request::get("/.well-known/acme-challenge/token").body(Body::empty())?;
+//! let response = service.clone().oneshot(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert_eq!(body, "token.key");
+//!
+//! let request = Request::get("/robots.txt").body(Body::empty())?;
+//! let response = service.oneshot(request).await?;
+//! assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use futures_util::future::{self, BoxFuture, FutureExt};
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::Full;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+const TEXT_PLAIN_UTF_8: &str = "text/plain; charset=utf-8";
+
+/// Service that serves `/.well-known/*` and `robots.txt` style endpoints.
+///
+/// See the [module docs](crate::services::well_known) for more details.
+#[derive(Clone, Default)]
+pub struct WellKnown {
+    files: Arc<HashMap<String, StaticFile>>,
+    providers: Arc<HashMap<String, Provider>>,
+}
+
+#[derive(Clone, Debug)]
+struct StaticFile {
+    content_type: HeaderValue,
+    body: Bytes,
+}
+
+type ProviderFn = Arc<dyn Fn(String) -> BoxFuture<'static, Option<Bytes>> + Send + Sync>;
+
+#[derive(Clone)]
+struct Provider {
+    content_type: HeaderValue,
+    provide: ProviderFn,
+}
+
+impl WellKnown {
+    /// Create a new [`WellKnown`] without any endpoints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `body` at `/robots.txt`.
+    pub fn robots_txt<B>(self, body: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        self.file(
+            "/robots.txt".to_owned(),
+            HeaderValue::from_static(TEXT_PLAIN_UTF_8),
+            body.into(),
+        )
+    }
+
+    /// Serve `body` at `/.well-known/security.txt`, see [RFC 9116].
+    ///
+    /// [RFC 9116]: https://www.rfc-editor.org/rfc/rfc9116
+    pub fn security_txt<B>(self, body: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        self.well_known(
+            "security.txt",
+            HeaderValue::from_static(TEXT_PLAIN_UTF_8),
+            body,
+        )
+    }
+
+    /// Serve `body` with the given `Content-Type` at `/.well-known/{name}`.
+    pub fn well_known<B>(self, name: &str, content_type: HeaderValue, body: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        self.file(well_known_path(name), content_type, body.into())
+    }
+
+    /// Generate the bodies of paths below `/.well-known/{name}/` with `provider`.
+    ///
+    /// `provider` is called with the rest of the path, which is a single non-empty segment, and
+    /// responses are `404 Not Found` if it returns `None`.
+    pub fn provider<F, Fut>(mut self, name: &str, content_type: HeaderValue, provider: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Bytes>> + Send + 'static,
+    {
+        let provide: ProviderFn = Arc::new(move |segment| provider(segment).boxed());
+        Arc::make_mut(&mut self.providers).insert(
+            format!("{}/", well_known_path(name)),
+            Provider {
+                content_type,
+                provide,
+            },
+        );
+        self
+    }
+
+    /// Respond to ACME `http-01` challenges at `/.well-known/acme-challenge/{token}`, see
+    /// [RFC 8555].
+    ///
+    /// `provider` is called with the token and returns the key authorization.
+    ///
+    /// [RFC 8555]: https://www.rfc-editor.org/rfc/rfc8555#section-8.3
+    pub fn acme_challenge<F, Fut>(self, provider: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Bytes>> + Send + 'static,
+    {
+        self.provider(
+            "acme-challenge",
+            HeaderValue::from_static("application/octet-stream"),
+            provider,
+        )
+    }
+
+    fn file(mut self, path: String, content_type: HeaderValue, body: Bytes) -> Self {
+        Arc::make_mut(&mut self.files).insert(path, StaticFile { content_type, body });
+        self
+    }
+
+    fn respond(&self, path: &str) -> BoxFuture<'static, Response<Full<Bytes>>> {
+        if let Some(file) = self.files.get(path) {
+            let res = response(file.content_type.clone(), file.body.clone());
+            return future::ready(res).boxed();
+        }
+
+        let provided = self.providers.iter().find_map(|(prefix, provider)| {
+            let segment = path.strip_prefix(prefix.as_str())?;
+            if segment.is_empty() || segment.contains('/') {
+                return None;
+            }
+            Some((provider.clone(), segment.to_owned()))
+        });
+        match provided {
+            Some((provider, segment)) => async move {
+                match (provider.provide)(segment).await {
+                    Some(body) => response(provider.content_type, body),
+                    None => status(StatusCode::NOT_FOUND),
+                }
+            }
+            .boxed(),
+            None => future::ready(status(StatusCode::NOT_FOUND)).boxed(),
+        }
+    }
+}
+
+fn well_known_path(name: &str) -> String {
+    format!("/.well-known/{}", name.trim_matches('/'))
+}
+
+fn response(content_type: HeaderValue, body: Bytes) -> Response<Full<Bytes>> {
+    let mut res = Response::new(Full::new(body));
+    res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    res
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut res = Response::new(Full::default());
+    *res.status_mut() = status;
+    res
+}
+
+impl fmt::Debug for WellKnown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WellKnown")
+            .field("files", &self.files)
+            .field("providers", &self.providers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<ReqBody> Service<Request<ReqBody>> for WellKnown {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            let mut res = status(StatusCode::METHOD_NOT_ALLOWED);
+            res.headers_mut()
+                .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+            return future::ready(Ok(res)).boxed();
+        }
+
+        self.respond(req.uri().path()).map(Ok).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::ServiceExt;
+
+    async fn get(svc: &WellKnown, uri: &str) -> (StatusCode, Option<HeaderValue>, Bytes) {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let content_type = res.headers().get(header::CONTENT_TYPE).cloned();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, content_type, body)
+    }
+
+    #[tokio::test]
+    async fn static_files() {
+        let svc = WellKnown::new()
+            .robots_txt("User-agent: *\n")
+            .security_txt("Contact: mailto:security@example.com\n")
+            .well_known(
+                "/openid-configuration/",
+                HeaderValue::from_static("application/json"),
+                "{}",
+            );
+
+        let (status, content_type, body) = get(&svc, "/robots.txt").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type.unwrap(), TEXT_PLAIN_UTF_8);
+        assert_eq!(body, "User-agent: *\n");
+
+        let (_, _, body) = get(&svc, "/.well-known/security.txt").await;
+        assert_eq!(body, "Contact: mailto:security@example.com\n");
+
+        let (_, content_type, body) = get(&svc, "/.well-known/openid-configuration").await;
+        assert_eq!(content_type.unwrap(), "application/json");
+        assert_eq!(body, "{}");
+
+        for uri in ["/", "/robots.txt/", "/.well-known/", "/.well-known/other"] {
+            let (status, content_type, _) = get(&svc, uri).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+            assert!(content_type.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn providers() {
+        let svc = WellKnown::new().acme_challenge(|token| async move {
+            if token == "known" {
+                Some(Bytes::from("known.key"))
+            } else {
+                None
+            }
+        });
+
+        let (status, content_type, body) = get(&svc, "/.well-known/acme-challenge/known").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type.unwrap(), "application/octet-stream");
+        assert_eq!(body, "known.key");
+
+        for uri in [
+            "/.well-known/acme-challenge/unknown",
+            "/.well-known/acme-challenge/",
+            "/.well-known/acme-challenge/known/nested",
+            "/.well-known/acme-challenge",
+        ] {
+            let (status, _, _) = get(&svc, uri).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn only_get_and_head() {
+        let svc = WellKnown::new().robots_txt("User-agent: *\n");
+
+        let req = Request::head("/robots.txt").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::post("/robots.txt").body(Body::empty()).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD");
+    }
+}
@@ -31,10 +31,53 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! The status can also be computed from the original status and headers. For example a gateway
+//! can hide the details of errors from its backends:
+//!
+//! ```
+//! use tower_http::set_status::SetStatusLayer;
+//! use http::{HeaderMap, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, Service, ServiceExt};
+//!
+//! async fn backend(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let mut response = Response::new(Body::from("connection to db-7 refused"));
+//!     *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+//!     Ok(response)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         SetStatusLayer::map(|status, _: &HeaderMap| {
+//!             if status.is_server_error() {
+//!                 StatusCode::BAD_GATEWAY
+//!             } else {
+//!                 status
+//!             }
+//!         })
+//!         // don't leak the error message
+//!         .clear_body(),
+//!     )
+//!     .service_fn(backend);
+//!
+//! let request = Request::builder().body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+//! assert!(hyper::body::to_bytes(response.into_body()).await?.is_empty());
+//! #
+//! # Ok(())
+//! # }
+//! ```
 
-use http::{Request, Response, StatusCode};
+use http::{header, HeaderMap, Request, Response, StatusCode};
 use pin_project_lite::pin_project;
 use std::{
+    fmt,
     future::Future,
     pin::Pin,
     task::{Context, Poll},
@@ -42,10 +85,66 @@ use std::{
 use tower_layer::Layer;
 use tower_service::Service;
 
-/// Layer that applies [`SetStatus`] which overrides the status codes.
+/// Trait for setting the status of responses.
+///
+/// This is implemented for [`StatusCode`], which sets that status, for closures with the
+/// signature `FnOnce(StatusCode, &HeaderMap) -> StatusCode`, which are called with the status
+/// and headers of the response, and for [`ClearBody`].
+pub trait MapStatus<B> {
+    /// Set the status of `response`.
+    fn map_status(self, response: &mut Response<B>);
+}
+
+impl<B> MapStatus<B> for StatusCode {
+    fn map_status(self, response: &mut Response<B>) {
+        *response.status_mut() = self;
+    }
+}
+
+impl<B, F> MapStatus<B> for F
+where
+    F: FnOnce(StatusCode, &HeaderMap) -> StatusCode,
+{
+    fn map_status(self, response: &mut Response<B>) {
+        let status = self(response.status(), response.headers());
+        *response.status_mut() = status;
+    }
+}
+
+/// [`MapStatus`] that clears the body of responses whose status it changes.
+///
+/// The body is replaced with an empty one and the `Content-Length`, `Content-Type` and
+/// `Content-Encoding` headers are removed. Responses whose status is left unchanged are passed
+/// through as is.
+///
+/// Created with [`SetStatusLayer::clear_body`].
 #[derive(Debug, Clone, Copy)]
-pub struct SetStatusLayer {
-    status: StatusCode,
+pub struct ClearBody<M> {
+    map: M,
+}
+
+impl<B, M> MapStatus<B> for ClearBody<M>
+where
+    B: Default,
+    M: MapStatus<B>,
+{
+    fn map_status(self, response: &mut Response<B>) {
+        let original = response.status();
+        self.map.map_status(response);
+        if response.status() != original {
+            *response.body_mut() = B::default();
+            let headers = response.headers_mut();
+            headers.remove(header::CONTENT_LENGTH);
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::CONTENT_ENCODING);
+        }
+    }
+}
+
+/// Layer that applies [`SetStatus`] which overrides the status codes.
+#[derive(Clone, Copy)]
+pub struct SetStatusLayer<M = StatusCode> {
+    map: M,
 }
 
 impl SetStatusLayer {
@@ -53,25 +152,60 @@ impl SetStatusLayer {
     ///
     /// The response status code will be `status` regardless of what the inner service returns.
     pub fn new(status: StatusCode) -> Self {
-        SetStatusLayer { status }
+        SetStatusLayer { map: status }
+    }
+}
+
+impl<M> SetStatusLayer<M> {
+    /// Create a new [`SetStatusLayer`] that sets the status returned by `map`.
+    ///
+    /// `map` is called with the status and headers of the responses of the inner service.
+    pub fn map(map: M) -> Self
+    where
+        M: Fn(StatusCode, &HeaderMap) -> StatusCode + Clone,
+    {
+        SetStatusLayer { map }
+    }
+
+    /// Clear the body of responses whose status is changed.
+    ///
+    /// The body is preserved by default. See [`ClearBody`] for more details.
+    pub fn clear_body(self) -> SetStatusLayer<ClearBody<M>> {
+        SetStatusLayer {
+            map: ClearBody { map: self.map },
+        }
     }
 }
 
-impl<S> Layer<S> for SetStatusLayer {
-    type Service = SetStatus<S>;
+impl<M> fmt::Debug for SetStatusLayer<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetStatusLayer")
+            .field("map", &std::any::type_name::<M>())
+            .finish()
+    }
+}
+
+impl<S, M> Layer<S> for SetStatusLayer<M>
+where
+    M: Clone,
+{
+    type Service = SetStatus<S, M>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        SetStatus::new(inner, self.status)
+        SetStatus {
+            inner,
+            map: self.map.clone(),
+        }
     }
 }
 
 /// Middleware to override status codes.
 ///
 /// See the [module docs](self) for more details.
-#[derive(Debug, Clone, Copy)]
-pub struct SetStatus<S> {
+#[derive(Clone, Copy)]
+pub struct SetStatus<S, M = StatusCode> {
     inner: S,
-    status: StatusCode,
+    map: M,
 }
 
 impl<S> SetStatus<S> {
@@ -79,11 +213,9 @@ impl<S> SetStatus<S> {
     ///
     /// The response status code will be `status` regardless of what the inner service returns.
     pub fn new(inner: S, status: StatusCode) -> Self {
-        Self { status, inner }
+        Self { map: status, inner }
     }
 
-    define_inner_service_accessors!();
-
     /// Returns a new [`Layer`] that wraps services with a `SetStatus` middleware.
     ///
     /// [`Layer`]: tower_layer::Layer
@@ -92,13 +224,50 @@ impl<S> SetStatus<S> {
     }
 }
 
-impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetStatus<S>
+impl<S, M> SetStatus<S, M> {
+    /// Create a new [`SetStatus`] that sets the status returned by `map`.
+    ///
+    /// See [`SetStatusLayer::map`] for more details.
+    pub fn map(inner: S, map: M) -> Self
+    where
+        M: Fn(StatusCode, &HeaderMap) -> StatusCode + Clone,
+    {
+        Self { inner, map }
+    }
+
+    /// Clear the body of responses whose status is changed.
+    ///
+    /// See [`SetStatusLayer::clear_body`] for more details.
+    pub fn clear_body(self) -> SetStatus<S, ClearBody<M>> {
+        SetStatus {
+            inner: self.inner,
+            map: ClearBody { map: self.map },
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S, M> fmt::Debug for SetStatus<S, M>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetStatus")
+            .field("inner", &self.inner)
+            .field("map", &std::any::type_name::<M>())
+            .finish()
+    }
+}
+
+impl<S, M, ReqBody, ResBody> Service<Request<ReqBody>> for SetStatus<S, M>
 where
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    M: MapStatus<ResBody> + Clone,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = ResponseFuture<S::Future>;
+    type Future = ResponseFuture<S::Future, M>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
@@ -107,30 +276,97 @@ where
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
         ResponseFuture {
             inner: self.inner.call(req),
-            status: Some(self.status),
+            map: Some(self.map.clone()),
         }
     }
 }
 
 pin_project! {
     /// Response future for [`SetStatus`].
-    pub struct ResponseFuture<F> {
+    pub struct ResponseFuture<F, M = StatusCode> {
         #[pin]
         inner: F,
-        status: Option<StatusCode>,
+        map: Option<M>,
     }
 }
 
-impl<F, B, E> Future for ResponseFuture<F>
+impl<F, M, B, E> Future for ResponseFuture<F, M>
 where
     F: Future<Output = Result<Response<B>, E>>,
+    M: MapStatus<B>,
 {
     type Output = F::Output;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
         let mut response = futures_core::ready!(this.inner.poll(cx)?);
-        *response.status_mut() = this.status.take().expect("future polled after completion");
+        this.map
+            .take()
+            .expect("future polled after completion")
+            .map_status(&mut response);
         Poll::Ready(Ok(response))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceBuilder, ServiceExt};
+
+    async fn not_found(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::CONTENT_LENGTH, "9")
+            .body(Body::from("not found"))
+            .unwrap())
+    }
+
+    #[tokio::test]
+    async fn maps_status() {
+        let svc = ServiceBuilder::new()
+            .layer(SetStatusLayer::map(
+                |status: StatusCode, headers: &HeaderMap| {
+                    if status == StatusCode::NOT_FOUND && headers.contains_key(header::CONTENT_TYPE)
+                    {
+                        StatusCode::OK
+                    } else {
+                        status
+                    }
+                },
+            ))
+            .service_fn(not_found);
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "not found");
+    }
+
+    #[tokio::test]
+    async fn clears_body_when_status_changes() {
+        let svc = SetStatus::new(service_fn(not_found), StatusCode::GONE).clear_body();
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::GONE);
+        assert!(!res.headers().contains_key(header::CONTENT_TYPE));
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_body_when_status_is_unchanged() {
+        let svc = ServiceBuilder::new()
+            .layer(SetStatusLayer::new(StatusCode::NOT_FOUND).clear_body())
+            .service_fn(not_found);
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "not found");
+    }
+}
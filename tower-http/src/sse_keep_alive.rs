@@ -0,0 +1,399 @@
+//! Middleware that keeps idle server-sent event streams alive.
+//!
+//! Proxies and load balancers often close connections that have been idle for a while, which
+//! breaks long-lived `text/event-stream` responses that only send events occasionally.
+//! [`SseKeepAlive`] injects a comment line, `:keepalive` by default, into the body of such
+//! responses whenever no data has been sent for the configured interval. Clients ignore comment
+//! lines, so the events seen by them are unchanged.
+//!
+//! A comment is only injected when the body is at the start of a line, that is when nothing has
+//! been sent yet or the last chunk ended with `\n`, so an event that is sent in several chunks is
+//! never broken up. Responses with other content types are passed through unchanged.
+//!
+//! # Compression
+//!
+//! Apply this middleware inside [`Compression`](crate::compression::Compression), so the comments
+//! are compressed along with the rest of the body. The compression encoder flushes whenever the
+//! body it reads from has no more data ready, which is the case right after a comment is injected,
+//! so the comments reach the client immediately rather than sitting in the encoder's buffer.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::sse_keep_alive::SseKeepAliveLayer;
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let (sender, body) = Body::channel();
+//!     // send events with `sender`...
+//!     # drop(sender);
+//!     Ok(Response::builder()
+//!         .header(header::CONTENT_TYPE, "text/event-stream")
+//!         .body(body)
+//!         .unwrap())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     // send a comment after 15 seconds without events
+//!     .layer(SseKeepAliveLayer::new(Duration::from_secs(15)))
+//!     .service_fn(handle);
+//!
+//! let request = Request::new(Body::empty());
+//! let response = service.ready().await?.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+use http::{header, HeaderMap, Request, Response};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep, Instant, Sleep};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies [`SseKeepAlive`] which keeps idle server-sent event streams alive.
+///
+/// See the [module docs](crate::sse_keep_alive) for an example.
+#[derive(Debug, Clone)]
+pub struct SseKeepAliveLayer {
+    interval: Duration,
+    frame: Bytes,
+}
+
+impl SseKeepAliveLayer {
+    /// Create a new `SseKeepAliveLayer` that sends a comment after `interval` without data.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            frame: Bytes::from_static(b":keepalive\n"),
+        }
+    }
+
+    /// Set the text of the comment that is sent. Defaults to `keepalive`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `comment` contains a line break.
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.frame = comment_frame(comment);
+        self
+    }
+}
+
+fn comment_frame(comment: &str) -> Bytes {
+    assert!(
+        !comment.contains(['\n', '\r']),
+        "keep-alive comment must not contain line breaks"
+    );
+    format!(":{}\n", comment).into()
+}
+
+impl<S> Layer<S> for SseKeepAliveLayer {
+    type Service = SseKeepAlive<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SseKeepAlive {
+            inner,
+            interval: self.interval,
+            frame: self.frame.clone(),
+        }
+    }
+}
+
+/// Middleware that keeps idle server-sent event streams alive.
+///
+/// See the [module docs](crate::sse_keep_alive) for an example.
+#[derive(Debug, Clone)]
+pub struct SseKeepAlive<S> {
+    inner: S,
+    interval: Duration,
+    frame: Bytes,
+}
+
+impl<S> SseKeepAlive<S> {
+    /// Create a new `SseKeepAlive` that sends a comment after `interval` without data.
+    pub fn new(inner: S, interval: Duration) -> Self {
+        SseKeepAliveLayer::new(interval).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `SseKeepAlive` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(interval: Duration) -> SseKeepAliveLayer {
+        SseKeepAliveLayer::new(interval)
+    }
+
+    /// Set the text of the comment that is sent.
+    ///
+    /// See [`SseKeepAliveLayer::comment`] for more details.
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.frame = comment_frame(comment);
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SseKeepAlive<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            interval: self.interval,
+            frame: self.frame.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`SseKeepAlive`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        interval: Duration,
+        frame: Bytes,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+
+        let is_event_stream = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.starts_with("text/event-stream"));
+        let interval = *this.interval;
+        let frame = this.frame.clone();
+
+        Poll::Ready(Ok(res.map(|body| ResponseBody {
+            inner: body,
+            sleep: if is_event_stream {
+                Some(Box::pin(sleep(interval)))
+            } else {
+                None
+            },
+            interval,
+            frame,
+            at_line_start: true,
+        })))
+    }
+}
+
+pin_project! {
+    /// Response body for [`SseKeepAlive`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        inner: B,
+        // `None` if the response is not an event stream
+        sleep: Option<Pin<Box<Sleep>>>,
+        interval: Duration,
+        frame: Bytes,
+        at_line_start: bool,
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+
+        match this.inner.poll_data(cx) {
+            Poll::Ready(Some(Ok(mut data))) => {
+                let data = data.copy_to_bytes(data.remaining());
+                if let Some(last) = data.last() {
+                    *this.at_line_start = *last == b'\n';
+                }
+                if let Some(sleep) = this.sleep {
+                    sleep.as_mut().reset(Instant::now() + *this.interval);
+                }
+                return Poll::Ready(Some(Ok(data)));
+            }
+            Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+
+        let sleep = match this.sleep {
+            Some(sleep) => sleep,
+            None => return Poll::Pending,
+        };
+        loop {
+            ready!(sleep.as_mut().poll(cx));
+            sleep.as_mut().reset(Instant::now() + *this.interval);
+            // never break up a line the inner body is in the middle of
+            if *this.at_line_start {
+                return Poll::Ready(Some(Ok(this.frame.clone())));
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let hint = self.inner.size_hint();
+        if self.sleep.is_some() {
+            let mut without_upper = SizeHint::new();
+            without_upper.set_lower(hint.lower());
+            without_upper
+        } else {
+            hint
+        }
+    }
+}
+
+impl<B> fmt::Debug for ResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody")
+            .field("interval", &self.interval)
+            .field("frame", &self.frame)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    async fn event_stream(content_type: &str) -> (hyper::body::Sender, ResponseBody<Body>) {
+        let (sender, body) = Body::channel();
+        let content_type = content_type.to_owned();
+        let mut body = Some(body);
+        let svc = SseKeepAliveLayer::new(INTERVAL).layer(service_fn(move |_| {
+            let res = Response::builder()
+                .header(header::CONTENT_TYPE, &content_type)
+                .body(body.take().unwrap())
+                .unwrap();
+            async move { Ok::<_, BoxError>(res) }
+        }));
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        (sender, res.into_body())
+    }
+
+    #[tokio::test]
+    async fn sends_comments_when_idle() {
+        let (mut sender, mut body) = event_stream("text/event-stream; charset=utf-8").await;
+
+        assert_eq!(body.data().await.unwrap().unwrap(), ":keepalive\n");
+
+        sender.send_data("data: a\n\n".into()).await.unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), "data: a\n\n");
+        assert_eq!(body.data().await.unwrap().unwrap(), ":keepalive\n");
+
+        drop(sender);
+        assert!(body.data().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn does_not_break_up_lines() {
+        let (mut sender, mut body) = event_stream("text/event-stream").await;
+
+        sender.send_data("data: a".into()).await.unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), "data: a");
+        tokio::time::sleep(INTERVAL * 3).await;
+        sender.send_data("\n\n".into()).await.unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), "\n\n");
+    }
+
+    #[tokio::test]
+    async fn other_responses_are_unchanged() {
+        let (sender, mut body) = event_stream("text/plain").await;
+
+        let data = tokio::time::timeout(INTERVAL * 3, body.data()).await;
+        assert!(data.is_err());
+        drop(sender);
+        assert!(body.data().await.is_none());
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn comments_are_flushed_through_compression() {
+        use crate::compression::CompressionLayer;
+        use flate2::write::GzDecoder;
+        use std::io::Write;
+
+        let (sender, body) = Body::channel();
+        let mut body = Some(body);
+        let svc = tower::ServiceBuilder::new()
+            .layer(CompressionLayer::new())
+            .layer(SseKeepAliveLayer::new(INTERVAL))
+            .service(service_fn(move |_| {
+                let res = Response::builder()
+                    .header(header::CONTENT_TYPE, "text/event-stream")
+                    .body(body.take().unwrap())
+                    .unwrap();
+                async move { Ok::<_, BoxError>(res) }
+            }));
+
+        let req = Request::builder()
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let mut body = svc.oneshot(req).await.unwrap().into_body();
+
+        let mut decoder = GzDecoder::new(Vec::new());
+        while decoder.get_ref().is_empty() {
+            let data = body.data().await.unwrap().unwrap();
+            decoder.write_all(&data).unwrap();
+            decoder.flush().unwrap();
+        }
+        assert_eq!(decoder.get_ref(), b":keepalive\n");
+        drop(sender);
+    }
+}
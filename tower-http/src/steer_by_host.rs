@@ -0,0 +1,354 @@
+//! Middleware that routes requests to different services based on their host.
+//!
+//! [`SteerByHost`] picks a service by the host of the request URI, which is where the
+//! `:authority` of HTTP/2 requests ends up, or the `Host` header of HTTP/1 requests. Hosts are
+//! matched case-insensitively and without the port. Patterns are either exact names, such as
+//! `example.com`, or wildcards such as `*.example.com`, which match any subdomain of
+//! `example.com` but not `example.com` itself.
+//!
+//! Exact names take precedence over wildcards, and longer wildcards over shorter ones. Requests
+//! that match no pattern, or don't have a host, are sent to the wrapped service.
+//!
+//! All services must have the same type, use [`BoxCloneService`] to combine services of
+//! different types. Services are cloned and driven to readiness for each request, so they must
+//! implement [`Clone`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{service_fn, util::BoxCloneService, ServiceBuilder, ServiceExt};
+//! use tower_http::steer_by_host::SteerByHostLayer;
+//!
+//! type Service = BoxCloneService<Request<Body>, Response<Body>, Infallible>;
+//!
+//! fn respond_with(text: &'static str) -> Service {
+//!     BoxCloneService::new(service_fn(move |_: Request<Body>| async move {
+//!         Ok::<_, Infallible>(Response::new(Body::from(text)))
+//!     }))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let service = ServiceBuilder::new()
+//!     .layer(
+//!         SteerByHostLayer::new()
+//!             .host("api.example.com", respond_with("api"))
+//!             .host("*.example.com", respond_with("tenant")),
+//!     )
+//!     .service(respond_with("default"));
+//!
+//! let request = Request::builder()
+//!     .uri("/")
+//!     .header("host", "acme.example.com:8080")
+//!     .body(Body::empty())?;
+//! let response = service.clone().oneshot(request).await?;
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert_eq!(body, "tenant");
+//!
+//! let request = Request::get("https://api.example.com/").body(Body::empty())?;
+//! let response = service.oneshot(request).await?;
+//! let body = hyper::body::to_bytes(response.into_body()).await?;
+//! assert_eq!(body, "api");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`BoxCloneService`]: https://docs.rs/tower/latest/tower/util/struct.BoxCloneService.html
+
+use crate::ready::take_ready;
+use http::{header, uri::Authority, Request};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::util::Oneshot;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies [`SteerByHost`], which routes requests based on their host.
+///
+/// See the [module docs](crate::steer_by_host) for more details.
+#[derive(Clone)]
+pub struct SteerByHostLayer<S> {
+    hosts: Hosts<S>,
+}
+
+impl<S> SteerByHostLayer<S> {
+    /// Create a new [`SteerByHostLayer`] without any hosts.
+    pub fn new() -> Self {
+        Self {
+            hosts: Hosts::default(),
+        }
+    }
+
+    /// Send requests for hosts matching `pattern` to `service`.
+    ///
+    /// `pattern` is either an exact name, such as `example.com`, or a wildcard such as
+    /// `*.example.com`. Adding the same pattern again replaces the service.
+    pub fn host(mut self, pattern: &str, service: S) -> Self
+    where
+        S: Clone,
+    {
+        self.hosts.insert(pattern, service);
+        self
+    }
+}
+
+impl<S> Default for SteerByHostLayer<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> fmt::Debug for SteerByHostLayer<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SteerByHostLayer")
+            .field("hosts", &self.hosts)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for SteerByHostLayer<S>
+where
+    S: Clone,
+{
+    type Service = SteerByHost<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SteerByHost {
+            inner,
+            hosts: self.hosts.clone(),
+        }
+    }
+}
+
+/// Middleware that routes requests to different services based on their host.
+///
+/// See the [module docs](crate::steer_by_host) for more details.
+#[derive(Clone)]
+pub struct SteerByHost<S> {
+    inner: S,
+    hosts: Hosts<S>,
+}
+
+impl<S> SteerByHost<S> {
+    /// Create a new [`SteerByHost`] sending requests that match no host to `inner`.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            hosts: Hosts::default(),
+        }
+    }
+
+    /// Send requests for hosts matching `pattern` to `service`.
+    ///
+    /// See [`SteerByHostLayer::host`] for more details.
+    pub fn host(mut self, pattern: &str, service: S) -> Self
+    where
+        S: Clone,
+    {
+        self.hosts.insert(pattern, service);
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S> fmt::Debug for SteerByHost<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SteerByHost")
+            .field("inner", &self.inner)
+            .field("hosts", &self.hosts)
+            .finish()
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for SteerByHost<S>
+where
+    S: Service<Request<ReqBody>> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Oneshot<S, Request<ReqBody>>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Only the service the request is routed to is driven to readiness, in `call`.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let service = match request_host(&req).and_then(|host| self.hosts.get(&host)) {
+            Some(service) => service.clone(),
+            None => take_ready(&mut self.inner),
+        };
+        Oneshot::new(service, req)
+    }
+}
+
+/// The host of the request without port or trailing dot, in lowercase.
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    let host = match req.uri().host() {
+        Some(host) => host.to_owned(),
+        None => {
+            let host = req.headers().get(header::HOST)?;
+            Authority::try_from(host.as_bytes()).ok()?.host().to_owned()
+        }
+    };
+    Some(normalize(&host))
+}
+
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+struct Hosts<S> {
+    exact: Arc<HashMap<String, S>>,
+    // the suffixes of wildcard patterns, including the leading dot, longest first
+    wildcards: Arc<Vec<(String, S)>>,
+}
+
+impl<S> Hosts<S> {
+    fn insert(&mut self, pattern: &str, service: S)
+    where
+        S: Clone,
+    {
+        let pattern = normalize(pattern);
+        match pattern.strip_prefix('*') {
+            Some(suffix) => {
+                let wildcards = Arc::make_mut(&mut self.wildcards);
+                wildcards.retain(|(existing, _)| existing != suffix);
+                wildcards.push((suffix.to_owned(), service));
+                wildcards.sort_by_key(|(suffix, _)| Reverse(suffix.len()));
+            }
+            None => {
+                Arc::make_mut(&mut self.exact).insert(pattern, service);
+            }
+        }
+    }
+
+    fn get(&self, host: &str) -> Option<&S> {
+        self.exact.get(host).or_else(|| {
+            self.wildcards
+                .iter()
+                .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+                .map(|(_, service)| service)
+        })
+    }
+}
+
+impl<S> Default for Hosts<S> {
+    fn default() -> Self {
+        Self {
+            exact: Arc::new(HashMap::new()),
+            wildcards: Arc::new(Vec::new()),
+        }
+    }
+}
+
+impl<S> Clone for Hosts<S> {
+    fn clone(&self) -> Self {
+        Self {
+            exact: self.exact.clone(),
+            wildcards: self.wildcards.clone(),
+        }
+    }
+}
+
+impl<S> fmt::Debug for Hosts<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.exact.keys())
+            .entries(
+                self.wildcards
+                    .iter()
+                    .map(|(suffix, _)| format!("*{}", suffix)),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Response;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{service_fn, util::BoxCloneService, ServiceExt};
+
+    type BoxService = BoxCloneService<Request<Body>, Response<&'static str>, Infallible>;
+
+    fn named(name: &'static str) -> BoxService {
+        BoxCloneService::new(service_fn(move |_: Request<Body>| async move {
+            Ok(Response::new(name))
+        }))
+    }
+
+    async fn route(svc: &SteerByHost<BoxService>, uri: &str, host: Option<&str>) -> &'static str {
+        let mut req = Request::get(uri);
+        if let Some(host) = host {
+            req = req.header(header::HOST, host);
+        }
+        let res = svc
+            .clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        res.into_body()
+    }
+
+    #[tokio::test]
+    async fn routes_by_host() {
+        let svc = SteerByHost::new(named("default"))
+            .host("example.com", named("apex"))
+            .host("*.example.com", named("subdomain"))
+            .host("*.eu.example.com", named("eu"))
+            .host("Static.Example.COM.", named("static"));
+
+        for (host, expected) in [
+            ("example.com", "apex"),
+            ("EXAMPLE.com:8080", "apex"),
+            ("example.com.", "apex"),
+            ("www.example.com", "subdomain"),
+            ("a.b.example.com", "subdomain"),
+            ("paris.eu.example.com", "eu"),
+            ("eu.example.com", "subdomain"),
+            ("static.example.com", "static"),
+            ("badexample.com", "default"),
+            ("example.org", "default"),
+            ("[::1]:8080", "default"),
+        ] {
+            assert_eq!(route(&svc, "/", Some(host)).await, expected, "{}", host);
+        }
+
+        assert_eq!(route(&svc, "/", None).await, "default");
+        assert_eq!(route(&svc, "/", Some("not a host")).await, "default");
+
+        // the host of the URI takes precedence over the `Host` header
+        assert_eq!(
+            route(&svc, "https://www.example.com/", Some("example.com")).await,
+            "subdomain"
+        );
+    }
+
+    #[tokio::test]
+    async fn layer() {
+        let svc = SteerByHostLayer::new()
+            .host("example.com", named("apex"))
+            .host("example.com", named("replaced"))
+            .layer(named("default"));
+
+        assert_eq!(route(&svc, "/", Some("example.com")).await, "replaced");
+        assert_eq!(route(&svc, "/", Some("example.org")).await, "default");
+    }
+}
@@ -0,0 +1,556 @@
+//! Middleware for sticky sessions, which route requests from a client to the same backend.
+//!
+//! Sticky sessions are split into two middleware around a service that steers requests to
+//! backends, such as a load balancer:
+//!
+//! - [`ExtractAffinity`] reads the affinity token from a cookie or header of the request and
+//!   inserts it as an [`Affinity`] request extension. The steering service uses it to pick the
+//!   backend the client was previously sent to.
+//! - [`SetAffinityCookie`] sets the affinity cookie on responses whose extensions contain a
+//!   [`Backend`], which the steering service inserts to tell which backend it chose.
+//!
+//! The token is a hash of the backend identity. The steering service finds the backend of a token
+//! by hashing its backends the same way, with [`hash_backend`] unless the hash is changed with
+//! [`SetAffinityCookieLayer::hash`]. The default hash isn't keyed, so clients can recover backend
+//! identities from a small space such as private addresses by hashing guesses. Set a keyed hash if
+//! backend identities must stay private.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::sticky_session::{
+//!     hash_backend, Affinity, Backend, ExtractAffinityLayer, SetAffinityCookieLayer,
+//! };
+//!
+//! const BACKENDS: [&str; 2] = ["10.0.0.1:8080", "10.0.0.2:8080"];
+//!
+//! async fn steer(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let backend = req
+//!         .extensions()
+//!         .get::<Affinity>()
+//!         .and_then(|affinity| BACKENDS.iter().find(|b| hash_backend(b) == affinity.0))
+//!         .unwrap_or(&BACKENDS[0]);
+//!
+//!     // forward the request to the backend...
+//!     let mut res = Response::new(Body::empty());
+//!     res.extensions_mut().insert(Backend(backend.to_string()));
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(ExtractAffinityLayer::cookie("backend"))
+//!     .layer(SetAffinityCookieLayer::new("backend"))
+//!     .service_fn(steer);
+//!
+//! let request = Request::get("/").body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! let token = hash_backend("10.0.0.1:8080");
+//! assert_eq!(
+//!     response.headers()[header::SET_COOKIE],
+//!     format!("backend={}; Path=/; Secure; HttpOnly; SameSite=Lax", token),
+//! );
+//!
+//! // the cookie isn't set again while the client sticks to the backend
+//! let request = Request::get("/")
+//!     .header(header::COOKIE, format!("backend={}", token))
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert!(!response.headers().contains_key(header::SET_COOKIE));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    cookies::{self, attribute_value, cookie_name},
+    fnv::fnv1a,
+};
+use futures_util::ready;
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Request, Response,
+};
+use pin_project_lite::pin_project;
+use std::{
+    borrow::Cow,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+pub use crate::cookies::SameSite;
+
+/// Affinity token sent by the client.
+///
+/// Inserted as a request extension by [`ExtractAffinity`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Affinity(pub String);
+
+/// Identity of the backend a request was sent to, such as its address.
+///
+/// Inserted as a response extension by the steering service, and read by [`SetAffinityCookie`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Backend(pub String);
+
+/// Hash a backend identity into an affinity token.
+///
+/// This is the default hash of [`SetAffinityCookieLayer`]. It is a 64-bit FNV-1a hash encoded as
+/// 16 lowercase hex digits, which is stable across processes and releases. It isn't keyed, so
+/// clients can check guesses of backend identities against it. Use [`SetAffinityCookieLayer::hash`]
+/// with a keyed hash if that matters.
+pub fn hash_backend(backend: &str) -> String {
+    format!("{:016x}", fnv1a(backend.as_bytes()))
+}
+
+/// Get the value of the first cookie called `name` from the `Cookie` headers.
+fn get_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<Cow<'a, str>> {
+    cookies::parse(headers)
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value)
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    Cookie(Arc<str>),
+    Header(HeaderName),
+}
+
+/// Layer that applies [`ExtractAffinity`] which inserts the affinity token of requests as an
+/// [`Affinity`] extension.
+///
+/// See the [module docs](crate::sticky_session) for an example.
+#[derive(Debug, Clone)]
+pub struct ExtractAffinityLayer {
+    source: Source,
+}
+
+impl ExtractAffinityLayer {
+    /// Read the affinity token from the cookie called `name`.
+    pub fn cookie(name: &str) -> Self {
+        Self {
+            source: Source::Cookie(name.into()),
+        }
+    }
+
+    /// Read the affinity token from the header `name`.
+    pub fn header(name: HeaderName) -> Self {
+        Self {
+            source: Source::Header(name),
+        }
+    }
+}
+
+impl<S> Layer<S> for ExtractAffinityLayer {
+    type Service = ExtractAffinity<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExtractAffinity {
+            inner,
+            source: self.source.clone(),
+        }
+    }
+}
+
+/// Middleware that inserts the affinity token of requests as an [`Affinity`] extension.
+///
+/// See the [module docs](crate::sticky_session) for an example.
+#[derive(Debug, Clone)]
+pub struct ExtractAffinity<S> {
+    inner: S,
+    source: Source,
+}
+
+impl<S> ExtractAffinity<S> {
+    /// Create a new `ExtractAffinity` that reads the affinity token from the cookie called
+    /// `name`.
+    pub fn cookie(inner: S, name: &str) -> Self {
+        ExtractAffinityLayer::cookie(name).layer(inner)
+    }
+
+    /// Create a new `ExtractAffinity` that reads the affinity token from the header `name`.
+    pub fn header(inner: S, name: HeaderName) -> Self {
+        ExtractAffinityLayer::header(name).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ExtractAffinity<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let token = match &self.source {
+            Source::Cookie(name) => get_cookie(req.headers(), name),
+            Source::Header(name) => req
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| Cow::Borrowed(value.trim())),
+        };
+        if let Some(token) = token.filter(|token| !token.is_empty()) {
+            let affinity = Affinity(token.into_owned());
+            req.extensions_mut().insert(affinity);
+        }
+        self.inner.call(req)
+    }
+}
+
+#[derive(Clone)]
+struct Config {
+    name: Arc<str>,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    hash: Arc<dyn Fn(&str) -> String + Send + Sync>,
+}
+
+impl Config {
+    fn set_cookie(&self, token: &str) -> Option<HeaderValue> {
+        let mut cookie = format!("{}={}", self.name, token);
+        if let Some(path) = &self.path {
+            cookie.push_str(&format!("; Path={}", path));
+        }
+        if let Some(domain) = &self.domain {
+            cookie.push_str(&format!("; Domain={}", domain));
+        }
+        if let Some(max_age) = self.max_age {
+            cookie.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        if self.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            cookie.push_str(&format!("; SameSite={}", same_site.as_str()));
+        }
+        HeaderValue::from_str(&cookie).ok()
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("domain", &self.domain)
+            .field("max_age", &self.max_age)
+            .field("secure", &self.secure)
+            .field("http_only", &self.http_only)
+            .field("same_site", &self.same_site)
+            .finish()
+    }
+}
+
+/// Layer that applies [`SetAffinityCookie`] which sets the affinity cookie on responses with a
+/// [`Backend`] extension.
+///
+/// See the [module docs](crate::sticky_session) for an example.
+#[derive(Debug, Clone)]
+pub struct SetAffinityCookieLayer {
+    config: Arc<Config>,
+}
+
+impl SetAffinityCookieLayer {
+    /// Create a new `SetAffinityCookieLayer` that sets the cookie called `name`.
+    ///
+    /// The cookie defaults to `Path=/; Secure; HttpOnly; SameSite=Lax` and lasts for the browser
+    /// session.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is empty or contains characters that aren't allowed in cookie names.
+    pub fn new(name: &str) -> Self {
+        Self {
+            config: Arc::new(Config {
+                name: cookie_name(name).into(),
+                path: Some("/".to_owned()),
+                domain: None,
+                max_age: None,
+                secure: true,
+                http_only: true,
+                same_site: Some(SameSite::Lax),
+                hash: Arc::new(hash_backend),
+            }),
+        }
+    }
+
+    /// Set the `Path` attribute of the cookie, or remove it with `None`.
+    ///
+    /// # Panics
+    ///
+    /// If `path` contains control characters or `;`.
+    pub fn path(mut self, path: Option<&str>) -> Self {
+        Arc::make_mut(&mut self.config).path = path.map(|path| attribute_value(path).to_owned());
+        self
+    }
+
+    /// Set the `Domain` attribute of the cookie.
+    ///
+    /// # Panics
+    ///
+    /// If `domain` contains control characters or `;`.
+    pub fn domain(mut self, domain: &str) -> Self {
+        Arc::make_mut(&mut self.config).domain = Some(attribute_value(domain).to_owned());
+        self
+    }
+
+    /// Set the `Max-Age` attribute of the cookie, instead of it lasting for the browser session.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        Arc::make_mut(&mut self.config).max_age = Some(max_age);
+        self
+    }
+
+    /// Set whether the cookie has the `Secure` attribute. Defaults to `true`.
+    pub fn secure(mut self, secure: bool) -> Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+
+    /// Set whether the cookie has the `HttpOnly` attribute. Defaults to `true`.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        Arc::make_mut(&mut self.config).http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute of the cookie, or remove it with `None`. Defaults to
+    /// [`SameSite::Lax`].
+    pub fn same_site(mut self, same_site: Option<SameSite>) -> Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Set the function that hashes backend identities into affinity tokens. Defaults to
+    /// [`hash_backend`].
+    ///
+    /// Tokens must only contain characters allowed in cookie values, otherwise the cookie isn't
+    /// set.
+    pub fn hash<F>(mut self, hash: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).hash = Arc::new(hash);
+        self
+    }
+}
+
+impl<S> Layer<S> for SetAffinityCookieLayer {
+    type Service = SetAffinityCookie<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetAffinityCookie {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that sets the affinity cookie on responses with a [`Backend`] extension.
+///
+/// See the [module docs](crate::sticky_session) for an example.
+#[derive(Debug, Clone)]
+pub struct SetAffinityCookie<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> SetAffinityCookie<S> {
+    /// Create a new `SetAffinityCookie` that sets the cookie called `name`.
+    ///
+    /// See [`SetAffinityCookieLayer::new`] for more details.
+    pub fn new(inner: S, name: &str) -> Self {
+        SetAffinityCookieLayer::new(name).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SetAffinityCookie` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer(name: &str) -> SetAffinityCookieLayer {
+        SetAffinityCookieLayer::new(name)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetAffinityCookie<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let current = get_cookie(req.headers(), &self.config.name).map(Cow::into_owned);
+        ResponseFuture {
+            inner: self.inner.call(req),
+            config: self.config.clone(),
+            current,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`SetAffinityCookie`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        config: Arc<Config>,
+        current: Option<String>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+
+        if let Some(Backend(backend)) = res.extensions().get::<Backend>() {
+            let token = (this.config.hash)(backend);
+            if this.current.as_deref() != Some(token.as_str()) {
+                if let Some(cookie) = this.config.set_cookie(&token) {
+                    res.headers_mut().append(header::SET_COOKIE, cookie);
+                }
+            }
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash_backend(""), "cbf29ce484222325");
+        assert_eq!(hash_backend("a"), "af63dc4c8601ec8c");
+    }
+
+    #[tokio::test]
+    async fn extract_affinity() {
+        async fn handle(req: Request<Body>) -> Result<Response<Option<Affinity>>, BoxError> {
+            Ok(Response::new(req.extensions().get::<Affinity>().cloned()))
+        }
+
+        let svc = ExtractAffinityLayer::cookie("backend").layer(service_fn(handle));
+        let req = Request::get("/")
+            .header(header::COOKIE, "session=1; backend=abc")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.into_body(), Some(Affinity("abc".to_owned())));
+
+        let req = Request::get("/")
+            .header(header::COOKIE, "backend=")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.into_body(), None);
+
+        let svc = ExtractAffinityLayer::header(HeaderName::from_static("x-backend"))
+            .layer(service_fn(handle));
+        let req = Request::get("/")
+            .header("x-backend", "abc")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.into_body(), Some(Affinity("abc".to_owned())));
+    }
+
+    #[tokio::test]
+    async fn set_affinity_cookie() {
+        async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+            let mut res = Response::new(Body::empty());
+            if req.uri().path() == "/steered" {
+                res.extensions_mut().insert(Backend("a".to_owned()));
+            }
+            Ok(res)
+        }
+
+        let svc = SetAffinityCookieLayer::new("backend")
+            .path(None)
+            .domain("example.com")
+            .max_age(Duration::from_secs(60))
+            .secure(false)
+            .http_only(false)
+            .same_site(Some(SameSite::Strict))
+            .hash(|backend| format!("h-{}", backend))
+            .layer(service_fn(handle));
+
+        let req = Request::get("/steered").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::SET_COOKIE],
+            "backend=h-a; Domain=example.com; Max-Age=60; SameSite=Strict"
+        );
+
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert!(!res.headers().contains_key(header::SET_COOKIE));
+
+        // a stale token is replaced
+        let req = Request::get("/steered")
+            .header(header::COOKIE, "backend=h-b")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert!(res.headers().contains_key(header::SET_COOKIE));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cookie name")]
+    fn invalid_cookie_name() {
+        SetAffinityCookieLayer::new("a=b");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cookie attribute")]
+    fn invalid_domain() {
+        SetAffinityCookieLayer::new("affinity").domain("example.com; Secure");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cookie attribute")]
+    fn invalid_path() {
+        SetAffinityCookieLayer::new("affinity").path(Some("/\r\nSet-Cookie: a=b"));
+    }
+}
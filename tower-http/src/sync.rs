@@ -0,0 +1,11 @@
+//! Helpers for state shared between requests.
+
+use std::sync::{Mutex, MutexGuard};
+
+/// Lock `mutex`, carrying on with its state if a thread panicked while holding the lock.
+///
+/// Only use this for state that is mutated in small steps, so it's never left inconsistent by a
+/// panic.
+pub(crate) fn lock_ignore_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
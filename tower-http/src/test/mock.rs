@@ -0,0 +1,249 @@
+use super::collect;
+use crate::sync::lock_ignore_poison;
+use crate::BoxError;
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http::{Request, Response, StatusCode};
+use http_body::{Body, Full};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+type Fallback = Arc<dyn Fn(&Request<Bytes>) -> Response<Bytes> + Send + Sync>;
+
+/// Inner service for testing middleware.
+///
+/// `MockService` responds with the scripted responses and errors in order, and with its
+/// fallback once they run out, which by default responds with an empty `200 OK`. It collects
+/// the body of every request it receives and keeps the requests, including their extensions,
+/// so tests can check what reached it.
+///
+/// Clones share the script and the captured requests, so keep a clone around when passing the
+/// service to a layer. See the [module docs](crate::test) for an example.
+#[derive(Clone)]
+pub struct MockService {
+    state: Arc<Mutex<State>>,
+    fallback: Fallback,
+}
+
+#[derive(Default)]
+struct State {
+    script: VecDeque<Result<Response<Bytes>, BoxError>>,
+    calls: usize,
+    requests: VecDeque<Request<Bytes>>,
+}
+
+impl MockService {
+    /// Create a new `MockService` that responds with an empty `200 OK`.
+    pub fn new() -> Self {
+        Self {
+            state: Default::default(),
+            fallback: Arc::new(|_| Response::new(Bytes::new())),
+        }
+    }
+
+    /// Respond with `response` to the next request that isn't scripted yet.
+    pub fn respond_with(self, response: Response<Bytes>) -> Self {
+        lock_ignore_poison(&self.state)
+            .script
+            .push_back(Ok(response));
+        self
+    }
+
+    /// Respond with an empty response with `status` to the next request that isn't scripted
+    /// yet.
+    pub fn respond_with_status(self, status: StatusCode) -> Self {
+        let mut response = Response::new(Bytes::new());
+        *response.status_mut() = status;
+        self.respond_with(response)
+    }
+
+    /// Fail the next request that isn't scripted yet with `error`.
+    pub fn fail_with<E>(self, error: E) -> Self
+    where
+        E: Into<BoxError>,
+    {
+        lock_ignore_poison(&self.state)
+            .script
+            .push_back(Err(error.into()));
+        self
+    }
+
+    /// Respond to requests with `fallback` once the scripted responses have run out.
+    pub fn fallback<F>(mut self, fallback: F) -> Self
+    where
+        F: Fn(&Request<Bytes>) -> Response<Bytes> + Send + Sync + 'static,
+    {
+        self.fallback = Arc::new(fallback);
+        self
+    }
+
+    /// Returns the number of times the service has been called.
+    pub fn calls(&self) -> usize {
+        lock_ignore_poison(&self.state).calls
+    }
+
+    /// Remove and return the oldest captured request.
+    ///
+    /// Requests are captured once their body has been collected.
+    pub fn take_request(&self) -> Option<Request<Bytes>> {
+        lock_ignore_poison(&self.state).requests.pop_front()
+    }
+
+    /// Remove and return all captured requests, oldest first.
+    pub fn take_requests(&self) -> Vec<Request<Bytes>> {
+        lock_ignore_poison(&self.state).requests.drain(..).collect()
+    }
+
+    /// Assert that the service has been called `times` times.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the service has been called a different number of times.
+    #[track_caller]
+    pub fn assert_called_times(&self, times: usize) {
+        let calls = self.calls();
+        assert_eq!(
+            calls, times,
+            "expected the service to be called {} times, but it was called {} times",
+            times, calls
+        );
+    }
+
+    /// Assert that the service hasn't been called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the service has been called.
+    #[track_caller]
+    pub fn assert_not_called(&self) {
+        self.assert_called_times(0);
+    }
+}
+
+impl Default for MockService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MockService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = lock_ignore_poison(&self.state);
+        f.debug_struct("MockService")
+            .field("scripted", &state.script.len())
+            .field("calls", &state.calls)
+            .field("requests", &state.requests)
+            .finish()
+    }
+}
+
+impl<B> Service<Request<B>> for MockService
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<Full<Bytes>>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // take the scripted response now so responses follow the order of the calls
+        let scripted = {
+            let mut state = lock_ignore_poison(&self.state);
+            state.calls += 1;
+            state.script.pop_front()
+        };
+        let state = self.state.clone();
+        let fallback = self.fallback.clone();
+
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let (body, _) = collect(body).await?;
+            let req = Request::from_parts(parts, body);
+
+            let res = match scripted {
+                Some(scripted) => scripted,
+                None => Ok(fallback(&req)),
+            };
+            lock_ignore_poison(&state).requests.push_back(req);
+            res.map(|res| res.map(Full::new))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::call;
+    use hyper::Body;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn responds_with_script_then_fallback() {
+        let mut mock = MockService::new()
+            .respond_with_status(StatusCode::CREATED)
+            .fail_with("boom")
+            .fallback(|req| Response::new(req.body().clone()));
+
+        call(&mut mock, Request::new(Body::empty()))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let err = mock
+            .clone()
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+
+        call(&mut mock, Request::new(Body::from("echo")))
+            .await
+            .assert_status(StatusCode::OK)
+            .assert_body("echo");
+
+        mock.assert_called_times(3);
+    }
+
+    #[tokio::test]
+    async fn captures_requests() {
+        let mock = MockService::new();
+        mock.assert_not_called();
+
+        let mut req = Request::put("/one").body(Body::from("1")).unwrap();
+        req.extensions_mut().insert(42_u32);
+        mock.clone().oneshot(req).await.unwrap();
+        mock.clone()
+            .oneshot(Request::get("/two").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let first = mock.take_request().unwrap();
+        assert_eq!(first.uri(), "/one");
+        assert_eq!(first.body(), "1");
+        assert_eq!(first.extensions().get::<u32>(), Some(&42));
+
+        let rest = mock.take_requests();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].uri(), "/two");
+        assert!(mock.take_request().is_none());
+        mock.assert_called_times(2);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "expected the service to be called 1 times, but it was called 0 times"
+    )]
+    fn assert_called_times_panics() {
+        MockService::new().assert_called_times(1);
+    }
+}
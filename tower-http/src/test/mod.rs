@@ -0,0 +1,82 @@
+//! Utilities for testing middleware.
+//!
+//! Testing a layer usually means wrapping a `service_fn` that returns a canned response,
+//! sending a request through it with `oneshot`, collecting the response body and comparing the
+//! parts one by one. This module packages those steps:
+//!
+//! - [`MockService`] is an inner service that responds with scripted responses and errors,
+//!   counts its calls and captures the requests it receives, with their bodies collected, so
+//!   tests can check what the layer passed on.
+//! - [`TestResponse`] is a response with its body collected, with assertions for its status,
+//!   headers and body that can be chained.
+//! - [`call`] drives a service to readiness, calls it and collects the response.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, HeaderValue, Request, StatusCode};
+//! use hyper::Body;
+//! use tower::ServiceBuilder;
+//! use tower_http::{
+//!     set_header::SetResponseHeaderLayer,
+//!     test::{call, MockService},
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mock = MockService::new().respond_with_status(StatusCode::CREATED);
+//!
+//! let mut service = ServiceBuilder::new()
+//!     .layer(SetResponseHeaderLayer::overriding(
+//!         header::CACHE_CONTROL,
+//!         HeaderValue::from_static("no-store"),
+//!     ))
+//!     .service(mock.clone());
+//!
+//! let request = Request::post("/items").body(Body::from("item")).unwrap();
+//! call(&mut service, request)
+//!     .await
+//!     .assert_status(StatusCode::CREATED)
+//!     .assert_header(header::CACHE_CONTROL, "no-store")
+//!     .assert_body("");
+//!
+//! // the request reached the inner service unchanged
+//! mock.assert_called_times(1);
+//! let request = mock.take_request().unwrap();
+//! assert_eq!(request.uri(), "/items");
+//! assert_eq!(request.body(), "item");
+//! # }
+//! ```
+
+mod mock;
+mod response;
+
+pub use self::{
+    mock::MockService,
+    response::{call, TestResponse},
+};
+
+use crate::BoxError;
+use bytes::{Buf, Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::Body;
+
+/// Collect a body and its trailers.
+async fn collect<B>(body: B) -> Result<(Bytes, Option<HeaderMap>), BoxError>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    futures_util::pin_mut!(body);
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        let mut chunk = chunk.map_err(Into::into)?;
+        while chunk.has_remaining() {
+            let len = chunk.chunk().len();
+            bytes.extend_from_slice(chunk.chunk());
+            chunk.advance(len);
+        }
+    }
+    let trailers = body.trailers().await.map_err(Into::into)?;
+    Ok((bytes.freeze(), trailers))
+}
@@ -0,0 +1,250 @@
+use super::collect;
+use crate::BoxError;
+use bytes::Bytes;
+use http::{header::AsHeaderName, Extensions, HeaderMap, Request, Response, StatusCode, Version};
+use http_body::Body;
+use std::fmt;
+use tower_service::Service;
+
+/// Call `service` with `request` once it is ready, and collect the response.
+///
+/// See the [module docs](crate::test) for an example.
+///
+/// # Panics
+///
+/// Panics if the service or the response body fails.
+pub async fn call<S, ReqBody, ResBody>(service: &mut S, request: Request<ReqBody>) -> TestResponse
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: fmt::Display,
+    ResBody: Body,
+    ResBody::Error: Into<BoxError>,
+{
+    if let Err(err) = futures_util::future::poll_fn(|cx| service.poll_ready(cx)).await {
+        panic!("service failed to become ready: {}", err);
+    }
+    match service.call(request).await {
+        Ok(response) => TestResponse::collect(response).await,
+        Err(err) => panic!("service failed: {}", err),
+    }
+}
+
+/// A response with its body collected, for making assertions about it.
+///
+/// The assertion methods panic with a message describing the mismatch and return the response,
+/// so they can be chained. See the [module docs](crate::test) for an example.
+#[derive(Debug)]
+pub struct TestResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    extensions: Extensions,
+    body: Bytes,
+    trailers: Option<HeaderMap>,
+}
+
+impl TestResponse {
+    /// Collect the body and trailers of `response`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the response body fails.
+    pub async fn collect<B>(response: Response<B>) -> Self
+    where
+        B: Body,
+        B::Error: Into<BoxError>,
+    {
+        let (parts, body) = response.into_parts();
+        let (body, trailers) = match collect(body).await {
+            Ok(collected) => collected,
+            Err(err) => panic!("response body failed: {}", err),
+        };
+        Self {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            extensions: parts.extensions,
+            body,
+            trailers,
+        }
+    }
+
+    /// Returns the response status.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the HTTP version of the response.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns the response headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the response extensions.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns the response body.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Returns the response body as text.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body isn't valid UTF-8.
+    #[track_caller]
+    pub fn text(&self) -> &str {
+        match std::str::from_utf8(&self.body) {
+            Ok(text) => text,
+            Err(err) => panic!("response body isn't valid UTF-8: {}", err),
+        }
+    }
+
+    /// Returns the response trailers.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Assert that the response has `status`.
+    #[track_caller]
+    pub fn assert_status(&self, status: StatusCode) -> &Self {
+        assert_eq!(
+            self.status, status,
+            "expected status {}, got {}",
+            status, self.status
+        );
+        self
+    }
+
+    /// Assert that the response has a `name` header equal to `value`.
+    ///
+    /// If the header has several values, the first one is compared.
+    #[track_caller]
+    pub fn assert_header<K>(&self, name: K, value: &str) -> &Self
+    where
+        K: AsHeaderName + fmt::Display + Clone,
+    {
+        match self.headers.get(name.clone()) {
+            Some(actual) => assert!(
+                actual == value,
+                "expected header `{}` to be {:?}, got {:?}",
+                name,
+                value,
+                actual
+            ),
+            None => panic!(
+                "expected header `{}` to be {:?}, but it's missing",
+                name, value
+            ),
+        }
+        self
+    }
+
+    /// Assert that the response doesn't have a `name` header.
+    #[track_caller]
+    pub fn assert_no_header<K>(&self, name: K) -> &Self
+    where
+        K: AsHeaderName + fmt::Display + Clone,
+    {
+        if let Some(actual) = self.headers.get(name.clone()) {
+            panic!("expected no header `{}`, got {:?}", name, actual);
+        }
+        self
+    }
+
+    /// Assert that the response body is equal to `body`.
+    #[track_caller]
+    pub fn assert_body<T>(&self, body: T) -> &Self
+    where
+        T: AsRef<[u8]>,
+    {
+        let body = body.as_ref();
+        assert!(
+            self.body == body,
+            "expected body {:?}, got {:?}",
+            Bytes::copy_from_slice(body),
+            self.body
+        );
+        self
+    }
+
+    /// Assert that the response body contains `text`.
+    #[track_caller]
+    pub fn assert_body_contains(&self, text: &str) -> &Self {
+        assert!(
+            self.text().contains(text),
+            "expected body to contain {:?}, got {:?}",
+            text,
+            self.body
+        );
+        self
+    }
+
+    /// Convert this back into a [`Response`] with the collected body.
+    pub fn into_response(self) -> Response<Bytes> {
+        let mut response = Response::new(self.body);
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers;
+        *response.extensions_mut() = self.extensions;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header;
+    use hyper::Body;
+
+    async fn response() -> TestResponse {
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from("not found: /missing"))
+            .unwrap();
+        TestResponse::collect(response).await
+    }
+
+    #[tokio::test]
+    async fn assertions_pass() {
+        response()
+            .await
+            .assert_status(StatusCode::NOT_FOUND)
+            .assert_header(header::CONTENT_TYPE, "text/plain")
+            .assert_header("content-type", "text/plain")
+            .assert_no_header(header::CACHE_CONTROL)
+            .assert_body("not found: /missing")
+            .assert_body_contains("/missing");
+    }
+
+    #[tokio::test]
+    #[should_panic(
+        expected = "expected header `content-type` to be \"text/html\", got \"text/plain\""
+    )]
+    async fn assert_header_panics() {
+        response()
+            .await
+            .assert_header(header::CONTENT_TYPE, "text/html");
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "expected status 200 OK, got 404 Not Found")]
+    async fn assert_status_panics() {
+        response().await.assert_status(StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn converts_into_response() {
+        let response = response().await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.body(), "not found: /missing");
+    }
+}
@@ -0,0 +1,111 @@
+use super::{bucket::Buckets, Throttle};
+use bytes::Buf;
+use futures_core::ready;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+use tokio::time::{sleep, Sleep};
+
+pin_project! {
+    /// Body throttled by a [`Throttle`].
+    ///
+    /// Chunks exceeding the rate are held back until enough time has passed to deliver them.
+    pub struct ThrottledBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        inner: B,
+        buckets: Option<Buckets>,
+        pending: Option<B::Data>,
+        #[pin]
+        sleep: Option<Sleep>,
+    }
+}
+
+impl<B> ThrottledBody<B>
+where
+    B: Body,
+{
+    /// Create a new [`ThrottledBody`].
+    pub fn new(inner: B, throttle: &Throttle) -> Self {
+        let buckets = if throttle.is_enabled() {
+            Some(throttle.buckets())
+        } else {
+            None
+        };
+        Self {
+            inner,
+            buckets,
+            pending: None,
+            sleep: None,
+        }
+    }
+}
+
+impl<B> Body for ThrottledBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if let Some(sleep) = this.sleep.as_mut().as_pin_mut() {
+                ready!(sleep.poll(cx));
+                this.sleep.set(None);
+                return Poll::Ready(this.pending.take().map(Ok));
+            }
+
+            let data = match ready!(this.inner.as_mut().poll_data(cx)) {
+                Some(Ok(data)) => data,
+                other => return Poll::Ready(other),
+            };
+            let buckets = match this.buckets {
+                Some(buckets) => buckets,
+                None => return Poll::Ready(Some(Ok(data))),
+            };
+
+            let delay = buckets.take(data.remaining(), Instant::now());
+            if delay.is_zero() {
+                return Poll::Ready(Some(Ok(data)));
+            }
+            *this.pending = Some(data);
+            this.sleep.set(Some(sleep(delay)));
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = self.inner.size_hint();
+        if let Some(pending) = &self.pending {
+            let pending = pending.remaining() as u64;
+            hint.set_lower(hint.lower() + pending);
+            if let Some(upper) = hint.upper() {
+                hint.set_upper(upper + pending);
+            }
+        }
+        hint
+    }
+}
@@ -0,0 +1,136 @@
+use crate::sync::lock_ignore_poison;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The limits on the rate bodies are delivered at.
+///
+/// Bodies can be throttled by a global rate, shared by all bodies throttled with clones of the
+/// same `Throttle`, and by a rate applying to each body on its own. Both allow bursts of up to one
+/// second's worth of bytes.
+///
+/// By default bodies aren't throttled.
+#[derive(Clone, Debug, Default)]
+pub struct Throttle {
+    global: Option<Arc<Mutex<Bucket>>>,
+    per_body: Option<u64>,
+}
+
+impl Throttle {
+    /// Create a new `Throttle` that doesn't throttle bodies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit all bodies throttled with clones of this `Throttle` to `bytes_per_second` together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    pub fn global(mut self, bytes_per_second: u64) -> Self {
+        self.global = Some(Arc::new(Mutex::new(Bucket::new(bytes_per_second))));
+        self
+    }
+
+    /// Limit each body to `bytes_per_second`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    pub fn per_body(mut self, bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0, "rate must be greater than zero");
+        self.per_body = Some(bytes_per_second);
+        self
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.global.is_some() || self.per_body.is_some()
+    }
+
+    pub(super) fn buckets(&self) -> Buckets {
+        Buckets {
+            global: self.global.clone(),
+            body: self.per_body.map(Bucket::new),
+        }
+    }
+}
+
+/// The buckets a single body draws from.
+#[derive(Debug)]
+pub(super) struct Buckets {
+    global: Option<Arc<Mutex<Bucket>>>,
+    body: Option<Bucket>,
+}
+
+impl Buckets {
+    /// Take `bytes` from the buckets, returning how long to wait before delivering them.
+    pub(super) fn take(&mut self, bytes: usize, now: Instant) -> Duration {
+        let body = match &mut self.body {
+            Some(bucket) => bucket.take(bytes, now),
+            None => Duration::ZERO,
+        };
+        let global = match &self.global {
+            Some(bucket) => lock_ignore_poison(bucket).take(bytes, now),
+            None => Duration::ZERO,
+        };
+        body.max(global)
+    }
+}
+
+/// A token bucket that can go into debt, so chunks larger than the bucket are delayed rather
+/// than held back forever.
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0, "rate must be greater than zero");
+        let rate = bytes_per_second as f64;
+        Self {
+            rate,
+            tokens: rate,
+            updated: Instant::now(),
+        }
+    }
+
+    fn take(&mut self, bytes: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = elapsed.mul_add(self.rate, self.tokens).min(self.rate);
+        self.updated = self.updated.max(now);
+
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_delay_bytes_over_the_rate() {
+        let throttle = Throttle::new().global(200).per_body(100);
+        let now = Instant::now();
+        let mut first = throttle.buckets();
+        let mut second = throttle.buckets();
+
+        assert_eq!(first.take(100, now), Duration::ZERO);
+        assert_eq!(first.take(50, now), Duration::from_millis(500));
+        // the global bucket has 50 bytes left
+        assert_eq!(second.take(100, now), Duration::from_millis(250));
+
+        // the buckets refill over time but never hold more than a second's worth of bytes
+        let later = now + Duration::from_secs(10);
+        assert_eq!(first.take(100, later), Duration::ZERO);
+        assert_eq!(first.take(10, later), Duration::from_millis(100));
+    }
+}
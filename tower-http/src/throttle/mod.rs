@@ -0,0 +1,101 @@
+//! Middleware that throttles request and response bodies.
+//!
+//! [`ThrottleBody`] limits the number of body bytes delivered per second by holding back chunks
+//! that exceed the rate. A [`Throttle`] can limit all bodies together, which keeps large
+//! downloads or uploads from using up all bandwidth, and each body on its own, which shares the
+//! bandwidth fairly between clients. Throttling is also useful to test how clients and services
+//! behave on slow networks.
+//!
+//! Bodies are throttled after they have been produced, so the inner body may still be read as
+//! fast as it can be buffered by the transport.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::ServiceBuilder;
+//! use tower_http::throttle::{Throttle, ThrottleBodyLayer};
+//!
+//! async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(
+//!         ThrottleBodyLayer::new()
+//!             // Send at most 10 MB/s in total and 1 MB/s per response.
+//!             .response(Throttle::new().global(10_000_000).per_body(1_000_000))
+//!             // Receive at most 100 kB/s per request.
+//!             .request(Throttle::new().per_body(100_000)),
+//!     )
+//!     .service_fn(handle);
+//! ```
+
+mod body;
+mod bucket;
+mod service;
+
+pub use self::{
+    body::ThrottledBody,
+    bucket::Throttle,
+    service::{ResponseFuture, ThrottleBody, ThrottleBodyLayer},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{Request, Response};
+    use hyper::Body;
+    use std::{
+        convert::Infallible,
+        time::{Duration, Instant},
+    };
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[tokio::test]
+    async fn delays_chunks_over_the_rate() {
+        let svc = ServiceBuilder::new()
+            .layer(ThrottleBodyLayer::new().response(Throttle::new().per_body(1000)))
+            .service_fn(|_: Request<ThrottledBody<Body>>| async {
+                let chunks: Vec<Result<_, Infallible>> = vec![
+                    Ok(Bytes::from(vec![0; 1000])),
+                    Ok(Bytes::from(vec![0; 100])),
+                    Ok(Bytes::from(vec![0; 100])),
+                ];
+                Ok::<_, Infallible>(Response::new(Body::wrap_stream(futures::stream::iter(
+                    chunks,
+                ))))
+            });
+
+        let start = Instant::now();
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body.len(), 1200);
+
+        // the first chunk uses up the burst and the others are delivered at 1000 bytes/second
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(190), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn unthrottled_bodies_pass_through() {
+        let svc = ServiceBuilder::new()
+            .layer(ThrottleBodyLayer::new())
+            .service_fn(|req: Request<ThrottledBody<Body>>| async move {
+                let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                Ok::<_, hyper::Error>(Response::new(Body::from(body)))
+            });
+
+        let res = svc
+            .oneshot(Request::new(Body::from("hello")))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello");
+    }
+}
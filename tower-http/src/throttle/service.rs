@@ -0,0 +1,143 @@
+use super::{Throttle, ThrottledBody};
+use futures_core::ready;
+use http::{Request, Response};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that applies the [`ThrottleBody`] middleware which throttles request and response
+/// bodies.
+///
+/// See the [module docs](crate::throttle) for an example.
+#[derive(Clone, Debug, Default)]
+pub struct ThrottleBodyLayer {
+    request: Throttle,
+    response: Throttle,
+}
+
+impl ThrottleBodyLayer {
+    /// Create a new [`ThrottleBodyLayer`] that doesn't throttle any bodies yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Throttle request bodies with `throttle`.
+    pub fn request(mut self, throttle: Throttle) -> Self {
+        self.request = throttle;
+        self
+    }
+
+    /// Throttle response bodies with `throttle`.
+    pub fn response(mut self, throttle: Throttle) -> Self {
+        self.response = throttle;
+        self
+    }
+}
+
+impl<S> Layer<S> for ThrottleBodyLayer {
+    type Service = ThrottleBody<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ThrottleBody {
+            inner,
+            request: self.request.clone(),
+            response: self.response.clone(),
+        }
+    }
+}
+
+/// Middleware that throttles request and response bodies.
+///
+/// See the [module docs](crate::throttle) for an example.
+#[derive(Clone, Debug)]
+pub struct ThrottleBody<S> {
+    inner: S,
+    request: Throttle,
+    response: Throttle,
+}
+
+impl<S> ThrottleBody<S> {
+    /// Create a new [`ThrottleBody`] that doesn't throttle any bodies yet.
+    pub fn new(inner: S) -> Self {
+        ThrottleBodyLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ThrottleBody` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> ThrottleBodyLayer {
+        ThrottleBodyLayer::new()
+    }
+
+    /// Throttle request bodies with `throttle`.
+    ///
+    /// See [`ThrottleBodyLayer::request`] for more details.
+    pub fn request(mut self, throttle: Throttle) -> Self {
+        self.request = throttle;
+        self
+    }
+
+    /// Throttle response bodies with `throttle`.
+    ///
+    /// See [`ThrottleBodyLayer::response`] for more details.
+    pub fn response(mut self, throttle: Throttle) -> Self {
+        self.response = throttle;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ThrottleBody<S>
+where
+    S: Service<Request<ThrottledBody<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Body,
+    ResBody: Body,
+{
+    type Response = Response<ThrottledBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let req = req.map(|body| ThrottledBody::new(body, &self.request));
+        ResponseFuture {
+            inner: self.inner.call(req),
+            throttle: self.response.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ThrottleBody`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        throttle: Throttle,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<ThrottledBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+        let throttle = this.throttle;
+        Poll::Ready(Ok(res.map(|body| ThrottledBody::new(body, throttle))))
+    }
+}
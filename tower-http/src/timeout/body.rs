@@ -4,6 +4,10 @@ use http_body::Body;
 use pin_project_lite::pin_project;
 use std::{
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
     time::Duration,
 };
@@ -50,6 +54,7 @@ pin_project! {
     /// ```
     pub struct TimeoutBody<B> {
         timeout: Duration,
+        timed_out: Option<Arc<AtomicBool>>,
         // In http-body 1.0, `poll_*` will be merged into `poll_frame`.
         // Merge the two `sleep_data` and `sleep_trailers` into one `sleep`.
         // See: https://github.com/tower-rs/tower-http/pull/303#discussion_r1004834958
@@ -67,11 +72,25 @@ impl<B> TimeoutBody<B> {
     pub fn new(timeout: Duration, body: B) -> Self {
         TimeoutBody {
             timeout,
+            timed_out: None,
             sleep_data: None,
             sleep_trailers: None,
             body,
         }
     }
+
+    /// Set `timed_out` to `true` when the body times out.
+    pub(crate) fn notify_timed_out(mut self, timed_out: Arc<AtomicBool>) -> Self {
+        self.timed_out = Some(timed_out);
+        self
+    }
+}
+
+fn timeout_error(timed_out: &Option<Arc<AtomicBool>>) -> BoxError {
+    if let Some(timed_out) = timed_out {
+        timed_out.store(true, Ordering::SeqCst);
+    }
+    Box::new(TimeoutError(()))
 }
 
 impl<B> Body for TimeoutBody<B>
@@ -98,7 +117,7 @@ where
 
         // Error if the timeout has expired.
         if let Poll::Ready(()) = sleep_pinned.poll(cx) {
-            return Poll::Ready(Some(Err(Box::new(TimeoutError(())))));
+            return Poll::Ready(Some(Err(timeout_error(this.timed_out))));
         }
 
         // Check for body data.
@@ -128,7 +147,7 @@ where
 
         // Error if the timeout has expired.
         if let Poll::Ready(()) = sleep_pinned.poll(cx) {
-            return Poll::Ready(Err(Box::new(TimeoutError(()))));
+            return Poll::Ready(Err(timeout_error(this.timed_out)));
         }
 
         this.body.poll_trailers(cx).map_err(Into::into)
@@ -0,0 +1,453 @@
+use super::{DefaultOnTimeout, OnTimeout};
+use http::{header::HeaderName, HeaderValue, Request, Response};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const GRPC_TIMEOUT: HeaderName = HeaderName::from_static("grpc-timeout");
+
+/// The point in time by which a request must complete.
+///
+/// [`EnforceDeadline`] inserts this into the request extensions and [`PropagateDeadline`] reads
+/// it to forward the remaining time to upstream services.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Create a `Deadline` at the given instant.
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    /// Create a `Deadline` that expires after `timeout`.
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    /// The instant at which the deadline expires.
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// The time remaining until the deadline expires, or zero if it has expired.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Layer that applies the [`EnforceDeadline`] middleware.
+///
+/// See [`EnforceDeadline`] for more details.
+#[derive(Debug, Clone)]
+pub struct EnforceDeadlineLayer<T = DefaultOnTimeout> {
+    header: HeaderName,
+    max: Option<Duration>,
+    on_timeout: T,
+}
+
+impl EnforceDeadlineLayer {
+    /// Create a new `EnforceDeadlineLayer` that reads deadlines from the `grpc-timeout` header.
+    pub fn new() -> Self {
+        Self {
+            header: GRPC_TIMEOUT,
+            max: None,
+            on_timeout: DefaultOnTimeout::new(),
+        }
+    }
+}
+
+impl Default for EnforceDeadlineLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> EnforceDeadlineLayer<T> {
+    /// Read deadlines from `header` instead of `grpc-timeout`.
+    ///
+    /// The value must use the `grpc-timeout` format.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Clamp deadlines to at most `max` from now.
+    pub fn max_timeout(mut self, max: Duration) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Customize the response sent when the deadline expires.
+    ///
+    /// `NewT` is expected to implement [`OnTimeout`]. Defaults to [`DefaultOnTimeout`].
+    pub fn on_timeout<NewT>(self, on_timeout: NewT) -> EnforceDeadlineLayer<NewT> {
+        EnforceDeadlineLayer {
+            header: self.header,
+            max: self.max,
+            on_timeout,
+        }
+    }
+}
+
+impl<S, T> Layer<S> for EnforceDeadlineLayer<T>
+where
+    T: Clone,
+{
+    type Service = EnforceDeadline<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EnforceDeadline {
+            inner,
+            header: self.header.clone(),
+            max: self.max,
+            on_timeout: self.on_timeout.clone(),
+        }
+    }
+}
+
+/// Middleware that parses a deadline from the request and enforces it.
+///
+/// The deadline is read from the `grpc-timeout` header, or another header using the same format,
+/// and inserted into the request extensions as a [`Deadline`]. If the request already has an
+/// earlier [`Deadline`] extension that one is kept. If the deadline expires before the response
+/// is produced the response from [`OnTimeout`] is sent, `408 Request Timeout` by default.
+///
+/// Requests without a deadline are not timed out.
+///
+/// # Example
+///
+/// ```
+/// use http::{Request, Response};
+/// use hyper::Body;
+/// use std::convert::Infallible;
+/// use tower::ServiceBuilder;
+/// use tower_http::timeout::{EnforceDeadlineLayer, PropagateDeadlineLayer};
+///
+/// # let upstream = tower::service_fn(|_: Request<Body>| async {
+/// #     Ok::<_, Infallible>(Response::new(Body::empty()))
+/// # });
+/// // Proxy requests to `upstream`, forwarding the remaining time to it.
+/// let svc = ServiceBuilder::new()
+///     .layer(EnforceDeadlineLayer::new())
+///     .layer(PropagateDeadlineLayer::new())
+///     .service(upstream);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EnforceDeadline<S, T = DefaultOnTimeout> {
+    inner: S,
+    header: HeaderName,
+    max: Option<Duration>,
+    on_timeout: T,
+}
+
+impl<S> EnforceDeadline<S> {
+    /// Create a new `EnforceDeadline` that reads deadlines from the `grpc-timeout` header.
+    pub fn new(inner: S) -> Self {
+        EnforceDeadlineLayer::new().layer(inner)
+    }
+
+    /// Returns a new [`Layer`] that wraps services with an `EnforceDeadline` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> EnforceDeadlineLayer {
+        EnforceDeadlineLayer::new()
+    }
+}
+
+impl<S, T> EnforceDeadline<S, T> {
+    define_inner_service_accessors!();
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for EnforceDeadline<S, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    T: OnTimeout<ResBody> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = EnforceDeadlineFuture<S::Future, T>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let from_header = req
+            .headers()
+            .get(&self.header)
+            .and_then(decode_timeout)
+            .map(|timeout| match self.max {
+                Some(max) => timeout.min(max),
+                None => timeout,
+            })
+            .map(Deadline::after);
+
+        let existing = req.extensions().get::<Deadline>().copied();
+        let deadline = match (existing, from_header) {
+            (Some(existing), Some(from_header)) => Some(existing.min(from_header)),
+            (existing, from_header) => existing.or(from_header),
+        };
+        if let Some(deadline) = deadline {
+            req.extensions_mut().insert(deadline);
+        }
+
+        EnforceDeadlineFuture {
+            inner: self.inner.call(req),
+            sleep: deadline.map(|deadline| tokio::time::sleep_until(deadline.instant())),
+            on_timeout: self.on_timeout.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`EnforceDeadline`].
+    pub struct EnforceDeadlineFuture<F, T = DefaultOnTimeout> {
+        #[pin]
+        inner: F,
+        #[pin]
+        sleep: Option<Sleep>,
+        on_timeout: T,
+    }
+}
+
+impl<F, T, B, E> Future for EnforceDeadlineFuture<F, T>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    T: OnTimeout<B>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if let Some(sleep) = this.sleep.as_pin_mut() {
+            if sleep.poll(cx).is_ready() {
+                return Poll::Ready(Ok(this.on_timeout.on_timeout()));
+            }
+        }
+
+        this.inner.poll(cx)
+    }
+}
+
+/// Layer that applies the [`PropagateDeadline`] middleware.
+///
+/// See [`PropagateDeadline`] for more details.
+#[derive(Debug, Clone)]
+pub struct PropagateDeadlineLayer {
+    header: HeaderName,
+}
+
+impl PropagateDeadlineLayer {
+    /// Create a new `PropagateDeadlineLayer` that writes the `grpc-timeout` header.
+    pub fn new() -> Self {
+        Self {
+            header: GRPC_TIMEOUT,
+        }
+    }
+
+    /// Write `header` instead of `grpc-timeout`.
+    ///
+    /// The value uses the `grpc-timeout` format.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+impl Default for PropagateDeadlineLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for PropagateDeadlineLayer {
+    type Service = PropagateDeadline<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PropagateDeadline {
+            inner,
+            header: self.header.clone(),
+        }
+    }
+}
+
+/// Middleware that forwards the time remaining until a request's [`Deadline`] in a header.
+///
+/// This is meant for clients. The remaining time is written to the `grpc-timeout` header, or
+/// another header using the same format, replacing any existing value. Requests without a
+/// [`Deadline`] extension are passed through unchanged.
+///
+/// See [`EnforceDeadline`] for an example.
+#[derive(Debug, Clone)]
+pub struct PropagateDeadline<S> {
+    inner: S,
+    header: HeaderName,
+}
+
+impl<S> PropagateDeadline<S> {
+    /// Create a new `PropagateDeadline` that writes the `grpc-timeout` header.
+    pub fn new(inner: S) -> Self {
+        PropagateDeadlineLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `PropagateDeadline` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> PropagateDeadlineLayer {
+        PropagateDeadlineLayer::new()
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for PropagateDeadline<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if let Some(deadline) = req.extensions().get::<Deadline>() {
+            let value = encode_timeout(deadline.remaining());
+            req.headers_mut().insert(self.header.clone(), value);
+        }
+        self.inner.call(req)
+    }
+}
+
+/// Decode a timeout in the `grpc-timeout` format, for example `100m` for 100 milliseconds.
+fn decode_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?;
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount = amount.parse::<u64>().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+/// Encode a timeout in the `grpc-timeout` format, using the most precise unit that fits in the
+/// eight digits allowed.
+fn encode_timeout(timeout: Duration) -> HeaderValue {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    let units: [(u128, char); 6] = [
+        (1, 'n'),
+        (1_000, 'u'),
+        (1_000_000, 'm'),
+        (1_000_000_000, 'S'),
+        (60 * 1_000_000_000, 'M'),
+        (60 * 60 * 1_000_000_000, 'H'),
+    ];
+    for (nanos_per_unit, unit) in units.iter() {
+        // round down so the upstream never sees more time than is left
+        let amount = nanos / nanos_per_unit;
+        if amount <= MAX {
+            return HeaderValue::from_str(&format!("{}{}", amount, unit))
+                .expect("valid header value");
+        }
+    }
+    HeaderValue::from_static("99999999H")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[test]
+    fn grpc_timeout_format() {
+        let decode = |s: &'static str| decode_timeout(&HeaderValue::from_static(s));
+        assert_eq!(decode("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(decode("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(decode("5S"), Some(Duration::from_secs(5)));
+        assert_eq!(decode("5"), None);
+        assert_eq!(decode("m"), None);
+        assert_eq!(decode("123456789S"), None);
+        assert_eq!(decode("-1S"), None);
+        assert_eq!(decode("1x"), None);
+
+        assert_eq!(encode_timeout(Duration::from_nanos(500)), "500n");
+        assert_eq!(encode_timeout(Duration::from_millis(100)), "100000u");
+        assert_eq!(encode_timeout(Duration::from_secs(30)), "30000000u");
+        assert_eq!(
+            encode_timeout(Duration::from_secs(3 * 24 * 3600)),
+            "259200S"
+        );
+        // partial units are dropped
+        assert_eq!(
+            encode_timeout(Duration::from_nanos(100_000_000_999)),
+            "100000m"
+        );
+    }
+
+    #[tokio::test]
+    async fn enforces_and_propagates_deadline() {
+        let upstream = tower::service_fn(|req: Request<Body>| async move {
+            let timeout = req.headers().get(GRPC_TIMEOUT).and_then(decode_timeout);
+            let deadline = req.extensions().get::<Deadline>().copied();
+            assert!(deadline.is_some());
+            assert!(timeout.unwrap() <= Duration::from_millis(50));
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        });
+
+        let svc = ServiceBuilder::new()
+            .layer(EnforceDeadlineLayer::new())
+            .layer(PropagateDeadlineLayer::new())
+            .service(upstream);
+
+        let req = Request::builder()
+            .header(GRPC_TIMEOUT, "50m")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn no_deadline() {
+        let svc = ServiceBuilder::new()
+            .layer(EnforceDeadlineLayer::new())
+            .layer(PropagateDeadlineLayer::new())
+            .service_fn(|req: Request<Body>| async move {
+                assert!(req.headers().get(GRPC_TIMEOUT).is_none());
+                assert!(req.extensions().get::<Deadline>().is_none());
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -1,7 +1,8 @@
 //! Middleware that applies a timeout to requests.
 //!
 //! If the request does not complete within the specified timeout it will be aborted and a `408
-//! Request Timeout` response will be sent.
+//! Request Timeout` response will be sent. The response can be customized with
+//! [`TimeoutLayer::on_timeout`].
 //!
 //! # Differences from `tower::timeout`
 //!
@@ -40,10 +41,17 @@
 //! [`Infallible`]: std::convert::Infallible
 
 mod body;
+mod deadline;
+mod on_timeout;
 mod service;
 
 pub use body::{TimeoutBody, TimeoutError};
+pub use deadline::{
+    Deadline, EnforceDeadline, EnforceDeadlineFuture, EnforceDeadlineLayer, PropagateDeadline,
+    PropagateDeadlineLayer,
+};
+pub use on_timeout::{DefaultOnTimeout, OnTimeout};
 pub use service::{
-    RequestBodyTimeout, RequestBodyTimeoutLayer, ResponseBodyTimeout, ResponseBodyTimeoutLayer,
-    Timeout, TimeoutLayer,
+    Fail, RequestBodyTimeout, RequestBodyTimeoutFuture, RequestBodyTimeoutLayer, RequestTimeout,
+    Respond, ResponseBodyTimeout, ResponseBodyTimeoutLayer, Timeout, TimeoutLayer,
 };
@@ -0,0 +1,50 @@
+use http::{Response, StatusCode};
+
+/// Trait used to produce the response sent when a request times out.
+///
+/// This trait is implemented for closures with the signature `FnMut() -> Response<B>`.
+///
+/// See [`TimeoutLayer::on_timeout`] for an example.
+///
+/// [`TimeoutLayer::on_timeout`]: super::TimeoutLayer::on_timeout
+pub trait OnTimeout<B> {
+    /// Make the response.
+    fn on_timeout(&mut self) -> Response<B>;
+}
+
+impl<B, F> OnTimeout<B> for F
+where
+    F: FnMut() -> Response<B>,
+{
+    fn on_timeout(&mut self) -> Response<B> {
+        self()
+    }
+}
+
+/// The default [`OnTimeout`] used by [`Timeout`].
+///
+/// Produces a `408 Request Timeout` response with an empty body.
+///
+/// [`Timeout`]: super::Timeout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultOnTimeout {
+    _priv: (),
+}
+
+impl DefaultOnTimeout {
+    /// Create a new `DefaultOnTimeout`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<B> OnTimeout<B> for DefaultOnTimeout
+where
+    B: Default,
+{
+    fn on_timeout(&mut self) -> Response<B> {
+        let mut res = Response::new(B::default());
+        *res.status_mut() = StatusCode::REQUEST_TIMEOUT;
+        res
+    }
+}
@@ -1,10 +1,16 @@
+use super::{DefaultOnTimeout, OnTimeout};
 use crate::timeout::body::TimeoutBody;
 use futures_core::ready;
-use http::{Request, Response, StatusCode};
+use http::{HeaderName, Request, Response, StatusCode};
 use pin_project_lite::pin_project;
 use std::{
     future::Future,
+    marker::PhantomData,
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
     time::Duration,
 };
@@ -15,46 +21,153 @@ use tower_service::Service;
 /// Layer that applies the [`Timeout`] middleware which apply a timeout to requests.
 ///
 /// See the [module docs](super) for an example.
-#[derive(Debug, Clone, Copy)]
-pub struct TimeoutLayer {
-    timeout: Duration,
+#[derive(Debug, Clone)]
+pub struct TimeoutLayer<T = DefaultOnTimeout> {
+    config: TimeoutConfig,
+    on_timeout: T,
 }
 
 impl TimeoutLayer {
     /// Creates a new [`TimeoutLayer`].
     pub fn new(timeout: Duration) -> Self {
-        TimeoutLayer { timeout }
+        TimeoutLayer {
+            config: TimeoutConfig::new(timeout),
+            on_timeout: DefaultOnTimeout::new(),
+        }
+    }
+}
+
+impl<T> TimeoutLayer<T> {
+    /// Let callers specify the timeout, in milliseconds, with the given request header, for
+    /// example `x-timeout-ms`.
+    ///
+    /// A [`RequestTimeout`] request extension takes precedence over the header. Requested
+    /// timeouts are clamped to [`max_timeout`](Self::max_timeout), and if the header is missing
+    /// or invalid the default timeout is used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use http::{HeaderName, Request, Response};
+    /// use hyper::Body;
+    /// use std::{convert::Infallible, time::Duration};
+    /// use tower::ServiceBuilder;
+    /// use tower_http::timeout::TimeoutLayer;
+    ///
+    /// async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     // ...
+    ///     # Ok(Response::new(Body::empty()))
+    /// }
+    ///
+    /// let svc = ServiceBuilder::new()
+    ///     .layer(
+    ///         // Default to 10 seconds but let callers ask for up to 60 seconds.
+    ///         TimeoutLayer::new(Duration::from_secs(10))
+    ///             .timeout_header(HeaderName::from_static("x-timeout-ms"))
+    ///             .max_timeout(Duration::from_secs(60)),
+    ///     )
+    ///     .service_fn(handle);
+    /// ```
+    pub fn timeout_header(mut self, header: HeaderName) -> Self {
+        self.config.header = Some(header);
+        self
+    }
+
+    /// Set the maximum timeout a request can ask for, through a [`RequestTimeout`] extension or
+    /// the [`timeout_header`](Self::timeout_header).
+    ///
+    /// Defaults to the timeout passed to [`TimeoutLayer::new`], meaning requests can only shorten
+    /// the timeout.
+    pub fn max_timeout(mut self, max: Duration) -> Self {
+        self.config.max = Some(max);
+        self
+    }
+
+    /// Customize the response sent when a request times out.
+    ///
+    /// `NewT` is expected to implement [`OnTimeout`]. Defaults to [`DefaultOnTimeout`] which
+    /// sends a `408 Request Timeout` response.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use http::{header, Request, Response, StatusCode};
+    /// use hyper::Body;
+    /// use std::{convert::Infallible, time::Duration};
+    /// use tower::{Service, ServiceBuilder, ServiceExt};
+    /// use tower_http::timeout::TimeoutLayer;
+    ///
+    /// async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     tokio::time::sleep(Duration::from_secs(10)).await;
+    ///     Ok(Response::new(Body::empty()))
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut svc = ServiceBuilder::new()
+    ///     .layer(
+    ///         TimeoutLayer::new(Duration::from_millis(10)).on_timeout(|| {
+    ///             let mut res = Response::new(Body::from("upstream timed out"));
+    ///             *res.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+    ///             res.headers_mut()
+    ///                 .insert(header::RETRY_AFTER, "5".parse().unwrap());
+    ///             res
+    ///         }),
+    ///     )
+    ///     .service_fn(handle);
+    ///
+    /// let res = svc.ready().await?.call(Request::new(Body::empty())).await?;
+    /// assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_timeout<NewT>(self, on_timeout: NewT) -> TimeoutLayer<NewT> {
+        TimeoutLayer {
+            config: self.config,
+            on_timeout,
+        }
     }
 }
 
-impl<S> Layer<S> for TimeoutLayer {
-    type Service = Timeout<S>;
+impl<S, T> Layer<S> for TimeoutLayer<T>
+where
+    T: Clone,
+{
+    type Service = Timeout<S, T>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        Timeout::new(inner, self.timeout)
+        Timeout {
+            inner,
+            config: self.config.clone(),
+            on_timeout: self.on_timeout.clone(),
+        }
     }
 }
 
 /// Middleware which apply a timeout to requests.
 ///
 /// If the request does not complete within the specified timeout it will be aborted and a `408
-/// Request Timeout` response will be sent.
+/// Request Timeout` response will be sent. The response can be customized with
+/// [`Timeout::on_timeout`].
 ///
 /// See the [module docs](super) for an example.
-#[derive(Debug, Clone, Copy)]
-pub struct Timeout<S> {
+#[derive(Debug, Clone)]
+pub struct Timeout<S, T = DefaultOnTimeout> {
     inner: S,
-    timeout: Duration,
+    config: TimeoutConfig,
+    on_timeout: T,
 }
 
 impl<S> Timeout<S> {
     /// Creates a new [`Timeout`].
     pub fn new(inner: S, timeout: Duration) -> Self {
-        Self { inner, timeout }
+        Self {
+            inner,
+            config: TimeoutConfig::new(timeout),
+            on_timeout: DefaultOnTimeout::new(),
+        }
     }
 
-    define_inner_service_accessors!();
-
     /// Returns a new [`Layer`] that wraps services with a `Timeout` middleware.
     ///
     /// [`Layer`]: tower_layer::Layer
@@ -63,14 +176,45 @@ impl<S> Timeout<S> {
     }
 }
 
-impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Timeout<S>
+impl<S, T> Timeout<S, T> {
+    define_inner_service_accessors!();
+
+    /// Customize the response sent when a request times out.
+    ///
+    /// See [`TimeoutLayer::on_timeout`] for more details.
+    pub fn on_timeout<NewT>(self, on_timeout: NewT) -> Timeout<S, NewT> {
+        Timeout {
+            inner: self.inner,
+            config: self.config,
+            on_timeout,
+        }
+    }
+
+    /// Let callers specify the timeout, in milliseconds, with the given request header.
+    ///
+    /// See [`TimeoutLayer::timeout_header`] for more details.
+    pub fn timeout_header(mut self, header: HeaderName) -> Self {
+        self.config.header = Some(header);
+        self
+    }
+
+    /// Set the maximum timeout a request can ask for.
+    ///
+    /// See [`TimeoutLayer::max_timeout`] for more details.
+    pub fn max_timeout(mut self, max: Duration) -> Self {
+        self.config.max = Some(max);
+        self
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for Timeout<S, T>
 where
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
-    ResBody: Default,
+    T: OnTimeout<ResBody> + Clone,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = ResponseFuture<S::Future>;
+    type Future = ResponseFuture<S::Future, T>;
 
     #[inline]
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -78,28 +222,30 @@ where
     }
 
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
-        let sleep = tokio::time::sleep(self.timeout);
+        let sleep = tokio::time::sleep(self.config.timeout_for(&req));
         ResponseFuture {
             inner: self.inner.call(req),
             sleep,
+            on_timeout: self.on_timeout.clone(),
         }
     }
 }
 
 pin_project! {
     /// Response future for [`Timeout`].
-    pub struct ResponseFuture<F> {
+    pub struct ResponseFuture<F, T = DefaultOnTimeout> {
         #[pin]
         inner: F,
         #[pin]
         sleep: Sleep,
+        on_timeout: T,
     }
 }
 
-impl<F, B, E> Future for ResponseFuture<F>
+impl<F, T, B, E> Future for ResponseFuture<F, T>
 where
     F: Future<Output = Result<Response<B>, E>>,
-    B: Default,
+    T: OnTimeout<B>,
 {
     type Output = Result<Response<B>, E>;
 
@@ -107,41 +253,181 @@ where
         let this = self.project();
 
         if this.sleep.poll(cx).is_ready() {
-            let mut res = Response::new(B::default());
-            *res.status_mut() = StatusCode::REQUEST_TIMEOUT;
-            return Poll::Ready(Ok(res));
+            return Poll::Ready(Ok(this.on_timeout.on_timeout()));
         }
 
         this.inner.poll(cx)
     }
 }
 
+/// Request extension that overrides the timeout applied by [`Timeout`].
+///
+/// The timeout is still clamped to [`TimeoutLayer::max_timeout`]. Takes precedence over
+/// [`TimeoutLayer::timeout_header`].
+///
+/// # Example
+///
+/// ```
+/// use http::Request;
+/// use hyper::Body;
+/// use std::time::Duration;
+/// use tower_http::timeout::RequestTimeout;
+///
+/// let mut request = Request::new(Body::empty());
+/// request
+///     .extensions_mut()
+///     .insert(RequestTimeout::new(Duration::from_secs(5)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeout(Duration);
+
+impl RequestTimeout {
+    /// Create a new `RequestTimeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self(timeout)
+    }
+
+    /// Get the requested timeout.
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TimeoutConfig {
+    default: Duration,
+    max: Option<Duration>,
+    header: Option<HeaderName>,
+}
+
+impl TimeoutConfig {
+    fn new(default: Duration) -> Self {
+        Self {
+            default,
+            max: None,
+            header: None,
+        }
+    }
+
+    fn timeout_for<B>(&self, req: &Request<B>) -> Duration {
+        let requested = req
+            .extensions()
+            .get::<RequestTimeout>()
+            .map(RequestTimeout::duration)
+            .or_else(|| {
+                let value = req.headers().get(self.header.as_ref()?)?;
+                let millis = value.to_str().ok()?.trim().parse::<u64>().ok()?;
+                Some(Duration::from_millis(millis))
+            });
+
+        match requested {
+            Some(requested) => requested.min(self.max.unwrap_or(self.default)),
+            None => self.default,
+        }
+    }
+}
+
 /// Applies a [`TimeoutBody`] to the request body.
+///
+/// See [`RequestBodyTimeout`] for more details.
 #[derive(Clone, Debug)]
-pub struct RequestBodyTimeoutLayer {
+pub struct RequestBodyTimeoutLayer<M = Fail> {
     timeout: Duration,
+    _mode: PhantomData<M>,
 }
 
+/// Mode of [`RequestBodyTimeout`] where the inner service sees the [`TimeoutError`] and decides
+/// how to respond.
+///
+/// This is the default.
+///
+/// [`TimeoutError`]: super::TimeoutError
+#[derive(Clone, Copy, Debug)]
+pub struct Fail(());
+
+/// Mode of [`RequestBodyTimeout`] where a `408 Request Timeout` response is sent if the request
+/// body timed out.
+///
+/// See [`RequestBodyTimeoutLayer::respond_on_timeout`].
+#[derive(Clone, Copy, Debug)]
+pub struct Respond(());
+
 impl RequestBodyTimeoutLayer {
     /// Creates a new [`RequestBodyTimeoutLayer`].
     pub fn new(timeout: Duration) -> Self {
-        Self { timeout }
+        Self {
+            timeout,
+            _mode: PhantomData,
+        }
+    }
+}
+
+impl<M> RequestBodyTimeoutLayer<M> {
+    /// Respond with `408 Request Timeout` if the request body timed out.
+    ///
+    /// The response of the inner service is replaced whether it failed or handled the
+    /// [`TimeoutError`] itself, for example by responding with `500 Internal Server Error`.
+    ///
+    /// This protects against clients that send request bodies very slowly to tie up resources,
+    /// without tearing down the connection. The response body is created with [`Default`].
+    ///
+    /// [`TimeoutError`]: super::TimeoutError
+    pub fn respond_on_timeout(self) -> RequestBodyTimeoutLayer<Respond> {
+        RequestBodyTimeoutLayer {
+            timeout: self.timeout,
+            _mode: PhantomData,
+        }
     }
 }
 
-impl<S> Layer<S> for RequestBodyTimeoutLayer {
-    type Service = RequestBodyTimeout<S>;
+impl<S, M> Layer<S> for RequestBodyTimeoutLayer<M> {
+    type Service = RequestBodyTimeout<S, M>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        RequestBodyTimeout::new(inner, self.timeout)
+        RequestBodyTimeout {
+            inner,
+            timeout: self.timeout,
+            _mode: PhantomData,
+        }
     }
 }
 
 /// Applies a [`TimeoutBody`] to the request body.
+///
+/// The request body fails with a [`TimeoutError`] if no chunk arrives within the timeout. Unlike
+/// [`Timeout`], which limits the time until the response is produced, this limits the time
+/// between chunks so it also protects streaming reads.
+///
+/// By default the error is left to the inner service. Use
+/// [`RequestBodyTimeoutLayer::respond_on_timeout`] to send a `408 Request Timeout` response
+/// instead.
+///
+/// [`TimeoutError`]: super::TimeoutError
+///
+/// # Example
+///
+/// ```
+/// use http::{Request, Response};
+/// use hyper::Body;
+/// use std::time::Duration;
+/// use tower::{BoxError, ServiceBuilder};
+/// use tower_http::timeout::{RequestBodyTimeoutLayer, TimeoutBody};
+///
+/// async fn handle(req: Request<TimeoutBody<Body>>) -> Result<Response<Body>, BoxError> {
+///     // Fails if the client stops sending data for more than 10 seconds.
+///     let body = hyper::body::to_bytes(req.into_body()).await?;
+///     Ok(Response::new(Body::from(body)))
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(RequestBodyTimeoutLayer::new(Duration::from_secs(10)).respond_on_timeout())
+///     .service_fn(handle);
+/// ```
 #[derive(Clone, Debug)]
-pub struct RequestBodyTimeout<S> {
+pub struct RequestBodyTimeout<S, M = Fail> {
     inner: S,
     timeout: Duration,
+    _mode: PhantomData<M>,
 }
 
 impl<S> RequestBodyTimeout<S> {
@@ -150,6 +436,7 @@ impl<S> RequestBodyTimeout<S> {
         Self {
             inner: service,
             timeout,
+            _mode: PhantomData,
         }
     }
 
@@ -159,11 +446,24 @@ impl<S> RequestBodyTimeout<S> {
     pub fn layer(timeout: Duration) -> RequestBodyTimeoutLayer {
         RequestBodyTimeoutLayer::new(timeout)
     }
+}
 
+impl<S, M> RequestBodyTimeout<S, M> {
     define_inner_service_accessors!();
+
+    /// Respond with `408 Request Timeout` if the request body timed out.
+    ///
+    /// See [`RequestBodyTimeoutLayer::respond_on_timeout`] for more details.
+    pub fn respond_on_timeout(self) -> RequestBodyTimeout<S, Respond> {
+        RequestBodyTimeout {
+            inner: self.inner,
+            timeout: self.timeout,
+            _mode: PhantomData,
+        }
+    }
 }
 
-impl<S, ReqBody> Service<Request<ReqBody>> for RequestBodyTimeout<S>
+impl<S, ReqBody> Service<Request<ReqBody>> for RequestBodyTimeout<S, Fail>
 where
     S: Service<Request<TimeoutBody<ReqBody>>>,
     S::Error: Into<Box<dyn std::error::Error>>,
@@ -182,6 +482,60 @@ where
     }
 }
 
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestBodyTimeout<S, Respond>
+where
+    S: Service<Request<TimeoutBody<ReqBody>>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = RequestBodyTimeoutFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let timeout = self.timeout;
+        let req =
+            req.map(|body| TimeoutBody::new(timeout, body).notify_timed_out(timed_out.clone()));
+
+        RequestBodyTimeoutFuture {
+            inner: self.inner.call(req),
+            timed_out,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`RequestBodyTimeout`] in the [`Respond`] mode.
+    pub struct RequestBodyTimeoutFuture<F> {
+        #[pin]
+        inner: F,
+        timed_out: Arc<AtomicBool>,
+    }
+}
+
+impl<F, B, E> Future for RequestBodyTimeoutFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Default,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        if this.timed_out.load(Ordering::SeqCst) {
+            let mut res = Response::new(B::default());
+            *res.status_mut() = StatusCode::REQUEST_TIMEOUT;
+            return Poll::Ready(Ok(res));
+        }
+        Poll::Ready(result)
+    }
+}
+
 /// Applies a [`TimeoutBody`] to the response body.
 #[derive(Clone)]
 pub struct ResponseBodyTimeoutLayer {
@@ -272,3 +626,118 @@ where
         Poll::Ready(Ok(res.map(|body| TimeoutBody::new(timeout, body))))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use hyper::Body;
+    use tower::{BoxError, ServiceBuilder, ServiceExt};
+
+    #[test]
+    fn timeout_for_request() {
+        let config = TimeoutLayer::new(Duration::from_secs(10))
+            .timeout_header(HeaderName::from_static("x-timeout-ms"))
+            .max_timeout(Duration::from_secs(60))
+            .config;
+
+        let req = |header: Option<&str>, extension: Option<Duration>| {
+            let mut req = Request::new(());
+            if let Some(header) = header {
+                req.headers_mut()
+                    .insert("x-timeout-ms", header.parse().unwrap());
+            }
+            if let Some(extension) = extension {
+                req.extensions_mut().insert(RequestTimeout::new(extension));
+            }
+            req
+        };
+
+        let cases = vec![
+            (req(None, None), Duration::from_secs(10)),
+            (req(Some("1500"), None), Duration::from_millis(1500)),
+            (req(Some("600000"), None), Duration::from_secs(60)),
+            (req(Some("soon"), None), Duration::from_secs(10)),
+            (
+                req(Some("1500"), Some(Duration::from_secs(2))),
+                Duration::from_secs(2),
+            ),
+        ];
+        for (req, expected) in cases {
+            assert_eq!(config.timeout_for(&req), expected);
+        }
+
+        // without a max, requests can only shorten the timeout
+        let config = TimeoutLayer::new(Duration::from_secs(10)).config;
+        assert_eq!(
+            config.timeout_for(&req(None, Some(Duration::from_secs(30)))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_request_body_gets_408() {
+        let svc = ServiceBuilder::new()
+            .layer(RequestBodyTimeoutLayer::new(Duration::from_millis(10)).respond_on_timeout())
+            .service_fn(|req: Request<TimeoutBody<Body>>| async move {
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                Ok::<_, BoxError>(Response::new(Body::from(body)))
+            });
+
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data(Bytes::from_static(b"foo")).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            drop(tx);
+        });
+
+        let res = svc.oneshot(Request::new(body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn slow_request_body_gets_408_from_infallible_service() {
+        let svc = ServiceBuilder::new()
+            .layer(RequestBodyTimeoutLayer::new(Duration::from_millis(10)).respond_on_timeout())
+            .service_fn(|req: Request<TimeoutBody<Body>>| async move {
+                let res = match hyper::body::to_bytes(req.into_body()).await {
+                    Ok(body) => Response::new(Body::from(body)),
+                    Err(_) => {
+                        let mut res = Response::new(Body::empty());
+                        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                        res
+                    }
+                };
+                Ok::<_, std::convert::Infallible>(res)
+            });
+
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data(Bytes::from_static(b"foo")).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            drop(tx);
+        });
+
+        let res = svc.oneshot(Request::new(body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn slow_response_body_errors() {
+        let svc = ServiceBuilder::new()
+            .layer(ResponseBodyTimeoutLayer::new(Duration::from_millis(10)))
+            .service_fn(|_: Request<Body>| async {
+                let (tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    drop(tx);
+                });
+                Ok::<_, std::convert::Infallible>(Response::new(body))
+            });
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let err = hyper::body::to_bytes(res.into_body()).await.unwrap_err();
+        assert!(err.is::<crate::timeout::TimeoutError>());
+    }
+}
@@ -1,5 +1,8 @@
 use super::{OnBodyChunk, OnEos, OnFailure, OnResponse, ResponseBody};
-use crate::classify::{ClassifiedResponse, ClassifyResponse};
+use crate::{
+    classify::{ClassifiedResponse, ClassifyResponse},
+    matched_route::MatchedRoute,
+};
 use http::Response;
 use http_body::Body;
 use pin_project_lite::pin_project;
@@ -59,6 +62,10 @@ where
 
         match result {
             Ok(res) => {
+                if let Some(route) = res.extensions().get::<MatchedRoute>() {
+                    this.span.record("route", route.as_str());
+                }
+
                 let classification = classifier.classify_response(&res);
                 let start = *this.start;
 
@@ -1,5 +1,6 @@
+use crate::matched_route::MatchedRoute;
 use http::Request;
-use tracing::{Level, Span};
+use tracing::{field::Empty, Level, Span};
 
 use super::DEFAULT_MESSAGE_LEVEL;
 
@@ -30,6 +31,9 @@ where
 
 /// The default way [`Span`]s will be created for [`Trace`].
 ///
+/// The span has a `route` field which is set from a [`MatchedRoute`] in the request extensions or,
+/// once the response is produced, the response extensions.
+///
 /// [`Span`]: tracing::Span
 /// [`Trace`]: super::Trace
 #[derive(Debug, Clone)]
@@ -89,6 +93,7 @@ impl<B> MakeSpan<B> for DefaultMakeSpan {
                         uri = %request.uri(),
                         version = ?request.version(),
                         headers = ?request.headers(),
+                        route = Empty,
                     )
                 } else {
                     tracing::span!(
@@ -97,17 +102,24 @@ impl<B> MakeSpan<B> for DefaultMakeSpan {
                         method = %request.method(),
                         uri = %request.uri(),
                         version = ?request.version(),
+                        route = Empty,
                     )
                 }
             }
         }
 
-        match self.level {
+        let span = match self.level {
             Level::ERROR => make_span!(Level::ERROR),
             Level::WARN => make_span!(Level::WARN),
             Level::INFO => make_span!(Level::INFO),
             Level::DEBUG => make_span!(Level::DEBUG),
             Level::TRACE => make_span!(Level::TRACE),
+        };
+
+        if let Some(route) = request.extensions().get::<MatchedRoute>() {
+            span.record("route", route.as_str());
         }
+
+        span
     }
 }
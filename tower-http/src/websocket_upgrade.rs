@@ -0,0 +1,628 @@
+//! Middleware that validates WebSocket upgrade requests.
+//!
+//! [`WebSocketUpgrade`] checks requests with `Upgrade: websocket` against the opening handshake
+//! of [RFC 6455], and rejects bad handshakes before they reach the inner service:
+//!
+//! - Requests that don't use `GET` get `405 Method Not Allowed`.
+//! - Requests over HTTP/1.0, without `Connection: upgrade`, or without a valid
+//!   `Sec-WebSocket-Key` get `400 Bad Request`.
+//! - Requests with a `Sec-WebSocket-Version` other than `13` get `426 Upgrade Required` with a
+//!   `Sec-WebSocket-Version: 13` header.
+//! - If allowed origins are configured, requests from other origins, or without an `Origin`
+//!   header, get `403 Forbidden`.
+//!
+//! Valid requests are passed to the inner service with a [`WebSocketHandshake`] extension, which
+//! contains the `Sec-WebSocket-Accept` value and the negotiated subprotocol to respond with.
+//! Performing the upgrade itself is left to the inner service. Requests that don't ask for a
+//! WebSocket upgrade are passed to the inner service unchanged.
+//!
+//! [RFC 6455]: https://www.rfc-editor.org/rfc/rfc6455#section-4.2.1
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, HeaderValue, Request, Response, StatusCode};
+//! use hyper::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use tower_http::websocket_upgrade::{WebSocketHandshake, WebSocketUpgradeLayer};
+//!
+//! async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let handshake = req.extensions().get::<WebSocketHandshake>().unwrap();
+//!
+//!     let mut res = Response::builder()
+//!         .status(StatusCode::SWITCHING_PROTOCOLS)
+//!         .header(header::CONNECTION, "upgrade")
+//!         .header(header::UPGRADE, "websocket")
+//!         .header(header::SEC_WEBSOCKET_ACCEPT, handshake.accept().clone());
+//!     if let Some(protocol) = handshake.protocol() {
+//!         res = res.header(header::SEC_WEBSOCKET_PROTOCOL, protocol.clone());
+//!     }
+//!     // upgrade the connection with `hyper::upgrade::on(req)`...
+//!     Ok(res.body(Body::empty()).unwrap())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     .layer(
+//!         WebSocketUpgradeLayer::new()
+//!             .allowed_origins([HeaderValue::from_static("https://example.com")])
+//!             .protocols(["chat.v2", "chat.v1"]),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = Request::get("/ws")
+//!     .header(header::CONNECTION, "keep-alive, Upgrade")
+//!     .header(header::UPGRADE, "websocket")
+//!     .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+//!     .header(header::SEC_WEBSOCKET_VERSION, "13")
+//!     .header(header::SEC_WEBSOCKET_PROTOCOL, "chat.v1, chat.v0")
+//!     .header(header::ORIGIN, "https://example.com")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+//! assert_eq!(response.headers()[header::SEC_WEBSOCKET_ACCEPT], "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+//! assert_eq!(response.headers()[header::SEC_WEBSOCKET_PROTOCOL], "chat.v1");
+//!
+//! let request = Request::get("/ws")
+//!     .header(header::CONNECTION, "upgrade")
+//!     .header(header::UPGRADE, "websocket")
+//!     .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+//!     .header(header::SEC_WEBSOCKET_VERSION, "13")
+//!     .header(header::ORIGIN, "https://evil.example")
+//!     .body(Body::empty())?;
+//! let response = service.ready().await?.call(request).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::FORBIDDEN);
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::Engine as _;
+use futures_util::ready;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Version};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use sha1::{Digest, Sha1};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const WEBSOCKET_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Information about a validated WebSocket handshake.
+///
+/// This is added to the extensions of requests passed to the inner service by
+/// [`WebSocketUpgrade`].
+#[derive(Debug, Clone)]
+pub struct WebSocketHandshake {
+    key: HeaderValue,
+    accept: HeaderValue,
+    protocol: Option<HeaderValue>,
+    origin: Option<HeaderValue>,
+}
+
+impl WebSocketHandshake {
+    /// The `Sec-WebSocket-Key` sent by the client.
+    pub fn key(&self) -> &HeaderValue {
+        &self.key
+    }
+
+    /// The value of the `Sec-WebSocket-Accept` header to respond with.
+    pub fn accept(&self) -> &HeaderValue {
+        &self.accept
+    }
+
+    /// The negotiated subprotocol to respond with in `Sec-WebSocket-Protocol`, if any.
+    ///
+    /// This is the first subprotocol requested by the client that is supported, see
+    /// [`WebSocketUpgradeLayer::protocols`].
+    pub fn protocol(&self) -> Option<&HeaderValue> {
+        self.protocol.as_ref()
+    }
+
+    /// The `Origin` of the request, if any.
+    pub fn origin(&self) -> Option<&HeaderValue> {
+        self.origin.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Config {
+    allowed_origins: Option<Vec<HeaderValue>>,
+    protocols: Vec<HeaderValue>,
+}
+
+impl Config {
+    fn validate<B>(&self, req: &Request<B>) -> Result<WebSocketHandshake, Rejection> {
+        if req.method() != Method::GET {
+            return Err(Rejection::MethodNotAllowed);
+        }
+        if req.version() < Version::HTTP_11
+            || !has_token(req.headers(), header::CONNECTION, "upgrade")
+        {
+            return Err(Rejection::BadRequest);
+        }
+
+        let key = req
+            .headers()
+            .get(header::SEC_WEBSOCKET_KEY)
+            .filter(|key| is_valid_key(key))
+            .ok_or(Rejection::BadRequest)?;
+
+        if req
+            .headers()
+            .get(header::SEC_WEBSOCKET_VERSION)
+            .map_or(true, |version| version != "13")
+        {
+            return Err(Rejection::UnsupportedVersion);
+        }
+
+        let origin = req.headers().get(header::ORIGIN);
+        if let Some(allowed_origins) = &self.allowed_origins {
+            match origin {
+                Some(origin) if allowed_origins.contains(origin) => {}
+                _ => return Err(Rejection::Forbidden),
+            }
+        }
+
+        let protocol = req
+            .headers()
+            .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .find_map(|requested| {
+                self.protocols
+                    .iter()
+                    .find(|protocol| *protocol == requested)
+                    .cloned()
+            });
+
+        Ok(WebSocketHandshake {
+            key: key.clone(),
+            accept: accept_key(key),
+            protocol,
+            origin: origin.cloned(),
+        })
+    }
+}
+
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+fn is_valid_key(key: &HeaderValue) -> bool {
+    base64::engine::general_purpose::STANDARD
+        .decode(key.as_bytes())
+        .map_or(false, |key| key.len() == 16)
+}
+
+fn accept_key(key: &HeaderValue) -> HeaderValue {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(WEBSOCKET_GUID);
+    let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+    HeaderValue::from_str(&accept).expect("base64 is a valid header value")
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Rejection {
+    MethodNotAllowed,
+    BadRequest,
+    UnsupportedVersion,
+    Forbidden,
+}
+
+/// Layer that applies [`WebSocketUpgrade`] which validates WebSocket upgrade requests.
+///
+/// See the [module docs](crate::websocket_upgrade) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct WebSocketUpgradeLayer {
+    config: Arc<Config>,
+}
+
+impl WebSocketUpgradeLayer {
+    /// Create a new `WebSocketUpgradeLayer` that accepts any origin and doesn't support any
+    /// subprotocols.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accept handshakes with one of `origins` in the `Origin` header.
+    ///
+    /// Browsers always send the origin of the page opening the connection, so this protects
+    /// against other sites connecting with the user's cookies.
+    pub fn allowed_origins<I>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderValue>,
+    {
+        Arc::make_mut(&mut self.config).allowed_origins = Some(origins.into_iter().collect());
+        self
+    }
+
+    /// Set the supported subprotocols.
+    ///
+    /// The first subprotocol in the client's `Sec-WebSocket-Protocol` header that is supported is
+    /// negotiated. If none is supported the handshake is still accepted without a subprotocol,
+    /// and it is up to the inner service to reject it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a subprotocol is not a valid header value.
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = &'static str>,
+    {
+        Arc::make_mut(&mut self.config).protocols = protocols
+            .into_iter()
+            .map(HeaderValue::from_static)
+            .collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for WebSocketUpgradeLayer {
+    type Service = WebSocketUpgrade<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WebSocketUpgrade {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that validates WebSocket upgrade requests.
+///
+/// See the [module docs](crate::websocket_upgrade) for an example.
+#[derive(Debug, Clone)]
+pub struct WebSocketUpgrade<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> WebSocketUpgrade<S> {
+    /// Create a new `WebSocketUpgrade` that accepts any origin and doesn't support any
+    /// subprotocols.
+    pub fn new(inner: S) -> Self {
+        WebSocketUpgradeLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `WebSocketUpgrade` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> WebSocketUpgradeLayer {
+        WebSocketUpgradeLayer::new()
+    }
+
+    /// Only accept handshakes with one of `origins` in the `Origin` header.
+    ///
+    /// See [`WebSocketUpgradeLayer::allowed_origins`] for more details.
+    pub fn allowed_origins<I>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderValue>,
+    {
+        Arc::make_mut(&mut self.config).allowed_origins = Some(origins.into_iter().collect());
+        self
+    }
+
+    /// Set the supported subprotocols.
+    ///
+    /// See [`WebSocketUpgradeLayer::protocols`] for more details.
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = &'static str>,
+    {
+        Arc::make_mut(&mut self.config).protocols = protocols
+            .into_iter()
+            .map(HeaderValue::from_static)
+            .collect();
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for WebSocketUpgrade<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if !has_token(req.headers(), header::UPGRADE, "websocket") {
+            return ResponseFuture {
+                kind: Kind::Future {
+                    future: self.inner.call(req),
+                },
+            };
+        }
+
+        let kind = match self.config.validate(&req) {
+            Ok(handshake) => {
+                req.extensions_mut().insert(handshake);
+                Kind::Future {
+                    future: self.inner.call(req),
+                }
+            }
+            Err(rejection) => Kind::Reject { rejection },
+        };
+        ResponseFuture { kind }
+    }
+}
+
+pin_project! {
+    /// Response future for [`WebSocketUpgrade`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        kind: Kind<F>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F> {
+        Future {
+            #[pin]
+            future: F,
+        },
+        Reject {
+            rejection: Rejection,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Future { future } => {
+                let res = ready!(future.poll(cx))?;
+                Poll::Ready(Ok(res.map(|body| ResponseBody {
+                    kind: BodyKind::Body { body },
+                })))
+            }
+            KindProj::Reject { rejection } => {
+                let mut res = Response::new(ResponseBody {
+                    kind: BodyKind::Empty,
+                });
+                *res.status_mut() = match rejection {
+                    Rejection::MethodNotAllowed => {
+                        res.headers_mut()
+                            .insert(header::ALLOW, HeaderValue::from_static("GET"));
+                        StatusCode::METHOD_NOT_ALLOWED
+                    }
+                    Rejection::BadRequest => StatusCode::BAD_REQUEST,
+                    Rejection::UnsupportedVersion => {
+                        res.headers_mut().insert(
+                            header::SEC_WEBSOCKET_VERSION,
+                            HeaderValue::from_static("13"),
+                        );
+                        StatusCode::UPGRADE_REQUIRED
+                    }
+                    Rejection::Forbidden => StatusCode::FORBIDDEN,
+                };
+                Poll::Ready(Ok(res))
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Response body for [`WebSocketUpgrade`].
+    pub struct ResponseBody<B> {
+        #[pin]
+        kind: BodyKind<B>,
+    }
+}
+
+pin_project! {
+    #[project = BodyKindProj]
+    enum BodyKind<B> {
+        Body {
+            #[pin]
+            body: B,
+        },
+        Empty,
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_data(cx),
+            BodyKindProj::Empty => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().kind.project() {
+            BodyKindProj::Body { body } => body.poll_trailers(cx),
+            BodyKindProj::Empty => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            BodyKind::Body { body } => body.is_end_stream(),
+            BodyKind::Empty => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            BodyKind::Body { body } => body.size_hint(),
+            BodyKind::Empty => SizeHint::with_exact(0),
+        }
+    }
+}
+
+impl<B> fmt::Debug for ResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tower::{service_fn, BoxError, ServiceExt};
+
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        let mut res = Response::new(Body::empty());
+        if let Some(handshake) = req.extensions().get::<WebSocketHandshake>() {
+            *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+            res.headers_mut()
+                .insert(header::SEC_WEBSOCKET_ACCEPT, handshake.accept().clone());
+            if let Some(protocol) = handshake.protocol() {
+                res.headers_mut()
+                    .insert(header::SEC_WEBSOCKET_PROTOCOL, protocol.clone());
+            }
+        }
+        Ok(res)
+    }
+
+    fn handshake() -> http::request::Builder {
+        Request::get("/")
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "WebSocket")
+            .header(header::SEC_WEBSOCKET_KEY, KEY)
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+    }
+
+    async fn status<S>(svc: &S, req: http::request::Builder) -> StatusCode
+    where
+        S: Service<Request<Body>, Response = Response<ResponseBody<Body>>, Error = BoxError>
+            + Clone,
+    {
+        let req = req.body(Body::empty()).unwrap();
+        svc.clone().oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_handshake() {
+        let svc = WebSocketUpgradeLayer::new().layer(service_fn(handle));
+
+        let req = handshake().body(Body::empty()).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            res.headers()[header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert!(!res.headers().contains_key(header::SEC_WEBSOCKET_PROTOCOL));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_handshakes() {
+        let svc = WebSocketUpgradeLayer::new().layer(service_fn(handle));
+
+        let req = handshake().method(Method::POST);
+        assert_eq!(status(&svc, req).await, StatusCode::METHOD_NOT_ALLOWED);
+
+        let req = handshake().version(Version::HTTP_10);
+        assert_eq!(status(&svc, req).await, StatusCode::BAD_REQUEST);
+
+        let mut req = handshake().body(Body::empty()).unwrap();
+        req.headers_mut()
+            .insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let mut req = handshake().body(Body::empty()).unwrap();
+        req.headers_mut()
+            .insert(header::SEC_WEBSOCKET_KEY, HeaderValue::from_static("short"));
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let mut req = handshake().body(Body::empty()).unwrap();
+        req.headers_mut()
+            .insert(header::SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_VERSION], "13");
+
+        // requests that aren't upgrades are passed through
+        let req = Request::post("/");
+        assert_eq!(status(&svc, req).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn allowed_origins() {
+        let svc = WebSocketUpgradeLayer::new()
+            .allowed_origins([HeaderValue::from_static("https://example.com")])
+            .layer(service_fn(handle));
+
+        let req = handshake().header(header::ORIGIN, "https://example.com");
+        assert_eq!(status(&svc, req).await, StatusCode::SWITCHING_PROTOCOLS);
+
+        let req = handshake().header(header::ORIGIN, "https://example.org");
+        assert_eq!(status(&svc, req).await, StatusCode::FORBIDDEN);
+
+        assert_eq!(status(&svc, handshake()).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn negotiates_protocol() {
+        let svc = WebSocketUpgradeLayer::new()
+            .protocols(["b", "a"])
+            .layer(service_fn(handle));
+
+        let req = handshake()
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "c, a")
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "b")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_PROTOCOL], "a");
+
+        let req = handshake()
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "c")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert!(!res.headers().contains_key(header::SEC_WEBSOCKET_PROTOCOL));
+    }
+}